@@ -65,7 +65,7 @@ fn btree_mixed_benchmark_call<const FAST_PATH: bool>(num_read_threads: usize) {
 
         let handle = thread::spawn(move || {
             for key in start_key..end_key {
-                let _ = btree_clone.search(Key::new(key));
+                let _ = btree_clone.search(&Key::new(key));
             }
         });
 
@@ -79,15 +79,15 @@ fn btree_mixed_benchmark_call<const FAST_PATH: bool>(num_read_threads: usize) {
                 let record_id = RecordId::new(PageId::new(0), HeapPageSlotId::new(0));
 
                 if FAST_PATH {
-                    btree_clone.insert(Key::new(key), record_id).unwrap();
+                    btree_clone.insert(&Key::new(key), record_id).unwrap();
                 } else {
                     btree_clone
-                        .insert_slow_path(Key::new(key), record_id)
+                        .insert_slow_path(&Key::new(key), record_id)
                         .unwrap();
                 }
             }
             for key in start_key..end_key {
-                btree_clone.delete(Key::new(key)).unwrap();
+                btree_clone.delete(&Key::new(key)).unwrap();
             }
         }
     });
@@ -118,10 +118,12 @@ fn btree_write_benchmark_call<const FAST_PATH: bool>(num_threads: usize) {
             for key in start_key..end_key {
                 let record_id = RecordId::new(PageId::new(0), HeapPageSlotId::new(0));
                 if FAST_PATH {
-                    btree_clone.insert(Key::new(key as u32), record_id).unwrap();
+                    btree_clone
+                        .insert(&Key::new(key as u32), record_id)
+                        .unwrap();
                 } else {
                     btree_clone
-                        .insert_slow_path(Key::new(key as u32), record_id)
+                        .insert_slow_path(&Key::new(key as u32), record_id)
                         .unwrap();
                 }
             }
@@ -0,0 +1,68 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use joujoudb::cache::PageCache;
+use std::hint::black_box;
+
+extern crate joujoudb;
+use joujoudb::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+use joujoudb::sql::types::Value;
+use joujoudb::storage::FileStorage;
+use joujoudb::table::Table;
+use joujoudb::tuple::Tuple;
+
+use tempfile::NamedTempFile;
+
+const NUM_ROWS: usize = 100000;
+
+// Short strings get a 1-byte length header instead of 2 bytes: on this schema, a tuple is
+// made of a 10-byte header and 3 strings of 2 to 4 bytes.
+fn short_varchar_tuple(i: usize) -> Tuple {
+    const COUNTRIES: [&str; 4] = ["FR", "DE", "US", "JP"];
+    const CURRENCIES: [&str; 4] = ["EUR", "EUR", "USD", "JPY"];
+    const STATUSES: [&str; 3] = ["new", "paid", "void"];
+
+    Tuple::try_new(vec![
+        Value::VarChar(COUNTRIES[i % 4].to_string()),
+        Value::VarChar(CURRENCIES[i % 4].to_string()),
+        Value::VarChar(STATUSES[i % 3].to_string()),
+    ])
+    .unwrap()
+}
+
+fn short_varchar_benchmark(c: &mut Criterion) {
+    let column = |name: &str| {
+        Column::new(
+            name.to_string(),
+            DataType::VarChar(None),
+            ConstraintsBuilder::new().build(),
+        )
+    };
+    let schema = Schema::try_new(vec![
+        column("country"),
+        column("currency"),
+        column("status"),
+    ])
+    .unwrap();
+
+    let storage_path = NamedTempFile::new().unwrap();
+    let storage = FileStorage::create(storage_path).unwrap();
+    let page_cache = PageCache::try_new().unwrap();
+    let table = Table::try_new("orders", &schema, page_cache.cache_storage(storage)).unwrap();
+    for i in 0..NUM_ROWS {
+        table.insert(&short_varchar_tuple(i)).unwrap();
+    }
+    let avg_tuple_size = (0..12)
+        .map(|i| short_varchar_tuple(i).size())
+        .sum::<usize>()
+        / 12;
+
+    let mut group = c.benchmark_group("short varchar");
+    group.throughput(Throughput::Elements(NUM_ROWS as u64));
+    group.bench_function(
+        format!("scan {NUM_ROWS} tuples of {avg_tuple_size} bytes on average"),
+        |b| b.iter(|| black_box(table.iter().count())),
+    );
+    group.finish();
+}
+
+criterion_group!(benches, short_varchar_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+use joujoudb::pages::check::check_file;
+use joujoudb::pages::inspect::FileKind;
+use miette::{IntoDiagnostic, Result, bail};
+
+const USAGE: &str = "usage:
+    check <path>...    check the table (.tbl) and index (.idx) files, searching directories";
+
+/// Collects the table and index files under `path`, in a stable order.
+fn collect_files(path: &Path, files: &mut Vec<(PathBuf, FileKind)>) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)
+            .into_diagnostic()?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .into_diagnostic()?;
+        entries.sort();
+        for entry in entries {
+            collect_files(&entry, files)?;
+        }
+    } else if let Some(kind) = FileKind::from_path(path) {
+        files.push((path.to_path_buf(), kind));
+    } else if !path.exists() {
+        bail!("{}: no such file or directory", path.display());
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let paths = std::env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        bail!("{USAGE}");
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        collect_files(Path::new(path), &mut files)?;
+    }
+
+    let mut corrupted = 0;
+    for (path, kind) in &files {
+        let report = check_file(path, *kind).into_diagnostic()?;
+        println!("{}:", path.display());
+        for line in report.to_string().lines() {
+            println!("  {line}");
+        }
+        if !report.is_ok() {
+            corrupted += 1;
+        }
+    }
+    println!("{} files checked, {corrupted} corrupted", files.len());
+    if corrupted > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
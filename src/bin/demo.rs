@@ -0,0 +1,194 @@
+use std::path::Path;
+use std::process::Command;
+
+use joujoudb::cache::GLOBAL_PAGE_CACHE;
+use joujoudb::catalog::Catalog;
+use joujoudb::sql::exec::{MemoryBudget, QueryContext, RowStream, Tables};
+use joujoudb::sql::parser::parser::Parser;
+use joujoudb::sql::plan;
+use joujoudb::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+use joujoudb::sql::types::Value;
+use joujoudb::storage::{DatabaseName, FileStorage, TableName};
+use joujoudb::tuple::Tuple;
+use miette::{IntoDiagnostic, Result, bail, miette};
+
+const USAGE: &str = "usage:
+    demo                     create a database in a temporary directory, restart and query it
+    demo create <directory>  create the demo database in <directory>
+    demo check <directory>   query the demo database in <directory>";
+
+const DATABASE: &str = "shop";
+
+/// The memory the operators of a query may use.
+const QUERY_MEMORY: usize = 64 << 20;
+
+const USERS: [(i64, &str); 3] = [(1, "alice"), (2, "bob"), (3, "carol")];
+
+// (id, user_id, amount)
+const ORDERS: [(i64, i64, f64); 4] = [(10, 1, 25.0), (11, 3, 7.5), (12, 1, 12.0), (13, 2, 3.25)];
+
+fn column(name: &str, data_type: DataType) -> Column {
+    Column::new(
+        name.to_string(),
+        data_type,
+        ConstraintsBuilder::new().build(),
+    )
+}
+
+fn names() -> Result<(DatabaseName, TableName, TableName)> {
+    let db_name = DatabaseName::try_from(DATABASE).map_err(|e| miette!(e))?;
+    let users = TableName::try_from("users").map_err(|e| miette!(e))?;
+    let orders = TableName::try_from("orders").map_err(|e| miette!(e))?;
+    Ok((db_name, users, orders))
+}
+
+/// Creates the tables and inserts their tuples, then writes them to disk along
+/// with the statistics of the tables.
+fn create(root: &Path) -> Result<()> {
+    let mut catalog = Catalog::with_root_path(root);
+    let (db_name, users, orders) = names()?;
+    catalog.create_database(&db_name).into_diagnostic()?;
+
+    let schema = Schema::try_new(vec![
+        column("id", DataType::Integer),
+        column("name", DataType::VarChar(Some(32))),
+    ])
+    .into_diagnostic()?;
+    catalog
+        .create_table(&db_name, &users, &schema)
+        .into_diagnostic()?;
+    let schema = Schema::try_new(vec![
+        column("id", DataType::Integer),
+        column("user_id", DataType::Integer),
+        column("amount", DataType::Float),
+    ])
+    .into_diagnostic()?;
+    catalog
+        .create_table(&db_name, &orders, &schema)
+        .into_diagnostic()?;
+
+    let table = catalog.open_table(&db_name, &users).into_diagnostic()?;
+    for (id, name) in USERS {
+        let tuple = Tuple::try_new(vec![Value::Integer(id), Value::VarChar(name.to_string())])
+            .into_diagnostic()?;
+        table.insert(&tuple).into_diagnostic()?;
+    }
+    catalog.analyze_table(&db_name, &table).into_diagnostic()?;
+    let table = catalog.open_table(&db_name, &orders).into_diagnostic()?;
+    for (id, user_id, amount) in ORDERS {
+        let tuple = Tuple::try_new(vec![
+            Value::Integer(id),
+            Value::Integer(user_id),
+            Value::Float(amount),
+        ])
+        .into_diagnostic()?;
+        table.insert(&tuple).into_diagnostic()?;
+    }
+    catalog.analyze_table(&db_name, &table).into_diagnostic()?;
+
+    GLOBAL_PAGE_CACHE.flush();
+    println!(
+        "created {DATABASE}: {} users, {} orders",
+        USERS.len(),
+        ORDERS.len()
+    );
+
+    Ok(())
+}
+
+fn query(
+    tables: &Tables<FileStorage>,
+    context: &QueryContext,
+    source: &str,
+) -> Result<Vec<Vec<Value>>> {
+    let stmts = Parser::parse(source)?;
+    let plan = plan::plan(&stmts[0], tables).into_diagnostic()?;
+    print!("{source}\n{plan}");
+
+    let mut rows = Vec::new();
+    for tuple in RowStream::execute_with(&plan, tables, context).into_diagnostic()? {
+        let values = tuple.into_diagnostic()?.values().to_vec();
+        println!("  {values:?}");
+        rows.push(values);
+    }
+
+    Ok(rows)
+}
+
+/// Reopens the database and checks that queries see the tuples inserted by
+/// `create`.
+fn check(root: &Path) -> Result<()> {
+    let catalog = Catalog::with_root_path(root);
+    let (db_name, users, orders) = names()?;
+    let mut tables = Tables::new();
+    for table_name in [&users, &orders] {
+        let table = catalog
+            .open_table(&db_name, table_name)
+            .map_err(|e| miette!("{}: {e}", table_name.as_str()))?;
+        let stats = catalog
+            .table_stats(&db_name, table_name)
+            .into_diagnostic()?;
+        tables.set_stats(table_name.as_str(), stats);
+        tables.add_table(table);
+    }
+    // Operators going over the budget spill to the database directory.
+    let context = QueryContext {
+        budget: MemoryBudget::new(QUERY_MEMORY, root.join(DATABASE)),
+        ..QueryContext::default()
+    };
+
+    let rows = query(&tables, &context, "SELECT id, name FROM users")?;
+    if rows.len() != USERS.len() {
+        bail!("expected {} users, found {}", USERS.len(), rows.len());
+    }
+
+    let rows = query(
+        &tables,
+        &context,
+        "SELECT name, amount FROM orders o JOIN users u ON o.user_id = u.id WHERE amount > 10",
+    )?;
+    let mut names = rows
+        .iter()
+        .map(|row| match &row[0] {
+            Value::VarChar(name) => Ok(name.as_str()),
+            value => Err(miette!("unexpected name {value:?}")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    if names != ["alice", "alice"] {
+        bail!("unexpected orders above 10: {names:?}");
+    }
+
+    println!("{DATABASE} is persisted");
+    Ok(())
+}
+
+/// Runs `create` and `check` in two processes sharing a temporary directory.
+fn run() -> Result<()> {
+    let root = tempfile::TempDir::new().into_diagnostic()?;
+    let exe = std::env::current_exe().into_diagnostic()?;
+    for step in ["create", "check"] {
+        let status = Command::new(&exe)
+            .arg(step)
+            .arg(root.path())
+            .status()
+            .into_diagnostic()?;
+        if !status.success() {
+            bail!("demo {step} failed: {status}");
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args[..] {
+        [] => run(),
+        ["create", root] => create(Path::new(root)),
+        ["check", root] => check(Path::new(root)),
+        _ => bail!("{USAGE}"),
+    }
+}
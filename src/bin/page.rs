@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::path::Path;
+
+use joujoudb::pages::inspect::{self, FileKind};
+use joujoudb::pages::{
+    BTreeInnerPage, BTreeLeafPage, BTreePageType, BTreeSuperBlock, Key, PAGE_RESERVED, PAGE_SIZE,
+    Page, PageId, btree_try_get_page_type,
+};
+use joujoudb::storage::FileExt;
+use miette::{IntoDiagnostic, Result, bail, miette};
+
+const USAGE: &str = "usage:
+    page inspect <file> <page_id>    decode a page of a table (.tbl) or index (.idx) file
+    page tree <file>                 print the pages of an index (.idx) file, level by level";
+
+fn file_kind(path: &Path) -> Result<FileKind> {
+    match FileKind::from_path(path) {
+        Some(kind) => Ok(kind),
+        None => bail!(
+            "{}: not a table (.tbl) or index (.idx) file",
+            path.display()
+        ),
+    }
+}
+
+// Pages are read without O_DIRECT: the file may be in use by a database and we
+// don't need an aligned buffer.
+fn read_page(file: &File, page_id: PageId) -> Result<Page> {
+    let mut page = Page::new();
+    let offset = page_id.get() as u64 * PAGE_SIZE as u64;
+    file.read_exact_at(&mut page.data, offset)
+        .map_err(|e| miette!("cannot read page {}: {e}", page_id.get()))?;
+    Ok(page)
+}
+
+fn inspect_page(path: &Path, page_id: &str) -> Result<()> {
+    let kind = file_kind(path)?;
+    let page_id = PageId::new(page_id.parse().into_diagnostic()?);
+    let file = File::open(path).into_diagnostic()?;
+    let page = read_page(&file, page_id)?;
+    print!("{}", inspect::inspect(&page, page_id, kind));
+
+    Ok(())
+}
+
+fn format_keys(keys: impl Iterator<Item = Key>) -> String {
+    keys.map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_tree(path: &Path) -> Result<()> {
+    if file_kind(path)? != FileKind::BTree {
+        bail!("{}: not an index (.idx) file", path.display());
+    }
+    let file = File::open(path).into_diagnostic()?;
+    let superblock = read_page(&file, PAGE_RESERVED)?;
+    let root_page_id = <&BTreeSuperBlock>::from(&superblock).root_page_id;
+
+    let mut level = vec![root_page_id];
+    let mut depth = 0;
+    while !level.is_empty() {
+        println!("level {depth}:");
+        let mut queue = VecDeque::from(level);
+        level = Vec::new();
+        while let Some(page_id) = queue.pop_front() {
+            let page = read_page(&file, page_id)?;
+            match btree_try_get_page_type(&page) {
+                Some(BTreePageType::Inner) => {
+                    let inner = <&BTreeInnerPage>::from(&page);
+                    let keys = format_keys(inner.keys());
+                    let pointers = inner.pointers().map(|p| p.get()).collect::<Vec<_>>();
+                    println!(
+                        "  inner({}): keys=[{keys}] pointers={pointers:?}",
+                        page_id.get()
+                    );
+                    level.extend(inner.pointers());
+                }
+                Some(BTreePageType::Leaf) => {
+                    let leaf = <&BTreeLeafPage>::from(&page);
+                    let keys = format_keys(leaf.keys());
+                    println!(
+                        "  leaf({}) next={}: keys=[{keys}]",
+                        page_id.get(),
+                        leaf.next_page_id().get()
+                    );
+                }
+                None => println!("  unknown({}): type={:#04x}", page_id.get(), page.data[0]),
+            }
+        }
+        depth += 1;
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args[..] {
+        ["inspect", path, page_id] => inspect_page(Path::new(path), page_id),
+        ["tree", path] => print_tree(Path::new(path)),
+        _ => bail!("{USAGE}"),
+    }
+}
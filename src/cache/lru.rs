@@ -1,8 +1,10 @@
 use crate::cache::EvictionPolicy;
+use crate::clock::ClockSource;
 use crate::pages::PageId;
 use crate::storage::StorageId;
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use priority_queue::PriorityQueue;
 
@@ -13,20 +15,23 @@ pub struct LRU {
     // from the priority queue, keep track of the
     // last access in a hashmap
     last_access: HashMap<(StorageId, PageId), i64>,
+    clock: Arc<dyn ClockSource>,
 }
 
 impl LRU {
-    pub fn new() -> Self {
+    /// Creates a LRU policy timestamping accesses with the given clock.
+    pub fn with_clock(clock: Arc<dyn ClockSource>) -> Self {
         Self {
             queue: PriorityQueue::new(),
             last_access: HashMap::new(),
+            clock,
         }
     }
 }
 
 impl EvictionPolicy for LRU {
     fn record_access(&mut self, storage_id: StorageId, page_id: PageId) {
-        let now = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let now = self.clock.now();
         self.last_access.insert((storage_id, page_id), now);
         self.queue.push((storage_id, page_id), -now);
     }
@@ -55,9 +60,11 @@ impl EvictionPolicy for LRU {
 mod tests {
     use super::*;
 
+    use crate::clock::{ManualClock, SystemClock};
+
     #[test]
     fn lru_eviction_policy() {
-        let mut lru = LRU::new();
+        let mut lru = LRU::with_clock(Arc::new(SystemClock));
         lru.record_access(StorageId(0), PageId::new(0));
         lru.set_evictable(StorageId(0), PageId::new(0));
         lru.record_access(StorageId(0), PageId::new(1));
@@ -68,4 +75,28 @@ mod tests {
         lru.set_unevictable(StorageId(0), PageId::new(1));
         assert_eq!(lru.evict(), Some((StorageId(0), PageId::new(2))));
     }
+
+    #[test]
+    fn lru_manual_clock() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut lru = LRU::with_clock(clock.clone());
+        for page_id in [2, 0, 1] {
+            clock.advance(10);
+            lru.record_access(StorageId(0), PageId::new(page_id));
+            lru.set_evictable(StorageId(0), PageId::new(page_id));
+        }
+
+        // Page 2 is accessed again: it is now the most recently used.
+        clock.advance(10);
+        lru.record_access(StorageId(0), PageId::new(2));
+        // A page set evictable again keeps its last access time.
+        lru.set_unevictable(StorageId(0), PageId::new(0));
+        clock.advance(100);
+        lru.set_evictable(StorageId(0), PageId::new(0));
+
+        assert_eq!(lru.evict(), Some((StorageId(0), PageId::new(0))));
+        assert_eq!(lru.evict(), Some((StorageId(0), PageId::new(1))));
+        assert_eq!(lru.evict(), Some((StorageId(0), PageId::new(2))));
+        assert_eq!(lru.evict(), None);
+    }
 }
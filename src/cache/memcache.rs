@@ -1,6 +1,9 @@
 use crate::cache::{EvictionPolicy, lru::LRU};
+use crate::clock::ClockSource;
 use crate::config::CONFIG;
 use crate::pages::{BTreeInnerPage, BTreeLeafPage, BTreeSuperBlock, PAGE_INVALID, PAGE_SIZE};
+use crate::pages::{BloomFilterPage, FreeListPage};
+use crate::pages::{HashBucketPage, HashDirectoryPage, HashSuperBlock};
 use crate::pages::{HeapPage, Page, PageId, PageMetadata};
 use crate::storage::StorageId;
 
@@ -9,10 +12,12 @@ use std::cell::UnsafeCell;
 use std::collections::{HashMap, VecDeque};
 use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
-use std::slice;
-use std::sync::atomic::{Ordering, fence};
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::sync::{Arc, OnceLock};
 
 use memmap2::MmapMut;
+#[cfg(unix)]
+use memmap2::UncheckedAdvice;
 use thiserror::Error;
 
 // SAFETY:
@@ -20,8 +25,12 @@ use thiserror::Error;
 // 2. Shared and exclusive access to pages and pages metadata are handled with a separate RwLock stored
 //    in PageLatch.
 // 3. Memory mapping is managed by memmap2 which ensures the memory is valid for the lifetime
-//    of the MmapMut object.
+//    of the MmapMut object. Frame regions are never unmapped while the MemCache is alive.
 // 4. Page references are only created with proper synchronization through the page latch.
+//    Pages are also copied without their latch by `read_page_optimistic`: the copy is only
+//    used if the version of the latch shows that no writer held it meanwhile.
+// 5. Frames are retired (and their memory released with MADV_DONTNEED) only when they are
+//    neither mapped in the page table nor referenced.
 
 // In the future, consider looking at: https://github.com/rust-lang/rust/issues/95439
 struct UnsafePageMetadata(UnsafeCell<PageMetadata>);
@@ -35,28 +44,121 @@ impl UnsafePageMetadata {
 
 struct PageLatch {
     latch: RwLock<()>,
+    // Odd while the latch is held exclusively, incremented again on release: a
+    // page copied between two equal even versions wasn't written meanwhile.
+    version: AtomicU64,
 }
 
 impl Default for PageLatch {
     fn default() -> Self {
         Self {
             latch: RwLock::new(()),
+            version: AtomicU64::new(0),
         }
     }
 }
 
+impl PageLatch {
+    /// Called once the latch is held exclusively, before writing the page.
+    #[inline]
+    fn begin_write(&self) {
+        self.version.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+    }
+
+    /// Called before releasing the latch, once the page is written.
+    #[inline]
+    fn end_write(&self) {
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// The version of a page copied by `MemCache::read_page_optimistic`.
+pub struct PageVersion<'page> {
+    latch: &'page PageLatch,
+    version: u64,
+}
+
+impl PageVersion<'_> {
+    /// Returns whether the page is unchanged since it was copied: it wasn't
+    /// written nor removed from the cache.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        fence(Ordering::Acquire);
+        self.latch.version.load(Ordering::Relaxed) == self.version
+    }
+}
+
+/// The maximum number of frame regions: one for the initial mapping, the others for resizes.
+const MAX_FRAME_REGIONS: usize = 64;
+
+/// A contiguous memory-mapped region of page frames.
+///
+/// The first region is mapped when the cache is created, additional regions are mapped
+/// when the cache grows.
+struct FrameRegion {
+    // index of the first frame of the region
+    start: usize,
+    pages: MmapMut,
+    pages_metadata: Box<[UnsafePageMetadata]>,
+    pages_latch: Box<[PageLatch]>,
+}
+
+impl FrameRegion {
+    fn try_new(start: usize, num_frames: usize) -> Result<Self, MemCacheError> {
+        let pages = MmapMut::map_anon(num_frames * PAGE_SIZE).map_err(MemCacheError::MmapFailed)?;
+        let pages_metadata = std::iter::repeat_with(|| {
+            UnsafePageMetadata::new(StorageId(0) /* TODO */, PAGE_INVALID)
+        })
+        .take(num_frames);
+        let pages_latch = std::iter::repeat_with(PageLatch::default).take(num_frames);
+
+        Ok(Self {
+            start,
+            pages,
+            pages_metadata: Box::from_iter(pages_metadata),
+            pages_latch: Box::from_iter(pages_latch),
+        })
+    }
+
+    #[inline]
+    fn contains(&self, idx: usize) -> bool {
+        (self.start..self.start + self.pages_latch.len()).contains(&idx)
+    }
+}
+
 struct PageTable {
     map: HashMap<(StorageId, PageId), usize>,
     free_list: VecDeque<usize>,
+    // frames taken out of service by a shrink, reused first on grow
+    retired: Vec<usize>,
+    // number of frames mapped, whether in service or retired
+    num_frames: usize,
+    // target number of frames in service
+    capacity: usize,
 }
 
-impl Default for PageTable {
-    fn default() -> Self {
+impl PageTable {
+    fn new(capacity: usize) -> Self {
         Self {
             map: HashMap::new(),
-            free_list: VecDeque::from_iter(0..CONFIG.PAGE_CACHE_SIZE),
+            free_list: VecDeque::from_iter(0..capacity),
+            retired: Vec::new(),
+            num_frames: capacity,
+            capacity,
         }
     }
+
+    #[inline]
+    fn in_service(&self) -> usize {
+        self.num_frames - self.retired.len()
+    }
+
+    /// Number of frames to drain before the cache fits its capacity.
+    #[inline]
+    fn excess(&self) -> usize {
+        self.in_service().saturating_sub(self.capacity)
+    }
 }
 
 pub struct PageRef<'page> {
@@ -90,10 +192,31 @@ impl PageRef<'_> {
     pub fn btree_leaf_page(&self) -> &BTreeLeafPage {
         self.page().into()
     }
+
+    pub fn hash_superblock(&self) -> &HashSuperBlock {
+        self.page().into()
+    }
+
+    pub fn hash_directory_page(&self) -> &HashDirectoryPage {
+        self.page().into()
+    }
+
+    pub fn hash_bucket_page(&self) -> &HashBucketPage {
+        self.page().into()
+    }
+
+    pub fn bloom_filter_page(&self) -> &BloomFilterPage {
+        self.page().into()
+    }
+
+    pub fn free_list_page(&self) -> &FreeListPage {
+        self.page().into()
+    }
 }
 
 pub struct PageRefMut<'page> {
     _guard: RwLockWriteGuard<'page, ()>,
+    latch: &'page PageLatch,
     page: &'page mut Page,
     metadata: &'page mut PageMetadata,
     eviction_policy: &'page Mutex<dyn EvictionPolicy>,
@@ -144,12 +267,53 @@ impl<'page> PageRefMut<'page> {
         self.page_mut().into()
     }
 
+    pub fn hash_superblock(&self) -> &HashSuperBlock {
+        self.page().into()
+    }
+
+    pub fn hash_superblock_mut(&mut self) -> &mut HashSuperBlock {
+        self.page_mut().into()
+    }
+
+    pub fn hash_directory_page(&self) -> &HashDirectoryPage {
+        self.page().into()
+    }
+
+    pub fn hash_directory_page_mut(&mut self) -> &mut HashDirectoryPage {
+        self.page_mut().into()
+    }
+
+    pub fn hash_bucket_page(&self) -> &HashBucketPage {
+        self.page().into()
+    }
+
+    pub fn hash_bucket_page_mut(&mut self) -> &mut HashBucketPage {
+        self.page_mut().into()
+    }
+
+    pub fn bloom_filter_page(&self) -> &BloomFilterPage {
+        self.page().into()
+    }
+
+    pub fn bloom_filter_page_mut(&mut self) -> &mut BloomFilterPage {
+        self.page_mut().into()
+    }
+
+    pub fn free_list_page(&self) -> &FreeListPage {
+        self.page().into()
+    }
+
+    pub fn free_list_page_mut(&mut self) -> &mut FreeListPage {
+        self.page_mut().into()
+    }
+
     pub fn downgrade(self) -> PageRef<'page> {
         let this = ManuallyDrop::new(self);
 
         // SAFETY: The references are valid for the lifetime 'page because we still hold the lock.
         // Don't drop `this` with ManuallyDrop::drop(this), the Drop implementation of PageRef
         // would decrease the metadata counter and drop the guard.
+        this.latch.end_write();
         let _guard = RwLockWriteGuard::downgrade(unsafe { std::ptr::read(&this._guard) });
         let page = unsafe { &*(this.page as *const Page) };
         let metadata = unsafe { &*(this.metadata as *const PageMetadata) };
@@ -209,6 +373,7 @@ impl Drop for PageRef<'_> {
 
 impl Drop for PageRefMut<'_> {
     fn drop(&mut self) {
+        self.latch.end_write();
         let old_counter = self.metadata.counter().fetch_sub(1, Ordering::Release);
         if old_counter != 1 {
             return;
@@ -226,9 +391,7 @@ impl Drop for PageRefMut<'_> {
 }
 
 pub struct MemCache {
-    pages: MmapMut,
-    pages_metadata: Box<[UnsafePageMetadata]>,
-    pages_latch: Box<[PageLatch]>,
+    regions: Box<[OnceLock<FrameRegion>]>,
     page_table: Mutex<PageTable>,
     eviction_policy: Box<Mutex<dyn EvictionPolicy>>,
 }
@@ -239,59 +402,138 @@ pub enum MemCacheError {
     Full,
     #[error("page not found")]
     PageNotFound,
+    #[error("page already in cache")]
+    PageExists,
     #[error("mmap failed")]
     MmapFailed(#[from] std::io::Error),
+    #[error("too many frame regions")]
+    TooManyRegions,
 }
 
 impl MemCache {
-    pub fn try_new() -> Result<Self, MemCacheError> {
-        let pages = MmapMut::map_anon(CONFIG.PAGE_CACHE_SIZE * PAGE_SIZE)
-            .map_err(MemCacheError::MmapFailed)?;
-        let pages_metadata = std::iter::repeat_with(|| {
-            UnsafePageMetadata::new(StorageId(0) /* TODO */, PAGE_INVALID)
-        })
-        .take(CONFIG.PAGE_CACHE_SIZE);
-        let pages_lock = std::iter::repeat_with(PageLatch::default).take(CONFIG.PAGE_CACHE_SIZE);
+    /// Creates a `MemCache` whose eviction policy reads time from `clock`.
+    pub fn try_with_clock(clock: Arc<dyn ClockSource>) -> Result<Self, MemCacheError> {
+        let regions = Box::from_iter(std::iter::repeat_with(OnceLock::new).take(MAX_FRAME_REGIONS));
+        let _ = regions[0].set(FrameRegion::try_new(0, CONFIG.PAGE_CACHE_SIZE)?);
 
         Ok(Self {
-            pages,
-            pages_metadata: Box::from_iter(pages_metadata),
-            pages_latch: Box::from_iter(pages_lock),
-            page_table: Mutex::new(PageTable::default()),
-            eviction_policy: Box::new(Mutex::new(LRU::new())),
+            regions,
+            page_table: Mutex::new(PageTable::new(CONFIG.PAGE_CACHE_SIZE)),
+            eviction_policy: Box::new(Mutex::new(LRU::with_clock(clock))),
         })
     }
 
+    /// Returns the region of a frame and the position of the frame within the region.
     #[inline]
-    unsafe fn borrow_page(&self, idx: usize) -> &Page {
-        let pages = unsafe {
-            std::slice::from_raw_parts(self.pages.as_ptr() as *const Page, CONFIG.PAGE_CACHE_SIZE)
-        };
+    fn frame_region(&self, idx: usize) -> (&FrameRegion, usize) {
+        let region = self
+            .regions
+            .iter()
+            .map_while(OnceLock::get)
+            .find(|region| region.contains(idx))
+            .expect("frame index out of bounds");
+
+        (region, idx - region.start)
+    }
 
-        debug_assert!(idx < CONFIG.PAGE_CACHE_SIZE);
-        unsafe { pages.get_unchecked(idx) }
+    #[inline]
+    fn page_latch(&self, idx: usize) -> &PageLatch {
+        let (region, pos) = self.frame_region(idx);
+        &region.pages_latch[pos]
+    }
+
+    #[inline]
+    unsafe fn borrow_page(&self, idx: usize) -> &Page {
+        let (region, pos) = self.frame_region(idx);
+        unsafe { &*(region.pages.as_ptr() as *const Page).add(pos) }
     }
 
     #[allow(clippy::mut_from_ref)]
     #[inline]
     unsafe fn borrow_page_mut(&self, idx: usize) -> &mut Page {
-        let pages: &mut [Page] = unsafe {
-            slice::from_raw_parts_mut(self.pages.as_ptr() as *mut Page, CONFIG.PAGE_CACHE_SIZE)
-        };
-
-        debug_assert!(idx < CONFIG.PAGE_CACHE_SIZE);
-        unsafe { pages.get_unchecked_mut(idx) }
+        let (region, pos) = self.frame_region(idx);
+        unsafe { &mut *(region.pages.as_ptr() as *mut Page).add(pos) }
     }
 
     #[inline]
     unsafe fn borrow_page_metadata(&self, idx: usize) -> &PageMetadata {
-        unsafe { &*(self.pages_metadata[idx].0.get()) }
+        let (region, pos) = self.frame_region(idx);
+        unsafe { &*(region.pages_metadata[pos].0.get()) }
     }
 
     #[allow(clippy::mut_from_ref)]
     #[inline]
     unsafe fn borrow_page_metadata_mut(&self, idx: usize) -> &mut PageMetadata {
-        unsafe { &mut *(self.pages_metadata[idx].0.get()) }
+        let (region, pos) = self.frame_region(idx);
+        unsafe { &mut *(region.pages_metadata[pos].0.get()) }
+    }
+
+    /// Takes a frame out of service and gives its memory back to the kernel.
+    fn retire_frame(&self, page_table: &mut PageTable, idx: usize) {
+        // SAFETY: the frame is neither in the page table nor in the free list, nobody can
+        // hold a reference to it. The next access to the frame will read zeroes.
+        #[cfg(unix)]
+        {
+            let (region, pos) = self.frame_region(idx);
+            let _ = unsafe {
+                region.pages.unchecked_advise_range(
+                    UncheckedAdvice::DontNeed,
+                    pos * PAGE_SIZE,
+                    PAGE_SIZE,
+                )
+            };
+        }
+        // There is no MADV_DONTNEED: the memory is kept, zeroed.
+        #[cfg(not(unix))]
+        unsafe { self.borrow_page_mut(idx) }.data.fill(0);
+        page_table.retired.push(idx);
+    }
+
+    /// Returns the number of frames in service the cache is targeting.
+    pub fn capacity(&self) -> usize {
+        self.page_table.lock().capacity
+    }
+
+    /// Returns the number of frames to drain before the cache fits its capacity.
+    pub fn excess_frames(&self) -> usize {
+        self.page_table.lock().excess()
+    }
+
+    /// Sets the number of frames in service.
+    ///
+    /// Growing puts retired frames back in service first, then maps a new frame region for
+    /// the missing frames. Shrinking retires free frames: frames holding a page are retired
+    /// when the page is removed from the cache (see `excess_frames`).
+    pub fn resize(&self, capacity: usize) -> Result<(), MemCacheError> {
+        let mut page_table = self.page_table.lock();
+
+        while page_table.in_service() < capacity
+            && let Some(idx) = page_table.retired.pop()
+        {
+            page_table.free_list.push_back(idx);
+        }
+
+        let missing = capacity.saturating_sub(page_table.in_service());
+        if missing > 0 {
+            let slot = self
+                .regions
+                .iter()
+                .find(|region| region.get().is_none())
+                .ok_or(MemCacheError::TooManyRegions)?;
+            let start = page_table.num_frames;
+            let _ = slot.set(FrameRegion::try_new(start, missing)?);
+            page_table.free_list.extend(start..start + missing);
+            page_table.num_frames += missing;
+        }
+
+        page_table.capacity = capacity;
+        while page_table.excess() > 0
+            && let Some(idx) = page_table.free_list.pop_back()
+        {
+            self.retire_frame(&mut page_table, idx);
+        }
+
+        Ok(())
     }
 
     pub fn get_page(
@@ -299,17 +541,55 @@ impl MemCache {
         storage_id: StorageId,
         page_id: PageId,
     ) -> Result<PageRef<'_>, MemCacheError> {
-        let idx = {
-            let page_table = self.page_table.lock();
-            page_table
-                .map
-                .get(&(storage_id, page_id))
-                .copied()
-                .ok_or(MemCacheError::PageNotFound)?
-        };
+        loop {
+            let idx = self.lookup(storage_id, page_id)?;
+            let guard = self.page_latch(idx).latch.read();
+            if self.holds_page(storage_id, page_id, idx) {
+                return Ok(self.page_ref(storage_id, page_id, idx, guard));
+            }
+        }
+    }
+
+    /// Like `get_page`, but returns `None` rather than waiting for a writer to
+    /// release the page.
+    pub fn try_get_page(
+        &self,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<Option<PageRef<'_>>, MemCacheError> {
+        loop {
+            let idx = self.lookup(storage_id, page_id)?;
+            let Some(guard) = self.page_latch(idx).latch.try_read() else {
+                return Ok(None);
+            };
+            if self.holds_page(storage_id, page_id, idx) {
+                return Ok(Some(self.page_ref(storage_id, page_id, idx, guard)));
+            }
+        }
+    }
+
+    fn lookup(&self, storage_id: StorageId, page_id: PageId) -> Result<usize, MemCacheError> {
+        self.page_table
+            .lock()
+            .map
+            .get(&(storage_id, page_id))
+            .copied()
+            .ok_or(MemCacheError::PageNotFound)
+    }
+
+    /// Whether the frame still holds the page once latched: the page may have been
+    /// removed, and the frame reused, while waiting for the latch.
+    fn holds_page(&self, storage_id: StorageId, page_id: PageId, idx: usize) -> bool {
+        self.page_table.lock().map.get(&(storage_id, page_id)) == Some(&idx)
+    }
 
-        let latch = &self.pages_latch[idx].latch;
-        let _guard = latch.read();
+    fn page_ref<'a>(
+        &'a self,
+        storage_id: StorageId,
+        page_id: PageId,
+        idx: usize,
+        guard: RwLockReadGuard<'a, ()>,
+    ) -> PageRef<'a> {
         let page = unsafe { self.borrow_page(idx) };
         let metadata = unsafe { self.borrow_page_metadata(idx) };
         metadata.counter().fetch_add(1, Ordering::Relaxed);
@@ -320,12 +600,12 @@ impl MemCache {
             eviction_policy.set_unevictable(storage_id, page_id);
         }
 
-        Ok(PageRef {
-            _guard,
+        PageRef {
+            _guard: guard,
             page,
             metadata,
             eviction_policy: &self.eviction_policy,
-        })
+        }
     }
 
     pub fn get_page_mut(
@@ -333,17 +613,15 @@ impl MemCache {
         storage_id: StorageId,
         page_id: PageId,
     ) -> Result<PageRefMut<'_>, MemCacheError> {
-        let idx = {
-            let page_table = self.page_table.lock();
-            page_table
-                .map
-                .get(&(storage_id, page_id))
-                .copied()
-                .ok_or(MemCacheError::PageNotFound)?
+        let (idx, latch, _guard) = loop {
+            let idx = self.lookup(storage_id, page_id)?;
+            let latch = self.page_latch(idx);
+            let guard = latch.latch.write();
+            if self.holds_page(storage_id, page_id, idx) {
+                break (idx, latch, guard);
+            }
         };
-
-        let latch = &self.pages_latch[idx].latch;
-        let _guard = latch.write();
+        latch.begin_write();
         let page = unsafe { self.borrow_page_mut(idx) };
         let metadata = unsafe { self.borrow_page_metadata_mut(idx) };
         let old_counter = metadata.counter().fetch_add(1, Ordering::Relaxed);
@@ -357,6 +635,7 @@ impl MemCache {
 
         Ok(PageRefMut {
             _guard,
+            latch,
             page,
             metadata,
             eviction_policy: &self.eviction_policy,
@@ -376,8 +655,60 @@ impl MemCache {
                 .ok_or(MemCacheError::Full)?
         };
 
-        let latch = &self.pages_latch[idx].latch;
-        let _guard = latch.write();
+        self.new_page_mut_in_frame(idx, storage_id, page_id)
+    }
+
+    /// Like `new_page_mut`, but takes a frame beyond the capacity of the cache when no frame
+    /// is free, up to `CONFIG.PAGE_CACHE_RESERVE` frames.
+    ///
+    /// Borrowed frames are in excess of the capacity: they are given back (retired) as pages
+    /// are removed from the cache.
+    pub fn new_page_mut_from_reserve(
+        &self,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<PageRefMut<'_>, MemCacheError> {
+        let idx = {
+            let mut page_table = self.page_table.lock();
+            if let Some(idx) = page_table.free_list.pop_front() {
+                idx
+            } else {
+                if page_table.excess() >= CONFIG.PAGE_CACHE_RESERVE {
+                    return Err(MemCacheError::Full);
+                }
+                if page_table.retired.is_empty() {
+                    // Map the whole reserve at once, the frames stay mapped once retired.
+                    let slot = self
+                        .regions
+                        .iter()
+                        .find(|region| region.get().is_none())
+                        .ok_or(MemCacheError::TooManyRegions)?;
+                    let start = page_table.num_frames;
+                    let reserve = CONFIG.PAGE_CACHE_RESERVE;
+                    let _ = slot.set(FrameRegion::try_new(start, reserve)?);
+                    page_table.retired.extend(start..start + reserve);
+                    page_table.num_frames += reserve;
+                }
+                page_table.retired.pop().expect("reserve is mapped")
+            }
+        };
+
+        self.new_page_mut_in_frame(idx, storage_id, page_id)
+    }
+
+    /// Maps a page to a frame taken out of the free list or the reserve.
+    ///
+    /// Fails with `PageExists`, and gives the frame back, if another thread
+    /// added the page since the caller found it missing.
+    fn new_page_mut_in_frame(
+        &self,
+        idx: usize,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<PageRefMut<'_>, MemCacheError> {
+        let latch = self.page_latch(idx);
+        let _guard = latch.latch.write();
+        latch.begin_write();
         let page = unsafe { self.borrow_page_mut(idx) };
         let metadata = unsafe { self.borrow_page_metadata_mut(idx) };
         *metadata = PageMetadata::new(storage_id, page_id);
@@ -386,7 +717,16 @@ impl MemCache {
 
         {
             let mut page_table = self.page_table.lock();
-            assert!(!page_table.map.contains_key(&(storage_id, page_id)));
+            if page_table.map.contains_key(&(storage_id, page_id)) {
+                metadata.counter().fetch_sub(1, Ordering::Relaxed);
+                latch.end_write();
+                if page_table.excess() > 0 {
+                    self.retire_frame(&mut page_table, idx);
+                } else {
+                    page_table.free_list.push_back(idx);
+                }
+                return Err(MemCacheError::PageExists);
+            }
             page_table.map.insert((storage_id, page_id), idx);
         }
 
@@ -398,12 +738,69 @@ impl MemCache {
 
         Ok(PageRefMut {
             _guard,
+            latch,
             page,
             metadata,
             eviction_policy: &self.eviction_policy,
         })
     }
 
+    /// Copies a page into `page` without latching it, nor pinning it or
+    /// recording the access unless the eviction policy is free. Returns the
+    /// version the page was copied at, `None` if a writer held its latch
+    /// meanwhile.
+    ///
+    /// The frame of the page may be given to another page or retired while it
+    /// is copied, its version then changes too.
+    pub fn read_page_optimistic(
+        &self,
+        storage_id: StorageId,
+        page_id: PageId,
+        page: &mut Page,
+    ) -> Result<Option<PageVersion<'_>>, MemCacheError> {
+        let idx = self.lookup(storage_id, page_id)?;
+
+        let latch = self.page_latch(idx);
+        let version = latch.version.load(Ordering::Acquire);
+        if version % 2 == 1 {
+            return Ok(None);
+        }
+        // SAFETY: frames are never unmapped, the data may be torn by a writer
+        // but is discarded if so. Volatile reads keep the compiler from
+        // assuming it doesn't change.
+        let (data, metadata) = unsafe {
+            let frame = self.borrow_page(idx) as *const Page;
+            let metadata = self.borrow_page_metadata(idx) as *const PageMetadata;
+            (
+                std::ptr::read_volatile(&raw const (*frame).data),
+                std::ptr::read_volatile(metadata),
+            )
+        };
+        let version = PageVersion { latch, version };
+        // The frame was mapped to another page before the version was read.
+        if !version.is_valid()
+            || metadata.storage_id() != storage_id
+            || metadata.page_id() != page_id
+        {
+            return Ok(None);
+        }
+        page.data = data;
+
+        if let Some(mut eviction_policy) = self.eviction_policy.try_lock() {
+            eviction_policy.record_access(storage_id, page_id);
+        }
+
+        Ok(Some(version))
+    }
+
+    /// Returns whether a page is in the cache.
+    pub fn contains(&self, storage_id: StorageId, page_id: PageId) -> bool {
+        self.page_table
+            .lock()
+            .map
+            .contains_key(&(storage_id, page_id))
+    }
+
     pub fn remove_page(&self, storage_id: StorageId, page_id: PageId) -> Result<(), MemCacheError> {
         let idx = {
             let mut page_table = self.page_table.lock();
@@ -413,15 +810,21 @@ impl MemCache {
                 .ok_or(MemCacheError::PageNotFound)?
         };
 
-        let latch = &self.pages_latch[idx].latch;
-        let _guard = latch.write();
+        let latch = self.page_latch(idx);
+        let _guard = latch.latch.write();
+        latch.begin_write();
+        latch.end_write();
         let metadata = unsafe { self.borrow_page_metadata(idx) };
         assert_eq!(metadata.counter().load(Ordering::Relaxed), 0);
 
         self.eviction_policy.lock().remove(storage_id, page_id);
         {
             let mut page_table = self.page_table.lock();
-            page_table.free_list.push_back(idx);
+            if page_table.excess() > 0 {
+                self.retire_frame(&mut page_table, idx);
+            } else {
+                page_table.free_list.push_back(idx);
+            }
         }
 
         Ok(())
@@ -430,24 +833,99 @@ impl MemCache {
     pub fn evict(&self) -> Option<(StorageId, PageId)> {
         let page_table = self.page_table.lock();
 
-        if page_table.free_list.is_empty() {
+        if page_table.free_list.is_empty() || page_table.excess() > 0 {
             self.eviction_policy.lock().evict()
         } else {
             None
         }
     }
+
+    /// Like `evict`, but returns up to `n` victims, taking the locks once.
+    pub fn evict_n(&self, n: usize) -> Vec<(StorageId, PageId)> {
+        let page_table = self.page_table.lock();
+
+        if page_table.free_list.is_empty() || page_table.excess() > 0 {
+            let mut eviction_policy = self.eviction_policy.lock();
+            std::iter::from_fn(|| eviction_policy.evict())
+                .take(n)
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Removes pages from the cache, taking the page table lock once.
+    ///
+    /// Pages that are not in the cache or are in use are skipped. Returns the number of
+    /// pages removed.
+    pub fn remove_pages(&self, pages: &[(StorageId, PageId)]) -> usize {
+        let mut page_table = self.page_table.lock();
+        self.remove_pages_locked(&mut page_table, pages.iter().copied())
+    }
+
+    /// Removes all the pages of a storage from the cache, without writing them back.
+    ///
+    /// Pages in use are skipped, see `remove_pages`.
+    pub fn remove_storage(&self, storage_id: StorageId) -> usize {
+        let mut page_table = self.page_table.lock();
+        let pages = page_table
+            .map
+            .keys()
+            .filter(|(id, _)| *id == storage_id)
+            .copied()
+            .collect::<Vec<_>>();
+        self.remove_pages_locked(&mut page_table, pages)
+    }
+
+    fn remove_pages_locked(
+        &self,
+        page_table: &mut PageTable,
+        pages: impl IntoIterator<Item = (StorageId, PageId)>,
+    ) -> usize {
+        let mut eviction_policy = self.eviction_policy.lock();
+        let mut removed = 0;
+
+        for (storage_id, page_id) in pages {
+            let Some(&idx) = page_table.map.get(&(storage_id, page_id)) else {
+                continue;
+            };
+            // Latches are taken before the page table lock elsewhere: don't wait for one
+            // while holding the lock. A latched page is in use anyway.
+            let latch = self.page_latch(idx);
+            let Some(_guard) = latch.latch.try_write() else {
+                continue;
+            };
+            let metadata = unsafe { self.borrow_page_metadata(idx) };
+            if metadata.counter().load(Ordering::Relaxed) != 0 {
+                continue;
+            }
+            latch.begin_write();
+            latch.end_write();
+
+            page_table.map.remove(&(storage_id, page_id));
+            eviction_policy.remove(storage_id, page_id);
+            if page_table.excess() > 0 {
+                self.retire_frame(page_table, idx);
+            } else {
+                page_table.free_list.push_back(idx);
+            }
+            removed += 1;
+        }
+
+        removed
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use std::sync::Arc;
+    use crate::clock::SystemClock;
 
     #[test]
     fn high_contention_scenario() {
         let storage_id = StorageId(0);
-        let cache = Arc::new(MemCache::try_new().unwrap());
+        let cache = Arc::new(MemCache::try_with_clock(Arc::new(SystemClock)).unwrap());
 
         let mut handles = vec![];
 
@@ -484,4 +962,87 @@ mod tests {
             handle.join().unwrap();
         }
     }
+
+    #[test]
+    fn bulk_operations() {
+        let cache = MemCache::try_with_clock(Arc::new(SystemClock)).unwrap();
+        cache.resize(8).unwrap();
+        for storage_id in 0..2 {
+            for page_id in 0..4 {
+                let _ = cache
+                    .new_page_mut(StorageId(storage_id), PageId::new(page_id))
+                    .unwrap();
+            }
+        }
+
+        // Pinned pages are skipped.
+        let pinned = cache.get_page(StorageId(0), PageId::new(0)).unwrap();
+        assert_eq!(cache.remove_storage(StorageId(0)), 3);
+        assert!(cache.get_page(StorageId(0), PageId::new(1)).is_err());
+        assert!(cache.get_page(StorageId(1), PageId::new(1)).is_ok());
+        drop(pinned);
+
+        // Victims are only returned when the cache is full.
+        assert!(cache.evict_n(2).is_empty());
+        for page_id in 1..4 {
+            let _ = cache
+                .new_page_mut(StorageId(2), PageId::new(page_id))
+                .unwrap();
+        }
+        let victims = cache.evict_n(2);
+        assert_eq!(victims.len(), 2);
+        assert_eq!(cache.remove_pages(&victims), 2);
+        assert_eq!(cache.remove_pages(&victims), 0);
+        assert!(cache.new_page_mut(StorageId(3), PageId::new(0)).is_ok());
+    }
+
+    #[test]
+    fn read_page_optimistic() {
+        let cache = MemCache::try_with_clock(Arc::new(SystemClock)).unwrap();
+        let (storage_id, page_id) = (StorageId(0), PageId::new(1));
+        let mut page = Page::new();
+        assert!(matches!(
+            cache.read_page_optimistic(storage_id, page_id, &mut page),
+            Err(MemCacheError::PageNotFound)
+        ));
+
+        let mut page_ref = cache.new_page_mut(storage_id, page_id).unwrap();
+        page_ref.page_mut().data[0] = 42;
+        // The page is being written.
+        assert!(
+            cache
+                .read_page_optimistic(storage_id, page_id, &mut page)
+                .unwrap()
+                .is_none()
+        );
+        drop(page_ref);
+
+        let version = cache
+            .read_page_optimistic(storage_id, page_id, &mut page)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.data[0], 42);
+        // Readers don't change the version.
+        drop(cache.get_page(storage_id, page_id).unwrap());
+        assert!(version.is_valid());
+        drop(cache.get_page_mut(storage_id, page_id).unwrap());
+        assert!(!version.is_valid());
+
+        // A downgraded page can be read again.
+        let version = cache
+            .read_page_optimistic(storage_id, page_id, &mut page)
+            .unwrap()
+            .unwrap();
+        let page_ref = cache.get_page_mut(storage_id, page_id).unwrap().downgrade();
+        assert!(!version.is_valid());
+        let version = cache
+            .read_page_optimistic(storage_id, page_id, &mut page)
+            .unwrap()
+            .unwrap();
+        drop(page_ref);
+        assert!(version.is_valid());
+
+        cache.remove_page(storage_id, page_id).unwrap();
+        assert!(!version.is_valid());
+    }
 }
@@ -2,11 +2,13 @@ mod lru;
 // mod lruk;
 mod memcache;
 mod pagecache;
+mod usage;
 
 use crate::pages::PageId;
 use crate::storage::StorageId;
 
 pub const DEFAULT_PAGE_CACHE_SIZE: usize = 20000;
+pub const DEFAULT_PAGE_CACHE_RESERVE: usize = 64;
 
 pub trait EvictionPolicy: Send + Sync {
     fn record_access(&mut self, storage_id: StorageId, page_id: PageId);
@@ -16,5 +18,6 @@ pub trait EvictionPolicy: Send + Sync {
     fn remove(&mut self, storage_id: StorageId, page_id: PageId);
 }
 
-pub use memcache::{PageRef, PageRefMut};
-pub use pagecache::{GLOBAL_PAGE_CACHE, PageCache, PageCacheError, StoragePageCache};
+pub use memcache::{PageRef, PageRefMut, PageVersion};
+pub use pagecache::{GLOBAL_PAGE_CACHE, PageCache, PageCacheError, StoragePageCache, SyncMode};
+pub use usage::BufferUsage;
@@ -1,26 +1,74 @@
-use std::collections::{BTreeSet, HashMap};
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, LazyLock};
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
+use crate::cache::BufferUsage;
 use crate::cache::memcache::MemCache;
+use crate::clock::{ClockSource, SystemClock};
 use crate::config::CONFIG;
-use crate::pages::{PageId, PageMetadata};
-use crate::storage::{FileStorage, StorageBackend, StorageError, StorageId};
+use crate::pages::{Page, PageId, PageMetadata};
+use crate::storage::{FileStorage, StorageBackend, StorageError, StorageId, StorageStats};
 
-use super::memcache::{MemCacheError, PageRef, PageRefMut};
+use super::memcache::{MemCacheError, PageRef, PageRefMut, PageVersion};
 use parking_lot::{Mutex, RwLock};
 use thiserror::Error;
 
 pub static GLOBAL_PAGE_CACHE: LazyLock<PageCache<FileStorage>> =
     LazyLock::new(|| PageCache::try_new().expect("Could not initialize global page cache"));
 
+/// The number of dirty pages the writeback hands to storage at once.
+const WRITEBACK_BATCH: usize = 32;
+
+/// The number of pages read in a row, each following the previous one, that
+/// starts read-ahead.
+const READ_AHEAD_TRIGGER: u32 = 3;
+
+/// How long freeing pages waits for the ones still in use, e.g. held by a
+/// writeback in progress until written, see `PageCacheInner::free_pages`.
+const FREE_PAGES_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Error, Debug)]
 pub enum PageCacheError {
     #[error("storage")]
     Storage(#[from] StorageError),
     #[error("memcache")]
     MemCache(#[from] MemCacheError),
+    #[error("pages still in use")]
+    PagesInUse,
+}
+
+/// When dirty pages written back to storage are made durable.
+///
+/// The mode of the page cache applies to every storage but the ones given their
+/// own, see `StoragePageCache::set_sync_mode`. Both can be changed at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SyncMode {
+    /// Storage is fsynced after each writeback round and page eviction: a crash
+    /// loses at most the pages dirtied during the last writeback interval.
+    Interval = 0,
+    /// Storage is never fsynced, durability is left to the operating system.
+    /// A crash (of the host, not only of the process) may lose any write or
+    /// leave a mix of old and new pages. Meant for benchmarks and ephemeral data.
+    Off = 1,
+    /// As `Interval`, and the dirty pages of a storage are written back and
+    /// fsynced when its writes are committed, see `StoragePageCache::commit`:
+    /// a crash loses no committed write.
+    Full = 2,
+}
+
+impl SyncMode {
+    fn from_u8(mode: u8) -> Self {
+        match mode {
+            0 => SyncMode::Interval,
+            1 => SyncMode::Off,
+            2 => SyncMode::Full,
+            _ => unreachable!(),
+        }
+    }
 }
 
 /// A cache that manages pages in memory and interacts with the on-disk storage.
@@ -36,17 +84,28 @@ pub struct PageCache<S: StorageBackend + 'static> {
 impl<S: StorageBackend + 'static> PageCache<S> {
     /// Creates a new `PageCache`.
     pub fn try_new() -> Result<Self, PageCacheError> {
+        Self::try_with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates a new `PageCache` whose eviction policy reads time from `clock`.
+    pub fn try_with_clock(clock: Arc<dyn ClockSource>) -> Result<Self, PageCacheError> {
+        let (read_ahead_tx, read_ahead_rx) = mpsc::channel();
         let pagecache = Self {
             inner: Arc::new(PageCacheInner {
                 next_storage_id: AtomicU32::new(0),
                 storage_backends: RwLock::new(HashMap::new()),
-                mem_cache: MemCache::try_new().map_err(PageCacheError::MemCache)?,
+                mem_cache: MemCache::try_with_clock(clock).map_err(PageCacheError::MemCache)?,
                 dirty_pages: Mutex::new(None),
+                writeback_lock: Mutex::new(()),
                 writeback_jh: Mutex::new(None),
+                sync_mode: AtomicU8::new(CONFIG.SYNC_MODE as u8),
+                storage_sync_modes: RwLock::new(HashMap::new()),
+                read_ahead_tx,
             }),
         };
         let jh = Self::writeback_thread(&pagecache);
         *pagecache.writeback_jh.lock() = Some(jh);
+        Self::read_ahead_thread(&pagecache, read_ahead_rx);
 
         Ok(pagecache)
     }
@@ -62,6 +121,8 @@ impl<S: StorageBackend + 'static> PageCache<S> {
                 inner: Arc::clone(&self.inner),
             },
             storage_id,
+            buffer_usage: None,
+            read_ahead: Arc::default(),
         }
     }
 
@@ -78,6 +139,22 @@ impl<S: StorageBackend + 'static> PageCache<S> {
             }
         })
     }
+
+    /// Runs a background thread to prefetch the pages requested by read-ahead.
+    ///
+    /// Thread stops when the page cache is dropped, along with the sender. It
+    /// isn't joined: it may drop the last reference to the page cache itself.
+    fn read_ahead_thread(&self, requests: Receiver<(StorageId, PageId, PageId)>) {
+        let weak = Arc::downgrade(&self.inner);
+        std::thread::spawn(move || {
+            while let Ok((storage_id, first, last)) = requests.recv() {
+                let Some(pagecache) = weak.upgrade() else {
+                    break;
+                };
+                pagecache.prefetch(storage_id, first, last);
+            }
+        });
+    }
 }
 
 impl<S: StorageBackend + 'static> std::ops::Deref for PageCache<S> {
@@ -93,7 +170,13 @@ pub struct PageCacheInner<S: StorageBackend + 'static> {
     storage_backends: RwLock<HashMap<StorageId, S>>,
     mem_cache: MemCache,
     dirty_pages: Mutex<Option<HashMap<StorageId, BTreeSet<PageId>>>>,
+    // Held during a writeback, for `flush` to wait for the one in progress.
+    writeback_lock: Mutex<()>,
     writeback_jh: Mutex<Option<JoinHandle<()>>>,
+    sync_mode: AtomicU8,
+    // The storages given their own sync mode, see `storage_sync_mode`.
+    storage_sync_modes: RwLock<HashMap<StorageId, SyncMode>>,
+    read_ahead_tx: Sender<(StorageId, PageId, PageId)>,
 }
 
 impl<S: StorageBackend + 'static> Drop for PageCacheInner<S> {
@@ -116,22 +199,221 @@ impl<S: StorageBackend + 'static> PageCacheInner<S> {
         let storage = guard.get(&storage_id).unwrap();
         let page_id = storage.allocate_page()?;
 
-        // try evict a page if the memory cache is full
+        // try evict pages while the memory cache is full or above its capacity
         // FIXME: race condition
-        if let Some((storage_id, page_id)) = self.mem_cache.evict() {
-            if let Ok(page) = self.mem_cache.get_page(storage_id, page_id) {
-                storage.write_page(&page, page_id)?;
-                storage.fsync();
-            };
+        while let Some((storage_id, page_id)) = self.mem_cache.evict() {
+            self.evict_page(&guard, storage_id, page_id)?;
+        }
 
-            self.mem_cache.remove_page(storage_id, page_id)?;
+        match self.new_frame(&guard, storage_id, page_id) {
+            // Read meanwhile, e.g. by a scan reaching the end of the file: the
+            // page read is the new one written by `allocate_page`.
+            Err(PageCacheError::MemCache(MemCacheError::PageExists)) => {
+                drop(guard);
+                self.get_page_mut(storage_id, page_id)
+            }
+            result => result,
         }
+    }
+
+    /// Gets a frame of the memory cache for a page.
+    ///
+    /// When the cache is full, evicts a victim and retries. When every page is pinned and
+    /// nothing can be evicted, borrows a frame from the reserve so that a spike of pinned
+    /// pages doesn't fail the query. `MemCacheError::Full` is returned once the reserve is
+    /// exhausted too.
+    fn new_frame(
+        &self,
+        storage_backends: &HashMap<StorageId, S>,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<PageRefMut<'_>, PageCacheError> {
+        match self.new_frame_evicting(storage_backends, storage_id, page_id) {
+            Err(PageCacheError::MemCache(MemCacheError::Full)) => self
+                .mem_cache
+                .new_page_mut_from_reserve(storage_id, page_id)
+                .map_err(PageCacheError::MemCache),
+            result => result,
+        }
+    }
+
+    /// Like `new_frame`, without borrowing from the reserve: `MemCacheError::Full` is
+    /// returned as soon as nothing can be evicted.
+    fn new_frame_evicting(
+        &self,
+        storage_backends: &HashMap<StorageId, S>,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<PageRefMut<'_>, PageCacheError> {
+        loop {
+            match self.mem_cache.new_page_mut(storage_id, page_id) {
+                Err(MemCacheError::Full) => {}
+                result => return result.map_err(PageCacheError::MemCache),
+            }
+
+            let Some((victim_storage_id, victim_page_id)) = self.mem_cache.evict() else {
+                return Err(PageCacheError::MemCache(MemCacheError::Full));
+            };
+            self.evict_page(storage_backends, victim_storage_id, victim_page_id)?;
+        }
+    }
+
+    /// Writes a page back to its storage and removes it from the memory cache.
+    fn evict_page(
+        &self,
+        storage_backends: &HashMap<StorageId, S>,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<(), PageCacheError> {
+        if let Ok(page) = self.mem_cache.get_page(storage_id, page_id) {
+            let storage = storage_backends.get(&storage_id).unwrap();
+            storage.write_page(&page, page_id)?;
+            self.fsync(storage_id, storage);
+        };
 
         self.mem_cache
-            .new_page_mut(storage_id, page_id)
+            .remove_page(storage_id, page_id)
             .map_err(PageCacheError::MemCache)
     }
 
+    /// Returns the number of pages the cache can hold.
+    pub fn capacity(&self) -> usize {
+        self.mem_cache.capacity()
+    }
+
+    /// Resizes the cache to hold `num_pages` pages.
+    ///
+    /// Shrinking drains the pages in excess through the eviction policy, writing them back
+    /// to storage. Pinned pages can't be evicted: their frames are released later, when the
+    /// pages are evicted.
+    pub fn resize(&self, num_pages: usize) -> Result<(), PageCacheError> {
+        self.mem_cache
+            .resize(num_pages)
+            .map_err(PageCacheError::MemCache)?;
+
+        let guard = self.storage_backends.read();
+        loop {
+            let victims = self.mem_cache.evict_n(self.mem_cache.excess_frames());
+            if victims.is_empty() {
+                break;
+            }
+            self.evict_pages(&guard, &victims)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `evict_page` for a batch of victims: each storage is fsynced once and the pages
+    /// are removed from the memory cache at once.
+    fn evict_pages(
+        &self,
+        storage_backends: &HashMap<StorageId, S>,
+        victims: &[(StorageId, PageId)],
+    ) -> Result<(), PageCacheError> {
+        let mut written = HashSet::new();
+        for &(storage_id, page_id) in victims {
+            if let Ok(page) = self.mem_cache.get_page(storage_id, page_id) {
+                let storage = storage_backends.get(&storage_id).unwrap();
+                storage.write_page(&page, page_id)?;
+                written.insert(storage_id);
+            }
+        }
+        for storage_id in written {
+            self.fsync(storage_id, storage_backends.get(&storage_id).unwrap());
+        }
+
+        self.mem_cache.remove_pages(victims);
+        Ok(())
+    }
+
+    /// Removes a storage from the cache and returns it.
+    ///
+    /// Its cached pages are dropped without being written back, as when the table stored
+    /// in it is dropped. Pages still in use stay in the cache until evicted.
+    pub fn detach_storage(&self, storage_id: StorageId) -> Option<S> {
+        let mut guard = self.storage_backends.write();
+        let storage = guard.remove(&storage_id)?;
+        if let Some(dirty_pages) = self.dirty_pages.lock().as_mut() {
+            dirty_pages.remove(&storage_id);
+        }
+        self.storage_sync_modes.write().remove(&storage_id);
+        self.mem_cache.remove_storage(storage_id);
+
+        Some(storage)
+    }
+
+    /// Removes the pages of a storage after `last_page_id`, from the cache
+    /// without writing them back and from the storage.
+    ///
+    /// The pages removed must not be in use.
+    pub fn truncate_storage(
+        &self,
+        storage_id: StorageId,
+        last_page_id: PageId,
+    ) -> Result<(), PageCacheError> {
+        let guard = self.storage_backends.read();
+        let storage = guard.get(&storage_id).unwrap();
+        let removed = (last_page_id.get() + 1..=storage.last_page_id().get())
+            .map(|page_id| (storage_id, PageId::new(page_id)))
+            .collect::<Vec<_>>();
+        if let Some(dirty_pages) = self.dirty_pages.lock().as_mut()
+            && let Some(page_ids) = dirty_pages.get_mut(&storage_id)
+        {
+            page_ids.retain(|&page_id| page_id <= last_page_id);
+        }
+        self.mem_cache.remove_pages(&removed);
+
+        storage.truncate(last_page_id).map_err(PageCacheError::from)
+    }
+
+    /// Frees the pages of a storage from `first` to `last`, e.g. a range left
+    /// unused in the middle of the file: they are removed from the cache
+    /// without writing them back and their disk space is given back, see
+    /// `StorageBackend::free_pages`.
+    ///
+    /// The pages freed must not be in use: the ones held meanwhile, e.g. by a
+    /// writeback in progress, are waited for, up to `FREE_PAGES_TIMEOUT`.
+    /// Fails with a `PageCacheError::PagesInUse` if some are still held, their
+    /// content is then lost but their disk space is kept.
+    pub fn free_pages(
+        &self,
+        storage_id: StorageId,
+        first: PageId,
+        last: PageId,
+    ) -> Result<(), PageCacheError> {
+        let guard = self.storage_backends.read();
+        let storage = guard.get(&storage_id).unwrap();
+        let freed = (first.get()..=last.get())
+            .map(|page_id| (storage_id, PageId::new(page_id)))
+            .collect::<Vec<_>>();
+        if let Some(dirty_pages) = self.dirty_pages.lock().as_mut()
+            && let Some(page_ids) = dirty_pages.get_mut(&storage_id)
+        {
+            page_ids.retain(|&page_id| page_id < first || page_id > last);
+        }
+        // A writeback in progress may hold some of them until they're written:
+        // nothing signals their release, their pin counts are polled.
+        let deadline = Instant::now() + FREE_PAGES_TIMEOUT;
+        let mut backoff = Duration::from_micros(10);
+        let mut cached = freed;
+        loop {
+            self.mem_cache.remove_pages(&cached);
+            cached.retain(|&(storage_id, page_id)| self.mem_cache.contains(storage_id, page_id));
+            if cached.is_empty() {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(PageCacheError::PagesInUse);
+            }
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(Duration::from_millis(10));
+        }
+
+        storage
+            .free_pages(first, last)
+            .map_err(PageCacheError::from)
+    }
+
     /// Retrieves a a read-only reference to a page from the cache.
     ///
     /// If the page is not in the cache, it will be fetched from the disk.
@@ -140,23 +422,88 @@ impl<S: StorageBackend + 'static> PageCacheInner<S> {
         storage_id: StorageId,
         page_id: PageId,
     ) -> Result<PageRef<'_>, PageCacheError> {
-        if let Ok(page) = self.mem_cache.get_page(storage_id, page_id) {
-            Ok(page)
-        } else {
-            let mut new_page_ref = self
-                .mem_cache
-                .new_page_mut(storage_id, page_id)
-                .map_err(PageCacheError::MemCache)?;
+        self.get_page_with_usage(storage_id, page_id, None)
+    }
 
-            {
-                let guard = self.storage_backends.read();
-                let storage = guard.get(&storage_id).unwrap();
-                storage
-                    .read_page(page_id, new_page_ref.page_mut())
-                    .map_err(PageCacheError::Storage)?;
+    fn get_page_with_usage(
+        &self,
+        storage_id: StorageId,
+        page_id: PageId,
+        buffer_usage: Option<&BufferUsage>,
+    ) -> Result<PageRef<'_>, PageCacheError> {
+        loop {
+            if let Ok(page) = self.mem_cache.get_page(storage_id, page_id) {
+                if let Some(buffer_usage) = buffer_usage {
+                    buffer_usage.record_hit();
+                }
+                return Ok(page);
+            }
+            if let Some(new_page_ref) = self.read_frame(storage_id, page_id)? {
+                if let Some(buffer_usage) = buffer_usage {
+                    buffer_usage.record_miss();
+                }
+                return Ok(new_page_ref.downgrade());
             }
+        }
+    }
 
-            Ok(new_page_ref.downgrade())
+    /// Reads a page missing from the memory cache into a new frame. Returns
+    /// `None` if another thread, e.g. read-ahead, added it meanwhile.
+    fn read_frame(
+        &self,
+        storage_id: StorageId,
+        page_id: PageId,
+    ) -> Result<Option<PageRefMut<'_>>, PageCacheError> {
+        let guard = self.storage_backends.read();
+        let mut new_page_ref = match self.new_frame(&guard, storage_id, page_id) {
+            Err(PageCacheError::MemCache(MemCacheError::PageExists)) => return Ok(None),
+            result => result?,
+        };
+        let storage = guard.get(&storage_id).unwrap();
+        if let Err(e) = storage.read_page(page_id, new_page_ref.page_mut()) {
+            // The frame must not be found later on, with garbage in it.
+            drop(new_page_ref);
+            self.mem_cache.remove_pages(&[(storage_id, page_id)]);
+            return Err(PageCacheError::Storage(e));
+        }
+        Ok(Some(new_page_ref))
+    }
+
+    /// Reads the pages `first..=last` missing from the memory cache, at once.
+    ///
+    /// The reserve is left to the queries: prefetching stops at the first page
+    /// without a frame once nothing can be evicted.
+    fn prefetch(&self, storage_id: StorageId, first: PageId, last: PageId) {
+        let guard = self.storage_backends.read();
+        // The storage was detached since the pages were requested.
+        let Some(storage) = guard.get(&storage_id) else {
+            return;
+        };
+
+        let last = last.min(storage.last_page_id());
+        let mut frames = Vec::new();
+        for page_id in (first.get()..=last.get()).map(PageId::new) {
+            if self.mem_cache.contains(storage_id, page_id) {
+                continue;
+            }
+            match self.new_frame_evicting(&guard, storage_id, page_id) {
+                Ok(page_ref) => frames.push((page_id, page_ref)),
+                Err(PageCacheError::MemCache(MemCacheError::PageExists)) => {}
+                Err(_) => break,
+            }
+        }
+
+        let mut pages = frames
+            .iter_mut()
+            .map(|(page_id, page_ref)| (*page_id, page_ref.page_mut()))
+            .collect::<Vec<_>>();
+        if storage.read_pages(&mut pages).is_err() {
+            let removed = frames
+                .iter()
+                .map(|(page_id, _)| (storage_id, *page_id))
+                .collect::<Vec<_>>();
+            drop(frames);
+            self.mem_cache.remove_pages(&removed);
         }
     }
 
@@ -168,21 +515,28 @@ impl<S: StorageBackend + 'static> PageCacheInner<S> {
         storage_id: StorageId,
         page_id: PageId,
     ) -> Result<PageRefMut<'_>, PageCacheError> {
-        if let Ok(page) = self.mem_cache.get_page_mut(storage_id, page_id) {
-            Ok(page)
-        } else {
-            let mut new_page_ref = self
-                .mem_cache
-                .new_page_mut(storage_id, page_id)
-                .map_err(PageCacheError::MemCache)?;
-
-            let guard = self.storage_backends.read();
-            let storage = guard.get(&storage_id).unwrap();
-            storage
-                .read_page(page_id, new_page_ref.page_mut())
-                .map_err(PageCacheError::Storage)?;
+        self.get_page_mut_with_usage(storage_id, page_id, None)
+    }
 
-            Ok(new_page_ref)
+    fn get_page_mut_with_usage(
+        &self,
+        storage_id: StorageId,
+        page_id: PageId,
+        buffer_usage: Option<&BufferUsage>,
+    ) -> Result<PageRefMut<'_>, PageCacheError> {
+        loop {
+            if let Ok(page) = self.mem_cache.get_page_mut(storage_id, page_id) {
+                if let Some(buffer_usage) = buffer_usage {
+                    buffer_usage.record_hit();
+                }
+                return Ok(page);
+            }
+            if let Some(new_page_ref) = self.read_frame(storage_id, page_id)? {
+                if let Some(buffer_usage) = buffer_usage {
+                    buffer_usage.record_miss();
+                }
+                return Ok(new_page_ref);
+            }
         }
     }
 
@@ -198,28 +552,134 @@ impl<S: StorageBackend + 'static> PageCacheInner<S> {
             .or_insert(BTreeSet::from([metadata.page_id()]));
     }
 
+    /// Writes all the dirty pages to storage, as the writeback thread does
+    /// periodically. A writeback in progress is waited for: pages must not be
+    /// held while flushing.
+    pub fn flush(&self) {
+        self.writeback_dirty_pages();
+    }
+
     fn writeback_dirty_pages(&self) {
+        // The pages taken by a writeback in progress are on disk once it
+        // returns.
+        let _writeback = self.writeback_lock.lock();
         // Storage io can block: get dirty pages and release the lock.
         let dirty_pages = self.dirty_pages.lock().take();
         if let Some(dirty_pages) = dirty_pages {
             for (storage_id, page_ids) in dirty_pages {
                 let guard = self.storage_backends.read();
-                let storage = guard.get(&storage_id).unwrap();
-
-                for page_id in page_ids {
-                    let page_ref = self
-                        .get_page(storage_id, page_id)
-                        .expect("writeback failed");
-                    if page_ref.metadata().is_dirty() {
-                        storage
-                            .write_page(page_ref.page(), page_id)
-                            .expect("write_page failed");
-                        page_ref.metadata().clear_dirty();
+                // The storage was detached after its pages were dirtied.
+                let Some(storage) = guard.get(&storage_id) else {
+                    continue;
+                };
+                self.writeback_storage(storage_id, storage, page_ids);
+            }
+        }
+    }
+
+    /// Writes the dirty pages of a storage if its sync mode is `SyncMode::Full`,
+    /// and fsyncs it: its writes are durable once this returns. Does nothing with
+    /// the other modes, their writes are made durable by the writeback.
+    pub fn commit(&self, storage_id: StorageId) {
+        if self.storage_sync_mode(storage_id) != SyncMode::Full {
+            return;
+        }
+        let _writeback = self.writeback_lock.lock();
+        let page_ids = self
+            .dirty_pages
+            .lock()
+            .as_mut()
+            .and_then(|dirty_pages| dirty_pages.remove(&storage_id));
+        let guard = self.storage_backends.read();
+        let Some(storage) = guard.get(&storage_id) else {
+            return;
+        };
+        // Without dirty pages, the ones written by evictions are fsynced.
+        self.writeback_storage(storage_id, storage, page_ids.unwrap_or_default());
+    }
+
+    /// Writes back dirty pages of a storage, then fsyncs it according to its
+    /// sync mode.
+    fn writeback_storage(&self, storage_id: StorageId, storage: &S, page_ids: BTreeSet<PageId>) {
+        let mut batch = Vec::with_capacity(WRITEBACK_BATCH);
+        for page_id in page_ids {
+            // The storage was truncated after the page was dirtied.
+            if page_id > storage.last_page_id() {
+                continue;
+            }
+            // The pages of the batch are held until written: waiting
+            // for another page while holding them could deadlock with
+            // a writer holding it and waiting for one of them. Pages
+            // missing from the cache were written back when evicted,
+            // or freed: they mustn't be read again.
+            let page_ref = match self.mem_cache.try_get_page(storage_id, page_id) {
+                Ok(Some(page_ref)) => page_ref,
+                Ok(None) => {
+                    Self::writeback_batch(storage, &mut batch);
+                    match self.mem_cache.get_page(storage_id, page_id) {
+                        Ok(page_ref) => page_ref,
+                        Err(_) => continue,
                     }
                 }
-                storage.fsync();
+                Err(_) => continue,
+            };
+            if page_ref.metadata().is_dirty() {
+                batch.push((page_id, page_ref));
+            }
+            if batch.len() == WRITEBACK_BATCH {
+                Self::writeback_batch(storage, &mut batch);
             }
         }
+        Self::writeback_batch(storage, &mut batch);
+        self.fsync(storage_id, storage);
+    }
+
+    /// Writes a batch of dirty pages at once and releases them.
+    fn writeback_batch(storage: &S, batch: &mut Vec<(PageId, PageRef<'_>)>) {
+        let pages = batch
+            .iter()
+            .map(|(page_id, page_ref)| (page_ref.page(), *page_id))
+            .collect::<Vec<_>>();
+        storage.write_pages(&pages).expect("write_page failed");
+        for (_, page_ref) in batch.drain(..) {
+            page_ref.metadata().clear_dirty();
+        }
+    }
+
+    /// Returns the current sync mode.
+    pub fn sync_mode(&self) -> SyncMode {
+        SyncMode::from_u8(self.sync_mode.load(Ordering::Relaxed))
+    }
+
+    /// Changes the sync mode, it applies to the next writeback or eviction.
+    pub fn set_sync_mode(&self, mode: SyncMode) {
+        self.sync_mode.store(mode as u8, Ordering::Relaxed);
+    }
+
+    /// Returns the sync mode of a storage: its own if it was given one, the
+    /// mode of the page cache otherwise.
+    pub fn storage_sync_mode(&self, storage_id: StorageId) -> SyncMode {
+        match self.storage_sync_modes.read().get(&storage_id) {
+            Some(&mode) => mode,
+            None => self.sync_mode(),
+        }
+    }
+
+    /// Gives a storage its own sync mode, or the mode of the page cache again
+    /// if `None`. It applies to the next writeback, eviction or commit.
+    pub fn set_storage_sync_mode(&self, storage_id: StorageId, mode: Option<SyncMode>) {
+        let mut storage_sync_modes = self.storage_sync_modes.write();
+        match mode {
+            Some(mode) => storage_sync_modes.insert(storage_id, mode),
+            None => storage_sync_modes.remove(&storage_id),
+        };
+    }
+
+    fn fsync(&self, storage_id: StorageId, storage: &S) {
+        match self.storage_sync_mode(storage_id) {
+            SyncMode::Interval | SyncMode::Full => storage.fsync(),
+            SyncMode::Off => {}
+        }
     }
 
     /// Retrives the first page from the storage backend.
@@ -235,6 +695,12 @@ impl<S: StorageBackend + 'static> PageCacheInner<S> {
         let storage = guard.get(&storage_id).unwrap();
         storage.last_page_id()
     }
+
+    /// Returns the I/O counters of the storage backend, if it keeps them.
+    pub fn storage_stats(&self, storage_id: StorageId) -> Option<Arc<StorageStats>> {
+        let guard = self.storage_backends.read();
+        guard.get(&storage_id)?.stats()
+    }
 }
 
 impl<S: StorageBackend> Clone for PageCache<S> {
@@ -251,6 +717,21 @@ impl<S: StorageBackend> Clone for PageCache<S> {
 pub struct StoragePageCache<S: StorageBackend + 'static> {
     pagecache: PageCache<S>,
     storage_id: StorageId,
+    buffer_usage: Option<Arc<BufferUsage>>,
+    read_ahead: Arc<ReadAhead>,
+}
+
+/// The run of pages read through a `StoragePageCache` and its clones, to
+/// detect sequential reads. Updates racing with each other only make the
+/// detection less accurate.
+#[derive(Default)]
+struct ReadAhead {
+    // The last page read.
+    last: AtomicU32,
+    // The number of pages read in a row, each following the previous one.
+    run: AtomicU32,
+    // The last page prefetched.
+    end: AtomicU32,
 }
 
 impl<S: StorageBackend> Clone for StoragePageCache<S> {
@@ -258,25 +739,151 @@ impl<S: StorageBackend> Clone for StoragePageCache<S> {
         Self {
             pagecache: self.pagecache.clone(),
             storage_id: self.storage_id,
+            buffer_usage: self.buffer_usage.clone(),
+            read_ahead: self.read_ahead.clone(),
         }
     }
 }
 
 impl<S: StorageBackend + 'static> StoragePageCache<S> {
+    /// Returns a page cache for the same storage accounting page accesses in `buffer_usage`.
+    pub fn with_buffer_usage(&self, buffer_usage: Arc<BufferUsage>) -> Self {
+        Self {
+            pagecache: self.pagecache.clone(),
+            storage_id: self.storage_id,
+            buffer_usage: Some(buffer_usage),
+            read_ahead: self.read_ahead.clone(),
+        }
+    }
+
+    pub fn buffer_usage(&self) -> Option<&BufferUsage> {
+        self.buffer_usage.as_deref()
+    }
+
     pub fn new_page(&self) -> Result<PageRefMut<'_>, PageCacheError> {
         self.pagecache.new_page(self.storage_id)
     }
 
+    /// Returns the sync mode of the storage, see `PageCacheInner::storage_sync_mode`.
+    pub fn sync_mode(&self) -> SyncMode {
+        self.pagecache.storage_sync_mode(self.storage_id)
+    }
+
+    /// Gives the storage its own sync mode, `None` for the mode of the page
+    /// cache. The clones of this cache share it.
+    pub fn set_sync_mode(&self, mode: Option<SyncMode>) {
+        self.pagecache.set_storage_sync_mode(self.storage_id, mode)
+    }
+
+    /// Commits the writes to the storage: they are durable once this returns
+    /// if its sync mode is `SyncMode::Full`, see `PageCacheInner::commit`.
+    pub fn commit(&self) {
+        self.pagecache.commit(self.storage_id)
+    }
+
+    /// Removes the storage from the shared page cache, see
+    /// `PageCache::detach_storage`. The clones of this cache can't be used
+    /// anymore.
+    pub fn detach_storage(&self) -> Option<S> {
+        self.pagecache.detach_storage(self.storage_id)
+    }
+
+    /// Reads a page, see `PageCache::get_page`. Once pages are read in
+    /// sequence, e.g. by a table scan, the next `CONFIG.READ_AHEAD_PAGES` ones
+    /// are prefetched in the background.
     pub fn get_page(&self, page_id: PageId) -> Result<PageRef<'_>, PageCacheError> {
-        self.pagecache.get_page(self.storage_id, page_id)
+        self.read_ahead(page_id);
+        self.pagecache
+            .get_page_with_usage(self.storage_id, page_id, self.buffer_usage())
+    }
+
+    fn read_ahead(&self, page_id: PageId) {
+        let window = CONFIG.READ_AHEAD_PAGES;
+        let page_id = page_id.get();
+        let last = self.read_ahead.last.swap(page_id, Ordering::Relaxed);
+        if window == 0 || page_id == last {
+            return;
+        }
+        if page_id != last.wrapping_add(1) {
+            self.read_ahead.run.store(1, Ordering::Relaxed);
+            self.read_ahead.end.store(page_id, Ordering::Relaxed);
+            return;
+        }
+
+        let run = self.read_ahead.run.fetch_add(1, Ordering::Relaxed) + 1;
+        let end = self.read_ahead.end.load(Ordering::Relaxed);
+        // Prefetch again once the reads are half way through the window.
+        if run < READ_AHEAD_TRIGGER || end >= page_id.saturating_add(window / 2) {
+            return;
+        }
+        let new_end = page_id.saturating_add(window);
+        self.read_ahead.end.store(new_end, Ordering::Relaxed);
+        let first = PageId::new(end.max(page_id) + 1);
+        let _ = self
+            .pagecache
+            .read_ahead_tx
+            .send((self.storage_id, first, PageId::new(new_end)));
     }
 
     pub fn set_page_dirty(&self, metadata: &PageMetadata) {
+        if let Some(buffer_usage) = self.buffer_usage()
+            && !metadata.is_dirty()
+        {
+            buffer_usage.record_dirtied();
+        }
         self.pagecache.set_page_dirty(self.storage_id, metadata);
     }
 
     pub fn get_page_mut(&self, page_id: PageId) -> Result<PageRefMut<'_>, PageCacheError> {
-        self.pagecache.get_page_mut(self.storage_id, page_id)
+        self.pagecache
+            .get_page_mut_with_usage(self.storage_id, page_id, self.buffer_usage())
+    }
+
+    /// Copies a page of the memory cache without latching it, see
+    /// `MemCache::read_page_optimistic`. Returns `None` if the page isn't in
+    /// the memory cache or was written while copied.
+    pub fn read_page_optimistic(
+        &self,
+        page_id: PageId,
+        page: &mut Page,
+    ) -> Option<PageVersion<'_>> {
+        let version = self
+            .pagecache
+            .mem_cache
+            .read_page_optimistic(self.storage_id, page_id, page)
+            .ok()??;
+        if let Some(buffer_usage) = self.buffer_usage() {
+            buffer_usage.record_hit();
+        }
+        Some(version)
+    }
+
+    /// Like `get_page`, but returns `None` rather than reading a page missing
+    /// from the memory cache.
+    pub fn get_cached_page(&self, page_id: PageId) -> Option<PageRef<'_>> {
+        let page_ref = self
+            .pagecache
+            .mem_cache
+            .get_page(self.storage_id, page_id)
+            .ok()?;
+        if let Some(buffer_usage) = self.buffer_usage() {
+            buffer_usage.record_hit();
+        }
+        Some(page_ref)
+    }
+
+    /// Like `get_page_mut`, but returns `None` rather than reading a page
+    /// missing from the memory cache.
+    pub fn get_cached_page_mut(&self, page_id: PageId) -> Option<PageRefMut<'_>> {
+        let page_ref = self
+            .pagecache
+            .mem_cache
+            .get_page_mut(self.storage_id, page_id)
+            .ok()?;
+        if let Some(buffer_usage) = self.buffer_usage() {
+            buffer_usage.record_hit();
+        }
+        Some(page_ref)
     }
 
     pub fn first_page_id(&self) -> PageId {
@@ -286,6 +893,21 @@ impl<S: StorageBackend + 'static> StoragePageCache<S> {
     pub fn last_page_id(&self) -> PageId {
         self.pagecache.last_page_id(self.storage_id)
     }
+
+    pub fn storage_stats(&self) -> Option<Arc<StorageStats>> {
+        self.pagecache.storage_stats(self.storage_id)
+    }
+
+    /// Removes the pages after `last_page_id`, see `PageCache::truncate_storage`.
+    pub fn truncate(&self, last_page_id: PageId) -> Result<(), PageCacheError> {
+        self.pagecache
+            .truncate_storage(self.storage_id, last_page_id)
+    }
+
+    /// Frees the pages from `first` to `last`, see `PageCache::free_pages`.
+    pub fn free_pages(&self, first: PageId, last: PageId) -> Result<(), PageCacheError> {
+        self.pagecache.free_pages(self.storage_id, first, last)
+    }
 }
 
 #[cfg(test)]
@@ -293,9 +915,11 @@ mod tests {
     use super::*;
 
     use crate::cache::DEFAULT_PAGE_CACHE_SIZE;
-    use crate::pages::PAGE_RESERVED;
+    use crate::pages::{PAGE_RESERVED, PAGE_SIZE, Page};
     use crate::storage::FileStorage;
 
+    use std::time::{Duration, Instant};
+
     use tempfile::NamedTempFile;
 
     #[test]
@@ -321,4 +945,321 @@ mod tests {
         drop(page0);
         drop(page1);
     }
+
+    #[test]
+    fn resize() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let file_cache = page_cache.cache_storage(storage);
+
+        for _ in 1..DEFAULT_PAGE_CACHE_SIZE {
+            file_cache.new_page().unwrap();
+        }
+
+        // Grow: new pages don't need evictions.
+        page_cache.resize(DEFAULT_PAGE_CACHE_SIZE * 2).unwrap();
+        assert_eq!(page_cache.capacity(), DEFAULT_PAGE_CACHE_SIZE * 2);
+        for _ in 0..DEFAULT_PAGE_CACHE_SIZE {
+            file_cache.new_page().unwrap();
+        }
+        assert_eq!(page_cache.mem_cache.excess_frames(), 0);
+
+        // Shrink: pinned pages are drained once unpinned.
+        let pinned = (1..=10)
+            .map(|page_id| file_cache.get_page(PageId::new(page_id)).unwrap())
+            .collect::<Vec<_>>();
+        page_cache.resize(5).unwrap();
+        assert_eq!(page_cache.capacity(), 5);
+        assert_eq!(page_cache.mem_cache.excess_frames(), 5);
+        drop(pinned);
+        file_cache.new_page().unwrap();
+        assert_eq!(page_cache.mem_cache.excess_frames(), 0);
+    }
+
+    #[test]
+    fn cache_full() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let file_cache = page_cache.cache_storage(storage);
+
+        page_cache.resize(4).unwrap();
+        for _ in 1..=8 {
+            file_cache.new_page().unwrap();
+        }
+
+        // Reading back pages that were evicted evicts others.
+        for page_id in 1..=8 {
+            file_cache.get_page(PageId::new(page_id)).unwrap();
+        }
+
+        // Every page is pinned: frames are borrowed from the reserve...
+        let pinned = (1..=8)
+            .map(|page_id| file_cache.get_page(PageId::new(page_id)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(page_cache.mem_cache.excess_frames(), 4);
+
+        // ...until it is exhausted.
+        let mut reserve = Vec::new();
+        for _ in 4..CONFIG.PAGE_CACHE_RESERVE {
+            reserve.push(file_cache.new_page().unwrap());
+        }
+        assert!(matches!(
+            file_cache.new_page(),
+            Err(PageCacheError::MemCache(MemCacheError::Full))
+        ));
+
+        // Borrowed frames are given back once unpinned.
+        drop(pinned);
+        drop(reserve);
+        file_cache.new_page().unwrap();
+        assert_eq!(page_cache.mem_cache.excess_frames(), 0);
+    }
+
+    #[test]
+    fn detach_storage() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let file_cache = page_cache.cache_storage(storage);
+
+        for _ in 1..=8 {
+            let page = file_cache.new_page().unwrap();
+            file_cache.set_page_dirty(page.metadata());
+        }
+        assert!(page_cache.detach_storage(StorageId(0)).is_some());
+        assert!(page_cache.detach_storage(StorageId(0)).is_none());
+
+        // Pages are gone and the writeback skips the storage.
+        assert!(matches!(
+            page_cache.mem_cache.get_page(StorageId(0), PageId::new(1)),
+            Err(MemCacheError::PageNotFound)
+        ));
+        page_cache.writeback_dirty_pages();
+    }
+
+    #[test]
+    fn free_pages_in_use() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let file_cache = page_cache.cache_storage(storage);
+        for _ in 1..=8 {
+            file_cache.new_page().unwrap();
+        }
+
+        // A page released meanwhile is waited for.
+        let file_cache = &file_cache;
+        std::thread::scope(|scope| {
+            let (held, wait_held) = mpsc::channel();
+            scope.spawn(move || {
+                let _page = file_cache.get_page(PageId::new(2)).unwrap();
+                held.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+            });
+            wait_held.recv().unwrap();
+            file_cache
+                .free_pages(PageId::new(1), PageId::new(3))
+                .unwrap();
+        });
+        assert!(!page_cache.mem_cache.contains(StorageId(0), PageId::new(2)));
+
+        // A page held for longer fails the call.
+        let page = file_cache.get_page(PageId::new(5)).unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            file_cache.free_pages(PageId::new(4), PageId::new(6)),
+            Err(PageCacheError::PagesInUse)
+        ));
+        assert!(start.elapsed() >= FREE_PAGES_TIMEOUT);
+        assert!(page_cache.mem_cache.contains(StorageId(0), PageId::new(5)));
+        drop(page);
+    }
+
+    struct CountingStorage {
+        storage: FileStorage,
+        fsyncs: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl StorageBackend for CountingStorage {
+        fn read_page(&self, page_id: PageId, page: &mut Page) -> Result<(), StorageError> {
+            self.storage.read_page(page_id, page)
+        }
+
+        fn write_page(&self, page: &Page, page_id: PageId) -> Result<(), StorageError> {
+            self.storage.write_page(page, page_id)
+        }
+
+        fn fsync(&self) {
+            self.fsyncs.fetch_add(1, Ordering::Relaxed);
+            self.storage.fsync()
+        }
+
+        fn allocate_page(&self) -> Result<PageId, StorageError> {
+            self.storage.allocate_page()
+        }
+
+        fn truncate(&self, last_page_id: PageId) -> Result<(), StorageError> {
+            self.storage.truncate(last_page_id)
+        }
+
+        fn first_page_id(&self) -> PageId {
+            self.storage.first_page_id()
+        }
+
+        fn last_page_id(&self) -> PageId {
+            self.storage.last_page_id()
+        }
+    }
+
+    #[test]
+    fn sync_mode() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let fsyncs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let storage = CountingStorage {
+            storage: FileStorage::create(storage_path).unwrap(),
+            fsyncs: Arc::clone(&fsyncs),
+        };
+        let page_cache = PageCache::try_new().unwrap();
+        assert_eq!(page_cache.sync_mode(), SyncMode::Interval);
+        let file_cache = page_cache.cache_storage(storage);
+
+        page_cache.set_sync_mode(SyncMode::Off);
+        let page_ref = file_cache.new_page().unwrap();
+        file_cache.set_page_dirty(page_ref.metadata());
+        drop(page_ref);
+        page_cache.writeback_dirty_pages();
+        assert_eq!(fsyncs.load(Ordering::Relaxed), 0);
+
+        page_cache.set_sync_mode(SyncMode::Interval);
+        let page_ref = file_cache.get_page_mut(PageId::new(1)).unwrap();
+        file_cache.set_page_dirty(page_ref.metadata());
+        drop(page_ref);
+        // Either this writeback or the writeback thread fsyncs the storage.
+        page_cache.writeback_dirty_pages();
+        std::thread::sleep(CONFIG.WRITEBACK_INTERVAL_MS * 2);
+        assert!(fsyncs.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn storage_sync_mode() {
+        let page_cache = PageCache::try_new().unwrap();
+        page_cache.set_sync_mode(SyncMode::Off);
+        let counting_cache = || {
+            let fsyncs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let storage = CountingStorage {
+                storage: FileStorage::create(NamedTempFile::new().unwrap()).unwrap(),
+                fsyncs: Arc::clone(&fsyncs),
+            };
+            (page_cache.cache_storage(storage), fsyncs)
+        };
+        let (full, full_fsyncs) = counting_cache();
+        let (off, off_fsyncs) = counting_cache();
+        full.set_sync_mode(Some(SyncMode::Full));
+        assert_eq!(full.sync_mode(), SyncMode::Full);
+        assert_eq!(off.sync_mode(), SyncMode::Off);
+
+        for file_cache in [&full, &off] {
+            let page_ref = file_cache.new_page().unwrap();
+            file_cache.set_page_dirty(page_ref.metadata());
+        }
+        // Only a storage in full mode is written back and fsynced on commit.
+        off.commit();
+        assert_eq!(off_fsyncs.load(Ordering::Relaxed), 0);
+        full.commit();
+        assert!(full_fsyncs.load(Ordering::Relaxed) > 0);
+        let page_ref = full.get_page(PageId::new(1)).unwrap();
+        assert!(!page_ref.metadata().is_dirty());
+        drop(page_ref);
+
+        // Back to the mode of the page cache.
+        full.set_sync_mode(None);
+        assert_eq!(full.sync_mode(), SyncMode::Off);
+    }
+
+    #[test]
+    fn buffer_usage() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let buffer_usage = Arc::new(BufferUsage::new());
+        let file_cache = page_cache
+            .cache_storage(storage)
+            .with_buffer_usage(Arc::clone(&buffer_usage));
+
+        // Page 0 is on disk but not in memory.
+        drop(file_cache.get_page(PAGE_RESERVED).unwrap());
+        let page_ref = file_cache.get_page_mut(PAGE_RESERVED).unwrap();
+        file_cache.set_page_dirty(page_ref.metadata());
+        file_cache.set_page_dirty(page_ref.metadata());
+        drop(page_ref);
+
+        assert_eq!(buffer_usage.misses(), 1);
+        assert_eq!(buffer_usage.hits(), 1);
+        assert_eq!(buffer_usage.dirtied(), 1);
+        assert_eq!(buffer_usage.bytes_read(), PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn read_ahead() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        for _ in 0..64 {
+            let page_id = storage.allocate_page().unwrap();
+            storage.write_page(&Page::new(), page_id).unwrap();
+        }
+        let page_cache = PageCache::try_new().unwrap();
+        let buffer_usage = Arc::new(BufferUsage::new());
+        let file_cache = page_cache
+            .cache_storage(storage)
+            .with_buffer_usage(Arc::clone(&buffer_usage));
+        let storage_id = file_cache.storage_id;
+
+        for page_id in 1..=READ_AHEAD_TRIGGER {
+            drop(file_cache.get_page(PageId::new(page_id)).unwrap());
+        }
+        let end = PageId::new(READ_AHEAD_TRIGGER + CONFIG.READ_AHEAD_PAGES);
+        let start = Instant::now();
+        while !page_cache.mem_cache.contains(storage_id, end) {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // The prefetched pages are hits.
+        for page_id in READ_AHEAD_TRIGGER + 1..=end.get() {
+            drop(file_cache.get_page(PageId::new(page_id)).unwrap());
+        }
+        assert_eq!(buffer_usage.misses(), READ_AHEAD_TRIGGER as u64);
+    }
+
+    #[test]
+    fn prefetch_full_cache() {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        for _ in 0..16 {
+            let page_id = storage.allocate_page().unwrap();
+            storage.write_page(&Page::new(), page_id).unwrap();
+        }
+        let page_cache = PageCache::try_new().unwrap();
+        let file_cache = page_cache.cache_storage(storage);
+        let storage_id = file_cache.storage_id;
+        page_cache.resize(4).unwrap();
+
+        // Every page is pinned, read in reverse not to trigger read-ahead:
+        // nothing is prefetched, no frame is borrowed from the reserve.
+        let pinned = (1..=4)
+            .rev()
+            .map(|page_id| file_cache.get_page(PageId::new(page_id)).unwrap())
+            .collect::<Vec<_>>();
+        page_cache.prefetch(storage_id, PageId::new(5), PageId::new(12));
+        assert_eq!(page_cache.mem_cache.excess_frames(), 0);
+        assert!(!page_cache.mem_cache.contains(storage_id, PageId::new(5)));
+
+        // Unpinned pages are evicted for the prefetched ones.
+        drop(pinned);
+        page_cache.prefetch(storage_id, PageId::new(5), PageId::new(8));
+        assert_eq!(page_cache.mem_cache.excess_frames(), 0);
+        assert!(page_cache.mem_cache.contains(storage_id, PageId::new(8)));
+    }
 }
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::pages::PAGE_SIZE;
+
+/// Page cache counters of a query or of a single operator.
+///
+/// Shared with a `StoragePageCache` by `StoragePageCache::with_buffer_usage`, every access
+/// made through that cache is accounted here.
+#[derive(Debug, Default)]
+pub struct BufferUsage {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    dirtied: AtomicU64,
+}
+
+impl BufferUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of page accesses served from memory.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of page accesses that had to read the page from storage.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of clean pages made dirty.
+    pub fn dirtied(&self) -> u64 {
+        self.dirtied.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes read from storage.
+    pub fn bytes_read(&self) -> u64 {
+        self.misses() * PAGE_SIZE as u64
+    }
+
+    pub(super) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_dirtied(&self) {
+        self.dirtied.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -1,28 +1,98 @@
-use crate::cache::GLOBAL_PAGE_CACHE;
+use crate::cache::{GLOBAL_PAGE_CACHE, SyncMode};
 use crate::config::CONFIG;
+use crate::indexes::{
+    BTree, BTreeStats, FullTextIndex, HashIndex, HashIndexStats, IndexMethod, KeyColumn,
+};
+use crate::pages::{Key, RecordId};
+use crate::sql::plan::{IndexStats, SchemaProvider, TableStats};
 use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
 use crate::sql::types::Value;
+use crate::sql::types::memcomparable::KeyOptions;
 use crate::storage::{DatabaseName, DatabaseRootDirectory, FileStorage, StorageBackend, TableName};
-use crate::table::Table;
+use crate::table::{IndexBuild, Table};
 use crate::tuple::Tuple;
 
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::LazyLock;
 
 use thiserror::Error;
 
-struct Catalog<S: StorageBackend + 'static> {
+pub struct Catalog<S: StorageBackend + 'static> {
     db_root: DatabaseRootDirectory,
     information_schema_tables: Table<S>,
     information_schema_columns: Table<S>,
+    information_schema_indexes: Table<S>,
+    // (database, index name) -> indexed table and column.
+    indexes: HashMap<(DatabaseName, TableName), IndexEntry>,
+    // alias -> database of another root directory.
+    attached: HashMap<DatabaseName, AttachedDatabase<S>>,
+    // database -> sync mode of its tables, see `Catalog::set_sync_mode`.
+    sync_modes: HashMap<DatabaseName, SyncMode>,
+}
+
+/// A database of another root directory, resolved through the catalog of
+/// that root.
+struct AttachedDatabase<S: StorageBackend + 'static> {
+    catalog: Catalog<S>,
+    db_name: DatabaseName,
+}
+
+#[derive(Debug, PartialEq)]
+struct IndexEntry {
+    table_name: TableName,
+    // The columns of the key, a single one but for composite indexes.
+    key: Vec<KeyColumn>,
+    // The columns stored in the leaves of a covering index.
+    included: Vec<String>,
+    method: IndexMethod,
+}
+
+impl IndexEntry {
+    /// Whether the index is keyed by the values of a column alone, in
+    /// ascending order: the indexes used for lookups.
+    fn is_on(&self, column_name: &str) -> bool {
+        self.key == [KeyColumn::ascending(column_name)]
+    }
+}
+
+/// An index built while its table accepts writes, see
+/// `Catalog::build_index_online`.
+pub struct OnlineIndexBuild {
+    db_name: DatabaseName,
+    index_name: TableName,
+    entry: IndexEntry,
+    build: IndexBuild<FileStorage>,
 }
 
 #[derive(Debug, Error)]
-enum CatalogError {
+pub enum CatalogError {
     #[error("Database already exists")]
     CreateDatabase,
     #[error("table creation failed")]
     CreateTable,
+    #[error("index creation failed")]
+    CreateIndex,
+    #[error("column not found")]
+    ColumnNotFound,
+    #[error("index not found")]
+    IndexNotFound,
+    #[error("index drop failed")]
+    DropIndex,
+    #[error("table not found")]
+    TableNotFound,
+    #[error("cannot drop table, other objects depend on it: {}", .0.join(", "))]
+    DependentObjects(Vec<String>),
+    #[error("table drop failed")]
+    DropTable,
+    #[error("table statistics update failed")]
+    UpdateStatistics,
+    #[error("backup failed")]
+    Backup,
+    #[error("database attach failed")]
+    AttachDatabase,
+    #[error("database not found")]
+    DatabaseNotFound,
 }
 
 static INFORMATION_SCHEMA_TABLES: LazyLock<Schema> = LazyLock::new(|| {
@@ -30,27 +100,33 @@ static INFORMATION_SCHEMA_TABLES: LazyLock<Schema> = LazyLock::new(|| {
         // TABLE_SCHEMA: the name of the database to which the table belongs to.
         Column {
             column_name: "TABLE_SCHEMA".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().build(),
         },
         // TABLE_TYPE: table or index.
         Column {
             column_name: "TABLE_TYPE".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().build(),
         },
         // TABLE_NAME: the name of the table.
         Column {
             column_name: "TABLE_NAME".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().unique().build(),
         },
-        // TABLE_ROWS: the number of rows.
+        // TABLE_ROWS: the number of rows, as of the last analyze.
         Column {
             column_name: "TABLE_ROWS".into(),
             data_type: DataType::Integer,
             constraints: ConstraintsBuilder::new().build(),
         },
+        // TABLE_PAGES: the number of pages, as of the last analyze.
+        Column {
+            column_name: "TABLE_PAGES".into(),
+            data_type: DataType::Integer,
+            constraints: ConstraintsBuilder::new().build(),
+        },
     ])
     .unwrap()
 });
@@ -60,19 +136,19 @@ static INFORMATION_SCHEMA_COLUMNS: LazyLock<Schema> = LazyLock::new(|| {
         // TABLE_SCHEMA: the name of the database to which the column belongs.
         Column {
             column_name: "TABLE_SCHEMA".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().build(),
         },
         // TABLE_NAME: the name of the table.
         Column {
             column_name: "TABLE_NAME".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().build(),
         },
         // COLUMN_NAME: the name of the column.
         Column {
             column_name: "COLUMN_NAME".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().unique().build(),
         },
         // ORDINAL_POSITION: the position of the column within the table.
@@ -84,29 +160,166 @@ static INFORMATION_SCHEMA_COLUMNS: LazyLock<Schema> = LazyLock::new(|| {
         // COLUMN_DEFAULT: the default value of the column.
         // Column {
         //     column_name: "COLUMN_DEFAULT".into(),
-        //     data_type: DataType::VarChar,
+        //     data_type: DataType::VarChar(None),
         //     constraints: Constraints::new(false, false),
         // },
         // IS_NULLABLE: the column nullability.
         Column {
             column_name: "IS_NULLABLE".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().build(),
         },
         // DATA_TYPE: the data type.
         Column {
             column_name: "DATA_TYPE".into(),
-            data_type: DataType::VarChar,
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().build(),
+        },
+    ])
+    .unwrap()
+});
+
+static INFORMATION_SCHEMA_INDEXES: LazyLock<Schema> = LazyLock::new(|| {
+    Schema::try_new(vec![
+        // TABLE_SCHEMA: the name of the database to which the index belongs.
+        Column {
+            column_name: "TABLE_SCHEMA".into(),
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().build(),
+        },
+        // TABLE_NAME: the name of the indexed table.
+        Column {
+            column_name: "TABLE_NAME".into(),
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().build(),
+        },
+        // INDEX_NAME: the name of the index.
+        Column {
+            column_name: "INDEX_NAME".into(),
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().unique().build(),
+        },
+        // COLUMN_NAME: the name of the indexed column, the first column of
+        // the key of a composite index.
+        Column {
+            column_name: "COLUMN_NAME".into(),
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().build(),
+        },
+        // INDEX_HEIGHT: the number of levels of the index, as of the last
+        // analyze. Its keys and pages are in INFORMATION_SCHEMA.TABLES.
+        Column {
+            column_name: "INDEX_HEIGHT".into(),
+            data_type: DataType::Integer,
+            constraints: ConstraintsBuilder::new().build(),
+        },
+        // INCLUDED_COLUMNS: the columns stored in the leaves of a covering
+        // index, separated by commas. Empty for other indexes.
+        Column {
+            column_name: "INCLUDED_COLUMNS".into(),
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().build(),
+        },
+        // INDEX_TYPE: the data structure of the index, BTREE, HASH or FULLTEXT.
+        Column {
+            column_name: "INDEX_TYPE".into(),
+            data_type: DataType::VarChar(None),
+            constraints: ConstraintsBuilder::new().build(),
+        },
+        // KEY_COLUMNS: the columns of the key of a composite index along with
+        // their order, e.g. `a ASC,b DESC`. Empty for indexes on a single
+        // column in ascending order.
+        Column {
+            column_name: "KEY_COLUMNS".into(),
+            data_type: DataType::VarChar(None),
             constraints: ConstraintsBuilder::new().build(),
         },
     ])
     .unwrap()
 });
 
+/// The statistics of an index kept for the planner.
+fn index_stats(stats: &BTreeStats) -> IndexStats {
+    IndexStats {
+        keys: stats.keys,
+        pages: stats.pages(),
+        height: stats.height() as u64,
+    }
+}
+
+/// The statistics of a hash index kept for the planner. A lookup reads a
+/// directory page then a bucket, as many pages as in a B-tree of two levels.
+fn hash_index_stats(stats: &HashIndexStats) -> IndexStats {
+    IndexStats {
+        keys: stats.keys,
+        pages: stats.pages(),
+        height: 2,
+    }
+}
+
+/// The key columns of an index, with their order, and its included columns.
+type IndexColumns = (Vec<(usize, KeyOptions)>, Vec<usize>);
+
+/// The positions of the key columns of an index in a table, along with their
+/// order, and the positions of its included columns.
+fn index_columns<S: StorageBackend + 'static>(
+    table: &Table<S>,
+    key: &[KeyColumn],
+    included: &[&str],
+) -> Result<IndexColumns, CatalogError> {
+    let position = |column_name: &str| {
+        table
+            .schema
+            .columns()
+            .iter()
+            .position(|column| column.column_name == column_name)
+            .ok_or(CatalogError::ColumnNotFound)
+    };
+    let key = key
+        .iter()
+        .map(|column| {
+            let options = KeyOptions {
+                descending: column.descending,
+                ..KeyOptions::default()
+            };
+            Ok((position(&column.name)?, options))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let included = included
+        .iter()
+        .map(|column_name| position(column_name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((key, included))
+}
+
+/// Returns the integer stored in an INTEGER column of a catalog tuple.
+fn integer(tuple: &Tuple, column: usize) -> i64 {
+    match &tuple.values()[column] {
+        Value::Integer(i) => *i,
+        _ => unreachable!("catalog columns are not nullable INTEGERs"),
+    }
+}
+
+/// Returns the string stored in a VARCHAR column of a catalog tuple.
+fn varchar(tuple: &Tuple, column: usize) -> &str {
+    match &tuple.values()[column] {
+        Value::VarChar(s) => s.as_str(),
+        _ => unreachable!("catalog columns are not nullable VARCHARs"),
+    }
+}
+
+impl Default for Catalog<FileStorage> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Catalog<FileStorage> {
     const INFORMATION_SCHEMA_DB: &str = "INFORMATION_SCHEMA";
     const INFORMATION_SCHEMA_TABLES_TABLE: &str = "TABLES";
     const INFORMATION_SCHEMA_COLUMNS_TABLE: &str = "COLUMNS";
+    const INFORMATION_SCHEMA_INDEXES_TABLE: &str = "INDEXES";
 
     pub fn new() -> Self {
         Self::with_root_path(CONFIG.ROOT_DIRECTORY.as_str())
@@ -120,11 +333,16 @@ impl Catalog<FileStorage> {
         let tables = TableName::try_from(Self::INFORMATION_SCHEMA_TABLES_TABLE).unwrap();
         let columns = TableName::try_from(Self::INFORMATION_SCHEMA_COLUMNS_TABLE).unwrap();
 
+        let indexes = TableName::try_from(Self::INFORMATION_SCHEMA_INDEXES_TABLE).unwrap();
+
         if db_root.get_database_mut(&db).is_err() {
             db_root.create_database(&db).unwrap();
             db_root.create_table(&db, &tables).unwrap();
             db_root.create_table(&db, &columns).unwrap();
         }
+        if db_root.table_path(&db, &indexes).is_none() {
+            db_root.create_table(&db, &indexes).unwrap();
+        }
 
         let tables_path = db_root.table_path(&db, &tables).unwrap();
         let tables_storage = FileStorage::open(tables_path).unwrap();
@@ -156,22 +374,650 @@ impl Catalog<FileStorage> {
             )
         });
 
+        let indexes_path = db_root.table_path(&db, &indexes).unwrap();
+        let indexes_storage = FileStorage::open(indexes_path).unwrap();
+        let indexes_table = Table::try_new(
+            Self::INFORMATION_SCHEMA_INDEXES_TABLE,
+            &INFORMATION_SCHEMA_INDEXES,
+            GLOBAL_PAGE_CACHE.cache_storage(indexes_storage),
+        )
+        .unwrap_or_else(|e| {
+            panic!(
+                "Failed to open table {}: {}",
+                Self::INFORMATION_SCHEMA_INDEXES_TABLE,
+                e
+            )
+        });
+
+        let indexes = indexes_table
+            .iter()
+            .map(|tuple| {
+                let db_name = DatabaseName::try_from(varchar(&tuple, 0)).unwrap();
+                let index_name = TableName::try_from(varchar(&tuple, 2)).unwrap();
+                let key = match varchar(&tuple, 7) {
+                    "" => vec![KeyColumn::ascending(varchar(&tuple, 3))],
+                    key => key
+                        .split(',')
+                        .map(|column| KeyColumn::from_name(column).unwrap())
+                        .collect(),
+                };
+                let entry = IndexEntry {
+                    table_name: TableName::try_from(varchar(&tuple, 1)).unwrap(),
+                    key,
+                    included: varchar(&tuple, 5)
+                        .split(',')
+                        .filter(|column| !column.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                    method: IndexMethod::from_name(varchar(&tuple, 6)).unwrap(),
+                };
+                ((db_name, index_name), entry)
+            })
+            .collect();
+
         Self {
             db_root,
             information_schema_tables: tables_table,
             information_schema_columns: columns_table,
+            information_schema_indexes: indexes_table,
+            indexes,
+            attached: HashMap::new(),
+            sync_modes: HashMap::new(),
+        }
+    }
+
+    /// Attaches the database directory `path` of another root directory
+    /// under `alias`: its tables are then resolved as the tables of a
+    /// database named `alias`, e.g. `alias.table` in queries.
+    ///
+    /// The catalog of the other root is opened, its tables get their own
+    /// storages in the page cache. The alias can't be the name of a database
+    /// of this root, and only one database of a root can be attached.
+    pub fn attach<P: AsRef<Path>>(
+        &mut self,
+        alias: &DatabaseName,
+        path: P,
+    ) -> Result<(), CatalogError> {
+        if self.db_root.has_database(alias) || self.attached.contains_key(alias) {
+            return Err(CatalogError::AttachDatabase);
+        }
+
+        let path = path
+            .as_ref()
+            .canonicalize()
+            .map_err(|_| CatalogError::DatabaseNotFound)?;
+        let (Some(root), Some(db_name)) = (path.parent(), path.file_name()) else {
+            return Err(CatalogError::AttachDatabase);
+        };
+        let db_name = db_name
+            .to_str()
+            .and_then(|name| DatabaseName::try_from(name).ok())
+            .ok_or(CatalogError::DatabaseNotFound)?;
+        // Opening a catalog creates INFORMATION_SCHEMA: only attach roots
+        // which have one.
+        let is_root = root.join(Self::INFORMATION_SCHEMA_DB).is_dir();
+        // The catalog tables of a root must not be opened twice.
+        let is_open = self
+            .db_root
+            .path()
+            .canonicalize()
+            .is_ok_and(|own| own == root)
+            || self
+                .attached
+                .values()
+                .any(|attached| attached.catalog.db_root.path() == root);
+        if !path.is_dir() || !is_root || is_open {
+            return Err(CatalogError::AttachDatabase);
+        }
+
+        let catalog = Catalog::with_root_path(root);
+        if !catalog.db_root.has_database(&db_name) {
+            catalog.detach_storages();
+            return Err(CatalogError::DatabaseNotFound);
+        }
+        self.attached
+            .insert(alias.clone(), AttachedDatabase { catalog, db_name });
+
+        Ok(())
+    }
+
+    /// Detaches a database attached with `attach`. The page cache is flushed
+    /// then the catalog tables of the other root are removed from it, the
+    /// tables opened from the database must not be used anymore.
+    pub fn detach(&mut self, alias: &DatabaseName) -> Result<(), CatalogError> {
+        let attached = self
+            .attached
+            .remove(alias)
+            .ok_or(CatalogError::DatabaseNotFound)?;
+        GLOBAL_PAGE_CACHE.flush();
+        attached.catalog.detach_storages();
+
+        Ok(())
+    }
+
+    fn detach_storages(&self) {
+        self.information_schema_tables.detach();
+        self.information_schema_columns.detach();
+        self.information_schema_indexes.detach();
+    }
+
+    /// Takes a backup of all the databases to `path`, a new directory which
+    /// can be opened with `Catalog::with_root_path`.
+    ///
+    /// The page cache is flushed then the files are copied. There is no WAL
+    /// to replay the changes made during the copy: the backup is only
+    /// consistent if the tables aren't written until it returns.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<(), CatalogError> {
+        GLOBAL_PAGE_CACHE.flush();
+        self.db_root
+            .backup_to(path)
+            .map_err(|_| CatalogError::Backup)
+    }
+
+    /// Opens a table for reading and writing its tuples.
+    ///
+    /// The table file gets its own storage in the page cache: a table must not
+    /// be opened twice at the same time.
+    pub fn open_table(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &TableName,
+    ) -> Result<Table<FileStorage>, CatalogError> {
+        // Named after the qualified name the planner looks up, see
+        // `DatabaseSchemas`.
+        if let Some(attached) = self.attached.get(db_name) {
+            let mut table = attached.catalog.open_table(&attached.db_name, table_name)?;
+            table.name = format!("{}.{}", db_name.as_str(), table_name.as_str());
+            if let Some(mode) = self.sync_mode(db_name) {
+                table.set_sync_mode(Some(mode));
+            }
+            return Ok(table);
+        }
+
+        let schema = self.table_schema(db_name, table_name)?;
+        let path = self
+            .db_root
+            .table_path(db_name, table_name)
+            .ok_or(CatalogError::TableNotFound)?;
+        let storage = FileStorage::open(path).map_err(|_| CatalogError::TableNotFound)?;
+        let mut table = Table::try_new(
+            table_name.as_str(),
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .map_err(|_| CatalogError::TableNotFound)?;
+        if let Some(mode) = self.sync_mode(db_name) {
+            table.set_sync_mode(Some(mode));
+        }
+        Ok(table)
+    }
+
+    /// Gives the tables of a database their own sync mode, or the mode of the
+    /// page cache again if `None`, see `Table::set_sync_mode`. It applies to
+    /// the tables opened from then on: with `SyncMode::Full`, the writes of a
+    /// statement are durable once it returns. The mode isn't stored, it's
+    /// lost when the catalog is closed.
+    pub fn set_sync_mode(
+        &mut self,
+        db_name: &DatabaseName,
+        mode: Option<SyncMode>,
+    ) -> Result<(), CatalogError> {
+        if !self.db_root.has_database(db_name) && !self.attached.contains_key(db_name) {
+            return Err(CatalogError::DatabaseNotFound);
+        }
+        match mode {
+            Some(mode) => self.sync_modes.insert(db_name.clone(), mode),
+            None => self.sync_modes.remove(db_name),
+        };
+        Ok(())
+    }
+
+    /// The sync mode of the tables of a database, if it was given one, see
+    /// `set_sync_mode`.
+    pub fn sync_mode(&self, db_name: &DatabaseName) -> Option<SyncMode> {
+        self.sync_modes.get(db_name).copied()
+    }
+
+    /// Creates a B-tree index on a column of a table, the index file is stored
+    /// next to the table file. The values of the `included` columns are
+    /// stored in the leaves of the index, see `Table::add_covering_index`.
+    ///
+    /// The rows already in the table are bulk loaded into the index, they are
+    /// read from the table file: the rows not written back yet by an open
+    /// table are missed. Fails if the column is unique and has duplicate
+    /// values, the values of the other columns may repeat, see
+    /// `Table::index_entries`. The statistics of the index are stored, see
+    /// `analyze_index`.
+    pub fn create_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        table_name: &TableName,
+        column_name: &str,
+        included: &[&str],
+    ) -> Result<(), CatalogError> {
+        let key = [KeyColumn::ascending(column_name)];
+        self.create_composite_index(db_name, index_name, table_name, &key, included)
+    }
+
+    /// Same as `create_index`, the index being keyed by the values of several
+    /// columns, each in ascending or descending order, see
+    /// `Table::add_composite_index`. Rows may have the same values in these
+    /// columns.
+    pub fn create_composite_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        table_name: &TableName,
+        key: &[KeyColumn],
+        included: &[&str],
+    ) -> Result<(), CatalogError> {
+        if key.is_empty() {
+            return Err(CatalogError::CreateIndex);
+        }
+        self.check_new_index(db_name, index_name, table_name, key, included)?;
+        let entries = self.index_entries(db_name, table_name, key, included)?;
+        let stats = self.build_index(db_name, index_name, |storage| {
+            let btree =
+                BTree::bulk_load_with_payloads(GLOBAL_PAGE_CACHE.cache_storage(storage), entries)
+                    .ok()?;
+            Some(index_stats(&btree.stats().ok()?))
+        })?;
+        let entry = IndexEntry {
+            table_name: table_name.clone(),
+            key: key.to_vec(),
+            included: included.iter().map(|column| column.to_string()).collect(),
+            method: IndexMethod::BTree,
+        };
+        self.register_index(db_name, index_name, entry, stats)
+    }
+
+    /// Creates a hash index on a column of a table, for equality lookups
+    /// only, see `HashIndex`. The rows already in the table are loaded as by
+    /// `create_index`.
+    pub fn create_hash_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        table_name: &TableName,
+        column_name: &str,
+    ) -> Result<(), CatalogError> {
+        let key = [KeyColumn::ascending(column_name)];
+        self.check_new_index(db_name, index_name, table_name, &key, &[])?;
+        let table = self.open_table(db_name, table_name)?;
+        let (columns, _) = index_columns(&table, &key, &[])?;
+        let entries = table.index_keys(&columns);
+        table.detach();
+        let stats = self.build_index(db_name, index_name, |storage| {
+            let index =
+                HashIndex::bulk_load(GLOBAL_PAGE_CACHE.cache_storage(storage), entries).ok()?;
+            Some(hash_index_stats(&index.stats().ok()?))
+        })?;
+        let entry = IndexEntry {
+            table_name: table_name.clone(),
+            key: key.to_vec(),
+            included: Vec::new(),
+            method: IndexMethod::Hash,
+        };
+        self.register_index(db_name, index_name, entry, stats)
+    }
+
+    /// Creates a full-text index on a VARCHAR column of a table, for the
+    /// `CONTAINS` predicates on the column, see `FullTextIndex`. The rows
+    /// already in the table are loaded as by `create_index`.
+    pub fn create_fulltext_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        table_name: &TableName,
+        column_name: &str,
+    ) -> Result<(), CatalogError> {
+        let key = [KeyColumn::ascending(column_name)];
+        self.check_new_index(db_name, index_name, table_name, &key, &[])?;
+        let texts = self.column_texts(db_name, table_name, column_name)?;
+        let stats = self.build_index(db_name, index_name, |storage| {
+            let rows = texts
+                .iter()
+                .map(|(text, record_id)| (text.as_str(), *record_id));
+            let index =
+                FullTextIndex::bulk_load(GLOBAL_PAGE_CACHE.cache_storage(storage), rows).ok()?;
+            Some(index_stats(&index.stats().ok()?))
+        })?;
+        let entry = IndexEntry {
+            table_name: table_name.clone(),
+            key: key.to_vec(),
+            included: Vec::new(),
+            method: IndexMethod::FullText,
+        };
+        self.register_index(db_name, index_name, entry, stats)
+    }
+
+    /// Same as `create_composite_index`, the index being built while the table
+    /// accepts writes through `table`, an open handle on it, see
+    /// `Table::build_index_online`. The index is only registered by
+    /// `finish_index_build`: its file is left behind if the build is dropped.
+    pub fn build_index_online(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        table_name: &TableName,
+        table: &Table<FileStorage>,
+        key: &[KeyColumn],
+        included: &[&str],
+    ) -> Result<OnlineIndexBuild, CatalogError> {
+        if key.is_empty() {
+            return Err(CatalogError::CreateIndex);
+        }
+        self.check_new_index(db_name, index_name, table_name, key, included)?;
+        let (key_columns, included_columns) = index_columns(table, key, included)?;
+        let index_file = self
+            .db_root
+            .create_index(db_name, index_name)
+            .map_err(|_| CatalogError::CreateIndex)?;
+        let storage = index_file.open().map_err(|_| CatalogError::CreateIndex)?;
+        let build = table
+            .build_index_online(
+                key_columns,
+                included_columns,
+                GLOBAL_PAGE_CACHE.cache_storage(storage),
+            )
+            .map_err(|_| {
+                let _ = self.db_root.drop_index(db_name, index_name);
+                CatalogError::CreateIndex
+            })?;
+
+        Ok(OnlineIndexBuild {
+            db_name: db_name.clone(),
+            index_name: index_name.clone(),
+            entry: IndexEntry {
+                table_name: table_name.clone(),
+                key: key.to_vec(),
+                included: included.iter().map(|column| column.to_string()).collect(),
+                method: IndexMethod::BTree,
+            },
+            build,
+        })
+    }
+
+    /// Adds an index built by `build_index_online` to `table`, see
+    /// `Table::finish_index_build`, and registers it. Its file is removed if
+    /// the last changes to the table can't be applied.
+    pub fn finish_index_build(
+        &mut self,
+        table: &mut Table<FileStorage>,
+        build: OnlineIndexBuild,
+    ) -> Result<(), CatalogError> {
+        let OnlineIndexBuild {
+            db_name,
+            index_name,
+            entry,
+            build,
+        } = build;
+        // A handle on the same pages, for the statistics once swapped in.
+        let index = build.index().clone();
+        if table.finish_index_build(build).is_err() {
+            let _ = self.db_root.drop_index(&db_name, &index_name);
+            return Err(CatalogError::CreateIndex);
+        }
+        let stats = index.stats().map_err(|_| CatalogError::CreateIndex)?;
+        self.register_index(&db_name, &index_name, entry, index_stats(&stats))
+    }
+
+    /// Checks that the columns of a new index exist, and that its name isn't
+    /// taken.
+    fn check_new_index(
+        &self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        table_name: &TableName,
+        key: &[KeyColumn],
+        included: &[&str],
+    ) -> Result<(), CatalogError> {
+        let has_column = |column_name: &str| {
+            self.information_schema_columns.iter().any(|tuple| {
+                varchar(&tuple, 0) == db_name.as_str()
+                    && varchar(&tuple, 1) == table_name.as_str()
+                    && varchar(&tuple, 2) == column_name
+            })
+        };
+        if !key.iter().all(|column| has_column(&column.name))
+            || !included.iter().all(|column| has_column(column))
+        {
+            return Err(CatalogError::ColumnNotFound);
+        }
+
+        // Indexes and tables share the same namespace.
+        let name_taken = self.information_schema_tables.iter().any(|tuple| {
+            varchar(&tuple, 0) == db_name.as_str() && varchar(&tuple, 2) == index_name.as_str()
+        });
+        if name_taken {
+            return Err(CatalogError::CreateIndex);
+        }
+
+        Ok(())
+    }
+
+    /// Creates the file of an index and fills it with `build`, which returns
+    /// the statistics of the index. The file is removed if `build` fails.
+    fn build_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        build: impl FnOnce(FileStorage) -> Option<IndexStats>,
+    ) -> Result<IndexStats, CatalogError> {
+        let index_file = self
+            .db_root
+            .create_index(db_name, index_name)
+            .map_err(|_| CatalogError::CreateIndex)?;
+        let storage = index_file.open().map_err(|_| CatalogError::CreateIndex)?;
+        build(storage).ok_or_else(|| {
+            let _ = self.db_root.drop_index(db_name, index_name);
+            CatalogError::CreateIndex
+        })
+    }
+
+    /// Records a new index in INFORMATION_SCHEMA.
+    fn register_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        entry: IndexEntry,
+        stats: IndexStats,
+    ) -> Result<(), CatalogError> {
+        let tuple = Tuple::try_new(vec![
+            Value::VarChar(db_name.as_str().to_string()),
+            Value::VarChar("index".to_string()),
+            Value::VarChar(index_name.as_str().to_string()),
+            Value::Integer(stats.keys as i64),
+            Value::Integer(stats.pages as i64),
+        ])
+        .map_err(|_| CatalogError::CreateIndex)?;
+        self.information_schema_tables
+            .insert(&tuple)
+            .map_err(|_| CatalogError::CreateIndex)?;
+
+        let tuple = Tuple::try_new(vec![
+            Value::VarChar(db_name.as_str().to_string()),
+            Value::VarChar(entry.table_name.as_str().to_string()),
+            Value::VarChar(index_name.as_str().to_string()),
+            Value::VarChar(entry.key[0].name.clone()),
+            Value::Integer(stats.height as i64),
+            Value::VarChar(entry.included.join(",")),
+            Value::VarChar(entry.method.as_str().to_string()),
+            Value::VarChar(match &entry.key[..] {
+                [column] if !column.descending => String::new(),
+                key => key
+                    .iter()
+                    .map(KeyColumn::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            }),
+        ])
+        .map_err(|_| CatalogError::CreateIndex)?;
+        self.information_schema_indexes
+            .insert(&tuple)
+            .map_err(|_| CatalogError::CreateIndex)?;
+
+        self.indexes
+            .insert((db_name.clone(), index_name.clone()), entry);
+
+        Ok(())
+    }
+
+    /// The keys of the `key` columns of a table, sorted, along with the record
+    /// ids of their rows and the values of the `included` columns, see
+    /// `Table::index_entries`.
+    fn index_entries(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &TableName,
+        key: &[KeyColumn],
+        included: &[&str],
+    ) -> Result<Vec<(Key, RecordId, Vec<u8>)>, CatalogError> {
+        let table = self.open_table(db_name, table_name)?;
+        let (key, included) = index_columns(&table, key, included)?;
+        let entries = table.index_entries(&key, &included);
+        table.detach();
+
+        Ok(entries)
+    }
+
+    /// The texts of a VARCHAR column of a table along with the record ids of
+    /// their rows, NULLs left out.
+    fn column_texts(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &TableName,
+        column_name: &str,
+    ) -> Result<Vec<(String, RecordId)>, CatalogError> {
+        let table = self.open_table(db_name, table_name)?;
+        let column = table
+            .schema
+            .columns()
+            .iter()
+            .position(|column| column.column_name == column_name)
+            .ok_or(CatalogError::ColumnNotFound)?;
+        if !matches!(
+            table.schema.columns()[column].data_type,
+            DataType::VarChar(_)
+        ) {
+            table.detach();
+            return Err(CatalogError::CreateIndex);
+        }
+        let mut texts = Vec::new();
+        let mut iter = table.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if let Value::VarChar(text) = &tuple.values()[column] {
+                texts.push((text.clone(), record_id));
+            }
+        }
+        table.detach();
+
+        Ok(texts)
+    }
+
+    /// Drops a table. Indexes on the table are dropped with it if `cascade` is
+    /// set, otherwise the table can't be dropped while it has indexes.
+    pub fn drop_table(
+        &mut self,
+        db_name: &DatabaseName,
+        table_name: &TableName,
+        cascade: bool,
+    ) -> Result<(), CatalogError> {
+        if self.db_root.table_path(db_name, table_name).is_none() {
+            return Err(CatalogError::TableNotFound);
+        }
+
+        let mut dependent_indexes = self
+            .indexes
+            .iter()
+            .filter(|((db, _), entry)| db == db_name && entry.table_name == *table_name)
+            .map(|((_, index_name), _)| index_name.clone())
+            .collect::<Vec<_>>();
+        dependent_indexes.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        if !cascade && !dependent_indexes.is_empty() {
+            let objects = dependent_indexes
+                .iter()
+                .map(|index_name| format!("index {}", index_name.as_str()))
+                .collect();
+            return Err(CatalogError::DependentObjects(objects));
+        }
+
+        for index_name in dependent_indexes {
+            self.drop_index(db_name, &index_name)?;
+        }
+
+        let mut iter = self.information_schema_tables.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if varchar(&tuple, 0) == db_name.as_str()
+                && varchar(&tuple, 1) == "table"
+                && varchar(&tuple, 2) == table_name.as_str()
+            {
+                self.information_schema_tables
+                    .delete(record_id)
+                    .map_err(|_| CatalogError::DropTable)?;
+            }
+        }
+
+        let mut iter = self.information_schema_columns.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if varchar(&tuple, 0) == db_name.as_str() && varchar(&tuple, 1) == table_name.as_str() {
+                self.information_schema_columns
+                    .delete(record_id)
+                    .map_err(|_| CatalogError::DropTable)?;
+            }
+        }
+
+        self.db_root
+            .drop_table(db_name, table_name)
+            .map_err(|_| CatalogError::DropTable)
+    }
+
+    pub fn drop_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+    ) -> Result<(), CatalogError> {
+        self.indexes
+            .remove(&(db_name.clone(), index_name.clone()))
+            .ok_or(CatalogError::IndexNotFound)?;
+
+        let mut iter = self.information_schema_tables.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if varchar(&tuple, 0) == db_name.as_str()
+                && varchar(&tuple, 1) == "index"
+                && varchar(&tuple, 2) == index_name.as_str()
+            {
+                self.information_schema_tables
+                    .delete(record_id)
+                    .map_err(|_| CatalogError::DropIndex)?;
+            }
+        }
+
+        let mut iter = self.information_schema_indexes.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if varchar(&tuple, 0) == db_name.as_str() && varchar(&tuple, 2) == index_name.as_str() {
+                self.information_schema_indexes
+                    .delete(record_id)
+                    .map_err(|_| CatalogError::DropIndex)?;
+            }
         }
+
+        self.db_root
+            .drop_index(db_name, index_name)
+            .map_err(|_| CatalogError::DropIndex)
     }
 }
 
 impl<S: StorageBackend + 'static> Catalog<S> {
-    fn create_database(&mut self, db_name: &DatabaseName) -> Result<(), CatalogError> {
+    pub fn create_database(&mut self, db_name: &DatabaseName) -> Result<(), CatalogError> {
         self.db_root
             .create_database(db_name)
             .map_err(|_| CatalogError::CreateDatabase)
     }
 
-    fn create_table(
+    pub fn create_table(
         &mut self,
         db_name: &DatabaseName,
         table_name: &TableName,
@@ -185,7 +1031,8 @@ impl<S: StorageBackend + 'static> Catalog<S> {
             Value::VarChar(db_name.as_str().to_string()),
             Value::VarChar("table".to_string()),
             Value::VarChar(table_name.as_str().to_string()),
-            Value::Integer(schema.num_columns() as i64),
+            Value::Integer(0),
+            Value::Integer(0),
         ])
         .map_err(|_| CatalogError::CreateTable)?;
 
@@ -216,36 +1063,382 @@ impl<S: StorageBackend + 'static> Catalog<S> {
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Rebuilds the schema of a table from INFORMATION_SCHEMA.COLUMNS. Unique
+    /// constraints are not recorded there and are not restored.
+    pub fn table_schema(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &TableName,
+    ) -> Result<Schema, CatalogError> {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached.catalog.table_schema(&attached.db_name, table_name);
+        }
 
-    #[test]
-    fn insert_and_scan_catalog() {
-        let root_path = tempfile::TempDir::new()
-            .unwrap()
-            .keep()
-            .to_string_lossy()
-            .into_owned();
-        let mut catalog = Catalog::with_root_path(&root_path);
-        let db_name = DatabaseName::try_from("test_db").unwrap();
-        let _ = catalog.create_database(&db_name);
+        let mut columns = self
+            .information_schema_columns
+            .iter()
+            .filter(|tuple| {
+                varchar(tuple, 0) == db_name.as_str() && varchar(tuple, 1) == table_name.as_str()
+            })
+            .map(|tuple| {
+                let Value::Integer(ordinal_position) = tuple.values()[3] else {
+                    unreachable!("ORDINAL_POSITION is a non-nullable INTEGER");
+                };
+                let constraints = match varchar(&tuple, 4) {
+                    "YES" => ConstraintsBuilder::new().nullable().build(),
+                    _ => ConstraintsBuilder::new().build(),
+                };
+                let data_type = varchar(&tuple, 5)
+                    .parse()
+                    .expect("DATA_TYPE is written by create_table");
+                let column = Column::new(varchar(&tuple, 2).to_string(), data_type, constraints);
+                (ordinal_position, column)
+            })
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            return Err(CatalogError::TableNotFound);
+        }
 
-        let table_name = TableName::try_from("test_tbl").unwrap();
-        let schema = Schema::try_new(vec![
-            Column::new(
-                "id".into(),
-                DataType::Integer,
-                ConstraintsBuilder::new().build(),
-            ),
-            Column::new(
-                "name".into(),
-                DataType::VarChar,
-                ConstraintsBuilder::new().build(),
-            ),
-        ])
+        columns.sort_by_key(|(ordinal_position, _)| *ordinal_position);
+        Ok(Schema::try_new(columns.into_iter().map(|(_, column)| column).collect()).unwrap())
+    }
+
+    /// Counts the rows and the pages of a table and stores them in
+    /// INFORMATION_SCHEMA.TABLES, for the planner to order joins.
+    pub fn analyze_table<T: StorageBackend + 'static>(
+        &mut self,
+        db_name: &DatabaseName,
+        table: &Table<T>,
+    ) -> Result<TableStats, CatalogError> {
+        if let Some(attached) = self.attached.get_mut(db_name) {
+            return attached.catalog.analyze_table(&attached.db_name, table);
+        }
+        // Tables of attached databases are named `alias.table`.
+        let table_name = table
+            .name
+            .rsplit_once('.')
+            .map_or(table.name.as_str(), |(_, name)| name);
+        let stats = table.statistics();
+
+        let mut iter = self.information_schema_tables.iter();
+        let record_id = loop {
+            let (record_id, tuple) = iter.next_record().ok_or(CatalogError::TableNotFound)?;
+            if varchar(&tuple, 0) == db_name.as_str()
+                && varchar(&tuple, 1) == "table"
+                && varchar(&tuple, 2) == table_name
+            {
+                break record_id;
+            }
+        };
+
+        let tuple = Tuple::try_new(vec![
+            Value::VarChar(db_name.as_str().to_string()),
+            Value::VarChar("table".to_string()),
+            Value::VarChar(table_name.to_string()),
+            Value::Integer(stats.rows as i64),
+            Value::Integer(stats.pages as i64),
+        ])
+        .map_err(|_| CatalogError::UpdateStatistics)?;
+        self.information_schema_tables
+            .delete(record_id)
+            .map_err(|_| CatalogError::UpdateStatistics)?;
+        self.information_schema_tables
+            .insert(&tuple)
+            .map_err(|_| CatalogError::UpdateStatistics)?;
+
+        Ok(stats)
+    }
+
+    /// Returns the statistics of a table stored by `analyze_table`, zeros if
+    /// it was never analyzed.
+    pub fn table_stats(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &TableName,
+    ) -> Result<TableStats, CatalogError> {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached.catalog.table_stats(&attached.db_name, table_name);
+        }
+
+        let tuple = self
+            .information_schema_tables
+            .iter()
+            .find(|tuple| {
+                varchar(tuple, 0) == db_name.as_str()
+                    && varchar(tuple, 1) == "table"
+                    && varchar(tuple, 2) == table_name.as_str()
+            })
+            .ok_or(CatalogError::TableNotFound)?;
+        let (Value::Integer(rows), Value::Integer(pages)) =
+            (&tuple.values()[3], &tuple.values()[4])
+        else {
+            unreachable!("TABLE_ROWS and TABLE_PAGES are non-nullable INTEGERs");
+        };
+
+        Ok(TableStats {
+            rows: *rows as u64,
+            pages: *pages as u64,
+        })
+    }
+
+    /// Counts the keys, pages and levels of an index and stores them in
+    /// INFORMATION_SCHEMA.TABLES and INDEXES, for the planner to cost index
+    /// lookups.
+    pub fn analyze_index<T: StorageBackend + 'static>(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+        index: &BTree<T>,
+    ) -> Result<IndexStats, CatalogError> {
+        if let Some(attached) = self.attached.get_mut(db_name) {
+            return attached
+                .catalog
+                .analyze_index(&attached.db_name, index_name, index);
+        }
+        let stats = index.stats().map_err(|_| CatalogError::UpdateStatistics)?;
+        let stats = index_stats(&stats);
+
+        let mut iter = self.information_schema_tables.iter();
+        let (record_id, tuple) = loop {
+            let (record_id, tuple) = iter.next_record().ok_or(CatalogError::IndexNotFound)?;
+            if varchar(&tuple, 0) == db_name.as_str()
+                && varchar(&tuple, 1) == "index"
+                && varchar(&tuple, 2) == index_name.as_str()
+            {
+                break (record_id, tuple);
+            }
+        };
+        let mut values = tuple.values().to_vec();
+        values[3] = Value::Integer(stats.keys as i64);
+        values[4] = Value::Integer(stats.pages as i64);
+        let tuple = Tuple::try_new(values).map_err(|_| CatalogError::UpdateStatistics)?;
+        self.information_schema_tables
+            .update(record_id, &tuple)
+            .map_err(|_| CatalogError::UpdateStatistics)?;
+
+        let mut iter = self.information_schema_indexes.iter();
+        let (record_id, tuple) = loop {
+            let (record_id, tuple) = iter.next_record().ok_or(CatalogError::IndexNotFound)?;
+            if varchar(&tuple, 0) == db_name.as_str() && varchar(&tuple, 2) == index_name.as_str() {
+                break (record_id, tuple);
+            }
+        };
+        let mut values = tuple.values().to_vec();
+        values[4] = Value::Integer(stats.height as i64);
+        let tuple = Tuple::try_new(values).map_err(|_| CatalogError::UpdateStatistics)?;
+        self.information_schema_indexes
+            .update(record_id, &tuple)
+            .map_err(|_| CatalogError::UpdateStatistics)?;
+
+        Ok(stats)
+    }
+
+    /// Returns the statistics of the index on a column of a table, stored by
+    /// `create_index` and `analyze_index`.
+    pub fn index_stats(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &str,
+        column_name: &str,
+    ) -> Result<IndexStats, CatalogError> {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached
+                .catalog
+                .index_stats(&attached.db_name, table_name, column_name);
+        }
+
+        let ((_, index_name), _) = self
+            .indexes
+            .iter()
+            .find(|((db, _), entry)| {
+                db == db_name && entry.table_name.as_str() == table_name && entry.is_on(column_name)
+            })
+            .ok_or(CatalogError::IndexNotFound)?;
+        let tables_tuple = self
+            .information_schema_tables
+            .iter()
+            .find(|tuple| {
+                varchar(tuple, 0) == db_name.as_str()
+                    && varchar(tuple, 1) == "index"
+                    && varchar(tuple, 2) == index_name.as_str()
+            })
+            .ok_or(CatalogError::IndexNotFound)?;
+        let indexes_tuple = self
+            .information_schema_indexes
+            .iter()
+            .find(|tuple| {
+                varchar(tuple, 0) == db_name.as_str() && varchar(tuple, 2) == index_name.as_str()
+            })
+            .ok_or(CatalogError::IndexNotFound)?;
+
+        Ok(IndexStats {
+            keys: integer(&tables_tuple, 3) as u64,
+            pages: integer(&tables_tuple, 4) as u64,
+            height: integer(&indexes_tuple, 4) as u64,
+        })
+    }
+
+    /// Whether a column of a table has an index.
+    pub fn has_index(&self, db_name: &DatabaseName, table_name: &str, column_name: &str) -> bool {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached
+                .catalog
+                .has_index(&attached.db_name, table_name, column_name);
+        }
+
+        self.indexes.iter().any(|((db, _), entry)| {
+            db == db_name && entry.table_name.as_str() == table_name && entry.is_on(column_name)
+        })
+    }
+
+    /// The columns included in the index on a column of a table, empty if
+    /// the index isn't a covering one or if there is no index.
+    pub fn included_columns(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &str,
+        column_name: &str,
+    ) -> Vec<String> {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached
+                .catalog
+                .included_columns(&attached.db_name, table_name, column_name);
+        }
+
+        self.indexes
+            .iter()
+            .find(|((db, _), entry)| {
+                db == db_name && entry.table_name.as_str() == table_name && entry.is_on(column_name)
+            })
+            .map(|(_, entry)| entry.included.clone())
+            .unwrap_or_default()
+    }
+
+    /// The data structure of the index on a column of a table, `None` if
+    /// there is no index.
+    pub fn index_method(
+        &self,
+        db_name: &DatabaseName,
+        table_name: &str,
+        column_name: &str,
+    ) -> Option<IndexMethod> {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached
+                .catalog
+                .index_method(&attached.db_name, table_name, column_name);
+        }
+
+        self.indexes
+            .iter()
+            .find(|((db, _), entry)| {
+                db == db_name && entry.table_name.as_str() == table_name && entry.is_on(column_name)
+            })
+            .map(|(_, entry)| entry.method)
+    }
+
+    /// The key columns of an index, in order, `None` if there is no such
+    /// index.
+    pub fn index_columns(
+        &self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+    ) -> Option<Vec<KeyColumn>> {
+        if let Some(attached) = self.attached.get(db_name) {
+            return attached
+                .catalog
+                .index_columns(&attached.db_name, index_name);
+        }
+
+        self.indexes
+            .get(&(db_name.clone(), index_name.clone()))
+            .map(|entry| entry.key.clone())
+    }
+
+    /// Resolves the tables of a query in a database.
+    pub fn database<'a>(&'a self, db_name: &'a DatabaseName) -> DatabaseSchemas<'a, S> {
+        DatabaseSchemas {
+            catalog: self,
+            db_name,
+        }
+    }
+}
+
+/// The tables of a database, as seen by the planner. Tables of other
+/// databases, attached ones included, are named `database.table`.
+pub struct DatabaseSchemas<'a, S: StorageBackend + 'static> {
+    catalog: &'a Catalog<S>,
+    db_name: &'a DatabaseName,
+}
+
+impl<S: StorageBackend + 'static> DatabaseSchemas<'_, S> {
+    fn resolve<'n>(&self, name: &'n str) -> Option<(DatabaseName, &'n str)> {
+        match name.split_once('.') {
+            Some((db_name, table_name)) => {
+                Some((DatabaseName::try_from(db_name).ok()?, table_name))
+            }
+            None => Some((self.db_name.clone(), name)),
+        }
+    }
+}
+
+impl<S: StorageBackend + 'static> SchemaProvider for DatabaseSchemas<'_, S> {
+    fn table_schema(&self, name: &str) -> Option<Schema> {
+        let (db_name, table_name) = self.resolve(name)?;
+        let table_name = TableName::try_from(table_name).ok()?;
+        self.catalog.table_schema(&db_name, &table_name).ok()
+    }
+
+    fn table_stats(&self, name: &str) -> Option<TableStats> {
+        let (db_name, table_name) = self.resolve(name)?;
+        let table_name = TableName::try_from(table_name).ok()?;
+        self.catalog.table_stats(&db_name, &table_name).ok()
+    }
+
+    fn has_index(&self, table: &str, column: &str) -> bool {
+        self.resolve(table)
+            .is_some_and(|(db_name, table)| self.catalog.has_index(&db_name, table, column))
+    }
+
+    fn index_stats(&self, table: &str, column: &str) -> Option<IndexStats> {
+        let (db_name, table) = self.resolve(table)?;
+        self.catalog.index_stats(&db_name, table, column).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pages::check::check_file;
+    use crate::pages::inspect::FileKind;
+    use crate::pages::{HeapPageSlotId, PageId};
+    use crate::table;
+
+    #[test]
+    fn insert_and_scan_catalog() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        let _ = catalog.create_database(&db_name);
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
         .unwrap();
 
         catalog
@@ -261,4 +1454,857 @@ mod tests {
         assert_eq!(catalog.information_schema_tables.iter().count(), 1);
         assert_eq!(catalog.information_schema_columns.iter().count(), 2);
     }
+
+    #[test]
+    fn resolve_table_schema() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let data_types = [
+            DataType::Integer,
+            DataType::VarChar(Some(20)),
+            DataType::Char(3),
+            DataType::Decimal {
+                precision: 10,
+                scale: 2,
+            },
+        ];
+        let columns = data_types
+            .iter()
+            .enumerate()
+            .map(|(i, data_type)| {
+                Column::new(
+                    format!("c{i}"),
+                    *data_type,
+                    ConstraintsBuilder::new().nullable().build(),
+                )
+            })
+            .collect();
+        let schema = Schema::try_new(columns).unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+
+        let tables = catalog.database(&db_name);
+        let schema = tables.table_schema("test_tbl").unwrap();
+        let columns = schema
+            .columns()
+            .iter()
+            .map(|column| (column.column_name.as_str(), column.data_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            ["c0", "c1", "c2", "c3"]
+                .into_iter()
+                .zip(data_types)
+                .collect::<Vec<_>>()
+        );
+        assert!(schema.columns()[0].constraints.is_nullable());
+        assert!(tables.table_schema("nope").is_none());
+
+        let stmts = crate::sql::parser::parser::Parser::parse("SELECT c3 FROM test_tbl").unwrap();
+        let plan = crate::sql::plan::plan(&stmts[0], &tables).unwrap();
+        assert_eq!(
+            plan.schema().columns()[0].data_type,
+            Some(DataType::Decimal {
+                precision: 10,
+                scale: 2
+            })
+        );
+    }
+
+    #[test]
+    fn create_and_drop_index() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        assert!(matches!(
+            catalog.create_index(&db_name, &index_name, &table_name, "name", &[]),
+            Err(CatalogError::ColumnNotFound)
+        ));
+        assert!(matches!(
+            catalog.create_index(&db_name, &table_name, &table_name, "id", &[]),
+            Err(CatalogError::CreateIndex)
+        ));
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "id", &[])
+            .unwrap();
+        assert!(catalog.db_root.index_path(&db_name, &index_name).is_some());
+        assert_eq!(catalog.information_schema_tables.iter().count(), 2);
+
+        // the registry is rebuilt from INFORMATION_SCHEMA
+        drop(catalog);
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let key = (db_name.clone(), index_name.clone());
+        assert_eq!(
+            catalog.indexes.get(&key),
+            Some(&IndexEntry {
+                table_name: table_name.clone(),
+                key: vec![KeyColumn::ascending("id")],
+                included: vec![],
+                method: IndexMethod::BTree,
+            })
+        );
+
+        catalog.drop_index(&db_name, &index_name).unwrap();
+        assert!(matches!(
+            catalog.drop_index(&db_name, &index_name),
+            Err(CatalogError::IndexNotFound)
+        ));
+        assert!(catalog.db_root.index_path(&db_name, &index_name).is_none());
+        assert_eq!(catalog.information_schema_tables.iter().count(), 1);
+        assert_eq!(catalog.information_schema_indexes.iter().count(), 0);
+    }
+
+    #[test]
+    fn create_index_on_existing_rows() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let table = catalog.open_table(&db_name, &table_name).unwrap();
+        for id in (0..2000).rev() {
+            let name = match id % 3 {
+                0 => Value::Null,
+                _ => Value::VarChar(format!("name{}", id % 7)),
+            };
+            table
+                .insert(&Tuple::try_new(vec![Value::Integer(id), name]).unwrap())
+                .unwrap();
+        }
+        GLOBAL_PAGE_CACHE.flush();
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "id", &[])
+            .unwrap();
+        GLOBAL_PAGE_CACHE.flush();
+        let index_path = catalog.db_root.index_path(&db_name, &index_name).unwrap();
+        let report = check_file(index_path, FileKind::BTree).unwrap();
+        assert!(report.is_ok(), "{report}");
+        // The superblock, the free list, leaves and their root.
+        assert!(report.pages > 4);
+        let stats = catalog.index_stats(&db_name, "test_tbl", "id").unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                keys: 2000,
+                pages: report.pages as u64 - 2,
+                height: 2,
+            }
+        );
+
+        // Names repeat, the column isn't unique. NULLs aren't indexed.
+        let index_name = TableName::try_from("test_name_idx").unwrap();
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "name", &[])
+            .unwrap();
+        let stats = catalog.index_stats(&db_name, "test_tbl", "name").unwrap();
+        assert_eq!(stats.keys, 2000 - 667);
+    }
+
+    #[test]
+    fn create_hash_index() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let table = catalog.open_table(&db_name, &table_name).unwrap();
+        for id in 0..2000 {
+            let name = Value::VarChar(format!("name{}", id % 7));
+            table
+                .insert(&Tuple::try_new(vec![Value::Integer(id), name]).unwrap())
+                .unwrap();
+        }
+        GLOBAL_PAGE_CACHE.flush();
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        catalog
+            .create_hash_index(&db_name, &index_name, &table_name, "id")
+            .unwrap();
+        let stats = catalog.index_stats(&db_name, "test_tbl", "id").unwrap();
+        assert_eq!((stats.keys, stats.height), (2000, 2));
+        assert!(stats.pages > 2);
+        assert_eq!(
+            catalog.index_method(&db_name, "test_tbl", "id"),
+            Some(IndexMethod::Hash)
+        );
+        assert_eq!(catalog.index_method(&db_name, "test_tbl", "name"), None);
+
+        let name_index = TableName::try_from("test_name_idx").unwrap();
+        assert!(matches!(
+            catalog.create_hash_index(&db_name, &name_index, &table_name, "name"),
+            Err(CatalogError::CreateIndex)
+        ));
+        assert!(catalog.db_root.index_path(&db_name, &name_index).is_none());
+
+        // The method is persisted in INFORMATION_SCHEMA.
+        drop(catalog);
+        let mut catalog = Catalog::with_root_path(&root_path);
+        assert_eq!(
+            catalog.index_method(&db_name, "test_tbl", "id"),
+            Some(IndexMethod::Hash)
+        );
+        catalog.drop_index(&db_name, &index_name).unwrap();
+        assert_eq!(catalog.index_method(&db_name, "test_tbl", "id"), None);
+    }
+
+    #[test]
+    fn create_fulltext_index() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "body".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let table = catalog.open_table(&db_name, &table_name).unwrap();
+        for id in 0..2000 {
+            let body = match id % 3 {
+                0 => Value::Null,
+                _ => Value::VarChar(format!("row {id} of {}", id % 7)),
+            };
+            table
+                .insert(&Tuple::try_new(vec![Value::Integer(id), body]).unwrap())
+                .unwrap();
+        }
+        GLOBAL_PAGE_CACHE.flush();
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        catalog
+            .create_fulltext_index(&db_name, &index_name, &table_name, "body")
+            .unwrap();
+        // One posting per term of the texts: "row", "of", the id and the
+        // remainder, the same as the id for ids below 7.
+        let rows = (0..2000).filter(|id| id % 3 != 0);
+        let postings = rows.map(|id| if id < 7 { 3 } else { 4 }).sum::<u64>();
+        let stats = catalog.index_stats(&db_name, "test_tbl", "body").unwrap();
+        assert_eq!(stats.keys, postings);
+        assert_eq!(
+            catalog.index_method(&db_name, "test_tbl", "body"),
+            Some(IndexMethod::FullText)
+        );
+
+        let id_index = TableName::try_from("test_id_idx").unwrap();
+        assert!(matches!(
+            catalog.create_fulltext_index(&db_name, &id_index, &table_name, "id"),
+            Err(CatalogError::CreateIndex)
+        ));
+        assert!(catalog.db_root.index_path(&db_name, &id_index).is_none());
+
+        // The method is persisted in INFORMATION_SCHEMA.
+        drop(catalog);
+        let mut catalog = Catalog::with_root_path(&root_path);
+        assert_eq!(
+            catalog.index_method(&db_name, "test_tbl", "body"),
+            Some(IndexMethod::FullText)
+        );
+        catalog.drop_index(&db_name, &index_name).unwrap();
+        assert_eq!(catalog.index_method(&db_name, "test_tbl", "body"), None);
+    }
+
+    #[test]
+    fn create_composite_index() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let table = catalog.open_table(&db_name, &table_name).unwrap();
+        for id in 0..300 {
+            let name = match id % 10 {
+                0 => Value::Null,
+                _ => Value::VarChar(format!("name{}", id % 7)),
+            };
+            table
+                .insert(&Tuple::try_new(vec![Value::Integer(id), name]).unwrap())
+                .unwrap();
+        }
+        GLOBAL_PAGE_CACHE.flush();
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        let key = [KeyColumn::ascending("name"), KeyColumn::descending("id")];
+        assert!(matches!(
+            catalog.create_composite_index(&db_name, &index_name, &table_name, &[], &[]),
+            Err(CatalogError::CreateIndex)
+        ));
+        catalog
+            .create_composite_index(&db_name, &index_name, &table_name, &key, &[])
+            .unwrap();
+        GLOBAL_PAGE_CACHE.flush();
+        let index_path = catalog.db_root.index_path(&db_name, &index_name).unwrap();
+        let report = check_file(index_path, FileKind::BTree).unwrap();
+        assert!(report.is_ok(), "{report}");
+        // Names repeat, the column isn't unique.
+        let name_index = TableName::try_from("test_name_idx").unwrap();
+        catalog
+            .create_composite_index(
+                &db_name,
+                &name_index,
+                &table_name,
+                &[KeyColumn::descending("name")],
+                &[],
+            )
+            .unwrap();
+        // Not an index on a single column.
+        assert!(!catalog.has_index(&db_name, "test_tbl", "name"));
+
+        // The key columns are persisted in INFORMATION_SCHEMA.
+        drop(catalog);
+        let catalog = Catalog::with_root_path(&root_path);
+        assert_eq!(
+            catalog.index_columns(&db_name, &index_name),
+            Some(key.to_vec())
+        );
+        assert_eq!(
+            key.map(|column| column.to_string()),
+            ["name ASC", "id DESC"]
+        );
+    }
+
+    #[test]
+    fn build_index_online() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let mut table = catalog.open_table(&db_name, &table_name).unwrap();
+        let row = |id: i64| Tuple::try_new(vec![Value::Integer(id)]).unwrap();
+        for id in 0..1000 {
+            table.insert(&row(id)).unwrap();
+        }
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        let key = [KeyColumn::ascending("id")];
+        let build = catalog
+            .build_index_online(&db_name, &index_name, &table_name, &table, &key, &[])
+            .unwrap();
+        // Not registered until swapped in.
+        assert!(!catalog.has_index(&db_name, "test_tbl", "id"));
+        let record_id = table.insert(&row(1000)).unwrap();
+        catalog.finish_index_build(&mut table, build).unwrap();
+
+        assert!(catalog.has_index(&db_name, "test_tbl", "id"));
+        let stats = catalog.index_stats(&db_name, "test_tbl", "id").unwrap();
+        assert_eq!(stats.keys, 1001);
+        let index = table.index(0).unwrap();
+        assert_eq!(
+            table::lookup(index, &Key::from_values(&[Value::Integer(1000)])).unwrap(),
+            [record_id]
+        );
+        assert!(matches!(
+            catalog.build_index_online(&db_name, &index_name, &table_name, &table, &key, &[]),
+            Err(CatalogError::CreateIndex)
+        ));
+    }
+
+    #[test]
+    fn analyze_table() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let index_name = TableName::try_from("test_idx").unwrap();
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "id", &[])
+            .unwrap();
+        assert_eq!(
+            catalog.table_stats(&db_name, &table_name).unwrap(),
+            TableStats::default()
+        );
+
+        let table = catalog.open_table(&db_name, &table_name).unwrap();
+        for id in 0..10 {
+            let tuple = Tuple::try_new(vec![Value::Integer(id)]).unwrap();
+            table.insert(&tuple).unwrap();
+        }
+        let stats = catalog.analyze_table(&db_name, &table).unwrap();
+        assert_eq!(stats, TableStats { rows: 10, pages: 1 });
+        drop(table);
+
+        // the statistics are persisted, and seen by the planner
+        drop(catalog);
+        let catalog = Catalog::with_root_path(&root_path);
+        assert_eq!(catalog.table_stats(&db_name, &table_name).unwrap(), stats);
+        let schemas = catalog.database(&db_name);
+        assert_eq!(schemas.table_stats("test_tbl"), Some(stats));
+        assert!(schemas.has_index("test_tbl", "id"));
+        assert!(!schemas.has_index("test_idx", "id"));
+    }
+
+    #[test]
+    fn create_covering_index() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+
+        let index_name = TableName::try_from("test_idx").unwrap();
+        assert!(matches!(
+            catalog.create_index(&db_name, &index_name, &table_name, "id", &["nope"]),
+            Err(CatalogError::ColumnNotFound)
+        ));
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "id", &["name"])
+            .unwrap();
+        assert_eq!(
+            catalog.included_columns(&db_name, "test_tbl", "id"),
+            vec!["name".to_string()]
+        );
+        assert!(
+            catalog
+                .included_columns(&db_name, "test_tbl", "name")
+                .is_empty()
+        );
+
+        // the included columns are persisted in INFORMATION_SCHEMA
+        drop(catalog);
+        let catalog = Catalog::with_root_path(&root_path);
+        assert_eq!(
+            catalog.included_columns(&db_name, "test_tbl", "id"),
+            vec!["name".to_string()]
+        );
+    }
+
+    #[test]
+    fn analyze_index() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let index_name = TableName::try_from("test_idx").unwrap();
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "id", &[])
+            .unwrap();
+        let empty = IndexStats {
+            keys: 0,
+            pages: 1,
+            height: 1,
+        };
+        assert_eq!(
+            catalog.index_stats(&db_name, "test_tbl", "id").unwrap(),
+            empty
+        );
+
+        // Index files can't be reopened yet: analyze a tree built aside.
+        let storage = FileStorage::create(tempfile::NamedTempFile::new().unwrap()).unwrap();
+        let btree = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let record_id = RecordId::new(PageId::new(1), HeapPageSlotId::new(0));
+        for key in 0..5000 {
+            btree.insert(&Key::new(key), record_id).unwrap();
+        }
+        let stats = catalog
+            .analyze_index(&db_name, &index_name, &btree)
+            .unwrap();
+        assert_eq!((stats.keys, stats.height), (5000, 2));
+        assert!(stats.pages > 2);
+
+        // The statistics are persisted, and seen by the planner.
+        drop(catalog);
+        let catalog = Catalog::with_root_path(&root_path);
+        let schemas = catalog.database(&db_name);
+        assert_eq!(schemas.index_stats("test_tbl", "id"), Some(stats));
+        assert_eq!(schemas.index_stats("test_tbl", "name"), None);
+    }
+
+    #[test]
+    fn backup() {
+        let root_path = tempfile::TempDir::new().unwrap().keep();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        let index_name = TableName::try_from("test_idx").unwrap();
+        catalog
+            .create_index(&db_name, &index_name, &table_name, "id", &[])
+            .unwrap();
+        let table = catalog.open_table(&db_name, &table_name).unwrap();
+        for id in 0..10 {
+            let tuple = Tuple::try_new(vec![Value::Integer(id)]).unwrap();
+            table.insert(&tuple).unwrap();
+        }
+        drop(table);
+
+        let backup_path = root_path.join("backup");
+        catalog.backup_to(&backup_path).unwrap();
+        assert!(matches!(
+            catalog.backup_to(&backup_path),
+            Err(CatalogError::Backup)
+        ));
+
+        let backup = Catalog::with_root_path(&backup_path);
+        let table = backup.open_table(&db_name, &table_name).unwrap();
+        assert_eq!(table.iter().count(), 10);
+        assert!(backup.database(&db_name).has_index("test_tbl", "id"));
+    }
+
+    #[test]
+    fn sync_mode_per_database() {
+        let root_path = tempfile::TempDir::new().unwrap().keep();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        let users = TableName::try_from("users").unwrap();
+        let durable = DatabaseName::try_from("durable").unwrap();
+        let other = DatabaseName::try_from("other").unwrap();
+        for db_name in [&durable, &other] {
+            catalog.create_database(db_name).unwrap();
+            catalog.create_table(db_name, &users, &schema).unwrap();
+        }
+
+        catalog
+            .set_sync_mode(&durable, Some(SyncMode::Full))
+            .unwrap();
+        assert_eq!(catalog.sync_mode(&durable), Some(SyncMode::Full));
+        assert_eq!(catalog.sync_mode(&other), None);
+        let table = catalog.open_table(&durable, &users).unwrap();
+        assert_eq!(table.sync_mode(), SyncMode::Full);
+        let tuple = Tuple::try_new(vec![Value::Integer(1)]).unwrap();
+        table.insert(&tuple).unwrap();
+        table.commit();
+        let table = catalog.open_table(&other, &users).unwrap();
+        assert_eq!(table.sync_mode(), GLOBAL_PAGE_CACHE.sync_mode());
+
+        // Back to the mode of the page cache.
+        catalog.set_sync_mode(&durable, None).unwrap();
+        let table = catalog.open_table(&durable, &users).unwrap();
+        assert_eq!(table.sync_mode(), GLOBAL_PAGE_CACHE.sync_mode());
+        assert_eq!(table.iter().count(), 1);
+
+        let missing = DatabaseName::try_from("missing").unwrap();
+        assert!(matches!(
+            catalog.set_sync_mode(&missing, Some(SyncMode::Off)),
+            Err(CatalogError::DatabaseNotFound)
+        ));
+    }
+
+    #[test]
+    fn attach_database() {
+        let other_root = tempfile::TempDir::new().unwrap().keep();
+        let mut other = Catalog::with_root_path(&other_root);
+        let shop = DatabaseName::try_from("shop").unwrap();
+        other.create_database(&shop).unwrap();
+        let users = TableName::try_from("users").unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        other.create_table(&shop, &users, &schema).unwrap();
+        let index_name = TableName::try_from("users_id").unwrap();
+        other
+            .create_index(&shop, &index_name, &users, "id", &[])
+            .unwrap();
+        let table = other.open_table(&shop, &users).unwrap();
+        for id in 0..10 {
+            let tuple = Tuple::try_new(vec![Value::Integer(id)]).unwrap();
+            table.insert(&tuple).unwrap();
+        }
+        drop(table);
+        GLOBAL_PAGE_CACHE.flush();
+
+        let root_path = tempfile::TempDir::new().unwrap().keep();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+        let alias = DatabaseName::try_from("other_shop").unwrap();
+        catalog.attach(&alias, other_root.join("shop")).unwrap();
+
+        let table = catalog.open_table(&alias, &users).unwrap();
+        assert_eq!(table.name, "other_shop.users");
+        assert_eq!(table.iter().count(), 10);
+        let schemas = catalog.database(&db_name);
+        let resolved = schemas.table_schema("other_shop.users").unwrap();
+        assert_eq!(resolved.columns()[0].column_name, "id");
+        assert!(schemas.has_index("other_shop.users", "id"));
+        assert!(schemas.table_schema("users").is_none());
+        let stmts =
+            crate::sql::parser::parser::Parser::parse("SELECT id FROM other_shop.users").unwrap();
+        assert!(crate::sql::plan::plan(&stmts[0], &schemas).is_ok());
+
+        // The alias is taken, the database or the root don't exist, the root
+        // is already open.
+        let attach = |catalog: &mut Catalog<FileStorage>, alias: &str, path: &Path| {
+            catalog.attach(&DatabaseName::try_from(alias).unwrap(), path)
+        };
+        assert!(matches!(
+            attach(&mut catalog, "other_shop", &other_root.join("shop")),
+            Err(CatalogError::AttachDatabase)
+        ));
+        assert!(matches!(
+            attach(&mut catalog, "test_db", &other_root.join("shop")),
+            Err(CatalogError::AttachDatabase)
+        ));
+        assert!(matches!(
+            attach(&mut catalog, "missing", &other_root.join("missing")),
+            Err(CatalogError::DatabaseNotFound)
+        ));
+        std::fs::create_dir_all(root_path.join("plain").join("db")).unwrap();
+        assert!(matches!(
+            attach(
+                &mut catalog,
+                "not_a_root",
+                &root_path.join("plain").join("db")
+            ),
+            Err(CatalogError::AttachDatabase)
+        ));
+        assert!(matches!(
+            attach(&mut catalog, "self", &root_path.join("test_db")),
+            Err(CatalogError::AttachDatabase)
+        ));
+
+        catalog.detach(&alias).unwrap();
+        assert!(catalog.table_schema(&alias, &users).is_err());
+        assert!(matches!(
+            catalog.detach(&alias),
+            Err(CatalogError::DatabaseNotFound)
+        ));
+    }
+
+    #[test]
+    fn drop_table_with_indexes() {
+        let root_path = tempfile::TempDir::new()
+            .unwrap()
+            .keep()
+            .to_string_lossy()
+            .into_owned();
+        let mut catalog = Catalog::with_root_path(&root_path);
+        let db_name = DatabaseName::try_from("test_db").unwrap();
+        catalog.create_database(&db_name).unwrap();
+
+        let table_name = TableName::try_from("test_tbl").unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
+        .unwrap();
+        catalog
+            .create_table(&db_name, &table_name, &schema)
+            .unwrap();
+        for (index, column) in [("idx_id", "id"), ("idx_name", "name")] {
+            let index_name = TableName::try_from(index).unwrap();
+            catalog
+                .create_index(&db_name, &index_name, &table_name, column, &[])
+                .unwrap();
+        }
+
+        let err = catalog
+            .drop_table(&db_name, &table_name, false)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot drop table, other objects depend on it: index idx_id, index idx_name"
+        );
+        assert!(catalog.db_root.table_path(&db_name, &table_name).is_some());
+
+        catalog.drop_table(&db_name, &table_name, true).unwrap();
+        assert!(catalog.db_root.table_path(&db_name, &table_name).is_none());
+        assert!(catalog.indexes.is_empty());
+        assert_eq!(catalog.information_schema_tables.iter().count(), 0);
+        assert_eq!(catalog.information_schema_columns.iter().count(), 0);
+        assert_eq!(catalog.information_schema_indexes.iter().count(), 0);
+        assert!(matches!(
+            catalog.drop_table(&db_name, &table_name, true),
+            Err(CatalogError::TableNotFound)
+        ));
+    }
 }
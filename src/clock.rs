@@ -0,0 +1,51 @@
+//! Time sources.
+//!
+//! Components that need the current time (eviction policies, timestamps)
+//! read it from a `ClockSource` instead of the system clock, so that tests
+//! can control time with a `ManualClock`.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+pub trait ClockSource: Send + Sync {
+    /// Returns the current time, in nanoseconds since the Unix epoch.
+    fn now(&self) -> i64;
+}
+
+/// The system wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> i64 {
+        chrono::Utc::now().timestamp_nanos_opt().unwrap()
+    }
+}
+
+/// A clock only moving forward when told to.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: AtomicI64::new(now),
+        }
+    }
+
+    /// Moves the clock forward by `nanos` nanoseconds.
+    pub fn advance(&self, nanos: i64) {
+        self.now.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::Relaxed);
+    }
+}
+
+impl ClockSource for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
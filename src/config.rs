@@ -1,19 +1,174 @@
-use crate::cache::DEFAULT_PAGE_CACHE_SIZE;
+use crate::cache::{
+    DEFAULT_PAGE_CACHE_RESERVE, DEFAULT_PAGE_CACHE_SIZE, GLOBAL_PAGE_CACHE, PageCacheError,
+    SyncMode,
+};
 
+use std::path::Path;
 use std::{sync::LazyLock, time::Duration};
 
+use thiserror::Error;
+
 #[allow(non_snake_case)]
 pub struct Config {
     // number of pages in cache
     pub PAGE_CACHE_SIZE: usize,
+    // number of pages the cache can borrow beyond its size when every page is pinned
+    pub PAGE_CACHE_RESERVE: usize,
     // root directory
     pub ROOT_DIRECTORY: String,
     // interval between pagecache write back to storage
     pub WRITEBACK_INTERVAL_MS: Duration,
+    // initial sync mode of the page cache
+    pub SYNC_MODE: SyncMode,
+    // bypass the operating system's cache for storage files, where supported
+    pub DIRECT_IO: bool,
+    // number of pages storage files are preallocated by as they grow
+    pub EXTENSION_PAGES: u32,
+    // number of pages prefetched ahead of sequential reads, 0 to disable read-ahead
+    pub READ_AHEAD_PAGES: u32,
+    // file of the key storage files are encrypted with, unencrypted if unset
+    pub ENCRYPTION_KEY_FILE: Option<String>,
 }
 
 pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
     PAGE_CACHE_SIZE: DEFAULT_PAGE_CACHE_SIZE,
+    PAGE_CACHE_RESERVE: DEFAULT_PAGE_CACHE_RESERVE,
     ROOT_DIRECTORY: "/tmp/joujoudb".to_string(),
     WRITEBACK_INTERVAL_MS: Duration::from_millis(50),
+    SYNC_MODE: SyncMode::Interval,
+    DIRECT_IO: true,
+    EXTENSION_PAGES: 256,
+    READ_AHEAD_PAGES: 32,
+    ENCRYPTION_KEY_FILE: None,
 });
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unrecognized configuration parameter {0}")]
+    UnknownSetting(String),
+    #[error("invalid value for parameter {name}: {value}")]
+    InvalidValue { name: String, value: String },
+    #[error("syntax error in configuration file, line {0}: expected name = value")]
+    Syntax(usize),
+    #[error("configuration file error")]
+    Io(#[from] std::io::Error),
+    #[error("page cache error")]
+    PageCache(#[from] PageCacheError),
+}
+
+/// A parameter changed while running, by `SET GLOBAL name = value` or by
+/// reloading a configuration file, see `reload`. `CONFIG` keeps the values
+/// the database started with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    /// `page_cache_size`: the number of pages of the global page cache, see
+    /// `PageCacheInner::resize`.
+    PageCacheSize(usize),
+}
+
+impl Setting {
+    /// Parses the value of a parameter, given by its name.
+    pub fn parse(name: &str, value: &str) -> Result<Self, ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        match name.to_lowercase().as_str() {
+            "page_cache_size" => match value.parse::<usize>() {
+                Ok(0) | Err(_) => Err(invalid()),
+                Ok(num_pages) => Ok(Setting::PageCacheSize(num_pages)),
+            },
+            _ => Err(ConfigError::UnknownSetting(name.to_string())),
+        }
+    }
+
+    /// Applies the setting to the running database.
+    pub fn apply(&self) -> Result<(), ConfigError> {
+        match *self {
+            Setting::PageCacheSize(num_pages) => GLOBAL_PAGE_CACHE.resize(num_pages)?,
+        }
+        Ok(())
+    }
+}
+
+/// Reloads a configuration file and applies its settings, in order. Each line
+/// is `name = value`, empty lines and lines starting with `#` are skipped.
+///
+/// The file is parsed entirely first: if a line is invalid, no setting is
+/// applied. Returns the settings applied.
+pub fn reload<P: AsRef<Path>>(path: P) -> Result<Vec<Setting>, ConfigError> {
+    let settings = std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(ConfigError::Syntax(line_number))?;
+            Setting::parse(name.trim(), value.trim())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    settings.iter().try_for_each(Setting::apply)?;
+    Ok(settings)
+}
+
+/// Serializes the tests which change the settings of the running database.
+#[cfg(test)]
+pub(crate) static SETTINGS_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    fn config_file(contents: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn reload_page_cache_size() {
+        let _guard = SETTINGS_LOCK.lock();
+        let capacity = GLOBAL_PAGE_CACHE.capacity();
+
+        let file = config_file(&format!(
+            "# grown\n\npage_cache_size = {}\n  PAGE_CACHE_SIZE=  {}\n",
+            capacity + 32,
+            capacity + 16
+        ));
+        assert_eq!(
+            reload(file.path()).unwrap(),
+            [
+                Setting::PageCacheSize(capacity + 32),
+                Setting::PageCacheSize(capacity + 16)
+            ]
+        );
+        assert_eq!(GLOBAL_PAGE_CACHE.capacity(), capacity + 16);
+
+        // Nothing is applied when a line is invalid.
+        let valid = format!("page_cache_size = {}\n", capacity + 64);
+        let file = config_file(&(valid.clone() + "page_cache_size 8\n"));
+        assert!(matches!(reload(file.path()), Err(ConfigError::Syntax(2))));
+        let file = config_file(&(valid.clone() + "page_cache_size = 0\n"));
+        assert!(matches!(
+            reload(file.path()),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        let file = config_file(&(valid + "no_such_setting = 8\n"));
+        assert!(matches!(
+            reload(file.path()),
+            Err(ConfigError::UnknownSetting(_))
+        ));
+        assert_eq!(GLOBAL_PAGE_CACHE.capacity(), capacity + 16);
+
+        let file = config_file(&format!("page_cache_size = {capacity}"));
+        reload(file.path()).unwrap();
+        assert_eq!(GLOBAL_PAGE_CACHE.capacity(), capacity);
+    }
+}
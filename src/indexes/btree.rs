@@ -1,12 +1,20 @@
-use crate::cache::{PageCacheError, PageRef, PageRefMut, StoragePageCache};
+use crate::cache::{PageCacheError, PageRef, PageRefMut, PageVersion, StoragePageCache, SyncMode};
+use crate::pages::check::{CheckReport, check};
+use crate::pages::inspect::FileKind;
 use crate::pages::{
-    BTreePageError, BTreePageType, Key, PAGE_INVALID, PAGE_RESERVED, PageId, RecordId,
+    BLOOM_BITS_PER_KEY, BLOOM_PAGE_BITS, BTREE_MAX_BLOOM_PAGES, BTREE_MAX_KEY_SIZE, BTreeInnerPage,
+    BTreeLeafPage, BTreePage, BTreePageError, BTreePageType, BTreeSuperBlock, Key, PAGE_INVALID,
+    PAGE_RESERVED, Page, PageId, RecordId,
 };
 use crate::storage::StorageBackend;
 
-use crate::pages::btree_get_page_type;
+use crate::pages::{bloom_bits, btree_get_page_type, btree_try_get_page_type};
+
+use std::io;
+use std::ops::{Bound, RangeBounds};
 
 use thiserror::Error;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 /// A B+ tree implementation for indexing and storing key-value pairs.
 ///
@@ -15,11 +23,19 @@ use thiserror::Error;
 ///
 /// Key characteristics:
 /// - It is a B+ tree, meaning all records are stored in the leaf pages.
+/// - Keys are byte strings of variable length, compared byte by byte, see `Key`. Pages store
+///   the prefix shared by their keys once, and the separators of inner pages are truncated to
+///   the shortest key telling their children apart.
 /// - Leaf pages are linked together to allow for efficient range scans.
-/// - Deletion does not trigger merging or redistribution of nodes. This simplifies the
-///   implementation and can improve delete performance by avoiding complex rebalancing
-///   operations. However, it may lead to lower storage utilization over time if the
-///   workload has many deletions.
+/// - Lookups descend the tree without latching its pages (optimistic lock coupling): pages are
+///   copied along with their version, and the descent restarts if the parent of a page was
+///   written meanwhile. Only the leaf is latched, and after a few restarts the descent latches
+///   every page.
+/// - Pages split when their keys don't fit, leaving as many bytes on each side. Pages left
+///   with less than a third of their bytes used by a deletion borrow keys from a sibling,
+///   or are merged with it. The pages emptied by merges are freed, and the tree shrinks when the root
+///   is left with a single child.
+/// - The pages freed are recorded in a `FreeListPage`, and reused by splits before the file grows.
 ///
 /// B+ Tree Structure:
 /// ```text
@@ -53,6 +69,8 @@ use thiserror::Error;
 /// ```
 pub struct BTree<S: StorageBackend + 'static> {
     page_cache: StoragePageCache<S>,
+    // Also in the superblock, kept here not to latch it to allocate a page.
+    free_list_page_id: PageId,
 }
 
 #[derive(Error, Debug)]
@@ -61,12 +79,60 @@ pub enum BTreeError {
     Page(#[from] BTreePageError),
     #[error("page cache error")]
     PageCache(#[from] PageCacheError),
+    #[error("duplicate key {0}")]
+    DuplicateKey(Key),
+    #[error("key {0} is out of order")]
+    KeyNotSorted(Key),
+}
+
+/// The shape of a tree, see `BTree::stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BTreeStats {
+    /// The number of pages of each level, from the root to the leaves.
+    pub pages_per_level: Vec<u64>,
+    /// The fraction of the room of the pages used, averaged over the pages.
+    pub fill_factor: f64,
+    pub keys: u64,
+}
+
+impl BTreeStats {
+    /// The number of levels, 1 when the root is a leaf.
+    pub fn height(&self) -> usize {
+        self.pages_per_level.len()
+    }
+
+    pub fn pages(&self) -> u64 {
+        self.pages_per_level.iter().sum()
+    }
+}
+
+/// How many times an optimistic descent restarts before latching pages.
+const OPTIMISTIC_RESTARTS: usize = 4;
+
+/// The outcome of an optimistic descent.
+enum Descent<'a> {
+    /// The leaf of the key, with the version of its parent. The leaf is still
+    /// the one of the key as long as the version is valid.
+    Leaf(PageId, PageVersion<'a>),
+    /// A page was written during the descent.
+    Restart,
+    /// A page isn't in the memory cache, it has to be latched to be read.
+    Missing,
+}
+
+/// Duplicate keys are reported with the key.
+fn insert_error(key: &[u8], e: BTreePageError) -> BTreeError {
+    match e {
+        BTreePageError::DuplicateKey => BTreeError::DuplicateKey(Key::from(key)),
+        e => BTreeError::Page(e),
+    }
 }
 
 impl<S: StorageBackend> Clone for BTree<S> {
     fn clone(&self) -> Self {
         Self {
             page_cache: self.page_cache.clone(),
+            free_list_page_id: self.free_list_page_id,
         }
     }
 }
@@ -83,17 +149,238 @@ impl<S: StorageBackend + 'static> BTree<S> {
         let root_page_id = root_page_ref.metadata().page_id();
         let root_page = root_page_ref.btree_leaf_page_mut();
         root_page.init();
-        superblock.init(root_page_id);
+        let free_list_page_id = Self::new_free_list(&page_cache)?;
+        superblock.init(root_page_id, 0, free_list_page_id);
+        page_cache.set_page_dirty(root_page_ref.metadata());
+        page_cache.set_page_dirty(superblock_ref.metadata());
         drop(root_page_ref);
         drop(superblock_ref);
 
-        Ok(Self { page_cache })
+        Ok(Self {
+            page_cache,
+            free_list_page_id,
+        })
+    }
+
+    /// Allocates the empty free list of a new tree.
+    fn new_free_list(page_cache: &StoragePageCache<S>) -> Result<PageId, BTreeError> {
+        let mut page_ref = page_cache.new_page()?;
+        page_ref.free_list_page_mut().init();
+        page_cache.set_page_dirty(page_ref.metadata());
+        Ok(page_ref.metadata().page_id())
+    }
+
+    /// Creates a B-tree from entries sorted by key, e.g. to index the rows of
+    /// an existing table.
+    ///
+    /// The tree is built bottom-up: leaves are filled one after the other,
+    /// then each level of inner pages from the first keys of the level below.
+    /// Pages are full, unlike after inserting the keys one by one, but for the
+    /// last one of each level which takes keys from the previous one if it's
+    /// underfull.
+    ///
+    /// Returns a `BTreeError::DuplicateKey` or `BTreeError::KeyNotSorted` if
+    /// the keys are not strictly increasing.
+    pub fn bulk_load(
+        page_cache: StoragePageCache<S>,
+        entries: impl IntoIterator<Item = (Key, RecordId)>,
+    ) -> Result<Self, BTreeError> {
+        let entries = entries
+            .into_iter()
+            .map(|(key, record_id)| (key, record_id, Vec::new()));
+        Self::bulk_load_with_payloads(page_cache, entries)
+    }
+
+    /// Same as `bulk_load`, the entries having a payload, see
+    /// `BTree::insert_with_payload`.
+    pub fn bulk_load_with_payloads(
+        page_cache: StoragePageCache<S>,
+        entries: impl IntoIterator<Item = (Key, RecordId, Vec<u8>)>,
+    ) -> Result<Self, BTreeError> {
+        let mut leaf_page_ref = page_cache.new_page()?;
+        leaf_page_ref.btree_leaf_page_mut().init();
+        // The pages of a level, along with their first key: the separator on
+        // their left in the level above, unused for the first page.
+        let mut level = vec![(Key::from(Vec::new()), leaf_page_ref.metadata().page_id())];
+        let mut last_key: Option<Key> = None;
+        let mut num_keys = 0;
+        for (key, record_id, payload) in entries {
+            match &last_key {
+                Some(last_key) if key == *last_key => return Err(BTreeError::DuplicateKey(key)),
+                Some(last_key) if key < *last_key => return Err(BTreeError::KeyNotSorted(key)),
+                _ => {}
+            }
+            let entry_size = key.as_bytes().len() + payload.len();
+            if entry_size > BTREE_MAX_KEY_SIZE {
+                return Err(BTreePageError::KeyTooLarge(entry_size).into());
+            }
+
+            if !leaf_page_ref
+                .btree_leaf_page_mut()
+                .make_room(key.as_bytes(), payload.len())
+            {
+                let mut next_page_ref = page_cache.new_page()?;
+                next_page_ref.btree_leaf_page_mut().init();
+                let next_page_id = next_page_ref.metadata().page_id();
+                leaf_page_ref
+                    .btree_leaf_page_mut()
+                    .set_next_page_id(next_page_id);
+                page_cache.set_page_dirty(leaf_page_ref.metadata());
+                leaf_page_ref = next_page_ref;
+                let last_key = last_key.as_ref().expect("the full leaf has keys");
+                let separator = Key::shortest_separator(last_key.as_bytes(), key.as_bytes());
+                level.push((separator, next_page_id));
+            }
+            leaf_page_ref
+                .btree_leaf_page_mut()
+                .push(key.as_bytes(), record_id, &payload);
+            last_key = Some(key);
+            num_keys += 1;
+        }
+        page_cache.set_page_dirty(leaf_page_ref.metadata());
+        drop(leaf_page_ref);
+
+        Self::bulk_load_balance(&page_cache, &mut level)?;
+        while level.len() > 1 {
+            level = Self::bulk_load_level(&page_cache, &level)?;
+            Self::bulk_load_balance(&page_cache, &mut level)?;
+        }
+
+        let free_list_page_id = Self::new_free_list(&page_cache)?;
+        let mut superblock_ref = page_cache.get_page_mut(PAGE_RESERVED)?;
+        superblock_ref
+            .btree_superblock_mut()
+            .init(level[0].1, num_keys, free_list_page_id);
+        page_cache.set_page_dirty(superblock_ref.metadata());
+        drop(superblock_ref);
+
+        Ok(Self {
+            page_cache,
+            free_list_page_id,
+        })
+    }
+
+    /// Builds the inner pages pointing to the pages of `children`, returns
+    /// them along with their first key.
+    fn bulk_load_level(
+        page_cache: &StoragePageCache<S>,
+        children: &[(Key, PageId)],
+    ) -> Result<Vec<(Key, PageId)>, BTreeError> {
+        let mut level = Vec::new();
+        let mut inner_page_ref: Option<PageRefMut<'_>> = None;
+        for (key, page_id) in children {
+            if let Some(page_ref) = &mut inner_page_ref {
+                let inner_page = page_ref.btree_inner_page_mut();
+                if inner_page.make_room(key.as_bytes(), 0) {
+                    inner_page.push(key.as_bytes(), *page_id, &[]);
+                    continue;
+                }
+                page_cache.set_page_dirty(page_ref.metadata());
+            }
+            // The key goes up a level, on the left of the new page.
+            let mut page_ref = page_cache.new_page()?;
+            page_ref.btree_inner_page_mut().init_pointer(*page_id);
+            level.push((key.clone(), page_ref.metadata().page_id()));
+            inner_page_ref = Some(page_ref);
+        }
+        if let Some(page_ref) = inner_page_ref {
+            page_cache.set_page_dirty(page_ref.metadata());
+        }
+
+        Ok(level)
+    }
+
+    /// Moves keys from the second to last page of a level to the last one if
+    /// it's underfull.
+    fn bulk_load_balance(
+        page_cache: &StoragePageCache<S>,
+        level: &mut [(Key, PageId)],
+    ) -> Result<(), BTreeError> {
+        let [.., (_, lhs_page_id), (separator, rhs_page_id)] = level else {
+            return Ok(());
+        };
+        let mut lhs_page_ref = page_cache.get_page_mut(*lhs_page_id)?;
+        let mut rhs_page_ref = page_cache.get_page_mut(*rhs_page_id)?;
+        match btree_get_page_type(rhs_page_ref.page()) {
+            BTreePageType::Leaf => {
+                let rhs_page = rhs_page_ref.btree_leaf_page_mut();
+                if !rhs_page.is_underfull() {
+                    return Ok(());
+                }
+                *separator = lhs_page_ref.btree_leaf_page_mut().redistribute(rhs_page);
+            }
+            BTreePageType::Inner => {
+                let rhs_page = rhs_page_ref.btree_inner_page_mut();
+                if !rhs_page.is_underfull() {
+                    return Ok(());
+                }
+                *separator = lhs_page_ref
+                    .btree_inner_page_mut()
+                    .redistribute(separator.as_bytes(), rhs_page);
+            }
+        }
+        page_cache.set_page_dirty(lhs_page_ref.metadata());
+        page_cache.set_page_dirty(rhs_page_ref.metadata());
+
+        Ok(())
+    }
+
+    /// Descends to the leaf that should contain the given key without latching
+    /// pages. `page` is left with a copy of the leaf.
+    ///
+    /// Every page is validated once its child is copied: a parent left
+    /// unchanged still points to it for the key, since splits and merges write
+    /// to the parent.
+    fn find_leaf_page_optimistic(&self, key: &[u8], page: &mut Page) -> Descent<'_> {
+        let Some(mut parent_version) = self.page_cache.read_page_optimistic(PAGE_RESERVED, page)
+        else {
+            return Descent::Missing;
+        };
+        let mut page_id = <&BTreeSuperBlock>::from(&*page).root_page_id;
+
+        loop {
+            let Some(version) = self.page_cache.read_page_optimistic(page_id, page) else {
+                return Descent::Missing;
+            };
+            if !parent_version.is_valid() {
+                return Descent::Restart;
+            }
+            match btree_try_get_page_type(page) {
+                Some(BTreePageType::Inner) => {
+                    page_id = <&BTreeInnerPage>::from(&*page).get(key);
+                    parent_version = version;
+                }
+                Some(BTreePageType::Leaf) => return Descent::Leaf(page_id, parent_version),
+                None => return Descent::Restart,
+            }
+        }
     }
 
     /// Finds the leaf page that should contain the given key.
     ///
     /// Returns a `Result` containing a read-only reference to the leaf page, or a `BTreeError` on failure.
-    fn find_leaf_page(&self, key: Key) -> Result<PageRef<'_>, BTreeError> {
+    fn find_leaf_page(&self, key: &[u8]) -> Result<PageRef<'_>, BTreeError> {
+        let mut page = Page::new();
+        for _ in 0..OPTIMISTIC_RESTARTS {
+            match self.find_leaf_page_optimistic(key, &mut page) {
+                Descent::Leaf(page_id, parent_version) => {
+                    let Some(page_ref) = self.page_cache.get_cached_page(page_id) else {
+                        break;
+                    };
+                    if parent_version.is_valid() {
+                        return Ok(page_ref);
+                    }
+                }
+                Descent::Restart => {}
+                Descent::Missing => break,
+            }
+        }
+
+        self.find_leaf_page_latched(key)
+    }
+
+    /// Like `find_leaf_page`, but latches every page on the way down.
+    fn find_leaf_page_latched(&self, key: &[u8]) -> Result<PageRef<'_>, BTreeError> {
         let mut page_ref = {
             let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
             let superblock = superblock_ref.btree_superblock();
@@ -122,7 +409,28 @@ impl<S: StorageBackend + 'static> BTree<S> {
     /// Finds the leaf page that should contain the given key.
     ///
     /// Returns a `Result` containing a mutable reference to the leaf page, or a `BTreeError` on failure.
-    fn find_leaf_page_mut(&self, key: Key) -> Result<PageRefMut<'_>, BTreeError> {
+    fn find_leaf_page_mut(&self, key: &[u8]) -> Result<PageRefMut<'_>, BTreeError> {
+        let mut page = Page::new();
+        for _ in 0..OPTIMISTIC_RESTARTS {
+            match self.find_leaf_page_optimistic(key, &mut page) {
+                Descent::Leaf(page_id, parent_version) => {
+                    let Some(page_ref) = self.page_cache.get_cached_page_mut(page_id) else {
+                        break;
+                    };
+                    if parent_version.is_valid() {
+                        return Ok(page_ref);
+                    }
+                }
+                Descent::Restart => {}
+                Descent::Missing => break,
+            }
+        }
+
+        self.find_leaf_page_mut_latched(key)
+    }
+
+    /// Like `find_leaf_page_mut`, but latches every page on the way down.
+    fn find_leaf_page_mut_latched(&self, key: &[u8]) -> Result<PageRefMut<'_>, BTreeError> {
         let mut parent_page_ref = {
             let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
             let superblock = superblock_ref.btree_superblock();
@@ -164,47 +472,93 @@ impl<S: StorageBackend + 'static> BTree<S> {
         }
     }
 
+    /// Finds the rightmost leaf page that may contain keys within `end`, along
+    /// with the lower bound of its keys, `None` for the first leaf.
+    fn find_leaf_page_below(
+        &self,
+        end: Bound<&[u8]>,
+    ) -> Result<(PageRef<'_>, Option<Key>), BTreeError> {
+        let mut page_ref = {
+            let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+            let superblock = superblock_ref.btree_superblock();
+            self.page_cache
+                .get_page(superblock.root_page_id)
+                .map_err(BTreeError::PageCache)?
+        };
+
+        let mut low = None;
+        loop {
+            match btree_get_page_type(page_ref.page()) {
+                BTreePageType::Inner => {
+                    let inner_page = page_ref.btree_inner_page();
+                    // The child on the left of the first separator above end.
+                    let pos = count_below(inner_page, end);
+                    if pos > 0 {
+                        low = Some(inner_page.key_at(pos - 1));
+                    }
+                    let page_id = inner_page.pointer(pos);
+                    page_ref = self
+                        .page_cache
+                        .get_page(page_id)
+                        .map_err(BTreeError::PageCache)?;
+                }
+                BTreePageType::Leaf => {
+                    return Ok((page_ref, low));
+                }
+            }
+        }
+    }
+
     /// Searches for a record by its key.
     ///
     /// Returns an `Option` containing the `RecordId` if the key is found, or `None` otherwise.
-    pub fn search(&self, key: Key) -> Option<RecordId> {
+    pub fn search(&self, key: &Key) -> Option<RecordId> {
+        // The bloom filter is only a shortcut: if it can't be read, the tree is
+        // searched.
+        if let Ok(false) = self.may_contain(key) {
+            return None;
+        }
+
+        // The copy of the leaf is consistent, and is the leaf of the key as long as its parent
+        // is unchanged: no page is latched.
+        let mut page = Page::new();
+        for _ in 0..OPTIMISTIC_RESTARTS {
+            match self.find_leaf_page_optimistic(key.as_bytes(), &mut page) {
+                Descent::Leaf(_, parent_version) => {
+                    let record_id = <&BTreeLeafPage>::from(&page).get(key.as_bytes());
+                    if parent_version.is_valid() {
+                        return record_id;
+                    }
+                }
+                Descent::Restart => {}
+                Descent::Missing => break,
+            }
+        }
+
         // For convinience we return an Option.
         // We should log errors instead of unwraping.
-        let page_ref = self.find_leaf_page(key).unwrap();
+        let page_ref = self.find_leaf_page_latched(key.as_bytes()).unwrap();
         let leaf_page = page_ref.btree_leaf_page();
-        leaf_page.get(key)
+        leaf_page.get(key.as_bytes())
     }
 
-    fn insert_inner_r(
+    /// Inserts the separator of a child split in two into its parent, which
+    /// may in turn be split.
+    fn insert_inner(
         &self,
         inner_page_ref: &mut PageRefMut<'_>,
-        key: Key,
-        value: RecordId,
+        split_key: Key,
+        rhs_page_id: PageId,
     ) -> Result<Option<(Key, PageId)>, BTreeError> {
+        // The key is inserted into the page, split or not.
+        self.page_cache.set_page_dirty(inner_page_ref.metadata());
         let inner_page = inner_page_ref.btree_inner_page_mut();
-
-        let child_page_id = inner_page.get(key);
-        let mut child_page_ref = self
-            .page_cache
-            .get_page_mut(child_page_id)
-            .map_err(BTreeError::PageCache)?;
-
-        let result = match btree_get_page_type(child_page_ref.page()) {
-            BTreePageType::Inner => self.insert_inner_r(&mut child_page_ref, key, value)?,
-            BTreePageType::Leaf => self.insert_leaf(&mut child_page_ref, key, value)?,
-        };
-
-        if let Some((split_key, rhs_page_id)) = result
-            && let Some(mut split) = inner_page.insert(split_key, rhs_page_id)
-        {
-            let mut rhs_inner_page_ref =
-                self.page_cache.new_page().map_err(BTreeError::PageCache)?;
+        if let Some(mut split) = inner_page.insert(split_key.as_bytes(), rhs_page_id) {
+            let mut rhs_inner_page_ref = self.new_page()?;
             let rhs_inner_page_id = rhs_inner_page_ref.metadata().page_id();
             let rhs_inner_page = rhs_inner_page_ref.btree_inner_page_mut();
-            rhs_inner_page.init_header();
-            let split_key = split.split(rhs_inner_page, split_key, rhs_page_id);
+            let split_key = split.split(rhs_inner_page, split_key.as_bytes(), rhs_page_id);
 
-            self.page_cache.set_page_dirty(inner_page_ref.metadata());
             self.page_cache
                 .set_page_dirty(rhs_inner_page_ref.metadata());
 
@@ -217,19 +571,26 @@ impl<S: StorageBackend + 'static> BTree<S> {
     fn insert_leaf(
         &self,
         lhs_page_ref: &mut PageRefMut<'_>,
-        key: Key,
+        key: &[u8],
         value: RecordId,
+        payload: &[u8],
     ) -> Result<Option<(Key, PageId)>, BTreeError> {
+        self.page_cache.set_page_dirty(lhs_page_ref.metadata());
         let lhs = lhs_page_ref.btree_leaf_page_mut();
-        if let Some(mut split) = lhs.insert(key, value) {
-            let mut rhs_page_ref = self.page_cache.new_page().map_err(BTreeError::PageCache)?;
+        let next_page_id = lhs.next_page_id();
+        let split = lhs
+            .insert(key, value, payload)
+            .map_err(|e| insert_error(key, e))?;
+        if let Some(mut split) = split {
+            let mut rhs_page_ref = self.new_page()?;
             let rhs = rhs_page_ref.btree_leaf_page_mut();
             rhs.init();
-            let split_key = split.split(rhs, key, value);
+            let split_key = split.split(rhs, key, value, payload);
+            // Link rhs between lhs and its former successor.
+            rhs.set_next_page_id(next_page_id);
             let rhs_page_id = rhs_page_ref.metadata().page_id();
             lhs.set_next_page_id(rhs_page_id);
 
-            self.page_cache.set_page_dirty(lhs_page_ref.metadata());
             self.page_cache.set_page_dirty(rhs_page_ref.metadata());
 
             Ok(Some((split_key, rhs_page_id)))
@@ -241,189 +602,921 @@ impl<S: StorageBackend + 'static> BTree<S> {
     /// Inserts a new key-value pair into the B-tree.
     ///
     /// Returns an empty `Result` if successful, or a `BTreeError` on failure.
-    pub fn insert(&self, key: Key, record_id: RecordId) -> Result<(), BTreeError> {
+    pub fn insert(&self, key: &Key, record_id: RecordId) -> Result<(), BTreeError> {
+        self.insert_with_payload(key, record_id, &[])
+    }
+
+    /// Inserts a key along with a payload stored in its leaf entry, e.g. the
+    /// values of the columns included in a covering index. The key and the
+    /// payload take at most `BTREE_MAX_KEY_SIZE` bytes, see
+    /// `BTreeRangeIterator::next_with_payload` to read it back.
+    pub fn insert_with_payload(
+        &self,
+        key: &Key,
+        record_id: RecordId,
+        payload: &[u8],
+    ) -> Result<(), BTreeError> {
+        self.add_to_bloom_filter(key)?;
         // Fast path: get an exclusive lock on the leaf, every parent has its lock released.
         // This optimization is useful for mixed workload. For write-heavy applications
         // the performance decreases slightly : if a split occurs in the leaf we need to insert
         // the key via the slow path.
-        let mut leaf_page_ref = self.find_leaf_page_mut(key)?;
+        let mut leaf_page_ref = self.find_leaf_page_mut(key.as_bytes())?;
         let leaf_page = leaf_page_ref.btree_leaf_page_mut();
-        if leaf_page.insert(key, record_id).is_some() {
+        let split = leaf_page
+            .insert(key.as_bytes(), record_id, payload)
+            .map_err(|e| insert_error(key.as_bytes(), e))?;
+        if split.is_some() {
             drop(leaf_page_ref);
-            self.insert_slow_path(key, record_id)
+            self.insert_latched(key, record_id, payload)
         } else {
             self.page_cache.set_page_dirty(leaf_page_ref.metadata());
-            Ok(())
+            drop(leaf_page_ref);
+            self.count_key(true)
         }
     }
 
-    pub fn insert_slow_path(&self, key: Key, record_id: RecordId) -> Result<(), BTreeError> {
-        // Slow path: we descend in the tree, getting an exclusive lock at every step.
-        let mut superblock_ref = self.page_cache.get_page_mut(PAGE_RESERVED)?;
-        let superblock = superblock_ref.btree_superblock_mut();
-        let root_page_id = superblock.root_page_id;
+    pub fn insert_slow_path(&self, key: &Key, record_id: RecordId) -> Result<(), BTreeError> {
+        self.add_to_bloom_filter(key)?;
+        self.insert_latched(key, record_id, &[])
+    }
 
-        let mut root_page_ref = self
-            .page_cache
-            .get_page_mut(root_page_id)
-            .map_err(BTreeError::PageCache)?;
+    fn insert_latched(
+        &self,
+        key: &Key,
+        record_id: RecordId,
+        payload: &[u8],
+    ) -> Result<(), BTreeError> {
+        let key = key.as_bytes();
+        // Slow path: we descend in the tree, getting an exclusive lock at every step. The
+        // locks of the ancestors of a page with room for the key are released on the way
+        // (latch crabbing): a split below it stops there. The superblock is only kept
+        // locked if the root may split.
+        let mut superblock_ref = Some(self.page_cache.get_page_mut(PAGE_RESERVED)?);
+        let root_page_id = superblock_ref
+            .as_ref()
+            .map(|superblock_ref| superblock_ref.btree_superblock().root_page_id)
+            .unwrap();
+        let mut path = vec![
+            self.page_cache
+                .get_page_mut(root_page_id)
+                .map_err(BTreeError::PageCache)?,
+        ];
 
-        let result = match btree_get_page_type(root_page_ref.page()) {
-            BTreePageType::Inner => self.insert_inner_r(&mut root_page_ref, key, record_id)?,
-            BTreePageType::Leaf => self.insert_leaf(&mut root_page_ref, key, record_id)?,
-        };
+        loop {
+            let page_ref = path.last().unwrap();
+            // Inner pages get a separator of any size from a split child.
+            let (safe, child_page_id) = match btree_get_page_type(page_ref.page()) {
+                BTreePageType::Inner => {
+                    let inner_page = page_ref.btree_inner_page();
+                    (inner_page.fits_any(), Some(inner_page.get(key)))
+                }
+                BTreePageType::Leaf => (page_ref.btree_leaf_page().fits(key, payload.len()), None),
+            };
+            if safe {
+                superblock_ref = None;
+                path.drain(..path.len() - 1);
+            }
+            let Some(child_page_id) = child_page_id else {
+                break;
+            };
+            let child_page_ref = self
+                .page_cache
+                .get_page_mut(child_page_id)
+                .map_err(BTreeError::PageCache)?;
+            path.push(child_page_ref);
+        }
 
-        if let Some((split_key, rhs_page_id)) = result {
-            let mut new_root_page_ref =
-                self.page_cache.new_page().map_err(BTreeError::PageCache)?;
-            let new_root_page_id = new_root_page_ref.metadata().page_id();
-            let new_root_page = new_root_page_ref.btree_inner_page_mut();
-            new_root_page.init(split_key, root_page_id, rhs_page_id);
-            self.page_cache.set_page_dirty(new_root_page_ref.metadata());
-            superblock.root_page_id = new_root_page_id;
+        let mut leaf_page_ref = path.pop().unwrap();
+        let mut result = self.insert_leaf(&mut leaf_page_ref, key, record_id, payload)?;
+        while let Some((split_key, rhs_page_id)) = result {
+            let Some(mut inner_page_ref) = path.pop() else {
+                // The root was split, the superblock is still locked.
+                let mut superblock_ref = superblock_ref.take().unwrap();
+                let mut new_root_page_ref = self.new_page()?;
+                let new_root_page_id = new_root_page_ref.metadata().page_id();
+                let new_root_page = new_root_page_ref.btree_inner_page_mut();
+                new_root_page.init(split_key.as_bytes(), root_page_id, rhs_page_id);
+                self.page_cache.set_page_dirty(new_root_page_ref.metadata());
+                superblock_ref.btree_superblock_mut().root_page_id = new_root_page_id;
+                self.page_cache.set_page_dirty(superblock_ref.metadata());
+                break;
+            };
+            result = self.insert_inner(&mut inner_page_ref, split_key, rhs_page_id)?;
         }
+        drop(leaf_page_ref);
+        drop(path);
+        drop(superblock_ref);
 
-        Ok(())
+        self.count_key(true)
     }
 
     /// Deletes a key-value pair from the B-tree.
     ///
     /// Returns an empty `Result` if successful, or a `BTreeError` if the key is not found.
-    pub fn delete(&self, key: Key) -> Result<(), BTreeError> {
+    pub fn delete(&self, key: &Key) -> Result<(), BTreeError> {
+        let key = key.as_bytes();
+        // Fast path, as for inserts: if the leaf keeps enough keys, no other page changes.
         let mut leaf_page_ref = self.find_leaf_page_mut(key)?;
-        let leaf_page = leaf_page_ref.btree_leaf_page_mut();
-        leaf_page
-            .delete(key)
-            .map(|_| {
-                self.page_cache.set_page_dirty(leaf_page_ref.metadata());
-            })
-            .map_err(BTreeError::Page)
+        let leaf_page = leaf_page_ref.btree_leaf_page();
+        let pos = leaf_page
+            .search(key)
+            .map_err(|_| BTreePageError::KeyNotFound)?;
+        if !leaf_page.is_underfull_without(pos) {
+            self.delete_leaf(&mut leaf_page_ref, key)?;
+            drop(leaf_page_ref);
+        } else {
+            drop(leaf_page_ref);
+            self.delete_slow_path(key)?;
+        }
+
+        self.count_key(false)
     }
 
-    /// Creates an iterator over a range of keys.
-    ///
-    /// Returns a `Result` containing the `BTreeRangeIterator`, or a `BTreeError` on failure.
-    pub fn iter(&self, start: Key) -> Result<BTreeRangeIterator<'_, S>, BTreeError> {
-        let page_ref = self.find_leaf_page(start)?;
-        let leaf_page = page_ref.btree_leaf_page();
-        // FIXME: what if the key doesn't exist ?
-        let pos = match leaf_page.keys().binary_search(&start) {
-            Ok(pos) => pos,
-            Err(pos) => pos,
-        };
+    /// Counts a key inserted or deleted in the superblock, once the pages of
+    /// the tree are released: the superblock is latched before them.
+    fn count_key(&self, inserted: bool) -> Result<(), BTreeError> {
+        let mut superblock_ref = self.page_cache.get_page_mut(PAGE_RESERVED)?;
+        let superblock = superblock_ref.btree_superblock_mut();
+        match inserted {
+            true => superblock.increment_num_keys(),
+            false => superblock.decrement_num_keys(),
+        }
+        self.page_cache.set_page_dirty(superblock_ref.metadata());
+        Ok(())
+    }
 
-        Ok(BTreeRangeIterator {
-            pos,
-            btree: self,
-            page_ref,
-        })
+    /// Gives the tree its own sync mode, see `StoragePageCache::set_sync_mode`.
+    pub fn set_sync_mode(&self, mode: Option<SyncMode>) {
+        self.page_cache.set_sync_mode(mode);
     }
-}
 
-pub struct BTreeRangeIterator<'btree, S: StorageBackend + 'static> {
-    pos: usize,
-    btree: &'btree BTree<S>,
-    page_ref: PageRef<'btree>,
-}
+    /// Commits the writes to the tree, see `StoragePageCache::commit`.
+    pub fn commit(&self) {
+        self.page_cache.commit();
+    }
 
-impl<'btree, S: StorageBackend + 'static> Iterator for BTreeRangeIterator<'btree, S> {
-    type Item = (Key, RecordId);
+    /// Returns the number of keys, kept in the superblock: it may lag behind
+    /// concurrent inserts and deletes.
+    pub fn len(&self) -> Result<usize, BTreeError> {
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        Ok(superblock_ref.btree_superblock().num_keys() as usize)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let leaf_page = self.page_ref.btree_leaf_page();
+    pub fn is_empty(&self) -> Result<bool, BTreeError> {
+        Ok(self.len()? == 0)
+    }
 
-        if self.pos >= leaf_page.len() {
-            if leaf_page.next_page_id() == PAGE_INVALID {
-                return None;
+    /// Adds a bloom filter of the keys, replacing the previous one: lookups
+    /// of most keys missing from the tree then read no leaf, see
+    /// `may_contain`. The filter takes `BLOOM_BITS_PER_KEY` bits per expected
+    /// key, up to `BTREE_MAX_BLOOM_PAGES` pages. Keys inserted beyond make
+    /// false positives more likely, deleted keys are kept in the filter.
+    ///
+    /// The keys of the tree are read to fill the filter: the tree must not be
+    /// modified meanwhile.
+    pub fn create_bloom_filter(&self, expected_keys: u64) -> Result<(), BTreeError> {
+        let num_pages = (expected_keys * BLOOM_BITS_PER_KEY)
+            .div_ceil(BLOOM_PAGE_BITS as u64)
+            .clamp(1, BTREE_MAX_BLOOM_PAGES as u64) as usize;
+        let mut page_refs = (0..num_pages)
+            .map(|_| self.new_page())
+            .collect::<Result<Vec<_>, _>>()?;
+        page_refs
+            .iter_mut()
+            .for_each(|page_ref| page_ref.bloom_filter_page_mut().clear());
+        for (key, _) in self.range(..)? {
+            for bit in bloom_bits(key.as_bytes(), num_pages * BLOOM_PAGE_BITS) {
+                let page_ref = &page_refs[bit / BLOOM_PAGE_BITS];
+                page_ref.bloom_filter_page().set(bit % BLOOM_PAGE_BITS);
             }
+        }
+        let page_ids = page_refs
+            .iter()
+            .map(|page_ref| page_ref.metadata().page_id())
+            .collect::<Vec<_>>();
+        for page_ref in page_refs {
+            self.page_cache.set_page_dirty(page_ref.metadata());
+        }
 
-            self.page_ref = self
-                .btree
+        let mut superblock_ref = self.page_cache.get_page_mut(PAGE_RESERVED)?;
+        let superblock = superblock_ref.btree_superblock_mut();
+        let previous = superblock.bloom_filter().to_vec();
+        superblock.set_bloom_filter(&page_ids);
+        self.page_cache.set_page_dirty(superblock_ref.metadata());
+        drop(superblock_ref);
+        for page_id in previous {
+            self.free_page(page_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `false` if the key is definitely not in the tree, according to
+    /// its bloom filter. Returns `true` if the key may be in the tree, or if
+    /// the tree has no bloom filter.
+    pub fn may_contain(&self, key: &Key) -> Result<bool, BTreeError> {
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        let bloom_filter = superblock_ref.btree_superblock().bloom_filter();
+        if bloom_filter.is_empty() {
+            return Ok(true);
+        }
+        for bit in bloom_bits(key.as_bytes(), bloom_filter.len() * BLOOM_PAGE_BITS) {
+            let page_ref = self
                 .page_cache
-                .get_page(leaf_page.next_page_id())
-                .map_err(|_| todo!("log errors"))
-                .ok()?;
+                .get_page(bloom_filter[bit / BLOOM_PAGE_BITS])?;
+            if !page_ref.bloom_filter_page().get(bit % BLOOM_PAGE_BITS) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 
-            self.pos = 0;
+    /// Sets the bits of a key in the bloom filter, if the tree has one, before
+    /// the key is inserted: lookups finding the key find its bits.
+    fn add_to_bloom_filter(&self, key: &Key) -> Result<(), BTreeError> {
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        let bloom_filter = superblock_ref.btree_superblock().bloom_filter();
+        if bloom_filter.is_empty() {
+            return Ok(());
         }
+        for bit in bloom_bits(key.as_bytes(), bloom_filter.len() * BLOOM_PAGE_BITS) {
+            let page_ref = self
+                .page_cache
+                .get_page(bloom_filter[bit / BLOOM_PAGE_BITS])?;
+            page_ref.bloom_filter_page().set(bit % BLOOM_PAGE_BITS);
+            self.page_cache.set_page_dirty(page_ref.metadata());
+        }
+        Ok(())
+    }
 
-        let leaf_page = self.page_ref.btree_leaf_page();
-        let (key, record_id) = (leaf_page.key_at(self.pos), leaf_page.value_at(self.pos));
-        self.pos += 1;
+    /// Allocates a page, reusing the page freed last if any: its content is
+    /// left to be initialized.
+    fn new_page(&self) -> Result<PageRefMut<'_>, BTreeError> {
+        let mut free_list_ref = self.page_cache.get_page_mut(self.free_list_page_id)?;
+        let Some(page_id) = free_list_ref.free_list_page_mut().pop() else {
+            drop(free_list_ref);
+            return Ok(self.page_cache.new_page()?);
+        };
+        self.page_cache.set_page_dirty(free_list_ref.metadata());
+        // Nothing else latches the page freed, the free list is released first.
+        drop(free_list_ref);
+        Ok(self.page_cache.get_page_mut(page_id)?)
+    }
 
-        Some((key, record_id))
+    /// Frees a page no longer in the tree: its disk space is given back to
+    /// the storage, see `StoragePageCache::free_pages`, and it's recorded in
+    /// the free list to be reused by `new_page`. Pages freed once the free
+    /// list is full aren't reused.
+    fn free_page(&self, page_id: PageId) -> Result<(), BTreeError> {
+        self.page_cache.free_pages(page_id, page_id)?;
+        let mut free_list_ref = self.page_cache.get_page_mut(self.free_list_page_id)?;
+        if free_list_ref.free_list_page_mut().push(page_id) {
+            self.page_cache.set_page_dirty(free_list_ref.metadata());
+        }
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn delete_slow_path(&self, key: &[u8]) -> Result<(), BTreeError> {
+        // Slow path: we descend in the tree, getting an exclusive lock at every step.
+        let mut superblock_ref = self.page_cache.get_page_mut(PAGE_RESERVED)?;
+        let root_page_id = superblock_ref.btree_superblock().root_page_id;
+        let mut root_page_ref = self
+            .page_cache
+            .get_page_mut(root_page_id)
+            .map_err(BTreeError::PageCache)?;
 
-    use crate::cache::PageCache;
-    use crate::pages::HeapPageSlotId;
-    use crate::storage::FileStorage;
+        if btree_get_page_type(root_page_ref.page()).is_leaf() {
+            return self.delete_leaf(&mut root_page_ref, key);
+        }
+        self.delete_inner_r(&mut root_page_ref, key)?;
+
+        // The root is left with a single child after a merge: it becomes the root.
+        let root_page = root_page_ref.btree_inner_page();
+        if root_page.len() == 0 {
+            let new_root_page_id = root_page.pointer(0);
+            drop(root_page_ref);
+            superblock_ref.btree_superblock_mut().root_page_id = new_root_page_id;
+            self.page_cache.set_page_dirty(superblock_ref.metadata());
+            self.free_page(root_page_id)?;
+        }
 
-    use std::{collections::VecDeque, sync::Arc};
+        Ok(())
+    }
 
-    use tempfile::NamedTempFile;
+    fn delete_inner_r(
+        &self,
+        inner_page_ref: &mut PageRefMut<'_>,
+        key: &[u8],
+    ) -> Result<(), BTreeError> {
+        let pos = inner_page_ref.btree_inner_page().position(key);
+        let child_page_id = inner_page_ref.btree_inner_page().pointer(pos);
+        let mut child_page_ref = self
+            .page_cache
+            .get_page_mut(child_page_id)
+            .map_err(BTreeError::PageCache)?;
 
-    const NR_KEYS: usize = 1000;
+        let is_underfull = match btree_get_page_type(child_page_ref.page()) {
+            BTreePageType::Inner => {
+                self.delete_inner_r(&mut child_page_ref, key)?;
+                child_page_ref.btree_inner_page().is_underfull()
+            }
+            BTreePageType::Leaf => {
+                self.delete_leaf(&mut child_page_ref, key)?;
+                child_page_ref.btree_leaf_page().is_underfull()
+            }
+        };
 
-    fn create_btree() -> BTree<FileStorage> {
-        let storage_path = NamedTempFile::new().unwrap();
-        let storage = FileStorage::create(storage_path).unwrap();
-        let page_cache = PageCache::try_new().unwrap();
-        let file_cache = page_cache.cache_storage(storage);
-        BTree::try_new(file_cache).unwrap()
+        if is_underfull {
+            self.rebalance(inner_page_ref, pos, child_page_ref)?;
+        }
+
+        Ok(())
     }
 
-    fn make_record() -> RecordId {
-        RecordId::new(PageId::new(0), HeapPageSlotId::new(0))
+    fn delete_leaf(
+        &self,
+        leaf_page_ref: &mut PageRefMut<'_>,
+        key: &[u8],
+    ) -> Result<(), BTreeError> {
+        leaf_page_ref.btree_leaf_page_mut().delete(key)?;
+        self.page_cache.set_page_dirty(leaf_page_ref.metadata());
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    fn print_btree(btree: &BTree<FileStorage>) {
-        let root_page_id = {
-            let superblock_ref = btree.page_cache.get_page(PAGE_RESERVED).unwrap();
-            let superblock = superblock_ref.btree_superblock();
-            superblock.root_page_id
+    /// Refills the child at `pos` of an inner page, left underfull, from its
+    /// right sibling, or its left one for the last child: keys are moved from
+    /// one to the other, or the right page is merged into the left one and
+    /// freed.
+    ///
+    /// The new separator of pages redistributed may be longer than the old
+    /// one: if the parent may not have room for it, they are left as is.
+    fn rebalance(
+        &self,
+        parent_page_ref: &mut PageRefMut<'_>,
+        pos: usize,
+        child_page_ref: PageRefMut<'_>,
+    ) -> Result<(), BTreeError> {
+        let parent_page = parent_page_ref.btree_inner_page();
+        // Siblings are locked from left to right, the order iterators follow
+        // the leaves in, the parent keeps other writers away.
+        let (pos, mut lhs_page_ref, mut rhs_page_ref) = if pos < parent_page.len() {
+            let rhs_page_ref = self.page_cache.get_page_mut(parent_page.pointer(pos + 1))?;
+            (pos, child_page_ref, rhs_page_ref)
+        } else {
+            drop(child_page_ref);
+            let lhs_page_ref = self.page_cache.get_page_mut(parent_page.pointer(pos - 1))?;
+            let rhs_page_ref = self.page_cache.get_page_mut(parent_page.pointer(pos))?;
+            (pos - 1, lhs_page_ref, rhs_page_ref)
+        };
+        let can_redistribute = parent_page.fits_any();
+        // The key between the two pages, `None` once merged.
+        let separator = parent_page.key_at(pos);
+        let separator = match btree_get_page_type(lhs_page_ref.page()) {
+            BTreePageType::Inner => {
+                let lhs = lhs_page_ref.btree_inner_page_mut();
+                let rhs = rhs_page_ref.btree_inner_page_mut();
+                if lhs.can_merge(separator.as_bytes(), rhs) {
+                    lhs.merge(separator.as_bytes(), rhs);
+                    None
+                } else if can_redistribute {
+                    Some(lhs.redistribute(separator.as_bytes(), rhs))
+                } else {
+                    return Ok(());
+                }
+            }
+            BTreePageType::Leaf => {
+                let lhs = lhs_page_ref.btree_leaf_page_mut();
+                let rhs = rhs_page_ref.btree_leaf_page_mut();
+                if lhs.can_merge(rhs) {
+                    lhs.merge(rhs);
+                    None
+                } else if can_redistribute {
+                    Some(lhs.redistribute(rhs))
+                } else {
+                    return Ok(());
+                }
+            }
         };
-        let mut queue = VecDeque::from([vec![root_page_id]]);
 
-        while let Some(page_ids) = queue.pop_front()
-            && !page_ids.is_empty()
-        {
-            let mut new_page_ids = vec![];
-            for page_id in page_ids {
-                let page_ref = btree.page_cache.get_page(page_id).unwrap();
-                let page_id = page_ref.metadata().page_id();
+        self.page_cache.set_page_dirty(lhs_page_ref.metadata());
+        self.page_cache.set_page_dirty(parent_page_ref.metadata());
+        let parent_page = parent_page_ref.btree_inner_page_mut();
+        if let Some(separator) = separator {
+            parent_page.set_key(pos, separator.as_bytes());
+            self.page_cache.set_page_dirty(rhs_page_ref.metadata());
+        } else {
+            parent_page.remove(pos);
+            let rhs_page_id = rhs_page_ref.metadata().page_id();
+            drop(rhs_page_ref);
+            self.free_page(rhs_page_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Walks the whole tree level by level, counting its pages and keys.
+    ///
+    /// Pages are read one at a time: the statistics of a tree modified
+    /// meanwhile are approximate.
+    pub fn stats(&self) -> Result<BTreeStats, BTreeError> {
+        let root_page_id = {
+            let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+            superblock_ref.btree_superblock().root_page_id
+        };
+
+        let mut stats = BTreeStats::default();
+        let mut fill_factor = 0.0;
+        let mut level = vec![root_page_id];
+        while !level.is_empty() {
+            stats.pages_per_level.push(level.len() as u64);
+            let mut children = Vec::new();
+            for page_id in level {
+                let page_ref = self.page_cache.get_page(page_id)?;
                 match btree_get_page_type(page_ref.page()) {
                     BTreePageType::Inner => {
                         let inner_page = page_ref.btree_inner_page();
-                        print!(
-                            " Inner({:?}): keys={:?} pointers={:?} |",
-                            page_id,
-                            inner_page.keys(),
-                            inner_page.pointers()
-                        );
-                        new_page_ids.extend(inner_page.pointers());
+                        fill_factor += inner_page.fill_factor();
+                        children.extend(inner_page.pointers());
                     }
                     BTreePageType::Leaf => {
                         let leaf_page = page_ref.btree_leaf_page();
-                        print!(
-                            " Leaf({:?})=>({:?}): keys={:?} |",
-                            page_id,
-                            leaf_page.next_page_id(),
-                            leaf_page.keys()
-                        );
+                        fill_factor += leaf_page.fill_factor();
+                        stats.keys += leaf_page.len() as u64;
+                    }
+                }
+            }
+            level = children;
+        }
+        stats.fill_factor = fill_factor / stats.pages() as f64;
+
+        Ok(stats)
+    }
+
+    /// Estimates the number of keys within `range` without reading the leaves
+    /// in between, for the planner to cost index scans.
+    ///
+    /// The tree is descended to both bounds at once: the keys are counted
+    /// exactly while the bounds are in the same page. Below the page where
+    /// they part, the number of keys of a subtree is estimated from the
+    /// fan-out of the pages on the way down to each bound.
+    pub fn estimate_range(&self, range: impl RangeBounds<Key>) -> Result<u64, BTreeError> {
+        // The bounds as cuts between keys, see `count_below`.
+        let first = Bound::Excluded(&[][..]);
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Excluded(key.as_bytes()),
+            Bound::Excluded(key) => Bound::Included(key.as_bytes()),
+            Bound::Unbounded => first,
+        };
+        match range.end_bound() {
+            // The pages on the right edge are the least filled by increasing
+            // keys: the keys below the start are estimated instead.
+            Bound::Unbounded => {
+                let keys = self.len()? as u64;
+                Ok(keys.saturating_sub(self.estimate_between(first, start)?))
+            }
+            end => self.estimate_between(start, end.map(Key::as_bytes)),
+        }
+    }
+
+    /// Estimates the number of keys between two cuts, see `estimate_range`.
+    fn estimate_between(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<u64, BTreeError> {
+        let mut page_ref = {
+            let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+            let superblock = superblock_ref.btree_superblock();
+            self.page_cache.get_page(superblock.root_page_id)?
+        };
+        loop {
+            match btree_get_page_type(page_ref.page()) {
+                BTreePageType::Inner => {
+                    let inner_page = page_ref.btree_inner_page();
+                    let low = count_below(inner_page, start);
+                    let high = count_below(inner_page, end);
+                    if low >= high {
+                        // The start may be after the end, e.g. `10..5`.
+                        let page_id = inner_page.pointer(low.min(high));
+                        page_ref = self.page_cache.get_page(page_id)?;
+                        continue;
                     }
+
+                    let (low_page_id, high_page_id) =
+                        (inner_page.pointer(low), inner_page.pointer(high));
+                    drop(page_ref);
+                    let (below_start, low_keys) = self.estimate_below(low_page_id, start)?;
+                    let (below_end, high_keys) = self.estimate_below(high_page_id, end)?;
+                    // The subtrees in between are taken as large as those on both sides.
+                    let between = (high - low - 1) as f64 * (low_keys + high_keys) / 2.0;
+                    let keys = low_keys - below_start + between + below_end;
+
+                    return Ok(keys.round() as u64);
+                }
+                BTreePageType::Leaf => {
+                    let leaf_page = page_ref.btree_leaf_page();
+                    let keys =
+                        count_below(leaf_page, end).saturating_sub(count_below(leaf_page, start));
+
+                    return Ok(keys as u64);
                 }
             }
-            queue.push_back(new_page_ids);
-            println!();
         }
     }
 
+    /// Descends from `page_id` towards a cut between keys, and estimates the
+    /// number of keys of its subtree and of those below the cut, taking each
+    /// page as large as the one on the way down times its fan-out.
+    fn estimate_below(&self, page_id: PageId, cut: Bound<&[u8]>) -> Result<(f64, f64), BTreeError> {
+        // The position of the cut and the number of entries of each page.
+        let mut path = Vec::new();
+        let mut page_ref = self.page_cache.get_page(page_id)?;
+        loop {
+            match btree_get_page_type(page_ref.page()) {
+                BTreePageType::Inner => {
+                    let inner_page = page_ref.btree_inner_page();
+                    let pos = count_below(inner_page, cut);
+                    path.push((pos, inner_page.len() + 1));
+                    let page_id = inner_page.pointer(pos);
+                    page_ref = self.page_cache.get_page(page_id)?;
+                }
+                BTreePageType::Leaf => {
+                    let leaf_page = page_ref.btree_leaf_page();
+                    path.push((count_below(leaf_page, cut), leaf_page.len()));
+                    break;
+                }
+            }
+        }
+
+        let (mut below, mut keys) = (0.0, 1.0);
+        for (pos, len) in path.into_iter().rev() {
+            below += pos as f64 * keys;
+            keys *= len as f64;
+        }
+
+        Ok((below, keys))
+    }
+
+    /// Walks the whole tree and reports the pages breaking its invariants, see
+    /// `pages::check`: keys sorted within pages and bounded by the separators
+    /// of their parent, leaves at the same depth and chained in order, and
+    /// every page reachable from the root but the freed ones.
+    ///
+    /// Pages are read one at a time through the page cache: the tree must not
+    /// be modified meanwhile.
+    pub fn verify(&self) -> Result<CheckReport, BTreeError> {
+        let num_pages = self.page_cache.last_page_id().get() + 1;
+        let report = check(FileKind::BTree, num_pages, |page_id| {
+            let page_ref = self
+                .page_cache
+                .get_page(page_id)
+                .map_err(io::Error::other)?;
+            let mut page = Page::new();
+            page.data = page_ref.page().data;
+            Ok(page)
+        });
+        // The only I/O errors are the page cache ones.
+        report.map_err(
+            |e| match e.into_inner().map(|e| e.downcast::<PageCacheError>()) {
+                Some(Ok(e)) => BTreeError::PageCache(*e),
+                _ => unreachable!(),
+            },
+        )
+    }
+
+    /// Creates an iterator over the keys from `start` to the last one.
+    ///
+    /// Returns a `Result` containing the `BTreeRangeIterator`, or a `BTreeError` on failure.
+    pub fn iter(&self, start: &Key) -> Result<BTreeRangeIterator<'_, S>, BTreeError> {
+        self.range(start.clone()..)
+    }
+
+    /// Creates an iterator over the keys within `range`, e.g. `start..=end`,
+    /// `..end` or `..` for all of them, in increasing order.
+    ///
+    /// The iterator holds a shared latch on its current leaf until it moves to
+    /// the next one, see `stable_range` for one that holds none.
+    ///
+    /// Returns a `Result` containing the `BTreeRangeIterator`, or a `BTreeError` on failure.
+    pub fn range(
+        &self,
+        range: impl RangeBounds<Key>,
+    ) -> Result<BTreeRangeIterator<'_, S>, BTreeError> {
+        let start = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => key.as_bytes(),
+            Bound::Unbounded => &[],
+        };
+        let page_ref = self.find_leaf_page(start)?;
+        let leaf_page = page_ref.btree_leaf_page();
+        let pos = match (leaf_page.search(start), range.start_bound()) {
+            (Ok(pos), Bound::Excluded(_)) => pos + 1,
+            (Ok(pos) | Err(pos), _) => pos,
+        };
+
+        Ok(BTreeRangeIterator {
+            pos,
+            end: range.end_bound().cloned(),
+            btree: self,
+            page_ref,
+        })
+    }
+
+    /// Same as `range`, but no page is latched between the calls to the
+    /// iterator: the entries of a leaf are copied at once, and the next leaf
+    /// is found by descending the tree again, from the last key returned.
+    ///
+    /// Whatever the splits and merges meanwhile, keys are returned in
+    /// increasing order, each at most once, and the keys within `range` for
+    /// the whole iteration are all returned. The keys inserted or deleted
+    /// meanwhile may or may not be, and an entry is returned as it was when
+    /// its leaf was copied. `range` instead holds a latch on its current leaf,
+    /// which writers to that leaf wait for. The iterator returns the error of
+    /// a descent and stops.
+    ///
+    /// Returns a `Result` containing the `BTreeStableRangeIterator`, or a `BTreeError` on failure.
+    pub fn stable_range(
+        &self,
+        range: impl RangeBounds<Key>,
+    ) -> Result<BTreeStableRangeIterator<'_, S>, BTreeError> {
+        let mut iter = BTreeStableRangeIterator {
+            entries: Vec::new(),
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            done: false,
+            btree: self,
+        };
+        iter.copy_leaf()?;
+
+        Ok(iter)
+    }
+
+    /// Creates an iterator over the keys from `start` down to the first one.
+    ///
+    /// Returns a `Result` containing the `BTreeRevRangeIterator`, or a `BTreeError` on failure.
+    pub fn rev_iter(&self, start: &Key) -> Result<BTreeRevRangeIterator<'_, S>, BTreeError> {
+        self.rev_range(..=start.clone())
+    }
+
+    /// Creates an iterator over the keys within `range` in decreasing order.
+    ///
+    /// Leaves are only linked to the next one: the previous leaf is found by
+    /// descending the tree again, towards the keys below the current leaf.
+    /// The iterator returns the error of a descent and stops.
+    ///
+    /// Returns a `Result` containing the `BTreeRevRangeIterator`, or a `BTreeError` on failure.
+    pub fn rev_range(
+        &self,
+        range: impl RangeBounds<Key>,
+    ) -> Result<BTreeRevRangeIterator<'_, S>, BTreeError> {
+        let end = range.end_bound().map(Key::as_bytes);
+        let (page_ref, low) = self.find_leaf_page_below(end)?;
+        let pos = count_below(page_ref.btree_leaf_page(), end);
+
+        Ok(BTreeRevRangeIterator {
+            pos,
+            low,
+            start: range.start_bound().cloned(),
+            btree: self,
+            page_ref: Some(page_ref),
+        })
+    }
+}
+
+/// The number of keys of a page within `end`, i.e. below the cut it makes
+/// between keys. For inner pages, the position of the pointer to the keys
+/// around the cut.
+fn count_below<V: FromBytes + IntoBytes + Immutable + Copy>(
+    page: &BTreePage<V>,
+    end: Bound<&[u8]>,
+) -> usize {
+    match end {
+        Bound::Included(end) => page.search(end).map_or_else(|pos| pos, |pos| pos + 1),
+        Bound::Excluded(end) => page.search(end).unwrap_or_else(|pos| pos),
+        Bound::Unbounded => page.len(),
+    }
+}
+
+pub struct BTreeRangeIterator<'btree, S: StorageBackend + 'static> {
+    pos: usize,
+    end: Bound<Key>,
+    btree: &'btree BTree<S>,
+    page_ref: PageRef<'btree>,
+}
+
+impl<'btree, S: StorageBackend + 'static> BTreeRangeIterator<'btree, S> {
+    /// Same as `next`, along with the payload of the entry, see
+    /// `BTree::insert_with_payload`.
+    pub fn next_with_payload(&mut self) -> Option<(Key, RecordId, Vec<u8>)> {
+        let (key, record_id) = self.next()?;
+        let payload = self.page_ref.btree_leaf_page().payload_at(self.pos - 1);
+        Some((key, record_id, payload.to_vec()))
+    }
+}
+
+impl<'btree, S: StorageBackend + 'static> Iterator for BTreeRangeIterator<'btree, S> {
+    type Item = (Key, RecordId);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Leaves emptied by deletes are skipped.
+        while self.pos >= self.page_ref.btree_leaf_page().len() {
+            let next_page_id = self.page_ref.btree_leaf_page().next_page_id();
+            if next_page_id == PAGE_INVALID {
+                return None;
+            }
+
+            self.page_ref = self
+                .btree
+                .page_cache
+                .get_page(next_page_id)
+                .map_err(|_| todo!("log errors"))
+                .ok()?;
+
+            self.pos = 0;
+        }
+
+        let leaf_page = self.page_ref.btree_leaf_page();
+        let (key, record_id) = (leaf_page.key_at(self.pos), leaf_page.value_at(self.pos));
+        // Past the end, the position is kept for the next calls to stop too.
+        if !below_end(&key, &self.end) {
+            return None;
+        }
+        self.pos += 1;
+
+        Some((key, record_id))
+    }
+}
+
+/// Whether a key is within the `end` bound of a range.
+fn below_end(key: &Key, end: &Bound<Key>) -> bool {
+    match end {
+        Bound::Included(end) => key <= end,
+        Bound::Excluded(end) => key < end,
+        Bound::Unbounded => true,
+    }
+}
+
+/// An entry of a leaf: its key, its record id and its payload.
+pub type BTreeEntry = (Key, RecordId, Vec<u8>);
+
+/// See `BTree::stable_range`.
+pub struct BTreeStableRangeIterator<'btree, S: StorageBackend + 'static> {
+    // The entries left of the leaf copied last, the next one at the end.
+    entries: Vec<BTreeEntry>,
+    // The keys left to return: after the last one copied.
+    start: Bound<Key>,
+    end: Bound<Key>,
+    // Set once the last leaf or the end of the range is copied.
+    done: bool,
+    btree: &'btree BTree<S>,
+}
+
+impl<'btree, S: StorageBackend + 'static> BTreeStableRangeIterator<'btree, S> {
+    /// Copies the entries of the first leaf holding keys after `start`, the
+    /// leaves emptied by deletes are skipped.
+    fn copy_leaf(&mut self) -> Result<(), BTreeError> {
+        let start = match &self.start {
+            Bound::Included(key) | Bound::Excluded(key) => key.as_bytes(),
+            Bound::Unbounded => &[],
+        };
+        let mut page_ref = self.btree.find_leaf_page(start)?;
+        let mut pos = match (page_ref.btree_leaf_page().search(start), &self.start) {
+            (Ok(pos), Bound::Excluded(_)) => pos + 1,
+            (Ok(pos) | Err(pos), _) => pos,
+        };
+        while pos >= page_ref.btree_leaf_page().len() {
+            let next_page_id = page_ref.btree_leaf_page().next_page_id();
+            if next_page_id == PAGE_INVALID {
+                self.done = true;
+                return Ok(());
+            }
+            page_ref = self.btree.page_cache.get_page(next_page_id)?;
+            pos = 0;
+        }
+
+        let leaf_page = page_ref.btree_leaf_page();
+        self.done = leaf_page.next_page_id() == PAGE_INVALID;
+        for pos in pos..leaf_page.len() {
+            let key = leaf_page.key_at(pos);
+            if !below_end(&key, &self.end) {
+                self.done = true;
+                break;
+            }
+            let payload = leaf_page.payload_at(pos).to_vec();
+            self.entries.push((key, leaf_page.value_at(pos), payload));
+        }
+        self.entries.reverse();
+        if let Some((key, ..)) = self.entries.first() {
+            self.start = Bound::Excluded(key.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Same as `next`, along with the payload of the entry, see
+    /// `BTree::insert_with_payload`.
+    pub fn next_with_payload(&mut self) -> Option<Result<BTreeEntry, BTreeError>> {
+        if self.entries.is_empty()
+            && !self.done
+            && let Err(e) = self.copy_leaf()
+        {
+            self.done = true;
+            return Some(Err(e));
+        }
+        self.entries.pop().map(Ok)
+    }
+}
+
+/// Returns the entries in increasing order, then `None`. An error copying the
+/// next leaf is returned in place of its first entry, and ends the iteration.
+impl<'btree, S: StorageBackend + 'static> Iterator for BTreeStableRangeIterator<'btree, S> {
+    type Item = Result<(Key, RecordId), BTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.next_with_payload()?;
+        Some(entry.map(|(key, record_id, _)| (key, record_id)))
+    }
+}
+
+pub struct BTreeRevRangeIterator<'btree, S: StorageBackend + 'static> {
+    // The keys of the leaf before `pos` are left to return.
+    pos: usize,
+    // The lower bound of the keys of the leaf, `None` for the first leaf.
+    low: Option<Key>,
+    start: Bound<Key>,
+    btree: &'btree BTree<S>,
+    // Released while looking for the previous leaf.
+    page_ref: Option<PageRef<'btree>>,
+}
+
+/// Returns the entries in decreasing order, then `None`. An error finding the
+/// previous leaf is returned in place of the next entry, and ends the
+/// iteration.
+impl<'btree, S: StorageBackend + 'static> Iterator for BTreeRevRangeIterator<'btree, S> {
+    type Item = Result<(Key, RecordId), BTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Leaves emptied by deletes are skipped.
+        while self.pos == 0 {
+            let low = self.low.take()?;
+            let below = Bound::Excluded(low.as_bytes());
+            // Writers lock pages from the root down: the leaf is released
+            // before descending again.
+            self.page_ref = None;
+            let (page_ref, low_bound) = match self.btree.find_leaf_page_below(below) {
+                Ok(found) => found,
+                Err(e) => return Some(Err(e)),
+            };
+            self.pos = count_below(page_ref.btree_leaf_page(), below);
+            self.low = low_bound;
+            self.page_ref = Some(page_ref);
+        }
+
+        let leaf_page = self.page_ref.as_ref()?.btree_leaf_page();
+        let pos = self.pos - 1;
+        let (key, record_id) = (leaf_page.key_at(pos), leaf_page.value_at(pos));
+        let in_range = match &self.start {
+            Bound::Included(start) => key >= *start,
+            Bound::Excluded(start) => key > *start,
+            Bound::Unbounded => true,
+        };
+        // Past the start, the position is kept for the next calls to stop too.
+        if !in_range {
+            return None;
+        }
+        self.pos = pos;
+
+        Some(Ok((key, record_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::PageCache;
+    use crate::pages::HeapPageSlotId;
+    use crate::pages::check::{Corruption, check_file};
+    use crate::sql::schema::DataType;
+    use crate::sql::types::Value;
+    use crate::sql::types::memcomparable::KeyOptions;
+    use crate::storage::FileStorage;
+
+    use std::sync::{Arc, mpsc};
+    use std::time::Duration;
+
+    use tempfile::NamedTempFile;
+
+    const NR_KEYS: usize = 1000;
+
+    fn create_btree() -> BTree<FileStorage> {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let file_cache = page_cache.cache_storage(storage);
+        BTree::try_new(file_cache).unwrap()
+    }
+
+    fn make_record() -> RecordId {
+        RecordId::new(PageId::new(0), HeapPageSlotId::new(0))
+    }
+
     #[test]
     fn btree_new() {
         let _ = create_btree();
@@ -434,11 +1527,11 @@ mod tests {
         let btree = create_btree();
 
         for key in 0..NR_KEYS {
-            btree.insert(Key::new(key as u32), make_record()).unwrap();
+            btree.insert(&Key::new(key as u32), make_record()).unwrap();
         }
 
         for key in 0..NR_KEYS {
-            assert!(btree.search(Key::new(key as u32)).is_some());
+            assert!(btree.search(&Key::new(key as u32)).is_some());
         }
     }
 
@@ -447,11 +1540,11 @@ mod tests {
         let btree = create_btree();
 
         for key in (0..NR_KEYS).rev() {
-            btree.insert(Key::new(key as u32), make_record()).unwrap();
+            btree.insert(&Key::new(key as u32), make_record()).unwrap();
         }
 
         for key in (0..NR_KEYS).rev() {
-            assert!(btree.search(Key::new(key as u32)).is_some());
+            assert!(btree.search(&Key::new(key as u32)).is_some());
         }
     }
 
@@ -461,21 +1554,169 @@ mod tests {
 
         for key in 0..NR_KEYS {
             let key = if key % 2 == 0 { key } else { key * 1000 };
-            btree.insert(Key::new(key as u32), make_record()).unwrap();
+            btree.insert(&Key::new(key as u32), make_record()).unwrap();
         }
         for key in 0..NR_KEYS {
             let key = if key % 2 == 0 { key } else { key * 1000 };
-            assert!(btree.search(Key::new(key as u32)).is_some());
+            assert!(btree.search(&Key::new(key as u32)).is_some());
         }
     }
 
     #[test]
-    #[should_panic]
     fn insert_duplicate_key() {
         let btree = create_btree();
-        let key = Key::new(10);
-        btree.insert(key, make_record()).unwrap();
-        btree.insert(key, make_record()).unwrap();
+        for key in 0..1000 {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        for key in [10, 999] {
+            assert!(matches!(
+                btree.insert(&Key::new(key), make_record()),
+                Err(BTreeError::DuplicateKey(k)) if k == Key::new(key)
+            ));
+        }
+        assert_eq!(btree.range(..).unwrap().count(), 1000);
+    }
+
+    #[test]
+    fn insert_variable_length_keys() {
+        let btree = create_btree();
+
+        // Long keys split pages after a few dozen entries.
+        let key = |i: usize| Key::from(format!("{i:04}{}", "x".repeat(i % 300)).into_bytes());
+        for i in (0..2000).rev() {
+            btree.insert(&key(i), make_record()).unwrap();
+        }
+        let keys = btree.range(..).unwrap().map(|(key, _)| key);
+        assert!(keys.eq((0..2000).map(key)));
+        let keys = btree.range(key(10)..key(13)).unwrap().map(|(key, _)| key);
+        assert!(keys.eq((10..13).map(key)));
+        // Shorter keys sort first.
+        let prefix = Key::from(&b"0010"[..]);
+        btree.insert(&prefix, make_record()).unwrap();
+        let keys = btree.iter(&prefix).unwrap().map(|(key, _)| key);
+        assert!(keys.take(2).eq([prefix.clone(), key(10)]));
+
+        for i in (0..2000).step_by(3) {
+            btree.delete(&key(i)).unwrap();
+        }
+        for i in 0..2000 {
+            assert_eq!(btree.search(&key(i)).is_some(), i % 3 != 0);
+        }
+
+        let too_large = Key::from(vec![0; BTREE_MAX_KEY_SIZE + 1]);
+        assert!(matches!(
+            btree.insert(&too_large, make_record()),
+            Err(BTreeError::Page(BTreePageError::KeyTooLarge(_)))
+        ));
+    }
+
+    #[test]
+    fn shared_prefix_keys() {
+        let btree = create_btree();
+
+        // Keys that only differ in the middle.
+        let key = |i: usize| {
+            Key::from(format!("{}{i:06}{}", "x".repeat(200), "y".repeat(300)).into_bytes())
+        };
+        for i in 0..5000 {
+            btree.insert(&key(i * 7 % 5000), make_record()).unwrap();
+        }
+        let keys = btree.range(..).unwrap().map(|(key, _)| key);
+        assert!(keys.eq((0..5000).map(key)));
+        let keys = btree
+            .rev_range(key(10)..=key(20))
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(key, _)| key);
+        assert!(keys.eq((10..=20).rev().map(key)));
+
+        // The separators are truncated right after the first byte that differs
+        // between the last key of a page and the first key of the next one.
+        let superblock_ref = btree.page_cache.get_page(PAGE_RESERVED).unwrap();
+        let root_page_id = superblock_ref.btree_superblock().root_page_id;
+        let root_page_ref = btree.page_cache.get_page(root_page_id).unwrap();
+        let root_page = root_page_ref.btree_inner_page();
+        assert_ne!(root_page.len(), 0);
+        assert!(root_page.keys().all(|key| key.as_bytes().len() <= 206));
+        drop(root_page_ref);
+        drop(superblock_ref);
+
+        for i in (0..5000).step_by(2) {
+            btree.delete(&key(i)).unwrap();
+        }
+        for i in 0..5000 {
+            assert_eq!(btree.search(&key(i)).is_some(), i % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn value_keys() {
+        let btree = create_btree();
+        // (name, score DESC): NULLs sort last, so first in a descending column.
+        let options = [KeyOptions::default(), KeyOptions::descending()];
+        let columns = [
+            (DataType::VarChar(None), options[0]),
+            (DataType::Float, options[1]),
+        ];
+        let rows = [
+            ("b", Some(1.5)),
+            ("a", None),
+            ("a", Some(-2.0)),
+            ("b", Some(f64::INFINITY)),
+            ("a", Some(3.0)),
+        ];
+        for (name, score) in rows {
+            let values = [
+                Value::VarChar(name.to_string()),
+                score.map_or(Value::Null, Value::Float),
+            ];
+            let key = Key::from_values_with(&values, &options);
+            btree.insert(&key, make_record()).unwrap();
+        }
+        btree
+            .insert(
+                &Key::from_values_with(&[Value::Null, Value::Null], &options),
+                make_record(),
+            )
+            .unwrap();
+
+        let values = btree
+            .range(..)
+            .unwrap()
+            .map(|(key, _)| key.values(&columns).unwrap())
+            .collect::<Vec<_>>();
+        let expected = [
+            [Value::VarChar("a".to_string()), Value::Null],
+            [Value::VarChar("a".to_string()), Value::Float(3.0)],
+            [Value::VarChar("a".to_string()), Value::Float(-2.0)],
+            [Value::VarChar("b".to_string()), Value::Float(f64::INFINITY)],
+            [Value::VarChar("b".to_string()), Value::Float(1.5)],
+            [Value::Null, Value::Null],
+        ];
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn len() {
+        let btree = create_btree();
+        assert!(btree.is_empty().unwrap());
+
+        for key in 0..5000 {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        assert!(btree.insert(&Key::new(42), make_record()).is_err());
+        assert_eq!(btree.len().unwrap(), 5000);
+        for key in (0..5000).step_by(2) {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        assert!(btree.delete(&Key::new(42)).is_err());
+        assert_eq!(btree.len().unwrap(), 2500);
+        assert_eq!(btree.len().unwrap(), btree.range(..).unwrap().count());
+
+        for key in (1..5000).step_by(2) {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        assert!(btree.is_empty().unwrap());
     }
 
     #[test]
@@ -484,56 +1725,56 @@ mod tests {
 
         for key in 0..NR_KEYS {
             btree
-                .insert(Key::new(key as u32 * 2), make_record())
+                .insert(&Key::new(key as u32 * 2), make_record())
                 .unwrap();
         }
-        assert!(btree.search(Key::new(10)).is_some());
-        assert!(btree.search(Key::new(9)).is_none());
-        assert!(btree.search(Key::new(11)).is_none());
+        assert!(btree.search(&Key::new(10)).is_some());
+        assert!(btree.search(&Key::new(9)).is_none());
+        assert!(btree.search(&Key::new(11)).is_none());
     }
 
     #[test]
     fn search_empty_tree() {
         let btree = create_btree();
-        assert!(btree.search(Key::new(42)).is_none());
+        assert!(btree.search(&Key::new(42)).is_none());
     }
 
     #[test]
     fn search_nonexistent_key() {
         let btree = create_btree();
-        btree.insert(Key::new(10), make_record()).unwrap();
-        btree.insert(Key::new(20), make_record()).unwrap();
+        btree.insert(&Key::new(10), make_record()).unwrap();
+        btree.insert(&Key::new(20), make_record()).unwrap();
 
         // Search for keys that don't exist
-        assert!(btree.search(Key::new(1)).is_none());
-        assert!(btree.search(Key::new(15)).is_none());
-        assert!(btree.search(Key::new(25)).is_none());
+        assert!(btree.search(&Key::new(1)).is_none());
+        assert!(btree.search(&Key::new(15)).is_none());
+        assert!(btree.search(&Key::new(25)).is_none());
     }
 
     #[test]
     fn delete_existing_key() {
         let btree = create_btree();
-        btree.insert(Key::new(10), make_record()).unwrap();
-        btree.insert(Key::new(20), make_record()).unwrap();
-        btree.insert(Key::new(30), make_record()).unwrap();
+        btree.insert(&Key::new(10), make_record()).unwrap();
+        btree.insert(&Key::new(20), make_record()).unwrap();
+        btree.insert(&Key::new(30), make_record()).unwrap();
 
-        let _ = btree.delete(Key::new(20));
+        let _ = btree.delete(&Key::new(20));
 
-        assert!(btree.search(Key::new(20)).is_none());
-        assert!(btree.search(Key::new(10)).is_some());
-        assert!(btree.search(Key::new(30)).is_some());
+        assert!(btree.search(&Key::new(20)).is_none());
+        assert!(btree.search(&Key::new(10)).is_some());
+        assert!(btree.search(&Key::new(30)).is_some());
     }
 
     #[test]
     fn delete_nonexistent_key() {
         let btree = create_btree();
-        btree.insert(Key::new(10), make_record()).unwrap();
+        btree.insert(&Key::new(10), make_record()).unwrap();
 
         assert!(matches!(
-            btree.delete(Key::new(20)),
+            btree.delete(&Key::new(20)),
             Err(BTreeError::Page(BTreePageError::KeyNotFound))
         ));
-        assert!(btree.search(Key::new(10)).is_some());
+        assert!(btree.search(&Key::new(10)).is_some());
     }
 
     #[test]
@@ -541,7 +1782,7 @@ mod tests {
         let btree = create_btree();
 
         assert!(matches!(
-            btree.delete(Key::new(20)),
+            btree.delete(&Key::new(20)),
             Err(BTreeError::Page(BTreePageError::KeyNotFound))
         ));
     }
@@ -551,16 +1792,355 @@ mod tests {
         let btree = create_btree();
 
         for key in 0..1000 {
-            btree.insert(Key::new(key as u32), make_record()).unwrap();
+            btree.insert(&Key::new(key as u32), make_record()).unwrap();
+        }
+
+        for key in 0..1000 {
+            let _ = btree.delete(&Key::new(key));
         }
 
         for key in 0..1000 {
-            let _ = btree.delete(Key::new(key));
+            assert!(btree.search(&Key::new(key)).is_none());
+        }
+    }
+
+    #[test]
+    fn payloads() {
+        let payload = |key: u32| format!("payload of {key}").into_bytes();
+        let check = |btree: &BTree<FileStorage>, expected: &mut dyn Iterator<Item = u32>| {
+            let mut iter = btree.range(..).unwrap();
+            while let Some((key, _, stored)) = iter.next_with_payload() {
+                assert_eq!(Some(key.get()), expected.next());
+                assert_eq!(stored, payload(key.get()));
+            }
+            assert!(expected.next().is_none());
+            assert!(btree.verify().unwrap().is_ok());
+        };
+
+        // Payloads move with their keys when pages are split, merged or
+        // redistributed.
+        let btree = create_btree();
+        for key in (0..5000).map(|key| key * 7 % 5000) {
+            let key = key as u32;
+            btree
+                .insert_with_payload(&Key::new(key), make_record(), &payload(key))
+                .unwrap();
+        }
+        check(&btree, &mut (0..5000));
+        for key in (0..5000).filter(|key| key % 3 != 0) {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        check(&btree, &mut (0..5000).step_by(3));
+
+        let page_cache = PageCache::try_new().unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let entries = (0..5000).map(|key| (Key::new(key), make_record(), payload(key)));
+        let btree =
+            BTree::bulk_load_with_payloads(page_cache.cache_storage(storage), entries).unwrap();
+        check(&btree, &mut (0..5000));
+
+        let too_large = vec![0; BTREE_MAX_KEY_SIZE];
+        assert!(matches!(
+            btree.insert_with_payload(&Key::new(5000), make_record(), &too_large),
+            Err(BTreeError::Page(BTreePageError::KeyTooLarge(_)))
+        ));
+    }
+
+    #[test]
+    fn bulk_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.idx");
+        let page_cache = PageCache::try_new().unwrap();
+        let nr_keys = 20_000;
+        let entries = (0..nr_keys).map(|key| (Key::new(key * 2), make_record()));
+        let btree = BTree::bulk_load(
+            page_cache.cache_storage(FileStorage::create(&path).unwrap()),
+            entries,
+        )
+        .unwrap();
+        assert_eq!(btree.len().unwrap(), nr_keys as usize);
+        let keys = btree.range(..).unwrap().map(|(key, _)| key.get());
+        assert!(keys.eq((0..nr_keys).map(|key| key * 2)));
+        let keys = btree
+            .rev_range(..)
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(key, _)| key.get());
+        assert!(keys.eq((0..nr_keys).rev().map(|key| key * 2)));
+        page_cache.flush();
+        let report = check_file(&path, FileKind::BTree).unwrap();
+        assert!(report.is_ok(), "{report}");
+
+        // Half as many pages as when the keys are inserted one by one.
+        let inserted = create_btree();
+        for key in 0..nr_keys {
+            inserted.insert(&Key::new(key * 2), make_record()).unwrap();
         }
+        let num_pages = |btree: &BTree<_>| btree.page_cache.last_page_id().get();
+        assert!(num_pages(&btree) * 3 < num_pages(&inserted) * 2);
 
+        // Full pages are split.
+        for key in 0..nr_keys {
+            btree.insert(&Key::new(key * 2 + 1), make_record()).unwrap();
+        }
+        for key in (0..nr_keys * 2).step_by(3) {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        let keys = btree.range(..).unwrap().map(|(key, _)| key.get());
+        assert!(keys.eq((0..nr_keys * 2).filter(|key| key % 3 != 0)));
+        page_cache.flush();
+        let report = check_file(&path, FileKind::BTree).unwrap();
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn bulk_load_errors() {
+        let page_cache = PageCache::try_new().unwrap();
+        let bulk_load = |keys: &[u32]| {
+            let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+            let entries = keys.iter().map(|&key| (Key::new(key), make_record()));
+            BTree::bulk_load(page_cache.cache_storage(storage), entries)
+        };
+
+        let btree = bulk_load(&[]).unwrap();
+        assert_eq!(btree.range(..).unwrap().count(), 0);
+        btree.insert(&Key::new(1), make_record()).unwrap();
+        assert!(matches!(
+            bulk_load(&[1, 2, 2, 3]),
+            Err(BTreeError::DuplicateKey(k)) if k == Key::new(2)
+        ));
+        assert!(matches!(
+            bulk_load(&[1, 3, 2]),
+            Err(BTreeError::KeyNotSorted(k)) if k == Key::new(2)
+        ));
+    }
+
+    #[test]
+    fn delete_rebalance() {
+        let btree = create_btree();
+        let nr_keys = 60_000;
+        for key in 0..nr_keys {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        let root_page_type = || {
+            let superblock_ref = btree.page_cache.get_page(PAGE_RESERVED).unwrap();
+            let root_page_id = superblock_ref.btree_superblock().root_page_id;
+            let root_page_ref = btree.page_cache.get_page(root_page_id).unwrap();
+            btree_get_page_type(root_page_ref.page())
+        };
+        let check = |expected: &[u32]| {
+            let keys = btree.range(..).unwrap().map(|(key, _)| key.get());
+            assert!(keys.eq(expected.iter().copied()));
+            let keys = btree
+                .rev_range(..)
+                .unwrap()
+                .map(Result::unwrap)
+                .map(|(key, _)| key.get());
+            assert!(keys.eq(expected.iter().rev().copied()));
+            for &key in expected.iter().step_by(97) {
+                assert!(btree.search(&Key::new(key)).is_some());
+            }
+            let report = btree.verify().unwrap();
+            assert!(report.is_ok(), "{report}");
+        };
+        assert!(root_page_type().is_inner());
+
+        // Leaves borrow keys from their siblings.
+        let mut expected = (0..nr_keys).filter(|key| key % 4 == 0).collect::<Vec<_>>();
+        for key in (0..nr_keys).filter(|key| key % 4 != 0) {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        check(&expected);
+
+        // Whole subtrees are merged, from both sides.
+        expected.retain(|&key| !(10_000..50_000).contains(&key));
+        for key in (10_000..30_000).step_by(4) {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        for key in (30_000..50_000).step_by(4).rev() {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        check(&expected);
+
+        for &key in &expected {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        check(&[]);
+        assert!(root_page_type().is_leaf());
+
+        // The tree grows again.
         for key in 0..1000 {
-            assert!(btree.search(Key::new(key)).is_none());
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        check(&(0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn free_list() {
+        let btree = create_btree();
+        for key in 0..20_000 {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        let last_page_id = btree.page_cache.last_page_id();
+        for key in 0..20_000 {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        let free_list_len = || {
+            let free_list_ref = btree.page_cache.get_page(btree.free_list_page_id).unwrap();
+            free_list_ref.free_list_page().len()
+        };
+        assert!(free_list_len() > 0);
+        let report = btree.verify().unwrap();
+        assert!(report.is_ok(), "{report}");
+
+        // Splits reuse the pages freed by merges, the file doesn't grow.
+        for key in 0..20_000 {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        assert_eq!(btree.page_cache.last_page_id(), last_page_id);
+        let report = btree.verify().unwrap();
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(btree.len().unwrap(), 20_000);
+    }
+
+    #[test]
+    fn stats() {
+        let btree = create_btree();
+        let stats = btree.stats().unwrap();
+        assert_eq!(stats.pages_per_level, [1]);
+        assert_eq!((stats.height(), stats.keys, stats.fill_factor), (1, 0, 0.0));
+
+        for key in 0..20_000 {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        let stats = btree.stats().unwrap();
+        assert_eq!(stats.keys, 20_000);
+        assert_eq!(stats.pages_per_level[0], 1);
+        assert!(stats.height() >= 2);
+        // Pages split in two halves, but increasing keys are appended to the
+        // last leaf.
+        assert!(stats.fill_factor > 0.4 && stats.fill_factor < 1.0);
+        // No page was freed, they are all in the tree but the reserved one and
+        // the free list.
+        assert_eq!(
+            stats.pages() + 1,
+            btree.page_cache.last_page_id().get() as u64
+        );
+    }
+
+    #[test]
+    fn estimate_range() {
+        let btree = create_btree();
+        let key = |key| Key::new(key);
+        assert_eq!(btree.estimate_range(..).unwrap(), 0);
+
+        for key in 0..20_000 {
+            btree.insert(&Key::new(key * 2), make_record()).unwrap();
         }
+        // Within a leaf, the keys are counted.
+        assert_eq!(btree.estimate_range(key(10)..=key(16)).unwrap(), 4);
+        assert_eq!(btree.estimate_range(key(10)..key(16)).unwrap(), 3);
+        assert_eq!(
+            btree
+                .estimate_range((Bound::Excluded(key(10)), Bound::Excluded(key(16))))
+                .unwrap(),
+            2
+        );
+        assert_eq!(btree.estimate_range(key(20)..key(10)).unwrap(), 0);
+        assert_eq!(btree.estimate_range(key(40_000)..).unwrap(), 0);
+
+        // Across leaves, the estimates are close.
+        let close = |estimate: u64, keys: u64| estimate.abs_diff(keys) * 10 <= keys;
+        assert!(close(btree.estimate_range(..).unwrap(), 20_000));
+        assert!(close(btree.estimate_range(key(1000)..).unwrap(), 19_500));
+        assert!(close(btree.estimate_range(..key(30_000)).unwrap(), 15_000));
+        assert!(close(
+            btree.estimate_range(key(5000)..key(25_000)).unwrap(),
+            10_000
+        ));
+        assert!(close(
+            btree.estimate_range(key(9000)..=key(11_000)).unwrap(),
+            1001
+        ));
+    }
+
+    #[test]
+    fn bloom_filter() {
+        let btree = create_btree();
+        for key in (0..NR_KEYS as u32).map(|key| key * 2) {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        // Without a filter, every key may be in the tree.
+        assert!(btree.may_contain(&Key::new(1)).unwrap());
+
+        btree.create_bloom_filter(NR_KEYS as u64).unwrap();
+        for key in (0..NR_KEYS as u32).map(|key| key * 2) {
+            assert!(btree.may_contain(&Key::new(key)).unwrap());
+            assert!(btree.search(&Key::new(key)).is_some());
+        }
+        let false_positives = (0..NR_KEYS as u32)
+            .map(|key| key * 2 + 1)
+            .filter(|&key| btree.may_contain(&Key::new(key)).unwrap())
+            .count();
+        assert!(false_positives < NR_KEYS / 20, "{false_positives}");
+
+        // Inserted keys are added to the filter.
+        btree.insert(&Key::new(1), make_record()).unwrap();
+        assert!(btree.may_contain(&Key::new(1)).unwrap());
+        assert!(btree.search(&Key::new(1)).is_some());
+
+        // The filter pages are accounted for, the pages of a replaced filter
+        // are freed.
+        let report = btree.verify().unwrap();
+        assert!(report.is_ok(), "{report}");
+        btree.create_bloom_filter(100 * NR_KEYS as u64).unwrap();
+        assert!(btree.may_contain(&Key::new(1)).unwrap());
+        let report = btree.verify().unwrap();
+        assert!(report.is_ok(), "{report}");
+
+        // A filter which can't be read doesn't fail lookups.
+        {
+            let mut superblock_ref = btree.page_cache.get_page_mut(PAGE_RESERVED).unwrap();
+            superblock_ref
+                .btree_superblock_mut()
+                .set_bloom_filter(&[PageId::new(u32::MAX - 1)]);
+        }
+        assert!(btree.may_contain(&Key::new(1)).is_err());
+        assert!(btree.search(&Key::new(1)).is_some());
+    }
+
+    #[test]
+    fn verify() {
+        let btree = create_btree();
+        for key in 0..5000 {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        let report = btree.verify().unwrap();
+        assert!(report.is_ok(), "{report}");
+
+        // Unlink the second leaf from the chain.
+        let mut leaf_page_ref = btree.find_leaf_page_mut(Key::new(0).as_bytes()).unwrap();
+        let leaf_page_id = leaf_page_ref.metadata().page_id();
+        let leaf_page = leaf_page_ref.btree_leaf_page_mut();
+        let second_page_id = leaf_page.next_page_id();
+        let third_page_id = {
+            let page_ref = btree.page_cache.get_page(second_page_id).unwrap();
+            page_ref.btree_leaf_page().next_page_id()
+        };
+        leaf_page.set_next_page_id(third_page_id);
+        drop(leaf_page_ref);
+
+        let report = btree.verify().unwrap();
+        assert_eq!(
+            report.corruptions,
+            [(
+                leaf_page_id,
+                Corruption::LeafChain {
+                    found: third_page_id.get(),
+                    expected: second_page_id.get(),
+                }
+            )]
+        );
     }
 
     #[test]
@@ -568,15 +2148,210 @@ mod tests {
         let btree = create_btree();
 
         for key in 0..1000 {
-            btree.insert(Key::new(key), make_record()).unwrap();
+            btree.insert(&Key::new(key), make_record()).unwrap();
         }
-        assert!(btree.search(Key::new(0)).is_some());
-        assert!(btree.search(Key::new(999)).is_some());
-        assert_eq!(btree.iter(Key::new(0)).unwrap().count(), 1000);
-        let keys = btree.iter(Key::new(0)).unwrap().map(|(key, _)| key);
+        assert!(btree.search(&Key::new(0)).is_some());
+        assert!(btree.search(&Key::new(999)).is_some());
+        assert_eq!(btree.iter(&Key::new(0)).unwrap().count(), 1000);
+        let keys = btree.iter(&Key::new(0)).unwrap().map(|(key, _)| key);
         assert!(keys.eq((0..1000).map(Key::new)));
     }
 
+    #[test]
+    fn iterator_descending_inserts() {
+        let btree = create_btree();
+
+        // Leaves are split in the middle of the chain.
+        for key in (0..1000).rev() {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+        let keys = btree.iter(&Key::new(0)).unwrap().map(|(key, _)| key);
+        assert!(keys.eq((0..1000).map(Key::new)));
+        let keys = btree.iter(&Key::new(500)).unwrap().map(|(key, _)| key);
+        assert!(keys.eq((500..1000).map(Key::new)));
+    }
+
+    #[test]
+    fn range() {
+        let btree = create_btree();
+
+        for key in 0..1000 {
+            btree.insert(&Key::new(key * 2), make_record()).unwrap();
+        }
+        let keys = |range: (Bound<Key>, Bound<Key>)| {
+            btree
+                .range(range)
+                .unwrap()
+                .map(|(key, _)| key.get())
+                .collect::<Vec<_>>()
+        };
+        let key = |key| Key::new(key);
+        assert_eq!(btree.range(..).unwrap().count(), 1000);
+        assert_eq!(
+            keys((Bound::Included(key(10)), Bound::Included(key(16)))),
+            [10, 12, 14, 16]
+        );
+        assert_eq!(
+            keys((Bound::Excluded(key(10)), Bound::Excluded(key(16)))),
+            [12, 14]
+        );
+        assert_eq!(
+            keys((Bound::Included(key(11)), Bound::Included(key(15)))),
+            [12, 14]
+        );
+        assert_eq!(keys((Bound::Unbounded, Bound::Excluded(key(6)))), [0, 2, 4]);
+        assert_eq!(
+            keys((Bound::Excluded(key(1994)), Bound::Unbounded)),
+            [1996, 1998]
+        );
+        assert!(keys((Bound::Included(key(20)), Bound::Included(key(10)))).is_empty());
+        assert!(keys((Bound::Included(key(2000)), Bound::Unbounded)).is_empty());
+
+        // Across leaves, one of them emptied.
+        for key in 400..800 {
+            let _ = btree.delete(&Key::new(key));
+        }
+        let keys = btree
+            .range(key(390)..=key(810))
+            .unwrap()
+            .map(|(key, _)| key.get());
+        assert!(keys.eq([390, 392, 394, 396, 398, 800, 802, 804, 806, 808, 810]));
+
+        let mut iter = btree.range(..key(4)).unwrap();
+        assert_eq!(iter.by_ref().count(), 2);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn reverse_range() {
+        let btree = create_btree();
+
+        for key in (0..1000).rev() {
+            btree.insert(&Key::new(key * 2), make_record()).unwrap();
+        }
+        let keys = |range: (Bound<Key>, Bound<Key>)| {
+            btree
+                .rev_range(range)
+                .unwrap()
+                .map(Result::unwrap)
+                .map(|(key, _)| key.get())
+                .collect::<Vec<_>>()
+        };
+        let key = |key| Key::new(key);
+        let all = btree
+            .rev_range(..)
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(key, _)| key.get());
+        assert!(all.eq((0..1000).rev().map(|key| key * 2)));
+        let from = btree
+            .rev_iter(&key(1001))
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(key, _)| key.get());
+        assert!(from.eq((0..501).rev().map(|key| key * 2)));
+        assert_eq!(
+            keys((Bound::Included(key(10)), Bound::Included(key(16)))),
+            [16, 14, 12, 10]
+        );
+        assert_eq!(
+            keys((Bound::Excluded(key(10)), Bound::Excluded(key(16)))),
+            [14, 12]
+        );
+        assert_eq!(keys((Bound::Unbounded, Bound::Excluded(key(6)))), [4, 2, 0]);
+        assert_eq!(
+            keys((Bound::Excluded(key(1994)), Bound::Unbounded)),
+            [1998, 1996]
+        );
+        assert!(keys((Bound::Included(key(20)), Bound::Included(key(10)))).is_empty());
+
+        // Across leaves, some of them emptied.
+        for key in 400..800 {
+            let _ = btree.delete(&Key::new(key));
+        }
+        let keys = btree
+            .rev_range(key(390)..=key(810))
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(key, _)| key.get());
+        assert!(keys.eq([810, 808, 806, 804, 802, 800, 398, 396, 394, 392, 390]));
+
+        let mut iter = btree.rev_iter(&key(2)).unwrap();
+        assert_eq!(iter.by_ref().map(Result::unwrap).count(), 2);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn stable_range() {
+        let btree = create_btree();
+
+        for key in 0..1000 {
+            btree
+                .insert_with_payload(&Key::new(key * 2), make_record(), &key.to_be_bytes())
+                .unwrap();
+        }
+        let key = |key| Key::new(key);
+        for range in [
+            (Bound::Unbounded, Bound::Unbounded),
+            (Bound::Included(key(10)), Bound::Included(key(16))),
+            (Bound::Excluded(key(10)), Bound::Excluded(key(1600))),
+            (Bound::Unbounded, Bound::Excluded(key(6))),
+            (Bound::Excluded(key(1994)), Bound::Unbounded),
+            (Bound::Included(key(20)), Bound::Included(key(10))),
+            (Bound::Included(key(2000)), Bound::Unbounded),
+        ] {
+            let stable = btree.stable_range(range.clone()).unwrap();
+            assert!(stable.map(Result::unwrap).eq(btree.range(range).unwrap()));
+        }
+        let mut iter = btree.stable_range(key(10)..).unwrap();
+        let (key, _, payload) = iter.next_with_payload().unwrap().unwrap();
+        assert_eq!((key.get(), payload), (10, 5u32.to_be_bytes().to_vec()));
+
+        // Across leaves, one of them emptied.
+        for key in 400..800 {
+            let _ = btree.delete(&Key::new(key));
+        }
+        let keys = btree
+            .stable_range(Key::new(390)..=Key::new(810))
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(key, _)| key.get());
+        assert!(keys.eq([390, 392, 394, 396, 398, 800, 802, 804, 806, 808, 810]));
+    }
+
+    #[test]
+    fn stable_range_during_splits() {
+        const NUM_KEYS: u32 = 20_000;
+        let btree = create_btree();
+        for key in (0..NUM_KEYS).step_by(2) {
+            btree.insert(&Key::new(key), make_record()).unwrap();
+        }
+
+        // Odd keys split the leaves and half of the even ones are deleted
+        // while iterating, the other even ones must all be returned.
+        let writer = btree.clone();
+        let handle = std::thread::spawn(move || {
+            for key in (1..NUM_KEYS).step_by(2) {
+                writer.insert(&Key::new(key), make_record()).unwrap();
+                if key % 4 == 1 {
+                    writer.delete(&Key::new(key + 1)).unwrap();
+                }
+            }
+        });
+        for _ in 0..4 {
+            let keys = btree
+                .stable_range(..)
+                .unwrap()
+                .map(Result::unwrap)
+                .map(|(key, _)| key.get())
+                .collect::<Vec<_>>();
+            assert!(keys.is_sorted_by(|a, b| a < b));
+            let kept = keys.iter().filter(|&&key| key % 4 == 0);
+            assert!(kept.copied().eq((0..NUM_KEYS).step_by(4)));
+        }
+        handle.join().unwrap();
+    }
+
     #[test]
     fn concurrent_insert() {
         const NUM_THREADS: usize = 8;
@@ -589,7 +2364,7 @@ mod tests {
             let handle = std::thread::spawn(move || {
                 for key in 0..KEYS_PER_THREAD {
                     let key = i * KEYS_PER_THREAD + key;
-                    btree.insert(Key::new(key as u32), make_record()).unwrap();
+                    btree.insert(&Key::new(key as u32), make_record()).unwrap();
                 }
             });
             handles.push(handle);
@@ -600,10 +2375,92 @@ mod tests {
         }
 
         for key in 0..NUM_THREADS * KEYS_PER_THREAD {
-            assert!(btree.search(Key::new(key as u32)).is_some());
+            assert!(btree.search(&Key::new(key as u32)).is_some());
         }
     }
 
+    #[test]
+    fn search_during_splits() {
+        const NUM_KEYS: u32 = 20_000;
+        let btree = create_btree();
+        let record = |key: u32| RecordId::new(PageId::new(key), HeapPageSlotId::new(0));
+        for key in (0..NUM_KEYS).step_by(2) {
+            btree.insert(&Key::new(key), record(key)).unwrap();
+        }
+
+        // Searches descend optimistically while pages split under them.
+        let mut handles = Vec::new();
+        for i in 0..4 {
+            let writer = btree.clone();
+            handles.push(std::thread::spawn(move || {
+                for key in (2 * i + 1..NUM_KEYS).step_by(8) {
+                    writer.insert(&Key::new(key), record(key)).unwrap();
+                }
+            }));
+            let reader = btree.clone();
+            handles.push(std::thread::spawn(move || {
+                for key in (0..NUM_KEYS).step_by(2) {
+                    assert_eq!(reader.search(&Key::new(key)), Some(record(key)));
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        for key in 0..NUM_KEYS {
+            assert_eq!(btree.search(&Key::new(key)), Some(record(key)));
+        }
+    }
+
+    #[test]
+    fn insert_latch_crabbing() {
+        let btree = create_btree();
+        let key = |i: usize| Key::from(format!("{i:06}{}", "x".repeat(100)).into_bytes());
+        for i in (0..30_000).step_by(2) {
+            btree.insert(&key(i), make_record()).unwrap();
+        }
+        let leaf_page_id = btree
+            .find_leaf_page(key(0).as_bytes())
+            .unwrap()
+            .metadata()
+            .page_id();
+        let superblock_ref = btree.page_cache.get_page(PAGE_RESERVED).unwrap();
+        let root_page_id = superblock_ref.btree_superblock().root_page_id;
+        let root_page_ref = btree.page_cache.get_page(root_page_id).unwrap();
+        let child_page_id = root_page_ref.btree_inner_page().pointer(0);
+        let child_page_ref = btree.page_cache.get_page(child_page_id).unwrap();
+        assert!(!btree_get_page_type(child_page_ref.page()).is_leaf());
+        drop((child_page_ref, root_page_ref, superblock_ref));
+
+        // An insert splitting the first leaf waits for it, holding its parent
+        // only: the other pages have room for a separator.
+        let leaf_page_ref = btree.page_cache.get_page_mut(leaf_page_id).unwrap();
+        let blocked = {
+            let btree = btree.clone();
+            std::thread::spawn(move || btree.insert_slow_path(&key(1), make_record()))
+        };
+        std::thread::sleep(Duration::from_millis(100));
+
+        // Meanwhile leaves of other subtrees are split.
+        let (sender, receiver) = mpsc::channel();
+        {
+            let btree = btree.clone();
+            std::thread::spawn(move || {
+                for i in (20_001..20_201).step_by(2) {
+                    btree.insert(&key(i), make_record()).unwrap();
+                }
+                sender.send(()).unwrap();
+            });
+        }
+        assert!(receiver.recv_timeout(Duration::from_secs(10)).is_ok());
+
+        drop(leaf_page_ref);
+        blocked.join().unwrap().unwrap();
+        assert!(btree.search(&key(1)).is_some());
+        assert_eq!(btree.range(..).unwrap().count(), 15_101);
+    }
+
     #[test]
     fn concurrent_insert_search_delete() {
         fn create_ranges(count: usize, step: usize) -> Vec<core::ops::Range<usize>> {
@@ -623,20 +2480,20 @@ mod tests {
                 0..NUM_RANGES => {
                     let range = ranges[i % NUM_RANGES].clone();
                     for key in range {
-                        btree.insert(Key::new(key as u32), make_record()).unwrap();
+                        btree.insert(&Key::new(key as u32), make_record()).unwrap();
                     }
                 }
                 NUM_RANGES.. if i % 2 == 0 => {
                     let range = ranges[i % NUM_RANGES].clone();
                     for key in range {
-                        let _ = btree.search(Key::new(key as u32));
+                        let _ = btree.search(&Key::new(key as u32));
                     }
                 }
                 NUM_RANGES.. => {
                     if i % 2 == 1 {
                         let range = ranges[i % NUM_RANGES].clone();
                         for key in range {
-                            let _ = btree.delete(Key::new(key as u32));
+                            let _ = btree.delete(&Key::new(key as u32));
                         }
                     }
                 }
@@ -0,0 +1,301 @@
+use crate::cache::{StoragePageCache, SyncMode};
+use crate::indexes::{BTree, BTreeError, BTreeStats};
+use crate::pages::{HeapPageSlotId, Key, PageId, RecordId};
+use crate::storage::StorageBackend;
+
+use std::collections::BTreeSet;
+
+/// The longest term indexed, in bytes: longer ones are cut at a character
+/// boundary, see `tokenize`.
+pub const FULLTEXT_MAX_TERM_SIZE: usize = 128;
+
+/// Splits a text into its terms: the runs of alphanumeric characters,
+/// lowercased, each one once.
+pub fn tokenize(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut term = word.to_lowercase();
+            if term.len() > FULLTEXT_MAX_TERM_SIZE {
+                let end = (0..=FULLTEXT_MAX_TERM_SIZE)
+                    .rfind(|&end| term.is_char_boundary(end))
+                    .unwrap();
+                term.truncate(end);
+            }
+            term
+        })
+        .collect()
+}
+
+/// Whether a text has all the terms of `query`, as `CONTAINS(text, query)`.
+/// A query without terms matches no text.
+pub fn text_contains(text: &str, query: &str) -> bool {
+    let query = tokenize(query);
+    !query.is_empty() && query.is_subset(&tokenize(text))
+}
+
+/// The key of the posting of a term for a row: the term, a NUL byte, then the
+/// page and the slot of the record id in big endian. Terms have no NUL
+/// characters: the postings of a term are the keys starting with the term
+/// and a NUL byte, sorted by record id.
+fn posting_key(term: &str, record_id: RecordId) -> Key {
+    let mut key = Vec::with_capacity(term.len() + 7);
+    key.extend_from_slice(term.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&record_id.page_id.get().to_be_bytes());
+    key.extend_from_slice(&record_id.slot_id.get().to_be_bytes());
+    Key::from(key)
+}
+
+/// The record id of a posting, see `posting_key`.
+fn posting_record_id(key: &Key) -> RecordId {
+    let bytes = key.as_bytes();
+    let (page_id, slot_id) = bytes[bytes.len() - 6..].split_at(4);
+    RecordId::new(
+        PageId::new(u32::from_be_bytes(page_id.try_into().unwrap())),
+        HeapPageSlotId::new(u16::from_be_bytes(slot_id.try_into().unwrap())),
+    )
+}
+
+/// A full-text index on a VARCHAR column: for each term of its texts, see
+/// `tokenize`, the postings list of the rows whose text has the term.
+///
+/// Postings are stored in the pages of a `BTree`, one entry per term of a
+/// row, see `posting_key`: the postings list of a term is read by a range
+/// scan. NULLs aren't indexed.
+pub struct FullTextIndex<S: StorageBackend + 'static> {
+    btree: BTree<S>,
+}
+
+impl<S: StorageBackend> Clone for FullTextIndex<S> {
+    fn clone(&self) -> Self {
+        Self {
+            btree: self.btree.clone(),
+        }
+    }
+}
+
+impl<S: StorageBackend + 'static> FullTextIndex<S> {
+    /// Creates an empty full-text index.
+    pub fn try_new(page_cache: StoragePageCache<S>) -> Result<Self, BTreeError> {
+        Ok(Self {
+            btree: BTree::try_new(page_cache)?,
+        })
+    }
+
+    /// Creates a full-text index of the texts of `rows`, e.g. the rows of an
+    /// existing table. The postings are sorted then bulk loaded, see
+    /// `BTree::bulk_load`.
+    pub fn bulk_load<'a>(
+        page_cache: StoragePageCache<S>,
+        rows: impl IntoIterator<Item = (&'a str, RecordId)>,
+    ) -> Result<Self, BTreeError> {
+        let mut postings = rows
+            .into_iter()
+            .flat_map(|(text, record_id)| {
+                tokenize(text)
+                    .into_iter()
+                    .map(move |term| (posting_key(&term, record_id), record_id))
+            })
+            .collect::<Vec<_>>();
+        postings.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+        Ok(Self {
+            btree: BTree::bulk_load(page_cache, postings)?,
+        })
+    }
+
+    /// Adds the postings of the terms of the text of a row. None is left if
+    /// one can't be inserted.
+    pub fn insert(&self, text: &str, record_id: RecordId) -> Result<(), BTreeError> {
+        let terms = tokenize(text);
+        for (i, term) in terms.iter().enumerate() {
+            if let Err(e) = self.btree.insert(&posting_key(term, record_id), record_id) {
+                for term in terms.iter().take(i) {
+                    self.btree.delete(&posting_key(term, record_id))?;
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the postings of the terms of the text of a row, as inserted
+    /// by `insert`.
+    pub fn delete(&self, text: &str, record_id: RecordId) -> Result<(), BTreeError> {
+        for term in tokenize(text) {
+            self.btree.delete(&posting_key(&term, record_id))?;
+        }
+
+        Ok(())
+    }
+
+    /// Gives the index its own sync mode, see `BTree::set_sync_mode`.
+    pub fn set_sync_mode(&self, mode: Option<SyncMode>) {
+        self.btree.set_sync_mode(mode);
+    }
+
+    /// Commits the writes to the index, see `BTree::commit`.
+    pub fn commit(&self) {
+        self.btree.commit();
+    }
+
+    /// The postings list of a term, sorted by record id. The term is taken as
+    /// is, see `tokenize`.
+    pub fn postings(&self, term: &str) -> Result<Vec<RecordId>, BTreeError> {
+        let mut start = term.as_bytes().to_vec();
+        start.push(0);
+        let mut end = start.clone();
+        *end.last_mut().unwrap() = 1;
+        let postings = self
+            .btree
+            .stable_range(Key::from(start)..Key::from(end))?
+            .map(|entry| entry.map(|(key, _)| posting_record_id(&key)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(postings)
+    }
+
+    /// The record ids of the rows whose text has all the terms of `query`,
+    /// see `text_contains`, sorted: the intersection of the postings lists of its
+    /// terms.
+    pub fn search(&self, query: &str) -> Result<Vec<RecordId>, BTreeError> {
+        let order = |record_id: &RecordId| (record_id.page_id.get(), record_id.slot_id.get());
+        let mut terms = tokenize(query).into_iter();
+        let Some(term) = terms.next() else {
+            return Ok(Vec::new());
+        };
+        let mut record_ids = self.postings(&term)?;
+        for term in terms {
+            if record_ids.is_empty() {
+                break;
+            }
+            let postings = self.postings(&term)?;
+            record_ids.retain(|record_id| {
+                postings
+                    .binary_search_by_key(&order(record_id), order)
+                    .is_ok()
+            });
+        }
+
+        Ok(record_ids)
+    }
+
+    /// The statistics of the tree of the postings, one key per posting.
+    pub fn stats(&self) -> Result<BTreeStats, BTreeError> {
+        self.btree.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::PageCache;
+    use crate::storage::FileStorage;
+
+    use tempfile::NamedTempFile;
+
+    fn page_cache() -> StoragePageCache<FileStorage> {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        PageCache::try_new().unwrap().cache_storage(storage)
+    }
+
+    fn make_record(i: u32) -> RecordId {
+        RecordId::new(
+            PageId::new(i / 100 + 1),
+            HeapPageSlotId::new((i % 100) as u16),
+        )
+    }
+
+    #[test]
+    fn tokenize_and_contains() {
+        let terms = tokenize("The quick, quick (brown) Fox—l'été 42!");
+        assert!(
+            terms
+                .iter()
+                .eq(["42", "brown", "fox", "l", "quick", "the", "été"])
+        );
+        assert!(tokenize(" ,;! ").is_empty());
+        let long = "é".repeat(FULLTEXT_MAX_TERM_SIZE);
+        let term = tokenize(&long).pop_first().unwrap();
+        assert_eq!(term, "é".repeat(FULLTEXT_MAX_TERM_SIZE / 2));
+
+        assert!(text_contains("The quick brown fox", "QUICK"));
+        assert!(text_contains("The quick brown fox", "fox, the"));
+        assert!(!text_contains("The quick brown fox", "quick dog"));
+        assert!(!text_contains("The quick brown fox", "qui"));
+        assert!(!text_contains("The quick brown fox", ""));
+    }
+
+    #[test]
+    fn insert_search_delete() {
+        let index = FullTextIndex::try_new(page_cache()).unwrap();
+        let texts = [
+            "red apple",
+            "green apple",
+            "red cherry",
+            "apple pie, apple juice",
+        ];
+        for i in 0..1000 {
+            let text = texts[i as usize % texts.len()];
+            index.insert(text, make_record(i)).unwrap();
+        }
+        let expected =
+            |filter: fn(u32) -> bool| (0..1000).filter(move |&i| filter(i)).map(make_record);
+
+        assert!(
+            index
+                .search("APPLE")
+                .unwrap()
+                .into_iter()
+                .eq(expected(|i| i % 4 != 2))
+        );
+        assert!(
+            index
+                .search("red")
+                .unwrap()
+                .into_iter()
+                .eq(expected(|i| i % 2 == 0))
+        );
+        assert!(
+            index
+                .search("apple red")
+                .unwrap()
+                .into_iter()
+                .eq(expected(|i| i % 4 == 0))
+        );
+        assert!(index.search("apple banana").unwrap().is_empty());
+        assert!(index.search("app").unwrap().is_empty());
+        assert!(index.search("").unwrap().is_empty());
+        assert_eq!(index.stats().unwrap().keys, 250 * (2 + 2 + 2 + 3));
+
+        for i in (0..1000).step_by(2) {
+            let text = texts[i as usize % texts.len()];
+            index.delete(text, make_record(i)).unwrap();
+        }
+        assert!(index.search("red").unwrap().is_empty());
+        assert!(
+            index
+                .search("apple")
+                .unwrap()
+                .into_iter()
+                .eq(expected(|i| i % 2 == 1))
+        );
+        assert!(index.delete("red apple", make_record(0)).is_err());
+    }
+
+    #[test]
+    fn bulk_load() {
+        let rows = [
+            ("b a", make_record(3)),
+            ("a c", make_record(1)),
+            ("", make_record(2)),
+        ];
+        let index = FullTextIndex::bulk_load(page_cache(), rows).unwrap();
+        assert_eq!(index.search("a").unwrap(), [make_record(1), make_record(3)]);
+        assert_eq!(index.search("c").unwrap(), [make_record(1)]);
+        assert_eq!(index.postings("b").unwrap(), [make_record(3)]);
+    }
+}
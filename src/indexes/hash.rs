@@ -0,0 +1,543 @@
+use crate::cache::{PageCacheError, PageRefMut, StoragePageCache};
+use crate::pages::{
+    HASH_DIRECTORY_SIZE, HASH_MAX_DEPTH, HASH_MAX_KEY_SIZE, HashSuperBlock, Key, PAGE_INVALID,
+    PAGE_RESERVED, PageId, RecordId, hash_key,
+};
+use crate::storage::StorageBackend;
+
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+/// A hash index, for equality lookups in a constant number of page reads.
+///
+/// It uses extendible hashing: a directory of `2^global_depth` slots maps the
+/// low bits of the hash of a key to a bucket page. A full bucket is split in
+/// two on the next bit of the hash, and the directory doubles when the bucket
+/// was pointed to by a single slot. Buckets that can't be split anymore, their
+/// keys sharing `HASH_MAX_DEPTH` bits of hash, are chained to overflow pages.
+///
+/// Unlike a `BTree`, keys aren't ordered: there are no range scans. Buckets
+/// emptied by deletions aren't merged back.
+///
+/// The superblock is latched before the directory and the buckets: lookups,
+/// deletions and most insertions read latch it, only the insertions splitting
+/// a bucket write latch it.
+pub struct HashIndex<S: StorageBackend + 'static> {
+    page_cache: StoragePageCache<S>,
+}
+
+#[derive(Error, Debug)]
+pub enum HashIndexError {
+    #[error("page cache error")]
+    PageCache(#[from] PageCacheError),
+    #[error("duplicate key {0}")]
+    DuplicateKey(Key),
+    #[error("key not found")]
+    KeyNotFound,
+    #[error("key of {0} bytes, at most {max} are supported", max = HASH_MAX_KEY_SIZE)]
+    KeyTooLarge(usize),
+}
+
+/// The shape of a hash index, see `HashIndex::stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HashIndexStats {
+    pub global_depth: u32,
+    pub directory_pages: u64,
+    pub buckets: u64,
+    pub overflow_pages: u64,
+    pub keys: u64,
+}
+
+impl HashIndexStats {
+    pub fn pages(&self) -> u64 {
+        self.directory_pages + self.buckets + self.overflow_pages
+    }
+}
+
+impl<S: StorageBackend> Clone for HashIndex<S> {
+    fn clone(&self) -> Self {
+        Self {
+            page_cache: self.page_cache.clone(),
+        }
+    }
+}
+
+impl<S: StorageBackend + 'static> HashIndex<S> {
+    /// Creates an empty hash index: a directory of a single slot pointing to
+    /// an empty bucket.
+    pub fn try_new(page_cache: StoragePageCache<S>) -> Result<Self, HashIndexError> {
+        let mut superblock_ref = page_cache.get_page_mut(PAGE_RESERVED)?;
+        let mut bucket_page_ref = page_cache.new_page()?;
+        bucket_page_ref.hash_bucket_page_mut().init(0);
+        let mut directory_page_ref = page_cache.new_page()?;
+        directory_page_ref
+            .hash_directory_page_mut()
+            .set(0, bucket_page_ref.metadata().page_id());
+        superblock_ref
+            .hash_superblock_mut()
+            .init(directory_page_ref.metadata().page_id());
+        page_cache.set_page_dirty(bucket_page_ref.metadata());
+        page_cache.set_page_dirty(directory_page_ref.metadata());
+        page_cache.set_page_dirty(superblock_ref.metadata());
+        drop(bucket_page_ref);
+        drop(directory_page_ref);
+        drop(superblock_ref);
+
+        Ok(Self { page_cache })
+    }
+
+    /// Creates a hash index holding `entries`, e.g. to index the rows of an
+    /// existing table. The entries don't have to be sorted.
+    pub fn bulk_load(
+        page_cache: StoragePageCache<S>,
+        entries: impl IntoIterator<Item = (Key, RecordId)>,
+    ) -> Result<Self, HashIndexError> {
+        let index = Self::try_new(page_cache)?;
+        for (key, record_id) in entries {
+            index.insert(&key, record_id)?;
+        }
+
+        Ok(index)
+    }
+
+    /// Returns the bucket of a slot of the directory.
+    fn bucket_page_id(
+        &self,
+        superblock: &HashSuperBlock,
+        slot: usize,
+    ) -> Result<PageId, HashIndexError> {
+        let (page_id, pos) = superblock.directory_page(slot);
+        let directory_page_ref = self.page_cache.get_page(page_id)?;
+        Ok(directory_page_ref.hash_directory_page().get(pos))
+    }
+
+    fn set_bucket_page_id(
+        &self,
+        superblock: &HashSuperBlock,
+        slot: usize,
+        bucket_page_id: PageId,
+    ) -> Result<(), HashIndexError> {
+        let (page_id, pos) = superblock.directory_page(slot);
+        let mut directory_page_ref = self.page_cache.get_page_mut(page_id)?;
+        directory_page_ref
+            .hash_directory_page_mut()
+            .set(pos, bucket_page_id);
+        self.page_cache
+            .set_page_dirty(directory_page_ref.metadata());
+        Ok(())
+    }
+
+    /// Returns the `RecordId` of a key.
+    pub fn search(&self, key: &Key) -> Result<Option<RecordId>, HashIndexError> {
+        let key = key.as_bytes();
+        let hash = hash_key(key);
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        let superblock = superblock_ref.hash_superblock();
+        let mut page_id = self.bucket_page_id(superblock, superblock.directory_slot(hash))?;
+        while page_id != PAGE_INVALID {
+            let page_ref = self.page_cache.get_page(page_id)?;
+            let bucket_page = page_ref.hash_bucket_page();
+            if let Some(record_id) = bucket_page.get(hash, key) {
+                return Ok(Some(record_id));
+            }
+            page_id = bucket_page.overflow_page_id();
+        }
+
+        Ok(None)
+    }
+
+    /// Inserts a key along with the `RecordId` of its row.
+    ///
+    /// Returns a `HashIndexError::DuplicateKey` if the key is already indexed.
+    pub fn insert(&self, key: &Key, record_id: RecordId) -> Result<(), HashIndexError> {
+        let key = key.as_bytes();
+        if key.len() > HASH_MAX_KEY_SIZE {
+            return Err(HashIndexError::KeyTooLarge(key.len()));
+        }
+        let hash = hash_key(key);
+
+        // Fast path: the directory is left unchanged, only the pages of the
+        // bucket are latched for writing.
+        {
+            let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+            let superblock = superblock_ref.hash_superblock();
+            let page_id = self.bucket_page_id(superblock, superblock.directory_slot(hash))?;
+            if self.insert_bucket(page_id, hash, key, record_id)? {
+                drop(superblock_ref);
+                return self.count_key(true);
+            }
+        }
+
+        // Slow path: the bucket is full. The superblock is latched for
+        // writing, no other operation runs on the index while the bucket is
+        // split, until the key fits.
+        let mut superblock_ref = self.page_cache.get_page_mut(PAGE_RESERVED)?;
+        loop {
+            let superblock = superblock_ref.hash_superblock();
+            let page_id = self.bucket_page_id(superblock, superblock.directory_slot(hash))?;
+            if self.insert_bucket(page_id, hash, key, record_id)? {
+                break;
+            }
+            self.grow_bucket(&mut superblock_ref, page_id, hash)?;
+        }
+        superblock_ref.hash_superblock_mut().increment_num_keys();
+        self.page_cache.set_page_dirty(superblock_ref.metadata());
+
+        Ok(())
+    }
+
+    /// Inserts a key in the first page of the bucket with room for it, the
+    /// pages of the bucket are latched for writing. Returns `false` if none
+    /// has room.
+    fn insert_bucket(
+        &self,
+        page_id: PageId,
+        hash: u32,
+        key: &[u8],
+        record_id: RecordId,
+    ) -> Result<bool, HashIndexError> {
+        let mut bucket = Vec::new();
+        let mut page_id = page_id;
+        while page_id != PAGE_INVALID {
+            let page_ref = self.page_cache.get_page_mut(page_id)?;
+            let bucket_page = page_ref.hash_bucket_page();
+            if bucket_page.get(hash, key).is_some() {
+                return Err(HashIndexError::DuplicateKey(Key::from(key)));
+            }
+            page_id = bucket_page.overflow_page_id();
+            bucket.push(page_ref);
+        }
+
+        let Some(page_ref) = bucket
+            .iter_mut()
+            .find(|page_ref| page_ref.hash_bucket_page().fits(key))
+        else {
+            return Ok(false);
+        };
+        page_ref.hash_bucket_page_mut().push(hash, key, record_id);
+        self.page_cache.set_page_dirty(page_ref.metadata());
+
+        Ok(true)
+    }
+
+    /// Makes room in the full bucket of `hash`: splits it, doubling the
+    /// directory if needed, or chains it to a new overflow page once it's
+    /// `HASH_MAX_DEPTH` deep. The superblock is latched for writing.
+    fn grow_bucket(
+        &self,
+        superblock_ref: &mut PageRefMut<'_>,
+        page_id: PageId,
+        hash: u32,
+    ) -> Result<(), HashIndexError> {
+        let mut bucket_page_ref = self.page_cache.get_page_mut(page_id)?;
+        let local_depth = bucket_page_ref.hash_bucket_page().local_depth();
+
+        if local_depth == HASH_MAX_DEPTH {
+            while bucket_page_ref.hash_bucket_page().overflow_page_id() != PAGE_INVALID {
+                let page_id = bucket_page_ref.hash_bucket_page().overflow_page_id();
+                bucket_page_ref = self.page_cache.get_page_mut(page_id)?;
+            }
+            let mut overflow_page_ref = self.page_cache.new_page()?;
+            overflow_page_ref.hash_bucket_page_mut().init(local_depth);
+            bucket_page_ref
+                .hash_bucket_page_mut()
+                .set_overflow_page_id(overflow_page_ref.metadata().page_id());
+            self.page_cache.set_page_dirty(overflow_page_ref.metadata());
+            self.page_cache.set_page_dirty(bucket_page_ref.metadata());
+            return Ok(());
+        }
+
+        if local_depth == superblock_ref.hash_superblock().global_depth() {
+            self.double_directory(superblock_ref.hash_superblock_mut())?;
+        }
+
+        // The keys with the next bit of their hash set move to the new bucket.
+        let mut new_page_ref = self.page_cache.new_page()?;
+        let new_page_id = new_page_ref.metadata().page_id();
+        let new_page = new_page_ref.hash_bucket_page_mut();
+        new_page.init(local_depth + 1);
+        let bucket_page = bucket_page_ref.hash_bucket_page_mut();
+        bucket_page.set_local_depth(local_depth + 1);
+        let (moved, kept): (Vec<_>, Vec<_>) = bucket_page
+            .entries()
+            .into_iter()
+            .partition(|(hash, ..)| hash & (1 << local_depth) != 0);
+        bucket_page.set_entries(&kept);
+        new_page.set_entries(&moved);
+        self.page_cache.set_page_dirty(new_page_ref.metadata());
+        self.page_cache.set_page_dirty(bucket_page_ref.metadata());
+
+        // So do the slots of the directory.
+        let superblock = superblock_ref.hash_superblock();
+        let first_slot = (hash as usize & ((1 << local_depth) - 1)) | 1 << local_depth;
+        for slot in (first_slot..superblock.directory_size()).step_by(1 << (local_depth + 1)) {
+            self.set_bucket_page_id(superblock, slot, new_page_id)?;
+        }
+        self.page_cache.set_page_dirty(superblock_ref.metadata());
+
+        Ok(())
+    }
+
+    /// Doubles the directory, the new slots pointing to the same buckets as
+    /// the slots sharing their low bits. Directory pages are added once the
+    /// first one is full.
+    fn double_directory(&self, superblock: &mut HashSuperBlock) -> Result<(), HashIndexError> {
+        let size = superblock.directory_size();
+        if size < HASH_DIRECTORY_SIZE {
+            for slot in 0..size {
+                let page_id = self.bucket_page_id(superblock, slot)?;
+                self.set_bucket_page_id(superblock, size + slot, page_id)?;
+            }
+        } else {
+            let num_pages = size / HASH_DIRECTORY_SIZE;
+            for i in 0..num_pages {
+                let (page_id, _) = superblock.directory_page(i * HASH_DIRECTORY_SIZE);
+                let page_ref = self.page_cache.get_page(page_id)?;
+                let mut new_page_ref = self.page_cache.new_page()?;
+                new_page_ref.page_mut().data = page_ref.page().data;
+                self.page_cache.set_page_dirty(new_page_ref.metadata());
+                superblock.set_directory_page(num_pages + i, new_page_ref.metadata().page_id());
+            }
+        }
+        superblock.set_global_depth(superblock.global_depth() + 1);
+
+        Ok(())
+    }
+
+    /// Deletes a key.
+    ///
+    /// Returns a `HashIndexError::KeyNotFound` if the key isn't indexed.
+    pub fn delete(&self, key: &Key) -> Result<(), HashIndexError> {
+        let key = key.as_bytes();
+        let hash = hash_key(key);
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        let superblock = superblock_ref.hash_superblock();
+        let mut page_id = self.bucket_page_id(superblock, superblock.directory_slot(hash))?;
+        while page_id != PAGE_INVALID {
+            let mut page_ref = self.page_cache.get_page_mut(page_id)?;
+            let bucket_page = page_ref.hash_bucket_page_mut();
+            if bucket_page.remove(hash, key) {
+                self.page_cache.set_page_dirty(page_ref.metadata());
+                drop(page_ref);
+                drop(superblock_ref);
+                return self.count_key(false);
+            }
+            page_id = bucket_page.overflow_page_id();
+        }
+
+        Err(HashIndexError::KeyNotFound)
+    }
+
+    /// Counts a key inserted or deleted in the superblock, once the pages of
+    /// the buckets are released: the superblock is latched before them.
+    fn count_key(&self, inserted: bool) -> Result<(), HashIndexError> {
+        let mut superblock_ref = self.page_cache.get_page_mut(PAGE_RESERVED)?;
+        let superblock = superblock_ref.hash_superblock_mut();
+        match inserted {
+            true => superblock.increment_num_keys(),
+            false => superblock.decrement_num_keys(),
+        }
+        self.page_cache.set_page_dirty(superblock_ref.metadata());
+        Ok(())
+    }
+
+    /// Returns the number of keys, kept in the superblock: it may lag behind
+    /// concurrent inserts and deletes.
+    pub fn len(&self) -> Result<usize, HashIndexError> {
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        Ok(superblock_ref.hash_superblock().num_keys() as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, HashIndexError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Walks the directory and the buckets, counting their pages and keys.
+    pub fn stats(&self) -> Result<HashIndexStats, HashIndexError> {
+        let superblock_ref = self.page_cache.get_page(PAGE_RESERVED)?;
+        let superblock = superblock_ref.hash_superblock();
+        let size = superblock.directory_size();
+        let mut stats = HashIndexStats {
+            global_depth: superblock.global_depth(),
+            directory_pages: size.div_ceil(HASH_DIRECTORY_SIZE) as u64,
+            ..Default::default()
+        };
+
+        let mut buckets = HashSet::new();
+        for slot in 0..size {
+            let page_id = self.bucket_page_id(superblock, slot)?;
+            if !buckets.insert(page_id) {
+                continue;
+            }
+            stats.buckets += 1;
+            let mut page_id = page_id;
+            while page_id != PAGE_INVALID {
+                let page_ref = self.page_cache.get_page(page_id)?;
+                let bucket_page = page_ref.hash_bucket_page();
+                stats.keys += bucket_page.len() as u64;
+                page_id = bucket_page.overflow_page_id();
+                if page_id != PAGE_INVALID {
+                    stats.overflow_pages += 1;
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::PageCache;
+    use crate::pages::HeapPageSlotId;
+    use crate::storage::FileStorage;
+
+    use std::sync::Arc;
+
+    use tempfile::NamedTempFile;
+
+    const NR_KEYS: u32 = 10000;
+
+    fn create_index() -> HashIndex<FileStorage> {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        HashIndex::try_new(page_cache.cache_storage(storage)).unwrap()
+    }
+
+    fn make_record(i: u32) -> RecordId {
+        RecordId::new(PageId::new(i), HeapPageSlotId::new(i as u16))
+    }
+
+    #[test]
+    fn insert_search_delete() {
+        let index = create_index();
+        assert!(index.is_empty().unwrap());
+        for key in 0..NR_KEYS {
+            index.insert(&Key::new(key), make_record(key)).unwrap();
+        }
+        assert_eq!(index.len().unwrap(), NR_KEYS as usize);
+        for key in 0..NR_KEYS {
+            assert_eq!(
+                index.search(&Key::new(key)).unwrap(),
+                Some(make_record(key))
+            );
+        }
+        assert_eq!(index.search(&Key::new(NR_KEYS)).unwrap(), None);
+        assert!(matches!(
+            index.insert(&Key::new(42), make_record(0)),
+            Err(HashIndexError::DuplicateKey(key)) if key == Key::new(42)
+        ));
+
+        for key in (0..NR_KEYS).step_by(2) {
+            index.delete(&Key::new(key)).unwrap();
+        }
+        assert!(matches!(
+            index.delete(&Key::new(0)),
+            Err(HashIndexError::KeyNotFound)
+        ));
+        assert_eq!(index.len().unwrap(), NR_KEYS as usize / 2);
+        for key in 0..NR_KEYS {
+            let expected = (key % 2 == 1).then(|| make_record(key));
+            assert_eq!(index.search(&Key::new(key)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn key_too_large() {
+        let index = create_index();
+        let key = Key::from(vec![0; HASH_MAX_KEY_SIZE + 1]);
+        assert!(matches!(
+            index.insert(&key, make_record(0)),
+            Err(HashIndexError::KeyTooLarge(size)) if size == HASH_MAX_KEY_SIZE + 1
+        ));
+        let key = Key::from(vec![0; HASH_MAX_KEY_SIZE]);
+        index.insert(&key, make_record(0)).unwrap();
+    }
+
+    #[test]
+    fn directory_pages() {
+        // Large keys fill buckets quickly: the directory spans several pages.
+        let index = create_index();
+        let key = |i: u32| {
+            let mut key = vec![0; 400];
+            key[..4].copy_from_slice(&i.to_be_bytes());
+            Key::from(key)
+        };
+        for i in 0..NR_KEYS {
+            index.insert(&key(i), make_record(i)).unwrap();
+        }
+        for i in 0..NR_KEYS {
+            assert_eq!(index.search(&key(i)).unwrap(), Some(make_record(i)));
+        }
+
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.keys, NR_KEYS as u64);
+        assert!(stats.directory_pages > 1);
+        assert_eq!(
+            stats.directory_pages,
+            (1 << stats.global_depth) / HASH_DIRECTORY_SIZE as u64
+        );
+        assert!(stats.buckets > NR_KEYS as u64 / 9);
+        assert_eq!(stats.overflow_pages, 0);
+    }
+
+    #[test]
+    fn overflow_pages() {
+        // Keys sharing the low `HASH_MAX_DEPTH` bits of their hash end up in
+        // the same bucket, chained to overflow pages once it's full.
+        let index = create_index();
+        let mask = (1 << HASH_MAX_DEPTH) - 1;
+        let keys = (0u32..)
+            .filter(|i| hash_key(&i.to_be_bytes()) & mask == 0)
+            .map(Key::new)
+            .take(300)
+            .collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            index.insert(key, make_record(i as u32)).unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(index.search(key).unwrap(), Some(make_record(i as u32)));
+        }
+
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.global_depth, HASH_MAX_DEPTH);
+        assert_eq!(stats.overflow_pages, 1);
+        assert_eq!(stats.keys, keys.len() as u64);
+
+        // Keys are found in, and deleted from, overflow pages.
+        for key in &keys {
+            index.delete(key).unwrap();
+        }
+        assert!(index.is_empty().unwrap());
+        assert_eq!(index.stats().unwrap().keys, 0);
+    }
+
+    #[test]
+    fn concurrent_inserts() {
+        let index = Arc::new(create_index());
+        let threads = (0..4)
+            .map(|thread| {
+                let index = index.clone();
+                std::thread::spawn(move || {
+                    for key in (thread..NR_KEYS).step_by(4) {
+                        index.insert(&Key::new(key), make_record(key)).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(index.len().unwrap(), NR_KEYS as usize);
+        for key in 0..NR_KEYS {
+            assert_eq!(
+                index.search(&Key::new(key)).unwrap(),
+                Some(make_record(key))
+            );
+        }
+    }
+}
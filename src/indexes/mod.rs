@@ -1,3 +1,80 @@
 mod btree;
+mod fulltext;
+mod hash;
 
-pub use btree::{BTree, BTreeError};
+pub use btree::{
+    BTree, BTreeEntry, BTreeError, BTreeRangeIterator, BTreeRevRangeIterator,
+    BTreeStableRangeIterator, BTreeStats,
+};
+pub use fulltext::{FULLTEXT_MAX_TERM_SIZE, FullTextIndex, text_contains, tokenize};
+pub use hash::{HashIndex, HashIndexError, HashIndexStats};
+
+/// The data structure of an index, chosen when the index is created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexMethod {
+    /// Ordered keys: lookups and range scans.
+    #[default]
+    BTree,
+    /// Lookups of a key in a constant number of page reads, see `HashIndex`.
+    Hash,
+    /// The rows whose text has given terms, see `FullTextIndex`.
+    FullText,
+}
+
+impl IndexMethod {
+    /// The name of the method, as in `CREATE INDEX ... USING name`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexMethod::BTree => "BTREE",
+            IndexMethod::Hash => "HASH",
+            IndexMethod::FullText => "FULLTEXT",
+        }
+    }
+
+    /// Parses the name of a method, case insensitively.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [IndexMethod::BTree, IndexMethod::Hash, IndexMethod::FullText]
+            .into_iter()
+            .find(|method| method.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+/// A column of the key of an index, and the order of its values, as in
+/// `CREATE INDEX ... (name [ASC | DESC], ...)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyColumn {
+    pub name: String,
+    pub descending: bool,
+}
+
+impl KeyColumn {
+    pub fn ascending(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            descending: false,
+        }
+    }
+
+    pub fn descending(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            descending: true,
+        }
+    }
+
+    /// Parses `name ASC` or `name DESC`, see the `Display` implementation.
+    pub fn from_name(column: &str) -> Option<Self> {
+        match column.rsplit_once(' ')? {
+            (name, "ASC") => Some(Self::ascending(name)),
+            (name, "DESC") => Some(Self::descending(name)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for KeyColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let order = if self.descending { "DESC" } else { "ASC" };
+        write!(f, "{} {order}", self.name)
+    }
+}
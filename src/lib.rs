@@ -1,5 +1,6 @@
 pub mod cache;
 pub mod catalog;
+pub mod clock;
 pub mod config;
 pub mod indexes;
 pub mod pages;
@@ -0,0 +1,79 @@
+use crate::pages::{PAGE_SIZE, Page};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use zerocopy_derive::*;
+
+/// The number of bits of a bloom filter page.
+pub const BLOOM_PAGE_BITS: usize = PAGE_SIZE * 8;
+
+/// The bits of a bloom filter per key it's sized for: about 1% of false
+/// positives with `BLOOM_HASHES` bits per key.
+pub const BLOOM_BITS_PER_KEY: u64 = 10;
+
+/// The number of bits set per key.
+pub const BLOOM_HASHES: u64 = 7;
+
+/// The 64 bits FNV-1a hash of a key. Its halves are the two hashes the bits
+/// of the key are derived from.
+fn hash_key64(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The bits of a key in a bloom filter of `num_bits` bits, by double hashing.
+pub fn bloom_bits(key: &[u8], num_bits: usize) -> impl Iterator<Item = usize> {
+    let hash = hash_key64(key);
+    let (h1, h2) = (hash & 0xffff_ffff, hash >> 32 | 1);
+    (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits as u64) as usize)
+}
+
+/// A page of the bits of a bloom filter. Bits are only ever set, with the
+/// page read latched: the words are atomic, in the byte order of the host.
+#[derive(FromBytes, KnownLayout)]
+#[repr(C)]
+pub struct BloomFilterPage {
+    words: [AtomicU64; PAGE_SIZE / 8],
+}
+
+impl BloomFilterPage {
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|word| *word.get_mut() = 0);
+    }
+
+    pub fn set(&self, bit: usize) {
+        self.words[bit / 64].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+    }
+
+    pub fn get(&self, bit: usize) -> bool {
+        self.words[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+    }
+
+    /// The number of bits set.
+    pub fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.load(Ordering::Relaxed).count_ones() as usize)
+            .sum()
+    }
+}
+
+impl From<&Page> for &BloomFilterPage {
+    fn from(page: &Page) -> Self {
+        unsafe { &*(page.data.as_ptr() as *const BloomFilterPage) }
+    }
+}
+
+impl From<&mut Page> for &mut BloomFilterPage {
+    fn from(page: &mut Page) -> Self {
+        unsafe { &mut *(page.data.as_mut_ptr() as *mut BloomFilterPage) }
+    }
+}
+
+impl std::fmt::Display for BloomFilterPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "type: bloom filter")?;
+        writeln!(f, "bits_set: {}/{}", self.count_ones(), BLOOM_PAGE_BITS)
+    }
+}
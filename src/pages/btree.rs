@@ -1,14 +1,23 @@
-use crate::pages::{PAGE_INVALID, PAGE_SIZE, Page, PageId, RecordId};
+use crate::pages::{FILE_HEADER_SIZE, PAGE_INVALID, PAGE_SIZE, Page, PageId, RecordId};
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+use crate::sql::types::memcomparable::{self, DecodeError, KeyOptions};
+
+use std::marker::PhantomData;
 
 use thiserror::Error;
 use zerocopy::{
-    little_endian::{U16, U32},
+    little_endian::{U16, U64},
     *,
 };
 use zerocopy_derive::*;
 
-const BTREE_BRANCHING_FACTOR: usize = 341;
-const BTREE_NUM_KEYS: usize = BTREE_BRANCHING_FACTOR - 1;
+/// The largest key, along with the payload of its entry: split or merged pages
+/// always have room for a few of them.
+pub const BTREE_MAX_KEY_SIZE: usize = 512;
+
+const BTREE_PAGE_TYPE_INNER: u8 = 0;
+const BTREE_PAGE_TYPE_LEAF: u8 = 1;
 
 pub enum BTreePageType {
     Inner,
@@ -32,325 +41,958 @@ struct BTreePageHeader {
     // FromBytes trait doesn't support enum
     page_type: u8,
     num_keys: U16,
+    // Entries are added from the prefix down to this offset.
+    entries_start: U16,
+    // The bytes shared by all the keys, stored once at the end of the page.
+    prefix_size: U16,
+    // The next leaf of a leaf page, the leftmost child of an inner page.
+    link: PageId,
 }
 
+const BTREE_PAGE_DATA_SIZE: usize = PAGE_SIZE - size_of::<BTreePageHeader>();
+
+/// Pages but the root using fewer bytes are refilled from a sibling or merged
+/// with it. Pages split in two use more.
+const BTREE_MIN_USED: usize = BTREE_PAGE_DATA_SIZE / 3;
+
 pub fn btree_get_page_type(page: &Page) -> BTreePageType {
+    btree_try_get_page_type(page).unwrap()
+}
+
+/// Same as `btree_get_page_type` but returns `None` if the page type is unknown,
+/// e.g. when inspecting a corrupted page.
+pub fn btree_try_get_page_type(page: &Page) -> Option<BTreePageType> {
     let (header, _) = BTreePageHeader::ref_from_prefix(&page.data).unwrap();
 
     match header.page_type {
-        0 => BTreePageType::Inner,
-        1 => BTreePageType::Leaf,
-        _ => unreachable!(),
+        BTREE_PAGE_TYPE_INNER => Some(BTreePageType::Inner),
+        BTREE_PAGE_TYPE_LEAF => Some(BTreePageType::Leaf),
+        _ => None,
     }
 }
 
-#[derive(
-    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, FromBytes, IntoBytes, KnownLayout, Immutable,
-)]
-pub struct Key(U32);
+/// A B-tree key. Keys are byte strings compared byte by byte: values are
+/// encoded so that their bytes compare in the same order, see `Key::new` for
+/// integers or `sql::types::memcomparable` for SQL values and composite keys.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key(Vec<u8>);
 
 impl Key {
+    /// An integer key, stored in big-endian to compare as integers.
     pub fn new(key: u32) -> Self {
-        Self(U32::new(key))
+        Self(key.to_be_bytes().to_vec())
+    }
+
+    /// A key made of SQL values, in ascending order with the binary collation.
+    /// NULLs sort after all the other values.
+    pub fn from_values(values: &[Value]) -> Self {
+        Self(memcomparable::encode(values))
     }
 
+    /// A key made of SQL values, each encoded with its options: descending
+    /// order or collation.
+    ///
+    /// Panics if there isn't one option per value.
+    pub fn from_values_with(values: &[Value], options: &[KeyOptions]) -> Self {
+        assert_eq!(values.len(), options.len());
+        let mut key = Vec::new();
+        for (value, &options) in values.iter().zip(options) {
+            memcomparable::encode_value(value, options, &mut key);
+        }
+        Self(key)
+    }
+
+    /// Decodes the values of a key made with `Key::from_values` or
+    /// `Key::from_values_with`, given their types and options.
+    pub fn values(&self, columns: &[(DataType, KeyOptions)]) -> Result<Vec<Value>, DecodeError> {
+        memcomparable::decode(&self.0, columns)
+    }
+
+    /// The shortest key separating two adjacent keys `lhs < rhs`: the
+    /// shortest prefix of `rhs` greater than `lhs`. Inner pages only route
+    /// searches, their keys don't have to be keys of the leaves.
+    pub fn shortest_separator(lhs: &[u8], rhs: &[u8]) -> Self {
+        let size = common_prefix_size(lhs, rhs) + 1;
+        Self(rhs[..size.min(rhs.len())].to_vec())
+    }
+
+    /// The integer of a key made with `Key::new`.
+    ///
+    /// Panics if the key isn't 4 bytes long.
     pub fn get(&self) -> u32 {
-        self.0.get()
+        u32::from_be_bytes(self.0.as_slice().try_into().unwrap())
     }
 
-    pub fn set(&mut self, key: u32) {
-        self.0.set(key)
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
     }
 }
 
-#[derive(FromBytes, KnownLayout, Immutable)]
+impl From<Vec<u8>> for Key {
+    fn from(key: Vec<u8>) -> Self {
+        Self(key)
+    }
+}
+
+impl From<&[u8]> for Key {
+    fn from(key: &[u8]) -> Self {
+        Self(key.to_vec())
+    }
+}
+
+impl AsRef<[u8]> for Key {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_key(&self.0, f)
+    }
+}
+
+fn fmt_key(key: &[u8], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    key.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+}
+
+/// The largest bloom filter of a B-tree, in pages.
+pub const BTREE_MAX_BLOOM_PAGES: usize = 64;
+
+/// Stored in the reserved page, after the `FileHeader`.
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
 #[repr(C)]
 pub struct BTreeSuperBlock {
     pub root_page_id: PageId,
+    num_keys: U64,
+    // The pages of the bloom filter of the keys, if any, followed by
+    // PAGE_INVALID. See `BTree::create_bloom_filter`.
+    bloom_filter: [PageId; BTREE_MAX_BLOOM_PAGES],
+    // The `FreeListPage` of the pages freed by merges, reused by splits.
+    pub free_list_page_id: PageId,
 }
 
 impl BTreeSuperBlock {
-    pub fn init(&mut self, root_page_id: PageId) {
+    /// A tree without a bloom filter.
+    pub fn init(&mut self, root_page_id: PageId, num_keys: u64, free_list_page_id: PageId) {
         self.root_page_id = root_page_id;
+        self.num_keys.set(num_keys);
+        self.bloom_filter = [PAGE_INVALID; BTREE_MAX_BLOOM_PAGES];
+        self.free_list_page_id = free_list_page_id;
+    }
+
+    /// The pages of the bloom filter, empty if the tree has none.
+    pub fn bloom_filter(&self) -> &[PageId] {
+        let len = self
+            .bloom_filter
+            .iter()
+            .position(|&page_id| page_id == PAGE_INVALID)
+            .unwrap_or(BTREE_MAX_BLOOM_PAGES);
+        &self.bloom_filter[..len]
+    }
+
+    /// Replaces the pages of the bloom filter, at most `BTREE_MAX_BLOOM_PAGES`.
+    pub fn set_bloom_filter(&mut self, page_ids: &[PageId]) {
+        self.bloom_filter = [PAGE_INVALID; BTREE_MAX_BLOOM_PAGES];
+        self.bloom_filter[..page_ids.len()].copy_from_slice(page_ids);
+    }
+
+    pub fn num_keys(&self) -> u64 {
+        self.num_keys.get()
+    }
+
+    pub fn increment_num_keys(&mut self) {
+        self.num_keys += 1;
+    }
+
+    pub fn decrement_num_keys(&mut self) {
+        self.num_keys -= 1;
     }
 }
 
-impl From<&Page> for &BTreeSuperBlock {
-    fn from(page: &Page) -> Self {
-        unsafe { &*(page.data.as_ptr() as *const BTreeSuperBlock) }
+impl<'a> From<&'a Page> for &'a BTreeSuperBlock {
+    fn from(page: &'a Page) -> Self {
+        let (superblock, _) =
+            BTreeSuperBlock::ref_from_prefix(&page.data[FILE_HEADER_SIZE..]).unwrap();
+        superblock
     }
 }
 
-impl From<&mut Page> for &mut BTreeSuperBlock {
-    fn from(page: &mut Page) -> Self {
-        unsafe { &mut *(page.data.as_mut_ptr() as *mut BTreeSuperBlock) }
+impl<'a> From<&'a mut Page> for &'a mut BTreeSuperBlock {
+    fn from(page: &'a mut Page) -> Self {
+        let (superblock, _) =
+            BTreeSuperBlock::mut_from_prefix(&mut page.data[FILE_HEADER_SIZE..]).unwrap();
+        superblock
+    }
+}
+
+impl std::fmt::Display for BTreeSuperBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "type: superblock")?;
+        writeln!(f, "root_page_id: {}", self.root_page_id.get())?;
+        writeln!(f, "num_keys: {}", self.num_keys())?;
+        writeln!(f, "free_list_page_id: {}", self.free_list_page_id.get())?;
+        let bloom_filter = self.bloom_filter();
+        if !bloom_filter.is_empty() {
+            let pages = bloom_filter
+                .iter()
+                .map(|page_id| page_id.get())
+                .collect::<Vec<_>>();
+            writeln!(f, "bloom_filter: {pages:?}")?;
+        }
+        Ok(())
     }
 }
 
-const _: () = assert!(std::mem::size_of::<BTreeSuperBlock>() <= PAGE_SIZE);
+const _: () = assert!(std::mem::size_of::<BTreeSuperBlock>() <= PAGE_SIZE - FILE_HEADER_SIZE);
+
+/// Where the entry of a key is in the page.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct BTreeSlot {
+    offset: U16,
+    key_size: U16,
+    payload_size: U16,
+}
+
+impl BTreeSlot {
+    const SIZE: usize = size_of::<Self>();
+}
 
+/// A slotted B-tree page: the slots, sorted by key, grow from the start of
+/// the page and the entries they point to, the bytes of the key followed by
+/// the value and the payload, grow from the end. The value is a `RecordId` in
+/// leaf pages and the pointer on the right of the key in inner pages. The
+/// payload holds the values of the columns included in a covering index, it
+/// is empty in inner pages.
+///
+/// Keys are prefix compressed: the bytes shared by all the keys are stored
+/// once at the end of the page, the entries only hold the rest of the keys.
+/// The prefix is shortened when a key not starting with it is inserted and
+/// lengthened when the page is rewritten, e.g. compacted or split.
 #[derive(FromBytes, KnownLayout, Immutable)]
 #[repr(C)]
-pub struct BTreeInnerPage {
+pub struct BTreePage<V> {
     header: BTreePageHeader,
-    keys: [Key; BTREE_NUM_KEYS],
-    pointers: [PageId; BTREE_BRANCHING_FACTOR],
+    data: [u8; BTREE_PAGE_DATA_SIZE],
+    _value: PhantomData<V>,
+}
+
+pub type BTreeLeafPage = BTreePage<RecordId>;
+pub type BTreeInnerPage = BTreePage<PageId>;
+
+const _: () = assert!(std::mem::size_of::<BTreeLeafPage>() == PAGE_SIZE);
+const _: () = assert!(std::mem::size_of::<BTreeInnerPage>() == PAGE_SIZE);
+// Both sides of a split or a redistribution use half of the bytes give or
+// take an entry, see `split_point`: with entries up to a sixth of a page, they
+// fit and aren't underfull.
+const _: () = assert!(
+    6 * (BTreeSlot::SIZE + BTREE_MAX_KEY_SIZE + size_of::<RecordId>()) <= BTREE_PAGE_DATA_SIZE
+);
+
+/// The entries of a page, to be redistributed: their key, value and payload.
+type Entries<V> = Vec<(Vec<u8>, V, Vec<u8>)>;
+
+impl<V: FromBytes + IntoBytes + Immutable + Copy> BTreePage<V> {
+    /// The bytes taken by an entry whose suffix and payload are `size` bytes.
+    const fn entry_size(size: usize) -> usize {
+        BTreeSlot::SIZE + size + size_of::<V>()
+    }
+
+    fn reset(&mut self, page_type: u8, link: PageId) {
+        self.header = BTreePageHeader {
+            page_type,
+            num_keys: U16::new(0),
+            entries_start: U16::new(BTREE_PAGE_DATA_SIZE as u16),
+            prefix_size: U16::new(0),
+            link,
+        };
+    }
+
+    #[inline]
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.header.num_keys.get() as usize
+    }
+
+    fn slot(&self, pos: usize) -> BTreeSlot {
+        let offset = pos * BTreeSlot::SIZE;
+        BTreeSlot::read_from_bytes(&self.data[offset..offset + BTreeSlot::SIZE]).unwrap()
+    }
+
+    fn set_slot(&mut self, pos: usize, slot: BTreeSlot) {
+        let offset = pos * BTreeSlot::SIZE;
+        self.data[offset..offset + BTreeSlot::SIZE].copy_from_slice(slot.as_bytes());
+    }
+
+    /// The bytes shared by all the keys of the page.
+    #[inline]
+    pub fn prefix(&self) -> &[u8] {
+        &self.data[BTREE_PAGE_DATA_SIZE - self.header.prefix_size.get() as usize..]
+    }
+
+    /// The bytes of the key at `pos` after the prefix.
+    #[inline]
+    fn suffix_at(&self, pos: usize) -> &[u8] {
+        let slot = self.slot(pos);
+        let offset = slot.offset.get() as usize;
+        &self.data[offset..offset + slot.key_size.get() as usize]
+    }
+
+    pub fn key_at(&self, pos: usize) -> Key {
+        Key([self.prefix(), self.suffix_at(pos)].concat())
+    }
+
+    fn value(&self, pos: usize) -> V {
+        let slot = self.slot(pos);
+        let offset = slot.offset.get() as usize + slot.key_size.get() as usize;
+        V::read_from_bytes(&self.data[offset..offset + size_of::<V>()]).unwrap()
+    }
+
+    /// The payload of the entry at `pos`, after its value.
+    pub fn payload_at(&self, pos: usize) -> &[u8] {
+        let slot = self.slot(pos);
+        let offset = slot.offset.get() as usize + slot.key_size.get() as usize + size_of::<V>();
+        &self.data[offset..offset + slot.payload_size.get() as usize]
+    }
+
+    /// The keys of the page, in increasing order.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = Key> + ExactSizeIterator {
+        (0..self.len()).map(|pos| self.key_at(pos))
+    }
+
+    /// Compares the key at `pos` to `key`, without copying it.
+    fn cmp_at(&self, pos: usize, key: &[u8]) -> std::cmp::Ordering {
+        let prefix = self.prefix();
+        let (head, tail) = key.split_at(prefix.len().min(key.len()));
+        match prefix[..head.len()].cmp(head) {
+            // `key` is a prefix of the prefix.
+            std::cmp::Ordering::Equal if head.len() < prefix.len() => std::cmp::Ordering::Greater,
+            std::cmp::Ordering::Equal => self.suffix_at(pos).cmp(tail),
+            ordering => ordering,
+        }
+    }
+
+    /// Binary searches `key`, see `slice::binary_search`.
+    pub fn search(&self, key: &[u8]) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.cmp_at(mid, key) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(low)
+    }
+
+    /// The bytes the entries would take with a prefix of `prefix_size` bytes,
+    /// at most the size of the current one.
+    fn size_with_prefix(&self, prefix_size: usize) -> usize {
+        let growth = self.header.prefix_size.get() as usize - prefix_size;
+        (0..self.len())
+            .map(|pos| {
+                let slot = self.slot(pos);
+                Self::entry_size(
+                    slot.key_size.get() as usize + growth + slot.payload_size.get() as usize,
+                )
+            })
+            .sum()
+    }
+
+    /// The bytes taken by the prefix, the slots and the entries.
+    pub fn used(&self) -> usize {
+        let prefix_size = self.header.prefix_size.get() as usize;
+        prefix_size + self.size_with_prefix(prefix_size)
+    }
+
+    pub fn is_underfull(&self) -> bool {
+        self.used() < BTREE_MIN_USED
+    }
+
+    /// The fraction of the room of the page used, see `used`.
+    pub fn fill_factor(&self) -> f64 {
+        self.used() as f64 / BTREE_PAGE_DATA_SIZE as f64
+    }
+
+    /// Whether the page has room for `key` and a payload of `payload_size`
+    /// bytes, once compacted.
+    pub fn fits(&self, key: &[u8], payload_size: usize) -> bool {
+        let prefix = self.prefix();
+        let shared = common_prefix_size(prefix, key);
+        if shared == prefix.len() {
+            let slots_end = (self.len() + 1) * BTreeSlot::SIZE;
+            let entry_size = key.len() - shared + size_of::<V>() + payload_size;
+            if slots_end + entry_size <= self.header.entries_start.get() as usize {
+                return true;
+            }
+        }
+        shared + self.size_with_prefix(shared) + Self::entry_size(key.len() - shared + payload_size)
+            <= BTREE_PAGE_DATA_SIZE
+    }
+
+    /// Whether the page has room for any key, whatever its prefix.
+    pub fn fits_any(&self) -> bool {
+        self.size_with_prefix(0) + Self::entry_size(BTREE_MAX_KEY_SIZE) <= BTREE_PAGE_DATA_SIZE
+    }
+
+    /// Whether the page has room for `key` and a payload of `payload_size`
+    /// bytes, compacting it if it hasn't: the prefix shared by its keys may
+    /// have grown since it was last rewritten.
+    pub fn make_room(&mut self, key: &[u8], payload_size: usize) -> bool {
+        self.fits(key, payload_size) || {
+            self.compact();
+            self.fits(key, payload_size)
+        }
+    }
+
+    /// Appends an entry after the last one, when bulk loading. The page must
+    /// have room for it, see `fits`.
+    pub fn push(&mut self, key: &[u8], value: V, payload: &[u8]) {
+        debug_assert!(self.len() == 0 || self.cmp_at(self.len() - 1, key).is_lt());
+        self.insert_at(self.len(), key, value, payload);
+    }
+
+    /// Inserts an entry at `pos`, the page must have room for it. The page is
+    /// rewritten if the key doesn't start with the prefix or if there is no
+    /// room left between the slots and the entries.
+    fn insert_at(&mut self, pos: usize, key: &[u8], value: V, payload: &[u8]) {
+        let prefix_size = self.header.prefix_size.get() as usize;
+        let slots_end = (self.len() + 1) * BTreeSlot::SIZE;
+        let entry_size = key.len().saturating_sub(prefix_size) + size_of::<V>() + payload.len();
+        if key.starts_with(self.prefix())
+            && slots_end + entry_size <= self.header.entries_start.get() as usize
+        {
+            self.insert_suffix_at(pos, &key[prefix_size..], value, payload);
+        } else {
+            let mut entries = self.entries();
+            entries.insert(pos, (key.to_vec(), value, payload.to_vec()));
+            self.set_entries(&entries);
+        }
+    }
+
+    /// Inserts an entry whose key is `suffix` after the prefix, there must be
+    /// room for it between the slots and the entries.
+    fn insert_suffix_at(&mut self, pos: usize, suffix: &[u8], value: V, payload: &[u8]) {
+        let num_keys = self.len();
+        let entry_size = suffix.len() + size_of::<V>() + payload.len();
+        let offset = self.header.entries_start.get() as usize - entry_size;
+        let value_offset = offset + suffix.len();
+        let payload_offset = value_offset + size_of::<V>();
+        self.data[offset..value_offset].copy_from_slice(suffix);
+        self.data[value_offset..payload_offset].copy_from_slice(value.as_bytes());
+        self.data[payload_offset..offset + entry_size].copy_from_slice(payload);
+        self.header.entries_start.set(offset as u16);
+
+        self.data.copy_within(
+            pos * BTreeSlot::SIZE..num_keys * BTreeSlot::SIZE,
+            (pos + 1) * BTreeSlot::SIZE,
+        );
+        let slot = BTreeSlot {
+            offset: U16::new(offset as u16),
+            key_size: U16::new(suffix.len() as u16),
+            payload_size: U16::new(payload.len() as u16),
+        };
+        self.set_slot(pos, slot);
+        self.header.num_keys += 1;
+    }
+
+    /// Removes the entry at `pos`, its bytes are reclaimed by `compact`.
+    fn remove_at(&mut self, pos: usize) {
+        let num_keys = self.len();
+        self.data.copy_within(
+            (pos + 1) * BTreeSlot::SIZE..num_keys * BTreeSlot::SIZE,
+            pos * BTreeSlot::SIZE,
+        );
+        self.header.num_keys -= 1;
+    }
+
+    /// Moves the entries to the end of the page, leaving no hole between them,
+    /// with the longest prefix shared by the keys.
+    pub fn compact(&mut self) {
+        let entries = self.entries();
+        self.set_entries(&entries);
+    }
+
+    fn entries(&self) -> Entries<V> {
+        (0..self.len())
+            .map(|pos| {
+                (
+                    self.key_at(pos).0,
+                    self.value(pos),
+                    self.payload_at(pos).to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    /// Rewrites the page with `entries`, sorted by key: the prefix is the
+    /// one shared by the first and the last keys, hence by all of them.
+    fn set_entries(&mut self, entries: &[(Vec<u8>, V, Vec<u8>)]) {
+        let prefix_size = match (entries.first(), entries.last()) {
+            (Some((first, ..)), Some((last, ..))) => common_prefix_size(first, last),
+            _ => 0,
+        };
+        let prefix_start = BTREE_PAGE_DATA_SIZE - prefix_size;
+        if let Some((first, ..)) = entries.first() {
+            self.data[prefix_start..].copy_from_slice(&first[..prefix_size]);
+        }
+        self.header.num_keys.set(0);
+        self.header.prefix_size.set(prefix_size as u16);
+        self.header.entries_start.set(prefix_start as u16);
+        for (pos, (key, value, payload)) in entries.iter().enumerate() {
+            self.insert_suffix_at(pos, &key[prefix_size..], *value, payload);
+        }
+    }
+
+    /// Checks that the slot array fits in the page and that the entries lie
+    /// between it and the prefix without overlapping. Returns the first slot
+    /// that doesn't.
+    pub fn check_slots(&self) -> Result<(), u16> {
+        let num_keys = self.len();
+        let slots_end = num_keys * BTreeSlot::SIZE;
+        if slots_end > BTREE_PAGE_DATA_SIZE {
+            return Err((BTREE_PAGE_DATA_SIZE / BTreeSlot::SIZE) as u16);
+        }
+        let Some(entries_end) =
+            BTREE_PAGE_DATA_SIZE.checked_sub(self.header.prefix_size.get() as usize)
+        else {
+            return Err(0);
+        };
+
+        let mut entries = (0..num_keys)
+            .map(|pos| {
+                let slot = self.slot(pos);
+                let offset = slot.offset.get() as usize;
+                let size = slot.key_size.get() as usize
+                    + size_of::<V>()
+                    + slot.payload_size.get() as usize;
+                (offset..offset + size, pos)
+            })
+            .collect::<Vec<_>>();
+        if let Some((_, pos)) = entries
+            .iter()
+            .find(|(entry, _)| entry.start < slots_end || entry.end > entries_end)
+        {
+            return Err(*pos as u16);
+        }
+        entries.sort_by_key(|(entry, _)| entry.start);
+        match entries.windows(2).find(|w| w[0].0.end > w[1].0.start) {
+            Some(w) => Err(w[1].1 as u16),
+            None => Ok(()),
+        }
+    }
+
+    /// The prefix, `None` if it's larger than the page, e.g. when inspecting a
+    /// corrupted page.
+    fn try_prefix(&self) -> Option<&[u8]> {
+        let prefix_size = self.header.prefix_size.get() as usize;
+        self.data
+            .get(BTREE_PAGE_DATA_SIZE.checked_sub(prefix_size)?..)
+    }
+
+    /// The suffix of the key, the value and the payload at `pos`, `None` if
+    /// the slot points out of the page.
+    fn try_entry(&self, pos: usize) -> Option<(&[u8], V, &[u8])> {
+        if (pos + 1) * BTreeSlot::SIZE > BTREE_PAGE_DATA_SIZE {
+            return None;
+        }
+        let slot = self.slot(pos);
+        let offset = slot.offset.get() as usize;
+        let value_offset = offset + slot.key_size.get() as usize;
+        let payload_offset = value_offset + size_of::<V>();
+        let value = self.data.get(value_offset..payload_offset)?;
+        let payload = self
+            .data
+            .get(payload_offset..payload_offset + slot.payload_size.get() as usize)?;
+        Some((
+            &self.data[offset..value_offset],
+            V::read_from_bytes(value).ok()?,
+            payload,
+        ))
+    }
+
+    /// Writes the prefix of the page, if any, for `Display`.
+    fn fmt_prefix(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.try_prefix() {
+            Some([]) => Ok(()),
+            Some(prefix) => {
+                write!(f, "prefix: ")?;
+                fmt_key(prefix, f)?;
+                writeln!(f)
+            }
+            None => writeln!(f, "prefix: invalid size"),
+        }
+    }
+}
+
+/// The number of bytes at the start of both `lhs` and `rhs`.
+fn common_prefix_size(lhs: &[u8], rhs: &[u8]) -> usize {
+    lhs.iter().zip(rhs).take_while(|(a, b)| a == b).count()
 }
 
-const _: () = assert!(std::mem::size_of::<BTreeInnerPage>() <= PAGE_SIZE);
+/// The number of entries to leave on the left of a split, so that both sides
+/// use about as many bytes: at least one entry is left on each side.
+fn split_point<V>(entries: &[(Vec<u8>, V, Vec<u8>)]) -> usize {
+    let entry_size = |(key, _, payload): &(Vec<u8>, V, Vec<u8>)| {
+        BTreeSlot::SIZE + key.len() + size_of::<V>() + payload.len()
+    };
+    let total = entries.iter().map(entry_size).sum::<usize>();
+    let mut used = 0;
+    let pos = entries
+        .iter()
+        .position(|entry| {
+            used += entry_size(entry);
+            2 * used >= total
+        })
+        .unwrap_or(0);
+    (pos + 1).clamp(1, entries.len() - 1)
+}
 
 pub struct SplitInner<'page> {
     lhs: &'page mut BTreeInnerPage,
+    pos: usize,
 }
 
 impl SplitInner<'_> {
-    pub fn split(&mut self, rhs: &mut BTreeInnerPage, key: Key, right_pointer: PageId) -> Key {
-        let lhs_num_keys = self.lhs.header.num_keys.get() as usize;
-        let split_at = lhs_num_keys.div_ceil(2) - 1;
-        let rhs_num_keys = lhs_num_keys - split_at;
-        // FIXME: optimize: insert key before copying
-        rhs.keys[..rhs_num_keys - 1].copy_from_slice(&self.lhs.keys[split_at + 1..]);
-        rhs.pointers[..rhs_num_keys].copy_from_slice(&self.lhs.pointers[split_at + 1..]);
-
-        self.lhs.header.num_keys.set(split_at as u16);
-        rhs.header.num_keys.set((rhs_num_keys - 1) as u16);
-
-        let split_key = self.lhs.keys[split_at];
-        if key > split_key {
-            rhs.insert(key, right_pointer);
-        } else if key < split_key {
-            self.lhs.insert(key, right_pointer);
-        } else {
-            unreachable!();
-        }
-
-        split_key
+    /// Moves half of the bytes of the page, with the key being inserted, to
+    /// `rhs`. Returns the key between them, which goes up to the parent.
+    pub fn split(&mut self, rhs: &mut BTreeInnerPage, key: &[u8], right_pointer: PageId) -> Key {
+        let mut entries = self.lhs.entries();
+        entries.insert(self.pos, (key.to_vec(), right_pointer, Vec::new()));
+        let split_at = split_point(&entries);
+
+        self.lhs.set_entries(&entries[..split_at]);
+        let (split_key, left_pointer, _) = &entries[split_at];
+        rhs.init_header();
+        rhs.header.link = *left_pointer;
+        rhs.set_entries(&entries[split_at + 1..]);
+
+        Key(split_key.clone())
     }
 }
 
 impl BTreeInnerPage {
+    /// The pointer on the left of the key at `pos`, or on the right of the
+    /// last key for `len()`.
     #[inline]
-    pub fn keys(&self) -> &[Key] {
-        let num_keys = self.header.num_keys.get() as usize;
-        &self.keys[..num_keys]
+    pub fn pointer(&self, pos: usize) -> PageId {
+        if pos == 0 {
+            self.header.link
+        } else {
+            self.value(pos - 1)
+        }
     }
 
-    #[inline]
-    pub fn pointers(&self) -> &[PageId] {
-        let num_keys = self.header.num_keys.get() as usize;
-        &self.pointers[..num_keys + 1]
+    pub fn pointers(&self) -> impl DoubleEndedIterator<Item = PageId> {
+        (0..=self.len()).map(|pos| self.pointer(pos))
     }
 
-    pub fn get(&self, key: Key) -> PageId {
-        match self.keys().binary_search(&key) {
-            Ok(pos) => self.pointers[pos + 1],
-            Err(pos) => self.pointers[pos],
-        }
+    pub fn get(&self, key: &[u8]) -> PageId {
+        self.pointer(self.position(key))
     }
 
-    pub fn init(&mut self, key: Key, left_pointer: PageId, right_pointer: PageId) {
-        self.header = BTreePageHeader {
-            page_type: 0,
-            num_keys: U16::new(1),
-        };
+    /// The position of the pointer to follow for `key`.
+    pub fn position(&self, key: &[u8]) -> usize {
+        match self.search(key) {
+            Ok(pos) => pos + 1,
+            Err(pos) => pos,
+        }
+    }
 
-        self.keys[0] = key;
-        self.pointers[0] = left_pointer;
-        self.pointers[1] = right_pointer;
+    pub fn init(&mut self, key: &[u8], left_pointer: PageId, right_pointer: PageId) {
+        self.init_header();
+        self.header.link = left_pointer;
+        self.insert_at(0, key, right_pointer, &[]);
     }
 
     pub fn init_header(&mut self) {
-        self.header = BTreePageHeader {
-            page_type: 0,
-            num_keys: U16::new(0),
-        };
+        self.reset(BTREE_PAGE_TYPE_INNER, PAGE_INVALID);
+    }
+
+    /// An inner page with a single pointer, keys are then appended with
+    /// `push`.
+    pub fn init_pointer(&mut self, pointer: PageId) {
+        self.reset(BTREE_PAGE_TYPE_INNER, pointer);
     }
 
-    pub fn insert(&mut self, key: Key, right_pointer: PageId) -> Option<SplitInner<'_>> {
-        match self.keys().binary_search(&key) {
+    pub fn insert(&mut self, key: &[u8], right_pointer: PageId) -> Option<SplitInner<'_>> {
+        match self.search(key) {
             Ok(_) => {
-                unimplemented!("duplicate keys");
+                unreachable!("separators are unique");
             }
             Err(pos) => {
-                let num_keys = self.header.num_keys.get() as usize;
-                if num_keys < BTREE_NUM_KEYS {
-                    self.keys.copy_within(pos..num_keys, pos + 1);
-                    self.keys[pos] = key;
-                    self.pointers.copy_within(pos + 1..num_keys + 1, pos + 2);
-                    self.pointers[pos + 1] = right_pointer;
-                    self.header.num_keys += 1;
+                if self.make_room(key, 0) {
+                    self.insert_at(pos, key, right_pointer, &[]);
                     None
                 } else {
-                    Some(SplitInner { lhs: self })
+                    Some(SplitInner { lhs: self, pos })
                 }
             }
         }
     }
 
-    pub fn delete(&mut self, key: Key) -> Result<(), BTreePageError> {
-        let pos = self
-            .keys()
-            .binary_search(&key)
-            .map_err(|_| BTreePageError::KeyNotFound)?;
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), BTreePageError> {
+        let pos = self.search(key).map_err(|_| BTreePageError::KeyNotFound)?;
+        self.remove(pos);
 
-        let num_keys = self.header.num_keys.get() as usize;
-        self.keys.copy_within(pos + 1..num_keys - 1, pos);
-        self.pointers.copy_within(pos..num_keys, pos + 1);
-        self.header.num_keys -= 1;
+        Ok(())
+    }
+
+    /// Removes the key at `pos` and the pointer on its right.
+    pub fn remove(&mut self, pos: usize) {
+        self.remove_at(pos);
+    }
+
+    /// Replaces the key at `pos`, the page must have room for it, see `fits`.
+    pub fn set_key(&mut self, pos: usize, key: &[u8]) {
+        let right_pointer = self.value(pos);
+        self.remove_at(pos);
+        self.insert_at(pos, key, right_pointer, &[]);
+    }
+
+    /// Whether `merge` has room for `separator` and the keys of `rhs`.
+    pub fn can_merge(&self, separator: &[u8], rhs: &BTreeInnerPage) -> bool {
+        // A prefix of all the keys, the merged page may share more.
+        let shared = common_prefix_size(self.prefix(), rhs.prefix());
+        let shared = common_prefix_size(&self.prefix()[..shared], separator);
+        shared
+            + self.size_with_prefix(shared)
+            + Self::entry_size(separator.len() - shared)
+            + rhs.size_with_prefix(shared)
+            <= BTREE_PAGE_DATA_SIZE
+    }
+
+    /// Appends `separator`, the key between this page and `rhs` in their
+    /// parent, and the keys and pointers of `rhs`.
+    pub fn merge(&mut self, separator: &[u8], rhs: &BTreeInnerPage) {
+        assert!(self.can_merge(separator, rhs));
+
+        let mut entries = self.entries();
+        entries.push((separator.to_vec(), rhs.header.link, Vec::new()));
+        entries.extend(rhs.entries());
+        self.set_entries(&entries);
+    }
+
+    /// Moves keys and pointers between this page and `rhs` through
+    /// `separator`, the key between them in their parent, until they use
+    /// about as many bytes.
+    ///
+    /// Returns the new separator.
+    pub fn redistribute(&mut self, separator: &[u8], rhs: &mut BTreeInnerPage) -> Key {
+        let mut entries = self.entries();
+        entries.push((separator.to_vec(), rhs.header.link, Vec::new()));
+        entries.extend(rhs.entries());
+        let split_at = split_point(&entries);
+
+        self.set_entries(&entries[..split_at]);
+        let (separator, left_pointer, _) = &entries[split_at];
+        rhs.header.link = *left_pointer;
+        rhs.set_entries(&entries[split_at + 1..]);
+
+        Key(separator.clone())
+    }
+}
+
+impl std::fmt::Display for BTreeInnerPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // num_keys isn't trusted, the page may be corrupted.
+        writeln!(f, "type: inner")?;
+        writeln!(f, "num_keys: {}", self.header.num_keys.get())?;
+        self.fmt_prefix(f)?;
+        writeln!(f, "pointer[0]: {}", self.header.link.get())?;
+        for i in 0..self.len() {
+            let Some((key, pointer, _)) = self.try_entry(i) else {
+                writeln!(f, "key[{i}]: invalid slot")?;
+                break;
+            };
+            write!(f, "key[{i}]: ")?;
+            fmt_key(self.try_prefix().unwrap_or_default(), f)?;
+            fmt_key(key, f)?;
+            writeln!(f, " pointer[{}]: {}", i + 1, pointer.get())?;
+        }
 
         Ok(())
     }
 }
 
-impl From<&Page> for &BTreeInnerPage {
+impl<V> From<&Page> for &BTreePage<V> {
     fn from(page: &Page) -> Self {
-        unsafe { &*(page.data.as_ptr() as *const BTreeInnerPage) }
+        unsafe { &*(page.data.as_ptr() as *const BTreePage<V>) }
     }
 }
 
-impl From<&mut Page> for &mut BTreeInnerPage {
+impl<V> From<&mut Page> for &mut BTreePage<V> {
     fn from(page: &mut Page) -> Self {
-        unsafe { &mut *(page.data.as_mut_ptr() as *mut BTreeInnerPage) }
+        unsafe { &mut *(page.data.as_mut_ptr() as *mut BTreePage<V>) }
     }
 }
 
-#[derive(FromBytes, KnownLayout, Immutable)]
-#[repr(C)]
-pub struct BTreeLeafPage {
-    header: BTreePageHeader,
-    keys: [Key; BTREE_NUM_KEYS],
-    values: [RecordId; BTREE_NUM_KEYS],
-    next: PageId,
-}
-
-const _: () = assert!(std::mem::size_of::<BTreeLeafPage>() <= PAGE_SIZE);
-
 #[derive(Error, Debug)]
 pub enum BTreePageError {
     #[error("key not found")]
     KeyNotFound,
+    #[error("duplicate key")]
+    DuplicateKey,
+    #[error("key of {0} bytes, at most {max} are supported", max = BTREE_MAX_KEY_SIZE)]
+    KeyTooLarge(usize),
 }
 
 pub struct SplitLeaf<'page> {
     lhs: &'page mut BTreeLeafPage,
+    pos: usize,
 }
 
 impl SplitLeaf<'_> {
-    pub fn split(&mut self, rhs: &mut BTreeLeafPage, key: Key, value: RecordId) -> Key {
-        let lhs_num_keys = self.lhs.header.num_keys.get() as usize;
-        let split_at = lhs_num_keys.div_ceil(2);
-        let rhs_num_keys = lhs_num_keys - split_at;
-        let median_key = self.lhs.keys[split_at];
-        // FIXME: optimize: insert key before copying
-        rhs.keys[..rhs_num_keys].copy_from_slice(&self.lhs.keys[split_at..]);
-        rhs.values[..rhs_num_keys].copy_from_slice(&self.lhs.values[split_at..]);
-
-        self.lhs.header.num_keys.set(split_at as u16);
-        rhs.header.num_keys.set(rhs_num_keys as u16);
-
-        if key < median_key {
-            self.lhs.insert(key, value);
-        } else if key > median_key {
-            rhs.insert(key, value);
-        } else {
-            unreachable!();
-        }
-
-        rhs.keys().first().copied().unwrap()
+    /// Moves half of the bytes of the leaf, with the entry being inserted, to
+    /// `rhs`. Returns the separator between them, see `Key::shortest_separator`.
+    pub fn split(
+        &mut self,
+        rhs: &mut BTreeLeafPage,
+        key: &[u8],
+        value: RecordId,
+        payload: &[u8],
+    ) -> Key {
+        let mut entries = self.lhs.entries();
+        entries.insert(self.pos, (key.to_vec(), value, payload.to_vec()));
+        let split_at = split_point(&entries);
+
+        self.lhs.set_entries(&entries[..split_at]);
+        rhs.set_entries(&entries[split_at..]);
+
+        Key::shortest_separator(&entries[split_at - 1].0, &entries[split_at].0)
     }
 }
 
 impl BTreeLeafPage {
-    #[inline]
-    #[allow(clippy::len_without_is_empty)]
-    pub fn len(&self) -> usize {
-        self.header.num_keys.get() as usize
-    }
-
-    #[inline]
-    pub fn keys(&self) -> &[Key] {
-        let num_keys = self.header.num_keys.get() as usize;
-        &self.keys[..num_keys]
-    }
-
-    #[inline]
-    pub fn key_at(&self, pos: usize) -> Key {
-        self.keys[pos]
-    }
-
     #[inline]
     pub fn value_at(&self, pos: usize) -> RecordId {
-        self.values[pos]
+        self.value(pos)
     }
 
     #[inline]
     pub fn next_page_id(&self) -> PageId {
-        self.next
+        self.header.link
     }
 
     #[inline]
     pub fn set_next_page_id(&mut self, page_id: PageId) {
-        self.next = page_id;
+        self.header.link = page_id;
     }
 
-    pub fn get(&self, key: Key) -> Option<RecordId> {
-        let pos = self.keys().binary_search(&key).ok()?;
-        Some(self.values[pos])
+    pub fn get(&self, key: &[u8]) -> Option<RecordId> {
+        let pos = self.search(key).ok()?;
+        Some(self.value(pos))
     }
 
     pub fn init(&mut self) {
-        self.header = BTreePageHeader {
-            page_type: 1,
-            num_keys: U16::new(0),
-        };
-        self.next = PAGE_INVALID;
+        self.reset(BTREE_PAGE_TYPE_LEAF, PAGE_INVALID);
     }
 
-    pub fn insert(&mut self, key: Key, value: RecordId) -> Option<SplitLeaf<'_>> {
-        match self.keys().binary_search(&key) {
-            Ok(_) => {
-                unimplemented!("duplicate keys");
-            }
+    /// Inserts `key` with its payload, or returns a `SplitLeaf` to move half
+    /// of the keys to a new leaf if this one is full.
+    pub fn insert(
+        &mut self,
+        key: &[u8],
+        value: RecordId,
+        payload: &[u8],
+    ) -> Result<Option<SplitLeaf<'_>>, BTreePageError> {
+        if key.len() + payload.len() > BTREE_MAX_KEY_SIZE {
+            return Err(BTreePageError::KeyTooLarge(key.len() + payload.len()));
+        }
+
+        match self.search(key) {
+            Ok(_) => Err(BTreePageError::DuplicateKey),
             Err(pos) => {
-                let num_keys = self.header.num_keys.get() as usize;
-                if num_keys < BTREE_NUM_KEYS {
-                    self.keys.copy_within(pos..num_keys, pos + 1);
-                    self.keys[pos] = key;
-                    self.values.copy_within(pos..num_keys, pos + 1);
-                    self.values[pos] = value;
-                    self.header.num_keys += 1;
-                    None
+                if self.make_room(key, payload.len()) {
+                    self.insert_at(pos, key, value, payload);
+                    Ok(None)
                 } else {
-                    Some(SplitLeaf { lhs: self })
+                    Ok(Some(SplitLeaf { lhs: self, pos }))
                 }
             }
         }
     }
 
-    pub fn delete(&mut self, key: Key) -> Result<(), BTreePageError> {
-        let num_keys = self.header.num_keys.get() as usize;
-        let pos = self
-            .keys()
-            .binary_search(&key)
-            .map_err(|_| BTreePageError::KeyNotFound)?;
-
-        self.keys.copy_within(pos + 1..num_keys, pos);
-        self.values.copy_within(pos + 1..num_keys, pos);
-        self.header.num_keys -= 1;
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), BTreePageError> {
+        let pos = self.search(key).map_err(|_| BTreePageError::KeyNotFound)?;
+        self.remove_at(pos);
 
         Ok(())
     }
-}
 
-impl From<&Page> for &BTreeLeafPage {
-    fn from(page: &Page) -> Self {
-        unsafe { &*(page.data.as_ptr() as *const BTreeLeafPage) }
+    /// Whether the leaf would be underfull without the key at `pos`.
+    pub fn is_underfull_without(&self, pos: usize) -> bool {
+        let slot = self.slot(pos);
+        let size = slot.key_size.get() as usize + slot.payload_size.get() as usize;
+        self.used() - Self::entry_size(size) < BTREE_MIN_USED
+    }
+
+    /// Whether `merge` has room for the keys of `rhs`.
+    pub fn can_merge(&self, rhs: &BTreeLeafPage) -> bool {
+        // A prefix of all the keys, the merged leaf may share more.
+        let shared = common_prefix_size(self.prefix(), rhs.prefix());
+        shared + self.size_with_prefix(shared) + rhs.size_with_prefix(shared)
+            <= BTREE_PAGE_DATA_SIZE
+    }
+
+    /// Appends the keys of `rhs`, the next leaf, which is then unlinked.
+    pub fn merge(&mut self, rhs: &BTreeLeafPage) {
+        assert!(self.can_merge(rhs));
+
+        let mut entries = self.entries();
+        entries.extend(rhs.entries());
+        self.set_entries(&entries);
+        self.header.link = rhs.header.link;
+    }
+
+    /// Moves keys between this leaf and `rhs`, the next one, until they use
+    /// about as many bytes.
+    ///
+    /// Returns the new separator between them, see `Key::shortest_separator`.
+    pub fn redistribute(&mut self, rhs: &mut BTreeLeafPage) -> Key {
+        let mut entries = self.entries();
+        entries.extend(rhs.entries());
+        let split_at = split_point(&entries);
+
+        self.set_entries(&entries[..split_at]);
+        rhs.set_entries(&entries[split_at..]);
+
+        Key::shortest_separator(&entries[split_at - 1].0, &entries[split_at].0)
     }
 }
 
-impl From<&mut Page> for &mut BTreeLeafPage {
-    fn from(page: &mut Page) -> Self {
-        unsafe { &mut *(page.data.as_mut_ptr() as *mut BTreeLeafPage) }
+impl std::fmt::Display for BTreeLeafPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // num_keys isn't trusted, the page may be corrupted.
+        writeln!(f, "type: leaf")?;
+        writeln!(f, "num_keys: {}", self.header.num_keys.get())?;
+        self.fmt_prefix(f)?;
+        writeln!(f, "next: {}", self.header.link.get())?;
+        for i in 0..self.len() {
+            let Some((key, value, payload)) = self.try_entry(i) else {
+                writeln!(f, "key[{i}]: invalid slot")?;
+                break;
+            };
+            write!(f, "key[{i}]: ")?;
+            fmt_key(self.try_prefix().unwrap_or_default(), f)?;
+            fmt_key(key, f)?;
+            write!(
+                f,
+                " record: ({}, {})",
+                value.page_id.get(),
+                value.slot_id.get()
+            )?;
+            if !payload.is_empty() {
+                write!(f, " payload: ")?;
+                fmt_key(payload, f)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -360,8 +1002,6 @@ mod tests {
 
     use crate::pages::HeapPageSlotId;
 
-    use zerocopy::little_endian::U16;
-
     fn make_record() -> RecordId {
         RecordId {
             page_id: PageId::new(0),
@@ -369,43 +1009,125 @@ mod tests {
         }
     }
 
-    #[cfg(test)]
-    impl Default for BTreeLeafPage {
-        fn default() -> Self {
-            Self {
-                header: BTreePageHeader {
-                    page_type: BTreePageType::Leaf as u8,
-                    num_keys: U16::new(0),
-                },
-                keys: [Key::new(0); BTREE_NUM_KEYS],
-                values: [make_record(); BTREE_NUM_KEYS],
-                next: PageId::new(0),
-            }
-        }
+    fn key(key: u32) -> Vec<u8> {
+        Key::new(key).as_bytes().to_vec()
+    }
+
+    fn keys<V: FromBytes + IntoBytes + Immutable + Copy>(page: &BTreePage<V>) -> Vec<u32> {
+        page.keys().map(|key| key.get()).collect()
+    }
+
+    fn leaf_page(page: &mut Page) -> &mut BTreeLeafPage {
+        let leaf = <&mut BTreeLeafPage>::from(page);
+        leaf.init();
+        leaf
+    }
+
+    fn inner_page(page: &mut Page) -> &mut BTreeInnerPage {
+        let inner = <&mut BTreeInnerPage>::from(page);
+        inner.init_header();
+        inner
     }
 
     #[test]
     fn test_leaf_page_basic() {
-        let mut leaf = BTreeLeafPage::default();
-        for key in 0..BTREE_NUM_KEYS {
-            let _ = leaf.insert(Key::new(key as u32), make_record());
+        let mut page = Page::new();
+        let leaf = leaf_page(&mut page);
+        let mut num_keys = 0;
+        while let Ok(None) = leaf.insert(&key(num_keys), make_record(), &[]) {
+            num_keys += 1;
         }
-        assert_eq!(leaf.keys().len(), BTREE_NUM_KEYS);
+        assert_eq!(leaf.len(), num_keys as usize);
         assert!(leaf.keys().is_sorted());
+        assert!(leaf.check_slots().is_ok());
 
-        let key = Key::new((BTREE_NUM_KEYS / 2) as u32);
-        assert!(leaf.get(key).is_some());
-        let _ = leaf.delete(key);
-        assert!(leaf.get(key).is_none());
+        let key = key(num_keys / 2);
+        assert!(leaf.get(&key).is_some());
+        let _ = leaf.delete(&key);
+        assert!(leaf.get(&key).is_none());
         assert!(leaf.keys().is_sorted());
+
+        // The hole left is reused.
+        assert!(matches!(leaf.insert(&key, make_record(), &[]), Ok(None)));
+        assert!(leaf.check_slots().is_ok());
+    }
+
+    #[test]
+    fn test_insert_leaf_page_variable_length() {
+        let mut page = Page::new();
+        let leaf = leaf_page(&mut page);
+        let keys = ["b", "", "abc", "ab", "a\0", "a", "c"];
+        for key in keys {
+            leaf.insert(key.as_bytes(), make_record(), &[]).unwrap();
+        }
+
+        let mut sorted = keys.map(|key| Key::from(key.as_bytes()));
+        sorted.sort();
+        assert!(leaf.keys().eq(sorted));
+        assert!(matches!(
+            leaf.insert(&[0; BTREE_MAX_KEY_SIZE + 1], make_record(), &[]),
+            Err(BTreePageError::KeyTooLarge(_))
+        ));
+        leaf.insert(&[0; BTREE_MAX_KEY_SIZE], make_record(), &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_leaf_page_prefix() {
+        let mut page = Page::new();
+        let leaf = leaf_page(&mut page);
+        let key = |i: u32| format!("https://example.com/users/{i:05}").into_bytes();
+        let mut num_keys = 0;
+        while let Ok(None) = leaf.insert(&key(num_keys), make_record(), &[]) {
+            num_keys += 1;
+        }
+        // The shared bytes are stored once.
+        let entry_size = key(0).len() + size_of::<RecordId>() + BTreeSlot::SIZE;
+        assert!(num_keys as usize * entry_size > BTREE_PAGE_DATA_SIZE);
+        assert_eq!(leaf.prefix(), b"https://example.com/users/00");
+        assert!(leaf.keys().eq((0..num_keys).map(|i| Key::from(key(i)))));
+        assert!(leaf.check_slots().is_ok());
+
+        assert_eq!(leaf.search(b"https://example.com/users/0001"), Err(10));
+        assert_eq!(leaf.search(b"https://example.com/users/0"), Err(0));
+        assert_eq!(leaf.search(b"https://example.com/users/00"), Err(0));
+        assert_eq!(leaf.search(&key(7)), Ok(7));
+        assert_eq!(
+            leaf.search(b"https://example.com/v"),
+            Err(num_keys as usize)
+        );
+
+        // A key outside of the prefix shortens it.
+        for i in 0..num_keys / 2 {
+            leaf.delete(&key(i)).unwrap();
+        }
+        leaf.insert(b"https://example.com/", make_record(), &[])
+            .unwrap();
+        assert_eq!(leaf.prefix(), b"https://example.com/");
+        assert_eq!(leaf.key_at(0).as_bytes(), b"https://example.com/");
+        assert_eq!(leaf.get(&key(num_keys - 1)), Some(make_record()));
+        assert!(leaf.check_slots().is_ok());
+    }
+
+    #[test]
+    fn test_insert_leaf_page_duplicate() {
+        let mut page = Page::new();
+        let leaf = leaf_page(&mut page);
+        leaf.insert(&key(1), make_record(), &[]).unwrap();
+        assert!(matches!(
+            leaf.insert(&key(1), make_record(), &[]),
+            Err(BTreePageError::DuplicateKey)
+        ));
+        assert_eq!(leaf.len(), 1);
     }
 
     #[test]
     fn test_insert_leaf_page_not_monotonic() {
-        let mut leaf = BTreeLeafPage::default();
-        for key in 0..BTREE_NUM_KEYS {
+        let mut page = Page::new();
+        let leaf = leaf_page(&mut page);
+        for key in 0..200 {
             let key = if key % 2 == 0 { key } else { key * 1000 };
-            let _ = leaf.insert(Key::new(key as u32), make_record());
+            leaf.insert(&self::key(key), make_record(), &[]).unwrap();
         }
 
         assert!(leaf.keys().is_sorted());
@@ -413,47 +1135,144 @@ mod tests {
 
     #[test]
     fn test_split_leaf_page() {
-        let mut lhs = BTreeLeafPage::default();
-        let mut rhs = BTreeLeafPage::default();
+        let (mut lhs_page, mut rhs_page) = (Page::new(), Page::new());
+        let lhs = leaf_page(&mut lhs_page);
+        let rhs = leaf_page(&mut rhs_page);
 
         // fill lhs
-        for key in 0..BTREE_NUM_KEYS {
-            let key = key * 2;
-            lhs.insert(Key::new(key as u32), make_record());
+        let mut num_keys = 0;
+        while let Ok(None) = lhs.insert(&key(num_keys * 2), make_record(), &[]) {
+            num_keys += 1;
         }
 
         // lhs is full, split needed
-        let key = BTREE_NUM_KEYS - BTREE_NUM_KEYS % 2 + 1;
-        let (key, value) = (Key::new(key as u32), make_record());
-        let split = lhs.insert(key, value);
+        let (key, value) = (key(num_keys / 2 * 2 + 1), make_record());
+        let split = lhs.insert(&key, value, &[]).unwrap();
         assert!(split.is_some());
-        split.unwrap().split(&mut rhs, key, value);
-
-        assert!(lhs.keys().iter().chain(rhs.keys().iter()).is_sorted());
-        assert_eq!(lhs.keys().len() + rhs.keys().len(), BTREE_NUM_KEYS + 1);
-    }
-
-    #[cfg(test)]
-    impl Default for BTreeInnerPage {
-        fn default() -> Self {
-            Self {
-                header: BTreePageHeader {
-                    page_type: BTreePageType::Inner as u8,
-                    num_keys: U16::new(0),
-                },
-                keys: [Key::new(0); BTREE_NUM_KEYS],
-                pointers: [PageId::new(0); BTREE_BRANCHING_FACTOR],
-            }
+        let split_key = split.unwrap().split(rhs, &key, value, &[]);
+
+        assert!(lhs.keys().chain(rhs.keys()).is_sorted());
+        assert_eq!(lhs.len() + rhs.len(), num_keys as usize + 1);
+        // The separator is truncated, it may not be a key of rhs.
+        assert!(lhs.key_at(lhs.len() - 1) < split_key && split_key <= rhs.key_at(0));
+        assert!(lhs.len().abs_diff(rhs.len()) <= 1);
+    }
+
+    #[test]
+    fn test_leaf_page_payload() {
+        let (mut lhs_page, mut rhs_page) = (Page::new(), Page::new());
+        let lhs = leaf_page(&mut lhs_page);
+        let rhs = leaf_page(&mut rhs_page);
+        let payload = |key: u32| vec![key as u8; key as usize % 16];
+
+        let mut num_keys = 0;
+        while let Ok(None) = lhs.insert(&key(num_keys), make_record(), &payload(num_keys)) {
+            num_keys += 1;
         }
+        assert!(lhs.check_slots().is_ok());
+        // The payloads take room: fewer keys fit than without them.
+        assert!(num_keys < 300);
+        let split = lhs.insert(&key(num_keys), make_record(), &payload(num_keys));
+        split
+            .unwrap()
+            .unwrap()
+            .split(rhs, &key(num_keys), make_record(), &payload(num_keys));
+
+        let payloads = (0..lhs.len())
+            .map(|pos| lhs.payload_at(pos))
+            .chain((0..rhs.len()).map(|pos| rhs.payload_at(pos)))
+            .collect::<Vec<_>>();
+        assert_eq!(payloads, (0..=num_keys).map(payload).collect::<Vec<_>>());
+        assert!(lhs.check_slots().is_ok() && rhs.check_slots().is_ok());
     }
 
     #[test]
     fn test_inner_page_basic() {
-        let mut inner = BTreeInnerPage::default();
+        let mut page = Page::new();
+        let inner = inner_page(&mut page);
+
+        inner.init(&key(0), PageId::new(1), PageId::new(2));
+        for key in 1..100 {
+            assert!(
+                inner
+                    .insert(&self::key(key), PageId::new(key + 2))
+                    .is_none()
+            );
+        }
+        assert_eq!(inner.get(&key(0)), PageId::new(2));
+        assert_eq!(inner.get(&key(50)), PageId::new(52));
+        assert_eq!(inner.get(&[]), PageId::new(1));
+        inner.delete(&key(50)).unwrap();
+        assert_eq!(inner.get(&key(50)), PageId::new(51));
+    }
 
-        inner.init(Key::new(0), PageId::new(1), PageId::new(2));
-        for key in 1..BTREE_NUM_KEYS {
-            inner.insert(Key::new(key as u32), PageId::new(key as u32));
+    #[test]
+    fn test_redistribute_merge_leaf_pages() {
+        let (mut lhs_page, mut rhs_page) = (Page::new(), Page::new());
+        let lhs = leaf_page(&mut lhs_page);
+        let rhs = leaf_page(&mut rhs_page);
+        for key in 0..10 {
+            lhs.insert(&self::key(key), make_record(), &[]).unwrap();
+        }
+        for key in 10..100 {
+            rhs.insert(&self::key(key), make_record(), &[]).unwrap();
         }
+        rhs.set_next_page_id(PageId::new(42));
+
+        assert!(lhs.is_underfull());
+        assert_eq!(lhs.redistribute(rhs).get(), 50);
+        assert_eq!((lhs.len(), rhs.len()), (50, 50));
+        assert_eq!(rhs.key_at(0), Key::new(50));
+        lhs.delete(&key(0)).unwrap();
+        assert_eq!(lhs.redistribute(rhs).get(), 51);
+        assert_eq!((lhs.len(), rhs.len()), (50, 49));
+
+        assert!(lhs.can_merge(rhs));
+        lhs.merge(rhs);
+        assert_eq!(keys(lhs), (1..100).collect::<Vec<_>>());
+        assert_eq!(lhs.next_page_id(), PageId::new(42));
+    }
+
+    #[test]
+    fn test_redistribute_merge_inner_pages() {
+        // Key i is on the left of pointer i.
+        let (mut lhs_page, mut rhs_page) = (Page::new(), Page::new());
+        let lhs = inner_page(&mut lhs_page);
+        lhs.init(&key(1), PageId::new(0), PageId::new(1));
+        for key in 2..5 {
+            lhs.insert(&self::key(key), PageId::new(key));
+        }
+        let rhs = inner_page(&mut rhs_page);
+        rhs.init(&key(11), PageId::new(10), PageId::new(11));
+        for key in 12..30 {
+            rhs.insert(&self::key(key), PageId::new(key));
+        }
+
+        let check = |lhs: &BTreeInnerPage, separator: &Key, rhs: &BTreeInnerPage| {
+            let keys = [keys(lhs), vec![separator.get()], keys(rhs)].concat();
+            let pointers = lhs.pointers().chain(rhs.pointers()).collect::<Vec<_>>();
+            let expected = [1, 2, 3, 4, 10]
+                .into_iter()
+                .chain(11..30)
+                .collect::<Vec<_>>();
+            assert_eq!(keys, expected);
+            assert_eq!(pointers[0], PageId::new(0));
+            assert!(pointers[1..].iter().zip(&keys).all(|(p, &k)| p.get() == k));
+        };
+
+        let separator = lhs.redistribute(&key(10), rhs);
+        assert_eq!((lhs.len(), rhs.len()), (12, 11));
+        check(lhs, &separator, rhs);
+        let separator = lhs.redistribute(separator.as_bytes(), rhs);
+        assert_eq!((lhs.len(), rhs.len()), (12, 11));
+        check(lhs, &separator, rhs);
+
+        lhs.merge(separator.as_bytes(), rhs);
+        assert_eq!(lhs.len(), 24);
+        lhs.remove(0);
+        assert_eq!(lhs.get(&key(1)), PageId::new(0));
+        assert_eq!(lhs.get(&key(2)), PageId::new(2));
+        lhs.set_key(0, &[0; BTREE_MAX_KEY_SIZE]);
+        assert_eq!(lhs.get(&key(1)), PageId::new(2));
     }
 }
@@ -0,0 +1,376 @@
+//! Offline consistency checks of table and index files, see `check_file`.
+//!
+//! Pages don't carry checksums, only the file header does. Heap pages are
+//! checked one by one, B-tree pages are walked from the root: every page but
+//! the reserved one, the pages of the bloom filter, the free list and the
+//! pages freed by merges, which read as zeros, must be reached exactly once. Encrypted files
+//! can't be checked, their header doesn't decode. `BTree::verify` runs the
+//! same checks on a live index, through the page cache.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::pages::inspect::FileKind;
+use crate::pages::{
+    BTreeInnerPage, BTreeLeafPage, BTreePageType, BTreeSuperBlock, FileHeader, FileHeaderError,
+    FreeListPage, HeapPage, Key, PAGE_INVALID, PAGE_RESERVED, Page, PageId,
+    btree_try_get_page_type,
+};
+use crate::storage::{SEGMENT_PAGES, SegmentedFile, StorageError};
+
+/// A problem found in a page.
+#[derive(Debug, Error, PartialEq)]
+pub enum Corruption {
+    #[error("invalid file header: {0}")]
+    Header(#[from] FileHeaderError),
+    #[error("slot {0} is out of bounds or overlaps another entry")]
+    Slot(u16),
+    #[error("unknown page type {0:#04x}")]
+    UnknownPageType(u8),
+    #[error("keys are not in increasing order")]
+    KeysNotSorted,
+    #[error("key {0} is out of the range of its parent pointer")]
+    KeyOutOfRange(Key),
+    #[error("pointer to invalid page {0}")]
+    InvalidPointer(u32),
+    #[error("page is referenced more than once")]
+    SharedPage,
+    #[error("leaf at depth {depth}, expected {expected}")]
+    Unbalanced { depth: usize, expected: usize },
+    #[error("next leaf is {found}, expected {expected}")]
+    LeafChain { found: u32, expected: u32 },
+    #[error("page is not reachable from the root")]
+    Unreachable,
+    #[error("superblock counts {found} keys, the leaves hold {expected}")]
+    KeyCount { found: u64, expected: u64 },
+}
+
+/// What `check_file` found.
+#[derive(Debug)]
+pub struct CheckReport {
+    pub pages: u32,
+    pub corruptions: Vec<(PageId, Corruption)>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.corruptions.is_empty()
+    }
+}
+
+impl std::fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (page_id, corruption) in &self.corruptions {
+            writeln!(f, "page {}: {corruption}", page_id.get())?;
+        }
+        writeln!(
+            f,
+            "{} pages checked, {} corruptions",
+            self.pages,
+            self.corruptions.len()
+        )
+    }
+}
+
+/// Checks a table (`FileKind::Heap`) or index (`FileKind::BTree`) file,
+/// reading its segments without the page cache: the file may be in use, the
+/// pages not written back yet are then missed.
+pub fn check_file(path: &Path, kind: FileKind) -> Result<CheckReport, StorageError> {
+    let file = SegmentedFile::open_read_only(path, SEGMENT_PAGES)?;
+    let num_pages = file.num_pages()? as u32;
+    let report = check(kind, num_pages, |page_id| {
+        let mut page = Page::new();
+        file.read_exact_at(&mut page.data, page_id)?;
+        Ok(page)
+    })?;
+    Ok(report)
+}
+
+/// Checks the `num_pages` pages of a file, read with `read_page`.
+pub fn check(
+    kind: FileKind,
+    num_pages: u32,
+    read_page: impl Fn(PageId) -> io::Result<Page>,
+) -> io::Result<CheckReport> {
+    let mut report = CheckReport {
+        pages: num_pages,
+        corruptions: Vec::new(),
+    };
+    // The rest of the file can't be trusted.
+    if let Err(e) = <&FileHeader>::from(&read_page(PAGE_RESERVED)?).validate() {
+        report.corruptions.push((PAGE_RESERVED, e.into()));
+        return Ok(report);
+    }
+
+    match kind {
+        FileKind::Heap => {
+            for page_id in 1..num_pages {
+                let page_id = PageId::new(page_id);
+                let page = read_page(page_id)?;
+                if let Err(slot_id) = <&HeapPage>::from(&page).check_slots() {
+                    let corruption = Corruption::Slot(slot_id.get());
+                    report.corruptions.push((page_id, corruption));
+                }
+            }
+        }
+        FileKind::BTree => check_btree(num_pages, &read_page, &mut report.corruptions)?,
+    }
+    Ok(report)
+}
+
+/// Walks the tree depth first, from left to right, checking that the keys of
+/// each page are within the bounds set by its parent, that all the leaves are
+/// at the same depth and chained in order, and hold as many keys as counted by
+/// the superblock.
+fn check_btree(
+    num_pages: u32,
+    read_page: &impl Fn(PageId) -> io::Result<Page>,
+    corruptions: &mut Vec<(PageId, Corruption)>,
+) -> io::Result<()> {
+    let is_valid = |page_id: PageId| page_id != PAGE_RESERVED && page_id.get() < num_pages;
+    let superblock = read_page(PAGE_RESERVED)?;
+    let superblock = <&BTreeSuperBlock>::from(&superblock);
+    let root_page_id = superblock.root_page_id;
+    if !is_valid(root_page_id) {
+        let corruption = Corruption::InvalidPointer(root_page_id.get());
+        corruptions.push((PAGE_RESERVED, corruption));
+        return Ok(());
+    }
+
+    // The pages of the bloom filter and the free list, and the pages it
+    // records, aren't part of the tree.
+    let mut visited = HashSet::new();
+    let free_list_page_id = superblock.free_list_page_id;
+    for &page_id in superblock.bloom_filter().iter().chain([&free_list_page_id]) {
+        if !is_valid(page_id) {
+            corruptions.push((PAGE_RESERVED, Corruption::InvalidPointer(page_id.get())));
+        } else if !visited.insert(page_id.get()) {
+            corruptions.push((page_id, Corruption::SharedPage));
+        }
+    }
+    if is_valid(free_list_page_id) {
+        let free_list = read_page(free_list_page_id)?;
+        for &page_id in <&FreeListPage>::from(&free_list).page_ids() {
+            if !is_valid(page_id) {
+                let corruption = Corruption::InvalidPointer(page_id.get());
+                corruptions.push((free_list_page_id, corruption));
+            } else if !visited.insert(page_id.get()) {
+                corruptions.push((page_id, Corruption::SharedPage));
+            }
+        }
+    }
+    let mut leaves = Vec::new();
+    let mut leaf_depth = None;
+    let mut num_keys = 0;
+    // The page, the range of its keys and its depth.
+    let mut stack: Vec<(PageId, Option<Key>, Option<Key>, usize)> =
+        vec![(root_page_id, None, None, 0)];
+    while let Some((page_id, low, high, depth)) = stack.pop() {
+        if !visited.insert(page_id.get()) {
+            corruptions.push((page_id, Corruption::SharedPage));
+            continue;
+        }
+
+        let page = read_page(page_id)?;
+        let (keys, pointers): (Vec<Key>, Vec<PageId>) = match btree_try_get_page_type(&page) {
+            Some(BTreePageType::Inner) => {
+                let inner = <&BTreeInnerPage>::from(&page);
+                if let Err(slot) = inner.check_slots() {
+                    corruptions.push((page_id, Corruption::Slot(slot)));
+                    continue;
+                }
+                (inner.keys().collect(), inner.pointers().collect())
+            }
+            Some(BTreePageType::Leaf) => {
+                let leaf = <&BTreeLeafPage>::from(&page);
+                if let Err(slot) = leaf.check_slots() {
+                    corruptions.push((page_id, Corruption::Slot(slot)));
+                    continue;
+                }
+                match leaf_depth {
+                    Some(expected) if expected != depth => {
+                        let corruption = Corruption::Unbalanced { depth, expected };
+                        corruptions.push((page_id, corruption));
+                    }
+                    _ => leaf_depth = Some(depth),
+                }
+                leaves.push((page_id, leaf.next_page_id()));
+                num_keys += leaf.len() as u64;
+                (leaf.keys().collect(), Vec::new())
+            }
+            None => {
+                let corruption = Corruption::UnknownPageType(page.data[0]);
+                corruptions.push((page_id, corruption));
+                continue;
+            }
+        };
+
+        if !keys.is_sorted_by(|a, b| a < b) {
+            corruptions.push((page_id, Corruption::KeysNotSorted));
+        }
+        let out_of_range = keys.iter().find(|&key| {
+            low.as_ref().is_some_and(|low| key < low)
+                || high.as_ref().is_some_and(|high| key >= high)
+        });
+        if let Some(key) = out_of_range {
+            corruptions.push((page_id, Corruption::KeyOutOfRange(key.clone())));
+        }
+
+        // Pushed from right to left, to be walked from left to right.
+        for (i, &pointer) in pointers.iter().enumerate().rev() {
+            if !is_valid(pointer) {
+                corruptions.push((page_id, Corruption::InvalidPointer(pointer.get())));
+                continue;
+            }
+            let low = i
+                .checked_sub(1)
+                .map_or(low.clone(), |i| Some(keys[i].clone()));
+            let high = keys.get(i).cloned().or(high.clone());
+            stack.push((pointer, low, high, depth + 1));
+        }
+    }
+
+    for (i, &(page_id, next_page_id)) in leaves.iter().enumerate() {
+        let expected = leaves
+            .get(i + 1)
+            .map_or(PAGE_INVALID, |&(page_id, _)| page_id);
+        if next_page_id != expected {
+            let corruption = Corruption::LeafChain {
+                found: next_page_id.get(),
+                expected: expected.get(),
+            };
+            corruptions.push((page_id, corruption));
+        }
+    }
+    if superblock.num_keys() != num_keys {
+        let corruption = Corruption::KeyCount {
+            found: superblock.num_keys(),
+            expected: num_keys,
+        };
+        corruptions.push((PAGE_RESERVED, corruption));
+    }
+    for page_id in 1..num_pages {
+        if visited.contains(&page_id) {
+            continue;
+        }
+        let page_id = PageId::new(page_id);
+        if read_page(page_id)?.data.iter().any(|&byte| byte != 0) {
+            corruptions.push((page_id, Corruption::Unreachable));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::PageCache;
+    use crate::indexes::BTree;
+    use crate::pages::{HeapPageSlotId, RecordId};
+    use crate::sql::types::Value;
+    use crate::storage::FileStorage;
+    use crate::tuple::Tuple;
+
+    use zerocopy::IntoBytes;
+
+    fn read_pages(path: &Path) -> Vec<Page> {
+        let file = SegmentedFile::open_read_only(path, SEGMENT_PAGES).unwrap();
+        (0..file.num_pages().unwrap() as u32)
+            .map(|page_id| {
+                let mut page = Page::new();
+                file.read_exact_at(&mut page.data, PageId::new(page_id))
+                    .unwrap();
+                page
+            })
+            .collect()
+    }
+
+    fn check_pages(kind: FileKind, pages: &[Page]) -> CheckReport {
+        check(kind, pages.len() as u32, |page_id| {
+            let mut page = Page::new();
+            page.data = pages[page_id.get() as usize].data;
+            Ok(page)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn check_btree_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.idx");
+        let page_cache = PageCache::try_new().unwrap();
+        let btree =
+            BTree::try_new(page_cache.cache_storage(FileStorage::create(&path).unwrap())).unwrap();
+        let record_id = RecordId::new(PageId::new(1), HeapPageSlotId::new(0));
+        for key in 0..2000 {
+            btree.insert(&Key::new(key), record_id).unwrap();
+        }
+        // Merges free pages.
+        for key in 500..1800 {
+            btree.delete(&Key::new(key)).unwrap();
+        }
+        page_cache.flush();
+
+        let report = check_file(&path, FileKind::BTree).unwrap();
+        assert!(report.is_ok(), "{report}");
+        assert!(report.pages > 3);
+
+        // Overwrite the first key of the second leaf with its second key, keys
+        // have the same size. The entry is found by its key suffix, past the
+        // prefix shared by the keys of the page, followed by its record id.
+        let mut pages = read_pages(&path);
+        let leaf = (1..pages.len())
+            .filter(|&i| btree_try_get_page_type(&pages[i]).is_some_and(|t| t.is_leaf()))
+            .nth(1)
+            .unwrap();
+        let (entry, second) = {
+            let leaf = <&BTreeLeafPage>::from(&pages[leaf]);
+            let prefix_size = leaf.prefix().len();
+            let suffix = |pos| leaf.key_at(pos).as_bytes()[prefix_size..].to_vec();
+            let entry = [&suffix(0)[..], record_id.as_bytes()].concat();
+            (entry, suffix(1))
+        };
+        let offset = pages[leaf]
+            .data
+            .windows(entry.len())
+            .position(|w| w == entry)
+            .unwrap();
+        pages[leaf].data[offset..offset + second.len()].copy_from_slice(&second);
+        let report = check_pages(FileKind::BTree, &pages);
+        assert_eq!(
+            report.corruptions,
+            [(PageId::new(leaf as u32), Corruption::KeysNotSorted)]
+        );
+    }
+
+    #[test]
+    fn check_heap_slots() {
+        let mut header = Page::new();
+        <&mut FileHeader>::from(&mut header).init();
+        let mut page = Page::new();
+        let heappage = <&mut HeapPage>::from(&mut page);
+        let tuple = Tuple::try_new(vec![Value::Integer(1)]).unwrap();
+        heappage.insert_tuple(&tuple).unwrap();
+        heappage.insert_tuple(&tuple).unwrap();
+        let mut pages = [header, page];
+        assert!(check_pages(FileKind::Heap, &pages).is_ok());
+
+        // Point the second slot one byte into the first tuple.
+        let offset = u16::from_le_bytes([pages[1].data[2], pages[1].data[3]]) + 1;
+        pages[1].data[6..8].copy_from_slice(&offset.to_le_bytes());
+        let report = check_pages(FileKind::Heap, &pages);
+        assert_eq!(report.corruptions, [(PageId::new(1), Corruption::Slot(1))]);
+
+        pages[0].data[0] = b'X';
+        let report = check_pages(FileKind::Heap, &pages);
+        assert_eq!(
+            report.corruptions,
+            [(PAGE_RESERVED, FileHeaderError::BadMagic.into())]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::pages::{PAGE_SIZE, Page, PageId};
+
+use zerocopy::little_endian::U32;
+use zerocopy_derive::*;
+
+/// The number of page ids a free list page holds.
+pub const FREE_LIST_CAPACITY: usize = (PAGE_SIZE - size_of::<U32>()) / size_of::<PageId>();
+
+/// A page of the ids of the pages freed in a file, to be reused before the
+/// file grows. The ids are pushed and popped at the end of the list.
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct FreeListPage {
+    len: U32,
+    page_ids: [PageId; FREE_LIST_CAPACITY],
+}
+
+impl FreeListPage {
+    pub fn init(&mut self) {
+        self.len.set(0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.get() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The page ids, from the first freed to the last one.
+    pub fn page_ids(&self) -> &[PageId] {
+        &self.page_ids[..self.len().min(FREE_LIST_CAPACITY)]
+    }
+
+    /// Records a page freed, returns `false` if the page is full.
+    pub fn push(&mut self, page_id: PageId) -> bool {
+        let len = self.len();
+        if len == FREE_LIST_CAPACITY {
+            return false;
+        }
+        self.page_ids[len] = page_id;
+        self.len.set(len as u32 + 1);
+        true
+    }
+
+    /// Takes the page freed last, if any.
+    pub fn pop(&mut self) -> Option<PageId> {
+        let len = self.len().checked_sub(1)?;
+        self.len.set(len as u32);
+        Some(self.page_ids[len])
+    }
+}
+
+impl From<&Page> for &FreeListPage {
+    fn from(page: &Page) -> Self {
+        unsafe { &*(page.data.as_ptr() as *const FreeListPage) }
+    }
+}
+
+impl From<&mut Page> for &mut FreeListPage {
+    fn from(page: &mut Page) -> Self {
+        unsafe { &mut *(page.data.as_mut_ptr() as *mut FreeListPage) }
+    }
+}
+
+impl std::fmt::Display for FreeListPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "type: free list")?;
+        let pages = self
+            .page_ids()
+            .iter()
+            .map(|page_id| page_id.get())
+            .collect::<Vec<_>>();
+        writeln!(f, "pages: {pages:?}")
+    }
+}
+
+const _: () = assert!(size_of::<FreeListPage>() <= PAGE_SIZE);
@@ -0,0 +1,461 @@
+use crate::pages::{
+    BTREE_MAX_KEY_SIZE, FILE_HEADER_SIZE, PAGE_INVALID, PAGE_SIZE, Page, PageId, RecordId,
+};
+
+use zerocopy::little_endian::{U16, U32, U64};
+use zerocopy::*;
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// The largest key of a hash index, the same as in a B-tree: a bucket page
+/// has room for a few of them.
+pub const HASH_MAX_KEY_SIZE: usize = BTREE_MAX_KEY_SIZE;
+
+/// Follows the B-tree page types, so that the pages of an index file are told
+/// apart.
+const HASH_PAGE_TYPE_BUCKET: u8 = 2;
+
+/// The number of buckets a directory page points to.
+pub const HASH_DIRECTORY_SIZE: usize = PAGE_SIZE / size_of::<PageId>();
+
+/// The number of directory pages listed in the superblock.
+pub const HASH_MAX_DIRECTORY_PAGES: usize = 256;
+
+/// The largest global depth: the directory then fills all its pages. Buckets
+/// this deep are chained to overflow pages rather than split.
+pub const HASH_MAX_DEPTH: u32 = (HASH_DIRECTORY_SIZE * HASH_MAX_DIRECTORY_PAGES).ilog2();
+
+/// The hash of a key, FNV-1a: it's stored in the index, it must not change
+/// between versions unlike the hasher of the standard library.
+pub fn hash_key(key: &[u8]) -> u32 {
+    key.iter().fold(0x811c_9dc5, |hash: u32, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Stored in the reserved page of a hash index, after the `FileHeader`.
+///
+/// The directory maps the `global_depth` low bits of the hash of a key to its
+/// bucket. It's stored in directory pages of `HASH_DIRECTORY_SIZE` buckets.
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct HashSuperBlock {
+    num_keys: U64,
+    global_depth: U32,
+    directory: [PageId; HASH_MAX_DIRECTORY_PAGES],
+}
+
+impl HashSuperBlock {
+    /// A directory of a single bucket, in the first slot of `directory_page_id`.
+    pub fn init(&mut self, directory_page_id: PageId) {
+        self.num_keys.set(0);
+        self.global_depth.set(0);
+        self.directory = [PAGE_INVALID; HASH_MAX_DIRECTORY_PAGES];
+        self.directory[0] = directory_page_id;
+    }
+
+    pub fn global_depth(&self) -> u32 {
+        self.global_depth.get()
+    }
+
+    pub fn set_global_depth(&mut self, global_depth: u32) {
+        debug_assert!(global_depth <= HASH_MAX_DEPTH);
+        self.global_depth.set(global_depth);
+    }
+
+    /// The number of slots of the directory, `2^global_depth`.
+    pub fn directory_size(&self) -> usize {
+        1 << self.global_depth()
+    }
+
+    /// The slot of the directory of a hash.
+    pub fn directory_slot(&self, hash: u32) -> usize {
+        hash as usize & (self.directory_size() - 1)
+    }
+
+    /// The directory page holding a slot, and the position of the slot in it.
+    pub fn directory_page(&self, slot: usize) -> (PageId, usize) {
+        (
+            self.directory[slot / HASH_DIRECTORY_SIZE],
+            slot % HASH_DIRECTORY_SIZE,
+        )
+    }
+
+    pub fn set_directory_page(&mut self, i: usize, page_id: PageId) {
+        self.directory[i] = page_id;
+    }
+
+    pub fn num_keys(&self) -> u64 {
+        self.num_keys.get()
+    }
+
+    pub fn increment_num_keys(&mut self) {
+        self.num_keys += 1;
+    }
+
+    pub fn decrement_num_keys(&mut self) {
+        self.num_keys -= 1;
+    }
+}
+
+impl<'a> From<&'a Page> for &'a HashSuperBlock {
+    fn from(page: &'a Page) -> Self {
+        let (superblock, _) =
+            HashSuperBlock::ref_from_prefix(&page.data[FILE_HEADER_SIZE..]).unwrap();
+        superblock
+    }
+}
+
+impl<'a> From<&'a mut Page> for &'a mut HashSuperBlock {
+    fn from(page: &'a mut Page) -> Self {
+        let (superblock, _) =
+            HashSuperBlock::mut_from_prefix(&mut page.data[FILE_HEADER_SIZE..]).unwrap();
+        superblock
+    }
+}
+
+impl std::fmt::Display for HashSuperBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "type: hash superblock")?;
+        writeln!(f, "global_depth: {}", self.global_depth())?;
+        writeln!(f, "num_keys: {}", self.num_keys())?;
+        let pages = self
+            .directory
+            .iter()
+            .take_while(|page_id| **page_id != PAGE_INVALID)
+            .map(|page_id| page_id.get())
+            .collect::<Vec<_>>();
+        writeln!(f, "directory: {pages:?}")
+    }
+}
+
+const _: () = assert!(std::mem::size_of::<HashSuperBlock>() <= PAGE_SIZE - FILE_HEADER_SIZE);
+
+/// A page of the directory: the buckets of `HASH_DIRECTORY_SIZE` slots.
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct HashDirectoryPage {
+    buckets: [PageId; HASH_DIRECTORY_SIZE],
+}
+
+impl HashDirectoryPage {
+    pub fn get(&self, pos: usize) -> PageId {
+        self.buckets[pos]
+    }
+
+    pub fn set(&mut self, pos: usize, page_id: PageId) {
+        self.buckets[pos] = page_id;
+    }
+}
+
+impl From<&Page> for &HashDirectoryPage {
+    fn from(page: &Page) -> Self {
+        unsafe { &*(page.data.as_ptr() as *const HashDirectoryPage) }
+    }
+}
+
+impl From<&mut Page> for &mut HashDirectoryPage {
+    fn from(page: &mut Page) -> Self {
+        unsafe { &mut *(page.data.as_mut_ptr() as *mut HashDirectoryPage) }
+    }
+}
+
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct HashBucketHeader {
+    page_type: u8,
+    // The number of low bits of the hash shared by the keys of the bucket.
+    local_depth: u8,
+    num_entries: U16,
+    // Entries are added from the end of the page down to this offset.
+    entries_start: U16,
+    // The next page of the bucket, once it can't be split anymore.
+    overflow_page_id: PageId,
+}
+
+const HASH_BUCKET_DATA_SIZE: usize = PAGE_SIZE - size_of::<HashBucketHeader>();
+
+/// Where the entry of a key is in the page.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct HashSlot {
+    // Compared before the key, and read back when the bucket is split.
+    hash: U32,
+    offset: U16,
+    key_size: U16,
+}
+
+impl HashSlot {
+    const SIZE: usize = size_of::<Self>();
+}
+
+const RECORD_ID_SIZE: usize = size_of::<RecordId>();
+
+/// A slotted bucket page of a hash index: the slots grow from the start of
+/// the page and the entries they point to, the bytes of the key followed by
+/// its `RecordId`, grow from the end. Entries aren't sorted, a lookup compares
+/// the hash of every slot.
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct HashBucketPage {
+    header: HashBucketHeader,
+    data: [u8; HASH_BUCKET_DATA_SIZE],
+}
+
+/// Returns whether the page is a bucket of a hash index, rather than a B-tree
+/// page.
+pub fn hash_is_bucket_page(page: &Page) -> bool {
+    page.data[0] == HASH_PAGE_TYPE_BUCKET
+}
+
+impl HashBucketPage {
+    pub fn init(&mut self, local_depth: u32) {
+        self.header.page_type = HASH_PAGE_TYPE_BUCKET;
+        self.header.overflow_page_id = PAGE_INVALID;
+        self.set_local_depth(local_depth);
+        self.clear();
+    }
+
+    pub fn local_depth(&self) -> u32 {
+        self.header.local_depth as u32
+    }
+
+    pub fn set_local_depth(&mut self, local_depth: u32) {
+        debug_assert!(local_depth <= HASH_MAX_DEPTH);
+        self.header.local_depth = local_depth as u8;
+    }
+
+    pub fn overflow_page_id(&self) -> PageId {
+        self.header.overflow_page_id
+    }
+
+    pub fn set_overflow_page_id(&mut self, page_id: PageId) {
+        self.header.overflow_page_id = page_id;
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.num_entries.get() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all the entries, the local depth and the overflow page are
+    /// kept.
+    pub fn clear(&mut self) {
+        self.header.num_entries.set(0);
+        self.header.entries_start.set(HASH_BUCKET_DATA_SIZE as u16);
+    }
+
+    fn try_slot(&self, pos: usize) -> Option<HashSlot> {
+        let bytes = self
+            .data
+            .get(pos * HashSlot::SIZE..(pos + 1) * HashSlot::SIZE)?;
+        HashSlot::read_from_bytes(bytes).ok()
+    }
+
+    fn slot(&self, pos: usize) -> HashSlot {
+        self.try_slot(pos).unwrap()
+    }
+
+    fn try_entry(&self, slot: &HashSlot) -> Option<(&[u8], RecordId)> {
+        let offset = slot.offset.get() as usize;
+        let key_size = slot.key_size.get() as usize;
+        let key = self.data.get(offset..offset + key_size)?;
+        let record_id = self
+            .data
+            .get(offset + key_size..offset + key_size + RECORD_ID_SIZE)?;
+        Some((key, RecordId::read_from_bytes(record_id).ok()?))
+    }
+
+    fn position(&self, hash: u32, key: &[u8]) -> Option<usize> {
+        (0..self.len()).find(|&pos| {
+            let slot = self.slot(pos);
+            slot.hash.get() == hash && self.try_entry(&slot).unwrap().0 == key
+        })
+    }
+
+    /// Returns the `RecordId` of a key, given its hash.
+    pub fn get(&self, hash: u32, key: &[u8]) -> Option<RecordId> {
+        let pos = self.position(hash, key)?;
+        Some(self.try_entry(&self.slot(pos)).unwrap().1)
+    }
+
+    /// Returns whether the page has room for the entry of a key.
+    pub fn fits(&self, key: &[u8]) -> bool {
+        let slots_end = self.len() * HashSlot::SIZE;
+        let free = self.header.entries_start.get() as usize - slots_end;
+        free >= HashSlot::SIZE + key.len() + RECORD_ID_SIZE
+    }
+
+    /// Adds an entry, the page must have room for it, see `fits`. Keys are
+    /// not checked for duplicates.
+    pub fn push(&mut self, hash: u32, key: &[u8], record_id: RecordId) {
+        assert!(self.fits(key));
+        let offset = self.header.entries_start.get() as usize - key.len() - RECORD_ID_SIZE;
+        self.data[offset..offset + key.len()].copy_from_slice(key);
+        self.data[offset + key.len()..offset + key.len() + RECORD_ID_SIZE]
+            .copy_from_slice(record_id.as_bytes());
+        let slot = HashSlot {
+            hash: U32::new(hash),
+            offset: U16::new(offset as u16),
+            key_size: U16::new(key.len() as u16),
+        };
+        let pos = self.len();
+        self.data[pos * HashSlot::SIZE..(pos + 1) * HashSlot::SIZE]
+            .copy_from_slice(slot.as_bytes());
+        self.header.num_entries.set(pos as u16 + 1);
+        self.header.entries_start.set(offset as u16);
+    }
+
+    /// Removes the entry of a key, returns whether it was found. The page is
+    /// compacted.
+    pub fn remove(&mut self, hash: u32, key: &[u8]) -> bool {
+        let Some(pos) = self.position(hash, key) else {
+            return false;
+        };
+        let mut entries = self.entries();
+        entries.remove(pos);
+        self.set_entries(&entries);
+        true
+    }
+
+    /// The entries of the page: the hash of their key, the key and its
+    /// `RecordId`.
+    pub fn entries(&self) -> Vec<(u32, Vec<u8>, RecordId)> {
+        (0..self.len())
+            .map(|pos| {
+                let slot = self.slot(pos);
+                let (key, record_id) = self.try_entry(&slot).unwrap();
+                (slot.hash.get(), key.to_vec(), record_id)
+            })
+            .collect()
+    }
+
+    /// Replaces the entries of the page, they must fit.
+    pub fn set_entries(&mut self, entries: &[(u32, Vec<u8>, RecordId)]) {
+        self.clear();
+        for (hash, key, record_id) in entries {
+            self.push(*hash, key, *record_id);
+        }
+    }
+}
+
+impl From<&Page> for &HashBucketPage {
+    fn from(page: &Page) -> Self {
+        unsafe { &*(page.data.as_ptr() as *const HashBucketPage) }
+    }
+}
+
+impl From<&mut Page> for &mut HashBucketPage {
+    fn from(page: &mut Page) -> Self {
+        unsafe { &mut *(page.data.as_mut_ptr() as *mut HashBucketPage) }
+    }
+}
+
+impl std::fmt::Display for HashBucketPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // num_entries isn't trusted, the page may be corrupted.
+        writeln!(f, "type: hash bucket")?;
+        writeln!(f, "local_depth: {}", self.local_depth())?;
+        writeln!(f, "num_entries: {}", self.len())?;
+        writeln!(f, "overflow: {}", self.overflow_page_id().get())?;
+        for i in 0..self.len() {
+            let Some((slot, (key, record_id))) = self
+                .try_slot(i)
+                .and_then(|slot| Some((slot, self.try_entry(&slot)?)))
+            else {
+                writeln!(f, "key[{i}]: invalid slot")?;
+                break;
+            };
+            write!(f, "key[{i}]: ")?;
+            key.iter().try_for_each(|byte| write!(f, "{byte:02x}"))?;
+            writeln!(
+                f,
+                " hash: {:08x} record: ({}, {})",
+                slot.hash.get(),
+                record_id.page_id.get(),
+                record_id.slot_id.get()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pages::HeapPageSlotId;
+
+    fn record_id(i: u32) -> RecordId {
+        RecordId::new(PageId::new(i), HeapPageSlotId::new(i as u16))
+    }
+
+    #[test]
+    fn hash_key_is_stable() {
+        assert_eq!(hash_key(b""), 0x811c_9dc5);
+        assert_eq!(hash_key(b"a"), 0xe40c_292c);
+        assert_eq!(hash_key(b"foobar"), 0xbf9c_f968);
+    }
+
+    #[test]
+    fn bucket_page() {
+        let mut page = Page::new();
+        assert!(!hash_is_bucket_page(&page));
+        let bucket = <&mut HashBucketPage>::from(&mut page);
+        bucket.init(3);
+        assert_eq!(bucket.local_depth(), 3);
+        assert_eq!(bucket.overflow_page_id(), PAGE_INVALID);
+        assert!(bucket.is_empty());
+
+        let keys = (0..100u32).map(|i| i.to_be_bytes()).collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            bucket.push(hash_key(key), key, record_id(i as u32));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(bucket.get(hash_key(key), key), Some(record_id(i as u32)));
+        }
+        assert_eq!(bucket.get(hash_key(b"nope"), b"nope"), None);
+
+        assert!(bucket.remove(hash_key(&keys[10]), &keys[10]));
+        assert!(!bucket.remove(hash_key(&keys[10]), &keys[10]));
+        assert_eq!(bucket.len(), 99);
+        assert_eq!(bucket.get(hash_key(&keys[10]), &keys[10]), None);
+        assert_eq!(
+            bucket.get(hash_key(&keys[11]), &keys[11]),
+            Some(record_id(11))
+        );
+
+        // The page is filled up to its last byte.
+        let mut i = 100u32;
+        while bucket.fits(&i.to_be_bytes()) {
+            let key = i.to_be_bytes();
+            bucket.push(hash_key(&key), &key, record_id(i));
+            i += 1;
+        }
+        let used = bucket.len() * (HashSlot::SIZE + 4 + RECORD_ID_SIZE);
+        assert!(HASH_BUCKET_DATA_SIZE - used < HashSlot::SIZE + 4 + RECORD_ID_SIZE);
+        assert!(hash_is_bucket_page(&page));
+    }
+
+    // The frames of the page cache are aligned, for the atomic of the
+    // superblock.
+    #[repr(align(4096))]
+    struct AlignedPage(Page);
+
+    #[test]
+    fn superblock_directory() {
+        let mut page = AlignedPage(Page::new());
+        let superblock = <&mut HashSuperBlock>::from(&mut page.0);
+        superblock.init(PageId::new(1));
+        assert_eq!(superblock.directory_slot(0xffff_ffff), 0);
+        superblock.set_global_depth(12);
+        assert_eq!(superblock.directory_size(), 4096);
+        assert_eq!(superblock.directory_slot(0xffff_ffff), 4095);
+        assert_eq!(superblock.directory_page(1023), (PageId::new(1), 1023));
+        assert_eq!(superblock.directory_page(1024), (PAGE_INVALID, 0));
+        assert_eq!(HASH_MAX_DEPTH, 18);
+    }
+}
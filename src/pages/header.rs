@@ -0,0 +1,155 @@
+use crate::pages::{PAGE_SIZE, Page};
+
+use chrono::DateTime;
+use thiserror::Error;
+use zerocopy::little_endian::{U32, U64};
+use zerocopy_derive::*;
+
+/// The first bytes of every storage file.
+pub const FILE_MAGIC: [u8; 8] = *b"JOUJOUDB";
+/// The version of the on-disk format, bumped on incompatible changes.
+pub const FORMAT_VERSION: u32 = 5;
+/// The bytes of the reserved page taken by the header: what a file kind
+/// stores there, e.g. the B-tree superblock, comes after.
+pub const FILE_HEADER_SIZE: usize = 64;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum FileHeaderError {
+    #[error("not a joujoudb file")]
+    BadMagic,
+    #[error("header checksum mismatch")]
+    Checksum,
+    #[error("unsupported format version {0}, expected {expected}", expected = FORMAT_VERSION)]
+    UnsupportedVersion(u32),
+    #[error("page size {0}, expected {expected}", expected = PAGE_SIZE)]
+    PageSizeMismatch(u32),
+}
+
+/// The header of a storage file, at the start of its reserved page.
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct FileHeader {
+    magic: [u8; 8],
+    format_version: U32,
+    page_size: U32,
+    // Seconds since the Unix epoch.
+    created_at: U64,
+    // Of the fields above.
+    checksum: U64,
+}
+
+impl FileHeader {
+    pub fn init(&mut self) {
+        self.magic = FILE_MAGIC;
+        self.format_version.set(FORMAT_VERSION);
+        self.page_size.set(PAGE_SIZE as u32);
+        self.created_at
+            .set(chrono::Utc::now().timestamp().max(0) as u64);
+        self.checksum.set(self.checksum());
+    }
+
+    fn checksum(&self) -> u64 {
+        let bytes = [
+            &self.magic[..],
+            &self.format_version.get().to_le_bytes(),
+            &self.page_size.get().to_le_bytes(),
+            &self.created_at.get().to_le_bytes(),
+        ];
+        bytes
+            .concat()
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    /// Checks that the file was written by this version of the database.
+    pub fn validate(&self) -> Result<(), FileHeaderError> {
+        if self.magic != FILE_MAGIC {
+            Err(FileHeaderError::BadMagic)
+        } else if self.checksum.get() != self.checksum() {
+            Err(FileHeaderError::Checksum)
+        } else if self.format_version.get() != FORMAT_VERSION {
+            Err(FileHeaderError::UnsupportedVersion(
+                self.format_version.get(),
+            ))
+        } else if self.page_size.get() != PAGE_SIZE as u32 {
+            Err(FileHeaderError::PageSizeMismatch(self.page_size.get()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version.get()
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size.get()
+    }
+
+    /// Returns when the file was created, in seconds since the Unix epoch.
+    pub fn created_at(&self) -> u64 {
+        self.created_at.get()
+    }
+}
+
+impl From<&Page> for &FileHeader {
+    fn from(page: &Page) -> Self {
+        unsafe { &*(page.data.as_ptr() as *const FileHeader) }
+    }
+}
+
+impl From<&mut Page> for &mut FileHeader {
+    fn from(page: &mut Page) -> Self {
+        unsafe { &mut *(page.data.as_mut_ptr() as *mut FileHeader) }
+    }
+}
+
+impl std::fmt::Display for FileHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "type: file header")?;
+        writeln!(f, "magic: {}", self.magic.escape_ascii())?;
+        writeln!(f, "format_version: {}", self.format_version())?;
+        writeln!(f, "page_size: {}", self.page_size())?;
+        match DateTime::from_timestamp(self.created_at() as i64, 0) {
+            Some(created_at) => writeln!(f, "created_at: {}", created_at.to_rfc3339()),
+            None => writeln!(f, "created_at: {}", self.created_at()),
+        }
+    }
+}
+
+const _: () = assert!(std::mem::size_of::<FileHeader>() <= FILE_HEADER_SIZE);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_header() {
+        let mut page = Page::new();
+        assert_eq!(
+            <&FileHeader>::from(&page).validate(),
+            Err(FileHeaderError::BadMagic)
+        );
+
+        let header = <&mut FileHeader>::from(&mut page);
+        header.init();
+        assert_eq!(header.validate(), Ok(()));
+
+        header.page_size.set(8192);
+        assert_eq!(header.validate(), Err(FileHeaderError::Checksum));
+        header.checksum.set(header.checksum());
+        assert_eq!(
+            header.validate(),
+            Err(FileHeaderError::PageSizeMismatch(8192))
+        );
+
+        header.format_version.set(FORMAT_VERSION + 1);
+        header.checksum.set(header.checksum());
+        assert_eq!(
+            header.validate(),
+            Err(FileHeaderError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+    }
+}
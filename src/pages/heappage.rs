@@ -33,7 +33,7 @@ impl HeapPageSlotId {
 }
 
 // The identifier for a unique entry in a table
-#[derive(Copy, Clone, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromBytes, IntoBytes, KnownLayout, Immutable)]
 #[repr(C)]
 pub struct RecordId {
     pub page_id: PageId,
@@ -215,6 +215,42 @@ impl HeapPage {
         self.free_space() >= (HeapPageSlot::SIZE + tuple.size())
     }
 
+    /// Checks that the slot array fits in the page and that the tuples of the
+    /// live slots lie in the tuple section without overlapping. Returns the
+    /// first slot that doesn't.
+    pub fn check_slots(&self) -> Result<(), HeapPageSlotId> {
+        let num_slots = self.header.num_slots.get() as usize;
+        let slots_end = num_slots * HeapPageSlot::SIZE;
+        if slots_end > Self::DATA_SIZE {
+            let max_slots = Self::DATA_SIZE / HeapPageSlot::SIZE;
+            return Err(HeapPageSlotId::new(max_slots as u16));
+        }
+
+        let mut tuples = Vec::new();
+        for slot_id in 0..num_slots as u16 {
+            let slot_id = HeapPageSlotId::new(slot_id);
+            let slot = self.get_slot(slot_id).unwrap();
+            if slot.is_deleted() {
+                continue;
+            }
+            let tuple = slot.offset()..slot.offset() + slot.size();
+            if tuple.start < slots_end || tuple.end > Self::DATA_SIZE {
+                return Err(slot_id);
+            }
+            tuples.push((tuple, slot_id));
+        }
+        tuples.sort_by_key(|(tuple, _)| tuple.start);
+        match tuples.windows(2).find(|w| w[0].0.end > w[1].0.start) {
+            Some(w) => Err(w[1].1),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes all the slots, leaving an empty page.
+    pub fn clear(&mut self) {
+        self.header.num_slots.set(0);
+    }
+
     /// Inserts a tuple into the heap page.
     ///
     /// Returns a `Result` containing the `HeapPageSlotId` of the new tuple, or a `HeapPageError` if there is not enough free space.
@@ -265,6 +301,34 @@ impl HeapPage {
     }
 }
 
+impl std::fmt::Display for HeapPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // num_slots isn't trusted, the page may be corrupted.
+        let num_slots = self
+            .header
+            .num_slots
+            .get()
+            .min((Self::DATA_SIZE / HeapPageSlot::SIZE) as u16);
+        writeln!(f, "type: heap")?;
+        writeln!(f, "num_slots: {}", self.header.num_slots.get())?;
+        for slot_id in 0..num_slots {
+            let slot = self.get_slot(HeapPageSlotId::new(slot_id)).unwrap();
+            if slot.is_deleted() {
+                writeln!(f, "slot[{slot_id}]: deleted")?;
+            } else {
+                writeln!(
+                    f,
+                    "slot[{slot_id}]: offset={} size={}",
+                    slot.offset(),
+                    slot.size()
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl<'a> From<&'a Page> for &'a HeapPage {
     fn from(page: &'a Page) -> &'a HeapPage {
         unsafe { &*(page.data.as_ptr() as *const HeapPage) }
@@ -293,7 +357,7 @@ mod tests {
             ),
             Column::new(
                 "b".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().build(),
             ),
         ])
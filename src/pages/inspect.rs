@@ -0,0 +1,164 @@
+//! Decoding of raw pages, for debugging.
+//!
+//! Pages don't carry their type: the kind of file they are read from tells
+//! heap pages from B-tree pages, and B-tree pages are told apart with their
+//! header. Page 0 holds the file header, followed by the superblock in B-tree
+//! files.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::pages::{
+    BTreeInnerPage, BTreeLeafPage, BTreePageType, BTreeSuperBlock, FileHeader, HeapPage,
+    PAGE_RESERVED, Page, PageId, btree_try_get_page_type,
+};
+
+/// The kind of file a page is read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Heap,
+    BTree,
+}
+
+impl FileKind {
+    /// Tells the kind of a file from its extension, `.tbl` or `.idx`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("tbl") => Some(FileKind::Heap),
+            Some("idx") => Some(FileKind::BTree),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a human readable description of the page: its decoded header and
+/// content followed by a hexdump.
+pub fn inspect(page: &Page, page_id: PageId, kind: FileKind) -> String {
+    let mut output = format!("page: {}\n", page_id.get());
+    output.push_str(&describe(page, page_id, kind));
+    output.push('\n');
+    output.push_str(&hexdump(&page.data));
+    output
+}
+
+/// Decodes the page header and content.
+pub fn describe(page: &Page, page_id: PageId, kind: FileKind) -> String {
+    match kind {
+        FileKind::Heap if page_id == PAGE_RESERVED => <&FileHeader>::from(page).to_string(),
+        FileKind::Heap => <&HeapPage>::from(page).to_string(),
+        FileKind::BTree if page_id == PAGE_RESERVED => format!(
+            "{}{}",
+            <&FileHeader>::from(page),
+            <&BTreeSuperBlock>::from(page)
+        ),
+        FileKind::BTree => match btree_try_get_page_type(page) {
+            Some(BTreePageType::Inner) => <&BTreeInnerPage>::from(page).to_string(),
+            Some(BTreePageType::Leaf) => <&BTreeLeafPage>::from(page).to_string(),
+            None => format!("type: unknown ({:#04x})\n", page.data[0]),
+        },
+    }
+}
+
+/// Formats bytes as 16 bytes wide rows of hex and ASCII, in the style of
+/// `hexdump -C`. Runs of identical rows are collapsed into a single `*`.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut collapsed = false;
+
+    for (i, row) in bytes.chunks(16).enumerate() {
+        if previous == Some(row) {
+            if !collapsed {
+                output.push_str("*\n");
+                collapsed = true;
+            }
+            continue;
+        }
+        previous = Some(row);
+        collapsed = false;
+
+        write!(output, "{:08x} ", i * 16).unwrap();
+        for (j, byte) in row.iter().enumerate() {
+            if j == 8 {
+                output.push(' ');
+            }
+            write!(output, " {byte:02x}").unwrap();
+        }
+        let ascii = row
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        writeln!(output, "  |{ascii}|").unwrap();
+    }
+    writeln!(output, "{:08x}", bytes.len()).unwrap();
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pages::{HeapPageSlotId, Key, RecordId};
+    use crate::sql::types::Value;
+    use crate::tuple::Tuple;
+
+    #[test]
+    fn hexdump_collapses_rows() {
+        let mut bytes = [0u8; 64];
+        bytes[..5].copy_from_slice(b"hello");
+        assert_eq!(
+            hexdump(&bytes),
+            "00000000  68 65 6c 6c 6f 00 00 00  00 00 00 00 00 00 00 00  |hello...........|\n\
+             00000010  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+             *\n\
+             00000040\n"
+        );
+    }
+
+    #[test]
+    fn describe_pages() {
+        let mut page = Page::new();
+        let heappage = <&mut HeapPage>::from(&mut page);
+        let slot_id = heappage
+            .insert_tuple(&Tuple::try_new(vec![Value::Integer(1)]).unwrap())
+            .unwrap();
+        heappage.delete_tuple(slot_id).unwrap();
+        heappage
+            .insert_tuple(&Tuple::try_new(vec![Value::Integer(2)]).unwrap())
+            .unwrap();
+        assert_eq!(
+            describe(&page, PageId::new(1), FileKind::Heap),
+            "type: heap\nnum_slots: 2\nslot[0]: deleted\nslot[1]: offset=4058 size=18\n"
+        );
+        let mut page = Page::new();
+        <&mut FileHeader>::from(&mut page).init();
+        assert!(describe(&page, PAGE_RESERVED, FileKind::Heap).starts_with(
+            "type: file header\nmagic: JOUJOUDB\nformat_version: 5\npage_size: 4096\n"
+        ));
+
+        let mut page = Page::new();
+        let leaf = <&mut BTreeLeafPage>::from(&mut page);
+        leaf.init();
+        let record_id = RecordId::new(PageId::new(3), HeapPageSlotId::new(4));
+        leaf.insert(Key::new(42).as_bytes(), record_id, &[])
+            .unwrap();
+        leaf.set_next_page_id(PageId::new(7));
+        assert_eq!(
+            describe(&page, PageId::new(2), FileKind::BTree),
+            "type: leaf\nnum_keys: 1\nnext: 7\nkey[0]: 0000002a record: (3, 4)\n"
+        );
+
+        page.data[0] = 0xff;
+        assert_eq!(
+            describe(&page, PageId::new(2), FileKind::BTree),
+            "type: unknown (0xff)\n"
+        );
+    }
+}
@@ -1,9 +1,25 @@
+mod bloom;
 mod btree;
+pub mod check;
+mod freelist;
+mod hash;
+mod header;
 mod heappage;
+pub mod inspect;
 mod page;
 
-pub use btree::{BTreeInnerPage, BTreeLeafPage, BTreePageError, BTreeSuperBlock, Key};
+pub use bloom::{BLOOM_BITS_PER_KEY, BLOOM_HASHES, BLOOM_PAGE_BITS, BloomFilterPage, bloom_bits};
+pub use btree::{
+    BTREE_MAX_BLOOM_PAGES, BTREE_MAX_KEY_SIZE, BTreeInnerPage, BTreeLeafPage, BTreePage,
+    BTreePageError, BTreeSuperBlock, Key,
+};
+pub use freelist::{FREE_LIST_CAPACITY, FreeListPage};
+pub use hash::{
+    HASH_DIRECTORY_SIZE, HASH_MAX_DEPTH, HASH_MAX_DIRECTORY_PAGES, HASH_MAX_KEY_SIZE,
+    HashBucketPage, HashDirectoryPage, HashSuperBlock, hash_is_bucket_page, hash_key,
+};
+pub use header::{FILE_HEADER_SIZE, FILE_MAGIC, FORMAT_VERSION, FileHeader, FileHeaderError};
 pub use heappage::{HeapPage, HeapPageError, HeapPageSlotId, RecordId};
 pub use page::{PAGE_INVALID, PAGE_RESERVED, PAGE_SIZE, Page, PageId, PageMetadata};
 
-pub use btree::{BTreePageType, btree_get_page_type};
+pub use btree::{BTreePageType, btree_get_page_type, btree_try_get_page_type};
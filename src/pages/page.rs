@@ -46,11 +46,15 @@ impl PageId {
     }
 }
 
-/// the actual data read from/written to disk
+/// the actual data read from/written to disk, aligned for direct I/O wherever
+/// it's allocated, e.g. on the stack
+#[repr(C, align(4096))]
 pub struct Page {
     pub data: [u8; PAGE_SIZE],
 }
 
+const _: () = assert!(std::mem::align_of::<Page>() == PAGE_SIZE);
+
 impl Default for Page {
     fn default() -> Self {
         Self {
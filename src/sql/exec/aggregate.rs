@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::sql::exec::{
+    BATCH_SIZE, Evaluator, ExecError, Executor, MemoryBudget, Reservation, SpillPartitions,
+    SpillReader, memory_size,
+};
+use crate::sql::parser::ast::AggregateFunction;
+use crate::sql::plan::{AggregateExpr, Expr};
+use crate::sql::types::Value;
+use crate::tuple::Tuple;
+
+/// An aggregate function applied to a column of the input tuples.
+#[derive(Clone, Copy, Debug)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// The aggregated column, `None` for COUNT(*).
+    pub column: Option<usize>,
+    /// Whether each distinct value is only aggregated once.
+    pub distinct: bool,
+}
+
+impl Aggregate {
+    pub fn new(function: AggregateFunction, column: usize) -> Self {
+        Self {
+            function,
+            column: Some(column),
+            distinct: false,
+        }
+    }
+
+    /// An aggregate ignoring duplicate values, e.g. `COUNT(DISTINCT x)`.
+    pub fn distinct(function: AggregateFunction, column: usize) -> Self {
+        Self {
+            distinct: true,
+            ..Self::new(function, column)
+        }
+    }
+
+    pub fn count_star() -> Self {
+        Self {
+            function: AggregateFunction::Count,
+            column: None,
+            distinct: false,
+        }
+    }
+}
+
+/// The running state of an aggregate over one group.
+#[derive(Debug)]
+enum Accumulator {
+    Count(i64),
+    Sum(Value),
+    Min(Value),
+    Max(Value),
+    Avg { sum: f64, count: i64 },
+}
+
+impl Accumulator {
+    fn new(function: AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::Count => Self::Count(0),
+            AggregateFunction::Sum => Self::Sum(Value::Null),
+            AggregateFunction::Min => Self::Min(Value::Null),
+            AggregateFunction::Max => Self::Max(Value::Null),
+            AggregateFunction::Avg => Self::Avg { sum: 0.0, count: 0 },
+        }
+    }
+
+    /// Feeds a value to the accumulator, NULLs are ignored (COUNT(*) is fed
+    /// with `Value::Boolean(true)` for every row).
+    fn update(&mut self, value: &Value) -> Result<(), ExecError> {
+        if value.is_null() {
+            return Ok(());
+        }
+
+        match self {
+            Self::Count(count) => *count += 1,
+            Self::Sum(sum) => {
+                *sum = match (&*sum, value) {
+                    (Value::Null, Value::Integer(_) | Value::Float(_)) => value.clone(),
+                    (Value::Integer(lhs), Value::Integer(rhs)) => {
+                        Value::Integer(lhs.checked_add(*rhs).ok_or(ExecError::IntegerOverflow)?)
+                    }
+                    (Value::Float(lhs), Value::Float(rhs)) => Value::Float(lhs + rhs),
+                    _ => return Err(invalid(AggregateFunction::Sum, value)),
+                }
+            }
+            Self::Min(min) => {
+                if min.is_null() || value < min {
+                    *min = value.clone();
+                }
+            }
+            Self::Max(max) => {
+                if max.is_null() || value > max {
+                    *max = value.clone();
+                }
+            }
+            Self::Avg { sum, count } => {
+                *sum += match value {
+                    Value::Integer(i) => *i as f64,
+                    Value::Float(f) => *f,
+                    _ => return Err(invalid(AggregateFunction::Avg, value)),
+                };
+                *count += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Self::Count(count) => Value::Integer(count),
+            Self::Sum(value) | Self::Min(value) | Self::Max(value) => value,
+            Self::Avg { count: 0, .. } => Value::Null,
+            Self::Avg { sum, count } => Value::Float(sum / count as f64),
+        }
+    }
+}
+
+/// The state of an aggregate over one group: DISTINCT aggregates also keep
+/// the values they were already fed.
+#[derive(Debug)]
+struct AggregateState {
+    accumulator: Accumulator,
+    seen: Option<HashSet<Value>>,
+}
+
+impl AggregateState {
+    fn new(aggregate: &Aggregate) -> Self {
+        Self {
+            accumulator: Accumulator::new(aggregate.function),
+            seen: aggregate.distinct.then(HashSet::new),
+        }
+    }
+
+    fn update(&mut self, value: &Value) -> Result<(), ExecError> {
+        if let Some(seen) = &mut self.seen
+            && !value.is_null()
+            && !seen.insert(value.clone())
+        {
+            return Ok(());
+        }
+
+        self.accumulator.update(value)
+    }
+
+    fn finish(self) -> Value {
+        self.accumulator.finish()
+    }
+}
+
+fn invalid(function: AggregateFunction, value: &Value) -> ExecError {
+    // NULLs are skipped before reaching here.
+    ExecError::InvalidAggregate(function, value.data_type().unwrap())
+}
+
+/// Hash aggregation: tuples are grouped on the values of the `group_by`
+/// columns and every group gets its own set of accumulators.
+///
+/// Output tuples are made of the group columns followed by the aggregates,
+/// groups are returned in the order they were first seen. NULLs are grouped
+/// together as in standard SQL.
+pub struct HashAggregate {
+    group_by: Vec<usize>,
+    aggregates: Vec<Aggregate>,
+    groups: HashMap<Vec<Value>, usize>,
+    accumulators: Vec<(Vec<Value>, Vec<AggregateState>)>,
+}
+
+impl HashAggregate {
+    pub fn new(group_by: Vec<usize>, aggregates: Vec<Aggregate>) -> Self {
+        Self {
+            group_by,
+            aggregates,
+            groups: HashMap::new(),
+            accumulators: Vec::new(),
+        }
+    }
+
+    /// Returns whether a group already has accumulators.
+    pub fn contains_group(&self, key: &[Value]) -> bool {
+        self.groups.contains_key(key)
+    }
+
+    /// Adds an input tuple to its group.
+    pub fn update(&mut self, tuple: &Tuple) -> Result<(), ExecError> {
+        let values = tuple.values();
+        let column = |idx: usize| values.get(idx).ok_or(ExecError::ColumnOutOfRange(idx));
+
+        let key = self
+            .group_by
+            .iter()
+            .map(|&idx| column(idx).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        let group = match self.groups.get(&key) {
+            Some(&group) => group,
+            None => {
+                let group = self.accumulators.len();
+                let accumulators = self.aggregates.iter().map(AggregateState::new).collect();
+                self.accumulators.push((key.clone(), accumulators));
+                self.groups.insert(key, group);
+                group
+            }
+        };
+
+        let (_, accumulators) = &mut self.accumulators[group];
+        for (aggregate, accumulator) in self.aggregates.iter().zip(accumulators) {
+            match aggregate.column {
+                Some(idx) => accumulator.update(column(idx)?)?,
+                None => accumulator.update(&Value::Boolean(true))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Consumes all the input tuples and returns the aggregated tuples.
+    pub fn aggregate<I>(mut self, input: I) -> Result<Vec<Tuple>, ExecError>
+    where
+        I: IntoIterator<Item = Tuple>,
+    {
+        for tuple in input {
+            self.update(&tuple)?;
+        }
+
+        self.finish()
+    }
+
+    /// Returns one tuple per group.
+    ///
+    /// Without GROUP BY, an empty input still produces a single row (e.g.
+    /// `SELECT COUNT(*)` on an empty table returns 0).
+    pub fn finish(mut self) -> Result<Vec<Tuple>, ExecError> {
+        if self.group_by.is_empty() && self.accumulators.is_empty() {
+            let accumulators = self.aggregates.iter().map(AggregateState::new).collect();
+            self.accumulators.push((Vec::new(), accumulators));
+        }
+
+        self.accumulators
+            .into_iter()
+            .map(|(mut values, accumulators)| {
+                values.extend(accumulators.into_iter().map(AggregateState::finish));
+                Tuple::try_new(values).map_err(ExecError::Tuple)
+            })
+            .collect()
+    }
+}
+
+/// Runs a `HashAggregate` over the tuples of its input.
+///
+/// The whole input is consumed on the first call, the groups are then returned
+/// in batches. The GROUP BY expressions and the arguments of the aggregates
+/// are evaluated for each input tuple before being fed to the hash table.
+///
+/// Once the memory budget is exhausted, the tuples of new groups are spilled
+/// to partitions on their group values, while the groups already in memory
+/// keep being updated. The partitions are then aggregated one at a time.
+pub struct HashAggregation<'a> {
+    input: Box<dyn Executor + 'a>,
+    group_by: Vec<Evaluator>,
+    aggregates: &'a [AggregateExpr],
+    // The argument of each aggregate, `None` for COUNT(*).
+    args: Vec<Option<Evaluator>>,
+    budget: MemoryBudget,
+    reservation: Reservation,
+    // `None` until the input is consumed.
+    output: Option<std::vec::IntoIter<Tuple>>,
+    // The spilled partitions left to aggregate.
+    partitions: Vec<SpillReader>,
+}
+
+impl<'a> HashAggregation<'a> {
+    pub fn new(
+        input: Box<dyn Executor + 'a>,
+        group_by: &'a [Expr],
+        aggregates: &'a [AggregateExpr],
+        budget: MemoryBudget,
+    ) -> Self {
+        Self {
+            input,
+            group_by: group_by.iter().map(Evaluator::new).collect(),
+            aggregates,
+            args: aggregates
+                .iter()
+                .map(|aggregate| aggregate.arg.as_ref().map(Evaluator::new))
+                .collect(),
+            reservation: budget.reservation(),
+            budget,
+            output: None,
+            partitions: Vec::new(),
+        }
+    }
+
+    /// The aggregate hash table: the tuples fed to it are made of the group
+    /// values followed by one argument per aggregate (NULL for COUNT(*)).
+    fn hash_aggregate(&self) -> HashAggregate {
+        let group_len = self.group_by.len();
+        let aggregates = self
+            .aggregates
+            .iter()
+            .enumerate()
+            .map(|(i, aggregate)| match aggregate.arg {
+                Some(_) if aggregate.distinct => {
+                    Aggregate::distinct(aggregate.function, group_len + i)
+                }
+                Some(_) => Aggregate::new(aggregate.function, group_len + i),
+                None => Aggregate::count_star(),
+            })
+            .collect();
+        HashAggregate::new((0..group_len).collect(), aggregates)
+    }
+
+    fn aggregate(&mut self) -> Result<Vec<Tuple>, ExecError> {
+        let group_len = self.group_by.len();
+        let mut hash_aggregate = self.hash_aggregate();
+        let mut partitions = None;
+
+        while let Some(batch) = self.input.next_batch()? {
+            for tuple in &batch {
+                let values = self
+                    .group_by
+                    .iter()
+                    .map(Some)
+                    .chain(self.args.iter().map(Option::as_ref))
+                    .map(|expr| expr.map_or(Ok(Value::Null), |expr| expr.evaluate(tuple.values())))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let tuple = Tuple::try_new(values)?;
+
+                let key = &tuple.values()[..group_len];
+                // Without GROUP BY there is a single group, never spilled.
+                if group_len > 0 && !hash_aggregate.contains_group(key) {
+                    if partitions.is_none() && !self.reservation.try_grow(memory_size(&tuple)) {
+                        partitions = Some(SpillPartitions::new(&self.budget)?);
+                    }
+                    if let Some(partitions) = &mut partitions {
+                        partitions.write(key, &tuple)?;
+                        continue;
+                    }
+                }
+                hash_aggregate.update(&tuple)?;
+            }
+        }
+
+        if let Some(partitions) = partitions {
+            self.partitions = partitions.into_readers()?;
+            self.partitions.reverse();
+        }
+        hash_aggregate.finish()
+    }
+
+    /// Aggregates a spilled partition, its groups are assumed to fit in
+    /// memory.
+    fn aggregate_partition(&mut self, mut partition: SpillReader) -> Result<Vec<Tuple>, ExecError> {
+        self.reservation.free();
+        let mut hash_aggregate = self.hash_aggregate();
+        while let Some(tuple) = partition.read()? {
+            if !hash_aggregate.contains_group(&tuple.values()[..self.group_by.len()]) {
+                self.reservation.grow(memory_size(&tuple));
+            }
+            hash_aggregate.update(&tuple)?;
+        }
+        hash_aggregate.finish()
+    }
+}
+
+impl Executor for HashAggregation<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if self.output.is_none() {
+            self.output = Some(self.aggregate()?.into_iter());
+        }
+
+        loop {
+            let output = self.output.as_mut().unwrap();
+            let batch = output.take(BATCH_SIZE).collect::<Vec<_>>();
+            if !batch.is_empty() {
+                return Ok(Some(batch));
+            }
+
+            let Some(partition) = self.partitions.pop() else {
+                return Ok(None);
+            };
+            self.output = Some(self.aggregate_partition(partition)?.into_iter());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuple(values: Vec<Value>) -> Tuple {
+        Tuple::try_new(values).unwrap()
+    }
+
+    fn rows(tuples: Vec<Tuple>) -> Vec<Vec<Value>> {
+        tuples.iter().map(|t| t.values().to_vec()).collect()
+    }
+
+    #[test]
+    fn group_by() {
+        let input = vec![
+            tuple(vec![Value::VarChar("a".into()), Value::Integer(1)]),
+            tuple(vec![Value::VarChar("b".into()), Value::Integer(10)]),
+            tuple(vec![Value::VarChar("a".into()), Value::Integer(3)]),
+            tuple(vec![Value::Null, Value::Integer(5)]),
+            tuple(vec![Value::VarChar("b".into()), Value::Null]),
+            tuple(vec![Value::Null, Value::Integer(7)]),
+        ];
+        let aggregate = HashAggregate::new(
+            vec![0],
+            vec![
+                Aggregate::count_star(),
+                Aggregate::new(AggregateFunction::Count, 1),
+                Aggregate::new(AggregateFunction::Sum, 1),
+                Aggregate::new(AggregateFunction::Min, 1),
+                Aggregate::new(AggregateFunction::Max, 1),
+                Aggregate::new(AggregateFunction::Avg, 1),
+            ],
+        );
+
+        let output = rows(aggregate.aggregate(input).unwrap());
+        use Value::*;
+        assert_eq!(
+            output,
+            [
+                vec![
+                    VarChar("a".into()),
+                    Integer(2),
+                    Integer(2),
+                    Integer(4),
+                    Integer(1),
+                    Integer(3),
+                    Float(2.0)
+                ],
+                vec![
+                    VarChar("b".into()),
+                    Integer(2),
+                    Integer(1),
+                    Integer(10),
+                    Integer(10),
+                    Integer(10),
+                    Float(10.0)
+                ],
+                vec![
+                    Null,
+                    Integer(2),
+                    Integer(2),
+                    Integer(12),
+                    Integer(5),
+                    Integer(7),
+                    Float(6.0)
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_input() {
+        let aggregates = vec![
+            Aggregate::count_star(),
+            Aggregate::new(AggregateFunction::Sum, 0),
+            Aggregate::new(AggregateFunction::Avg, 0),
+        ];
+
+        let output = HashAggregate::new(vec![], aggregates.clone())
+            .aggregate(vec![])
+            .unwrap();
+        assert_eq!(
+            rows(output),
+            [vec![Value::Integer(0), Value::Null, Value::Null]]
+        );
+
+        let output = HashAggregate::new(vec![0], aggregates)
+            .aggregate(vec![])
+            .unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn invalid_aggregates() {
+        let sum = HashAggregate::new(vec![], vec![Aggregate::new(AggregateFunction::Sum, 0)]);
+        let input = vec![tuple(vec![Value::VarChar("a".into())])];
+        assert!(matches!(
+            sum.aggregate(input),
+            Err(ExecError::InvalidAggregate(AggregateFunction::Sum, _))
+        ));
+
+        let sum = HashAggregate::new(vec![], vec![Aggregate::new(AggregateFunction::Sum, 0)]);
+        let input = vec![
+            tuple(vec![Value::Integer(i64::MAX)]),
+            tuple(vec![Value::Integer(1)]),
+        ];
+        assert!(matches!(
+            sum.aggregate(input),
+            Err(ExecError::IntegerOverflow)
+        ));
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::sql::exec::{ExecError, Executor};
+use crate::tuple::Tuple;
+
+/// Cancels the queries it was handed to, e.g. from a Ctrl-C handler or a
+/// timeout thread. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the queries fail with `ExecError::QueryCancelled` the next time
+    /// one of their operators asks for a batch.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Checks the cancellation token before pulling each batch of its input.
+pub struct Cancellable<'a> {
+    input: Box<dyn Executor + 'a>,
+    token: CancellationToken,
+}
+
+impl<'a> Cancellable<'a> {
+    pub fn new(input: Box<dyn Executor + 'a>, token: CancellationToken) -> Self {
+        Self { input, token }
+    }
+}
+
+impl Executor for Cancellable<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if self.token.is_cancelled() {
+            return Err(ExecError::QueryCancelled);
+        }
+
+        self.input.next_batch()
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+use crate::sql::exec::{
+    ExecError, Executor, MemoryBudget, Reservation, SpillPartitions, SpillReader, memory_size,
+};
+use crate::sql::types::Value;
+use crate::tuple::Tuple;
+
+/// Removes the duplicate tuples of its input with a hash set of the tuples
+/// already seen, NULLs are not distinct from each other.
+///
+/// Unlike a grouping, tuples are streamed: each one is returned as soon as it
+/// is first seen, in input order. Once the memory budget is exhausted, the
+/// tuples not seen yet are spilled to partitions which are de-duplicated one
+/// at a time at the end of the input.
+pub struct HashDistinct<'a> {
+    // `None` once exhausted.
+    input: Option<Box<dyn Executor + 'a>>,
+    seen: HashSet<Vec<Value>>,
+    budget: MemoryBudget,
+    reservation: Reservation,
+    // Set once the budget is exhausted.
+    partitions: Option<SpillPartitions>,
+    // The spilled partitions left to de-duplicate, then the partition being
+    // de-duplicated.
+    spilled: Vec<SpillReader>,
+    partition: Option<SpillReader>,
+}
+
+impl<'a> HashDistinct<'a> {
+    pub fn new(input: Box<dyn Executor + 'a>, budget: MemoryBudget) -> Self {
+        Self {
+            input: Some(input),
+            seen: HashSet::new(),
+            reservation: budget.reservation(),
+            budget,
+            partitions: None,
+            spilled: Vec::new(),
+            partition: None,
+        }
+    }
+
+    /// Returns whether a tuple is seen for the first time, spills it if it
+    /// doesn't fit in memory.
+    fn first_seen(&mut self, tuple: &Tuple) -> Result<bool, ExecError> {
+        if self.seen.contains(tuple.values()) {
+            return Ok(false);
+        }
+        if self.partitions.is_none() && !self.reservation.try_grow(memory_size(tuple)) {
+            self.partitions = Some(SpillPartitions::new(&self.budget)?);
+        }
+        match &mut self.partitions {
+            Some(partitions) => {
+                partitions.write(tuple.values(), tuple)?;
+                Ok(false)
+            }
+            None => Ok(self.seen.insert(tuple.values().to_vec())),
+        }
+    }
+
+    /// Returns the next batch of distinct tuples of the spilled partitions,
+    /// each of them is assumed to fit in memory.
+    fn next_spilled_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        loop {
+            if let Some(partition) = &mut self.partition {
+                while let Some(batch) = partition.next_batch()? {
+                    let mut output = Vec::new();
+                    for tuple in batch {
+                        if !self.seen.contains(tuple.values()) {
+                            self.reservation.grow(memory_size(&tuple));
+                            self.seen.insert(tuple.values().to_vec());
+                            output.push(tuple);
+                        }
+                    }
+                    if !output.is_empty() {
+                        return Ok(Some(output));
+                    }
+                }
+            }
+
+            let Some(partition) = self.spilled.pop() else {
+                return Ok(None);
+            };
+            // Partitions have no tuples in common.
+            self.seen.clear();
+            self.reservation.free();
+            self.partition = Some(partition);
+        }
+    }
+}
+
+impl Executor for HashDistinct<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        while let Some(input) = &mut self.input {
+            let Some(batch) = input.next_batch()? else {
+                self.input = None;
+                break;
+            };
+            let mut output = Vec::with_capacity(batch.len());
+            for tuple in batch {
+                if self.first_seen(&tuple)? {
+                    output.push(tuple);
+                }
+            }
+
+            // Don't return empty batches, they would end the query.
+            if !output.is_empty() {
+                return Ok(Some(output));
+            }
+        }
+
+        if let Some(partitions) = self.partitions.take() {
+            self.spilled = partitions.into_readers()?;
+            self.spilled.reverse();
+        }
+        self.next_spilled_batch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns its batches one after the other.
+    struct Batches(std::vec::IntoIter<Vec<Tuple>>);
+
+    impl Executor for Batches {
+        fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+            Ok(self.0.next())
+        }
+    }
+
+    fn batch(rows: &[(Value, Value)]) -> Vec<Tuple> {
+        rows.iter()
+            .map(|(a, b)| Tuple::try_new(vec![a.clone(), b.clone()]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn hash_distinct() {
+        use Value::*;
+        let input = Batches(
+            vec![
+                batch(&[(Integer(1), Null), (Integer(2), Null), (Integer(1), Null)]),
+                // Only duplicates: skipped rather than ending the stream.
+                batch(&[(Integer(2), Null)]),
+                batch(&[(Integer(1), VarChar("a".into())), (Integer(2), Null)]),
+            ]
+            .into_iter(),
+        );
+        let mut distinct = HashDistinct::new(Box::new(input), MemoryBudget::unlimited());
+
+        let mut batches = Vec::new();
+        while let Some(batch) = distinct.next_batch().unwrap() {
+            let rows = batch.iter().map(|tuple| tuple.values().to_vec());
+            batches.push(rows.collect::<Vec<_>>());
+        }
+        assert_eq!(
+            batches,
+            [
+                vec![vec![Integer(1), Null], vec![Integer(2), Null]],
+                vec![vec![Integer(1), VarChar("a".into())]],
+            ]
+        );
+    }
+}
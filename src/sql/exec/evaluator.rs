@@ -0,0 +1,293 @@
+use std::cmp::Ordering;
+
+use crate::sql::exec::ExecError;
+use crate::sql::exec::expr::{
+    and, boolean, evaluate_binary, evaluate_unary, invalid_operands, like,
+};
+use crate::sql::plan::Expr;
+use crate::sql::types::Value;
+
+type Eval = Box<dyn Fn(&[Value]) -> Result<Value, ExecError>>;
+
+/// A bound scalar expression compiled into closures: the expression tree is
+/// walked once, then evaluated against the values of each input tuple.
+///
+/// NULLs propagate through operators and comparisons, AND, OR and NOT use
+/// three valued logic. Integer arithmetic fails on overflow rather than
+/// wrapping, integers are coerced to floats when mixed with floats.
+pub struct Evaluator {
+    eval: Eval,
+}
+
+impl Evaluator {
+    pub fn new(expr: &Expr) -> Self {
+        Self {
+            eval: compile(expr).eval,
+        }
+    }
+
+    /// Evaluates the expression, columns are positions in `values`.
+    pub fn evaluate(&self, values: &[Value]) -> Result<Value, ExecError> {
+        (self.eval)(values)
+    }
+
+    /// Returns whether a predicate is true: false and NULL are not.
+    pub fn satisfies(&self, values: &[Value]) -> Result<bool, ExecError> {
+        match self.evaluate(values)? {
+            Value::Boolean(b) => Ok(b),
+            Value::Null => Ok(false),
+            value => Err(ExecError::NotBoolean(value.data_type().unwrap())),
+        }
+    }
+}
+
+struct Compiled {
+    eval: Eval,
+    // Whether the expression reads no column.
+    constant: bool,
+}
+
+impl Compiled {
+    fn new(eval: Eval, constant: bool) -> Self {
+        Self { eval, constant }
+    }
+
+    /// Evaluates a constant expression once. Those failing, e.g. `1 / 0`, are
+    /// kept as is: the error is only raised if they are evaluated.
+    fn fold(self) -> Self {
+        if !self.constant {
+            return self;
+        }
+        match (self.eval)(&[]) {
+            Ok(value) => Self::new(Box::new(move |_| Ok(value.clone())), true),
+            Err(_) => self,
+        }
+    }
+}
+
+fn compile_all(exprs: &[Expr]) -> (Vec<Eval>, bool) {
+    let compiled = exprs.iter().map(compile).collect::<Vec<_>>();
+    let constant = compiled.iter().all(|compiled| compiled.constant);
+    (compiled.into_iter().map(|c| c.eval).collect(), constant)
+}
+
+fn compile(expr: &Expr) -> Compiled {
+    let compiled = match expr {
+        Expr::Column(idx) => {
+            let idx = *idx;
+            let eval = move |values: &[Value]| {
+                values
+                    .get(idx)
+                    .cloned()
+                    .ok_or(ExecError::ColumnOutOfRange(idx))
+            };
+            return Compiled::new(Box::new(eval), false);
+        }
+        Expr::Literal(value) => {
+            let value = value.clone();
+            return Compiled::new(Box::new(move |_| Ok(value.clone())), true);
+        }
+        Expr::Binary { op, lhs, rhs } => {
+            let (op, lhs, rhs) = (*op, compile(lhs), compile(rhs));
+            let constant = lhs.constant && rhs.constant;
+            let eval = move |values: &[Value]| {
+                evaluate_binary(op, (lhs.eval)(values)?, (rhs.eval)(values)?)
+            };
+            Compiled::new(Box::new(eval), constant)
+        }
+        Expr::Unary { op, expr } => {
+            let (op, expr) = (*op, compile(expr));
+            let constant = expr.constant;
+            let eval = move |values: &[Value]| evaluate_unary(op, (expr.eval)(values)?);
+            Compiled::new(Box::new(eval), constant)
+        }
+        Expr::Function { function, args } => {
+            let function = *function;
+            let (args, constant) = compile_all(args);
+            let eval = move |values: &[Value]| {
+                let args = args
+                    .iter()
+                    .map(|arg| arg(values))
+                    .collect::<Result<Vec<_>, _>>()?;
+                function.call(&args)
+            };
+            Compiled::new(Box::new(eval), constant)
+        }
+        Expr::Cast { expr, data_type } => {
+            let (data_type, expr) = (*data_type, compile(expr));
+            let constant = expr.constant;
+            let eval = move |values: &[Value]| Ok((expr.eval)(values)?.cast(data_type)?);
+            Compiled::new(Box::new(eval), constant)
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let (negated, expr) = (*negated, compile(expr));
+            let (list, constant) = compile_all(list);
+            let constant = constant && expr.constant;
+            let eval = move |values: &[Value]| {
+                let value = (expr.eval)(values)?;
+                if value.is_null() {
+                    return Ok(Value::Null);
+                }
+
+                // x IN (a, b) is x = a OR x = b: NULL if nothing matches and a
+                // comparison was NULL.
+                let mut result = Some(false);
+                for item in &list {
+                    match value.compare(&item(values)?)? {
+                        Some(Ordering::Equal) => {
+                            result = Some(true);
+                            break;
+                        }
+                        Some(_) => {}
+                        None => result = None,
+                    }
+                }
+                Ok(boolean(result.map(|b| b != negated)))
+            };
+            Compiled::new(Box::new(eval), constant)
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => {
+            let negated = *negated;
+            let (expr, low, high) = (compile(expr), compile(low), compile(high));
+            let constant = expr.constant && low.constant && high.constant;
+            let eval = move |values: &[Value]| {
+                let value = (expr.eval)(values)?;
+                let above = value
+                    .compare(&(low.eval)(values)?)?
+                    .map(|o| o != Ordering::Less);
+                let below = value
+                    .compare(&(high.eval)(values)?)?
+                    .map(|o| o != Ordering::Greater);
+                Ok(boolean(and(above, below).map(|b| b != negated)))
+            };
+            Compiled::new(Box::new(eval), constant)
+        }
+        Expr::Like {
+            expr,
+            pattern,
+            negated,
+        } => {
+            let (negated, expr, pattern) = (*negated, compile(expr), compile(pattern));
+            let constant = expr.constant && pattern.constant;
+            let eval = move |values: &[Value]| {
+                Ok(match ((expr.eval)(values)?, (pattern.eval)(values)?) {
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (Value::VarChar(text), Value::VarChar(pattern)) => {
+                        Value::Boolean(like(&text, &pattern) != negated)
+                    }
+                    (lhs, rhs) => return Err(invalid_operands("LIKE", &lhs, &rhs)),
+                })
+            };
+            Compiled::new(Box::new(eval), constant)
+        }
+        // Subqueries are run by the executors of their expression, which
+        // replace them with their results, see `subquery::SubqueryApply`.
+        Expr::Subquery(_) | Expr::Param(_) => {
+            return Compiled::new(
+                Box::new(|_| Err(ExecError::Unsupported("unplanned subqueries"))),
+                false,
+            );
+        }
+    };
+
+    compiled.fold()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sql::plan::BinaryOp;
+
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    #[test]
+    fn evaluate_rows() {
+        // (#0 + 1) * 2 > #1
+        let expr = binary(
+            BinaryOp::Greater,
+            binary(
+                BinaryOp::Mul,
+                binary(
+                    BinaryOp::Plus,
+                    Expr::Column(0),
+                    Expr::Literal(Value::Integer(1)),
+                ),
+                Expr::Literal(Value::Integer(2)),
+            ),
+            Expr::Column(1),
+        );
+        let evaluator = Evaluator::new(&expr);
+
+        let rows = [
+            ([Value::Integer(1), Value::Integer(3)], Value::Boolean(true)),
+            (
+                [Value::Integer(1), Value::Float(4.5)],
+                Value::Boolean(false),
+            ),
+            ([Value::Null, Value::Integer(3)], Value::Null),
+        ];
+        for (values, expected) in rows {
+            assert_eq!(evaluator.evaluate(&values).unwrap(), expected);
+        }
+        assert!(!evaluator.satisfies(&[Value::Null, Value::Null]).unwrap());
+        assert!(matches!(
+            evaluator.evaluate(&[Value::Integer(i64::MAX), Value::Integer(0)]),
+            Err(ExecError::IntegerOverflow)
+        ));
+        assert!(matches!(
+            evaluator.evaluate(&[Value::Integer(1)]),
+            Err(ExecError::ColumnOutOfRange(1))
+        ));
+        assert!(matches!(
+            Evaluator::new(&Expr::Column(0)).satisfies(&[Value::Integer(1)]),
+            Err(ExecError::NotBoolean(_))
+        ));
+    }
+
+    #[test]
+    fn constant_folding() {
+        // 1 + 2 is folded, the column is still read.
+        let expr = binary(
+            BinaryOp::Plus,
+            Expr::Column(0),
+            binary(
+                BinaryOp::Plus,
+                Expr::Literal(Value::Integer(1)),
+                Expr::Literal(Value::Integer(2)),
+            ),
+        );
+        let evaluator = Evaluator::new(&expr);
+        assert_eq!(
+            evaluator.evaluate(&[Value::Integer(1)]).unwrap(),
+            Value::Integer(4)
+        );
+
+        // Failing constants only fail when evaluated.
+        let expr = binary(
+            BinaryOp::Div,
+            Expr::Literal(Value::Integer(1)),
+            Expr::Literal(Value::Integer(0)),
+        );
+        let evaluator = Evaluator::new(&expr);
+        assert!(matches!(
+            evaluator.evaluate(&[]),
+            Err(ExecError::DivisionByZero)
+        ));
+    }
+}
@@ -0,0 +1,1286 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::indexes::BTree;
+use crate::pages::PageId;
+use crate::sql::exec::cancel::{Cancellable, CancellationToken};
+use crate::sql::exec::distinct::HashDistinct;
+use crate::sql::exec::index_scan::{
+    FullTextScan, IndexOnlyScan, IndexScan, fulltext_search, index_only, index_order, index_range,
+};
+use crate::sql::exec::insert::{Insert, reads_table};
+use crate::sql::exec::join::{HashJoin, IndexNestedLoopJoin, NestedLoopJoin, index_lookup};
+use crate::sql::exec::parallel::ParallelSeqScan;
+use crate::sql::exec::set_global::SetGlobal;
+use crate::sql::exec::sort::Sort;
+use crate::sql::exec::subquery::{apply, keeping_columns};
+use crate::sql::exec::vacuum::Vacuum;
+use crate::sql::exec::{Evaluator, ExecError, HashAggregation, MemoryBudget};
+use crate::sql::plan::{
+    Expr, JoinStrategy, LogicalPlan, PlanSchema, SchemaProvider, SortKey, TableStats,
+    equi_join_keys,
+};
+use crate::sql::schema::Schema;
+use crate::storage::StorageBackend;
+use crate::table::{Table, TableIterator};
+use crate::tuple::Tuple;
+
+/// The number of tuples operators try to put in a batch.
+pub const BATCH_SIZE: usize = 1024;
+
+/// A physical operator: tuples are pulled from the root of the tree, which
+/// pulls them from its inputs.
+pub trait Executor {
+    /// Returns the next batch of tuples, `None` once the operator is
+    /// exhausted. Batches are never empty.
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError>;
+}
+
+/// Produces a single tuple without columns.
+pub struct SingleRow {
+    done: bool,
+}
+
+impl SingleRow {
+    pub fn new() -> Self {
+        Self { done: false }
+    }
+}
+
+impl Default for SingleRow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor for SingleRow {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if std::mem::replace(&mut self.done, true) {
+            return Ok(None);
+        }
+
+        Ok(Some(vec![Tuple::try_new(Vec::new())?]))
+    }
+}
+
+/// Reads all the tuples of a table, in storage order.
+pub struct SeqScan<'table, S: StorageBackend + 'static> {
+    iter: TableIterator<'table, S>,
+}
+
+impl<'table, S: StorageBackend + 'static> SeqScan<'table, S> {
+    pub fn new(table: &'table Table<S>) -> Self {
+        Self { iter: table.iter() }
+    }
+
+    /// Only decodes the given columns, see `Table::iter_columns`.
+    pub fn with_columns(table: &'table Table<S>, columns: &'table [usize]) -> Self {
+        Self {
+            iter: table.iter_columns(columns),
+        }
+    }
+
+    /// Only reads the heap pages `first..=last`, see `Table::iter_pages`.
+    pub fn with_pages(
+        table: &'table Table<S>,
+        first: PageId,
+        last: PageId,
+        columns: Option<&'table [usize]>,
+    ) -> Self {
+        Self {
+            iter: table.iter_pages(first, last, columns),
+        }
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for SeqScan<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let batch = self.iter.by_ref().take(BATCH_SIZE).collect::<Vec<_>>();
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// Keeps the tuples for which the predicate is true: false and NULL both
+/// filter the tuple out.
+pub struct Filter<'a> {
+    input: Box<dyn Executor + 'a>,
+    predicate: Evaluator,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new(input: Box<dyn Executor + 'a>, predicate: &Expr) -> Self {
+        Self {
+            input,
+            predicate: Evaluator::new(predicate),
+        }
+    }
+}
+
+impl Executor for Filter<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        while let Some(batch) = self.input.next_batch()? {
+            let mut output = Vec::with_capacity(batch.len());
+            for tuple in batch {
+                if self.predicate.satisfies(tuple.values())? {
+                    output.push(tuple);
+                }
+            }
+
+            // Don't return empty batches, they would end the query.
+            if !output.is_empty() {
+                return Ok(Some(output));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Computes an output tuple out of each input tuple.
+pub struct Projection<'a> {
+    input: Box<dyn Executor + 'a>,
+    exprs: Vec<Evaluator>,
+}
+
+impl<'a> Projection<'a> {
+    pub fn new(input: Box<dyn Executor + 'a>, exprs: &[Expr]) -> Self {
+        Self {
+            input,
+            exprs: exprs.iter().map(Evaluator::new).collect(),
+        }
+    }
+}
+
+impl Executor for Projection<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let Some(batch) = self.input.next_batch()? else {
+            return Ok(None);
+        };
+
+        batch
+            .iter()
+            .map(|tuple| {
+                let values = self
+                    .exprs
+                    .iter()
+                    .map(|expr| expr.evaluate(tuple.values()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Tuple::try_new(values)?)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+}
+
+/// Skips the first `offset` tuples of its input, then returns at most `limit`
+/// of them: the input isn't read further.
+pub struct Limit<'a> {
+    input: Box<dyn Executor + 'a>,
+    // `None` for no limit.
+    remaining: Option<u64>,
+    offset: u64,
+}
+
+impl<'a> Limit<'a> {
+    pub fn new(input: Box<dyn Executor + 'a>, limit: Option<u64>, offset: u64) -> Self {
+        Self {
+            input,
+            remaining: limit,
+            offset,
+        }
+    }
+}
+
+impl Executor for Limit<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        while self.remaining != Some(0)
+            && let Some(mut batch) = self.input.next_batch()?
+        {
+            let skipped = batch.len().min(self.offset as usize);
+            batch.drain(..skipped);
+            self.offset -= skipped as u64;
+            if let Some(remaining) = &mut self.remaining {
+                batch.truncate(batch.len().min(*remaining as usize));
+                *remaining -= batch.len() as u64;
+            }
+
+            // Don't return empty batches, they would end the query.
+            if !batch.is_empty() {
+                return Ok(Some(batch));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// The tables a query runs on, along with their indexes and statistics.
+pub struct Tables<S: StorageBackend + 'static> {
+    tables: HashMap<String, Table<S>>,
+    stats: HashMap<String, TableStats>,
+    // The number of threads scanning a table, 1 for sequential scans.
+    workers: usize,
+}
+
+impl<S: StorageBackend + 'static> Tables<S> {
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+            stats: HashMap::new(),
+            workers: 1,
+        }
+    }
+
+    pub fn add_table(&mut self, table: Table<S>) {
+        self.tables.insert(table.name.clone(), table);
+    }
+
+    /// Registers an index on a column of a table added before, see
+    /// `Table::add_index`.
+    pub fn add_index(
+        &mut self,
+        table: &str,
+        column: &str,
+        index: BTree<S>,
+    ) -> Result<(), ExecError> {
+        let table = self
+            .tables
+            .get_mut(table)
+            .ok_or_else(|| ExecError::UnknownTable(table.to_string()))?;
+        let column = table
+            .schema
+            .columns()
+            .iter()
+            .position(|c| c.column_name == column)
+            .ok_or_else(|| ExecError::UnknownColumn(column.to_string()))?;
+        table.add_index(column, index);
+        Ok(())
+    }
+
+    /// Gives the planner the statistics of a table, usually read from the
+    /// catalog.
+    pub fn set_stats(&mut self, table: &str, stats: TableStats) {
+        self.stats.insert(table.to_string(), stats);
+    }
+
+    /// Scans the tables with `workers` threads, tuples are then no longer
+    /// returned in storage order.
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = workers.max(1);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Table<S>> {
+        self.tables.get(name)
+    }
+
+    pub fn index(&self, table: &str, column: &str) -> Option<&BTree<S>> {
+        let table = self.get(table)?;
+        let column = table
+            .schema
+            .columns()
+            .iter()
+            .position(|c| c.column_name == column)?;
+        table.index(column)
+    }
+}
+
+impl<S: StorageBackend + 'static> Default for Tables<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: StorageBackend + 'static> SchemaProvider for Tables<S> {
+    fn table_schema(&self, name: &str) -> Option<Schema> {
+        self.get(name).map(|table| table.schema.clone())
+    }
+
+    fn table_stats(&self, name: &str) -> Option<TableStats> {
+        self.stats.get(name).copied()
+    }
+
+    fn has_index(&self, table: &str, column: &str) -> bool {
+        self.index(table, column).is_some()
+    }
+}
+
+/// The state shared by the operators of a query.
+#[derive(Clone, Debug, Default)]
+pub struct QueryContext {
+    pub token: CancellationToken,
+    pub budget: MemoryBudget,
+}
+
+/// Builds the tree of physical operators running a plan. Each operator checks
+/// the cancellation token before pulling a batch from its input.
+pub fn build<'a, S: StorageBackend + 'static>(
+    plan: &'a LogicalPlan,
+    tables: &'a Tables<S>,
+    context: &QueryContext,
+) -> Result<Box<dyn Executor + 'a>, ExecError> {
+    let operator = build_operator(plan, tables, context)?;
+    Ok(Box::new(Cancellable::new(operator, context.token.clone())))
+}
+
+/// Describes the operators running a plan, one per line like the plan
+/// itself: the filters reading a table through an index show the index scan
+/// in place of the table scan, as do the sorts reading it in index order.
+pub fn explain<S: StorageBackend + 'static>(plan: &LogicalPlan, tables: &Tables<S>) -> String {
+    let mut output = String::new();
+    explain_indent(plan, tables, 0, &mut output);
+    output
+}
+
+fn explain_indent<S: StorageBackend + 'static>(
+    plan: &LogicalPlan,
+    tables: &Tables<S>,
+    depth: usize,
+    output: &mut String,
+) {
+    write!(output, "{:width$}", "", width = depth * 2).unwrap();
+    plan.fmt_node(output).unwrap();
+    output.push('\n');
+
+    if let LogicalPlan::Filter { input, predicate } = plan
+        && let Some((table, column, _, range)) = index_range(input, predicate, tables)
+    {
+        let scan = if index_only(input, table, column) {
+            "Index Only Scan"
+        } else {
+            "Index Scan"
+        };
+        writeln!(
+            output,
+            "{:width$}{scan} {} on {} {range}",
+            "",
+            table.name,
+            table.schema.columns()[column].column_name,
+            width = (depth + 1) * 2
+        )
+        .unwrap();
+        return;
+    }
+    if let LogicalPlan::Filter { input, predicate } = plan
+        && let Some((table, column, _, query)) = fulltext_search(input, predicate, tables)
+    {
+        writeln!(
+            output,
+            "{:width$}Full Text Scan {} on {} [contains '{query}']",
+            "",
+            table.name,
+            table.schema.columns()[column].column_name,
+            width = (depth + 1) * 2
+        )
+        .unwrap();
+        return;
+    }
+    if let LogicalPlan::Sort { input, keys } = plan
+        && let Some((table, key, _, backward)) = index_order(input, keys, tables)
+    {
+        let mut depth = depth + 1;
+        if let LogicalPlan::Filter { .. } = input.as_ref() {
+            write!(output, "{:width$}", "", width = depth * 2).unwrap();
+            input.fmt_node(output).unwrap();
+            output.push('\n');
+            depth += 1;
+        }
+        let scan = if backward {
+            "Index Scan Backward"
+        } else {
+            "Index Scan"
+        };
+        let columns = key
+            .iter()
+            .map(|&(column, options)| {
+                let order = if options.descending { "DESC" } else { "ASC" };
+                format!("{} {order}", table.schema.columns()[column].column_name)
+            })
+            .collect::<Vec<_>>();
+        writeln!(
+            output,
+            "{:width$}{scan} {} on {}",
+            "",
+            table.name,
+            columns.join(", "),
+            width = depth * 2
+        )
+        .unwrap();
+        return;
+    }
+    for input in plan.inputs() {
+        explain_indent(input, tables, depth + 1, output);
+    }
+}
+
+fn build_operator<'a, S: StorageBackend + 'static>(
+    plan: &'a LogicalPlan,
+    tables: &'a Tables<S>,
+    context: &QueryContext,
+) -> Result<Box<dyn Executor + 'a>, ExecError> {
+    Ok(match plan {
+        LogicalPlan::SingleRow => Box::new(SingleRow::new()),
+        LogicalPlan::Scan { table, columns, .. } => {
+            let table = tables
+                .get(table)
+                .ok_or_else(|| ExecError::UnknownTable(table.clone()))?;
+            match columns {
+                _ if tables.workers > 1 => Box::new(ParallelSeqScan::new(
+                    table,
+                    columns.as_deref(),
+                    tables.workers,
+                )),
+                Some(columns) => Box::new(SeqScan::with_columns(table, columns)),
+                None => Box::new(SeqScan::new(table)),
+            }
+        }
+        LogicalPlan::Filter { input, predicate } => {
+            let input = match index_range(input, predicate, tables) {
+                Some((table, column, index, range)) => {
+                    let scan: Box<dyn Executor> = if index_only(input, table, column) {
+                        Box::new(IndexOnlyScan::try_new(table, column, index, range)?)
+                    } else {
+                        Box::new(IndexScan::try_new(table, index, range)?)
+                    };
+                    Box::new(Cancellable::new(scan, context.token.clone()))
+                }
+                None => match fulltext_search(input, predicate, tables) {
+                    Some((table, _, index, query)) => {
+                        let scan = Box::new(FullTextScan::try_new(table, index, &query)?);
+                        Box::new(Cancellable::new(scan, context.token.clone()))
+                    }
+                    None => build(input, tables, context)?,
+                },
+            };
+            filter(input, plan.schema(), predicate, tables, context)?
+        }
+        LogicalPlan::Project { input, exprs, .. } => {
+            let width = input.schema().columns().len();
+            let (input, exprs) = apply(
+                build(input, tables, context)?,
+                width,
+                exprs,
+                tables,
+                context,
+            )?;
+            Box::new(Projection::new(input, &exprs))
+        }
+        LogicalPlan::Join {
+            kind,
+            left,
+            right,
+            on,
+            strategy,
+            ..
+        } => {
+            let outer = build(left, tables, context)?;
+            let on = on.as_ref();
+            // Falls back to a nested loop join when the strategy doesn't apply
+            // to the inputs.
+            let index = match strategy {
+                JoinStrategy::IndexNestedLoop => index_lookup(left, right, on, tables),
+                _ => None,
+            };
+            let keys = match strategy {
+                JoinStrategy::Hash => equi_join_keys(left, right, on),
+                _ => Vec::new(),
+            };
+            match index {
+                Some((outer_key, table, index)) => Box::new(IndexNestedLoopJoin::new(
+                    *kind, outer, outer_key, table, index, on,
+                )),
+                None if !keys.is_empty() => Box::new(HashJoin::new(
+                    *kind,
+                    outer,
+                    build(right, tables, context)?,
+                    right.schema().columns().len(),
+                    keys,
+                    on,
+                    context.budget.clone(),
+                )),
+                None => Box::new(NestedLoopJoin::new(
+                    *kind,
+                    outer,
+                    right,
+                    tables,
+                    context.clone(),
+                    on,
+                )),
+            }
+        }
+        // A grouping on all the columns without aggregates, i.e. a DISTINCT.
+        LogicalPlan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+            ..
+        } if aggregates.is_empty()
+            && group_by.len() == input.schema().columns().len()
+            && group_by
+                .iter()
+                .enumerate()
+                .all(|(idx, expr)| *expr == Expr::Column(idx)) =>
+        {
+            Box::new(HashDistinct::new(
+                build(input, tables, context)?,
+                context.budget.clone(),
+            ))
+        }
+        LogicalPlan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+            ..
+        } => Box::new(HashAggregation::new(
+            build(input, tables, context)?,
+            group_by,
+            aggregates,
+            context.budget.clone(),
+        )),
+        LogicalPlan::Sort { input, keys } => {
+            let Some((table, _, index, backward)) = index_order(input, keys, tables) else {
+                let exprs = keys.iter().map(|key| key.expr.clone()).collect::<Vec<_>>();
+                let width = input.schema().columns().len();
+                let input = build(input, tables, context)?;
+                return keeping_columns(input, width, &exprs, tables, context, |input, exprs| {
+                    let keys = keys
+                        .iter()
+                        .zip(exprs)
+                        .map(|(key, expr)| SortKey {
+                            expr: expr.clone(),
+                            descending: key.descending,
+                        })
+                        .collect::<Vec<_>>();
+                    Box::new(Sort::new(input, &keys, context.budget.clone()))
+                });
+            };
+            let scan = Box::new(IndexScan::ordered(table, index, backward)?);
+            let scan = Box::new(Cancellable::new(scan, context.token.clone()));
+            match input.as_ref() {
+                LogicalPlan::Filter { predicate, .. } => {
+                    filter(scan, input.schema(), predicate, tables, context)?
+                }
+                _ => scan,
+            }
+        }
+        LogicalPlan::Limit {
+            input,
+            limit,
+            offset,
+        } => Box::new(Limit::new(build(input, tables, context)?, *limit, *offset)),
+        LogicalPlan::Insert {
+            input,
+            table: name,
+            columns,
+            ..
+        } => {
+            let table = tables
+                .get(name)
+                .ok_or_else(|| ExecError::UnknownTable(name.clone()))?;
+            Box::new(Insert::new(
+                build(input, tables, context)?,
+                table,
+                columns,
+                reads_table(input, name),
+            ))
+        }
+        LogicalPlan::Vacuum { table, .. } => Box::new(Vacuum::new(tables, table)),
+        LogicalPlan::SetGlobal { setting } => Box::new(SetGlobal::new(*setting)),
+    })
+}
+
+/// Builds a filter on `input`, whose tuples are those of `schema`, running the
+/// subqueries of the predicate, see `subquery::apply`.
+fn filter<'a, S: StorageBackend + 'static>(
+    input: Box<dyn Executor + 'a>,
+    schema: &PlanSchema,
+    predicate: &Expr,
+    tables: &'a Tables<S>,
+    context: &QueryContext,
+) -> Result<Box<dyn Executor + 'a>, ExecError> {
+    let width = schema.columns().len();
+    let predicates = std::slice::from_ref(predicate);
+    keeping_columns(input, width, predicates, tables, context, |input, exprs| {
+        Box::new(Filter::new(input, &exprs[0]))
+    })
+}
+
+/// The rows returned by a query, pulled from the executor one batch at a time
+/// as they are iterated: only the current batch is held in memory, however
+/// large the result. Iteration ends after an error.
+pub struct RowStream<'a> {
+    schema: PlanSchema,
+    // `None` once the executor is exhausted or failed.
+    root: Option<Box<dyn Executor + 'a>>,
+    batch: std::vec::IntoIter<Tuple>,
+}
+
+impl<'a> RowStream<'a> {
+    /// Runs a plan over the given tables.
+    pub fn execute<S: StorageBackend + 'static>(
+        plan: &'a LogicalPlan,
+        tables: &'a Tables<S>,
+    ) -> Result<Self, ExecError> {
+        Self::execute_with(plan, tables, &QueryContext::default())
+    }
+
+    /// Runs a plan which fails with `ExecError::QueryCancelled` once the token
+    /// of `context` is cancelled, and spills once its budget is exhausted.
+    pub fn execute_with<S: StorageBackend + 'static>(
+        plan: &'a LogicalPlan,
+        tables: &'a Tables<S>,
+        context: &QueryContext,
+    ) -> Result<Self, ExecError> {
+        Ok(Self {
+            schema: plan.schema().clone(),
+            root: Some(build(plan, tables, context)?),
+            batch: Vec::new().into_iter(),
+        })
+    }
+
+    /// Returns the columns of the result tuples.
+    pub fn schema(&self) -> &PlanSchema {
+        &self.schema
+    }
+
+    /// Returns up to `size` more rows, less only once the stream is exhausted.
+    pub fn next_page(&mut self, size: usize) -> Result<Vec<Tuple>, ExecError> {
+        self.by_ref().take(size).collect()
+    }
+}
+
+impl Iterator for RowStream<'_> {
+    type Item = Result<Tuple, ExecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tuple) = self.batch.next() {
+                return Some(Ok(tuple));
+            }
+
+            match self.root.as_mut()?.next_batch() {
+                Ok(Some(batch)) => self.batch = batch.into_iter(),
+                Ok(None) => {
+                    self.root = None;
+                    return None;
+                }
+                Err(e) => {
+                    self.root = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan;
+    use crate::sql::schema::{Column, ConstraintsBuilder, DataType};
+    use crate::sql::types::Value;
+    use crate::storage::FileStorage;
+
+    const NR_ROWS: i64 = 3000;
+
+    fn users() -> Tables<FileStorage> {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let cache = GLOBAL_PAGE_CACHE.cache_storage(storage);
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+
+        let table = Table::try_new("users", &schema, cache).unwrap();
+        for id in 0..NR_ROWS {
+            // Every other user has no name.
+            let name = match id % 2 {
+                0 => Value::VarChar(format!("user{id}")),
+                _ => Value::Null,
+            };
+            table
+                .insert(&Tuple::try_new(vec![Value::Integer(id), name]).unwrap())
+                .unwrap();
+        }
+
+        let mut tables = Tables::new();
+        tables.add_table(table);
+        tables
+    }
+
+    fn query(tables: &Tables<FileStorage>, source: &str) -> Vec<Vec<Value>> {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        RowStream::execute(&plan, tables)
+            .unwrap()
+            .map(|tuple| tuple.unwrap().values().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn select() {
+        let tables = users();
+
+        assert_eq!(
+            query(&tables, "SELECT * FROM users").len(),
+            NR_ROWS as usize
+        );
+        assert_eq!(
+            query(&tables, "SELECT * FROM users WHERE id < 4 AND name != ''"),
+            query(&tables, "SELECT id, name FROM users WHERE id IN (0, 2)")
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT UPPER(name), id * 2 FROM users WHERE id IN (0, 2)"
+            ),
+            [
+                vec![Value::VarChar("USER0".into()), Value::Integer(0)],
+                vec![Value::VarChar("USER2".into()), Value::Integer(4)],
+            ]
+        );
+        // NULL predicates filter tuples out.
+        assert_eq!(
+            query(&tables, "SELECT id FROM users WHERE name LIKE 'user%'").len(),
+            NR_ROWS as usize / 2
+        );
+        // Filters spanning batches without a match.
+        assert_eq!(
+            query(&tables, "SELECT id FROM users WHERE id = 2999"),
+            [vec![Value::Integer(2999)]]
+        );
+        assert_eq!(query(&tables, "SELECT 1 + 1"), [vec![Value::Integer(2)]]);
+    }
+
+    #[test]
+    fn row_stream() {
+        let tables = users();
+
+        let stmts = Parser::parse("SELECT id, name FROM users").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let result = RowStream::execute(&plan, &tables).unwrap();
+        let columns = result
+            .schema()
+            .columns()
+            .iter()
+            .map(|column| (column.name.as_str(), column.data_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            [
+                ("id", Some(DataType::Integer)),
+                ("name", Some(DataType::VarChar(None)))
+            ]
+        );
+
+        // Pages pull only the batches they need.
+        let stmts = Parser::parse("SELECT id FROM users").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let mut stream = RowStream::execute(&plan, &tables).unwrap();
+        let page = stream.next_page(10).unwrap();
+        assert_eq!(page.len(), 10);
+        assert_eq!(stream.batch.len(), BATCH_SIZE - 10);
+        assert_eq!(stream.next_page(BATCH_SIZE).unwrap().len(), BATCH_SIZE);
+        assert_eq!(stream.batch.len(), BATCH_SIZE - 10);
+        let rows = 10 + BATCH_SIZE + stream.next_page(usize::MAX).unwrap().len();
+        assert_eq!(rows, NR_ROWS as usize);
+        assert!(stream.next_page(10).unwrap().is_empty());
+
+        let stmts = Parser::parse("SELECT id / 0 FROM users").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let mut result = RowStream::execute(&plan, &tables).unwrap();
+        assert!(matches!(
+            result.next(),
+            Some(Err(ExecError::DivisionByZero))
+        ));
+    }
+
+    #[test]
+    fn aggregations() {
+        let tables = users();
+        let sorted = |mut rows: Vec<Vec<Value>>| {
+            rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            rows
+        };
+
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT COUNT(*), COUNT(name), MIN(id), MAX(id) FROM users"
+            ),
+            [[NR_ROWS, NR_ROWS / 2, 0, NR_ROWS - 1].map(Value::Integer)]
+        );
+        assert_eq!(
+            sorted(query(
+                &tables,
+                "SELECT id / 1000, COUNT(name), SUM(id) FROM users GROUP BY id / 1000"
+            )),
+            [[0, 500, 499500], [1, 500, 1499500], [2, 500, 2499500]]
+                .map(|row| row.map(Value::Integer).to_vec())
+        );
+        assert_eq!(
+            sorted(query(
+                &tables,
+                "SELECT id / 1000, MIN(id) FROM users GROUP BY id / 1000 HAVING MAX(id) > 1500"
+            )),
+            [[1, 1000], [2, 2000]].map(|row| row.map(Value::Integer).to_vec())
+        );
+        // NULLs are grouped together.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT name, COUNT(*) FROM users WHERE id < 4 GROUP BY name"
+            ),
+            [
+                vec![Value::VarChar("user0".into()), Value::Integer(1)],
+                vec![Value::Null, Value::Integer(2)],
+                vec![Value::VarChar("user2".into()), Value::Integer(1)],
+            ]
+        );
+        // Without GROUP BY, an empty input gives a single row.
+        assert_eq!(
+            query(&tables, "SELECT COUNT(*), AVG(id) FROM users WHERE id < 0"),
+            [vec![Value::Integer(0), Value::Null]]
+        );
+        assert_eq!(
+            query(&tables, "SELECT DISTINCT id / 1000 FROM users").len(),
+            3
+        );
+    }
+
+    #[test]
+    fn cancellation() {
+        let tables = users();
+        let context = QueryContext::default();
+
+        let stmts = Parser::parse("SELECT id FROM users").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let mut result = RowStream::execute_with(&plan, &tables, &context).unwrap();
+        assert!(result.next().unwrap().is_ok());
+        context.token.cancel();
+        // The current batch is still returned.
+        let rest = result.collect::<Vec<_>>();
+        assert_eq!(rest.len(), BATCH_SIZE);
+        assert!(rest[..BATCH_SIZE - 1].iter().all(Result::is_ok));
+        assert!(matches!(rest.last(), Some(Err(ExecError::QueryCancelled))));
+
+        // Blocking operators stop between the batches of their input.
+        let stmts = Parser::parse("SELECT COUNT(*) FROM users").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let mut result = RowStream::execute_with(&plan, &tables, &context).unwrap();
+        assert!(matches!(
+            result.next(),
+            Some(Err(ExecError::QueryCancelled))
+        ));
+    }
+
+    #[test]
+    fn spilling() {
+        let tables = users();
+        let spill_dir = tempfile::tempdir().unwrap();
+        let run = |source: &str, budget: &MemoryBudget| {
+            let stmts = Parser::parse(source).unwrap();
+            let plan = plan::plan(&stmts[0], &tables).unwrap();
+            let context = QueryContext {
+                budget: budget.clone(),
+                ..QueryContext::default()
+            };
+            let mut rows = RowStream::execute_with(&plan, &tables, &context)
+                .unwrap()
+                .map(|tuple| tuple.unwrap().values().to_vec())
+                .collect::<Vec<_>>();
+            // NULLs are not ordered.
+            rows.sort_by_key(|row| format!("{row:?}"));
+            rows
+        };
+
+        for source in [
+            "SELECT id / 3, COUNT(*), COUNT(DISTINCT name) FROM users GROUP BY id / 3",
+            "SELECT DISTINCT id / 2, name FROM users",
+            "SELECT a.id, b.name FROM users a JOIN users b ON a.id = b.id WHERE b.id > 5",
+            "SELECT a.id, b.id FROM users a LEFT JOIN users b ON a.name = b.name",
+        ] {
+            let budget = MemoryBudget::new(16 * 1024, spill_dir.path());
+            assert_eq!(
+                run(source, &budget),
+                run(source, &MemoryBudget::unlimited()),
+                "{source}"
+            );
+            assert!(budget.spills() > 0, "{source}");
+            assert_eq!(budget.used(), 0);
+        }
+        // Spill files are removed once read.
+        assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn parallel_scans() {
+        let mut tables = users();
+        tables.set_workers(4);
+
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT COUNT(*), COUNT(name), SUM(id) FROM users WHERE id >= 1000"
+            ),
+            [[NR_ROWS - 1000, (NR_ROWS - 1000) / 2, 3999000].map(Value::Integer)]
+        );
+    }
+
+    #[test]
+    fn distinct() {
+        let tables = users();
+
+        // Tuples are returned in the order they are first seen, NULLs are
+        // not distinct from each other.
+        assert_eq!(
+            query(&tables, "SELECT DISTINCT id / 1000 FROM users"),
+            [[0], [1], [2]].map(|row| row.map(Value::Integer).to_vec())
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT DISTINCT name FROM users WHERE id > 2 AND id < 7"
+            ),
+            [
+                vec![Value::Null],
+                vec![Value::VarChar("user4".into())],
+                vec![Value::VarChar("user6".into())],
+            ]
+        );
+
+        // NULL arguments are skipped, each distinct value is counted once.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT COUNT(DISTINCT id - id / 10 * 10), COUNT(DISTINCT name), \
+                 SUM(DISTINCT id / 1000), COUNT(id / 1000) FROM users"
+            ),
+            [[10, NR_ROWS / 2, 3, NR_ROWS].map(Value::Integer)]
+        );
+    }
+
+    #[test]
+    fn order_by_limit() {
+        let tables = users();
+        let ints = |rows: &[i64]| {
+            rows.iter()
+                .map(|&i| vec![Value::Integer(i)])
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            query(&tables, "SELECT id FROM users ORDER BY id DESC LIMIT 3"),
+            ints(&[2999, 2998, 2997])
+        );
+        // NULLs sort last, first in descending order.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM users WHERE id < 6 ORDER BY name DESC, id"
+            ),
+            ints(&[1, 3, 5, 4, 2, 0])
+        );
+        // Keys by position in the select list.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM users WHERE id < 6 ORDER BY name, 1 DESC"
+            ),
+            ints(&[0, 2, 4, 5, 3, 1])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM users ORDER BY id LIMIT 2 OFFSET 10"
+            ),
+            ints(&[10, 11])
+        );
+        assert_eq!(
+            query(&tables, "SELECT id FROM users WHERE id < 5 OFFSET 3"),
+            ints(&[3, 4])
+        );
+        assert!(query(&tables, "SELECT id FROM users LIMIT 0").is_empty());
+        assert!(query(&tables, "SELECT id FROM users OFFSET 3000").is_empty());
+
+        // Aggregates, listed or not, and DISTINCT output columns.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id / 1000, MAX(id) FROM users GROUP BY id / 1000 ORDER BY MIN(id) DESC"
+            ),
+            [[2, 2999], [1, 1999], [0, 999]].map(|row| row.map(Value::Integer).to_vec())
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT DISTINCT id / 1000 FROM users ORDER BY 1 DESC LIMIT 2"
+            ),
+            ints(&[2, 1])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "INSERT INTO users SELECT id + 3000, name FROM users ORDER BY id DESC LIMIT 1"
+            ),
+            ints(&[1])
+        );
+        assert_eq!(
+            query(&tables, "SELECT id FROM users ORDER BY id DESC LIMIT 1"),
+            ints(&[5999])
+        );
+
+        for (source, error) in [
+            ("SELECT id FROM users ORDER BY 2", "ORDER BY position 2"),
+            (
+                "SELECT DISTINCT id / 2 FROM users ORDER BY id",
+                "SELECT DISTINCT",
+            ),
+            ("SELECT id FROM users LIMIT -1", "LIMIT"),
+            ("SELECT id FROM users OFFSET 'a'", "OFFSET"),
+        ] {
+            let stmts = Parser::parse(source).unwrap();
+            let err = plan::plan(&stmts[0], &tables).unwrap_err();
+            assert!(err.to_string().contains(error), "{source}: {err}");
+        }
+    }
+
+    #[test]
+    fn sort_spilling() {
+        let tables = users();
+        let spill_dir = tempfile::tempdir().unwrap();
+        let budget = MemoryBudget::new(16 * 1024, spill_dir.path());
+        let context = QueryContext {
+            budget: budget.clone(),
+            ..QueryContext::default()
+        };
+
+        let stmts = Parser::parse("SELECT id, name FROM users ORDER BY name DESC, id").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let ids = RowStream::execute_with(&plan, &tables, &context)
+            .unwrap()
+            .map(|tuple| match tuple.unwrap().values()[0] {
+                Value::Integer(id) => id,
+                _ => panic!("expected an INTEGER"),
+            })
+            .collect::<Vec<_>>();
+        // The NULL names, then the names in descending order.
+        let mut expected = (1..NR_ROWS).step_by(2).collect::<Vec<_>>();
+        let mut named = (0..NR_ROWS).step_by(2).collect::<Vec<_>>();
+        named.sort_by_key(|id| std::cmp::Reverse(format!("user{id}")));
+        expected.extend(named);
+        assert_eq!(ids, expected);
+
+        assert!(budget.spills() > 1);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+    }
+
+    /// Adds `teams (id, lead)` to the tables: (1, 10), (2, 11), (3, NULL) and
+    /// (4, 5000), whose lead isn't a user.
+    fn add_teams(tables: &mut Tables<FileStorage>) {
+        let storage_path = NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(storage_path).unwrap();
+        let cache = GLOBAL_PAGE_CACHE.cache_storage(storage);
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "lead".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+
+        let table = Table::try_new("teams", &schema, cache).unwrap();
+        for (id, lead) in [(1, Some(10)), (2, Some(11)), (3, None), (4, Some(5000))] {
+            let lead = lead.map_or(Value::Null, Value::Integer);
+            table
+                .insert(&Tuple::try_new(vec![Value::Integer(id), lead]).unwrap())
+                .unwrap();
+        }
+        tables.add_table(table);
+    }
+
+    #[test]
+    fn subqueries() {
+        let mut tables = users();
+        add_teams(&mut tables);
+        let ints = |rows: &[i64]| {
+            rows.iter()
+                .map(|&i| vec![Value::Integer(i)])
+                .collect::<Vec<_>>()
+        };
+
+        // Uncorrelated subqueries, run once.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM users WHERE id IN (SELECT lead FROM teams)"
+            ),
+            ints(&[10, 11])
+        );
+        // x NOT IN a list with NULL is never true, but an empty list is.
+        assert!(
+            query(
+                &tables,
+                "SELECT id FROM users WHERE id < 3 AND id NOT IN (SELECT lead FROM teams)"
+            )
+            .is_empty()
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM users WHERE id < 3 AND id NOT IN (SELECT lead FROM teams WHERE lead > 10)"
+            ),
+            ints(&[0, 1, 2])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM users WHERE id < 3 AND id NOT IN (SELECT lead FROM teams WHERE lead < 0)"
+            ),
+            ints(&[0, 1, 2])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT COUNT(*) FROM users WHERE EXISTS (SELECT * FROM teams WHERE lead > 4000)"
+            ),
+            ints(&[NR_ROWS])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT COUNT(*) FROM users WHERE NOT EXISTS (SELECT * FROM teams WHERE lead > 4000)"
+            ),
+            ints(&[0])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id, (SELECT COUNT(*) FROM teams) FROM users \
+                 WHERE id = (SELECT MAX(lead) FROM teams WHERE lead < 100)"
+            ),
+            [[11, 4].map(Value::Integer).to_vec()]
+        );
+        assert_eq!(
+            query(&tables, "SELECT (SELECT lead FROM teams WHERE id = 9)"),
+            [[Value::Null]]
+        );
+
+        // Correlated subqueries, run for each outer tuple.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM teams WHERE EXISTS (SELECT * FROM users WHERE users.id = teams.lead)"
+            ),
+            ints(&[1, 2])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM teams WHERE NOT EXISTS (SELECT * FROM users WHERE users.id = lead)"
+            ),
+            ints(&[3, 4])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id, (SELECT name FROM users WHERE users.id = teams.lead) FROM teams"
+            ),
+            [
+                vec![Value::Integer(1), Value::VarChar("user10".into())],
+                vec![Value::Integer(2), Value::Null],
+                vec![Value::Integer(3), Value::Null],
+                vec![Value::Integer(4), Value::Null],
+            ]
+        );
+        // NULL IN (...) is NULL, filtered out either way.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM teams WHERE lead - 9 IN (SELECT id FROM users WHERE id < teams.id * 2)"
+            ),
+            ints(&[1, 2])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM teams WHERE lead - 9 NOT IN (SELECT id FROM users WHERE id < teams.id * 2)"
+            ),
+            ints(&[4])
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM teams \
+                 ORDER BY (SELECT COUNT(*) FROM users WHERE users.id < teams.lead) DESC"
+            ),
+            ints(&[4, 2, 1, 3])
+        );
+        // A subquery referring to the query two levels up.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT id FROM teams WHERE EXISTS (SELECT * FROM users WHERE users.id = teams.lead \
+                 AND EXISTS (SELECT * FROM teams t WHERE t.id = teams.id AND users.id > 10))"
+            ),
+            ints(&[2])
+        );
+        // Referring to both sides of a join, and to the group columns.
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT t.id, u.id FROM teams t, users u WHERE u.id < 3 AND t.id = 1 \
+                 AND EXISTS (SELECT * FROM teams WHERE lead = t.lead + u.id)"
+            ),
+            [[1, 0], [1, 1]].map(|row| row.map(Value::Integer).to_vec())
+        );
+        assert_eq!(
+            query(
+                &tables,
+                "SELECT lead, (SELECT COUNT(*) FROM users WHERE id < lead) FROM teams \
+                 GROUP BY lead ORDER BY 1"
+            ),
+            [
+                vec![Value::Integer(10), Value::Integer(10)],
+                vec![Value::Integer(11), Value::Integer(11)],
+                vec![Value::Integer(5000), Value::Integer(NR_ROWS)],
+                vec![Value::Null, Value::Integer(0)],
+            ]
+        );
+
+        let stmts = Parser::parse("SELECT (SELECT lead FROM teams)").unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        assert!(matches!(
+            RowStream::execute(&plan, &tables),
+            Err(ExecError::SubqueryRows)
+        ));
+        let stmts = Parser::parse(
+            "SELECT id, (SELECT id FROM users WHERE id < teams.lead) FROM teams WHERE id = 1",
+        )
+        .unwrap();
+        let plan = plan::plan(&stmts[0], &tables).unwrap();
+        let mut rows = RowStream::execute(&plan, &tables).unwrap();
+        assert!(matches!(rows.next(), Some(Err(ExecError::SubqueryRows))));
+    }
+}
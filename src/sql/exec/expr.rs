@@ -0,0 +1,323 @@
+use std::cmp::Ordering;
+
+use crate::sql::exec::{Evaluator, ExecError};
+use crate::sql::plan::{BinaryOp, Expr, UnaryOp};
+use crate::sql::types::Value;
+use crate::tuple::Tuple;
+
+/// Evaluates a bound scalar expression against a tuple of its input, see
+/// `Evaluator` to evaluate it against many tuples.
+pub fn evaluate(expr: &Expr, tuple: &Tuple) -> Result<Value, ExecError> {
+    Evaluator::new(expr).evaluate(tuple.values())
+}
+
+pub(super) fn evaluate_binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, ExecError> {
+    let compare = |predicate: fn(Ordering) -> bool| {
+        Ok::<_, ExecError>(boolean(lhs.compare(&rhs)?.map(predicate)))
+    };
+
+    Ok(match op {
+        BinaryOp::Plus => arithmetic("+", lhs, rhs)?,
+        BinaryOp::Minus => arithmetic("-", lhs, rhs)?,
+        BinaryOp::Mul => arithmetic("*", lhs, rhs)?,
+        BinaryOp::Div => arithmetic("/", lhs, rhs)?,
+        BinaryOp::Equal => compare(Ordering::is_eq)?,
+        BinaryOp::NotEqual => compare(Ordering::is_ne)?,
+        BinaryOp::Less => compare(Ordering::is_lt)?,
+        BinaryOp::LessEqual => compare(Ordering::is_le)?,
+        BinaryOp::Greater => compare(Ordering::is_gt)?,
+        BinaryOp::GreaterEqual => compare(Ordering::is_ge)?,
+        BinaryOp::And => boolean(and(truth(lhs)?, truth(rhs)?)),
+        BinaryOp::Or => {
+            let (lhs, rhs) = (truth(lhs)?, truth(rhs)?);
+            // NOT (NOT a AND NOT b)
+            boolean(and(lhs.map(|b| !b), rhs.map(|b| !b)).map(|b| !b))
+        }
+    })
+}
+
+pub(super) fn evaluate_unary(op: UnaryOp, value: Value) -> Result<Value, ExecError> {
+    Ok(match op {
+        UnaryOp::Identity => match value {
+            value @ (Value::Integer(_) | Value::Float(_) | Value::Null) => value,
+            value => return Err(ExecError::InvalidOperand("+", value.data_type().unwrap())),
+        },
+        UnaryOp::Negate => match value {
+            Value::Integer(i) => Value::Integer(i.checked_neg().ok_or(ExecError::IntegerOverflow)?),
+            Value::Float(f) => Value::Float(-f),
+            Value::Null => Value::Null,
+            value => return Err(ExecError::InvalidOperand("-", value.data_type().unwrap())),
+        },
+        UnaryOp::Not => boolean(truth(value)?.map(|b| !b)),
+    })
+}
+
+fn arithmetic(op: &'static str, lhs: Value, rhs: Value) -> Result<Value, ExecError> {
+    Ok(match (&lhs, &rhs) {
+        (Value::Null, _) | (_, Value::Null) => Value::Null,
+        (Value::Integer(_) | Value::Float(_), Value::Integer(0))
+        | (Value::Integer(_) | Value::Float(_), Value::Float(0.0))
+            if op == "/" =>
+        {
+            return Err(ExecError::DivisionByZero);
+        }
+        (Value::Integer(l), Value::Integer(r)) => {
+            let result = match op {
+                "+" => l.checked_add(*r),
+                "-" => l.checked_sub(*r),
+                "*" => l.checked_mul(*r),
+                _ => l.checked_div(*r),
+            };
+            Value::Integer(result.ok_or(ExecError::IntegerOverflow)?)
+        }
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            let (l, r) = (as_f64(&lhs), as_f64(&rhs));
+            Value::Float(match op {
+                "+" => l + r,
+                "-" => l - r,
+                "*" => l * r,
+                _ => l / r,
+            })
+        }
+        _ => return Err(invalid_operands(op, &lhs, &rhs)),
+    })
+}
+
+fn as_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        _ => unreachable!(),
+    }
+}
+
+pub(super) fn invalid_operands(op: &'static str, lhs: &Value, rhs: &Value) -> ExecError {
+    // NULLs are handled before reaching here.
+    ExecError::InvalidOperands(op, lhs.data_type().unwrap(), rhs.data_type().unwrap())
+}
+
+/// Returns the truth value of a boolean operand, `None` for NULL.
+fn truth(value: Value) -> Result<Option<bool>, ExecError> {
+    match value {
+        Value::Boolean(b) => Ok(Some(b)),
+        Value::Null => Ok(None),
+        value => Err(ExecError::NotBoolean(value.data_type().unwrap())),
+    }
+}
+
+/// Three valued AND: false wins over NULL.
+pub(super) fn and(lhs: Option<bool>, rhs: Option<bool>) -> Option<bool> {
+    match (lhs, rhs) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+pub(super) fn boolean(b: Option<bool>) -> Value {
+    b.map_or(Value::Null, Value::Boolean)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LikeToken {
+    // %
+    Any,
+    // _
+    One,
+    Char(char),
+}
+
+fn like_tokens(pattern: &str) -> Vec<LikeToken> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => LikeToken::Any,
+            '_' => LikeToken::One,
+            // A trailing backslash matches itself.
+            '\\' => LikeToken::Char(chars.next().unwrap_or('\\')),
+            c => LikeToken::Char(c),
+        });
+    }
+    tokens
+}
+
+/// The characters every text matching a LIKE pattern starts with: those
+/// before its first wildcard, unescaped.
+pub fn like_prefix(pattern: &str) -> String {
+    like_tokens(pattern)
+        .into_iter()
+        .map_while(|token| match token {
+            LikeToken::Char(c) => Some(c),
+            LikeToken::Any | LikeToken::One => None,
+        })
+        .collect()
+}
+
+/// Matches `text` against a LIKE pattern: `%` matches any sequence of
+/// characters, `_` matches a single character and `\` escapes the next
+/// character.
+///
+/// The pattern is matched left to right, on a mismatch the last `%` is
+/// retried one character further: no regex is built and the matching is
+/// O(len(text) * len(pattern)) at worst.
+pub fn like(text: &str, pattern: &str) -> bool {
+    let tokens = like_tokens(pattern);
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut t, mut p) = (0, 0);
+    // Position in the pattern after the last `%` and in the text where it
+    // stopped matching.
+    let mut backtrack = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(LikeToken::Any) => {
+                p += 1;
+                backtrack = Some((p, t));
+                continue;
+            }
+            Some(LikeToken::One) => {
+                (t, p) = (t + 1, p + 1);
+                continue;
+            }
+            Some(LikeToken::Char(c)) if *c == text[t] => {
+                (t, p) = (t + 1, p + 1);
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some((after_any, any_end)) = backtrack else {
+            return false;
+        };
+        backtrack = Some((after_any, any_end + 1));
+        (t, p) = (any_end + 1, after_any);
+    }
+
+    tokens[p..].iter().all(|token| *token == LikeToken::Any)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan::{self, LogicalPlan, PlanError};
+    use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+
+    fn bind(source: &str) -> Result<Expr, PlanError> {
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "i".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "s".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "n".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        let tables = HashMap::from([("t".to_string(), schema)]);
+
+        let source = format!("SELECT {source} FROM t");
+        let stmts = Parser::parse(&source).unwrap();
+        match plan::plan(&stmts[0], &tables)? {
+            LogicalPlan::Project { mut exprs, .. } => Ok(exprs.remove(0)),
+            plan => panic!("expected a projection, found {plan}"),
+        }
+    }
+
+    fn eval(source: &str) -> Result<Value, ExecError> {
+        let tuple = Tuple::try_new(vec![
+            Value::Integer(42),
+            Value::VarChar("hello".into()),
+            Value::Null,
+        ])
+        .unwrap();
+
+        evaluate(&bind(source).unwrap(), &tuple)
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(eval("i * 2 + 1").unwrap(), Value::Integer(85));
+        assert_eq!(eval("i / 4.0").unwrap(), Value::Float(10.5));
+        assert_eq!(eval("i + n").unwrap(), Value::Null);
+        assert_eq!(eval("i = '42'").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("n = 1 OR i > 1").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("n = 1 AND i > 1").unwrap(), Value::Null);
+        assert_eq!(eval("NOT n = 1 AND i < 1").unwrap(), Value::Boolean(false));
+        assert_eq!(
+            eval("CAST(i AS VARCHAR)").unwrap(),
+            Value::VarChar("42".into())
+        );
+
+        assert!(matches!(eval("i / 0"), Err(ExecError::DivisionByZero)));
+        assert!(matches!(eval("s + 1"), Err(ExecError::InvalidOperands(..))));
+        assert!(matches!(eval("i AND TRUE"), Err(ExecError::NotBoolean(_))));
+        assert!(matches!(bind("x"), Err(PlanError::UnknownColumn(_))));
+        assert!(matches!(bind("f(i)"), Err(PlanError::UnknownFunction(_))));
+    }
+
+    #[test]
+    fn in_between_like() {
+        assert_eq!(eval("i IN (1, 42)").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("i IN (1, 2)").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("i IN (1, NULL)").unwrap(), Value::Null);
+        assert_eq!(eval("i NOT IN (1, 2)").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("n IN (1, 2)").unwrap(), Value::Null);
+
+        assert_eq!(eval("i BETWEEN 40 AND 42").unwrap(), Value::Boolean(true));
+        assert_eq!(
+            eval("i NOT BETWEEN 40 AND 42").unwrap(),
+            Value::Boolean(false)
+        );
+        assert_eq!(eval("i BETWEEN 50 AND n").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("i BETWEEN 40 AND n").unwrap(), Value::Null);
+
+        assert_eq!(eval("s LIKE 'h%o'").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("s NOT LIKE '_ello'").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("s LIKE n").unwrap(), Value::Null);
+        assert!(matches!(
+            eval("i LIKE '4%'"),
+            Err(ExecError::InvalidOperands(..))
+        ));
+    }
+
+    #[test]
+    fn like_patterns() {
+        assert!(like("", ""));
+        assert!(like("", "%"));
+        assert!(!like("", "_"));
+        assert!(like("abc", "abc"));
+        assert!(!like("abc", "ab"));
+        assert!(like("abc", "a%"));
+        assert!(like("abc", "%c"));
+        assert!(like("abc", "%b%"));
+        assert!(like("abc", "a_c"));
+        assert!(like("aXbXc", "%X%c"));
+        assert!(like("abcbc", "a%bc"));
+        assert!(!like("abcbd", "a%bc"));
+        assert!(like("été", "_t_"));
+        assert!(like("50%", "50\\%"));
+        assert!(!like("500", "50\\%"));
+        assert!(like("a\\", "a\\"));
+    }
+    #[test]
+    fn like_prefixes() {
+        assert_eq!(like_prefix("abc%"), "abc");
+        assert_eq!(like_prefix("ab_d%"), "ab");
+        assert_eq!(like_prefix("abc"), "abc");
+        assert_eq!(like_prefix("%bc"), "");
+        assert_eq!(like_prefix("50\\%%"), "50%");
+    }
+}
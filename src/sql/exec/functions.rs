@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+
+use crate::indexes::text_contains;
+use crate::sql::exec::ExecError;
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+
+/// The scalar functions known to the evaluator.
+pub static BUILTIN_FUNCTIONS: LazyLock<FunctionRegistry> = LazyLock::new(|| {
+    let mut functions = FunctionRegistry::new();
+    functions.register(ScalarFunction::new(
+        "UPPER",
+        1..=1,
+        DataType::VarChar(None),
+        upper,
+    ));
+    functions.register(ScalarFunction::new(
+        "LOWER",
+        1..=1,
+        DataType::VarChar(None),
+        lower,
+    ));
+    functions.register(ScalarFunction::new(
+        "LENGTH",
+        1..=1,
+        DataType::Integer,
+        length,
+    ));
+    functions.register(ScalarFunction::new(
+        "SUBSTR",
+        2..=3,
+        DataType::VarChar(None),
+        substr,
+    ));
+    functions.register(
+        ScalarFunction::new("CONCAT", 1..=usize::MAX, DataType::VarChar(None), concat)
+            .null_tolerant(),
+    );
+    functions.register(ScalarFunction::new(
+        "TRIM",
+        1..=1,
+        DataType::VarChar(None),
+        trim,
+    ));
+    functions.register(ScalarFunction::new(
+        "CONTAINS",
+        2..=2,
+        DataType::Boolean,
+        contains,
+    ));
+    functions
+});
+
+/// A scalar function: one value out of a list of values.
+pub struct ScalarFunction {
+    name: &'static str,
+    arity: RangeInclusive<usize>,
+    return_type: DataType,
+    // Unless set, the function returns NULL if any argument is NULL
+    // without being called.
+    null_tolerant: bool,
+    function: fn(&[Value]) -> Result<Value, ExecError>,
+}
+
+impl ScalarFunction {
+    pub fn new(
+        name: &'static str,
+        arity: RangeInclusive<usize>,
+        return_type: DataType,
+        function: fn(&[Value]) -> Result<Value, ExecError>,
+    ) -> Self {
+        Self {
+            name,
+            arity,
+            return_type,
+            null_tolerant: false,
+            function,
+        }
+    }
+
+    /// Passes NULL arguments to the function instead of returning NULL.
+    pub fn null_tolerant(mut self) -> Self {
+        self.null_tolerant = true;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn return_type(&self) -> DataType {
+        self.return_type
+    }
+
+    pub fn call(&self, args: &[Value]) -> Result<Value, ExecError> {
+        if !self.arity.contains(&args.len()) {
+            return Err(ExecError::ArgumentCount(self.name, args.len()));
+        }
+        if !self.null_tolerant && args.iter().any(Value::is_null) {
+            return Ok(Value::Null);
+        }
+
+        (self.function)(args)
+    }
+}
+
+impl std::fmt::Debug for ScalarFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ScalarFunction").field(&self.name).finish()
+    }
+}
+
+/// Functions are identified by their name, a registry holds one function per
+/// name.
+impl PartialEq for ScalarFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// Scalar functions by name, names are case-insensitive.
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, ScalarFunction>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a function, replacing any function with the same name.
+    pub fn register(&mut self, function: ScalarFunction) {
+        self.functions
+            .insert(function.name.to_ascii_uppercase(), function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ScalarFunction> {
+        self.functions.get(&name.to_ascii_uppercase())
+    }
+}
+
+fn varchar<'a>(function: &'static str, value: &'a Value) -> Result<&'a str, ExecError> {
+    match value {
+        Value::VarChar(s) => Ok(s),
+        // NULLs are handled before calling the function.
+        value => Err(ExecError::InvalidArgument(
+            function,
+            value.data_type().unwrap(),
+        )),
+    }
+}
+
+fn integer(function: &'static str, value: &Value) -> Result<i64, ExecError> {
+    match value {
+        Value::Integer(i) => Ok(*i),
+        value => Err(ExecError::InvalidArgument(
+            function,
+            value.data_type().unwrap(),
+        )),
+    }
+}
+
+fn upper(args: &[Value]) -> Result<Value, ExecError> {
+    Ok(Value::VarChar(varchar("UPPER", &args[0])?.to_uppercase()))
+}
+
+fn lower(args: &[Value]) -> Result<Value, ExecError> {
+    Ok(Value::VarChar(varchar("LOWER", &args[0])?.to_lowercase()))
+}
+
+/// The length in characters.
+fn length(args: &[Value]) -> Result<Value, ExecError> {
+    Ok(Value::Integer(
+        varchar("LENGTH", &args[0])?.chars().count() as i64
+    ))
+}
+
+/// SUBSTR(s, start [, length]): characters are numbered from 1. Positions
+/// before the first character count towards the length but select nothing.
+fn substr(args: &[Value]) -> Result<Value, ExecError> {
+    let s = varchar("SUBSTR", &args[0])?;
+    let start = integer("SUBSTR", &args[1])?;
+    let end = match args.get(2) {
+        Some(length) => {
+            let length = integer("SUBSTR", length)?;
+            if length < 0 {
+                return Err(ExecError::NegativeLength);
+            }
+            start.saturating_add(length)
+        }
+        None => i64::MAX,
+    };
+
+    let skip = start.max(1) - 1;
+    let take = end.saturating_sub(skip + 1).max(0);
+    Ok(Value::VarChar(
+        s.chars()
+            .skip(skip.try_into().unwrap_or(usize::MAX))
+            .take(take.try_into().unwrap_or(usize::MAX))
+            .collect(),
+    ))
+}
+
+/// Concatenates the arguments as strings, NULLs are skipped.
+fn concat(args: &[Value]) -> Result<Value, ExecError> {
+    let mut result = String::new();
+    for arg in args.iter().filter(|arg| !arg.is_null()) {
+        match arg.cast(DataType::VarChar(None))? {
+            Value::VarChar(s) => result.push_str(&s),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(Value::VarChar(result))
+}
+
+/// Removes leading and trailing spaces.
+fn trim(args: &[Value]) -> Result<Value, ExecError> {
+    Ok(Value::VarChar(
+        varchar("TRIM", &args[0])?.trim_matches(' ').to_string(),
+    ))
+}
+
+/// CONTAINS(text, query): whether the text has all the terms of the query,
+/// see `tokenize`. Filters over a table scan read the full-text index of the
+/// column instead, see `fulltext_search`.
+fn contains(args: &[Value]) -> Result<Value, ExecError> {
+    let text = varchar("CONTAINS", &args[0])?;
+    let query = varchar("CONTAINS", &args[1])?;
+    Ok(Value::Boolean(text_contains(text, query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, ExecError> {
+        BUILTIN_FUNCTIONS.get(name).unwrap().call(&args)
+    }
+
+    fn varchar(s: &str) -> Value {
+        Value::VarChar(s.to_string())
+    }
+
+    #[test]
+    fn string_functions() {
+        assert_eq!(call("upper", vec![varchar("été")]).unwrap(), varchar("ÉTÉ"));
+        assert_eq!(call("LOWER", vec![varchar("AbC")]).unwrap(), varchar("abc"));
+        assert_eq!(
+            call("LENGTH", vec![varchar("été")]).unwrap(),
+            Value::Integer(3)
+        );
+        assert_eq!(
+            call("TRIM", vec![varchar("  a b ")]).unwrap(),
+            varchar("a b")
+        );
+        assert_eq!(
+            call("CONCAT", vec![varchar("a"), Value::Null, Value::Integer(1)]).unwrap(),
+            varchar("a1")
+        );
+        assert_eq!(call("UPPER", vec![Value::Null]).unwrap(), Value::Null);
+
+        assert!(matches!(
+            call("UPPER", vec![Value::Integer(1)]),
+            Err(ExecError::InvalidArgument("UPPER", DataType::Integer))
+        ));
+        assert!(matches!(
+            call("UPPER", vec![]),
+            Err(ExecError::ArgumentCount("UPPER", 0))
+        ));
+        assert_eq!(
+            call("CONTAINS", vec![varchar("Hello, World"), varchar("world")]).unwrap(),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            call("CONTAINS", vec![varchar("Hello, World"), varchar("worlds")]).unwrap(),
+            Value::Boolean(false)
+        );
+        assert!(BUILTIN_FUNCTIONS.get("NOPE").is_none());
+    }
+
+    #[test]
+    fn substr() {
+        let substr = |start: i64, length: Option<i64>| {
+            let mut args = vec![varchar("hello"), Value::Integer(start)];
+            args.extend(length.map(Value::Integer));
+            call("SUBSTR", args)
+        };
+
+        assert_eq!(substr(2, None).unwrap(), varchar("ello"));
+        assert_eq!(substr(2, Some(3)).unwrap(), varchar("ell"));
+        assert_eq!(substr(0, Some(2)).unwrap(), varchar("h"));
+        assert_eq!(substr(-5, Some(2)).unwrap(), varchar(""));
+        assert_eq!(substr(4, Some(10)).unwrap(), varchar("lo"));
+        assert_eq!(substr(10, None).unwrap(), varchar(""));
+        assert!(matches!(
+            substr(1, Some(-1)),
+            Err(ExecError::NegativeLength)
+        ));
+    }
+}
@@ -0,0 +1,818 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use crate::indexes::{BTree, BTreeError, BTreeStableRangeIterator, FullTextIndex};
+use crate::pages::{Key, RecordId};
+use crate::sql::exec::executor::{BATCH_SIZE, Executor, Tables};
+use crate::sql::exec::{ExecError, like_prefix};
+use crate::sql::plan::{BinaryOp, Expr, LogicalPlan, SortKey};
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+use crate::sql::types::memcomparable::{self, Collation, KeyOptions};
+use crate::storage::StorageBackend;
+use crate::table::{Table, last_entry_key};
+use crate::tuple::Tuple;
+
+/// The keys of an index read by a scan.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyRange {
+    /// The INTEGER keys from the first value to the last one.
+    Integer(RangeInclusive<i64>),
+    /// The VARCHAR keys starting with a prefix, e.g. for `LIKE 'abc%'`.
+    Prefix(String),
+}
+
+impl std::fmt::Display for KeyRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyRange::Integer(range) => write!(f, "[{}..={}]", range.start(), range.end()),
+            KeyRange::Prefix(prefix) => write!(f, "[prefix '{prefix}']"),
+        }
+    }
+}
+
+/// The smallest byte string greater than all the ones starting with
+/// `prefix`, if any.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&byte| byte != u8::MAX)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+/// Iterates over the entries of an index whose key is in a range.
+fn key_range<S: StorageBackend + 'static>(
+    index: &BTree<S>,
+    range: KeyRange,
+) -> Result<BTreeStableRangeIterator<'_, S>, ExecError> {
+    // NULLs sort last but aren't indexed, the range can't read them.
+    match range {
+        KeyRange::Integer(range) => {
+            let (start, end) = range.into_inner();
+            let key = |value| Key::from_values(&[Value::Integer(value)]);
+            Ok(index.stable_range(key(start)..=last_entry_key(&key(end)))?)
+        }
+        KeyRange::Prefix(prefix) => {
+            let start = memcomparable::encode_prefix(&prefix);
+            match prefix_end(&start) {
+                Some(end) => Ok(index.stable_range(Key::from(start)..Key::from(end))?),
+                None => Ok(index.stable_range(Key::from(start)..)?),
+            }
+        }
+    }
+}
+
+/// Reads the tuples of a table whose indexed column is in a range of keys, in
+/// key order.
+pub struct IndexScan<'a, S: StorageBackend + 'static> {
+    table: &'a Table<S>,
+    iter: Box<dyn Iterator<Item = Result<(Key, RecordId), BTreeError>> + 'a>,
+}
+
+impl<'a, S: StorageBackend + 'static> IndexScan<'a, S> {
+    pub fn try_new(
+        table: &'a Table<S>,
+        index: &'a BTree<S>,
+        range: KeyRange,
+    ) -> Result<Self, ExecError> {
+        let iter = Box::new(key_range(index, range)?);
+        Ok(Self { table, iter })
+    }
+
+    /// Reads all the tuples of a table in the order of the keys of an index,
+    /// or in the reverse order if `backward` is set.
+    pub fn ordered(
+        table: &'a Table<S>,
+        index: &'a BTree<S>,
+        backward: bool,
+    ) -> Result<Self, ExecError> {
+        let iter: Box<dyn Iterator<Item = _>> = if backward {
+            Box::new(index.rev_range(..)?)
+        } else {
+            Box::new(index.stable_range(..)?)
+        };
+        Ok(Self { table, iter })
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for IndexScan<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let mut batch = Vec::new();
+        for entry in self.iter.by_ref().take(BATCH_SIZE) {
+            let (_, record_id) = entry?;
+            batch.push(self.table.get(record_id)?);
+        }
+
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// Reads the tuples of a table whose text in a column has all the terms of a
+/// query, through the full-text index of the column, in record id order.
+pub struct FullTextScan<'a, S: StorageBackend + 'static> {
+    table: &'a Table<S>,
+    record_ids: std::vec::IntoIter<RecordId>,
+}
+
+impl<'a, S: StorageBackend + 'static> FullTextScan<'a, S> {
+    pub fn try_new(
+        table: &'a Table<S>,
+        index: &'a FullTextIndex<S>,
+        query: &str,
+    ) -> Result<Self, ExecError> {
+        Ok(Self {
+            table,
+            record_ids: index.search(query)?.into_iter(),
+        })
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for FullTextScan<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let mut batch = Vec::new();
+        for record_id in self.record_ids.by_ref().take(BATCH_SIZE) {
+            batch.push(self.table.get(record_id)?);
+        }
+
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// Same as `IndexScan`, but the tuples are made of the key and the included
+/// columns of the index alone, without reading the heap: the other columns
+/// are NULL.
+pub struct IndexOnlyScan<'a, S: StorageBackend + 'static> {
+    iter: BTreeStableRangeIterator<'a, S>,
+    // The number of columns of the table.
+    width: usize,
+    column: usize,
+    included: &'a [usize],
+    key_type: [(DataType, KeyOptions); 1],
+    included_types: Vec<(DataType, KeyOptions)>,
+}
+
+impl<'a, S: StorageBackend + 'static> IndexOnlyScan<'a, S> {
+    pub fn try_new(
+        table: &'a Table<S>,
+        column: usize,
+        index: &'a BTree<S>,
+        range: KeyRange,
+    ) -> Result<Self, ExecError> {
+        let included = table.included(column).unwrap_or_default();
+        let column_type = |column: usize| {
+            let data_type = table.schema.columns()[column].data_type;
+            (data_type, KeyOptions::default())
+        };
+
+        Ok(Self {
+            iter: key_range(index, range)?,
+            width: table.schema.columns().len(),
+            column,
+            included,
+            key_type: [column_type(column)],
+            included_types: included.iter().map(|&column| column_type(column)).collect(),
+        })
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for IndexOnlyScan<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let mut batch = Vec::new();
+        while batch.len() < BATCH_SIZE {
+            let Some(entry) = self.iter.next_with_payload() else {
+                break;
+            };
+            let (key, _, payload) = entry?;
+            let mut values = vec![Value::Null; self.width];
+            values[self.column] = key.values(&self.key_type)?.remove(0);
+            let included = memcomparable::decode(&payload, &self.included_types)?;
+            for (&column, value) in self.included.iter().zip(included) {
+                values[column] = value;
+            }
+            batch.push(Tuple::try_new(values)?);
+        }
+
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// Whether the columns read by a table scan are all stored in the index on
+/// `column`, its key or included columns: the scan can then be run by an
+/// `IndexOnlyScan`.
+pub fn index_only<S: StorageBackend + 'static>(
+    input: &LogicalPlan,
+    table: &Table<S>,
+    column: usize,
+) -> bool {
+    let (LogicalPlan::Scan { columns, .. }, Some(included)) = (input, table.included(column))
+    else {
+        return false;
+    };
+    let covered = |idx: &usize| *idx == column || included.contains(idx);
+    match columns {
+        Some(columns) => columns.iter().all(covered),
+        None => (0..table.schema.columns().len()).all(|idx| covered(&idx)),
+    }
+}
+
+/// A table, the key columns of one of its indexes, the index, and whether it
+/// is scanned backward.
+type IndexOrder<'a, S> = (&'a Table<S>, &'a [(usize, KeyOptions)], &'a BTree<S>, bool);
+
+/// Finds whether a sort of a table scan, filtered or not, can read the table
+/// in the order of an index instead: the sort keys must be columns, the first
+/// key columns of the index, each in the direction of the index or each in
+/// the opposite one, the index then being scanned backward. The key columns
+/// must be NOT NULL since NULLs aren't indexed, nor sorted case-insensitively.
+pub fn index_order<'a, S: StorageBackend + 'static>(
+    input: &LogicalPlan,
+    keys: &[SortKey],
+    tables: &'a Tables<S>,
+) -> Option<IndexOrder<'a, S>> {
+    let scan = match input {
+        LogicalPlan::Filter { input, .. } => input,
+        input => input,
+    };
+    let LogicalPlan::Scan { table, .. } = scan else {
+        return None;
+    };
+    let table = tables.get(table)?;
+    let columns = table.schema.columns();
+    table.indexes().find_map(|(index_key, index)| {
+        let usable = index_key.len() >= keys.len()
+            && index_key.iter().all(|(column, options)| {
+                !columns[*column].constraints.is_nullable()
+                    && options.collation == Collation::Binary
+            });
+        if !usable || keys.is_empty() {
+            return None;
+        }
+        let mut directions = keys.iter().zip(index_key).map(|(key, (column, options))| {
+            (key.expr == Expr::Column(*column)).then_some(key.descending != options.descending)
+        });
+        let backward = directions.next()??;
+        directions
+            .all(|reversed| reversed == Some(backward))
+            .then_some((table, index_key, index, backward))
+    })
+}
+
+/// Narrows the range of values of a column with a comparison to a constant.
+fn narrow(range: &mut (i64, i64), op: BinaryOp, value: i64) {
+    let (low, high) = range;
+    match op {
+        BinaryOp::Equal => {
+            *low = (*low).max(value);
+            *high = (*high).min(value);
+        }
+        BinaryOp::Greater => *low = (*low).max(value.saturating_add(1)),
+        BinaryOp::GreaterEqual => *low = (*low).max(value),
+        BinaryOp::Less => *high = (*high).min(value.saturating_sub(1)),
+        BinaryOp::LessEqual => *high = (*high).min(value),
+        _ => {}
+    }
+}
+
+/// `lhs op rhs` as `rhs op lhs`.
+fn commute(op: BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Greater => BinaryOp::Less,
+        BinaryOp::GreaterEqual => BinaryOp::LessEqual,
+        BinaryOp::Less => BinaryOp::Greater,
+        BinaryOp::LessEqual => BinaryOp::GreaterEqual,
+        op => op,
+    }
+}
+
+/// A table, one of its indexed columns, the index and a range of its keys.
+type IndexRange<'a, S> = (&'a Table<S>, usize, &'a BTree<S>, KeyRange);
+
+/// Finds whether a filter over a table scan can read the table through an
+/// index: the predicate must compare an indexed INTEGER column to INTEGER
+/// constants (`=`, `<`, `<=`, `>`, `>=` or BETWEEN), or match an indexed
+/// VARCHAR column against a LIKE pattern starting with a prefix, e.g.
+/// `'abc%'`. Ranges of INTEGER keys are preferred.
+///
+/// Returns the table, the indexed column, its index and the range of keys
+/// satisfying these comparisons. The predicate is still to be checked on the
+/// tuples read.
+pub fn index_range<'a, S: StorageBackend + 'static>(
+    input: &LogicalPlan,
+    predicate: &Expr,
+    tables: &'a Tables<S>,
+) -> Option<IndexRange<'a, S>> {
+    let LogicalPlan::Scan { table, schema, .. } = input else {
+        return None;
+    };
+    let integer_column = |expr: &Expr| match expr {
+        &Expr::Column(column) if schema.columns()[column].data_type == Some(DataType::Integer) => {
+            Some(column)
+        }
+        _ => None,
+    };
+    let integer_literal = |expr: &Expr| match expr {
+        &Expr::Literal(Value::Integer(value)) => Some(value),
+        _ => None,
+    };
+    let varchar_column = |expr: &Expr| match expr {
+        &Expr::Column(column)
+            if matches!(
+                schema.columns()[column].data_type,
+                Some(DataType::VarChar(_))
+            ) =>
+        {
+            Some(column)
+        }
+        _ => None,
+    };
+
+    // Ranges of the columns compared to constants, ordered by column so that
+    // the chosen index doesn't depend on the order of the conjuncts.
+    let mut ranges = BTreeMap::new();
+    // The longest prefix of the columns matched against LIKE patterns.
+    let mut prefixes = BTreeMap::<usize, String>::new();
+    let mut conjuncts = vec![predicate];
+    while let Some(expr) = conjuncts.pop() {
+        match expr {
+            Expr::Binary {
+                op: BinaryOp::And,
+                lhs,
+                rhs,
+            } => conjuncts.extend([lhs.as_ref(), rhs]),
+            Expr::Binary { op, lhs, rhs } => {
+                let (column, op, value) = match (integer_column(lhs), integer_column(rhs)) {
+                    (Some(column), None) => (column, *op, integer_literal(rhs)),
+                    (None, Some(column)) => (column, commute(*op), integer_literal(lhs)),
+                    _ => continue,
+                };
+                if let Some(value) = value {
+                    let range = ranges.entry(column).or_insert((i64::MIN, i64::MAX));
+                    narrow(range, op, value);
+                }
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated: false,
+            } => {
+                let Some(column) = integer_column(expr) else {
+                    continue;
+                };
+                if let (Some(low), Some(high)) = (integer_literal(low), integer_literal(high)) {
+                    let range = ranges.entry(column).or_insert((i64::MIN, i64::MAX));
+                    narrow(range, BinaryOp::GreaterEqual, low);
+                    narrow(range, BinaryOp::LessEqual, high);
+                }
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                negated: false,
+            } => {
+                let (Some(column), Expr::Literal(Value::VarChar(pattern))) =
+                    (varchar_column(expr), pattern.as_ref())
+                else {
+                    continue;
+                };
+                let prefix = like_prefix(pattern);
+                let longest = prefixes.entry(column).or_default();
+                if prefix.len() > longest.len() {
+                    *longest = prefix;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let ranges = ranges
+        .into_iter()
+        .filter(|(_, (low, high))| (*low, *high) != (i64::MIN, i64::MAX))
+        .map(|(column, (low, high))| (column, KeyRange::Integer(low..=high)));
+    let prefixes = prefixes
+        .into_iter()
+        .filter(|(_, prefix)| !prefix.is_empty())
+        .map(|(column, prefix)| (column, KeyRange::Prefix(prefix)));
+    ranges.chain(prefixes).find_map(|(column, range)| {
+        let index = tables.index(table, &schema.columns()[column].name)?;
+        Some((tables.get(table)?, column, index, range))
+    })
+}
+
+/// A table, one of its columns, the full-text index of the column and the
+/// query of a `CONTAINS` predicate on it.
+type FullTextSearch<'a, S> = (&'a Table<S>, usize, &'a FullTextIndex<S>, String);
+
+/// Finds whether a filter over a table scan can read the table through a
+/// full-text index: a conjunct of the predicate must be `CONTAINS(column,
+/// 'query')`, the column having a full-text index.
+///
+/// Returns the table, the column, its index and the query. The predicate is
+/// still to be checked on the tuples read.
+pub fn fulltext_search<'a, S: StorageBackend + 'static>(
+    input: &LogicalPlan,
+    predicate: &Expr,
+    tables: &'a Tables<S>,
+) -> Option<FullTextSearch<'a, S>> {
+    let LogicalPlan::Scan { table, .. } = input else {
+        return None;
+    };
+    let table = tables.get(table)?;
+    let mut conjuncts = vec![predicate];
+    while let Some(expr) = conjuncts.pop() {
+        match expr {
+            Expr::Binary {
+                op: BinaryOp::And,
+                lhs,
+                rhs,
+            } => conjuncts.extend([lhs.as_ref(), rhs]),
+            Expr::Function { function, args } if function.name() == "CONTAINS" => {
+                let [Expr::Column(column), Expr::Literal(Value::VarChar(query))] = &args[..] else {
+                    continue;
+                };
+                if let Some(index) = table.fulltext_index(*column) {
+                    return Some((table, *column, index, query.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::sql::exec::{RowStream, explain};
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan;
+    use crate::sql::schema::{Column, ConstraintsBuilder, Schema};
+    use crate::storage::FileStorage;
+    use crate::table::index_payload;
+
+    const NR_ROWS: i64 = 3000;
+
+    /// t(id, v, w) with v = w = id, indexed on id, including v.
+    fn tables() -> Tables<FileStorage> {
+        let columns = ["id", "v", "w"]
+            .map(|name| {
+                Column::new(
+                    name.to_string(),
+                    DataType::Integer,
+                    ConstraintsBuilder::new().build(),
+                )
+            })
+            .to_vec();
+        let schema = Schema::try_new(columns).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let mut table =
+            Table::try_new("t", &schema, GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+
+        // Inserted in reverse order: index scans return tuples in key order.
+        for id in (0..NR_ROWS).rev() {
+            let tuple = Tuple::try_new(vec![Value::Integer(id); 3]).unwrap();
+            let record_id = table.insert(&tuple).unwrap();
+            let key = Key::from_values(&[Value::Integer(id)]);
+            index
+                .insert_with_payload(&key, record_id, &index_payload(&tuple, &[1]))
+                .unwrap();
+        }
+        table.add_covering_index(0, vec![1], index);
+
+        let mut tables = Tables::new();
+        tables.add_table(table);
+        tables
+    }
+
+    fn range(tables: &Tables<FileStorage>, predicate: &str) -> Option<RangeInclusive<i64>> {
+        let source = format!("SELECT * FROM t WHERE {predicate}");
+        let stmts = Parser::parse(&source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        let LogicalPlan::Project { input, .. } = &plan else {
+            panic!("expected a projection");
+        };
+        let LogicalPlan::Filter { input, predicate } = input.as_ref() else {
+            panic!("expected a filter");
+        };
+        index_range(input, predicate, tables).map(|(.., range)| match range {
+            KeyRange::Integer(range) => range,
+            range => panic!("expected a range of INTEGER keys, got {range}"),
+        })
+    }
+
+    fn query(tables: &Tables<FileStorage>, source: &str) -> (String, Vec<Vec<Value>>) {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        let rows = RowStream::execute(&plan, tables)
+            .unwrap()
+            .map(|tuple| tuple.unwrap().values().to_vec())
+            .collect();
+        (explain(&plan, tables), rows)
+    }
+
+    fn ids(tables: &Tables<FileStorage>, predicate: &str) -> Vec<i64> {
+        let (_, rows) = query(tables, &format!("SELECT id FROM t WHERE {predicate}"));
+        rows.into_iter()
+            .map(|row| match row[0] {
+                Value::Integer(id) => id,
+                _ => panic!("expected an INTEGER"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn index_ranges() {
+        let tables = tables();
+
+        assert_eq!(range(&tables, "id = 7"), Some(7..=7));
+        assert_eq!(range(&tables, "id > 10 AND 20 >= id"), Some(11..=20));
+        assert_eq!(range(&tables, "id BETWEEN 5 AND 9 AND v < 3"), Some(5..=9));
+        assert_eq!(
+            range(&tables, "id <= 5000000000"),
+            Some(i64::MIN..=5000000000)
+        );
+        assert_eq!(range(&tables, "id < 0"), Some(i64::MIN..=-1));
+        assert!(range(&tables, "id > 10 AND id < 5").unwrap().is_empty());
+
+        assert_eq!(range(&tables, "v = 7"), None);
+        assert_eq!(range(&tables, "id + 0 = 7"), None);
+        assert_eq!(range(&tables, "id = 7 OR id = 8"), None);
+        assert_eq!(range(&tables, "id <> 7"), None);
+    }
+
+    #[test]
+    fn index_scan() {
+        let tables = tables();
+
+        assert_eq!(ids(&tables, "id >= 0"), (0..NR_ROWS).collect::<Vec<_>>());
+        assert_eq!(ids(&tables, "id = 1234"), [1234]);
+        assert_eq!(
+            ids(&tables, "id BETWEEN 100 AND 1500 AND v <> 200"),
+            (100..=1500).filter(|&id| id != 200).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ids(&tables, "id > 2990 AND id < 100000"),
+            (2991..NR_ROWS).collect::<Vec<_>>()
+        );
+        assert!(ids(&tables, "id < 0").is_empty());
+        assert!(ids(&tables, "id > 10 AND id < 5").is_empty());
+
+        let mut scanned = ids(&tables, "v BETWEEN 100 AND 1500");
+        scanned.sort();
+        assert_eq!(scanned, ids(&tables, "id BETWEEN 100 AND 1500"));
+    }
+
+    #[test]
+    fn index_scan_negative_and_null_keys() {
+        let tables = tables();
+        for row in ["-5, -5, -5", "NULL, 0, 0", "-1, -1, -1"] {
+            let source = format!("INSERT INTO t SELECT {row}");
+            let stmts = Parser::parse(&source).unwrap();
+            let plan = plan::plan(&stmts[0], &tables).unwrap();
+            RowStream::execute(&plan, &tables).unwrap().for_each(drop);
+        }
+
+        // Negative keys sort first, NULLs aren't read.
+        assert_eq!(ids(&tables, "id < 2"), [-5, -1, 0, 1]);
+        assert_eq!(ids(&tables, "id > 2997"), [2998, 2999]);
+        assert_eq!(ids(&tables, "id >= -5").len(), NR_ROWS as usize + 2);
+    }
+
+    #[test]
+    fn index_only_scan() {
+        let tables = tables();
+
+        // id and v are in the index, w isn't.
+        let (plan, rows) = query(&tables, "SELECT v, id FROM t WHERE id BETWEEN 10 AND 12");
+        assert_eq!(
+            plan,
+            "Project #1, #0\n  Filter (#0 BETWEEN 10 AND 12)\n    Index Only Scan t on id [10..=12]\n"
+        );
+        assert_eq!(
+            rows,
+            (10..=12)
+                .map(|id| vec![Value::Integer(id); 2])
+                .collect::<Vec<_>>()
+        );
+
+        let (plan, rows) = query(&tables, "SELECT w FROM t WHERE id > 2997");
+        assert!(plan.ends_with("    Index Scan t on id [2998..=9223372036854775807]\n"));
+        assert_eq!(rows, [[Value::Integer(2998)], [Value::Integer(2999)]]);
+
+        let (plan, _) = query(&tables, "SELECT * FROM t WHERE id = 5");
+        assert!(plan.ends_with("    Index Scan t on id [5..=5]\n"));
+
+        let (plan, _) = query(&tables, "SELECT id FROM t WHERE v = 5");
+        assert!(plan.ends_with("    Scan t [#0, #1]\n"));
+
+        // The included columns follow the updates of the table.
+        let (_, rows) = query(&tables, "INSERT INTO t SELECT 3000, 42, 0");
+        assert_eq!(rows, [[Value::Integer(1)]]);
+        let (_, rows) = query(&tables, "SELECT v FROM t WHERE id >= 3000");
+        assert_eq!(rows, [[Value::Integer(42)]]);
+    }
+
+    #[test]
+    fn index_scan_like_prefix() {
+        let schema = Schema::try_new(vec![Column::new(
+            "name".to_string(),
+            DataType::VarChar(None),
+            ConstraintsBuilder::new().nullable().build(),
+        )])
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let mut table =
+            Table::try_new("n", &schema, GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        for name in ["b", "ab", "a", "abc", "a%c", "ab\0", "abd", "ac", "Ab"] {
+            let tuple = Tuple::try_new(vec![Value::VarChar(name.to_string())]).unwrap();
+            let record_id = table.insert(&tuple).unwrap();
+            let key = Key::from_values(tuple.values());
+            index.insert(&key, record_id).unwrap();
+        }
+        table.add_index(0, index);
+        let mut tables = Tables::new();
+        tables.add_table(table);
+
+        let names = |pattern: &str| {
+            let source = format!("SELECT name FROM n WHERE name LIKE '{pattern}'");
+            let (plan, rows) = query(&tables, &source);
+            let names = rows
+                .into_iter()
+                .map(|row| match &row[0] {
+                    Value::VarChar(name) => name.clone(),
+                    _ => panic!("expected a VARCHAR"),
+                })
+                .collect::<Vec<_>>();
+            (plan.lines().last().unwrap().trim().to_string(), names)
+        };
+
+        assert_eq!(
+            names("ab%"),
+            (
+                "Index Only Scan n on name [prefix 'ab']".to_string(),
+                vec!["ab".into(), "ab\0".into(), "abc".into(), "abd".into()]
+            )
+        );
+        // The predicate is still checked on the keys of the range.
+        assert_eq!(names("a_c").1, ["a%c", "abc"]);
+        assert_eq!(names("ab_").1, ["ab\0", "abc", "abd"]);
+        assert_eq!(names("a\\%%").1, ["a%c"]);
+        // Patterns starting with a wildcard scan the table.
+        let (plan, mut found) = names("%b");
+        assert_eq!(plan, "Scan n");
+        found.sort();
+        assert_eq!(found, ["Ab", "ab", "b"]);
+    }
+
+    #[test]
+    fn index_order_scan() {
+        let columns = ["a", "b"]
+            .map(|name| {
+                Column::new(
+                    name.to_string(),
+                    DataType::Integer,
+                    ConstraintsBuilder::new().build(),
+                )
+            })
+            .to_vec();
+        let schema = Schema::try_new(columns).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let mut table =
+            Table::try_new("o", &schema, GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        // Indexed on a ASC, b DESC.
+        let key = vec![(0, KeyOptions::default()), (1, KeyOptions::descending())];
+        for (a, b) in [(1, 1), (0, 5), (1, 3), (0, -2), (2, 0), (1, 2)] {
+            let tuple = Tuple::try_new(vec![Value::Integer(a), Value::Integer(b)]).unwrap();
+            let record_id = table.insert(&tuple).unwrap();
+            let key =
+                Key::from_values_with(tuple.values(), &key.iter().map(|k| k.1).collect::<Vec<_>>());
+            index.insert(&key, record_id).unwrap();
+        }
+        table.add_composite_index(key, Vec::new(), index);
+        let mut tables = Tables::new();
+        tables.add_table(table);
+
+        let sorted = |predicate: &str, order_by: &str| {
+            let source = format!("SELECT * FROM o WHERE {predicate} ORDER BY {order_by}");
+            let stmts = Parser::parse(&source).unwrap();
+            let plan = plan::plan(&stmts[0], &tables).unwrap();
+            let rows = RowStream::execute(&plan, &tables)
+                .unwrap()
+                .map(|tuple| match tuple.unwrap().values() {
+                    [Value::Integer(a), Value::Integer(b)] => (*a, *b),
+                    _ => panic!("expected INTEGERs"),
+                })
+                .collect::<Vec<_>>();
+            (explain(&plan, &tables), rows)
+        };
+
+        let (plan, rows) = sorted("b <> 3", "a, b DESC");
+        assert_eq!(
+            plan,
+            "Project #0, #1\n  Sort #0 ASC, #1 DESC\n    Filter (#1 != 3)\n      Index Scan o on a ASC, b DESC\n"
+        );
+        assert_eq!(rows, [(0, 5), (0, -2), (1, 2), (1, 1), (2, 0)]);
+
+        // All the directions reversed, or a prefix of the key.
+        let (plan, rows) = sorted("a >= 1", "1 DESC, b ASC");
+        assert!(plan.ends_with("      Index Scan Backward o on a ASC, b DESC\n"));
+        assert_eq!(rows, [(2, 0), (1, 1), (1, 2), (1, 3)]);
+        let (_, rows) = sorted("a < 1", "a");
+        assert_eq!(rows, [(0, 5), (0, -2)]);
+        let (plan, rows) = sorted("a >= 0", "a DESC, b LIMIT 2 OFFSET 1");
+        assert!(plan.starts_with("Limit 2 offset 1\n"));
+        assert!(plan.ends_with("      Index Scan Backward o on a ASC, b DESC\n"));
+        assert_eq!(rows, [(1, 1), (1, 2)]);
+
+        // Other orders sort the tuples of the table scan.
+        let (plan, rows) = sorted("a >= 0", "a, b");
+        assert!(!plan.contains("Index Scan"));
+        assert_eq!(rows, [(0, -2), (0, 5), (1, 1), (1, 2), (1, 3), (2, 0)]);
+        let (plan, rows) = sorted("a >= 0", "b DESC");
+        assert!(!plan.contains("Index Scan"));
+        assert_eq!(rows, [(0, 5), (1, 3), (1, 2), (1, 1), (2, 0), (0, -2)]);
+    }
+
+    #[test]
+    fn fulltext_scan() {
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".to_string(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "body".to_string(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let mut table =
+            Table::try_new("d", &schema, GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = FullTextIndex::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        table.add_fulltext_index(1, index);
+        let mut tables = Tables::new();
+        tables.add_table(table);
+
+        // The index is maintained by the inserts.
+        let bodies = ["'Red apple'", "'green apple'", "'red cherry'", "NULL"];
+        for id in 0..200 {
+            let body = bodies[id % bodies.len()];
+            query(&tables, &format!("INSERT INTO d SELECT {id}, {body}"));
+        }
+        let ids = |predicate: &str| {
+            let (plan, rows) = query(&tables, &format!("SELECT id FROM d WHERE {predicate}"));
+            let ids = rows
+                .into_iter()
+                .map(|row| match row[0] {
+                    Value::Integer(id) => id,
+                    _ => panic!("expected an INTEGER"),
+                })
+                .collect::<Vec<_>>();
+            (plan.lines().last().unwrap().trim().to_string(), ids)
+        };
+
+        let (plan, found) = ids("CONTAINS(body, 'apple')");
+        assert_eq!(plan, "Full Text Scan d on body [contains 'apple']");
+        assert_eq!(found, (0..200).filter(|id| id % 4 < 2).collect::<Vec<_>>());
+        // The other conjuncts are checked on the tuples read.
+        let (plan, found) = ids("id < 10 AND contains(body, 'RED apple')");
+        assert_eq!(plan, "Full Text Scan d on body [contains 'RED apple']");
+        assert_eq!(found, [0, 4, 8]);
+        assert!(ids("CONTAINS(body, 'banana')").1.is_empty());
+
+        // Without a literal query, the table is scanned.
+        let (plan, found) = ids("CONTAINS(body, body)");
+        assert_eq!(plan, "Scan d");
+        assert_eq!(found.len(), 150);
+
+        // The index is maintained by the deletes.
+        let table = tables.get("d").unwrap();
+        let mut iter = table.iter();
+        let mut deleted = Vec::new();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if let Value::Integer(id @ 0..100) = tuple.values()[0] {
+                deleted.push((id, record_id));
+            }
+        }
+        for (_, record_id) in deleted {
+            table.delete(record_id).unwrap();
+        }
+        let (_, found) = ids("CONTAINS(body, 'red')");
+        assert_eq!(found, (100..200).step_by(2).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::sql::exec::{ExecError, Executor};
+use crate::sql::plan::LogicalPlan;
+use crate::sql::types::Value;
+use crate::storage::StorageBackend;
+use crate::table::Table;
+use crate::tuple::Tuple;
+
+/// Inserts the tuples of its input into a table and commits them, see
+/// `Table::commit`, then outputs a single tuple with the number of rows
+/// inserted.
+///
+/// Values are cast to the type of their target column and the tuples are
+/// validated against the schema of the table, which updates its indexes.
+pub struct Insert<'a, S: StorageBackend + 'static> {
+    input: Box<dyn Executor + 'a>,
+    table: &'a Table<S>,
+    columns: &'a [usize],
+    // Whether the input reads the table: it is then read entirely before
+    // inserting, otherwise the inserted tuples could be read back.
+    materialize: bool,
+    done: bool,
+}
+
+impl<'a, S: StorageBackend + 'static> Insert<'a, S> {
+    pub fn new(
+        input: Box<dyn Executor + 'a>,
+        table: &'a Table<S>,
+        columns: &'a [usize],
+        materialize: bool,
+    ) -> Self {
+        Self {
+            input,
+            table,
+            columns,
+            materialize,
+            done: false,
+        }
+    }
+
+    fn insert(&self, tuple: &Tuple) -> Result<(), ExecError> {
+        let schema = self.table.schema.columns();
+        let mut values = vec![Value::Null; schema.len()];
+        for (value, &idx) in tuple.values().iter().zip(self.columns) {
+            values[idx] = match value {
+                Value::Null => Value::Null,
+                value => value.cast(schema[idx].data_type)?,
+            };
+        }
+
+        self.table.insert(&Tuple::try_new(values)?)?;
+        Ok(())
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for Insert<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if std::mem::replace(&mut self.done, true) {
+            return Ok(None);
+        }
+
+        let mut rows = 0;
+        if self.materialize {
+            let mut tuples = Vec::new();
+            while let Some(batch) = self.input.next_batch()? {
+                tuples.extend(batch);
+            }
+            for tuple in &tuples {
+                self.insert(tuple)?;
+            }
+            rows = tuples.len();
+        } else {
+            while let Some(batch) = self.input.next_batch()? {
+                for tuple in &batch {
+                    self.insert(tuple)?;
+                }
+                rows += batch.len();
+            }
+        }
+        self.table.commit();
+
+        let rows = i64::try_from(rows).map_err(|_| ExecError::IntegerOverflow)?;
+        Ok(Some(vec![Tuple::try_new(vec![Value::Integer(rows)])?]))
+    }
+}
+
+/// Returns whether a plan scans a table.
+pub fn reads_table(plan: &LogicalPlan, table: &str) -> bool {
+    match plan {
+        LogicalPlan::SingleRow | LogicalPlan::SetGlobal { .. } => false,
+        LogicalPlan::Scan { table: name, .. } | LogicalPlan::Vacuum { table: name, .. } => {
+            name == table
+        }
+        LogicalPlan::Join { left, right, .. } => {
+            reads_table(left, table) || reads_table(right, table)
+        }
+        LogicalPlan::Filter { input, .. }
+        | LogicalPlan::Project { input, .. }
+        | LogicalPlan::Aggregate { input, .. }
+        | LogicalPlan::Sort { input, .. }
+        | LogicalPlan::Limit { input, .. }
+        | LogicalPlan::Insert { input, .. } => reads_table(input, table),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::sql::exec::{RowStream, Tables};
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan;
+    use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+    use crate::storage::FileStorage;
+
+    fn table(name: &str) -> Table<FileStorage> {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let cache = GLOBAL_PAGE_CACHE.cache_storage(storage);
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        Table::try_new(name, &schema, cache).unwrap()
+    }
+
+    fn query(tables: &Tables<FileStorage>, source: &str) -> Result<Vec<Vec<Value>>, ExecError> {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        RowStream::execute(&plan, tables)?
+            .map(|tuple| tuple.map(|tuple| tuple.values().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn insert_select() {
+        let users = table("users");
+        for id in 0..100 {
+            let name = Value::VarChar(format!("user{id}"));
+            users
+                .insert(&Tuple::try_new(vec![Value::Integer(id), name]).unwrap())
+                .unwrap();
+        }
+        let mut tables = Tables::new();
+        tables.add_table(users);
+        tables.add_table(table("archive"));
+
+        let count = |tables: &Tables<FileStorage>, table: &str| {
+            query(tables, &format!("SELECT COUNT(*) FROM {table}")).unwrap()[0][0].clone()
+        };
+
+        // Columns are matched by position, the missing ones are NULL.
+        assert_eq!(
+            query(
+                &tables,
+                "INSERT INTO archive (id) SELECT id * 2 FROM users WHERE id < 10"
+            )
+            .unwrap(),
+            vec![vec![Value::Integer(10)]]
+        );
+        assert_eq!(
+            query(&tables, "SELECT id, name FROM archive WHERE id = 18").unwrap(),
+            vec![vec![Value::Integer(18), Value::Null]]
+        );
+
+        // The inserted tuples are not read back.
+        assert_eq!(
+            query(&tables, "INSERT INTO users SELECT * FROM users").unwrap(),
+            vec![vec![Value::Integer(100)]]
+        );
+        assert_eq!(count(&tables, "users"), Value::Integer(200));
+
+        // Tuples are validated against the schema of the table.
+        assert!(matches!(
+            query(&tables, "INSERT INTO archive (name) SELECT name FROM users"),
+            Err(ExecError::Table(_))
+        ));
+        assert!(matches!(
+            query(&tables, "INSERT INTO archive (id) SELECT 'a'"),
+            Err(ExecError::Cast(_))
+        ));
+    }
+}
@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::slice;
+
+use crate::indexes::BTree;
+use crate::pages::Key;
+use crate::sql::exec::executor::{Executor, QueryContext, Tables, build};
+use crate::sql::exec::{
+    Evaluator, ExecError, MemoryBudget, Reservation, SpillPartitions, SpillReader, memory_size,
+};
+use crate::sql::parser::ast::JoinKind;
+use crate::sql::plan::{Expr, LogicalPlan, equi_join_keys};
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+use crate::storage::StorageBackend;
+use crate::table::{self, Table};
+use crate::tuple::Tuple;
+
+/// Returns the outer tuple followed by the inner values.
+fn concat(outer: &Tuple, inner: &[Value]) -> Result<Tuple, ExecError> {
+    let values = outer.values().iter().chain(inner).cloned().collect();
+    Ok(Tuple::try_new(values)?)
+}
+
+/// Pads an outer tuple without match of a LEFT JOIN with NULLs.
+fn pad(outer: &Tuple, inner_width: usize) -> Result<Tuple, ExecError> {
+    concat(outer, &vec![Value::Null; inner_width])
+}
+
+/// Block nested loop join: the inner side is run again for each batch of
+/// outer tuples, each inner tuple is matched against the whole batch.
+pub struct NestedLoopJoin<'a, S: StorageBackend + 'static> {
+    kind: JoinKind,
+    outer: Box<dyn Executor + 'a>,
+    inner: &'a LogicalPlan,
+    tables: &'a Tables<S>,
+    // Handed to each run of the inner side.
+    context: QueryContext,
+    // `None` for a cross join.
+    on: Option<Evaluator>,
+}
+
+impl<'a, S: StorageBackend + 'static> NestedLoopJoin<'a, S> {
+    pub fn new(
+        kind: JoinKind,
+        outer: Box<dyn Executor + 'a>,
+        inner: &'a LogicalPlan,
+        tables: &'a Tables<S>,
+        context: QueryContext,
+        on: Option<&Expr>,
+    ) -> Self {
+        Self {
+            kind,
+            outer,
+            inner,
+            tables,
+            context,
+            on: on.map(Evaluator::new),
+        }
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for NestedLoopJoin<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        while let Some(outer) = self.outer.next_batch()? {
+            let mut matched = vec![false; outer.len()];
+            let mut output = Vec::new();
+
+            let mut inner = build(self.inner, self.tables, &self.context)?;
+            while let Some(batch) = inner.next_batch()? {
+                for inner_tuple in &batch {
+                    for (i, outer_tuple) in outer.iter().enumerate() {
+                        let tuple = concat(outer_tuple, inner_tuple.values())?;
+                        if self
+                            .on
+                            .as_ref()
+                            .map_or(Ok(true), |on| on.satisfies(tuple.values()))?
+                        {
+                            matched[i] = true;
+                            output.push(tuple);
+                        }
+                    }
+                }
+            }
+
+            if self.kind == JoinKind::Left {
+                let inner_width = self.inner.schema().columns().len();
+                for (outer_tuple, _) in outer.iter().zip(matched).filter(|(_, m)| !m) {
+                    output.push(pad(outer_tuple, inner_width)?);
+                }
+            }
+
+            if !output.is_empty() {
+                return Ok(Some(output));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Nested loop join looking up the inner tuples of each outer tuple in an
+/// index of the inner table, instead of scanning the table.
+pub struct IndexNestedLoopJoin<'a, S: StorageBackend + 'static> {
+    kind: JoinKind,
+    outer: Box<dyn Executor + 'a>,
+    // The outer column equal to the indexed column.
+    outer_key: usize,
+    table: &'a Table<S>,
+    index: &'a BTree<S>,
+    // Checked on the joined tuples: it may have other conditions than the
+    // equality on the key.
+    on: Option<Evaluator>,
+}
+
+impl<'a, S: StorageBackend + 'static> IndexNestedLoopJoin<'a, S> {
+    pub fn new(
+        kind: JoinKind,
+        outer: Box<dyn Executor + 'a>,
+        outer_key: usize,
+        table: &'a Table<S>,
+        index: &'a BTree<S>,
+        on: Option<&Expr>,
+    ) -> Self {
+        Self {
+            kind,
+            outer,
+            outer_key,
+            table,
+            index,
+            on: on.map(Evaluator::new),
+        }
+    }
+
+    fn lookup(&self, outer: &Tuple) -> Result<Vec<Tuple>, ExecError> {
+        let key = match outer.values().get(self.outer_key) {
+            // NULLs match nothing, they aren't indexed.
+            Some(Value::Null) => return Ok(Vec::new()),
+            Some(value) => Key::from_values(slice::from_ref(value)),
+            None => return Err(ExecError::ColumnOutOfRange(self.outer_key)),
+        };
+
+        let mut tuples = Vec::new();
+        for record_id in table::lookup(self.index, &key)? {
+            tuples.push(self.table.get(record_id)?);
+        }
+
+        Ok(tuples)
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for IndexNestedLoopJoin<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        while let Some(outer) = self.outer.next_batch()? {
+            let mut output = Vec::new();
+            for outer_tuple in &outer {
+                let mut matched = false;
+                for inner_tuple in self.lookup(outer_tuple)? {
+                    let tuple = concat(outer_tuple, inner_tuple.values())?;
+                    if self
+                        .on
+                        .as_ref()
+                        .map_or(Ok(true), |on| on.satisfies(tuple.values()))?
+                    {
+                        output.push(tuple);
+                        matched = true;
+                    }
+                }
+                if !matched && self.kind == JoinKind::Left {
+                    output.push(pad(outer_tuple, self.table.schema.num_columns())?);
+                }
+            }
+
+            if !output.is_empty() {
+                return Ok(Some(output));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Hash join: the inner tuples are loaded in a hash table on the equi-join
+/// keys, each outer tuple is then matched against the inner tuples with the
+/// same keys. NULL keys never match.
+///
+/// When the inner tuples exceed the memory budget, both sides are spilled to
+/// partitions on their keys and joined one pair of partitions at a time
+/// (grace hash join). A partition of the inner side is assumed to fit in
+/// memory.
+pub struct HashJoin<'a> {
+    kind: JoinKind,
+    // The outer input, then the outer side of the partition being joined.
+    outer: Box<dyn Executor + 'a>,
+    // Taken once the hash table is built.
+    inner: Option<Box<dyn Executor + 'a>>,
+    inner_width: usize,
+    // (outer column, inner column)
+    keys: Vec<(usize, usize)>,
+    // Checked on the joined tuples: it may have other conditions than the
+    // equalities on the keys.
+    on: Option<Evaluator>,
+    table: HashMap<Vec<Value>, Vec<Tuple>>,
+    budget: MemoryBudget,
+    reservation: Reservation,
+    // The (inner, outer) spilled partitions left to join.
+    partitions: Vec<(SpillReader, SpillReader)>,
+}
+
+impl<'a> HashJoin<'a> {
+    pub fn new(
+        kind: JoinKind,
+        outer: Box<dyn Executor + 'a>,
+        inner: Box<dyn Executor + 'a>,
+        inner_width: usize,
+        keys: Vec<(usize, usize)>,
+        on: Option<&Expr>,
+        budget: MemoryBudget,
+    ) -> Self {
+        Self {
+            kind,
+            outer,
+            inner: Some(inner),
+            inner_width,
+            keys,
+            on: on.map(Evaluator::new),
+            table: HashMap::new(),
+            reservation: budget.reservation(),
+            budget,
+            partitions: Vec::new(),
+        }
+    }
+
+    /// Returns the values of the keys of a tuple, `None` if one is NULL.
+    fn key(
+        tuple: &Tuple,
+        columns: impl Iterator<Item = usize>,
+    ) -> Result<Option<Vec<Value>>, ExecError> {
+        let mut key = Vec::new();
+        for idx in columns {
+            match tuple.values().get(idx) {
+                Some(Value::Null) => return Ok(None),
+                Some(value) => key.push(value.clone()),
+                None => return Err(ExecError::ColumnOutOfRange(idx)),
+            }
+        }
+        Ok(Some(key))
+    }
+
+    fn inner_key(&self, tuple: &Tuple) -> Result<Option<Vec<Value>>, ExecError> {
+        Self::key(tuple, self.keys.iter().map(|&(_, inner_key)| inner_key))
+    }
+
+    fn outer_key(&self, tuple: &Tuple) -> Result<Option<Vec<Value>>, ExecError> {
+        Self::key(tuple, self.keys.iter().map(|&(outer_key, _)| outer_key))
+    }
+
+    fn build(&mut self) -> Result<(), ExecError> {
+        let Some(mut inner) = self.inner.take() else {
+            return Ok(());
+        };
+
+        let mut spilled: Option<SpillPartitions> = None;
+        while let Some(batch) = inner.next_batch()? {
+            for tuple in batch {
+                let Some(key) = self.inner_key(&tuple)? else {
+                    continue;
+                };
+                if spilled.is_none() && !self.reservation.try_grow(memory_size(&tuple)) {
+                    // Moves the hash table to the partitions.
+                    let mut partitions = SpillPartitions::new(&self.budget)?;
+                    for (key, tuples) in self.table.drain() {
+                        for tuple in &tuples {
+                            partitions.write(&key, tuple)?;
+                        }
+                    }
+                    self.reservation.free();
+                    spilled = Some(partitions);
+                }
+                match &mut spilled {
+                    Some(partitions) => partitions.write(&key, &tuple)?,
+                    None => self.table.entry(key).or_default().push(tuple),
+                }
+            }
+        }
+
+        let Some(inner) = spilled else {
+            return Ok(());
+        };
+        // Outer tuples with a NULL key match nothing, they are only kept to be
+        // padded by LEFT JOINs.
+        let mut outer = SpillPartitions::new(&self.budget)?;
+        while let Some(batch) = self.outer.next_batch()? {
+            for tuple in batch {
+                match self.outer_key(&tuple)? {
+                    Some(key) => outer.write(&key, &tuple)?,
+                    None if self.kind == JoinKind::Left => outer.write(&[], &tuple)?,
+                    None => {}
+                }
+            }
+        }
+
+        self.partitions = inner
+            .into_readers()?
+            .into_iter()
+            .zip(outer.into_readers()?)
+            .collect();
+        self.partitions.reverse();
+        self.next_partition()?;
+        Ok(())
+    }
+
+    /// Loads the next spilled partition of the inner side in the hash table,
+    /// returns false once all the partitions are joined.
+    fn next_partition(&mut self) -> Result<bool, ExecError> {
+        let Some((mut inner, outer)) = self.partitions.pop() else {
+            return Ok(false);
+        };
+
+        self.table.clear();
+        self.reservation.free();
+        while let Some(tuple) = inner.read()? {
+            // Spilled tuples have a key.
+            let key = self.inner_key(&tuple)?.unwrap();
+            self.reservation.grow(memory_size(&tuple));
+            self.table.entry(key).or_default().push(tuple);
+        }
+        self.outer = Box::new(outer);
+        Ok(true)
+    }
+}
+
+impl Executor for HashJoin<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        self.build()?;
+
+        loop {
+            while let Some(outer) = self.outer.next_batch()? {
+                let mut output = Vec::new();
+                for outer_tuple in &outer {
+                    let matches = match self.outer_key(outer_tuple)? {
+                        Some(key) => self.table.get(&key).map_or(&[][..], Vec::as_slice),
+                        None => &[],
+                    };
+
+                    let mut matched = false;
+                    for inner_tuple in matches {
+                        let tuple = concat(outer_tuple, inner_tuple.values())?;
+                        if self
+                            .on
+                            .as_ref()
+                            .map_or(Ok(true), |on| on.satisfies(tuple.values()))?
+                        {
+                            matched = true;
+                            output.push(tuple);
+                        }
+                    }
+                    if !matched && self.kind == JoinKind::Left {
+                        output.push(pad(outer_tuple, self.inner_width)?);
+                    }
+                }
+
+                if !output.is_empty() {
+                    return Ok(Some(output));
+                }
+            }
+
+            if !self.next_partition()? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Finds whether the inner side of a join can be read through an index: it
+/// must be a table scan and the join condition must have an equality between
+/// an INTEGER outer column and an indexed INTEGER inner column.
+///
+/// Returns the outer column, the inner table and its index.
+pub fn index_lookup<'a, S: StorageBackend + 'static>(
+    outer: &LogicalPlan,
+    inner: &'a LogicalPlan,
+    on: Option<&Expr>,
+    tables: &'a Tables<S>,
+) -> Option<(usize, &'a Table<S>, &'a BTree<S>)> {
+    let LogicalPlan::Scan { table, schema, .. } = inner else {
+        return None;
+    };
+
+    // Both keys have the same type.
+    equi_join_keys(outer, inner, on)
+        .into_iter()
+        .find_map(|(outer_key, inner_key)| {
+            let inner_column = &schema.columns()[inner_key];
+            if inner_column.data_type != Some(DataType::Integer) {
+                return None;
+            }
+            let index = tables.index(table, &inner_column.name)?;
+            Some((outer_key, tables.get(table)?, index))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::pages::RecordId;
+    use crate::sql::exec::RowStream;
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan::{self, JoinStrategy, TableStats};
+    use crate::sql::schema::{Column, ConstraintsBuilder, Schema};
+    use crate::storage::FileStorage;
+
+    fn create_table(
+        name: &str,
+        columns: &[&str],
+        rows: &[Vec<Value>],
+    ) -> (Table<FileStorage>, Vec<RecordId>) {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let columns = columns
+            .iter()
+            .map(|column| {
+                Column::new(
+                    column.to_string(),
+                    DataType::Integer,
+                    ConstraintsBuilder::new().nullable().build(),
+                )
+            })
+            .collect();
+        let schema = Schema::try_new(columns).unwrap();
+        let table =
+            Table::try_new(name, &schema, GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let record_ids = rows
+            .iter()
+            .map(|row| table.insert(&Tuple::try_new(row.clone()).unwrap()).unwrap())
+            .collect();
+        (table, record_ids)
+    }
+
+    /// users(id, age) indexed on id, orders(id, user_id). The planner is told
+    /// that users has `users_rows` rows.
+    fn tables(users_rows: u64) -> Tables<FileStorage> {
+        let users = (1..=4)
+            .map(|id| vec![Value::Integer(id), Value::Integer(20 + id)])
+            .collect::<Vec<_>>();
+        let (users, record_ids) = create_table("users", &["id", "age"], &users);
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        for (id, record_id) in (1..=4).zip(record_ids) {
+            index
+                .insert(&Key::from_values(&[Value::Integer(id)]), record_id)
+                .unwrap();
+        }
+
+        let orders = [
+            (10, Value::Integer(1)),
+            (11, Value::Integer(1)),
+            (12, Value::Integer(3)),
+            (13, Value::Null),
+            (14, Value::Integer(9)),
+        ]
+        .map(|(id, user_id)| vec![Value::Integer(id), user_id]);
+        let (orders, _) = create_table("orders", &["id", "user_id"], &orders);
+
+        let mut tables = Tables::new();
+        tables.set_stats("orders", orders.statistics());
+        tables.set_stats(
+            "users",
+            TableStats {
+                rows: users_rows,
+                pages: users_rows.div_ceil(100),
+            },
+        );
+        tables.add_table(users);
+        tables.add_table(orders);
+        tables.add_index("users", "id", index).unwrap();
+        tables
+    }
+
+    fn query(tables: &Tables<FileStorage>, source: &str) -> Vec<Vec<Value>> {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        let mut rows = RowStream::execute(&plan, tables)
+            .unwrap()
+            .map(|tuple| tuple.unwrap().values().to_vec())
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less));
+        rows
+    }
+
+    /// Returns the strategy of the topmost join of a query.
+    fn strategy(tables: &Tables<FileStorage>, source: &str) -> JoinStrategy {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        let mut node = &plan;
+        loop {
+            match node {
+                LogicalPlan::Join { strategy, .. } => return *strategy,
+                LogicalPlan::Project { input, .. } | LogicalPlan::Filter { input, .. } => {
+                    node = input
+                }
+                _ => panic!("expected a join"),
+            }
+        }
+    }
+
+    #[test]
+    fn inner_join() {
+        let expected = [[10, 21], [11, 21], [12, 23]].map(|row| row.map(Value::Integer).to_vec());
+        let sources = [
+            "SELECT o.id, age FROM orders o JOIN users u ON o.user_id = u.id",
+            "SELECT o.id, age FROM users u JOIN orders o ON o.user_id = u.id",
+        ];
+
+        // Many users: the few orders look up their user in the index.
+        let large = tables(100_000);
+        for source in sources {
+            assert_eq!(strategy(&large, source), JoinStrategy::IndexNestedLoop);
+            assert_eq!(query(&large, source), expected);
+        }
+        // Few users: they are loaded in a hash table.
+        let small = tables(4);
+        for source in sources {
+            assert_eq!(strategy(&small, source), JoinStrategy::Hash);
+            assert_eq!(query(&small, source), expected);
+        }
+
+        // Other conditions are checked on the joined tuples.
+        let source =
+            "SELECT o.id FROM orders o JOIN users u ON u.id = o.user_id AND o.id + age > 31";
+        for tables in [&large, &small] {
+            assert_eq!(
+                query(tables, source),
+                [[11], [12]].map(|row| row.map(Value::Integer).to_vec())
+            );
+        }
+
+        let source = "SELECT o.id FROM orders o JOIN users u ON o.user_id = u.age";
+        assert_eq!(strategy(&large, source), JoinStrategy::Hash);
+        assert!(query(&large, source).is_empty());
+
+        let source = "SELECT o.id FROM users, orders o";
+        assert_eq!(strategy(&large, source), JoinStrategy::NestedLoop);
+        assert_eq!(query(&large, source).len(), 20);
+    }
+
+    #[test]
+    fn left_join() {
+        let expected = vec![
+            vec![Value::Integer(10), Value::Integer(21)],
+            vec![Value::Integer(11), Value::Integer(21)],
+            vec![Value::Integer(12), Value::Integer(23)],
+            vec![Value::Integer(13), Value::Null],
+            vec![Value::Integer(14), Value::Null],
+        ];
+
+        let source = "SELECT o.id, age FROM orders o LEFT JOIN users u ON o.user_id = u.id";
+        for (users_rows, expected_strategy) in [
+            (100_000, JoinStrategy::IndexNestedLoop),
+            (4, JoinStrategy::Hash),
+        ] {
+            let tables = tables(users_rows);
+            assert_eq!(strategy(&tables, source), expected_strategy);
+            assert_eq!(query(&tables, source), expected);
+        }
+
+        let tables = tables(4);
+        let source = "SELECT o.id, age FROM orders o LEFT JOIN users u ON o.user_id + 0 = u.id";
+        assert_eq!(strategy(&tables, source), JoinStrategy::NestedLoop);
+        assert_eq!(query(&tables, source), expected);
+    }
+}
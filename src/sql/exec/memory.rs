@@ -0,0 +1,343 @@
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sql::exec::{BATCH_SIZE, ExecError, Executor};
+use crate::sql::types::Value;
+use crate::tuple::Tuple;
+
+/// The number of partitions operators spill their tuples to.
+pub const SPILL_PARTITIONS: usize = 16;
+
+/// The memory the operators of a query may use, the hash tables of hash
+/// joins, aggregations and DISTINCT. Clones share the same budget.
+///
+/// Operators register a `Reservation` and grow it as their state grows, they
+/// spill to temporary files in `spill_dir` once the budget is exhausted.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget(Arc<Budget>);
+
+#[derive(Debug)]
+struct Budget {
+    limit: usize,
+    used: AtomicUsize,
+    spill_dir: PathBuf,
+    spills: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize, spill_dir: impl Into<PathBuf>) -> Self {
+        Self(Arc::new(Budget {
+            limit,
+            used: AtomicUsize::new(0),
+            spill_dir: spill_dir.into(),
+            spills: AtomicUsize::new(0),
+        }))
+    }
+
+    /// A budget operators never exceed.
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX, std::env::temp_dir())
+    }
+
+    /// Returns the number of bytes reserved by the operators.
+    pub fn used(&self) -> usize {
+        self.0.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of spill files created.
+    pub fn spills(&self) -> usize {
+        self.0.spills.load(Ordering::Relaxed)
+    }
+
+    pub fn reservation(&self) -> Reservation {
+        Reservation {
+            budget: self.clone(),
+            size: 0,
+        }
+    }
+
+    /// Creates a file in the spill directory, removed once dropped.
+    pub fn spill_file(&self) -> Result<SpillFile, ExecError> {
+        let file = tempfile::tempfile_in(&self.0.spill_dir)?;
+        self.0.spills.fetch_add(1, Ordering::Relaxed);
+        Ok(SpillFile {
+            writer: BufWriter::new(file),
+            len: 0,
+        })
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+/// The memory used by an operator, released when dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: MemoryBudget,
+    size: usize,
+}
+
+impl Reservation {
+    /// Reserves `bytes` more, returns false without reserving anything if
+    /// that would exceed the budget.
+    pub fn try_grow(&mut self, bytes: usize) -> bool {
+        let budget = &self.budget.0;
+        let reserved = budget
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&used| used <= budget.limit)
+            })
+            .is_ok();
+        if reserved {
+            self.size += bytes;
+        }
+        reserved
+    }
+
+    /// Reserves `bytes` more even if that exceeds the budget, for state that
+    /// can't be spilled.
+    pub fn grow(&mut self, bytes: usize) {
+        self.budget.0.used.fetch_add(bytes, Ordering::Relaxed);
+        self.size += bytes;
+    }
+
+    /// Releases all the memory reserved.
+    pub fn free(&mut self) {
+        self.budget.0.used.fetch_sub(self.size, Ordering::Relaxed);
+        self.size = 0;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
+
+/// Returns an estimate of the memory used by a tuple.
+pub fn memory_size(tuple: &Tuple) -> usize {
+    std::mem::size_of::<Tuple>() + values_memory_size(tuple.values())
+}
+
+/// Returns an estimate of the memory used by values, e.g. a key.
+pub fn values_memory_size(values: &[Value]) -> usize {
+    let strings = values.iter().map(|value| match value {
+        Value::VarChar(s) => s.capacity(),
+        _ => 0,
+    });
+    std::mem::size_of_val(values) + strings.sum::<usize>()
+}
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_INTEGER: u8 = 3;
+const TAG_FLOAT: u8 = 4;
+const TAG_VARCHAR: u8 = 5;
+
+/// A temporary file tuples are written to, then read back in the same order.
+///
+/// Values are written with their type: spilled tuples don't have a schema.
+pub struct SpillFile {
+    writer: BufWriter<File>,
+    len: usize,
+}
+
+impl SpillFile {
+    pub fn write(&mut self, tuple: &Tuple) -> Result<(), ExecError> {
+        let writer = &mut self.writer;
+        writer.write_all(&(tuple.values().len() as u16).to_le_bytes())?;
+        for value in tuple.values() {
+            match value {
+                Value::Null => writer.write_all(&[TAG_NULL])?,
+                Value::Boolean(false) => writer.write_all(&[TAG_FALSE])?,
+                Value::Boolean(true) => writer.write_all(&[TAG_TRUE])?,
+                Value::Integer(i) => {
+                    writer.write_all(&[TAG_INTEGER])?;
+                    writer.write_all(&i.to_le_bytes())?;
+                }
+                Value::Float(f) => {
+                    writer.write_all(&[TAG_FLOAT])?;
+                    writer.write_all(&f.to_le_bytes())?;
+                }
+                Value::VarChar(s) => {
+                    writer.write_all(&[TAG_VARCHAR])?;
+                    writer.write_all(&(s.len() as u32).to_le_bytes())?;
+                    writer.write_all(s.as_bytes())?;
+                }
+            }
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns the number of tuples written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads the tuples back from the start of the file.
+    pub fn into_reader(self) -> Result<SpillReader, ExecError> {
+        let mut file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(SpillReader {
+            reader: BufReader::new(file),
+            remaining: self.len,
+        })
+    }
+}
+
+/// Reads back the tuples of a `SpillFile`.
+pub struct SpillReader {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl SpillReader {
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ExecError> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Returns the next tuple, `None` once all of them are read.
+    pub fn read(&mut self) -> Result<Option<Tuple>, ExecError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        let len = u16::from_le_bytes(self.read_array()?) as usize;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            let [tag] = self.read_array()?;
+            values.push(match tag {
+                TAG_NULL => Value::Null,
+                TAG_FALSE => Value::Boolean(false),
+                TAG_TRUE => Value::Boolean(true),
+                TAG_INTEGER => Value::Integer(i64::from_le_bytes(self.read_array()?)),
+                TAG_FLOAT => Value::Float(f64::from_le_bytes(self.read_array()?)),
+                TAG_VARCHAR => {
+                    let len = u32::from_le_bytes(self.read_array()?) as usize;
+                    let mut bytes = vec![0; len];
+                    self.reader.read_exact(&mut bytes)?;
+                    let s = String::from_utf8(bytes).map_err(std::io::Error::other)?;
+                    Value::VarChar(s)
+                }
+                tag => unreachable!("invalid spilled value tag {tag}"),
+            });
+        }
+
+        Ok(Some(Tuple::try_new(values)?))
+    }
+}
+
+impl Executor for SpillReader {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let mut batch = Vec::with_capacity(BATCH_SIZE.min(self.remaining));
+        while batch.len() < BATCH_SIZE
+            && let Some(tuple) = self.read()?
+        {
+            batch.push(tuple);
+        }
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
+
+/// Spill files tuples are distributed to on the hash of a key, tuples with the
+/// same key end up in the same partition.
+pub struct SpillPartitions {
+    files: Vec<SpillFile>,
+}
+
+impl SpillPartitions {
+    pub fn new(budget: &MemoryBudget) -> Result<Self, ExecError> {
+        let files = (0..SPILL_PARTITIONS)
+            .map(|_| budget.spill_file())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { files })
+    }
+
+    /// Returns the partition of a key, the same for all the `SpillPartitions`.
+    pub fn partition(key: &[Value]) -> usize {
+        // The default hasher has fixed keys, unlike a `RandomState`.
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % SPILL_PARTITIONS as u64) as usize
+    }
+
+    pub fn write(&mut self, key: &[Value], tuple: &Tuple) -> Result<(), ExecError> {
+        self.files[Self::partition(key)].write(tuple)
+    }
+
+    /// Reads the partitions back, in order.
+    pub fn into_readers(self) -> Result<Vec<SpillReader>, ExecError> {
+        self.files.into_iter().map(SpillFile::into_reader).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations() {
+        let budget = MemoryBudget::new(100, std::env::temp_dir());
+        let mut a = budget.reservation();
+        let mut b = budget.reservation();
+
+        assert!(a.try_grow(60));
+        assert!(!b.try_grow(60));
+        assert_eq!(b.size(), 0);
+        assert!(b.try_grow(40));
+        assert_eq!(budget.used(), 100);
+
+        // State that can't be spilled goes over the budget.
+        b.grow(10);
+        assert_eq!(budget.used(), 110);
+        drop(b);
+        assert_eq!(budget.used(), 60);
+        a.free();
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn spill_files() {
+        use Value::*;
+        let budget = MemoryBudget::unlimited();
+        let tuples = [
+            vec![Integer(-1), Float(1.5), VarChar("été".into())],
+            vec![Null, Boolean(true), Boolean(false), VarChar(String::new())],
+            vec![],
+        ]
+        .map(|values| Tuple::try_new(values).unwrap());
+
+        let mut file = budget.spill_file().unwrap();
+        for tuple in &tuples {
+            file.write(tuple).unwrap();
+        }
+        assert_eq!(file.len(), tuples.len());
+        assert_eq!(budget.spills(), 1);
+
+        let mut reader = file.into_reader().unwrap();
+        let batch = reader.next_batch().unwrap().unwrap();
+        let values = batch.iter().map(Tuple::values).collect::<Vec<_>>();
+        assert_eq!(values, tuples.iter().map(Tuple::values).collect::<Vec<_>>());
+        assert!(reader.next_batch().unwrap().is_none());
+    }
+}
@@ -0,0 +1,100 @@
+mod aggregate;
+mod cancel;
+mod distinct;
+mod evaluator;
+mod executor;
+mod expr;
+mod functions;
+mod index_scan;
+mod insert;
+mod join;
+mod memory;
+mod parallel;
+mod set_global;
+mod sort;
+mod subquery;
+mod vacuum;
+
+pub use aggregate::{Aggregate, HashAggregate, HashAggregation};
+pub use cancel::{Cancellable, CancellationToken};
+pub use distinct::HashDistinct;
+pub use evaluator::Evaluator;
+pub use executor::{
+    BATCH_SIZE, Executor, Filter, Limit, Projection, QueryContext, RowStream, SeqScan, SingleRow,
+    Tables, build, explain,
+};
+pub use expr::{evaluate, like, like_prefix};
+pub use functions::{BUILTIN_FUNCTIONS, FunctionRegistry, ScalarFunction};
+pub use index_scan::{FullTextScan, IndexOnlyScan, IndexScan, KeyRange};
+pub use insert::Insert;
+pub use join::{HashJoin, IndexNestedLoopJoin, NestedLoopJoin};
+pub use memory::{
+    MemoryBudget, Reservation, SPILL_PARTITIONS, SpillFile, SpillPartitions, SpillReader,
+    memory_size, values_memory_size,
+};
+pub use parallel::{Exchange, ExchangeSender, ParallelSeqScan};
+pub use set_global::SetGlobal;
+pub use sort::Sort;
+pub use subquery::SubqueryApply;
+pub use vacuum::Vacuum;
+
+use thiserror::Error;
+
+use crate::config::ConfigError;
+use crate::indexes::BTreeError;
+use crate::sql::parser::ast::AggregateFunction;
+use crate::sql::schema::DataType;
+use crate::sql::types::memcomparable::DecodeError;
+use crate::sql::types::value::CastError;
+use crate::table::TableError;
+use crate::tuple::TupleError;
+
+#[derive(Debug, Error)]
+pub enum ExecError {
+    #[error("{0:?} is not defined for {1} values")]
+    InvalidAggregate(AggregateFunction, DataType),
+    #[error("integer out of range")]
+    IntegerOverflow,
+    #[error("table {0} does not exist")]
+    UnknownTable(String),
+    #[error("column {0} does not exist")]
+    UnknownColumn(String),
+    #[error("column {0} out of range")]
+    ColumnOutOfRange(usize),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("operator {0} is not defined for {1}")]
+    InvalidOperand(&'static str, DataType),
+    #[error("operator {0} is not defined for {1} and {2}")]
+    InvalidOperands(&'static str, DataType, DataType),
+    #[error("expected a BOOLEAN, found {0}")]
+    NotBoolean(DataType),
+    #[error("wrong number of arguments for {0}: {1}")]
+    ArgumentCount(&'static str, usize),
+    #[error("{0} is not defined for {1} arguments")]
+    InvalidArgument(&'static str, DataType),
+    #[error("negative substring length")]
+    NegativeLength,
+    #[error("{0} are not supported yet")]
+    Unsupported(&'static str),
+    #[error("canceling statement due to user request")]
+    QueryCancelled,
+    #[error("spill file error")]
+    Spill(#[from] std::io::Error),
+    #[error("a worker thread panicked")]
+    WorkerPanicked,
+    #[error("cast error")]
+    Cast(#[from] CastError),
+    #[error("tuple error")]
+    Tuple(#[from] TupleError),
+    #[error("table error")]
+    Table(#[from] TableError),
+    #[error("index error")]
+    Index(#[from] BTreeError),
+    #[error("index key error")]
+    Key(#[from] DecodeError),
+    #[error("more than one row returned by a subquery used as an expression")]
+    SubqueryRows,
+    #[error("setting error")]
+    Setting(#[from] ConfigError),
+}
@@ -0,0 +1,225 @@
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::thread::JoinHandle;
+
+use crate::pages::PageId;
+use crate::sql::exec::{ExecError, Executor, SeqScan};
+use crate::storage::StorageBackend;
+use crate::table::Table;
+use crate::tuple::Tuple;
+
+/// The number of batches workers can send before waiting for the consumer.
+const EXCHANGE_CAPACITY: usize = 16;
+
+type Message = Result<Vec<Tuple>, ExecError>;
+
+/// The sending side of an `Exchange`, given to each of its workers.
+pub struct ExchangeSender(SyncSender<Message>);
+
+impl ExchangeSender {
+    /// Sends the batches of `input` until it is exhausted, fails or the
+    /// exchange is dropped.
+    pub fn send_all(&self, input: &mut dyn Executor) {
+        loop {
+            let message = match input.next_batch() {
+                Ok(Some(batch)) => Ok(batch),
+                Ok(None) => return,
+                Err(e) => Err(e),
+            };
+            let failed = message.is_err();
+            if self.0.send(message).is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+/// Merges the batches produced by worker threads into a single stream, in the
+/// order they arrive.
+///
+/// Workers wait once the consumer is `EXCHANGE_CAPACITY` batches behind, and
+/// stop when the exchange is dropped. The first error of a worker is returned
+/// to the consumer.
+pub struct Exchange {
+    // `None` once dropped, to unblock the workers before joining them.
+    receiver: Option<Receiver<Message>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Exchange {
+    /// Runs each worker on its own thread.
+    pub fn new<F>(workers: impl IntoIterator<Item = F>) -> Self
+    where
+        F: FnOnce(ExchangeSender) + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(EXCHANGE_CAPACITY);
+        let workers = workers
+            .into_iter()
+            .map(|worker| {
+                let sender = ExchangeSender(sender.clone());
+                std::thread::spawn(move || worker(sender))
+            })
+            .collect();
+
+        Self {
+            receiver: Some(receiver),
+            workers,
+        }
+    }
+
+    fn join(&mut self) -> Result<(), ExecError> {
+        let mut panicked = false;
+        for worker in self.workers.drain(..) {
+            panicked |= worker.join().is_err();
+        }
+        match panicked {
+            true => Err(ExecError::WorkerPanicked),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Executor for Exchange {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let Some(receiver) = &self.receiver else {
+            return Ok(None);
+        };
+
+        match receiver.recv() {
+            Ok(batch) => batch.map(Some),
+            // All the workers are done, a panic would otherwise truncate the
+            // result.
+            Err(_) => {
+                self.receiver = None;
+                self.join().map(|_| None)
+            }
+        }
+    }
+}
+
+impl Drop for Exchange {
+    fn drop(&mut self) {
+        self.receiver = None;
+        let _ = self.join();
+    }
+}
+
+/// Scans a table with a pool of workers, each reading a contiguous range of
+/// its heap pages through its own handle on the page cache.
+///
+/// Tuples are not returned in storage order. Only the pages present when the
+/// scan starts are read.
+pub struct ParallelSeqScan {
+    exchange: Exchange,
+}
+
+impl ParallelSeqScan {
+    /// Only decodes the given columns if any, see `Table::iter_columns`.
+    pub fn new<S: StorageBackend + 'static>(
+        table: &Table<S>,
+        columns: Option<&[usize]>,
+        workers: usize,
+    ) -> Self {
+        let ranges = match table.page_range() {
+            Some((first, last)) => partition(first.get(), last.get(), workers),
+            None => Vec::new(),
+        };
+        let workers = ranges.into_iter().map(|(first, last)| {
+            let table = table.clone();
+            let columns = columns.map(<[usize]>::to_vec);
+            move |sender: ExchangeSender| {
+                let (first, last) = (PageId::new(first), PageId::new(last));
+                let mut scan = SeqScan::with_pages(&table, first, last, columns.as_deref());
+                sender.send_all(&mut scan);
+            }
+        });
+
+        Self {
+            exchange: Exchange::new(workers),
+        }
+    }
+}
+
+impl Executor for ParallelSeqScan {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        self.exchange.next_batch()
+    }
+}
+
+/// Splits the pages `first..=last` into at most `parts` contiguous ranges of
+/// about the same size.
+fn partition(first: u32, last: u32, parts: usize) -> Vec<(u32, u32)> {
+    let pages = (last - first + 1) as usize;
+    let size = pages.div_ceil(parts.clamp(1, pages)) as u32;
+    (first..=last)
+        .step_by(size as usize)
+        .map(|start| (start, last.min(start + size - 1)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+    use crate::sql::types::Value;
+    use crate::storage::FileStorage;
+
+    const NR_ROWS: i64 = 20000;
+
+    #[test]
+    fn partitions() {
+        assert_eq!(partition(1, 10, 3), [(1, 4), (5, 8), (9, 10)]);
+        assert_eq!(partition(1, 2, 4), [(1, 1), (2, 2)]);
+        assert_eq!(partition(3, 3, 0), [(3, 3)]);
+    }
+
+    #[test]
+    fn parallel_scan() {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let cache = GLOBAL_PAGE_CACHE.cache_storage(storage);
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
+        .unwrap();
+        let table = Table::try_new("users", &schema, cache).unwrap();
+
+        let mut scan = ParallelSeqScan::new(&table, None, 4);
+        assert!(scan.next_batch().unwrap().is_none());
+
+        for id in 0..NR_ROWS {
+            let values = vec![Value::Integer(id), Value::VarChar(format!("user{id}"))];
+            table.insert(&Tuple::try_new(values).unwrap()).unwrap();
+        }
+
+        let mut scan = ParallelSeqScan::new(&table, Some(&[0]), 4);
+        let mut ids = Vec::new();
+        while let Some(batch) = scan.next_batch().unwrap() {
+            for tuple in batch {
+                assert_eq!(tuple.values()[1], Value::Null);
+                match tuple.values()[0] {
+                    Value::Integer(id) => ids.push(id),
+                    _ => panic!("expected an INTEGER"),
+                }
+            }
+        }
+        ids.sort();
+        assert_eq!(ids, (0..NR_ROWS).collect::<Vec<_>>());
+
+        // Dropping the scan early stops the workers.
+        let mut scan = ParallelSeqScan::new(&table, None, 4);
+        assert!(scan.next_batch().unwrap().is_some());
+        drop(scan);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::config::Setting;
+use crate::sql::exec::{ExecError, Executor};
+use crate::tuple::Tuple;
+
+/// Applies a setting to the running database, see `Setting::apply`, and
+/// outputs no tuples.
+pub struct SetGlobal {
+    setting: Setting,
+    done: bool,
+}
+
+impl SetGlobal {
+    pub fn new(setting: Setting) -> Self {
+        Self {
+            setting,
+            done: false,
+        }
+    }
+}
+
+impl Executor for SetGlobal {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if !std::mem::replace(&mut self.done, true) {
+            self.setting.apply()?;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::config::{ConfigError, SETTINGS_LOCK};
+    use crate::sql::exec::{RowStream, Tables};
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan::{self, PlanError};
+    use crate::storage::FileStorage;
+
+    fn set_global(tables: &Tables<FileStorage>, source: &str) -> Result<usize, PlanError> {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables)?;
+        Ok(RowStream::execute(&plan, tables).unwrap().count())
+    }
+
+    #[test]
+    fn page_cache_size() {
+        let _guard = SETTINGS_LOCK.lock();
+        let tables = Tables::new();
+        let capacity = GLOBAL_PAGE_CACHE.capacity();
+
+        let source = format!("SET GLOBAL page_cache_size = {}", capacity + 16);
+        assert_eq!(set_global(&tables, &source).unwrap(), 0);
+        assert_eq!(GLOBAL_PAGE_CACHE.capacity(), capacity + 16);
+        let source = format!("SET GLOBAL PAGE_CACHE_SIZE = '{capacity}'");
+        assert_eq!(set_global(&tables, &source).unwrap(), 0);
+        assert_eq!(GLOBAL_PAGE_CACHE.capacity(), capacity);
+
+        for source in [
+            "SET GLOBAL page_cache_size = 0",
+            "SET GLOBAL page_cache_size = -1",
+            "SET GLOBAL page_cache_size = 'lots'",
+        ] {
+            assert!(matches!(
+                set_global(&tables, source),
+                Err(PlanError::Setting(ConfigError::InvalidValue { .. }))
+            ));
+        }
+        assert!(matches!(
+            set_global(&tables, "SET GLOBAL no_such_setting = 1"),
+            Err(PlanError::Setting(ConfigError::UnknownSetting(_)))
+        ));
+        assert_eq!(GLOBAL_PAGE_CACHE.capacity(), capacity);
+    }
+}
@@ -0,0 +1,183 @@
+use std::cmp::Ordering;
+
+use crate::sql::exec::{
+    BATCH_SIZE, Evaluator, ExecError, Executor, MemoryBudget, Reservation, SpillReader,
+    memory_size, values_memory_size,
+};
+use crate::sql::plan::SortKey;
+use crate::sql::types::Value;
+use crate::tuple::Tuple;
+
+/// A tuple and the values of its sort keys.
+type Row = (Vec<Value>, Tuple);
+
+/// Sorts its input on the keys, in order. NULLs sort after the other values:
+/// last in ascending order, first in descending order. Tuples with equal keys
+/// keep their input order.
+///
+/// The input is read entirely first. Once the memory budget is exhausted, the
+/// tuples read are sorted and spilled as a run, each one after its keys, and
+/// the runs are merged at the end of the input.
+pub struct Sort<'a> {
+    input: Box<dyn Executor + 'a>,
+    keys: Vec<Evaluator>,
+    descending: Vec<bool>,
+    budget: MemoryBudget,
+    reservation: Reservation,
+    // `None` until the input is sorted.
+    output: Option<SortOutput>,
+}
+
+enum SortOutput {
+    Memory(std::vec::IntoIter<Row>),
+    // Each spilled run and its next row, `None` once exhausted.
+    Merge(Vec<(SpillReader, Option<Row>)>),
+}
+
+impl<'a> Sort<'a> {
+    pub fn new(input: Box<dyn Executor + 'a>, keys: &[SortKey], budget: MemoryBudget) -> Self {
+        Self {
+            input,
+            keys: keys.iter().map(|key| Evaluator::new(&key.expr)).collect(),
+            descending: keys.iter().map(|key| key.descending).collect(),
+            reservation: budget.reservation(),
+            budget,
+            output: None,
+        }
+    }
+
+    /// Compares the keys of two rows.
+    fn compare(descending: &[bool], lhs: &[Value], rhs: &[Value]) -> Ordering {
+        descending
+            .iter()
+            .zip(lhs.iter().zip(rhs))
+            .map(|(descending, (lhs, rhs))| {
+                let ordering = match (lhs, rhs) {
+                    (Value::Null, Value::Null) => Ordering::Equal,
+                    (Value::Null, _) => Ordering::Greater,
+                    (_, Value::Null) => Ordering::Less,
+                    // The keys of a column have the same type, or types
+                    // coerced to a common one.
+                    (lhs, rhs) => lhs.compare(rhs).ok().flatten().unwrap_or(Ordering::Equal),
+                };
+                match descending {
+                    true => ordering.reverse(),
+                    false => ordering,
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    fn sort_run(&self, run: &mut [Row]) {
+        run.sort_by(|(lhs, _), (rhs, _)| Self::compare(&self.descending, lhs, rhs));
+    }
+
+    /// Sorts the rows of a run and spills them.
+    fn spill(&mut self, run: &mut Vec<Row>) -> Result<SpillReader, ExecError> {
+        self.sort_run(run);
+        let mut file = self.budget.spill_file()?;
+        for (keys, tuple) in run.drain(..) {
+            file.write(&Tuple::try_new(keys)?)?;
+            file.write(&tuple)?;
+        }
+        self.reservation.free();
+        file.into_reader()
+    }
+
+    /// Reads the next row of a spilled run.
+    fn read(run: &mut SpillReader) -> Result<Option<Row>, ExecError> {
+        let Some(keys) = run.read()? else {
+            return Ok(None);
+        };
+        let tuple = run.read()?.unwrap();
+        Ok(Some((keys.values().to_vec(), tuple)))
+    }
+
+    fn sort(&mut self) -> Result<SortOutput, ExecError> {
+        let mut run = Vec::new();
+        let mut runs = Vec::new();
+        while let Some(batch) = self.input.next_batch()? {
+            for tuple in batch {
+                let keys = self
+                    .keys
+                    .iter()
+                    .map(|key| key.evaluate(tuple.values()))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let size = values_memory_size(&keys) + memory_size(&tuple);
+                if !self.reservation.try_grow(size) {
+                    if !run.is_empty() {
+                        runs.push(self.spill(&mut run)?);
+                    }
+                    // A run holds at least one row.
+                    self.reservation.grow(size);
+                }
+                run.push((keys, tuple));
+            }
+        }
+
+        if runs.is_empty() {
+            self.sort_run(&mut run);
+            return Ok(SortOutput::Memory(run.into_iter()));
+        }
+        if !run.is_empty() {
+            runs.push(self.spill(&mut run)?);
+        }
+        let runs = runs
+            .into_iter()
+            .map(|mut run| {
+                let next = Self::read(&mut run)?;
+                Ok((run, next))
+            })
+            .collect::<Result<Vec<_>, ExecError>>()?;
+        Ok(SortOutput::Merge(runs))
+    }
+
+    /// Returns the next row of the merged runs, from the first run on equal
+    /// keys: runs are spilled in input order.
+    fn merge_next(
+        descending: &[bool],
+        runs: &mut [(SpillReader, Option<Row>)],
+    ) -> Result<Option<Row>, ExecError> {
+        let first = runs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (_, next))| Some((idx, &next.as_ref()?.0)))
+            .reduce(|min, next| match Self::compare(descending, next.1, min.1) {
+                Ordering::Less => next,
+                _ => min,
+            });
+        let Some((idx, _)) = first else {
+            return Ok(None);
+        };
+        let (run, next) = &mut runs[idx];
+        let row = std::mem::replace(next, Self::read(run)?);
+        Ok(row)
+    }
+}
+
+impl Executor for Sort<'_> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if self.output.is_none() {
+            self.output = Some(self.sort()?);
+        }
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while batch.len() < BATCH_SIZE {
+            let row = match self.output.as_mut().unwrap() {
+                SortOutput::Memory(rows) => rows.next(),
+                SortOutput::Merge(runs) => Self::merge_next(&self.descending, runs)?,
+            };
+            let Some((_, tuple)) = row else {
+                break;
+            };
+            batch.push(tuple);
+        }
+
+        if batch.is_empty() {
+            self.reservation.free();
+        }
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+}
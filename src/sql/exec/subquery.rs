@@ -0,0 +1,185 @@
+use crate::sql::exec::{Evaluator, ExecError, Executor, Projection, QueryContext, Tables, build};
+use crate::sql::plan::{Expr, LogicalPlan, Subquery, SubqueryKind};
+use crate::sql::types::Value;
+use crate::storage::StorageBackend;
+use crate::tuple::Tuple;
+
+/// Prepares the expressions of an operator to be evaluated on its input, of
+/// `width` columns. Uncorrelated subqueries are run once, right away, and
+/// replaced with their results, see `materialize`. Correlated ones are run
+/// for each input tuple by a `SubqueryApply`, which appends their results to
+/// the tuple, and are replaced with these columns.
+///
+/// Returns the input to evaluate the expressions on: it has more than `width`
+/// columns if a subquery is correlated.
+pub(crate) fn apply<'a, S: StorageBackend + 'static>(
+    input: Box<dyn Executor + 'a>,
+    width: usize,
+    exprs: &[Expr],
+    tables: &'a Tables<S>,
+    context: &QueryContext,
+) -> Result<(Box<dyn Executor + 'a>, Vec<Expr>), ExecError> {
+    let mut correlated = Vec::new();
+    let exprs = exprs
+        .iter()
+        .map(|expr| {
+            expr.clone().try_map(&mut |expr| match expr {
+                Expr::Subquery(subquery) if subquery.args.is_empty() => {
+                    materialize(&subquery.kind, &subquery.plan, tables, context)
+                }
+                Expr::Subquery(subquery) => {
+                    correlated.push(*subquery);
+                    Ok(Expr::Column(width + correlated.len() - 1))
+                }
+                expr => Ok(expr),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if correlated.is_empty() {
+        return Ok((input, exprs));
+    }
+    let apply = SubqueryApply {
+        input,
+        subqueries: correlated
+            .into_iter()
+            .map(|subquery| {
+                let args = subquery.args.iter().map(Evaluator::new).collect();
+                (subquery, args)
+            })
+            .collect(),
+        tables,
+        context: context.clone(),
+    };
+    Ok((Box::new(apply), exprs))
+}
+
+/// Builds an operator evaluating `exprs` on its input, of `width` columns,
+/// and outputting these columns, e.g. a filter, see `apply`. The results of
+/// correlated subqueries are dropped from its output.
+pub(crate) fn keeping_columns<'a, S: StorageBackend + 'static>(
+    input: Box<dyn Executor + 'a>,
+    width: usize,
+    exprs: &[Expr],
+    tables: &'a Tables<S>,
+    context: &QueryContext,
+    operator: impl FnOnce(Box<dyn Executor + 'a>, &[Expr]) -> Box<dyn Executor + 'a>,
+) -> Result<Box<dyn Executor + 'a>, ExecError> {
+    let (input, exprs) = apply(input, width, exprs, tables, context)?;
+    let mut correlated = false;
+    exprs
+        .iter()
+        .for_each(|expr| expr.for_each_column(&mut |idx| correlated |= idx >= width));
+
+    let operator = operator(input, &exprs);
+    Ok(match correlated {
+        true => {
+            let columns = (0..width).map(Expr::Column).collect::<Vec<_>>();
+            Box::new(Projection::new(operator, &columns))
+        }
+        false => operator,
+    })
+}
+
+/// Runs a subquery whose parameters are bound, returns the expression it is
+/// replaced with:
+/// - a scalar subquery, the value of its row, NULL without rows,
+/// - EXISTS, whether it returns rows,
+/// - `expr IN (query)`, `expr IN (values...)`, false without rows even if
+///   `expr` is NULL.
+fn materialize<S: StorageBackend + 'static>(
+    kind: &SubqueryKind,
+    plan: &LogicalPlan,
+    tables: &Tables<S>,
+    context: &QueryContext,
+) -> Result<Expr, ExecError> {
+    let mut root = build(plan, tables, context)?;
+    Ok(match kind {
+        SubqueryKind::Scalar => {
+            let mut rows = Vec::new();
+            while rows.len() < 2
+                && let Some(batch) = root.next_batch()?
+            {
+                rows.extend(batch);
+            }
+            match rows.as_slice() {
+                [] => Expr::Literal(Value::Null),
+                [row] => Expr::Literal(row.values()[0].clone()),
+                _ => return Err(ExecError::SubqueryRows),
+            }
+        }
+        SubqueryKind::Exists => Expr::Literal(Value::Boolean(root.next_batch()?.is_some())),
+        SubqueryKind::In { expr, negated } => {
+            let mut list = Vec::new();
+            while let Some(batch) = root.next_batch()? {
+                list.extend(
+                    batch
+                        .iter()
+                        .map(|tuple| Expr::Literal(tuple.values()[0].clone())),
+                );
+            }
+            match list.is_empty() {
+                true => Expr::Literal(Value::Boolean(*negated)),
+                false => Expr::InList {
+                    expr: expr.clone(),
+                    list,
+                    negated: *negated,
+                },
+            }
+        }
+    })
+}
+
+/// Runs correlated subqueries for each input tuple: their arguments are
+/// evaluated on the tuple and bound to the parameters of their plan, and
+/// their results are appended to the tuple, in order.
+pub struct SubqueryApply<'a, S: StorageBackend + 'static> {
+    input: Box<dyn Executor + 'a>,
+    subqueries: Vec<(Subquery, Vec<Evaluator>)>,
+    tables: &'a Tables<S>,
+    context: QueryContext,
+}
+
+impl<S: StorageBackend + 'static> SubqueryApply<'_, S> {
+    /// Runs a subquery for a tuple, along with the results of the subqueries
+    /// before this one.
+    fn run(
+        &self,
+        subquery: &Subquery,
+        args: &[Evaluator],
+        values: &[Value],
+    ) -> Result<Value, ExecError> {
+        let params = args
+            .iter()
+            .map(|arg| arg.evaluate(values))
+            .collect::<Result<Vec<_>, _>>()?;
+        let plan = subquery
+            .plan
+            .clone()
+            .map_exprs(&mut |expr| expr.bind_params(&params));
+        let result = materialize(&subquery.kind, &plan, self.tables, &self.context)?;
+        // The operand of IN is an expression of the tuple.
+        Evaluator::new(&result).evaluate(values)
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for SubqueryApply<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        let Some(batch) = self.input.next_batch()? else {
+            return Ok(None);
+        };
+
+        batch
+            .into_iter()
+            .map(|tuple| {
+                let mut values = tuple.values().to_vec();
+                for (subquery, args) in &self.subqueries {
+                    let value = self.run(subquery, args, &values)?;
+                    values.push(value);
+                }
+                Ok(Tuple::try_new(values)?)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+}
@@ -0,0 +1,138 @@
+use crate::sql::exec::{ExecError, Executor, Tables};
+use crate::sql::types::Value;
+use crate::storage::StorageBackend;
+use crate::table::VacuumStats;
+use crate::tuple::Tuple;
+
+impl<S: StorageBackend + 'static> Tables<S> {
+    /// Compacts the heap of a table and its indexes, see `Table::vacuum`,
+    /// and commits the tuples moved.
+    pub fn vacuum(&self, name: &str) -> Result<VacuumStats, ExecError> {
+        let table = self
+            .get(name)
+            .ok_or_else(|| ExecError::UnknownTable(name.to_string()))?;
+        let stats = table.vacuum()?;
+        table.commit();
+        Ok(stats)
+    }
+}
+
+/// Vacuums a table, then outputs a single tuple with the number of tuples
+/// moved and of pages freed.
+pub struct Vacuum<'a, S: StorageBackend + 'static> {
+    tables: &'a Tables<S>,
+    table: &'a str,
+    done: bool,
+}
+
+impl<'a, S: StorageBackend + 'static> Vacuum<'a, S> {
+    pub fn new(tables: &'a Tables<S>, table: &'a str) -> Self {
+        Self {
+            tables,
+            table,
+            done: false,
+        }
+    }
+}
+
+impl<S: StorageBackend + 'static> Executor for Vacuum<'_, S> {
+    fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, ExecError> {
+        if std::mem::replace(&mut self.done, true) {
+            return Ok(None);
+        }
+
+        let stats = self.tables.vacuum(self.table)?;
+        let relocated =
+            i64::try_from(stats.relocations.len()).map_err(|_| ExecError::IntegerOverflow)?;
+        let values = vec![
+            Value::Integer(relocated),
+            Value::Integer(stats.pages_freed.into()),
+        ];
+        Ok(Some(vec![Tuple::try_new(values)?]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    use crate::cache::GLOBAL_PAGE_CACHE;
+    use crate::indexes::BTree;
+    use crate::pages::Key;
+    use crate::sql::exec::RowStream;
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan::{self, PlanError};
+    use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+    use crate::storage::FileStorage;
+    use crate::table::{self, Table};
+
+    const NR_ROWS: i64 = 3000;
+
+    fn query(tables: &Tables<FileStorage>, source: &str) -> Vec<Vec<Value>> {
+        let stmts = Parser::parse(source).unwrap();
+        let plan = plan::plan(&stmts[0], tables).unwrap();
+        RowStream::execute(&plan, tables)
+            .unwrap()
+            .map(|tuple| tuple.unwrap().values().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn vacuum() {
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let mut table =
+            Table::try_new("t", &schema, GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        table.add_index(
+            0,
+            BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap(),
+        );
+        for id in 0..NR_ROWS {
+            let record_id = table
+                .insert(&Tuple::try_new(vec![Value::Integer(id)]).unwrap())
+                .unwrap();
+            // Odd ids are deleted.
+            if id % 2 == 1 {
+                table.delete(record_id).unwrap();
+            }
+        }
+        let mut tables = Tables::new();
+        tables.add_table(table);
+
+        let rows = query(&tables, "VACUUM t");
+        let [Value::Integer(relocated), Value::Integer(pages_freed)] = rows[0][..] else {
+            panic!("expected two INTEGER columns");
+        };
+        assert!(relocated > 0 && pages_freed > 0);
+
+        // The index points to the tuples moved.
+        let (table, index) = (tables.get("t").unwrap(), tables.index("t", "id").unwrap());
+        for id in (0..NR_ROWS).step_by(2) {
+            let [record_id] =
+                table::lookup(index, &Key::from_values(&[Value::Integer(id)])).unwrap()[..]
+            else {
+                panic!("expected one row with id {id}");
+            };
+            assert_eq!(table.get(record_id).unwrap().values(), [Value::Integer(id)]);
+        }
+        assert_eq!(
+            query(&tables, "SELECT COUNT(*) FROM t"),
+            [[Value::Integer(NR_ROWS / 2)]]
+        );
+        assert_eq!(query(&tables, "VACUUM t"), [[0, 0].map(Value::Integer)]);
+
+        let stmts = Parser::parse("VACUUM u").unwrap();
+        assert!(matches!(
+            plan::plan(&stmts[0], &tables),
+            Err(PlanError::UnknownTable(_))
+        ));
+    }
+}
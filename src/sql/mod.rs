@@ -1,3 +1,5 @@
+pub mod exec;
 pub mod parser;
+pub mod plan;
 pub mod schema;
 pub mod types;
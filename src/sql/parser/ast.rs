@@ -1,16 +1,133 @@
 use std::borrow::Cow;
 
+use miette::SourceSpan;
+
+use crate::indexes::IndexMethod;
+use crate::sql::schema::DataType;
+
 #[derive(Debug)]
 pub enum Stmt<'source> {
     Select {
         distinct: bool,
-        columns: Vec<Expression<'source>>,
+        columns: Vec<Expr<'source>>,
         from: Option<Vec<From<'source>>>,
-        // r#where: Option<String>,
-        // group_by: Option<String>,
-        // having: Option<String>,
+        r#where: Option<Expr<'source>>,
+        group_by: Vec<Expr<'source>>,
+        having: Option<Expr<'source>>,
         // window: Option<String>,
+        order_by: Vec<OrderByExpr<'source>>,
+        limit: Option<Box<Expr<'source>>>,
+        offset: Option<Box<Expr<'source>>>,
+    },
+    // INSERT INTO table [(column, ...)] query
+    Insert {
+        table: Cow<'source, str>,
+        // Empty for all the columns of the table, in order.
+        columns: Vec<Cow<'source, str>>,
+        source: Box<Stmt<'source>>,
+    },
+    // SET GLOBAL variable = value
+    SetGlobal {
+        name: Cow<'source, str>,
+        value: Expr<'source>,
+    },
+    // CREATE INDEX [CONCURRENTLY] name ON table [USING {BTREE | HASH}]
+    //     (column [ASC | DESC], ...) [INCLUDE (column, ...)]
+    CreateIndex {
+        name: Cow<'source, str>,
+        // Whether the table accepts writes while the index is built.
+        concurrently: bool,
+        table: Cow<'source, str>,
+        method: IndexMethod,
+        // The columns of the key, in order.
+        columns: Vec<IndexColumn<'source>>,
+        // The columns stored in the leaves of a covering index.
+        include: Vec<Cow<'source, str>>,
+    },
+    // DROP INDEX name
+    DropIndex {
+        name: Cow<'source, str>,
+    },
+    // DROP TABLE name [CASCADE | RESTRICT]
+    DropTable {
+        name: Cow<'source, str>,
+        cascade: bool,
+    },
+    // ALTER TABLE name action
+    AlterTable {
+        name: Cow<'source, str>,
+        action: AlterTableAction<'source>,
+    },
+    // BEGIN [TRANSACTION]
+    Begin,
+    // COMMIT [TRANSACTION]
+    Commit,
+    // ROLLBACK [TRANSACTION]
+    Rollback,
+    // SET TRANSACTION ISOLATION LEVEL level
+    SetTransaction {
+        isolation_level: IsolationLevel,
+    },
+    // VACUUM table
+    Vacuum {
+        table: Cow<'source, str>,
     },
+    // ATTACH [DATABASE] 'path' AS alias
+    Attach {
+        path: Cow<'source, str>,
+        alias: Cow<'source, str>,
+    },
+    // DETACH [DATABASE] alias
+    Detach {
+        alias: Cow<'source, str>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+#[derive(Debug)]
+pub enum AlterTableAction<'source> {
+    // ADD [COLUMN] column_def
+    AddColumn(ColumnDef<'source>),
+    // DROP [COLUMN] name
+    DropColumn {
+        name: Cow<'source, str>,
+    },
+    // RENAME [COLUMN] from TO to
+    RenameColumn {
+        from: Cow<'source, str>,
+        to: Cow<'source, str>,
+    },
+}
+
+/// A column definition: `name type [NULL | NOT NULL] [UNIQUE]`.
+#[derive(Debug)]
+pub struct ColumnDef<'source> {
+    pub name: Cow<'source, str>,
+    pub data_type: DataType,
+    // Columns are nullable unless declared NOT NULL.
+    pub nullable: bool,
+    pub unique: bool,
+}
+
+/// A column of the key of an index: `name [ASC | DESC]`.
+#[derive(Debug)]
+pub struct IndexColumn<'source> {
+    pub name: Cow<'source, str>,
+    pub descending: bool,
+}
+
+/// An expression of the ORDER BY clause: `expr [ASC | DESC]`.
+#[derive(Debug)]
+pub struct OrderByExpr<'source> {
+    pub expr: Expr<'source>,
+    pub descending: bool,
 }
 
 // #[derive(Debug)]
@@ -20,13 +137,46 @@ pub enum Stmt<'source> {
 //     Expression,
 // }
 
+/// An item of the FROM clause: a table or a tree of joins.
 #[derive(Debug)]
-pub struct From<'source> {
-    pub table: Cow<'source, str>,
+pub enum From<'source> {
+    Table {
+        // The alias of an attached database, for `database.table`.
+        database: Option<Cow<'source, str>>,
+        name: Cow<'source, str>,
+        alias: Option<Cow<'source, str>>,
+    },
+    Join {
+        kind: JoinKind,
+        left: Box<From<'source>>,
+        right: Box<From<'source>>,
+        // None for CROSS JOIN.
+        on: Option<Expr<'source>>,
+    },
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Cross,
+}
+
+/// An expression and its location in the source, used to report errors.
 #[derive(Debug)]
-pub enum Expression<'source> {
+pub struct Expr<'source> {
+    pub kind: ExprKind<'source>,
+    pub span: SourceSpan,
+}
+
+impl<'source> Expr<'source> {
+    pub fn new(kind: ExprKind<'source>, span: SourceSpan) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExprKind<'source> {
     // All columns.
     All,
     // Column name and if specified, a table name.
@@ -38,18 +188,108 @@ pub enum Expression<'source> {
     Literal(Literal<'source>),
     // An operator (arithmetic expressions and more).
     Operator(Operator<'source>),
+    // An aggregate function, the argument of COUNT(*) is `ExprKind::All`.
+    // DISTINCT aggregates only see each distinct argument once.
+    Aggregate {
+        function: AggregateFunction,
+        distinct: bool,
+        arg: Box<Expr<'source>>,
+    },
+    // A scalar function call, resolved when the expression is evaluated.
+    Function {
+        name: Cow<'source, str>,
+        args: Vec<Expr<'source>>,
+    },
+    // A subquery returning a single value.
+    Subquery(Box<Stmt<'source>>),
+    // EXISTS (SELECT ...)
+    Exists(Box<Stmt<'source>>),
+    // expr [NOT] IN (SELECT ...)
+    InSubquery {
+        expr: Box<Expr<'source>>,
+        subquery: Box<Stmt<'source>>,
+        negated: bool,
+    },
+    // expr [NOT] IN (expr, ...)
+    InList {
+        expr: Box<Expr<'source>>,
+        list: Vec<Expr<'source>>,
+        negated: bool,
+    },
+    // expr [NOT] BETWEEN low AND high
+    Between {
+        expr: Box<Expr<'source>>,
+        low: Box<Expr<'source>>,
+        high: Box<Expr<'source>>,
+        negated: bool,
+    },
+    // expr [NOT] LIKE pattern
+    Like {
+        expr: Box<Expr<'source>>,
+        pattern: Box<Expr<'source>>,
+        negated: bool,
+    },
+    // CAST(expr AS data_type)
+    Cast {
+        expr: Box<Expr<'source>>,
+        data_type: DataType,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+impl TryFrom<&str> for AggregateFunction {
+    type Error = &'static str;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        let is = |s: &str| -> bool { s.eq_ignore_ascii_case(name) };
+        Ok(if is("COUNT") {
+            AggregateFunction::Count
+        } else if is("SUM") {
+            AggregateFunction::Sum
+        } else if is("MIN") {
+            AggregateFunction::Min
+        } else if is("MAX") {
+            AggregateFunction::Max
+        } else if is("AVG") {
+            AggregateFunction::Avg
+        } else {
+            return Err("not an aggregate function");
+        })
+    }
 }
 
 #[derive(Debug)]
 pub enum Operator<'source> {
-    Plus(Box<Expression<'source>>, Box<Expression<'source>>),
-    Minus(Box<Expression<'source>>, Box<Expression<'source>>),
-    Mul(Box<Expression<'source>>, Box<Expression<'source>>),
-    Div(Box<Expression<'source>>, Box<Expression<'source>>),
+    // Arithmetic
+    Plus(Box<Expr<'source>>, Box<Expr<'source>>),
+    Minus(Box<Expr<'source>>, Box<Expr<'source>>),
+    Mul(Box<Expr<'source>>, Box<Expr<'source>>),
+    Div(Box<Expr<'source>>, Box<Expr<'source>>),
+
+    // Comparison
+    Equal(Box<Expr<'source>>, Box<Expr<'source>>),
+    NotEqual(Box<Expr<'source>>, Box<Expr<'source>>),
+    Less(Box<Expr<'source>>, Box<Expr<'source>>),
+    LessEqual(Box<Expr<'source>>, Box<Expr<'source>>),
+    Greater(Box<Expr<'source>>, Box<Expr<'source>>),
+    GreaterEqual(Box<Expr<'source>>, Box<Expr<'source>>),
+
+    // Logical
+    And(Box<Expr<'source>>, Box<Expr<'source>>),
+    Or(Box<Expr<'source>>, Box<Expr<'source>>),
 
     // Unary
-    Identity(Box<Expression<'source>>),
-    Negate(Box<Expression<'source>>),
+    Identity(Box<Expr<'source>>),
+    Negate(Box<Expr<'source>>),
+    Not(Box<Expr<'source>>),
 }
 
 #[derive(Debug)]
@@ -59,4 +299,5 @@ pub enum Literal<'source> {
     Boolean(bool),
     Integer(i64),
     Float(f64),
+    Null,
 }
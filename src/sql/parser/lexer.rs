@@ -31,7 +31,8 @@ pub enum TokenKind {
     // Literals.
     Ident,
     String,
-    Number,
+    Integer,
+    Float,
     // Keywords.
     Keyword(Keyword),
     // EOF
@@ -58,7 +59,8 @@ pub enum TokenKindTag {
     LessEqual,
     Ident,
     String,
-    Number,
+    Integer,
+    Float,
     Keyword,
     Eof,
 }
@@ -78,6 +80,60 @@ pub enum Keyword {
     False,
     True,
     Null,
+    Set,
+    Global,
+    Not,
+    Join,
+    Inner,
+    Left,
+    Outer,
+    Cross,
+    On,
+    As,
+    Group,
+    By,
+    Having,
+    Order,
+    Limit,
+    Offset,
+    In,
+    Exists,
+    Create,
+    Drop,
+    Index,
+    Include,
+    Concurrently,
+    Using,
+    Asc,
+    Desc,
+    Alter,
+    Table,
+    Add,
+    Column,
+    Rename,
+    To,
+    Unique,
+    Begin,
+    Transaction,
+    Commit,
+    Rollback,
+    Isolation,
+    Level,
+    Read,
+    Uncommitted,
+    Committed,
+    Repeatable,
+    Serializable,
+    Cascade,
+    Restrict,
+    Cast,
+    Between,
+    Like,
+    Into,
+    Vacuum,
+    Attach,
+    Detach,
+    Database,
 }
 
 impl TryFrom<&str> for Keyword {
@@ -111,6 +167,114 @@ impl TryFrom<&str> for Keyword {
             Keyword::True
         } else if is("NULL") {
             Keyword::Null
+        } else if is("SET") {
+            Keyword::Set
+        } else if is("GLOBAL") {
+            Keyword::Global
+        } else if is("NOT") {
+            Keyword::Not
+        } else if is("JOIN") {
+            Keyword::Join
+        } else if is("INNER") {
+            Keyword::Inner
+        } else if is("LEFT") {
+            Keyword::Left
+        } else if is("OUTER") {
+            Keyword::Outer
+        } else if is("CROSS") {
+            Keyword::Cross
+        } else if is("ON") {
+            Keyword::On
+        } else if is("AS") {
+            Keyword::As
+        } else if is("GROUP") {
+            Keyword::Group
+        } else if is("BY") {
+            Keyword::By
+        } else if is("HAVING") {
+            Keyword::Having
+        } else if is("ORDER") {
+            Keyword::Order
+        } else if is("LIMIT") {
+            Keyword::Limit
+        } else if is("OFFSET") {
+            Keyword::Offset
+        } else if is("IN") {
+            Keyword::In
+        } else if is("EXISTS") {
+            Keyword::Exists
+        } else if is("CREATE") {
+            Keyword::Create
+        } else if is("DROP") {
+            Keyword::Drop
+        } else if is("INDEX") {
+            Keyword::Index
+        } else if is("INCLUDE") {
+            Keyword::Include
+        } else if is("CONCURRENTLY") {
+            Keyword::Concurrently
+        } else if is("USING") {
+            Keyword::Using
+        } else if is("ASC") {
+            Keyword::Asc
+        } else if is("DESC") {
+            Keyword::Desc
+        } else if is("ALTER") {
+            Keyword::Alter
+        } else if is("TABLE") {
+            Keyword::Table
+        } else if is("ADD") {
+            Keyword::Add
+        } else if is("COLUMN") {
+            Keyword::Column
+        } else if is("RENAME") {
+            Keyword::Rename
+        } else if is("TO") {
+            Keyword::To
+        } else if is("UNIQUE") {
+            Keyword::Unique
+        } else if is("BEGIN") {
+            Keyword::Begin
+        } else if is("TRANSACTION") {
+            Keyword::Transaction
+        } else if is("COMMIT") {
+            Keyword::Commit
+        } else if is("ROLLBACK") {
+            Keyword::Rollback
+        } else if is("ISOLATION") {
+            Keyword::Isolation
+        } else if is("LEVEL") {
+            Keyword::Level
+        } else if is("READ") {
+            Keyword::Read
+        } else if is("UNCOMMITTED") {
+            Keyword::Uncommitted
+        } else if is("COMMITTED") {
+            Keyword::Committed
+        } else if is("REPEATABLE") {
+            Keyword::Repeatable
+        } else if is("SERIALIZABLE") {
+            Keyword::Serializable
+        } else if is("CASCADE") {
+            Keyword::Cascade
+        } else if is("RESTRICT") {
+            Keyword::Restrict
+        } else if is("CAST") {
+            Keyword::Cast
+        } else if is("BETWEEN") {
+            Keyword::Between
+        } else if is("LIKE") {
+            Keyword::Like
+        } else if is("INTO") {
+            Keyword::Into
+        } else if is("VACUUM") {
+            Keyword::Vacuum
+        } else if is("ATTACH") {
+            Keyword::Attach
+        } else if is("DETACH") {
+            Keyword::Detach
+        } else if is("DATABASE") {
+            Keyword::Database
         } else {
             return Err("not a keyword");
         })
@@ -138,7 +302,8 @@ impl Display for TokenKind {
             TokenKind::LessEqual => "<=",
             TokenKind::Ident => "identifer",
             TokenKind::String => "string",
-            TokenKind::Number => "number",
+            TokenKind::Integer => "integer",
+            TokenKind::Float => "float",
             TokenKind::Eof => "EOF",
             TokenKind::Keyword(keyword) => return write!(f, "{}", keyword),
         };
@@ -163,6 +328,60 @@ impl Display for Keyword {
             Keyword::False => "FALSE",
             Keyword::True => "TRUE",
             Keyword::Null => "NULL",
+            Keyword::Set => "SET",
+            Keyword::Global => "GLOBAL",
+            Keyword::Not => "NOT",
+            Keyword::Join => "JOIN",
+            Keyword::Inner => "INNER",
+            Keyword::Left => "LEFT",
+            Keyword::Outer => "OUTER",
+            Keyword::Cross => "CROSS",
+            Keyword::On => "ON",
+            Keyword::As => "AS",
+            Keyword::Group => "GROUP",
+            Keyword::By => "BY",
+            Keyword::Having => "HAVING",
+            Keyword::Order => "ORDER",
+            Keyword::Limit => "LIMIT",
+            Keyword::Offset => "OFFSET",
+            Keyword::In => "IN",
+            Keyword::Exists => "EXISTS",
+            Keyword::Create => "CREATE",
+            Keyword::Drop => "DROP",
+            Keyword::Index => "INDEX",
+            Keyword::Include => "INCLUDE",
+            Keyword::Concurrently => "CONCURRENTLY",
+            Keyword::Using => "USING",
+            Keyword::Asc => "ASC",
+            Keyword::Desc => "DESC",
+            Keyword::Alter => "ALTER",
+            Keyword::Table => "TABLE",
+            Keyword::Add => "ADD",
+            Keyword::Column => "COLUMN",
+            Keyword::Rename => "RENAME",
+            Keyword::To => "TO",
+            Keyword::Unique => "UNIQUE",
+            Keyword::Begin => "BEGIN",
+            Keyword::Transaction => "TRANSACTION",
+            Keyword::Commit => "COMMIT",
+            Keyword::Rollback => "ROLLBACK",
+            Keyword::Isolation => "ISOLATION",
+            Keyword::Level => "LEVEL",
+            Keyword::Read => "READ",
+            Keyword::Uncommitted => "UNCOMMITTED",
+            Keyword::Committed => "COMMITTED",
+            Keyword::Repeatable => "REPEATABLE",
+            Keyword::Serializable => "SERIALIZABLE",
+            Keyword::Cascade => "CASCADE",
+            Keyword::Restrict => "RESTRICT",
+            Keyword::Cast => "CAST",
+            Keyword::Between => "BETWEEN",
+            Keyword::Like => "LIKE",
+            Keyword::Into => "INTO",
+            Keyword::Vacuum => "VACUUM",
+            Keyword::Attach => "ATTACH",
+            Keyword::Detach => "DETACH",
+            Keyword::Database => "DATABASE",
         };
 
         f.write_str(keyword)
@@ -174,6 +393,14 @@ pub struct Token<'source> {
     pub kind: TokenKind,
     pub text: Cow<'source, str>,
     pub offset: ByteOffset,
+    // Length of the token in the source, quotes included.
+    pub len: usize,
+}
+
+impl Token<'_> {
+    pub fn span(&self) -> SourceSpan {
+        (self.offset, self.len).into()
+    }
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -194,6 +421,33 @@ pub struct UnexpectedTokenError {
     err_span: SourceSpan,
 }
 
+#[derive(Error, Debug, Diagnostic)]
+#[error("SyntaxError: unterminated block comment")]
+pub struct UnterminatedCommentError {
+    #[source_code]
+    src: String,
+    #[label("comment starts here")]
+    err_span: SourceSpan,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("SyntaxError: {kind} literal out of range")]
+pub struct NumberOutOfRangeError {
+    kind: &'static str,
+    #[source_code]
+    src: String,
+    #[label("here")]
+    err_span: SourceSpan,
+}
+
+/// Parses the text of an `Integer` token, decimal or hexadecimal (`0x1F`).
+pub fn parse_integer(text: &str) -> Result<i64, std::num::ParseIntError> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+}
+
 pub struct Lexer<'source> {
     source: &'source str,
     offset: ByteOffset,
@@ -255,14 +509,17 @@ impl<'source> Lexer<'source> {
             (TokenKind::Bang, Some('=')) => Some(TokenKind::BangEqual),
             (TokenKind::Less, Some('=')) => Some(TokenKind::LessEqual),
             (TokenKind::Greater, Some('=')) => Some(TokenKind::GreaterEqual),
+            (TokenKind::Less, Some('>')) => Some(TokenKind::BangEqual),
             _ => None,
         };
 
         if let Some(double_char_token) = double_char_token {
+            self.chars.next();
             let token = Token {
                 kind: double_char_token,
                 text: Cow::from(&self.source[self.offset..self.offset + 2]),
                 offset,
+                len: 2,
             };
             self.offset += 2;
             Ok(Some(token))
@@ -271,6 +528,7 @@ impl<'source> Lexer<'source> {
                 kind: single_char_token,
                 text: Cow::from(&self.source[self.offset..self.offset + 1]),
                 offset,
+                len: 1,
             };
             self.offset += 1;
             Ok(Some(token))
@@ -292,49 +550,85 @@ impl<'source> Lexer<'source> {
                 kind: TokenKind::Keyword(keyword),
                 text: Cow::from(ident),
                 offset,
+                len: ident.len(),
             })
         } else {
             Some(Token {
                 kind: TokenKind::Ident,
                 text: Cow::from(ident),
                 offset,
+                len: ident.len(),
             })
         }
     }
 
-    fn scan_number(&mut self) -> Option<Token<'source>> {
-        let offset = self.offset;
-        let mut len = self
-            .peekable_take_while(char::is_ascii_digit)
+    fn take_digits(&mut self, radix: u32) -> usize {
+        self.peekable_take_while(|c| c.is_digit(radix))
             .map(char::len_utf8)
-            .sum::<usize>();
+            .sum::<usize>()
+    }
 
-        if self.next_eq('.') {
-            len += '.'.len_utf8();
-            len += self
-                .peekable_take_while(char::is_ascii_digit)
-                .map(char::len_utf8)
-                .sum::<usize>();
+    /// Scans an integer (`42`, `0x2A`) or a float (`4.2`, `42.`, `4.2e1`, `42e-1`).
+    fn scan_number(&mut self) -> Result<Option<Token<'source>>> {
+        let offset = self.offset;
+        let rest = &self.source[offset..];
+        let is_hex = (rest.starts_with("0x") || rest.starts_with("0X"))
+            && rest[2..].starts_with(|c: char| c.is_ascii_hexdigit());
+
+        let (kind, len) = if is_hex {
+            self.chars.next();
+            self.chars.next();
+            (TokenKind::Integer, 2 + self.take_digits(16))
+        } else {
+            let mut kind = TokenKind::Integer;
+            let mut len = self.take_digits(10);
 
-            if let Some(e) = self.next_if(|&c| c == 'e' || c == 'E') {
-                len += e.len_utf8();
-                if let Some(sign) = self.next_if(|&c| c == '+' || c == '-') {
-                    len += sign.len_utf8();
-                }
-                len += self
-                    .peekable_take_while(char::is_ascii_digit)
-                    .map(char::len_utf8)
-                    .sum::<usize>();
+            if self.next_eq('.') {
+                kind = TokenKind::Float;
+                len += '.'.len_utf8() + self.take_digits(10);
             }
-        }
-        let number = &self.source[self.offset..self.offset + len];
+
+            // The exponent must have digits, `1e` is the number 1 followed by
+            // the identifier e.
+            let exponent = &self.source.as_bytes()[offset + len..];
+            let sign_len = usize::from(matches!(exponent.get(1), Some(b'+' | b'-')));
+            if matches!(exponent.first(), Some(b'e' | b'E'))
+                && exponent.get(1 + sign_len).is_some_and(u8::is_ascii_digit)
+            {
+                kind = TokenKind::Float;
+                self.chars.next();
+                self.next_if(|&c| c == '+' || c == '-');
+                len += 1 + sign_len + self.take_digits(10);
+            }
+
+            (kind, len)
+        };
+
+        let number = &self.source[offset..offset + len];
         self.offset += len;
 
-        Some(Token {
-            kind: TokenKind::Number,
+        let in_range = match kind {
+            TokenKind::Integer => parse_integer(number).is_ok(),
+            _ => number.parse::<f64>().is_ok_and(f64::is_finite),
+        };
+        if !in_range {
+            return Err(NumberOutOfRangeError {
+                kind: if kind == TokenKind::Integer {
+                    "integer"
+                } else {
+                    "float"
+                },
+                src: self.source.to_string(),
+                err_span: (offset, len).into(),
+            })?;
+        }
+
+        Ok(Some(Token {
+            kind,
             text: Cow::from(number),
             offset,
-        })
+            len,
+        }))
     }
 
     fn scan_string_quoted(&mut self) -> Result<Option<Token<'source>>> {
@@ -384,6 +678,7 @@ impl<'source> Lexer<'source> {
             kind: TokenKind::String,
             text: s,
             offset: token_start,
+            len: self.offset - token_start,
         }))
     }
 
@@ -428,27 +723,73 @@ impl<'source> Lexer<'source> {
             kind: TokenKind::String,
             text: s,
             offset: token_start,
+            len: self.offset - token_start,
         }))
     }
 
+    fn advance(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skips whitespaces, `--` line comments and `/* */` block comments.
+    /// Block comments can be nested.
+    fn skip_whitespaces_and_comments(&mut self) -> Result<()> {
+        loop {
+            self.offset += self
+                .peekable_take_while(char::is_ascii_whitespace)
+                .map(char::len_utf8)
+                .sum::<usize>();
+
+            let rest = &self.source[self.offset..];
+            if rest.starts_with("--") {
+                while self.advance().is_some_and(|c| c != '\n') {}
+            } else if rest.starts_with("/*") {
+                let comment_start = self.offset;
+                self.advance();
+                self.advance();
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.advance() {
+                        Some('*') if self.next_eq('/') => {
+                            self.offset += '/'.len_utf8();
+                            depth -= 1;
+                        }
+                        Some('/') if self.next_eq('*') => {
+                            self.offset += '*'.len_utf8();
+                            depth += 1;
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Err(UnterminatedCommentError {
+                                src: self.source.to_string(),
+                                err_span: (comment_start, 2).into(),
+                            })?;
+                        }
+                    }
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
     fn scan(&mut self) -> Result<Option<Token<'source>>> {
-        // skip whitespaces
-        self.offset += self
-            .peekable_take_while(char::is_ascii_whitespace)
-            .map(char::len_utf8)
-            .sum::<usize>();
+        self.skip_whitespaces_and_comments()?;
 
         let Some(c) = self.chars.peek() else {
             return Ok(Some(Token {
                 kind: TokenKind::Eof,
                 text: Cow::from(&self.source[self.offset..]),
                 offset: self.offset.saturating_sub(1),
+                len: 0,
             }));
         };
         match c {
             '"' => self.scan_string_quoted(),
             '\'' => self.scan_string_const(),
-            '0'..='9' => Ok(self.scan_number()),
+            '0'..='9' => self.scan_number(),
             c if c.is_ascii_alphabetic() => Ok(self.scan_ident()),
             _ => self.scan_symbol(),
         }
@@ -466,3 +807,99 @@ impl<'source> Iterator for Lexer<'source> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Result<Vec<TokenKind>> {
+        let mut kinds = Vec::new();
+        for token in Lexer::new(source) {
+            let kind = token?.kind;
+            if kind == TokenKind::Eof {
+                break;
+            }
+            kinds.push(kind);
+        }
+        Ok(kinds)
+    }
+
+    #[test]
+    fn comments() {
+        let source = "-- leading comment
+            SELECT /* block /* nested */ comment */ a -- trailing comment
+            - /**/ 1 /* multi
+            line */;
+            -- no newline at the end";
+        assert_eq!(
+            kinds(source).unwrap(),
+            [
+                TokenKind::Keyword(Keyword::Select),
+                TokenKind::Ident,
+                TokenKind::Minus,
+                TokenKind::Integer,
+                TokenKind::SemiColon,
+            ]
+        );
+
+        // A single `-` or `/` is still an operator.
+        assert_eq!(
+            kinds("a-b/c").unwrap(),
+            [
+                TokenKind::Ident,
+                TokenKind::Minus,
+                TokenKind::Ident,
+                TokenKind::Slash,
+                TokenKind::Ident,
+            ]
+        );
+
+        let err = kinds("SELECT 1 /* /* */").unwrap_err();
+        assert_eq!(err.to_string(), "SyntaxError: unterminated block comment");
+    }
+
+    #[test]
+    fn comment_offsets() {
+        let source = "/* x */ a";
+        let token = Lexer::new(source).next().unwrap().unwrap();
+        assert_eq!(token.kind, TokenKind::Ident);
+        assert_eq!(&source[token.offset..token.offset + token.len], "a");
+    }
+
+    #[test]
+    fn numbers() {
+        let tokens = Lexer::new("42 0x2a 0XfF 4.2 42. 4.2e1 42E-1 1e+3 1e 0x")
+            .take_while(|token| token.as_ref().unwrap().kind != TokenKind::Eof)
+            .map(|token| {
+                let token = token.unwrap();
+                (token.kind, token.text.into_owned())
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            (TokenKind::Integer, "42"),
+            (TokenKind::Integer, "0x2a"),
+            (TokenKind::Integer, "0XfF"),
+            (TokenKind::Float, "4.2"),
+            (TokenKind::Float, "42."),
+            (TokenKind::Float, "4.2e1"),
+            (TokenKind::Float, "42E-1"),
+            (TokenKind::Float, "1e+3"),
+            (TokenKind::Integer, "1"),
+            (TokenKind::Ident, "e"),
+            (TokenKind::Integer, "0"),
+            (TokenKind::Ident, "x"),
+        ];
+        let expected = expected.map(|(kind, text)| (kind, text.to_string()));
+        assert_eq!(tokens, expected);
+
+        assert_eq!(parse_integer("0x2a"), Ok(42));
+        assert_eq!(parse_integer("0XfF"), Ok(255));
+
+        let err = kinds("SELECT 9223372036854775808").unwrap_err();
+        assert_eq!(err.to_string(), "SyntaxError: integer literal out of range");
+        assert!(kinds("0x8000000000000000").is_err());
+        assert!(kinds("1e400").is_err());
+        assert!(kinds("1é").is_err());
+        assert!(kinds("9223372036854775807 0x7fffffffffffffff 1e308").is_ok());
+    }
+}
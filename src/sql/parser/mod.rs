@@ -1,4 +1,4 @@
-mod ast;
+pub mod ast;
 pub mod lexer;
 pub mod parser;
 mod peekable_ext;
@@ -1,5 +1,7 @@
+use crate::indexes::IndexMethod;
 use crate::sql::parser::ast::{self, Stmt};
-use crate::sql::parser::lexer::{Keyword, Lexer, Token, TokenKind};
+use crate::sql::parser::lexer::{Keyword, Lexer, Token, TokenKind, parse_integer};
+use crate::sql::schema::DataType;
 
 use std::iter::Peekable;
 
@@ -33,18 +35,34 @@ trait TokenKindExt {
     fn infix_binding_power(&self) -> Option<(u8, u8)>;
 }
 
+// Binding powers, from the loosest to the tightest:
+// OR < AND < NOT < comparisons, [NOT] IN, BETWEEN, LIKE < +, - < *, / < unary +, -
 impl TokenKindExt for TokenKind {
     fn prefix_binding_power(&self) -> ((), u8) {
         match self {
-            TokenKind::Plus | TokenKind::Minus => ((), 5),
+            TokenKind::Keyword(Keyword::Not) => ((), 5),
+            TokenKind::Plus | TokenKind::Minus => ((), 13),
             _ => panic!("not an operator: {self}"),
         }
     }
 
     fn infix_binding_power(&self) -> Option<(u8, u8)> {
         let bp = match self {
-            TokenKind::Plus | TokenKind::Minus => (1, 2),
-            TokenKind::Asterisk | TokenKind::Slash => (3, 4),
+            TokenKind::Keyword(Keyword::Or) => (1, 2),
+            TokenKind::Keyword(Keyword::And) => (3, 4),
+            TokenKind::Equal
+            | TokenKind::BangEqual
+            | TokenKind::Less
+            | TokenKind::LessEqual
+            | TokenKind::Greater
+            | TokenKind::GreaterEqual
+            | TokenKind::Keyword(Keyword::In)
+            | TokenKind::Keyword(Keyword::Between)
+            | TokenKind::Keyword(Keyword::Like) => (7, 8),
+            // Infix NOT only prefixes IN, BETWEEN and LIKE.
+            TokenKind::Keyword(Keyword::Not) => (7, 8),
+            TokenKind::Plus | TokenKind::Minus => (9, 10),
+            TokenKind::Asterisk | TokenKind::Slash => (11, 12),
             _ => return None,
         };
 
@@ -52,6 +70,11 @@ impl TokenKindExt for TokenKind {
     }
 }
 
+/// Returns the span starting at `start` and ending at the end of `end`.
+fn span_between(start: SourceSpan, end: SourceSpan) -> SourceSpan {
+    (start.offset()..end.offset() + end.len()).into()
+}
+
 impl<'source> Parser<'source> {
     fn new(source: &'source str) -> Self {
         Self {
@@ -95,6 +118,7 @@ impl<'source> Parser<'source> {
     {
         let token = self.tokens.peek()?.as_ref().ok();
         let result = token.and_then(op)?;
+        self.tokens.next();
         Some(result)
     }
 
@@ -125,61 +149,169 @@ impl<'source> Parser<'source> {
         parser.parse_statement()
     }
 
-    fn parse_expr(&mut self) -> Result<ast::Expression<'source>> {
+    fn parse_expr(&mut self) -> Result<ast::Expr<'source>> {
         self.parse_expr_bp(0)
     }
 
+    /// Number tokens are validated by the lexer.
+    fn parse_number(&self, token: &Token<'source>) -> ast::Literal<'source> {
+        match token.kind {
+            TokenKind::Integer => {
+                ast::Literal::Integer(parse_integer(&token.text).expect("valid integer literal"))
+            }
+            TokenKind::Float => {
+                ast::Literal::Float(token.text.parse().expect("valid float literal"))
+            }
+            _ => unreachable!(),
+        }
+    }
+
     /// min_bp: minimal binding power to fold the expression.
-    fn parse_expr_bp(&mut self, min_bp: u8) -> Result<ast::Expression<'source>> {
-        let token = self.next()?.expect("should not happen");
+    fn parse_expr_bp(&mut self, min_bp: u8) -> Result<ast::Expr<'source>> {
+        let token = self.next()?.expect("lexer always ends with EOF");
+        let token_span = token.span();
 
         let mut lhs = match token.kind {
-            TokenKind::Asterisk => ast::Expression::All,
-            TokenKind::Ident => ast::Expression::Column {
-                // TODO: handle table name
-                table: None,
-                name: token.text,
-            },
-            TokenKind::Number => {
-                let n = token.text.as_ref();
-                if n.find('.').is_some() {
-                    ast::Expression::Literal(ast::Literal::Float(n.parse().map_err(|e| {
-                        ParserError {
-                            message: format!("{e}"),
-                            src: self.source.to_string(),
-                            err_span: token.offset.into(),
-                        }
-                    })?))
-                } else {
-                    ast::Expression::Literal(ast::Literal::Integer(n.parse().map_err(|e| {
-                        ParserError {
-                            message: format!("{e}"),
-                            src: self.source.to_string(),
-                            err_span: token.offset.into(),
-                        }
-                    })?))
+            TokenKind::Asterisk => ast::Expr::new(ast::ExprKind::All, token_span),
+            TokenKind::Ident if self.next_eq(TokenKind::Dot) => {
+                let column = self.expect(TokenKind::Ident)?;
+                let span = span_between(token_span, column.span());
+                ast::Expr::new(
+                    ast::ExprKind::Column {
+                        table: Some(token.text),
+                        name: column.text,
+                    },
+                    span,
+                )
+            }
+            TokenKind::Ident
+                if let Ok(function) = ast::AggregateFunction::try_from(token.text.as_ref())
+                    && self.next_eq(TokenKind::LeftParen) =>
+            {
+                let distinct = self.next_eq(TokenKind::Keyword(Keyword::Distinct));
+                let arg = self.parse_expr()?;
+                if matches!(arg.kind, ast::ExprKind::All)
+                    && (distinct || function != ast::AggregateFunction::Count)
+                {
+                    let name = token.text.to_uppercase();
+                    let name = match distinct {
+                        true => format!("{name}(DISTINCT)"),
+                        false => name,
+                    };
+                    return Err(ParserError {
+                        message: format!("{name} doesn't accept `*`"),
+                        src: self.source.to_string(),
+                        err_span: arg.span,
+                    })?;
                 }
+                let right_paren = self.expect(TokenKind::RightParen)?;
+                ast::Expr::new(
+                    ast::ExprKind::Aggregate {
+                        function,
+                        distinct,
+                        arg: Box::new(arg),
+                    },
+                    span_between(token_span, right_paren.span()),
+                )
+            }
+            TokenKind::Ident if self.next_eq(TokenKind::LeftParen) => {
+                let (args, right_paren) = match self.next_if(|kind| *kind == TokenKind::RightParen)
+                {
+                    Some(right_paren) => (Vec::new(), right_paren),
+                    None => (self.parse_expr_list()?, self.expect(TokenKind::RightParen)?),
+                };
+                ast::Expr::new(
+                    ast::ExprKind::Function {
+                        name: token.text,
+                        args,
+                    },
+                    span_between(token_span, right_paren.span()),
+                )
+            }
+            TokenKind::Ident => ast::Expr::new(
+                ast::ExprKind::Column {
+                    table: None,
+                    name: token.text,
+                },
+                token_span,
+            ),
+            TokenKind::Integer | TokenKind::Float => ast::Expr::new(
+                ast::ExprKind::Literal(self.parse_number(&token)),
+                token_span,
+            ),
+            TokenKind::String => ast::Expr::new(
+                ast::ExprKind::Literal(ast::Literal::String(token.text)),
+                token_span,
+            ),
+            TokenKind::Keyword(keyword @ (Keyword::True | Keyword::False | Keyword::Null)) => {
+                let literal = match keyword {
+                    Keyword::True => ast::Literal::Boolean(true),
+                    Keyword::False => ast::Literal::Boolean(false),
+                    _ => ast::Literal::Null,
+                };
+                ast::Expr::new(ast::ExprKind::Literal(literal), token_span)
+            }
+            TokenKind::Keyword(Keyword::Cast) => {
+                self.expect(TokenKind::LeftParen)?;
+                let expr = self.parse_expr()?;
+                self.expect(TokenKind::Keyword(Keyword::As))?;
+                let data_type = self.parse_data_type()?;
+                let right_paren = self.expect(TokenKind::RightParen)?;
+                ast::Expr::new(
+                    ast::ExprKind::Cast {
+                        expr: Box::new(expr),
+                        data_type,
+                    },
+                    span_between(token_span, right_paren.span()),
+                )
+            }
+            TokenKind::LeftParen if self.next_eq(TokenKind::Keyword(Keyword::Select)) => {
+                let subquery = self.parse_select()?;
+                let right_paren = self.expect(TokenKind::RightParen)?;
+                ast::Expr::new(
+                    ast::ExprKind::Subquery(Box::new(subquery)),
+                    span_between(token_span, right_paren.span()),
+                )
+            }
+            TokenKind::Keyword(Keyword::Exists) => {
+                self.expect(TokenKind::LeftParen)?;
+                self.expect(TokenKind::Keyword(Keyword::Select))?;
+                let subquery = self.parse_select()?;
+                let right_paren = self.expect(TokenKind::RightParen)?;
+                ast::Expr::new(
+                    ast::ExprKind::Exists(Box::new(subquery)),
+                    span_between(token_span, right_paren.span()),
+                )
             }
             TokenKind::LeftParen => {
-                let lhs = self.parse_expr_bp(0)?;
-                self.expect(TokenKind::RightParen)?;
-                lhs
+                let expr = self.parse_expr_bp(0)?;
+                let right_paren = self.expect(TokenKind::RightParen)?;
+                ast::Expr::new(expr.kind, span_between(token_span, right_paren.span()))
             }
-            TokenKind::Plus | TokenKind::Minus => {
+            TokenKind::Plus | TokenKind::Minus | TokenKind::Keyword(Keyword::Not) => {
                 let (_, r_bp) = token.kind.prefix_binding_power();
                 let rhs = self.parse_expr_bp(r_bp)?;
+                let span = span_between(token_span, rhs.span);
                 let operator = match token.kind {
                     TokenKind::Plus => ast::Operator::Identity(Box::new(rhs)),
                     TokenKind::Minus => ast::Operator::Negate(Box::new(rhs)),
+                    TokenKind::Keyword(Keyword::Not) => ast::Operator::Not(Box::new(rhs)),
                     _ => unreachable!(),
                 };
-                ast::Expression::Operator(operator)
+                ast::Expr::new(ast::ExprKind::Operator(operator), span)
+            }
+            TokenKind::Eof => {
+                return Err(ParserError {
+                    message: "unexpected end of file, expected an expression".to_string(),
+                    src: self.source.to_string(),
+                    err_span: token_span,
+                })?;
             }
             _ => {
                 return Err(ParserError {
                     message: format!("unexpected token '{}'", token.kind),
                     src: self.source.to_string(),
-                    err_span: token.offset.into(),
+                    err_span: token_span,
                 })?;
             }
         };
@@ -188,39 +320,107 @@ impl<'source> Parser<'source> {
             let Some(next_token) = self.peek()? else {
                 break;
             };
-            let kind = match next_token.kind {
-                TokenKind::Plus => TokenKind::Plus,
-                TokenKind::Minus => TokenKind::Minus,
-                TokenKind::Asterisk => TokenKind::Asterisk,
-                TokenKind::Slash => TokenKind::Slash,
-                TokenKind::RightParen => TokenKind::RightParen,
-                TokenKind::Eof => break,
-                _ => {
-                    break;
-                }
+            let kind = next_token.kind;
+            let Some((l_bp, r_bp)) = kind.infix_binding_power() else {
+                break;
             };
+            if l_bp < min_bp {
+                break;
+            }
+            self.next()?;
+            let span_start = lhs.span;
 
-            if let Some((l_bp, r_bp)) = kind.infix_binding_power() {
-                if l_bp < min_bp {
-                    break;
+            let (kind, negated) = if kind == TokenKind::Keyword(Keyword::Not) {
+                let token = self.next()?.expect("lexer always ends with EOF");
+                match token.kind {
+                    TokenKind::Keyword(Keyword::In | Keyword::Between | Keyword::Like) => {
+                        (token.kind, true)
+                    }
+                    _ => {
+                        return Err(ParserError {
+                            message: "expected `IN`, `BETWEEN` or `LIKE` after `NOT`".to_string(),
+                            src: self.source.to_string(),
+                            err_span: token.span(),
+                        })?;
+                    }
                 }
-                self.next()?;
+            } else {
+                (kind, false)
+            };
 
-                let Ok(rhs) = self.parse_expr_bp(r_bp) else {
-                    break;
-                };
-                let operator = match kind {
-                    TokenKind::Plus => ast::Operator::Plus(Box::new(lhs), Box::new(rhs)),
-                    TokenKind::Minus => ast::Operator::Minus(Box::new(lhs), Box::new(rhs)),
-                    TokenKind::Asterisk => ast::Operator::Mul(Box::new(lhs), Box::new(rhs)),
-                    TokenKind::Slash => ast::Operator::Div(Box::new(lhs), Box::new(rhs)),
-                    _ => todo!(),
-                };
-                lhs = ast::Expression::Operator(operator);
-                continue;
+            match kind {
+                TokenKind::Keyword(Keyword::In) => {
+                    self.expect(TokenKind::LeftParen)?;
+                    let kind = if self.next_eq(TokenKind::Keyword(Keyword::Select)) {
+                        ast::ExprKind::InSubquery {
+                            expr: Box::new(lhs),
+                            subquery: Box::new(self.parse_select()?),
+                            negated,
+                        }
+                    } else {
+                        ast::ExprKind::InList {
+                            expr: Box::new(lhs),
+                            list: self.parse_expr_list()?,
+                            negated,
+                        }
+                    };
+                    let right_paren = self.expect(TokenKind::RightParen)?;
+                    let span = span_between(span_start, right_paren.span());
+                    lhs = ast::Expr::new(kind, span);
+                    continue;
+                }
+                TokenKind::Keyword(Keyword::Between) => {
+                    // The bounds bind tighter than AND, which separates them.
+                    let low = self.parse_expr_bp(r_bp)?;
+                    self.expect(TokenKind::Keyword(Keyword::And))?;
+                    let high = self.parse_expr_bp(r_bp)?;
+                    let span = span_between(span_start, high.span);
+                    lhs = ast::Expr::new(
+                        ast::ExprKind::Between {
+                            expr: Box::new(lhs),
+                            low: Box::new(low),
+                            high: Box::new(high),
+                            negated,
+                        },
+                        span,
+                    );
+                    continue;
+                }
+                TokenKind::Keyword(Keyword::Like) => {
+                    let pattern = self.parse_expr_bp(r_bp)?;
+                    let span = span_between(span_start, pattern.span);
+                    lhs = ast::Expr::new(
+                        ast::ExprKind::Like {
+                            expr: Box::new(lhs),
+                            pattern: Box::new(pattern),
+                            negated,
+                        },
+                        span,
+                    );
+                    continue;
+                }
+                _ => {}
             }
 
-            break;
+            let rhs = self.parse_expr_bp(r_bp)?;
+            let span = span_between(lhs.span, rhs.span);
+            let (lhs_box, rhs_box) = (Box::new(lhs), Box::new(rhs));
+            let operator = match kind {
+                TokenKind::Plus => ast::Operator::Plus(lhs_box, rhs_box),
+                TokenKind::Minus => ast::Operator::Minus(lhs_box, rhs_box),
+                TokenKind::Asterisk => ast::Operator::Mul(lhs_box, rhs_box),
+                TokenKind::Slash => ast::Operator::Div(lhs_box, rhs_box),
+                TokenKind::Equal => ast::Operator::Equal(lhs_box, rhs_box),
+                TokenKind::BangEqual => ast::Operator::NotEqual(lhs_box, rhs_box),
+                TokenKind::Less => ast::Operator::Less(lhs_box, rhs_box),
+                TokenKind::LessEqual => ast::Operator::LessEqual(lhs_box, rhs_box),
+                TokenKind::Greater => ast::Operator::Greater(lhs_box, rhs_box),
+                TokenKind::GreaterEqual => ast::Operator::GreaterEqual(lhs_box, rhs_box),
+                TokenKind::Keyword(Keyword::And) => ast::Operator::And(lhs_box, rhs_box),
+                TokenKind::Keyword(Keyword::Or) => ast::Operator::Or(lhs_box, rhs_box),
+                _ => unreachable!(),
+            };
+            lhs = ast::Expr::new(ast::ExprKind::Operator(operator), span);
         }
 
         Ok(lhs)
@@ -239,9 +439,19 @@ impl<'source> Parser<'source> {
             if let TokenKind::Keyword(ref keyword) = token.kind {
                 let stmt = match keyword {
                     Keyword::Select => self.parse_select()?,
-                    Keyword::Insert => todo!(),
+                    Keyword::Insert => self.parse_insert()?,
                     Keyword::Update => todo!(),
                     Keyword::Delete => todo!(),
+                    Keyword::Set => self.parse_set()?,
+                    Keyword::Create => self.parse_create()?,
+                    Keyword::Drop => self.parse_drop()?,
+                    Keyword::Alter => self.parse_alter()?,
+                    Keyword::Begin => self.parse_transaction_control(ast::Stmt::Begin)?,
+                    Keyword::Commit => self.parse_transaction_control(ast::Stmt::Commit)?,
+                    Keyword::Rollback => self.parse_transaction_control(ast::Stmt::Rollback)?,
+                    Keyword::Vacuum => self.parse_vacuum()?,
+                    Keyword::Attach => self.parse_attach()?,
+                    Keyword::Detach => self.parse_detach()?,
                     _ => todo!("error: unknown statement"),
                 };
                 stmts.push(stmt);
@@ -265,13 +475,43 @@ impl<'source> Parser<'source> {
             .unwrap_or(false);
 
         let columns = self.parse_select_list()?;
-        let from = if self.next_eq(TokenKind::Eof) {
+        let from = if self.next_eq(TokenKind::Keyword(Keyword::From)) {
+            Some(self.parse_select_from()?)
+        } else {
             None
+        };
+        let r#where = if self.next_eq(TokenKind::Keyword(Keyword::Where)) {
+            Some(self.parse_expr()?)
         } else {
-            Some(self.parse_select_from()?)
+            None
+        };
+        let group_by = if self.next_eq(TokenKind::Keyword(Keyword::Group)) {
+            self.expect(TokenKind::Keyword(Keyword::By))?;
+            self.parse_expr_list()?
+        } else {
+            Vec::new()
+        };
+        let having = if self.next_eq(TokenKind::Keyword(Keyword::Having)) {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let order_by = if self.next_eq(TokenKind::Keyword(Keyword::Order)) {
+            self.expect(TokenKind::Keyword(Keyword::By))?;
+            self.parse_order_by()?
+        } else {
+            Vec::new()
+        };
+        let limit = if self.next_eq(TokenKind::Keyword(Keyword::Limit)) {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+        let offset = if self.next_eq(TokenKind::Keyword(Keyword::Offset)) {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
         };
-
-        // self.expect(TokenKind::Keyword(Keyword::Where))?;
 
         self.next_if(|kind| *kind == TokenKind::SemiColon);
 
@@ -279,30 +519,389 @@ impl<'source> Parser<'source> {
             distinct,
             columns,
             from,
+            r#where,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_insert(&mut self) -> Result<ast::Stmt<'source>> {
+        self.expect(TokenKind::Keyword(Keyword::Into))?;
+        let table = self.expect(TokenKind::Ident)?.text;
+
+        let mut columns = Vec::new();
+        if self.next_eq(TokenKind::LeftParen) {
+            loop {
+                columns.push(self.expect(TokenKind::Ident)?.text);
+                if !self.next_eq(TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.expect(TokenKind::RightParen)?;
+        }
+
+        self.expect(TokenKind::Keyword(Keyword::Select))?;
+        let source = Box::new(self.parse_select()?);
+
+        Ok(ast::Stmt::Insert {
+            table,
+            columns,
+            source,
+        })
+    }
+
+    fn parse_set(&mut self) -> Result<ast::Stmt<'source>> {
+        if self.next_eq(TokenKind::Keyword(Keyword::Transaction)) {
+            return self.parse_set_transaction();
+        }
+
+        self.expect(TokenKind::Keyword(Keyword::Global))?;
+        let name = self.expect(TokenKind::Ident)?.text;
+        self.expect(TokenKind::Equal)?;
+        let value = self.parse_expr()?;
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::SetGlobal { name, value })
+    }
+
+    /// BEGIN, COMMIT and ROLLBACK, optionally followed by TRANSACTION.
+    fn parse_transaction_control(
+        &mut self,
+        stmt: ast::Stmt<'source>,
+    ) -> Result<ast::Stmt<'source>> {
+        self.next_eq(TokenKind::Keyword(Keyword::Transaction));
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(stmt)
+    }
+
+    fn parse_set_transaction(&mut self) -> Result<ast::Stmt<'source>> {
+        self.expect(TokenKind::Keyword(Keyword::Isolation))?;
+        self.expect(TokenKind::Keyword(Keyword::Level))?;
+
+        let isolation_level = if self.next_eq(TokenKind::Keyword(Keyword::Read)) {
+            if self.next_eq(TokenKind::Keyword(Keyword::Uncommitted)) {
+                ast::IsolationLevel::ReadUncommitted
+            } else {
+                self.expect(TokenKind::Keyword(Keyword::Committed))?;
+                ast::IsolationLevel::ReadCommitted
+            }
+        } else if self.next_eq(TokenKind::Keyword(Keyword::Repeatable)) {
+            self.expect(TokenKind::Keyword(Keyword::Read))?;
+            ast::IsolationLevel::RepeatableRead
+        } else {
+            self.expect(TokenKind::Keyword(Keyword::Serializable))?;
+            ast::IsolationLevel::Serializable
+        };
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::SetTransaction { isolation_level })
+    }
+
+    fn parse_create(&mut self) -> Result<ast::Stmt<'source>> {
+        self.expect(TokenKind::Keyword(Keyword::Index))?;
+        let concurrently = self.next_if(|kind| *kind == TokenKind::Keyword(Keyword::Concurrently));
+        let name = self.expect(TokenKind::Ident)?.text;
+        self.expect(TokenKind::Keyword(Keyword::On))?;
+        let table = self.expect(TokenKind::Ident)?.text;
+        let mut method = IndexMethod::default();
+        if self.next_eq(TokenKind::Keyword(Keyword::Using)) {
+            let token = self.expect(TokenKind::Ident)?;
+            method = IndexMethod::from_name(&token.text).ok_or_else(|| ParserError {
+                message: format!("unknown index method `{}`", token.text),
+                src: self.source.to_string(),
+                err_span: token.span(),
+            })?;
+        }
+        self.expect(TokenKind::LeftParen)?;
+        let mut columns = Vec::new();
+        loop {
+            let name = self.expect(TokenKind::Ident)?.text;
+            let descending = self.next_eq(TokenKind::Keyword(Keyword::Desc));
+            if !descending {
+                self.next_eq(TokenKind::Keyword(Keyword::Asc));
+            }
+            columns.push(ast::IndexColumn { name, descending });
+            if !self.next_eq(TokenKind::Comma) {
+                break;
+            }
+        }
+        let token = self.expect(TokenKind::RightParen)?;
+        // Hash indexes only look up keys, and full-text ones terms: they
+        // have no order.
+        if method != IndexMethod::BTree && (columns.len() > 1 || columns[0].descending) {
+            let kind = match method {
+                IndexMethod::FullText => "full-text",
+                _ => "hash",
+            };
+            return Err(ParserError {
+                message: format!("{kind} indexes are on a single column, in no order"),
+                src: self.source.to_string(),
+                err_span: token.span(),
+            })?;
+        }
+
+        // Only B-trees are built online, see `Table::build_index_online`.
+        if let Some(token) = &concurrently
+            && method != IndexMethod::BTree
+        {
+            return Err(ParserError {
+                message: "only B-tree indexes are built concurrently".to_string(),
+                src: self.source.to_string(),
+                err_span: token.span(),
+            })?;
+        }
+
+        let mut include = Vec::new();
+        if let Some(token) = self.next_if(|kind| *kind == TokenKind::Keyword(Keyword::Include)) {
+            // Only B-tree leaves store the values of included columns.
+            if method != IndexMethod::BTree {
+                return Err(ParserError {
+                    message: "INCLUDE is only supported by B-tree indexes".to_string(),
+                    src: self.source.to_string(),
+                    err_span: token.span(),
+                })?;
+            }
+            self.expect(TokenKind::LeftParen)?;
+            loop {
+                include.push(self.expect(TokenKind::Ident)?.text);
+                if !self.next_eq(TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.expect(TokenKind::RightParen)?;
+        }
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::CreateIndex {
+            name,
+            concurrently: concurrently.is_some(),
+            table,
+            method,
+            columns,
+            include,
+        })
+    }
+
+    fn parse_drop(&mut self) -> Result<ast::Stmt<'source>> {
+        let stmt = if self.next_eq(TokenKind::Keyword(Keyword::Table)) {
+            let name = self.expect(TokenKind::Ident)?.text;
+            // RESTRICT is the default.
+            let cascade = self.next_eq(TokenKind::Keyword(Keyword::Cascade));
+            if !cascade {
+                self.next_eq(TokenKind::Keyword(Keyword::Restrict));
+            }
+            ast::Stmt::DropTable { name, cascade }
+        } else {
+            self.expect(TokenKind::Keyword(Keyword::Index))?;
+            let name = self.expect(TokenKind::Ident)?.text;
+            ast::Stmt::DropIndex { name }
+        };
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(stmt)
+    }
+
+    fn parse_vacuum(&mut self) -> Result<ast::Stmt<'source>> {
+        let table = self.expect(TokenKind::Ident)?.text;
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::Vacuum { table })
+    }
+
+    fn parse_attach(&mut self) -> Result<ast::Stmt<'source>> {
+        self.next_eq(TokenKind::Keyword(Keyword::Database));
+        let path = self.expect(TokenKind::String)?.text;
+        self.expect(TokenKind::Keyword(Keyword::As))?;
+        let alias = self.expect(TokenKind::Ident)?.text;
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::Attach { path, alias })
+    }
+
+    fn parse_detach(&mut self) -> Result<ast::Stmt<'source>> {
+        self.next_eq(TokenKind::Keyword(Keyword::Database));
+        let alias = self.expect(TokenKind::Ident)?.text;
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::Detach { alias })
+    }
+
+    fn parse_alter(&mut self) -> Result<ast::Stmt<'source>> {
+        self.expect(TokenKind::Keyword(Keyword::Table))?;
+        let name = self.expect(TokenKind::Ident)?.text;
+
+        let action = if self.next_eq(TokenKind::Keyword(Keyword::Add)) {
+            self.next_eq(TokenKind::Keyword(Keyword::Column));
+            ast::AlterTableAction::AddColumn(self.parse_column_def()?)
+        } else if self.next_eq(TokenKind::Keyword(Keyword::Drop)) {
+            self.next_eq(TokenKind::Keyword(Keyword::Column));
+            let name = self.expect(TokenKind::Ident)?.text;
+            ast::AlterTableAction::DropColumn { name }
+        } else if self.next_eq(TokenKind::Keyword(Keyword::Rename)) {
+            self.next_eq(TokenKind::Keyword(Keyword::Column));
+            let from = self.expect(TokenKind::Ident)?.text;
+            self.expect(TokenKind::Keyword(Keyword::To))?;
+            let to = self.expect(TokenKind::Ident)?.text;
+            ast::AlterTableAction::RenameColumn { from, to }
+        } else {
+            let token = self.next()?.expect("lexer always ends with EOF");
+            return Err(ParserError {
+                message: format!("expected ADD, DROP or RENAME, found `{}`", token.kind),
+                src: self.source.to_string(),
+                err_span: token.span(),
+            })?;
+        };
+
+        self.next_if(|kind| *kind == TokenKind::SemiColon);
+
+        Ok(ast::Stmt::AlterTable { name, action })
+    }
+
+    fn parse_column_def(&mut self) -> Result<ast::ColumnDef<'source>> {
+        let name = self.expect(TokenKind::Ident)?.text;
+        let data_type = self.parse_data_type()?;
+
+        let mut nullable = true;
+        let mut unique = false;
+        loop {
+            if self.next_eq(TokenKind::Keyword(Keyword::Not)) {
+                self.expect(TokenKind::Keyword(Keyword::Null))?;
+                nullable = false;
+            } else if self.next_eq(TokenKind::Keyword(Keyword::Null)) {
+                nullable = true;
+            } else if self.next_eq(TokenKind::Keyword(Keyword::Unique)) {
+                unique = true;
+            } else {
+                break;
+            }
+        }
+
+        Ok(ast::ColumnDef {
+            name,
+            data_type,
+            nullable,
+            unique,
         })
     }
 
-    fn parse_select_list(&mut self) -> Result<Vec<ast::Expression<'source>>> {
-        let mut select_list = Vec::new();
+    /// Type names aren't keywords, they are matched case-insensitively.
+    fn parse_data_type(&mut self) -> Result<DataType> {
+        let token = self.expect(TokenKind::Ident)?;
+        let is = |s: &str| -> bool { s.eq_ignore_ascii_case(&token.text) };
+
+        Ok(if is("BOOLEAN") || is("BOOL") {
+            DataType::Boolean
+        } else if is("INTEGER") || is("INT") {
+            DataType::Integer
+        } else if is("FLOAT") {
+            DataType::Float
+        } else if is("VARCHAR") {
+            if self.next_eq(TokenKind::LeftParen) {
+                let n = self.parse_type_param(1, u16::MAX as i64)?;
+                self.expect(TokenKind::RightParen)?;
+                DataType::VarChar(Some(n as u16))
+            } else {
+                DataType::VarChar(None)
+            }
+        } else if is("CHAR") || is("CHARACTER") {
+            // CHAR is CHAR(1).
+            let mut n = 1;
+            if self.next_eq(TokenKind::LeftParen) {
+                n = self.parse_type_param(1, u16::MAX as i64)?;
+                self.expect(TokenKind::RightParen)?;
+            }
+            DataType::Char(n as u16)
+        } else if is("DECIMAL") || is("NUMERIC") {
+            // DECIMAL is DECIMAL(MAX_DECIMAL_PRECISION, 0) and DECIMAL(p) is DECIMAL(p, 0).
+            let (mut precision, mut scale) = (DataType::MAX_DECIMAL_PRECISION as i64, 0);
+            if self.next_eq(TokenKind::LeftParen) {
+                precision = self.parse_type_param(1, DataType::MAX_DECIMAL_PRECISION as i64)?;
+                if self.next_eq(TokenKind::Comma) {
+                    scale = self.parse_type_param(0, precision)?;
+                }
+                self.expect(TokenKind::RightParen)?;
+            }
+            DataType::Decimal {
+                precision: precision as u8,
+                scale: scale as u8,
+            }
+        } else {
+            return Err(ParserError {
+                message: format!("unknown type `{}`", token.text),
+                src: self.source.to_string(),
+                err_span: token.span(),
+            })?;
+        })
+    }
+
+    /// Parses an integer type parameter in `min..=max`, e.g. the length of a VARCHAR.
+    fn parse_type_param(&mut self, min: i64, max: i64) -> Result<i64> {
+        let token = self.expect(TokenKind::Integer)?;
+        match parse_integer(&token.text) {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => Err(ParserError {
+                message: format!("expected a value between {min} and {max}"),
+                src: self.source.to_string(),
+                err_span: token.span(),
+            })?,
+        }
+    }
+
+    fn parse_select_list(&mut self) -> Result<Vec<ast::Expr<'source>>> {
+        self.parse_expr_list()
+    }
+
+    /// Parses a comma-separated list of expressions.
+    fn parse_expr_list(&mut self) -> Result<Vec<ast::Expr<'source>>> {
+        let mut list = Vec::new();
 
         loop {
             let expr = self.parse_expr()?;
-            select_list.push(expr);
+            list.push(expr);
             if !self.next_eq(TokenKind::Comma) {
                 break;
             }
         }
 
-        Ok(select_list)
+        Ok(list)
+    }
+
+    /// Parses the comma-separated expressions of an ORDER BY clause, each
+    /// one ascending unless followed by DESC.
+    fn parse_order_by(&mut self) -> Result<Vec<ast::OrderByExpr<'source>>> {
+        let mut order_by = Vec::new();
+
+        loop {
+            let expr = self.parse_expr()?;
+            let descending = self.next_eq(TokenKind::Keyword(Keyword::Desc));
+            if !descending {
+                self.next_eq(TokenKind::Keyword(Keyword::Asc));
+            }
+            order_by.push(ast::OrderByExpr { expr, descending });
+            if !self.next_eq(TokenKind::Comma) {
+                break;
+            }
+        }
+
+        Ok(order_by)
     }
 
     fn parse_select_from(&mut self) -> Result<Vec<ast::From<'source>>> {
-        self.expect(TokenKind::Keyword(Keyword::From))?;
         let mut select_from = Vec::new();
 
         loop {
-            let token = self.expect(TokenKind::Ident)?;
-            select_from.push(ast::From { table: token.text });
+            select_from.push(self.parse_from_item()?);
 
             if !self.next_eq(TokenKind::Comma) {
                 break;
@@ -311,4 +910,758 @@ impl<'source> Parser<'source> {
 
         Ok(select_from)
     }
+
+    /// Parses a table followed by any number of joins, joins are left-associative.
+    fn parse_from_item(&mut self) -> Result<ast::From<'source>> {
+        let mut from = self.parse_table_ref()?;
+
+        loop {
+            let kind = if self.next_eq(TokenKind::Keyword(Keyword::Join)) {
+                ast::JoinKind::Inner
+            } else if self.next_eq(TokenKind::Keyword(Keyword::Inner)) {
+                self.expect(TokenKind::Keyword(Keyword::Join))?;
+                ast::JoinKind::Inner
+            } else if self.next_eq(TokenKind::Keyword(Keyword::Left)) {
+                self.next_eq(TokenKind::Keyword(Keyword::Outer));
+                self.expect(TokenKind::Keyword(Keyword::Join))?;
+                ast::JoinKind::Left
+            } else if self.next_eq(TokenKind::Keyword(Keyword::Cross)) {
+                self.expect(TokenKind::Keyword(Keyword::Join))?;
+                ast::JoinKind::Cross
+            } else {
+                break;
+            };
+
+            let right = self.parse_table_ref()?;
+            let on = if kind == ast::JoinKind::Cross {
+                None
+            } else {
+                self.expect(TokenKind::Keyword(Keyword::On))?;
+                Some(self.parse_expr()?)
+            };
+
+            from = ast::From::Join {
+                kind,
+                left: Box::new(from),
+                right: Box::new(right),
+                on,
+            };
+        }
+
+        Ok(from)
+    }
+
+    fn parse_table_ref(&mut self) -> Result<ast::From<'source>> {
+        let mut database = None;
+        let mut name = self.expect(TokenKind::Ident)?.text;
+        if self.next_eq(TokenKind::Dot) {
+            database = Some(std::mem::replace(
+                &mut name,
+                self.expect(TokenKind::Ident)?.text,
+            ));
+        }
+        let alias = if self.next_eq(TokenKind::Keyword(Keyword::As)) {
+            Some(self.expect(TokenKind::Ident)?.text)
+        } else {
+            self.next_if(|kind| *kind == TokenKind::Ident)
+                .map(|token| token.text)
+        };
+
+        Ok(ast::From::Table {
+            database,
+            name,
+            alias,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sql::parser::ast::{Expr, ExprKind, Literal, Operator};
+
+    fn sexpr(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::All => "*".to_string(),
+            ExprKind::Column {
+                table: Some(table),
+                name,
+            } => format!("{table}.{name}"),
+            ExprKind::Column { name, .. } => name.to_string(),
+            ExprKind::Literal(literal) => match literal {
+                Literal::Ident(s) | Literal::String(s) => format!("'{s}'"),
+                Literal::Boolean(b) => b.to_string(),
+                Literal::Integer(i) => i.to_string(),
+                Literal::Float(f) => f.to_string(),
+                Literal::Null => "NULL".to_string(),
+            },
+            ExprKind::Operator(operator) => {
+                let (op, operands) = match operator {
+                    Operator::Plus(l, r) => ("+", vec![l, r]),
+                    Operator::Minus(l, r) => ("-", vec![l, r]),
+                    Operator::Mul(l, r) => ("*", vec![l, r]),
+                    Operator::Div(l, r) => ("/", vec![l, r]),
+                    Operator::Equal(l, r) => ("=", vec![l, r]),
+                    Operator::NotEqual(l, r) => ("!=", vec![l, r]),
+                    Operator::Less(l, r) => ("<", vec![l, r]),
+                    Operator::LessEqual(l, r) => ("<=", vec![l, r]),
+                    Operator::Greater(l, r) => (">", vec![l, r]),
+                    Operator::GreaterEqual(l, r) => (">=", vec![l, r]),
+                    Operator::And(l, r) => ("AND", vec![l, r]),
+                    Operator::Or(l, r) => ("OR", vec![l, r]),
+                    Operator::Identity(e) => ("+", vec![e]),
+                    Operator::Negate(e) => ("-", vec![e]),
+                    Operator::Not(e) => ("NOT", vec![e]),
+                };
+                let operands = operands.iter().map(|e| sexpr(e)).collect::<Vec<_>>();
+                format!("({op} {})", operands.join(" "))
+            }
+            ExprKind::Aggregate {
+                function,
+                distinct: false,
+                arg,
+            } => format!("({function:?} {})", sexpr(arg)),
+            ExprKind::Aggregate {
+                function,
+                distinct: true,
+                arg,
+            } => format!("({function:?} DISTINCT {})", sexpr(arg)),
+            ExprKind::Function { name, args } => {
+                let args = args.iter().map(|arg| format!(" {}", sexpr(arg)));
+                format!("({name}{})", args.collect::<String>())
+            }
+            ExprKind::Subquery(_) => "(SELECT)".to_string(),
+            ExprKind::Exists(_) => "(EXISTS (SELECT))".to_string(),
+            ExprKind::InSubquery { expr, negated, .. } => {
+                format!("({}IN {} (SELECT))", not(*negated), sexpr(expr))
+            }
+            ExprKind::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let list = list.iter().map(sexpr).collect::<Vec<_>>();
+                format!("({}IN {} ({}))", not(*negated), sexpr(expr), list.join(" "))
+            }
+            ExprKind::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => format!(
+                "({}BETWEEN {} {} {})",
+                not(*negated),
+                sexpr(expr),
+                sexpr(low),
+                sexpr(high)
+            ),
+            ExprKind::Like {
+                expr,
+                pattern,
+                negated,
+            } => format!("({}LIKE {} {})", not(*negated), sexpr(expr), sexpr(pattern)),
+            ExprKind::Cast { expr, data_type } => format!("(CAST {} {data_type})", sexpr(expr)),
+        }
+    }
+
+    fn not(negated: bool) -> &'static str {
+        if negated { "NOT " } else { "" }
+    }
+
+    fn parse_expr(source: &str) -> String {
+        let mut parser = Parser::new(source);
+        sexpr(&parser.parse_expr().unwrap())
+    }
+
+    #[test]
+    fn arithmetic_precedence() {
+        assert_eq!(parse_expr("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(parse_expr("1 - 2 - 3"), "(- (- 1 2) 3)");
+        assert_eq!(parse_expr("(1 + 2) * 3"), "(* (+ 1 2) 3)");
+        assert_eq!(parse_expr("-a * b"), "(* (- a) b)");
+        // `--` starts a comment.
+        assert_eq!(parse_expr("- -1.5"), "(- (- 1.5))");
+    }
+
+    #[test]
+    fn comparison_and_logical_precedence() {
+        assert_eq!(
+            parse_expr("a = 1 OR b < 2 AND c >= 3"),
+            "(OR (= a 1) (AND (< b 2) (>= c 3)))"
+        );
+        assert_eq!(
+            parse_expr("NOT a + 1 <> b AND TRUE"),
+            "(AND (NOT (!= (+ a 1) b)) true)"
+        );
+        assert_eq!(parse_expr("a <= 'x' OR NOT b"), "(OR (<= a 'x') (NOT b))");
+    }
+
+    #[test]
+    fn expression_span() {
+        let mut parser = Parser::new("SELECT (a + b) * 2");
+        parser.next().unwrap();
+        let expr = parser.parse_expr().unwrap();
+        assert_eq!(expr.span, (7, 11).into());
+    }
+
+    #[test]
+    fn select_where() {
+        let stmts = Parser::parse("SELECT a FROM t WHERE a > 1; SELECT 1;").unwrap();
+        assert_eq!(stmts.len(), 2);
+        let Stmt::Select { r#where, .. } = &stmts[0] else {
+            panic!("expected a SELECT statement");
+        };
+        assert_eq!(sexpr(r#where.as_ref().unwrap()), "(> a 1)");
+    }
+
+    #[test]
+    fn unexpected_end_of_expression() {
+        assert!(Parser::parse("SELECT a FROM t WHERE a >").is_err());
+        assert!(Parser::parse("SELECT (1 + 2").is_err());
+    }
+
+    fn sfrom(from: &ast::From) -> String {
+        match from {
+            ast::From::Table {
+                database,
+                name,
+                alias,
+            } => {
+                let database = database.as_ref().map(|db| format!("{db}."));
+                let alias = alias.as_ref().map(|alias| format!("@{alias}"));
+                format!(
+                    "{}{name}{}",
+                    database.unwrap_or_default(),
+                    alias.unwrap_or_default()
+                )
+            }
+            ast::From::Join {
+                kind,
+                left,
+                right,
+                on,
+            } => {
+                let on = on.as_ref().map(|on| format!(" {}", sexpr(on)));
+                format!(
+                    "({kind:?} {} {}{})",
+                    sfrom(left),
+                    sfrom(right),
+                    on.unwrap_or_default()
+                )
+            }
+        }
+    }
+
+    #[test]
+    fn select_joins() {
+        let stmts = Parser::parse(
+            "SELECT a.x, b.y FROM a JOIN b ON a.id = b.id \
+             LEFT OUTER JOIN c AS cc ON b.id = cc.id CROSS JOIN d, e ee",
+        )
+        .unwrap();
+        let Stmt::Select { columns, from, .. } = &stmts[0] else {
+            panic!("expected a SELECT statement");
+        };
+        assert_eq!(sexpr(&columns[0]), "a.x");
+        let from = from.as_ref().unwrap().iter().map(sfrom).collect::<Vec<_>>();
+        assert_eq!(
+            from,
+            [
+                "(Cross (Left (Inner a b (= a.id b.id)) c@cc (= b.id cc.id)) d)",
+                "e@ee"
+            ]
+        );
+    }
+
+    #[test]
+    fn join_without_on() {
+        assert!(Parser::parse("SELECT * FROM a JOIN b").is_err());
+        assert!(Parser::parse("SELECT * FROM a LEFT b ON a.id = b.id").is_err());
+    }
+
+    #[test]
+    fn select_group_by_having() {
+        let stmts = Parser::parse(
+            "SELECT a, count(*), SUM(b + 1), COUNT(DISTINCT b) FROM t \
+             GROUP BY a, c HAVING avg(b) > 2 AND MAX(c) < 3",
+        )
+        .unwrap();
+        let Stmt::Select {
+            columns,
+            group_by,
+            having,
+            ..
+        } = &stmts[0]
+        else {
+            panic!("expected a SELECT statement");
+        };
+        let columns = columns.iter().map(sexpr).collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            ["a", "(Count *)", "(Sum (+ b 1))", "(Count DISTINCT b)"]
+        );
+        let group_by = group_by.iter().map(sexpr).collect::<Vec<_>>();
+        assert_eq!(group_by, ["a", "c"]);
+        assert_eq!(
+            sexpr(having.as_ref().unwrap()),
+            "(AND (> (Avg b) 2) (< (Max c) 3))"
+        );
+    }
+
+    #[test]
+    fn select_order_by_limit() {
+        let stmts = Parser::parse(
+            "SELECT a, b FROM t ORDER BY a + 1 DESC, 2, b ASC LIMIT 10 OFFSET 5; \
+             SELECT a FROM t OFFSET 1",
+        )
+        .unwrap();
+        let Stmt::Select {
+            order_by,
+            limit,
+            offset,
+            ..
+        } = &stmts[0]
+        else {
+            panic!("expected a SELECT statement");
+        };
+        let order_by = order_by
+            .iter()
+            .map(|key| (sexpr(&key.expr), key.descending))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order_by,
+            [
+                ("(+ a 1)".to_string(), true),
+                ("2".to_string(), false),
+                ("b".to_string(), false)
+            ]
+        );
+        assert_eq!(sexpr(limit.as_ref().unwrap()), "10");
+        assert_eq!(sexpr(offset.as_ref().unwrap()), "5");
+        assert!(matches!(
+            &stmts[1],
+            Stmt::Select {
+                order_by,
+                limit: None,
+                offset: Some(_),
+                ..
+            } if order_by.is_empty()
+        ));
+
+        assert!(Parser::parse("SELECT a FROM t ORDER a").is_err());
+        assert!(Parser::parse("SELECT a FROM t ORDER BY").is_err());
+    }
+
+    #[test]
+    fn aggregate_star() {
+        assert!(Parser::parse("SELECT SUM(*) FROM t").is_err());
+        assert!(Parser::parse("SELECT COUNT(DISTINCT *) FROM t").is_err());
+        // Not followed by a parenthesis: a column.
+        let stmts = Parser::parse("SELECT count FROM t").unwrap();
+        let Stmt::Select { columns, .. } = &stmts[0] else {
+            panic!("expected a SELECT statement");
+        };
+        assert_eq!(sexpr(&columns[0]), "count");
+    }
+
+    #[test]
+    fn subqueries() {
+        let source = "SELECT a FROM t WHERE a IN (SELECT b FROM u WHERE c > 1) AND d = (SELECT 1)";
+        let stmts = Parser::parse(source).unwrap();
+        assert_eq!(stmts.len(), 1);
+        let Stmt::Select { r#where, .. } = &stmts[0] else {
+            panic!("expected a SELECT statement");
+        };
+        let r#where = r#where.as_ref().unwrap();
+        assert_eq!(sexpr(r#where), "(AND (IN a (SELECT)) (= d (SELECT)))");
+
+        let ExprKind::Operator(Operator::And(in_subquery, _)) = &r#where.kind else {
+            panic!("expected AND");
+        };
+        assert_eq!(
+            &source[in_subquery.span.offset()..][..in_subquery.span.len()],
+            "a IN (SELECT b FROM u WHERE c > 1)"
+        );
+        let ExprKind::InSubquery { subquery, .. } = &in_subquery.kind else {
+            panic!("expected IN");
+        };
+        let Stmt::Select { from, r#where, .. } = subquery.as_ref() else {
+            panic!("expected a SELECT statement");
+        };
+        assert_eq!(sfrom(&from.as_ref().unwrap()[0]), "u");
+        assert_eq!(sexpr(r#where.as_ref().unwrap()), "(> c 1)");
+
+        assert!(Parser::parse("SELECT a FROM t WHERE a IN ()").is_err());
+
+        assert_eq!(
+            parse_expr("NOT EXISTS (SELECT 1 FROM u WHERE u.b = t.a) AND c"),
+            "(AND (NOT (EXISTS (SELECT))) c)"
+        );
+        assert!(Parser::parse("SELECT a FROM t WHERE EXISTS (1)").is_err());
+        assert!(Parser::parse("SELECT a FROM t WHERE EXISTS SELECT 1").is_err());
+    }
+
+    #[test]
+    fn in_between_like() {
+        assert_eq!(parse_expr("a IN (1, 'b', c)"), "(IN a (1 'b' c))");
+        assert_eq!(
+            parse_expr("a NOT IN (SELECT b) OR c"),
+            "(OR (NOT IN a (SELECT)) c)"
+        );
+        assert_eq!(
+            parse_expr("a BETWEEN 1 AND b + 1 AND c"),
+            "(AND (BETWEEN a 1 (+ b 1)) c)"
+        );
+        assert_eq!(
+            parse_expr("NOT a NOT BETWEEN 1 AND 2"),
+            "(NOT (NOT BETWEEN a 1 2))"
+        );
+        assert_eq!(
+            parse_expr("a LIKE 'x%' AND b NOT LIKE '_'"),
+            "(AND (LIKE a 'x%') (NOT LIKE b '_'))"
+        );
+
+        assert!(Parser::parse("SELECT a BETWEEN 1").is_err());
+        assert!(Parser::parse("SELECT a NOT 1").is_err());
+    }
+
+    #[test]
+    fn function_calls() {
+        assert_eq!(
+            parse_expr("SUBSTR(a, 1, 2) = LOWER(b)"),
+            "(= (SUBSTR a 1 2) (LOWER b))"
+        );
+        assert_eq!(parse_expr("now() + 1"), "(+ (now) 1)");
+        assert!(Parser::parse("SELECT upper(a").is_err());
+        assert!(Parser::parse("SELECT concat(a,)").is_err());
+    }
+
+    #[test]
+    fn cast() {
+        assert_eq!(parse_expr("CAST(a AS INT) = 1"), "(= (CAST a INTEGER) 1)");
+        assert_eq!(
+            parse_expr("cast('1.5' as decimal(3, 1)) + 1"),
+            "(+ (CAST '1.5' DECIMAL(3,1)) 1)"
+        );
+        assert!(Parser::parse("SELECT CAST(a INT)").is_err());
+        assert!(Parser::parse("SELECT CAST(a AS)").is_err());
+    }
+
+    #[test]
+    fn create_drop_index() {
+        let stmts = Parser::parse("CREATE INDEX idx ON t (a); drop index idx").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [
+                Stmt::CreateIndex {
+                    name,
+                    concurrently: false,
+                    table,
+                    method: IndexMethod::BTree,
+                    columns,
+                    include,
+                },
+                Stmt::DropIndex { name: dropped },
+            ] if name == "idx" && table == "t" && include.is_empty() && dropped == "idx"
+                && matches!(&columns[..], [column] if column.name == "a" && !column.descending)
+        ));
+
+        let stmts = Parser::parse("create index concurrently idx ON t (a)").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::CreateIndex { name, concurrently: true, .. }] if name == "idx"
+        ));
+        assert!(Parser::parse("CREATE INDEX CONCURRENTLY idx ON t USING hash (a)").is_err());
+        assert!(Parser::parse("CREATE CONCURRENTLY INDEX idx ON t (a)").is_err());
+
+        let stmts = Parser::parse("CREATE INDEX idx ON t (a ASC, b desc, c)").unwrap();
+        let [Stmt::CreateIndex { columns, .. }] = &stmts[..] else {
+            panic!("expected a CREATE INDEX");
+        };
+        let columns = columns
+            .iter()
+            .map(|column| (column.name.as_ref(), column.descending))
+            .collect::<Vec<_>>();
+        assert_eq!(columns, [("a", false), ("b", true), ("c", false)]);
+
+        let stmts = Parser::parse("CREATE INDEX idx ON t (a) INCLUDE (b, c)").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::CreateIndex { include, .. }] if include == &["b", "c"]
+        ));
+
+        let stmts = Parser::parse("CREATE INDEX idx ON t USING hash (a)").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::CreateIndex { method: IndexMethod::Hash, columns, .. }] if columns[0].name == "a"
+        ));
+        let stmts = Parser::parse("CREATE INDEX idx ON t USING BTREE (a)").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::CreateIndex {
+                method: IndexMethod::BTree,
+                ..
+            }]
+        ));
+        let stmts = Parser::parse("CREATE INDEX idx ON t USING fulltext (a)").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::CreateIndex {
+                method: IndexMethod::FullText,
+                ..
+            }]
+        ));
+        assert!(Parser::parse("CREATE INDEX idx ON t USING fulltext (a, b)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t USING gist (a)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t USING hash (a) INCLUDE (b)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t USING hash (a, b)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t USING hash (a DESC)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t (a DESC ASC)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t (a,)").is_err());
+
+        assert!(Parser::parse("CREATE INDEX idx ON t").is_err());
+        assert!(Parser::parse("CREATE INDEX ON t (a)").is_err());
+        assert!(Parser::parse("CREATE INDEX idx ON t (a) INCLUDE ()").is_err());
+    }
+
+    #[test]
+    fn vacuum() {
+        let stmts = Parser::parse("VACUUM users; vacuum t").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::Vacuum { table: a }, Stmt::Vacuum { table: b }] if a == "users" && b == "t"
+        ));
+
+        assert!(Parser::parse("VACUUM").is_err());
+    }
+
+    #[test]
+    fn attach_detach() {
+        let stmts =
+            Parser::parse("ATTACH DATABASE '/data/shop' AS shop; attach 'x' as y; DETACH shop")
+                .unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [
+                Stmt::Attach { path: a, alias: b },
+                Stmt::Attach { path: c, alias: d },
+                Stmt::Detach { alias: e },
+            ] if a == "/data/shop" && b == "shop" && c == "x" && d == "y" && e == "shop"
+        ));
+
+        let stmts = Parser::parse("SELECT a FROM shop.users u JOIN t ON u.a = t.a").unwrap();
+        let Stmt::Select { from, .. } = &stmts[0] else {
+            panic!("expected a SELECT statement");
+        };
+        assert_eq!(
+            sfrom(&from.as_ref().unwrap()[0]),
+            "(Inner shop.users@u t (= u.a t.a))"
+        );
+
+        assert!(Parser::parse("ATTACH '/data/shop'").is_err());
+        assert!(Parser::parse("ATTACH shop AS shop").is_err());
+        assert!(Parser::parse("SELECT a FROM shop.").is_err());
+    }
+
+    #[test]
+    fn insert_select() {
+        let stmts =
+            Parser::parse("INSERT INTO t (a, b) SELECT x, y FROM u; INSERT INTO t SELECT 1")
+                .unwrap();
+        let [
+            Stmt::Insert {
+                table,
+                columns,
+                source,
+            },
+            Stmt::Insert {
+                columns: all_columns,
+                ..
+            },
+        ] = &stmts[..]
+        else {
+            panic!("expected two INSERT statements");
+        };
+        assert_eq!(table, "t");
+        assert_eq!(columns, &["a", "b"]);
+        assert!(matches!(
+            source.as_ref(),
+            Stmt::Select { from: Some(_), .. }
+        ));
+        assert!(all_columns.is_empty());
+
+        assert!(Parser::parse("INSERT t SELECT 1").is_err());
+        assert!(Parser::parse("INSERT INTO t () SELECT 1").is_err());
+        assert!(Parser::parse("INSERT INTO t (a) 1").is_err());
+    }
+
+    #[test]
+    fn alter_table() {
+        let stmts = Parser::parse(
+            "ALTER TABLE t ADD COLUMN a INTEGER NOT NULL UNIQUE;
+             ALTER TABLE t ADD b varchar;
+             ALTER TABLE t DROP COLUMN a;
+             ALTER TABLE t RENAME b TO c",
+        )
+        .unwrap();
+        assert_eq!(stmts.len(), 4);
+        let actions = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::AlterTable { name, action } if name == "t" => action,
+                _ => panic!("expected ALTER TABLE t"),
+            })
+            .collect::<Vec<_>>();
+
+        assert!(matches!(
+            actions[0],
+            ast::AlterTableAction::AddColumn(ast::ColumnDef {
+                name,
+                data_type: DataType::Integer,
+                nullable: false,
+                unique: true,
+            }) if name == "a"
+        ));
+        assert!(matches!(
+            actions[1],
+            ast::AlterTableAction::AddColumn(ast::ColumnDef {
+                name,
+                data_type: DataType::VarChar(None),
+                nullable: true,
+                unique: false,
+            }) if name == "b"
+        ));
+        assert!(matches!(
+            actions[2],
+            ast::AlterTableAction::DropColumn { name } if name == "a"
+        ));
+        assert!(matches!(
+            actions[3],
+            ast::AlterTableAction::RenameColumn { from, to } if from == "b" && to == "c"
+        ));
+
+        assert!(Parser::parse("ALTER TABLE t ADD COLUMN a BLOB").is_err());
+        assert!(Parser::parse("ALTER TABLE t MODIFY a INTEGER").is_err());
+        assert!(Parser::parse("ALTER TABLE t RENAME a b").is_err());
+    }
+
+    #[test]
+    fn transaction_control() {
+        let stmts = Parser::parse(
+            "BEGIN; SET TRANSACTION ISOLATION LEVEL REPEATABLE READ; COMMIT TRANSACTION;
+             begin transaction; rollback",
+        )
+        .unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [
+                Stmt::Begin,
+                Stmt::SetTransaction {
+                    isolation_level: ast::IsolationLevel::RepeatableRead
+                },
+                Stmt::Commit,
+                Stmt::Begin,
+                Stmt::Rollback,
+            ]
+        ));
+
+        let levels = [
+            ("READ UNCOMMITTED", ast::IsolationLevel::ReadUncommitted),
+            ("READ COMMITTED", ast::IsolationLevel::ReadCommitted),
+            ("SERIALIZABLE", ast::IsolationLevel::Serializable),
+        ];
+        for (source, level) in levels {
+            let source = format!("SET TRANSACTION ISOLATION LEVEL {source}");
+            let stmts = Parser::parse(&source).unwrap();
+            assert!(matches!(
+                stmts[..],
+                [Stmt::SetTransaction { isolation_level }] if isolation_level == level
+            ));
+        }
+
+        assert!(Parser::parse("SET TRANSACTION ISOLATION LEVEL READ").is_err());
+        assert!(Parser::parse("SET TRANSACTION READ COMMITTED").is_err());
+    }
+
+    #[test]
+    fn set_global() {
+        let stmts = Parser::parse("SET GLOBAL page_cache_size = 4096").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [Stmt::SetGlobal {
+                name,
+                value: Expr {
+                    kind: ExprKind::Literal(Literal::Integer(4096)),
+                    ..
+                },
+            }] if name == "page_cache_size"
+        ));
+
+        assert!(Parser::parse("SET page_cache_size = 4096").is_err());
+        assert!(Parser::parse("SET GLOBAL page_cache_size 4096").is_err());
+    }
+
+    #[test]
+    fn drop_table() {
+        let stmts =
+            Parser::parse("DROP TABLE a; DROP TABLE b CASCADE; DROP TABLE c RESTRICT").unwrap();
+        assert!(matches!(
+            &stmts[..],
+            [
+                Stmt::DropTable { name: a, cascade: false },
+                Stmt::DropTable { name: b, cascade: true },
+                Stmt::DropTable { name: c, cascade: false },
+            ] if a == "a" && b == "b" && c == "c"
+        ));
+    }
+
+    #[test]
+    fn parameterized_types() {
+        let types = [
+            ("VARCHAR(10)", DataType::VarChar(Some(10))),
+            ("char", DataType::Char(1)),
+            ("CHARACTER(3)", DataType::Char(3)),
+            (
+                "DECIMAL",
+                DataType::Decimal {
+                    precision: DataType::MAX_DECIMAL_PRECISION,
+                    scale: 0,
+                },
+            ),
+            (
+                "numeric(5)",
+                DataType::Decimal {
+                    precision: 5,
+                    scale: 0,
+                },
+            ),
+            (
+                "DECIMAL(5, 2)",
+                DataType::Decimal {
+                    precision: 5,
+                    scale: 2,
+                },
+            ),
+        ];
+        for (source, expected) in types {
+            let source = format!("ALTER TABLE t ADD a {source}");
+            let stmts = Parser::parse(&source).unwrap();
+            let [
+                Stmt::AlterTable {
+                    action: ast::AlterTableAction::AddColumn(column),
+                    ..
+                },
+            ] = &stmts[..]
+            else {
+                panic!("expected ALTER TABLE ADD COLUMN");
+            };
+            assert_eq!(column.data_type, expected, "{source}");
+        }
+
+        for source in ["VARCHAR(0)", "VARCHAR(65536)", "DECIMAL(5, 6)", "CHAR(1.5)"] {
+            let source = format!("ALTER TABLE t ADD a {source}");
+            assert!(Parser::parse(&source).is_err(), "{source}");
+        }
+    }
 }
@@ -0,0 +1,652 @@
+use crate::sql::exec::BATCH_SIZE;
+use crate::sql::parser::ast::JoinKind;
+use crate::sql::plan::optimizer::{conjunction, filter, split_conjuncts};
+use crate::sql::plan::{BinaryOp, Expr, JoinStrategy, LogicalPlan, SchemaProvider, UnaryOp};
+use crate::sql::schema::DataType;
+
+/// The size of a table, stored in the catalog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub rows: u64,
+    pub pages: u64,
+}
+
+/// The size of an index, stored in the catalog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    pub keys: u64,
+    pub pages: u64,
+    /// The number of levels of the tree, 1 when the root is a leaf.
+    pub height: u64,
+}
+
+/// Rows assumed for a table without statistics.
+const DEFAULT_ROWS: f64 = 1000.0;
+/// Rows per page assumed for a table without statistics.
+const ROWS_PER_PAGE: f64 = 100.0;
+/// The cost of processing a tuple, relative to reading a page.
+const TUPLE_COST: f64 = 0.01;
+/// The pages read to fetch a tuple through an index without statistics.
+const INDEX_LOOKUP_COST: f64 = 3.0;
+
+/// The estimated number of tuples produced by a plan and the cost of running
+/// it, in pages read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub rows: f64,
+    pub cost: f64,
+}
+
+/// Estimates the size and the cost of a plan from the statistics of the
+/// tables it reads.
+pub fn estimate(plan: &LogicalPlan, tables: &dyn SchemaProvider) -> Estimate {
+    match plan {
+        LogicalPlan::SingleRow | LogicalPlan::SetGlobal { .. } => Estimate {
+            rows: 1.0,
+            cost: 0.0,
+        },
+        LogicalPlan::Scan { table, .. } => {
+            let (rows, pages) = match tables.table_stats(table) {
+                Some(stats) => (stats.rows as f64, stats.pages as f64),
+                None => (DEFAULT_ROWS, DEFAULT_ROWS / ROWS_PER_PAGE),
+            };
+            Estimate {
+                rows,
+                cost: pages + rows * TUPLE_COST,
+            }
+        }
+        LogicalPlan::Filter { input, predicate } => {
+            let input = estimate(input, tables);
+            Estimate {
+                rows: input.rows * selectivity(predicate),
+                cost: input.cost + input.rows * TUPLE_COST,
+            }
+        }
+        LogicalPlan::Project { input, .. } | LogicalPlan::Sort { input, .. } => {
+            let input = estimate(input, tables);
+            Estimate {
+                rows: input.rows,
+                cost: input.cost + input.rows * TUPLE_COST,
+            }
+        }
+        LogicalPlan::Join {
+            kind,
+            left,
+            right,
+            on,
+            ..
+        } => best_join(*kind, left, right, on.as_ref(), tables).1,
+        LogicalPlan::Aggregate {
+            input, group_by, ..
+        } => {
+            let input = estimate(input, tables);
+            // Groups are assumed to have 10 tuples.
+            let rows = match group_by.is_empty() {
+                true => 1.0,
+                false => (input.rows / 10.0).max(1.0),
+            };
+            Estimate {
+                rows,
+                cost: input.cost + input.rows * TUPLE_COST,
+            }
+        }
+        LogicalPlan::Limit {
+            input,
+            limit,
+            offset,
+        } => {
+            let input = estimate(input, tables);
+            let rows = (input.rows - *offset as f64).max(0.0);
+            Estimate {
+                rows: limit.map_or(rows, |limit| rows.min(limit as f64)),
+                cost: input.cost,
+            }
+        }
+        LogicalPlan::Insert { input, .. } => {
+            let input = estimate(input, tables);
+            Estimate {
+                rows: 1.0,
+                cost: input.cost + input.rows * TUPLE_COST,
+            }
+        }
+        // Each page is read and written.
+        LogicalPlan::Vacuum { table, .. } => {
+            let pages = match tables.table_stats(table) {
+                Some(stats) => stats.pages as f64,
+                None => DEFAULT_ROWS / ROWS_PER_PAGE,
+            };
+            Estimate {
+                rows: 1.0,
+                cost: 2.0 * pages,
+            }
+        }
+    }
+}
+
+/// The fraction of the tuples satisfying a predicate, from rules of thumb.
+fn selectivity(predicate: &Expr) -> f64 {
+    let negate = |selectivity: f64, negated: bool| match negated {
+        true => 1.0 - selectivity,
+        false => selectivity,
+    };
+
+    match predicate {
+        Expr::Binary {
+            op: BinaryOp::And,
+            lhs,
+            rhs,
+        } => selectivity(lhs) * selectivity(rhs),
+        Expr::Binary {
+            op: BinaryOp::Or,
+            lhs,
+            rhs,
+        } => {
+            let (lhs, rhs) = (selectivity(lhs), selectivity(rhs));
+            lhs + rhs - lhs * rhs
+        }
+        Expr::Binary {
+            op: BinaryOp::Equal,
+            ..
+        } => 0.1,
+        Expr::Binary {
+            op: BinaryOp::NotEqual,
+            ..
+        } => 0.9,
+        Expr::Binary { op, .. } if op.is_comparison() => 1.0 / 3.0,
+        Expr::Unary {
+            op: UnaryOp::Not,
+            expr,
+        } => 1.0 - selectivity(expr),
+        Expr::Between { negated, .. } => negate(0.25, *negated),
+        Expr::InList { list, negated, .. } => negate((0.1 * list.len() as f64).min(0.5), *negated),
+        _ => 0.5,
+    }
+}
+
+/// Returns the conjuncts of a join condition.
+fn conjuncts(on: Option<&Expr>) -> Vec<&Expr> {
+    let mut conjuncts = Vec::new();
+    let mut stack = on.into_iter().collect::<Vec<_>>();
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Binary {
+                op: BinaryOp::And,
+                lhs,
+                rhs,
+            } => stack.extend([rhs.as_ref(), lhs]),
+            expr => conjuncts.push(expr),
+        }
+    }
+    conjuncts
+}
+
+/// Returns the left and right columns of an equality between a column of
+/// each side of a join.
+fn equi_key(conjunct: &Expr, left_width: usize) -> Option<(usize, usize)> {
+    let Expr::Binary {
+        op: BinaryOp::Equal,
+        lhs,
+        rhs,
+    } = conjunct
+    else {
+        return None;
+    };
+    let (&Expr::Column(lhs), &Expr::Column(rhs)) = (lhs.as_ref(), rhs.as_ref()) else {
+        return None;
+    };
+    match (lhs < left_width, rhs < left_width) {
+        (true, false) => Some((lhs, rhs - left_width)),
+        (false, true) => Some((rhs, lhs - left_width)),
+        _ => None,
+    }
+}
+
+/// Returns the pairs of left and right columns of the same type that the
+/// join condition requires to be equal. Right columns are numbered from 0.
+pub fn equi_join_keys(
+    left: &LogicalPlan,
+    right: &LogicalPlan,
+    on: Option<&Expr>,
+) -> Vec<(usize, usize)> {
+    let (left, right) = (left.schema().columns(), right.schema().columns());
+    conjuncts(on)
+        .into_iter()
+        .filter_map(|conjunct| equi_key(conjunct, left.len()))
+        .filter(|&(lhs, rhs)| {
+            left[lhs].data_type.is_some() && left[lhs].data_type == right[rhs].data_type
+        })
+        .collect()
+}
+
+/// The pages read to fetch a tuple through the cheapest index, if the right
+/// side of a join is a table scan with an index on one of its INTEGER join
+/// keys: a page per level of the index, then the heap page.
+fn index_lookup_cost(
+    right: &LogicalPlan,
+    keys: &[(usize, usize)],
+    tables: &dyn SchemaProvider,
+) -> Option<f64> {
+    let LogicalPlan::Scan { table, schema, .. } = right else {
+        return None;
+    };
+    keys.iter()
+        .filter_map(|&(_, key)| {
+            let column = &schema.columns()[key];
+            if column.data_type != Some(DataType::Integer) || !tables.has_index(table, &column.name)
+            {
+                return None;
+            }
+            Some(
+                tables
+                    .index_stats(table, &column.name)
+                    .map_or(INDEX_LOOKUP_COST, |stats| stats.height as f64 + 1.0),
+            )
+        })
+        .min_by(f64::total_cmp)
+}
+
+/// Picks the cheapest strategy to run a join, returns it along with the
+/// estimate of the join.
+fn best_join(
+    kind: JoinKind,
+    left: &LogicalPlan,
+    right: &LogicalPlan,
+    on: Option<&Expr>,
+    tables: &dyn SchemaProvider,
+) -> (JoinStrategy, Estimate) {
+    let (lhs, rhs) = (estimate(left, tables), estimate(right, tables));
+    let left_width = left.schema().columns().len();
+
+    // An equality between the columns of both sides is assumed to match a
+    // key: each tuple of the largest side has one match at most.
+    let selectivity = conjuncts(on)
+        .into_iter()
+        .map(|conjunct| match equi_key(conjunct, left_width) {
+            Some(_) => 1.0 / lhs.rows.max(rhs.rows).max(1.0),
+            None => selectivity(conjunct),
+        })
+        .product::<f64>();
+    let mut rows = lhs.rows * rhs.rows * selectivity;
+    if kind == JoinKind::Left {
+        rows = rows.max(lhs.rows);
+    }
+
+    let batches = (lhs.rows / BATCH_SIZE as f64).ceil().max(1.0);
+    let mut strategies = vec![(
+        JoinStrategy::NestedLoop,
+        lhs.cost + batches * rhs.cost + lhs.rows * rhs.rows * TUPLE_COST,
+    )];
+    let keys = equi_join_keys(left, right, on);
+    if !keys.is_empty() {
+        strategies.push((
+            JoinStrategy::Hash,
+            lhs.cost + rhs.cost + (lhs.rows + rhs.rows) * TUPLE_COST,
+        ));
+    }
+    if let Some(lookup_cost) = index_lookup_cost(right, &keys, tables) {
+        strategies.push((
+            JoinStrategy::IndexNestedLoop,
+            lhs.cost + lhs.rows * lookup_cost,
+        ));
+    }
+
+    let (strategy, cost) = strategies
+        .into_iter()
+        .min_by(|lhs, rhs| lhs.1.total_cmp(&rhs.1))
+        .unwrap();
+    let estimate = Estimate {
+        rows,
+        cost: cost + rows * TUPLE_COST,
+    };
+    (strategy, estimate)
+}
+
+/// Orders the inner and cross joins from their estimated cardinalities and
+/// picks the cheapest strategy for every join.
+///
+/// A chain of inner and cross joins becomes a left-deep tree starting with
+/// its smallest input, the next input is always the cheapest to join among
+/// those with a join condition, to avoid cross products. A projection puts
+/// the columns back in their order.
+pub fn plan_joins(plan: LogicalPlan, tables: &dyn SchemaProvider) -> LogicalPlan {
+    match plan {
+        LogicalPlan::Join {
+            kind: JoinKind::Inner | JoinKind::Cross,
+            ..
+        } => reorder(plan, tables),
+        LogicalPlan::Join {
+            kind,
+            left,
+            right,
+            on,
+            schema,
+            ..
+        } => {
+            let left = plan_joins(*left, tables);
+            let right = plan_joins(*right, tables);
+            let (strategy, _) = best_join(kind, &left, &right, on.as_ref(), tables);
+            LogicalPlan::Join {
+                kind,
+                left: Box::new(left),
+                right: Box::new(right),
+                on,
+                schema,
+                strategy,
+            }
+        }
+        plan => plan.map_inputs(&mut |input| plan_joins(input, tables)),
+    }
+}
+
+/// An input of a chain of joins.
+struct Relation {
+    plan: Option<LogicalPlan>,
+    // The position of its first column in the output of the chain.
+    offset: usize,
+    width: usize,
+    rows: f64,
+}
+
+/// Collects the inputs of a chain of inner and cross joins starting at column
+/// `offset`, and the conjuncts of their conditions.
+fn flatten(
+    plan: LogicalPlan,
+    offset: usize,
+    tables: &dyn SchemaProvider,
+    relations: &mut Vec<Relation>,
+    conjuncts: &mut Vec<Expr>,
+) {
+    match plan {
+        LogicalPlan::Join {
+            kind: JoinKind::Inner | JoinKind::Cross,
+            left,
+            right,
+            on,
+            ..
+        } => {
+            let right_offset = offset + left.schema().columns().len();
+            flatten(*left, offset, tables, relations, conjuncts);
+            flatten(*right, right_offset, tables, relations, conjuncts);
+            if let Some(on) = on {
+                let on = on.map_columns(&mut |idx| Expr::Column(idx + offset));
+                split_conjuncts(on, conjuncts);
+            }
+        }
+        plan => {
+            let plan = plan_joins(plan, tables);
+            relations.push(Relation {
+                offset,
+                width: plan.schema().columns().len(),
+                rows: estimate(&plan, tables).rows,
+                plan: Some(plan),
+            });
+        }
+    }
+}
+
+fn reorder(plan: LogicalPlan, tables: &dyn SchemaProvider) -> LogicalPlan {
+    let schema = plan.schema().clone();
+    let (mut relations, mut conjuncts) = (Vec::new(), Vec::new());
+    flatten(plan, 0, tables, &mut relations, &mut conjuncts);
+
+    let owner = |relations: &[Relation], idx: usize| {
+        relations
+            .iter()
+            .rposition(|relation| relation.offset <= idx && idx < relation.offset + relation.width)
+            .unwrap()
+    };
+    // The conjuncts along with the relations they read.
+    let mut pending = conjuncts
+        .into_iter()
+        .map(|conjunct| {
+            let mut reads = Vec::new();
+            conjunct.for_each_column(&mut |idx| reads.push(owner(&relations, idx)));
+            (conjunct, reads)
+        })
+        .collect::<Vec<_>>();
+
+    let mut remaining = (0..relations.len()).collect::<Vec<_>>();
+    let first = (0..remaining.len())
+        .min_by(|&lhs, &rhs| relations[lhs].rows.total_cmp(&relations[rhs].rows))
+        .unwrap();
+    let mut order = vec![remaining.remove(first)];
+    // The position of the first column of each joined relation.
+    let mut positions = vec![None; relations.len()];
+    positions[order[0]] = Some(0);
+    let mut tree = relations[order[0]].plan.take().unwrap();
+
+    // Moves the conjuncts only reading the joined relations and `next` out of
+    // `pending`, with their columns numbered as in the joined tuples.
+    let take_conjuncts = |pending: &mut Vec<(Expr, Vec<usize>)>,
+                          positions: &[Option<usize>],
+                          relations: &[Relation]| {
+        let (ready, rest) =
+            std::mem::take(pending)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(_, reads)| {
+                    reads.iter().all(|&relation| positions[relation].is_some())
+                });
+        *pending = rest;
+        ready
+            .into_iter()
+            .map(|(conjunct, _)| {
+                conjunct.map_columns(&mut |idx| {
+                    let relation = owner(relations, idx);
+                    Expr::Column(positions[relation].unwrap() + idx - relations[relation].offset)
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    tree = filter(tree, take_conjuncts(&mut pending, &positions, &relations));
+
+    while !remaining.is_empty() {
+        let width = tree.schema().columns().len();
+        let mut best: Option<(usize, bool, Estimate, JoinStrategy)> = None;
+        for (i, &relation) in remaining.iter().enumerate() {
+            let mut positions = positions.clone();
+            positions[relation] = Some(width);
+            let mut pending = pending.clone();
+            let on = conjunction(take_conjuncts(&mut pending, &positions, &relations));
+
+            let right = relations[relation].plan.as_ref().unwrap();
+            let (strategy, estimate) =
+                best_join(JoinKind::Inner, &tree, right, on.as_ref(), tables);
+            let connected = on.is_some();
+            let better = best.is_none_or(|(_, best_connected, best_estimate, _)| {
+                (connected, -estimate.cost) > (best_connected, -best_estimate.cost)
+            });
+            if better {
+                best = Some((i, connected, estimate, strategy));
+            }
+        }
+
+        let (i, _, _, strategy) = best.unwrap();
+        let relation = remaining.remove(i);
+        positions[relation] = Some(width);
+        order.push(relation);
+        let on = conjunction(take_conjuncts(&mut pending, &positions, &relations));
+        let right = relations[relation].plan.take().unwrap();
+        let schema = tree.schema().join(right.schema());
+        tree = LogicalPlan::Join {
+            kind: match on {
+                Some(_) => JoinKind::Inner,
+                None => JoinKind::Cross,
+            },
+            left: Box::new(tree),
+            right: Box::new(right),
+            on,
+            schema,
+            strategy,
+        };
+    }
+
+    if order.iter().copied().eq(0..relations.len()) {
+        return tree;
+    }
+    let exprs = (0..schema.columns().len())
+        .map(|idx| {
+            let relation = owner(&relations, idx);
+            Expr::Column(positions[relation].unwrap() + idx - relations[relation].offset)
+        })
+        .collect();
+    LogicalPlan::Project {
+        input: Box::new(tree),
+        exprs,
+        schema,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan::plan;
+    use crate::sql::schema::{Column, ConstraintsBuilder, Schema};
+
+    /// Tables with statistics and indexes on their `id` columns.
+    struct Tables(HashMap<String, (Schema, TableStats)>);
+
+    impl SchemaProvider for Tables {
+        fn table_schema(&self, name: &str) -> Option<Schema> {
+            self.0.get(name).map(|(schema, _)| schema.clone())
+        }
+
+        fn table_stats(&self, name: &str) -> Option<TableStats> {
+            self.0.get(name).map(|(_, stats)| *stats)
+        }
+
+        fn has_index(&self, _table: &str, column: &str) -> bool {
+            column == "id"
+        }
+    }
+
+    fn tables(sizes: &[(&str, &[&str], u64)]) -> Tables {
+        let tables = sizes.iter().map(|(name, columns, rows)| {
+            let columns = columns
+                .iter()
+                .map(|name| {
+                    Column::new(
+                        name.to_string(),
+                        DataType::Integer,
+                        ConstraintsBuilder::new().nullable().build(),
+                    )
+                })
+                .collect();
+            let stats = TableStats {
+                rows: *rows,
+                pages: rows.div_ceil(100),
+            };
+            (name.to_string(), (Schema::try_new(columns).unwrap(), stats))
+        });
+        Tables(tables.collect())
+    }
+
+    fn planned(tables: &dyn SchemaProvider, source: &str) -> String {
+        let stmts = Parser::parse(source).unwrap();
+        plan(&stmts[0], tables).unwrap().to_string()
+    }
+
+    #[test]
+    fn join_strategies() {
+        // Few orders: their users are looked up in the index.
+        let small = tables(&[
+            ("users", &["id", "age"], 100_000),
+            ("orders", &["id", "user_id"], 10),
+        ]);
+        let source = "SELECT o.id, age FROM orders o JOIN users u ON o.user_id = u.id";
+        assert_eq!(
+            planned(&small, source),
+            "Project #0, #3\n\
+             \x20 IndexNestedLoopJoin Inner on (#1 = #2)\n\
+             \x20   Scan orders\n\
+             \x20   Scan users\n"
+        );
+
+        // Many orders: both sides are read once with a hash join, starting
+        // with the smallest one.
+        let large = tables(&[
+            ("users", &["id", "age"], 100_000),
+            ("orders", &["id", "user_id"], 1_000_000),
+        ]);
+        assert_eq!(
+            planned(&large, source),
+            "Project #2, #1\n\
+             \x20 HashJoin Inner on (#3 = #0)\n\
+             \x20   Scan users\n\
+             \x20   Scan orders\n"
+        );
+
+        // Without equality, both sides are compared tuple by tuple.
+        let source = "SELECT o.id FROM orders o JOIN users u ON o.user_id < u.id";
+        assert!(planned(&small, source).contains("NestedLoopJoin Inner on (#1 < #2)"));
+    }
+
+    #[test]
+    fn index_lookup_cost() {
+        /// Tables whose indexes have the given height.
+        struct Indexed(Tables, u64);
+
+        impl SchemaProvider for Indexed {
+            fn table_schema(&self, name: &str) -> Option<Schema> {
+                self.0.table_schema(name)
+            }
+
+            fn table_stats(&self, name: &str) -> Option<TableStats> {
+                self.0.table_stats(name)
+            }
+
+            fn has_index(&self, table: &str, column: &str) -> bool {
+                self.0.has_index(table, column)
+            }
+
+            fn index_stats(&self, _table: &str, _column: &str) -> Option<IndexStats> {
+                Some(IndexStats {
+                    keys: 100_000,
+                    pages: 1000,
+                    height: self.1,
+                })
+            }
+        }
+
+        let source = "SELECT o.id, age FROM orders o JOIN users u ON o.user_id = u.id";
+        let tables = || {
+            tables(&[
+                ("users", &["id", "age"], 100_000),
+                ("orders", &["id", "user_id"], 10),
+            ])
+        };
+        let shallow = Indexed(tables(), 2);
+        assert!(planned(&shallow, source).contains("IndexNestedLoopJoin"));
+        // Each lookup reads a page per level, more than a hash join reads.
+        let deep = Indexed(tables(), 1000);
+        assert!(planned(&deep, source).contains("HashJoin"));
+    }
+
+    #[test]
+    fn join_order() {
+        let tables = tables(&[
+            ("users", &["id", "country_id"], 10_000),
+            ("orders", &["id", "user_id"], 1_000_000),
+            ("countries", &["id", "name"], 10),
+        ]);
+
+        // The smallest table comes first, the tables are then joined along
+        // the join conditions.
+        assert_eq!(
+            planned(
+                &tables,
+                "SELECT o.id, c.name FROM orders o, users u, countries c \
+                 WHERE o.user_id = u.id AND u.country_id = c.id"
+            ),
+            "Project #4, #1\n\
+             \x20 HashJoin Inner on (#5 = #2)\n\
+             \x20   HashJoin Inner on (#3 = #0)\n\
+             \x20     Scan countries\n\
+             \x20     Scan users\n\
+             \x20   Scan orders\n"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use std::convert::Infallible;
+
+use crate::sql::exec::ScalarFunction;
+use crate::sql::plan::{LogicalPlan, PlanSchema};
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+
+/// An expression whose names are resolved: columns are positions in the
+/// input tuples and functions are looked up.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Column(usize),
+    Literal(Value),
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+    },
+    Function {
+        function: &'static ScalarFunction,
+        args: Vec<Expr>,
+    },
+    Subquery(Box<Subquery>),
+    /// A parameter of a correlated subquery, the value of one of its
+    /// arguments, see `Subquery`.
+    Param(usize),
+}
+
+/// A query nested in an expression. Its arguments are the columns of the
+/// enclosing queries it refers to: they are evaluated on the outer tuple and
+/// bound to the parameters of the plan, see `Expr::Param`.
+///
+/// A subquery without arguments is uncorrelated: it is run once, rather than
+/// for each outer tuple.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subquery {
+    pub kind: SubqueryKind,
+    pub plan: LogicalPlan,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubqueryKind {
+    /// The value of the single column of its single row, NULL without rows.
+    Scalar,
+    /// Whether the query returns rows.
+    Exists,
+    /// `expr [NOT] IN (query)`, the query returning a single column.
+    In { expr: Box<Expr>, negated: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Plus,
+    Minus,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+        )
+    }
+}
+
+impl std::fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Equal => "=",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Identity,
+    Negate,
+    Not,
+}
+
+impl std::fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            UnaryOp::Identity => "+",
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "NOT",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Expr {
+    /// Returns the type of the values of this expression, `None` if it is
+    /// only known at runtime (e.g. NULL).
+    pub fn data_type(&self, input: &PlanSchema) -> Option<DataType> {
+        match self {
+            Expr::Column(idx) => input.columns()[*idx].data_type,
+            Expr::Literal(value) => value.data_type(),
+            Expr::Binary { op, .. } if *op == BinaryOp::And || *op == BinaryOp::Or => {
+                Some(DataType::Boolean)
+            }
+            Expr::Binary { op, .. } if op.is_comparison() => Some(DataType::Boolean),
+            Expr::Binary { lhs, rhs, .. } => match (lhs.data_type(input)?, rhs.data_type(input)?) {
+                (DataType::Integer, DataType::Integer) => Some(DataType::Integer),
+                _ => Some(DataType::Float),
+            },
+            Expr::Unary {
+                op: UnaryOp::Not, ..
+            } => Some(DataType::Boolean),
+            Expr::Unary { expr, .. } => expr.data_type(input),
+            Expr::Cast { data_type, .. } => Some(*data_type),
+            Expr::InList { .. } | Expr::Between { .. } | Expr::Like { .. } => {
+                Some(DataType::Boolean)
+            }
+            Expr::Function { function, .. } => Some(function.return_type()),
+            Expr::Subquery(subquery) => match subquery.kind {
+                SubqueryKind::Scalar => subquery.plan.schema().columns()[0].data_type,
+                SubqueryKind::Exists | SubqueryKind::In { .. } => Some(DataType::Boolean),
+            },
+            Expr::Param(_) => None,
+        }
+    }
+
+    /// Whether this expression has a subquery.
+    pub fn contains_subquery(&self) -> bool {
+        match self {
+            Expr::Subquery(_) => true,
+            Expr::Column(_) | Expr::Literal(_) | Expr::Param(_) => false,
+            Expr::Binary { lhs, rhs, .. } => lhs.contains_subquery() || rhs.contains_subquery(),
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => expr.contains_subquery(),
+            Expr::InList { expr, list, .. } => {
+                expr.contains_subquery() || list.iter().any(Expr::contains_subquery)
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => expr.contains_subquery() || low.contains_subquery() || high.contains_subquery(),
+            Expr::Like { expr, pattern, .. } => {
+                expr.contains_subquery() || pattern.contains_subquery()
+            }
+            Expr::Function { args, .. } => args.iter().any(Expr::contains_subquery),
+        }
+    }
+
+    /// Calls `f` on every column this expression refers to.
+    pub fn for_each_column(&self, f: &mut impl FnMut(usize)) {
+        match self {
+            Expr::Column(idx) => f(*idx),
+            Expr::Literal(_) => {}
+            Expr::Binary { lhs, rhs, .. } => {
+                lhs.for_each_column(f);
+                rhs.for_each_column(f);
+            }
+            Expr::Unary { expr, .. } | Expr::Cast { expr, .. } => expr.for_each_column(f),
+            Expr::InList { expr, list, .. } => {
+                expr.for_each_column(f);
+                list.iter().for_each(|expr| expr.for_each_column(f));
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                expr.for_each_column(f);
+                low.for_each_column(f);
+                high.for_each_column(f);
+            }
+            Expr::Like { expr, pattern, .. } => {
+                expr.for_each_column(f);
+                pattern.for_each_column(f);
+            }
+            Expr::Function { args, .. } => args.iter().for_each(|arg| arg.for_each_column(f)),
+            // The plan of a subquery only refers to the outer columns through
+            // its arguments.
+            Expr::Subquery(subquery) => {
+                if let SubqueryKind::In { expr, .. } = &subquery.kind {
+                    expr.for_each_column(f);
+                }
+                subquery.args.iter().for_each(|arg| arg.for_each_column(f));
+            }
+            Expr::Param(_) => {}
+        }
+    }
+
+    /// Replaces every column of this expression with the expression returned
+    /// by `f`, e.g. to evaluate it on the input of a projection.
+    pub fn map_columns(self, f: &mut impl FnMut(usize) -> Expr) -> Expr {
+        let Ok(expr) = self.try_map(&mut |expr| {
+            Ok::<_, Infallible>(match expr {
+                Expr::Column(idx) => f(idx),
+                expr => expr,
+            })
+        });
+        expr
+    }
+
+    /// Replaces every parameter of this expression with the value it is bound
+    /// to, see `Subquery`.
+    pub fn bind_params(self, params: &[Value]) -> Expr {
+        let Ok(expr) = self.try_map(&mut |expr| {
+            Ok::<_, Infallible>(match expr {
+                Expr::Param(idx) => Expr::Literal(params[idx].clone()),
+                expr => expr,
+            })
+        });
+        expr
+    }
+
+    /// Rebuilds the expression bottom-up, replacing each node with the
+    /// expression returned by `f`, once its operands are rebuilt. The plans
+    /// of subqueries are left as is, but their arguments are rebuilt.
+    pub fn try_map<E>(self, f: &mut impl FnMut(Expr) -> Result<Expr, E>) -> Result<Expr, E> {
+        let mut map = |expr: Box<Expr>| expr.try_map(f).map(Box::new);
+        let expr = match self {
+            Expr::Column(_) | Expr::Literal(_) | Expr::Param(_) => self,
+            Expr::Binary { op, lhs, rhs } => Expr::Binary {
+                op,
+                lhs: map(lhs)?,
+                rhs: map(rhs)?,
+            },
+            Expr::Unary { op, expr } => Expr::Unary {
+                op,
+                expr: map(expr)?,
+            },
+            Expr::Cast { expr, data_type } => Expr::Cast {
+                expr: map(expr)?,
+                data_type,
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: map(expr)?,
+                list: list
+                    .into_iter()
+                    .map(|expr| expr.try_map(f))
+                    .collect::<Result<_, _>>()?,
+                negated,
+            },
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => Expr::Between {
+                expr: map(expr)?,
+                low: map(low)?,
+                high: map(high)?,
+                negated,
+            },
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+            } => Expr::Like {
+                expr: map(expr)?,
+                pattern: map(pattern)?,
+                negated,
+            },
+            Expr::Function { function, args } => Expr::Function {
+                function,
+                args: args
+                    .into_iter()
+                    .map(|arg| arg.try_map(f))
+                    .collect::<Result<_, _>>()?,
+            },
+            Expr::Subquery(subquery) => {
+                let Subquery { kind, plan, args } = *subquery;
+                let kind = match kind {
+                    SubqueryKind::In { expr, negated } => SubqueryKind::In {
+                        expr: map(expr)?,
+                        negated,
+                    },
+                    kind => kind,
+                };
+                let args = args
+                    .into_iter()
+                    .map(|arg| arg.try_map(f))
+                    .collect::<Result<_, _>>()?;
+                Expr::Subquery(Box::new(Subquery { kind, plan, args }))
+            }
+        };
+        f(expr)
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let not = |negated: &bool| if *negated { "NOT " } else { "" };
+        match self {
+            Expr::Column(idx) => write!(f, "#{idx}"),
+            Expr::Literal(Value::VarChar(s)) => write!(f, "'{s}'"),
+            Expr::Literal(Value::Boolean(b)) => write!(f, "{b}"),
+            Expr::Literal(Value::Integer(i)) => write!(f, "{i}"),
+            Expr::Literal(Value::Float(x)) => write!(f, "{x}"),
+            Expr::Literal(Value::Null) => write!(f, "NULL"),
+            Expr::Binary { op, lhs, rhs } => write!(f, "({lhs} {op} {rhs})"),
+            Expr::Unary { op, expr } => write!(f, "({op} {expr})"),
+            Expr::Cast { expr, data_type } => write!(f, "CAST({expr} AS {data_type})"),
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let list = list.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "({expr} {}IN ({}))", not(negated), list.join(", "))
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => write!(f, "({expr} {}BETWEEN {low} AND {high})", not(negated)),
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+            } => write!(f, "({expr} {}LIKE {pattern})", not(negated)),
+            Expr::Function { function, args } => {
+                let args = args.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", function.name(), args.join(", "))
+            }
+            Expr::Subquery(subquery) => match &subquery.kind {
+                SubqueryKind::Scalar => write!(f, "(subquery)"),
+                SubqueryKind::Exists => write!(f, "EXISTS(subquery)"),
+                SubqueryKind::In { expr, negated } => {
+                    write!(f, "({expr} {}IN (subquery))", not(negated))
+                }
+            },
+            Expr::Param(idx) => write!(f, "${}", idx + 1),
+        }
+    }
+}
@@ -0,0 +1,493 @@
+mod cost;
+mod expr;
+mod optimizer;
+mod planner;
+
+pub use cost::{Estimate, IndexStats, TableStats, equi_join_keys, estimate, plan_joins};
+pub use expr::{BinaryOp, Expr, Subquery, SubqueryKind, UnaryOp};
+pub use optimizer::{optimize, prune_columns, push_down_predicates};
+pub use planner::plan;
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::config::{ConfigError, Setting};
+use crate::sql::parser::ast::{AggregateFunction, JoinKind};
+use crate::sql::schema::{DataType, Schema};
+
+/// Gives the planner the schema of the tables a query reads.
+pub trait SchemaProvider {
+    fn table_schema(&self, name: &str) -> Option<Schema>;
+
+    /// The statistics of a table, `None` if they were never gathered.
+    fn table_stats(&self, _name: &str) -> Option<TableStats> {
+        None
+    }
+
+    /// Whether a column of a table has an index.
+    fn has_index(&self, _table: &str, _column: &str) -> bool {
+        false
+    }
+
+    /// The statistics of the index on a column of a table, `None` if they
+    /// were never gathered.
+    fn index_stats(&self, _table: &str, _column: &str) -> Option<IndexStats> {
+        None
+    }
+}
+
+impl SchemaProvider for HashMap<String, Schema> {
+    fn table_schema(&self, name: &str) -> Option<Schema> {
+        self.get(name).cloned()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PlanError {
+    #[error("table {0} does not exist")]
+    UnknownTable(String),
+    #[error("column {0} does not exist")]
+    UnknownColumn(String),
+    #[error("column reference {0} is ambiguous")]
+    AmbiguousColumn(String),
+    #[error("function {0} does not exist")]
+    UnknownFunction(String),
+    #[error("aggregate functions are not allowed here")]
+    AggregateNotAllowed,
+    #[error("column {0} must appear in the GROUP BY clause or be used in an aggregate function")]
+    NotGrouped(String),
+    #[error("INSERT has {expected} target columns but the query has {found}")]
+    InsertColumnCount { expected: usize, found: usize },
+    #[error("column {0} specified more than once")]
+    DuplicateColumn(String),
+    #[error("ORDER BY position {0} is not in select list")]
+    OrderByPosition(i64),
+    #[error("for SELECT DISTINCT, ORDER BY expressions must appear in select list")]
+    OrderByNotSelected,
+    #[error("argument of {0} must be a non-negative integer")]
+    InvalidRowCount(&'static str),
+    #[error("subquery must return only one column, not {0}")]
+    SubqueryColumnCount(usize),
+    #[error("{0} are not supported yet")]
+    Unsupported(&'static str),
+    #[error("setting error")]
+    Setting(#[from] ConfigError),
+}
+
+/// A column of the tuples produced by a plan node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanColumn {
+    // The table (or its alias) the column comes from, if any.
+    pub table: Option<String>,
+    pub name: String,
+    // `None` when the type is only known at runtime, e.g. `SELECT NULL`.
+    pub data_type: Option<DataType>,
+}
+
+/// The columns of the tuples produced by a plan node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlanSchema {
+    columns: Vec<PlanColumn>,
+}
+
+impl PlanSchema {
+    pub fn new(columns: Vec<PlanColumn>) -> Self {
+        Self { columns }
+    }
+
+    pub fn columns(&self) -> &[PlanColumn] {
+        &self.columns
+    }
+
+    /// Finds a column by name, qualified by a table or not.
+    pub fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize, PlanError> {
+        let display = || match table {
+            Some(table) => format!("{table}.{name}"),
+            None => name.to_string(),
+        };
+
+        let mut matches = self.columns.iter().enumerate().filter(|(_, column)| {
+            column.name == name && table.is_none_or(|table| column.table.as_deref() == Some(table))
+        });
+        match (matches.next(), matches.next()) {
+            (Some((idx, _)), None) => Ok(idx),
+            (Some(_), Some(_)) => Err(PlanError::AmbiguousColumn(display())),
+            (None, _) => Err(PlanError::UnknownColumn(display())),
+        }
+    }
+
+    /// Returns the columns of `self` followed by those of `other`.
+    fn join(&self, other: &PlanSchema) -> PlanSchema {
+        let columns = self.columns.iter().chain(&other.columns).cloned();
+        PlanSchema::new(columns.collect())
+    }
+}
+
+/// An aggregate computed by `LogicalPlan::Aggregate`, `arg` is `None` for
+/// COUNT(*).
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateExpr {
+    pub function: AggregateFunction,
+    /// Whether duplicate arguments are ignored, e.g. `COUNT(DISTINCT x)`.
+    pub distinct: bool,
+    pub arg: Option<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+/// How the executor runs a join, picked by the optimizer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JoinStrategy {
+    /// The right side is run again for each batch of left tuples.
+    #[default]
+    NestedLoop,
+    /// The right side is a table scan, its tuples are looked up in an index
+    /// on the join key.
+    IndexNestedLoop,
+    /// The right side is loaded in a hash table on the equi-join keys.
+    Hash,
+}
+
+/// A tree of logical operators. Expressions refer to the columns of the
+/// node's input: for a join, the left columns followed by the right ones.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogicalPlan {
+    /// A single row without columns, the input of a SELECT without FROM.
+    SingleRow,
+    Scan {
+        table: String,
+        schema: PlanSchema,
+        /// The columns read, in increasing order, `None` for all of them.
+        /// The other columns are NULL.
+        columns: Option<Vec<usize>>,
+    },
+    Filter {
+        input: Box<LogicalPlan>,
+        predicate: Expr,
+    },
+    Project {
+        input: Box<LogicalPlan>,
+        exprs: Vec<Expr>,
+        schema: PlanSchema,
+    },
+    Join {
+        kind: JoinKind,
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        // `None` for a cross join.
+        on: Option<Expr>,
+        schema: PlanSchema,
+        strategy: JoinStrategy,
+    },
+    /// Groups the input on `group_by`, outputs the group columns followed by
+    /// the aggregates. Also used for DISTINCT, with no aggregates.
+    Aggregate {
+        input: Box<LogicalPlan>,
+        group_by: Vec<Expr>,
+        aggregates: Vec<AggregateExpr>,
+        schema: PlanSchema,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        keys: Vec<SortKey>,
+    },
+    Limit {
+        input: Box<LogicalPlan>,
+        limit: Option<u64>,
+        offset: u64,
+    },
+    /// Inserts the tuples of `input` into `table`: input column `i` goes to
+    /// the table column `columns[i]`, the others are NULL. Outputs the number
+    /// of rows inserted.
+    Insert {
+        input: Box<LogicalPlan>,
+        table: String,
+        columns: Vec<usize>,
+        schema: PlanSchema,
+    },
+    /// Compacts the heap of `table` and updates its indexes. Outputs the
+    /// number of tuples moved and of pages freed.
+    Vacuum { table: String, schema: PlanSchema },
+    /// Applies a setting to the running database, outputs no rows.
+    SetGlobal { setting: Setting },
+}
+
+impl LogicalPlan {
+    /// Returns the columns of the tuples this node produces.
+    pub fn schema(&self) -> &PlanSchema {
+        static EMPTY: PlanSchema = PlanSchema {
+            columns: Vec::new(),
+        };
+
+        match self {
+            LogicalPlan::SingleRow | LogicalPlan::SetGlobal { .. } => &EMPTY,
+            LogicalPlan::Scan { schema, .. }
+            | LogicalPlan::Project { schema, .. }
+            | LogicalPlan::Join { schema, .. }
+            | LogicalPlan::Aggregate { schema, .. }
+            | LogicalPlan::Insert { schema, .. }
+            | LogicalPlan::Vacuum { schema, .. } => schema,
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => input.schema(),
+        }
+    }
+
+    /// Rebuilds the node with `f` applied to each of its inputs.
+    pub(crate) fn map_inputs(self, f: &mut impl FnMut(LogicalPlan) -> LogicalPlan) -> Self {
+        let mut map = |input: Box<LogicalPlan>| Box::new(f(*input));
+        match self {
+            plan @ (LogicalPlan::SingleRow
+            | LogicalPlan::Scan { .. }
+            | LogicalPlan::Vacuum { .. }
+            | LogicalPlan::SetGlobal { .. }) => plan,
+            LogicalPlan::Filter { input, predicate } => LogicalPlan::Filter {
+                input: map(input),
+                predicate,
+            },
+            LogicalPlan::Project {
+                input,
+                exprs,
+                schema,
+            } => LogicalPlan::Project {
+                input: map(input),
+                exprs,
+                schema,
+            },
+            LogicalPlan::Join {
+                kind,
+                left,
+                right,
+                on,
+                schema,
+                strategy,
+            } => LogicalPlan::Join {
+                kind,
+                left: map(left),
+                right: map(right),
+                on,
+                schema,
+                strategy,
+            },
+            LogicalPlan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+                schema,
+            } => LogicalPlan::Aggregate {
+                input: map(input),
+                group_by,
+                aggregates,
+                schema,
+            },
+            LogicalPlan::Sort { input, keys } => LogicalPlan::Sort {
+                input: map(input),
+                keys,
+            },
+            LogicalPlan::Limit {
+                input,
+                limit,
+                offset,
+            } => LogicalPlan::Limit {
+                input: map(input),
+                limit,
+                offset,
+            },
+            LogicalPlan::Insert {
+                input,
+                table,
+                columns,
+                schema,
+            } => LogicalPlan::Insert {
+                input: map(input),
+                table,
+                columns,
+                schema,
+            },
+        }
+    }
+
+    /// Rebuilds the plan with `f` applied to each expression of its nodes.
+    pub(crate) fn map_exprs(self, f: &mut impl FnMut(Expr) -> Expr) -> Self {
+        let plan = self.map_inputs(&mut |input| input.map_exprs(f));
+        match plan {
+            LogicalPlan::Filter { input, predicate } => LogicalPlan::Filter {
+                input,
+                predicate: f(predicate),
+            },
+            LogicalPlan::Project {
+                input,
+                exprs,
+                schema,
+            } => LogicalPlan::Project {
+                input,
+                exprs: exprs.into_iter().map(&mut *f).collect(),
+                schema,
+            },
+            LogicalPlan::Join {
+                kind,
+                left,
+                right,
+                on,
+                schema,
+                strategy,
+            } => LogicalPlan::Join {
+                kind,
+                left,
+                right,
+                on: on.map(&mut *f),
+                schema,
+                strategy,
+            },
+            LogicalPlan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+                schema,
+            } => LogicalPlan::Aggregate {
+                input,
+                group_by: group_by.into_iter().map(&mut *f).collect(),
+                aggregates: aggregates
+                    .into_iter()
+                    .map(|aggregate| AggregateExpr {
+                        arg: aggregate.arg.map(&mut *f),
+                        ..aggregate
+                    })
+                    .collect(),
+                schema,
+            },
+            LogicalPlan::Sort { input, keys } => LogicalPlan::Sort {
+                input,
+                keys: keys
+                    .into_iter()
+                    .map(|key| SortKey {
+                        expr: f(key.expr),
+                        ..key
+                    })
+                    .collect(),
+            },
+            plan => plan,
+        }
+    }
+
+    /// Writes the node alone, without its inputs, on a single line.
+    pub(crate) fn fmt_node(&self, f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        let list = |exprs: &[Expr]| {
+            let exprs = exprs.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            exprs.join(", ")
+        };
+
+        match self {
+            LogicalPlan::SingleRow => write!(f, "SingleRow"),
+            LogicalPlan::Scan {
+                table,
+                columns: None,
+                ..
+            } => write!(f, "Scan {table}"),
+            LogicalPlan::Scan {
+                table,
+                columns: Some(columns),
+                ..
+            } => {
+                let columns = columns.iter().map(|idx| format!("#{idx}"));
+                write!(
+                    f,
+                    "Scan {table} [{}]",
+                    columns.collect::<Vec<_>>().join(", ")
+                )
+            }
+            LogicalPlan::Filter { predicate, .. } => write!(f, "Filter {predicate}"),
+            LogicalPlan::Project { exprs, .. } => write!(f, "Project {}", list(exprs)),
+            LogicalPlan::Join {
+                kind, on, strategy, ..
+            } => match on {
+                Some(on) => write!(f, "{strategy:?}Join {kind:?} on {on}"),
+                None => write!(f, "{strategy:?}Join {kind:?}"),
+            },
+            LogicalPlan::Aggregate {
+                group_by,
+                aggregates,
+                ..
+            } => {
+                let aggregates = aggregates
+                    .iter()
+                    .map(|aggregate| match &aggregate.arg {
+                        Some(arg) if aggregate.distinct => {
+                            format!("{:?}(DISTINCT {arg})", aggregate.function)
+                        }
+                        Some(arg) => format!("{:?}({arg})", aggregate.function),
+                        None => format!("{:?}(*)", aggregate.function),
+                    })
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "Aggregate [{}] [{}]",
+                    list(group_by),
+                    aggregates.join(", ")
+                )
+            }
+            LogicalPlan::Sort { keys, .. } => {
+                let keys = keys
+                    .iter()
+                    .map(|key| {
+                        let order = if key.descending { "DESC" } else { "ASC" };
+                        format!("{} {order}", key.expr)
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "Sort {}", keys.join(", "))
+            }
+            LogicalPlan::Limit { limit, offset, .. } => match limit {
+                Some(limit) => write!(f, "Limit {limit} offset {offset}"),
+                None => write!(f, "Limit ALL offset {offset}"),
+            },
+            LogicalPlan::Insert { table, columns, .. } => {
+                let columns = columns.iter().map(|idx| format!("#{idx}"));
+                write!(
+                    f,
+                    "Insert {table} [{}]",
+                    columns.collect::<Vec<_>>().join(", ")
+                )
+            }
+            LogicalPlan::Vacuum { table, .. } => write!(f, "Vacuum {table}"),
+            LogicalPlan::SetGlobal { setting } => write!(f, "SetGlobal {setting:?}"),
+        }
+    }
+
+    /// The inputs of the node, left to right.
+    pub(crate) fn inputs(&self) -> Vec<&LogicalPlan> {
+        match self {
+            LogicalPlan::SingleRow
+            | LogicalPlan::Scan { .. }
+            | LogicalPlan::Vacuum { .. }
+            | LogicalPlan::SetGlobal { .. } => Vec::new(),
+            LogicalPlan::Join { left, right, .. } => vec![left, right],
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Project { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. }
+            | LogicalPlan::Insert { input, .. } => vec![input],
+        }
+    }
+
+    fn fmt_indent(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{:width$}", "", width = depth * 2)?;
+        self.fmt_node(f)?;
+        writeln!(f)?;
+        self.inputs()
+            .into_iter()
+            .try_for_each(|input| input.fmt_indent(f, depth + 1))
+    }
+}
+
+/// One node per line, children are indented below their parent.
+impl std::fmt::Display for LogicalPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
@@ -0,0 +1,474 @@
+use crate::sql::parser::ast::JoinKind;
+use crate::sql::plan::{BinaryOp, Expr, LogicalPlan, SchemaProvider, plan_joins};
+
+/// Rewrites a plan into an equivalent one that is cheaper to run.
+pub fn optimize(plan: LogicalPlan, tables: &dyn SchemaProvider) -> LogicalPlan {
+    prune_columns(plan_joins(push_down_predicates(plan), tables))
+}
+
+/// Moves filters as close as possible to the scans, so that tuples are
+/// dropped before being joined, grouped or projected:
+/// - filters are pushed below projections, and below aggregations for the
+///   conditions on the group columns,
+/// - the conditions of a filter over a join, and of the join itself, on the
+///   columns of one side only are pushed to that side. A cross join with
+///   conditions on both sides becomes an inner join, but for the conditions
+///   with subqueries, which joins don't run.
+///
+/// A filter ending up right above a scan lets the executor read the table
+/// through an index.
+pub fn push_down_predicates(plan: LogicalPlan) -> LogicalPlan {
+    push_down(plan, Vec::new())
+}
+
+/// Splits a predicate on its ANDs.
+pub(super) fn split_conjuncts(predicate: Expr, conjuncts: &mut Vec<Expr>) {
+    match predicate {
+        Expr::Binary {
+            op: BinaryOp::And,
+            lhs,
+            rhs,
+        } => {
+            split_conjuncts(*lhs, conjuncts);
+            split_conjuncts(*rhs, conjuncts);
+        }
+        predicate => conjuncts.push(predicate),
+    }
+}
+
+pub(super) fn conjunction(conjuncts: Vec<Expr>) -> Option<Expr> {
+    conjuncts.into_iter().reduce(|lhs, rhs| Expr::Binary {
+        op: BinaryOp::And,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    })
+}
+
+pub(super) fn filter(input: LogicalPlan, conjuncts: Vec<Expr>) -> LogicalPlan {
+    match conjuncts.is_empty() {
+        true => input,
+        false => LogicalPlan::Filter {
+            input: Box::new(input),
+            predicate: conjunction(conjuncts).unwrap(),
+        },
+    }
+}
+
+/// Returns whether all the columns of an expression satisfy `f`.
+fn all_columns(expr: &Expr, f: impl Fn(usize) -> bool) -> bool {
+    let mut all = true;
+    expr.for_each_column(&mut |idx| all &= f(idx));
+    all
+}
+
+/// Returns `plan` filtered by `conjuncts`, with the conjuncts and the filters
+/// of `plan` pushed down.
+fn push_down(plan: LogicalPlan, mut conjuncts: Vec<Expr>) -> LogicalPlan {
+    match plan {
+        LogicalPlan::Filter { input, predicate } => {
+            split_conjuncts(predicate, &mut conjuncts);
+            push_down(*input, conjuncts)
+        }
+        LogicalPlan::Project {
+            input,
+            exprs,
+            schema,
+        } => {
+            let conjuncts = conjuncts
+                .into_iter()
+                .map(|conjunct| conjunct.map_columns(&mut |idx| exprs[idx].clone()))
+                .collect();
+            LogicalPlan::Project {
+                input: Box::new(push_down(*input, conjuncts)),
+                exprs,
+                schema,
+            }
+        }
+        LogicalPlan::Join {
+            kind,
+            left,
+            right,
+            on,
+            schema,
+            strategy,
+        } => {
+            let left_width = left.schema().columns().len();
+            let (mut to_left, mut to_right, mut kept_on, mut above) =
+                (vec![], vec![], vec![], vec![]);
+            let mut on_conjuncts = Vec::new();
+            if let Some(on) = on {
+                split_conjuncts(on, &mut on_conjuncts);
+            }
+
+            let shift =
+                |conjunct: Expr| conjunct.map_columns(&mut |idx| Expr::Column(idx - left_width));
+            let is_left = |conjunct: &Expr| all_columns(conjunct, |idx| idx < left_width);
+            let is_right = |conjunct: &Expr| all_columns(conjunct, |idx| idx >= left_width);
+            match kind {
+                JoinKind::Inner | JoinKind::Cross => {
+                    for conjunct in on_conjuncts.into_iter().chain(conjuncts) {
+                        if is_left(&conjunct) {
+                            to_left.push(conjunct);
+                        } else if is_right(&conjunct) {
+                            to_right.push(shift(conjunct));
+                        } else if conjunct.contains_subquery() {
+                            above.push(conjunct);
+                        } else {
+                            kept_on.push(conjunct);
+                        }
+                    }
+                }
+                // Tuples of the left side without a match are kept whatever
+                // the ON condition: only its conditions on the right side can
+                // be pushed, and only the WHERE conditions on the left side.
+                JoinKind::Left => {
+                    for conjunct in conjuncts {
+                        match is_left(&conjunct) {
+                            true => to_left.push(conjunct),
+                            false => above.push(conjunct),
+                        }
+                    }
+                    for conjunct in on_conjuncts {
+                        match is_right(&conjunct) && !is_left(&conjunct) {
+                            true => to_right.push(shift(conjunct)),
+                            false => kept_on.push(conjunct),
+                        }
+                    }
+                }
+            }
+
+            let kind = match (kind, kept_on.is_empty()) {
+                (JoinKind::Cross, false) => JoinKind::Inner,
+                (JoinKind::Inner, true) => JoinKind::Cross,
+                (kind, _) => kind,
+            };
+            let join = LogicalPlan::Join {
+                kind,
+                left: Box::new(push_down(*left, to_left)),
+                right: Box::new(push_down(*right, to_right)),
+                on: conjunction(kept_on),
+                schema,
+                strategy,
+            };
+            filter(join, above)
+        }
+        LogicalPlan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+            schema,
+        } => {
+            // Without GROUP BY, an empty input still gives a row: the
+            // conditions can't be checked before aggregating.
+            let (below, above) = conjuncts.into_iter().partition::<Vec<_>, _>(|conjunct| {
+                !group_by.is_empty() && all_columns(conjunct, |idx| idx < group_by.len())
+            });
+            let below = below
+                .into_iter()
+                .map(|conjunct| conjunct.map_columns(&mut |idx| group_by[idx].clone()))
+                .collect();
+            let aggregate = LogicalPlan::Aggregate {
+                input: Box::new(push_down(*input, below)),
+                group_by,
+                aggregates,
+                schema,
+            };
+            filter(aggregate, above)
+        }
+        LogicalPlan::Sort { input, keys } => LogicalPlan::Sort {
+            input: Box::new(push_down(*input, conjuncts)),
+            keys,
+        },
+        // Filtering before a limit changes the tuples that are kept.
+        LogicalPlan::Limit {
+            input,
+            limit,
+            offset,
+        } => {
+            let limit = LogicalPlan::Limit {
+                input: Box::new(push_down(*input, Vec::new())),
+                limit,
+                offset,
+            };
+            filter(limit, conjuncts)
+        }
+        plan @ LogicalPlan::Insert { .. } => {
+            let plan = plan.map_inputs(&mut |input| push_down(input, Vec::new()));
+            filter(plan, conjuncts)
+        }
+        plan @ (LogicalPlan::SingleRow
+        | LogicalPlan::Scan { .. }
+        | LogicalPlan::Vacuum { .. }
+        | LogicalPlan::SetGlobal { .. }) => filter(plan, conjuncts),
+    }
+}
+
+/// Restricts the scans to the columns the operators above them use: the
+/// other columns are not decoded, which saves allocating strings.
+///
+/// Columns keep their positions, the pruned ones are NULL.
+pub fn prune_columns(plan: LogicalPlan) -> LogicalPlan {
+    let used = vec![true; plan.schema().columns().len()];
+    prune(plan, used)
+}
+
+fn mark_columns(expr: &Expr, used: &mut [bool]) {
+    expr.for_each_column(&mut |idx| used[idx] = true);
+}
+
+/// Merges the projections below `exprs` that only move columns around, such
+/// as the ones put over reordered joins, so that their unused columns can be
+/// pruned.
+fn merge_projections(input: LogicalPlan, exprs: Vec<Expr>) -> (LogicalPlan, Vec<Expr>) {
+    match input {
+        LogicalPlan::Project {
+            input,
+            exprs: columns,
+            ..
+        } if columns.iter().all(|expr| matches!(expr, Expr::Column(_))) => {
+            let exprs = exprs
+                .into_iter()
+                .map(|expr| expr.map_columns(&mut |idx| columns[idx].clone()))
+                .collect();
+            merge_projections(*input, exprs)
+        }
+        input => (input, exprs),
+    }
+}
+
+/// Prunes the columns of the scans of `plan` that are neither `used` in its
+/// output nor by the operators of `plan`.
+fn prune(plan: LogicalPlan, mut used: Vec<bool>) -> LogicalPlan {
+    match plan {
+        plan @ (LogicalPlan::SingleRow
+        | LogicalPlan::Vacuum { .. }
+        | LogicalPlan::SetGlobal { .. }) => plan,
+        LogicalPlan::Scan { table, schema, .. } => {
+            let columns = match used.iter().all(|&used| used) {
+                true => None,
+                false => Some((0..used.len()).filter(|&idx| used[idx]).collect()),
+            };
+            LogicalPlan::Scan {
+                table,
+                schema,
+                columns,
+            }
+        }
+        LogicalPlan::Filter { input, predicate } => {
+            mark_columns(&predicate, &mut used);
+            LogicalPlan::Filter {
+                input: Box::new(prune(*input, used)),
+                predicate,
+            }
+        }
+        // Expressions are all evaluated: an unused one can still fail.
+        LogicalPlan::Project {
+            input,
+            exprs,
+            schema,
+        } => {
+            let (input, exprs) = merge_projections(*input, exprs);
+            let mut used = vec![false; input.schema().columns().len()];
+            exprs.iter().for_each(|expr| mark_columns(expr, &mut used));
+            LogicalPlan::Project {
+                input: Box::new(prune(input, used)),
+                exprs,
+                schema,
+            }
+        }
+        LogicalPlan::Join {
+            kind,
+            left,
+            right,
+            on,
+            schema,
+            strategy,
+        } => {
+            if let Some(on) = &on {
+                mark_columns(on, &mut used);
+            }
+            let right_used = used.split_off(left.schema().columns().len());
+            LogicalPlan::Join {
+                kind,
+                left: Box::new(prune(*left, used)),
+                right: Box::new(prune(*right, right_used)),
+                on,
+                schema,
+                strategy,
+            }
+        }
+        LogicalPlan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+            schema,
+        } => {
+            let mut used = vec![false; input.schema().columns().len()];
+            let args = aggregates
+                .iter()
+                .filter_map(|aggregate| aggregate.arg.as_ref());
+            group_by
+                .iter()
+                .chain(args)
+                .for_each(|expr| mark_columns(expr, &mut used));
+            LogicalPlan::Aggregate {
+                input: Box::new(prune(*input, used)),
+                group_by,
+                aggregates,
+                schema,
+            }
+        }
+        LogicalPlan::Sort { input, keys } => {
+            keys.iter()
+                .for_each(|key| mark_columns(&key.expr, &mut used));
+            LogicalPlan::Sort {
+                input: Box::new(prune(*input, used)),
+                keys,
+            }
+        }
+        LogicalPlan::Limit {
+            input,
+            limit,
+            offset,
+        } => LogicalPlan::Limit {
+            input: Box::new(prune(*input, used)),
+            limit,
+            offset,
+        },
+        // All the columns are inserted.
+        plan @ LogicalPlan::Insert { .. } => plan.map_inputs(&mut |input| {
+            let used = vec![true; input.schema().columns().len()];
+            prune(input, used)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::plan::plan;
+    use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
+
+    fn optimized(source: &str) -> String {
+        let schema = |columns: &[&str]| {
+            let columns = columns
+                .iter()
+                .map(|name| {
+                    Column::new(
+                        name.to_string(),
+                        DataType::Integer,
+                        ConstraintsBuilder::new().nullable().build(),
+                    )
+                })
+                .collect();
+            Schema::try_new(columns).unwrap()
+        };
+        let tables = HashMap::from([
+            ("users".to_string(), schema(&["id", "age"])),
+            ("orders".to_string(), schema(&["id", "user_id", "amount"])),
+        ]);
+
+        let stmts = Parser::parse(source).unwrap();
+        plan(&stmts[0], &tables).unwrap().to_string()
+    }
+
+    #[test]
+    fn push_down_into_joins() {
+        assert_eq!(
+            optimized(
+                "SELECT u.id FROM users u, orders o \
+                 WHERE u.id = o.user_id AND u.age > 18 AND o.amount < 10"
+            ),
+            "Project #0\n\
+             \x20 HashJoin Inner on (#0 = #3)\n\
+             \x20   Filter (#1 > 18)\n\
+             \x20     Scan users\n\
+             \x20   Filter (#2 < 10)\n\
+             \x20     Scan orders [#1, #2]\n"
+        );
+        assert_eq!(
+            optimized(
+                "SELECT u.id FROM users u JOIN orders o ON u.id = o.user_id AND u.age > 18 \
+                 WHERE o.amount < 10 OR u.age < 5"
+            ),
+            "Project #0\n\
+             \x20 HashJoin Inner on ((#0 = #3) AND ((#4 < 10) OR (#1 < 5)))\n\
+             \x20   Filter (#1 > 18)\n\
+             \x20     Scan users\n\
+             \x20   Scan orders [#1, #2]\n"
+        );
+    }
+
+    #[test]
+    fn push_down_left_joins() {
+        assert_eq!(
+            optimized(
+                "SELECT u.id FROM users u LEFT JOIN orders o \
+                 ON u.id = o.user_id AND u.age > 18 AND o.amount < 10 \
+                 WHERE u.id > 3 AND o.amount > 1"
+            ),
+            "Project #0\n\
+             \x20 Filter (#4 > 1)\n\
+             \x20   HashJoin Left on ((#0 = #3) AND (#1 > 18))\n\
+             \x20     Filter (#0 > 3)\n\
+             \x20       Scan users\n\
+             \x20     Filter (#2 < 10)\n\
+             \x20       Scan orders [#1, #2]\n"
+        );
+    }
+
+    #[test]
+    fn push_down_aggregates() {
+        assert_eq!(
+            optimized(
+                "SELECT age, COUNT(*) FROM users GROUP BY age \
+                 HAVING age > 18 AND COUNT(*) > 1"
+            ),
+            "Project #0, #1\n\
+             \x20 Filter (#1 > 1)\n\
+             \x20   Aggregate [#1] [Count(*)]\n\
+             \x20     Filter (#1 > 18)\n\
+             \x20       Scan users [#1]\n"
+        );
+        // Without GROUP BY, the condition is checked on the single group.
+        assert_eq!(
+            optimized("SELECT COUNT(*) FROM users HAVING 1 = 0"),
+            "Project #0\n\
+             \x20 Filter (1 = 0)\n\
+             \x20   Aggregate [] [Count(*)]\n\
+             \x20     Scan users []\n"
+        );
+    }
+
+    #[test]
+    fn prune() {
+        assert_eq!(
+            optimized("SELECT * FROM users u JOIN orders o ON u.id = o.user_id"),
+            "Project #0, #1, #2, #3, #4\n\
+             \x20 HashJoin Inner on (#0 = #3)\n\
+             \x20   Scan users\n\
+             \x20   Scan orders\n"
+        );
+        // The filtered orders are smaller and joined first: the projection
+        // restoring the order of the columns is merged into the one above.
+        assert_eq!(
+            optimized(
+                "SELECT o.id FROM users u JOIN orders o ON u.id = o.user_id \
+                 WHERE o.amount > 1"
+            ),
+            "Project #0\n\
+             \x20 HashJoin Inner on (#3 = #1)\n\
+             \x20   Filter (#2 > 1)\n\
+             \x20     Scan orders\n\
+             \x20   Scan users [#0]\n"
+        );
+        assert_eq!(
+            optimized("SELECT SUM(amount) FROM orders GROUP BY user_id"),
+            "Project #1\n\
+             \x20 Aggregate [#1] [Sum(#2)]\n\
+             \x20   Scan orders [#1, #2]\n"
+        );
+    }
+}
@@ -0,0 +1,1031 @@
+use std::cell::RefCell;
+
+use crate::config::{ConfigError, Setting};
+use crate::sql::exec::BUILTIN_FUNCTIONS;
+use crate::sql::parser::ast::{
+    self, AggregateFunction, ExprKind, JoinKind, Literal, Operator, Stmt,
+};
+use crate::sql::plan::{
+    AggregateExpr, BinaryOp, Expr, JoinStrategy, LogicalPlan, PlanColumn, PlanError, PlanSchema,
+    SchemaProvider, SortKey, Subquery, SubqueryKind, UnaryOp, optimize,
+};
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+
+/// Turns a statement into a tree of logical operators, resolving table and
+/// column names.
+///
+/// A SELECT is planned bottom-up: the FROM clause (comma separated items are
+/// cross joined), WHERE, GROUP BY and aggregates, HAVING, the select list and
+/// DISTINCT, planned as a grouping on all the output columns, ORDER BY, then
+/// LIMIT and OFFSET. The sort is put under the projection, see
+/// `bind_order_by`, but for DISTINCT, which sorts its output columns. The plan
+/// is then optimized.
+///
+/// Subqueries of WHERE, HAVING, the select list and ORDER BY are planned and
+/// optimized on their own. Their columns are resolved in the subquery first,
+/// then in the enclosing queries, innermost first: a subquery referring to
+/// an enclosing query is correlated, see `Subquery`.
+///
+/// The query of an INSERT is planned as a SELECT, its columns are matched by
+/// position with the target columns: the listed ones, or all the columns of
+/// the table. A VACUUM only checks that its table exists. The value of a SET
+/// GLOBAL is a literal, checked against its parameter, see `Setting::parse`.
+pub fn plan(stmt: &Stmt, tables: &dyn SchemaProvider) -> Result<LogicalPlan, PlanError> {
+    match stmt {
+        Stmt::Select { .. } => Ok(optimize(plan_select(stmt, tables, None)?, tables)),
+        Stmt::Insert {
+            table,
+            columns,
+            source,
+        } => {
+            let schema = tables
+                .table_schema(table)
+                .ok_or_else(|| PlanError::UnknownTable(table.to_string()))?;
+            let input = plan(source, tables)?;
+
+            let columns = match columns.is_empty() {
+                true => (0..schema.num_columns()).collect(),
+                false => {
+                    let mut positions = Vec::with_capacity(columns.len());
+                    for name in columns {
+                        let idx = schema
+                            .columns()
+                            .iter()
+                            .position(|column| column.column_name == *name)
+                            .ok_or_else(|| PlanError::UnknownColumn(name.to_string()))?;
+                        if positions.contains(&idx) {
+                            return Err(PlanError::DuplicateColumn(name.to_string()));
+                        }
+                        positions.push(idx);
+                    }
+                    positions
+                }
+            };
+            let found = input.schema().columns().len();
+            if columns.len() != found {
+                return Err(PlanError::InsertColumnCount {
+                    expected: columns.len(),
+                    found,
+                });
+            }
+
+            Ok(LogicalPlan::Insert {
+                input: Box::new(input),
+                table: table.to_string(),
+                columns,
+                schema: PlanSchema::new(vec![PlanColumn {
+                    table: None,
+                    name: "rows".to_string(),
+                    data_type: Some(DataType::Integer),
+                }]),
+            })
+        }
+        Stmt::Vacuum { table } => {
+            if tables.table_schema(table).is_none() {
+                return Err(PlanError::UnknownTable(table.to_string()));
+            }
+            let columns = ["relocated", "pages_freed"].map(|name| PlanColumn {
+                table: None,
+                name: name.to_string(),
+                data_type: Some(DataType::Integer),
+            });
+            Ok(LogicalPlan::Vacuum {
+                table: table.to_string(),
+                schema: PlanSchema::new(columns.to_vec()),
+            })
+        }
+        Stmt::SetGlobal { name, value } => {
+            let value = match &value.kind {
+                ExprKind::Literal(Literal::Integer(i)) => i.to_string(),
+                ExprKind::Literal(Literal::String(s) | Literal::Ident(s)) => s.to_string(),
+                _ => {
+                    return Err(PlanError::Setting(ConfigError::InvalidValue {
+                        name: name.to_string(),
+                        value: format!("{:?}", value.kind),
+                    }));
+                }
+            };
+            Ok(LogicalPlan::SetGlobal {
+                setting: Setting::parse(name, &value)?,
+            })
+        }
+        _ => Err(PlanError::Unsupported("statements other than SELECT")),
+    }
+}
+
+/// Plans a SELECT, unoptimized. `outer` is the scope of the enclosing query
+/// of a subquery, see `Binder::subquery`.
+fn plan_select(
+    stmt: &Stmt,
+    tables: &dyn SchemaProvider,
+    outer: Option<&OuterScope>,
+) -> Result<LogicalPlan, PlanError> {
+    let Stmt::Select {
+        distinct,
+        columns,
+        from,
+        r#where,
+        group_by,
+        having,
+        order_by,
+        limit,
+        offset,
+    } = stmt
+    else {
+        return Err(PlanError::Unsupported("statements other than SELECT"));
+    };
+    let mut plan = match from {
+        Some(from) => {
+            let mut items = from.iter().map(|item| plan_from(item, tables));
+            // The parser rejects an empty FROM clause.
+            let first = items.next().unwrap()?;
+            items.try_fold(first, |left, right| {
+                Ok::<_, PlanError>(join(JoinKind::Cross, left, right?, None))
+            })?
+        }
+        None => LogicalPlan::SingleRow,
+    };
+
+    if let Some(predicate) = r#where {
+        let predicate = Binder::with_context(plan.schema(), tables, outer).bind(predicate)?;
+        plan = LogicalPlan::Filter {
+            input: Box::new(plan),
+            predicate,
+        };
+    }
+
+    let is_aggregate =
+        !group_by.is_empty() || having.is_some() || columns.iter().any(contains_aggregate);
+    let (exprs, schema, keys) = if is_aggregate {
+        let group_by = group_by
+            .iter()
+            .map(|expr| Binder::new(plan.schema()).bind(expr))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut binder = Binder::with_context(plan.schema(), tables, outer);
+        binder.aggregate = Some(AggregateScope {
+            group_by: &group_by,
+            aggregates: Vec::new(),
+        });
+        let (exprs, schema) = binder.bind_select_list(columns)?;
+        let having = having
+            .as_ref()
+            .map(|having| binder.bind(having))
+            .transpose()?;
+        let keys = binder.bind_order_by(order_by, &exprs)?;
+        let aggregates = binder.aggregate.take().unwrap().aggregates;
+
+        let columns = group_by
+            .iter()
+            .map(|expr| match expr {
+                Expr::Column(idx) => plan.schema().columns()[*idx].clone(),
+                expr => PlanColumn {
+                    table: None,
+                    name: "?column?".to_string(),
+                    data_type: expr.data_type(plan.schema()),
+                },
+            })
+            .chain(aggregates.iter().map(|aggregate| PlanColumn {
+                table: None,
+                name: format!("{:?}", aggregate.function).to_lowercase(),
+                data_type: aggregate_type(aggregate, plan.schema()),
+            }))
+            .collect();
+        plan = LogicalPlan::Aggregate {
+            input: Box::new(plan),
+            group_by,
+            aggregates,
+            schema: PlanSchema::new(columns),
+        };
+
+        if let Some(predicate) = having {
+            plan = LogicalPlan::Filter {
+                input: Box::new(plan),
+                predicate,
+            };
+        }
+
+        (exprs, schema, keys)
+    } else {
+        let mut binder = Binder::with_context(plan.schema(), tables, outer);
+        let (exprs, schema) = binder.bind_select_list(columns)?;
+        let keys = binder.bind_order_by(order_by, &exprs)?;
+        (exprs, schema, keys)
+    };
+
+    // DISTINCT sorts its output: the keys must be output columns.
+    let distinct_keys = match distinct {
+        true => keys
+            .into_iter()
+            .map(
+                |key| match exprs.iter().position(|expr| *expr == key.expr) {
+                    Some(idx) => Ok(SortKey {
+                        expr: Expr::Column(idx),
+                        descending: key.descending,
+                    }),
+                    None => Err(PlanError::OrderByNotSelected),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?,
+        false => {
+            if !keys.is_empty() {
+                plan = LogicalPlan::Sort {
+                    input: Box::new(plan),
+                    keys,
+                };
+            }
+            Vec::new()
+        }
+    };
+
+    let mut plan = LogicalPlan::Project {
+        input: Box::new(plan),
+        exprs,
+        schema,
+    };
+
+    if *distinct {
+        let schema = plan.schema().clone();
+        plan = LogicalPlan::Aggregate {
+            input: Box::new(plan),
+            group_by: (0..schema.columns().len()).map(Expr::Column).collect(),
+            aggregates: Vec::new(),
+            schema,
+        };
+        if !distinct_keys.is_empty() {
+            plan = LogicalPlan::Sort {
+                input: Box::new(plan),
+                keys: distinct_keys,
+            };
+        }
+    }
+
+    if limit.is_some() || offset.is_some() {
+        plan = LogicalPlan::Limit {
+            input: Box::new(plan),
+            limit: limit
+                .as_ref()
+                .map(|limit| row_count(limit, "LIMIT"))
+                .transpose()?,
+            offset: offset
+                .as_ref()
+                .map(|offset| row_count(offset, "OFFSET"))
+                .transpose()?
+                .unwrap_or(0),
+        };
+    }
+
+    Ok(plan)
+}
+
+fn plan_from(from: &ast::From, tables: &dyn SchemaProvider) -> Result<LogicalPlan, PlanError> {
+    match from {
+        ast::From::Table {
+            database,
+            name,
+            alias,
+        } => {
+            // Tables of attached databases are looked up by qualified name,
+            // their columns are still qualified by the table name alone.
+            let qualified_name = match database {
+                Some(database) => format!("{database}.{name}"),
+                None => name.to_string(),
+            };
+            let schema = tables
+                .table_schema(&qualified_name)
+                .ok_or_else(|| PlanError::UnknownTable(qualified_name.clone()))?;
+            let table = alias.as_ref().unwrap_or(name);
+            let columns = schema
+                .columns()
+                .iter()
+                .map(|column| PlanColumn {
+                    table: Some(table.to_string()),
+                    name: column.column_name.clone(),
+                    data_type: Some(column.data_type),
+                })
+                .collect();
+
+            Ok(LogicalPlan::Scan {
+                table: qualified_name,
+                schema: PlanSchema::new(columns),
+                columns: None,
+            })
+        }
+        ast::From::Join {
+            kind,
+            left,
+            right,
+            on,
+        } => {
+            let left = plan_from(left, tables)?;
+            let right = plan_from(right, tables)?;
+            let schema = left.schema().join(right.schema());
+            let on = on
+                .as_ref()
+                .map(|on| Binder::new(&schema).bind(on))
+                .transpose()?;
+
+            Ok(join(*kind, left, right, on))
+        }
+    }
+}
+
+fn join(kind: JoinKind, left: LogicalPlan, right: LogicalPlan, on: Option<Expr>) -> LogicalPlan {
+    let schema = left.schema().join(right.schema());
+    LogicalPlan::Join {
+        kind,
+        left: Box::new(left),
+        right: Box::new(right),
+        on,
+        schema,
+        strategy: JoinStrategy::default(),
+    }
+}
+
+/// The number of rows of a LIMIT or an OFFSET clause, a non-negative INTEGER
+/// literal.
+fn row_count(expr: &ast::Expr, clause: &'static str) -> Result<u64, PlanError> {
+    match expr.kind {
+        ExprKind::Literal(Literal::Integer(count)) if count >= 0 => Ok(count as u64),
+        _ => Err(PlanError::InvalidRowCount(clause)),
+    }
+}
+
+fn contains_aggregate(expr: &ast::Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Aggregate { .. } => true,
+        ExprKind::All
+        | ExprKind::Column { .. }
+        | ExprKind::Literal(_)
+        | ExprKind::Subquery(_)
+        | ExprKind::Exists(_)
+        | ExprKind::InSubquery { .. } => false,
+        ExprKind::Operator(operator) => match operator {
+            Operator::Plus(lhs, rhs)
+            | Operator::Minus(lhs, rhs)
+            | Operator::Mul(lhs, rhs)
+            | Operator::Div(lhs, rhs)
+            | Operator::Equal(lhs, rhs)
+            | Operator::NotEqual(lhs, rhs)
+            | Operator::Less(lhs, rhs)
+            | Operator::LessEqual(lhs, rhs)
+            | Operator::Greater(lhs, rhs)
+            | Operator::GreaterEqual(lhs, rhs)
+            | Operator::And(lhs, rhs)
+            | Operator::Or(lhs, rhs) => contains_aggregate(lhs) || contains_aggregate(rhs),
+            Operator::Identity(expr) | Operator::Negate(expr) | Operator::Not(expr) => {
+                contains_aggregate(expr)
+            }
+        },
+        ExprKind::Function { args, .. } => args.iter().any(contains_aggregate),
+        ExprKind::InList { expr, list, .. } => {
+            contains_aggregate(expr) || list.iter().any(contains_aggregate)
+        }
+        ExprKind::Between {
+            expr, low, high, ..
+        } => contains_aggregate(expr) || contains_aggregate(low) || contains_aggregate(high),
+        ExprKind::Like { expr, pattern, .. } => {
+            contains_aggregate(expr) || contains_aggregate(pattern)
+        }
+        ExprKind::Cast { expr, .. } => contains_aggregate(expr),
+    }
+}
+
+fn aggregate_type(aggregate: &AggregateExpr, input: &PlanSchema) -> Option<DataType> {
+    match aggregate.function {
+        AggregateFunction::Count => Some(DataType::Integer),
+        AggregateFunction::Avg => Some(DataType::Float),
+        AggregateFunction::Sum | AggregateFunction::Min | AggregateFunction::Max => aggregate
+            .arg
+            .as_ref()
+            .and_then(|arg| arg.data_type(input))
+            .map(|data_type| data_type.storage_type()),
+    }
+}
+
+/// The expressions of an aggregating query are bound above the Aggregate
+/// node: group expressions and aggregates are replaced by the columns the
+/// node outputs.
+struct AggregateScope<'a> {
+    group_by: &'a [Expr],
+    aggregates: Vec<AggregateExpr>,
+}
+
+/// The query enclosing a subquery, as seen by the subquery: the columns the
+/// subquery doesn't resolve are looked up in its binder, and become the
+/// arguments of the subquery.
+struct OuterScope<'a> {
+    binder: &'a Binder<'a>,
+    args: RefCell<Vec<Expr>>,
+}
+
+impl OuterScope<'_> {
+    /// Resolves a column of the enclosing queries, returns the parameter of
+    /// the subquery bound to it.
+    fn param(&self, table: Option<&str>, name: &str) -> Result<Expr, PlanError> {
+        let arg = self.binder.grouped(self.binder.column(table, name)?)?;
+        let mut args = self.args.borrow_mut();
+        let idx = match args.iter().position(|a| *a == arg) {
+            Some(idx) => idx,
+            None => {
+                args.push(arg);
+                args.len() - 1
+            }
+        };
+        Ok(Expr::Param(idx))
+    }
+}
+
+/// Resolves the names of expressions against the columns of their input.
+/// Without `tables`, subqueries aren't allowed.
+struct Binder<'a> {
+    input: &'a PlanSchema,
+    aggregate: Option<AggregateScope<'a>>,
+    tables: Option<&'a dyn SchemaProvider>,
+    outer: Option<&'a OuterScope<'a>>,
+}
+
+impl<'a> Binder<'a> {
+    fn new(input: &'a PlanSchema) -> Self {
+        Self {
+            input,
+            aggregate: None,
+            tables: None,
+            outer: None,
+        }
+    }
+
+    /// A binder allowing subqueries, `outer` is the scope of the enclosing
+    /// query if this one is a subquery.
+    fn with_context(
+        input: &'a PlanSchema,
+        tables: &'a dyn SchemaProvider,
+        outer: Option<&'a OuterScope<'a>>,
+    ) -> Self {
+        Self {
+            tables: Some(tables),
+            outer,
+            ..Self::new(input)
+        }
+    }
+
+    /// A binder of the same input and scope, without aggregates.
+    fn nested(&self) -> Binder<'a> {
+        Binder {
+            aggregate: None,
+            ..*self
+        }
+    }
+
+    /// Binds the select list, `*` expands to all the input columns.
+    fn bind_select_list(
+        &mut self,
+        columns: &[ast::Expr],
+    ) -> Result<(Vec<Expr>, PlanSchema), PlanError> {
+        let mut exprs = Vec::new();
+        let mut schema = Vec::new();
+        for column in columns {
+            if let ExprKind::All = column.kind {
+                for (idx, input) in self.input.columns().iter().enumerate() {
+                    exprs.push(self.grouped(Expr::Column(idx))?);
+                    schema.push(input.clone());
+                }
+                continue;
+            }
+
+            let expr = self.bind(column)?;
+            let (table, name) = match &column.kind {
+                ExprKind::Column { .. } => match expr {
+                    Expr::Column(idx) if self.aggregate.is_none() => {
+                        let input = &self.input.columns()[idx];
+                        (input.table.clone(), input.name.clone())
+                    }
+                    _ => (None, self.output_name(column)),
+                },
+                _ => (None, self.output_name(column)),
+            };
+            schema.push(PlanColumn {
+                table,
+                name,
+                data_type: self.output_type(&expr),
+            });
+            exprs.push(expr);
+        }
+
+        Ok((exprs, PlanSchema::new(schema)))
+    }
+
+    /// Binds the ORDER BY clause of a query whose select list is `exprs`: a
+    /// key is either the position of an output column, starting at 1, or an
+    /// expression of the input of the projection, which the sort is put under.
+    fn bind_order_by(
+        &mut self,
+        order_by: &[ast::OrderByExpr],
+        exprs: &[Expr],
+    ) -> Result<Vec<SortKey>, PlanError> {
+        order_by
+            .iter()
+            .map(|key| {
+                let expr = match key.expr.kind {
+                    ExprKind::Literal(Literal::Integer(position)) => usize::try_from(position)
+                        .ok()
+                        .and_then(|position| exprs.get(position.checked_sub(1)?))
+                        .cloned()
+                        .ok_or(PlanError::OrderByPosition(position))?,
+                    _ => self.bind(&key.expr)?,
+                };
+                Ok(SortKey {
+                    expr,
+                    descending: key.descending,
+                })
+            })
+            .collect()
+    }
+
+    /// The name of an output column.
+    fn output_name(&self, expr: &ast::Expr) -> String {
+        match &expr.kind {
+            ExprKind::Column { name, .. } => name.to_string(),
+            ExprKind::Aggregate { function, .. } => format!("{function:?}").to_lowercase(),
+            ExprKind::Function { name, .. } => name.to_lowercase(),
+            _ => "?column?".to_string(),
+        }
+    }
+
+    /// The type of an expression bound by this binder.
+    fn output_type(&self, expr: &Expr) -> Option<DataType> {
+        match &self.aggregate {
+            Some(scope) => {
+                let columns = scope
+                    .group_by
+                    .iter()
+                    .map(|expr| expr.data_type(self.input))
+                    .chain(
+                        scope
+                            .aggregates
+                            .iter()
+                            .map(|aggregate| aggregate_type(aggregate, self.input)),
+                    )
+                    .map(|data_type| PlanColumn {
+                        table: None,
+                        name: String::new(),
+                        data_type,
+                    })
+                    .collect();
+                expr.data_type(&PlanSchema::new(columns))
+            }
+            None => expr.data_type(self.input),
+        }
+    }
+
+    /// Above an Aggregate node, replaces an expression by the group column it
+    /// is equal to.
+    fn grouped(&self, expr: Expr) -> Result<Expr, PlanError> {
+        let Some(scope) = &self.aggregate else {
+            return Ok(expr);
+        };
+
+        match scope.group_by.iter().position(|group| *group == expr) {
+            Some(idx) => Ok(Expr::Column(idx)),
+            None => match expr {
+                Expr::Column(idx) => {
+                    let column = &self.input.columns()[idx];
+                    let name = match &column.table {
+                        Some(table) => format!("{table}.{}", column.name),
+                        None => column.name.clone(),
+                    };
+                    Err(PlanError::NotGrouped(name))
+                }
+                expr => Ok(expr),
+            },
+        }
+    }
+
+    fn boxed(&mut self, expr: &ast::Expr) -> Result<Box<Expr>, PlanError> {
+        self.bind(expr).map(Box::new)
+    }
+
+    /// Resolves a column of the input, or else of the enclosing queries.
+    fn column(&self, table: Option<&str>, name: &str) -> Result<Expr, PlanError> {
+        match (self.input.resolve(table, name), self.outer) {
+            (Ok(idx), _) => Ok(Expr::Column(idx)),
+            (Err(PlanError::UnknownColumn(_)), Some(outer)) => outer.param(table, name),
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    /// Plans a subquery, which returns a single column but for EXISTS. It is
+    /// correlated if it refers to the columns of this query.
+    fn subquery(&self, kind: SubqueryKind, stmt: &Stmt) -> Result<Expr, PlanError> {
+        let Some(tables) = self.tables else {
+            return Err(PlanError::Unsupported("subqueries in this clause"));
+        };
+        let scope = OuterScope {
+            binder: self,
+            args: RefCell::new(Vec::new()),
+        };
+        let plan = optimize(plan_select(stmt, tables, Some(&scope))?, tables);
+        let num_columns = plan.schema().columns().len();
+        if num_columns != 1 && kind != SubqueryKind::Exists {
+            return Err(PlanError::SubqueryColumnCount(num_columns));
+        }
+        Ok(Expr::Subquery(Box::new(Subquery {
+            kind,
+            plan,
+            args: scope.args.into_inner(),
+        })))
+    }
+
+    fn bind(&mut self, expr: &ast::Expr) -> Result<Expr, PlanError> {
+        // A binder of the input, bound below the Aggregate node.
+        let mut nested = self.nested();
+        if let Some(scope) = &mut self.aggregate {
+            if let ExprKind::Aggregate {
+                function,
+                distinct,
+                arg,
+            } = &expr.kind
+            {
+                let arg = match arg.kind {
+                    ExprKind::All => None,
+                    // Aggregates can't be nested, nor have subqueries.
+                    _ => {
+                        nested.tables = None;
+                        Some(nested.bind(arg)?)
+                    }
+                };
+                let aggregate = AggregateExpr {
+                    function: *function,
+                    distinct: *distinct,
+                    arg,
+                };
+                let idx = match scope.aggregates.iter().position(|a| *a == aggregate) {
+                    Some(idx) => idx,
+                    None => {
+                        scope.aggregates.push(aggregate);
+                        scope.aggregates.len() - 1
+                    }
+                };
+                return Ok(Expr::Column(scope.group_by.len() + idx));
+            }
+
+            if !contains_aggregate(expr) {
+                let bound = nested.bind(expr)?;
+                if scope.group_by.contains(&bound) || matches!(bound, Expr::Column(_)) {
+                    return self.grouped(bound);
+                }
+            }
+        }
+
+        Ok(match &expr.kind {
+            ExprKind::Column { table, name } => self.column(table.as_deref(), name)?,
+            ExprKind::Literal(literal) => Expr::Literal(match literal {
+                Literal::Ident(s) | Literal::String(s) => Value::VarChar(s.to_string()),
+                Literal::Boolean(b) => Value::Boolean(*b),
+                Literal::Integer(i) => Value::Integer(*i),
+                Literal::Float(f) => Value::Float(*f),
+                Literal::Null => Value::Null,
+            }),
+            ExprKind::Operator(operator) => {
+                let binary = |op, lhs, rhs| Expr::Binary { op, lhs, rhs };
+                let unary = |op, expr| Expr::Unary { op, expr };
+                match operator {
+                    Operator::Plus(lhs, rhs) => {
+                        binary(BinaryOp::Plus, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Minus(lhs, rhs) => {
+                        binary(BinaryOp::Minus, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Mul(lhs, rhs) => {
+                        binary(BinaryOp::Mul, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Div(lhs, rhs) => {
+                        binary(BinaryOp::Div, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Equal(lhs, rhs) => {
+                        binary(BinaryOp::Equal, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::NotEqual(lhs, rhs) => {
+                        binary(BinaryOp::NotEqual, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Less(lhs, rhs) => {
+                        binary(BinaryOp::Less, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::LessEqual(lhs, rhs) => {
+                        binary(BinaryOp::LessEqual, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Greater(lhs, rhs) => {
+                        binary(BinaryOp::Greater, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::GreaterEqual(lhs, rhs) => {
+                        binary(BinaryOp::GreaterEqual, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::And(lhs, rhs) => {
+                        binary(BinaryOp::And, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Or(lhs, rhs) => {
+                        binary(BinaryOp::Or, self.boxed(lhs)?, self.boxed(rhs)?)
+                    }
+                    Operator::Identity(expr) => unary(UnaryOp::Identity, self.boxed(expr)?),
+                    Operator::Negate(expr) => unary(UnaryOp::Negate, self.boxed(expr)?),
+                    Operator::Not(expr) => unary(UnaryOp::Not, self.boxed(expr)?),
+                }
+            }
+            ExprKind::Function { name, args } => {
+                let function = BUILTIN_FUNCTIONS
+                    .get(name)
+                    .ok_or_else(|| PlanError::UnknownFunction(name.to_string()))?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.bind(arg))
+                    .collect::<Result<_, _>>()?;
+                Expr::Function { function, args }
+            }
+            ExprKind::Cast { expr, data_type } => Expr::Cast {
+                expr: self.boxed(expr)?,
+                data_type: *data_type,
+            },
+            ExprKind::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: self.boxed(expr)?,
+                list: list
+                    .iter()
+                    .map(|item| self.bind(item))
+                    .collect::<Result<_, _>>()?,
+                negated: *negated,
+            },
+            ExprKind::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => Expr::Between {
+                expr: self.boxed(expr)?,
+                low: self.boxed(low)?,
+                high: self.boxed(high)?,
+                negated: *negated,
+            },
+            ExprKind::Like {
+                expr,
+                pattern,
+                negated,
+            } => Expr::Like {
+                expr: self.boxed(expr)?,
+                pattern: self.boxed(pattern)?,
+                negated: *negated,
+            },
+            ExprKind::Aggregate { .. } => return Err(PlanError::AggregateNotAllowed),
+            ExprKind::All => return Err(PlanError::Unsupported("`*` in expressions")),
+            ExprKind::Subquery(stmt) => self.subquery(SubqueryKind::Scalar, stmt)?,
+            ExprKind::Exists(stmt) => self.subquery(SubqueryKind::Exists, stmt)?,
+            ExprKind::InSubquery {
+                expr,
+                subquery,
+                negated,
+            } => {
+                let kind = SubqueryKind::In {
+                    expr: self.boxed(expr)?,
+                    negated: *negated,
+                };
+                self.subquery(kind, subquery)?
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    use crate::sql::parser::parser::Parser;
+    use crate::sql::schema::{Column, ConstraintsBuilder, Schema};
+
+    fn plan_sql(source: &str) -> Result<LogicalPlan, PlanError> {
+        let column = |name: &str, data_type| {
+            Column::new(name.into(), data_type, ConstraintsBuilder::new().build())
+        };
+        let mut tables = HashMap::new();
+        tables.insert(
+            "users".to_string(),
+            Schema::try_new(vec![
+                column("id", DataType::Integer),
+                column("name", DataType::VarChar(None)),
+            ])
+            .unwrap(),
+        );
+        tables.insert(
+            "orders".to_string(),
+            Schema::try_new(vec![
+                column("id", DataType::Integer),
+                column("user_id", DataType::Integer),
+                column("amount", DataType::Float),
+            ])
+            .unwrap(),
+        );
+
+        let stmts = Parser::parse(source).unwrap();
+        plan(&stmts[0], &tables)
+    }
+
+    fn explain(source: &str) -> String {
+        plan_sql(source).unwrap().to_string()
+    }
+
+    #[test]
+    fn scan_filter_project() {
+        assert_eq!(
+            explain("SELECT name, id + 1 FROM users WHERE id = 1"),
+            "Project #1, (#0 + 1)\n  Filter (#0 = 1)\n    Scan users\n"
+        );
+        assert_eq!(
+            explain("SELECT * FROM users"),
+            "Project #0, #1\n  Scan users\n"
+        );
+        assert_eq!(explain("SELECT 1 + 2"), "Project (1 + 2)\n  SingleRow\n");
+
+        let plan = plan_sql("SELECT u.name, UPPER(name), 1.5 * id FROM users u").unwrap();
+        let names = plan
+            .schema()
+            .columns()
+            .iter()
+            .map(|column| (column.name.as_str(), column.data_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("name", Some(DataType::VarChar(None))),
+                ("upper", Some(DataType::VarChar(None))),
+                ("?column?", Some(DataType::Float)),
+            ]
+        );
+    }
+
+    #[test]
+    fn insert() {
+        assert_eq!(
+            explain("INSERT INTO users (name, id) SELECT 'a', amount FROM orders"),
+            "Insert users [#1, #0]\n  Project 'a', #2\n    Scan orders [#2]\n"
+        );
+        assert_eq!(
+            explain("INSERT INTO users SELECT * FROM users"),
+            "Insert users [#0, #1]\n  Project #0, #1\n    Scan users\n"
+        );
+
+        assert!(matches!(
+            plan_sql("INSERT INTO users SELECT id FROM users"),
+            Err(PlanError::InsertColumnCount {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            plan_sql("INSERT INTO users (id, id) SELECT 1, 2"),
+            Err(PlanError::DuplicateColumn(_))
+        ));
+        assert!(matches!(
+            plan_sql("INSERT INTO users (age) SELECT 1"),
+            Err(PlanError::UnknownColumn(_))
+        ));
+        assert!(matches!(
+            plan_sql("INSERT INTO missing SELECT 1"),
+            Err(PlanError::UnknownTable(_))
+        ));
+    }
+
+    #[test]
+    fn joins() {
+        assert_eq!(
+            explain("SELECT name, amount FROM users u JOIN orders o ON u.id = o.user_id"),
+            "Project #1, #4\n  HashJoin Inner on (#0 = #3)\n    Scan users\n    Scan orders [#1, #2]\n"
+        );
+        assert_eq!(
+            explain("SELECT users.id FROM users, orders"),
+            "Project #0\n  NestedLoopJoin Cross\n    Scan users [#0]\n    Scan orders []\n"
+        );
+
+        assert!(matches!(
+            plan_sql("SELECT id FROM users, orders"),
+            Err(PlanError::AmbiguousColumn(_))
+        ));
+        assert!(matches!(
+            plan_sql("SELECT users.id FROM users u"),
+            Err(PlanError::UnknownColumn(_))
+        ));
+        assert!(matches!(
+            plan_sql("SELECT * FROM nope"),
+            Err(PlanError::UnknownTable(_))
+        ));
+    }
+
+    #[test]
+    fn aggregates() {
+        assert_eq!(
+            explain(
+                "SELECT name, COUNT(*), SUM(amount) + 1 FROM users u \
+                 JOIN orders o ON u.id = o.user_id GROUP BY name HAVING COUNT(*) > 1"
+            ),
+            "Project #0, #1, (#2 + 1)\n  \
+               Filter (#1 > 1)\n    \
+                 Aggregate [#1] [Count(*), Sum(#4)]\n      \
+                   HashJoin Inner on (#0 = #3)\n        \
+                     Scan users\n        \
+                     Scan orders [#1, #2]\n"
+        );
+        assert_eq!(
+            explain("SELECT id + 1, MAX(name) FROM users GROUP BY id + 1"),
+            "Project #0, #1\n  Aggregate [(#0 + 1)] [Max(#1)]\n    Scan users\n"
+        );
+        assert_eq!(
+            explain("SELECT COUNT(DISTINCT name), COUNT(name) FROM users"),
+            "Project #0, #1\n  Aggregate [] [Count(DISTINCT #1), Count(#1)]\n    Scan users [#1]\n"
+        );
+        assert_eq!(
+            explain("SELECT DISTINCT name FROM users"),
+            "Aggregate [#0] []\n  Project #1\n    Scan users [#1]\n"
+        );
+
+        assert!(matches!(
+            plan_sql("SELECT name, COUNT(*) FROM users"),
+            Err(PlanError::NotGrouped(_))
+        ));
+        assert!(matches!(
+            plan_sql("SELECT id + 2 FROM users GROUP BY id + 1"),
+            Err(PlanError::NotGrouped(_))
+        ));
+        assert!(matches!(
+            plan_sql("SELECT id FROM users WHERE COUNT(*) > 1"),
+            Err(PlanError::AggregateNotAllowed)
+        ));
+        assert!(matches!(
+            plan_sql("SELECT SUM(COUNT(*)) FROM users"),
+            Err(PlanError::AggregateNotAllowed)
+        ));
+        assert!(matches!(
+            plan_sql("SELECT nope(id) FROM users"),
+            Err(PlanError::UnknownFunction(_))
+        ));
+    }
+
+    #[test]
+    fn subqueries() {
+        assert_eq!(
+            explain("SELECT name FROM users WHERE id IN (SELECT user_id FROM orders)"),
+            "Project #1\n  Filter (#0 IN (subquery))\n    Scan users\n"
+        );
+        // The outer columns a subquery refers to are its arguments, its plan
+        // refers to them as parameters.
+        let plan = plan_sql(
+            "SELECT u.id FROM users u, orders o \
+             WHERE EXISTS (SELECT * FROM orders WHERE user_id = u.id AND id = o.id)",
+        )
+        .unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "Project #0\n  \
+               Filter EXISTS(subquery)\n    \
+                 NestedLoopJoin Cross\n      \
+                   Scan users [#0]\n      \
+                   Scan orders [#0]\n"
+        );
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!("expected a projection");
+        };
+        let LogicalPlan::Filter {
+            predicate: Expr::Subquery(subquery),
+            ..
+        } = *input
+        else {
+            panic!("expected a subquery");
+        };
+        assert_eq!(subquery.args, [Expr::Column(0), Expr::Column(2)]);
+        assert_eq!(
+            subquery.plan.to_string(),
+            "Project #0, #1, #2\n  Filter ((#1 = $1) AND (#0 = $2))\n    Scan orders\n"
+        );
+
+        assert!(matches!(
+            plan_sql("SELECT id FROM users WHERE id IN (SELECT id, user_id FROM orders)"),
+            Err(PlanError::SubqueryColumnCount(2))
+        ));
+        assert!(matches!(
+            plan_sql("SELECT id FROM users WHERE id = (SELECT nope FROM orders)"),
+            Err(PlanError::UnknownColumn(_))
+        ));
+        assert!(matches!(
+            plan_sql("SELECT COUNT(*) FROM users GROUP BY (SELECT 1)"),
+            Err(PlanError::Unsupported(_))
+        ));
+        assert!(matches!(
+            plan_sql(
+                "SELECT (SELECT amount FROM orders WHERE user_id = users.id) FROM users GROUP BY name"
+            ),
+            Err(PlanError::NotGrouped(_))
+        ));
+    }
+}
@@ -2,23 +2,83 @@ use std::collections::HashSet;
 
 use thiserror::Error;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum DataType {
     Boolean,
     Integer,
     Float,
-    VarChar,
+    /// A string of at most `n` characters if the length is given.
+    VarChar(Option<u16>),
+    /// A string of at most `n` characters, stored as a VARCHAR. Shorter strings
+    /// are not padded.
+    Char(u16),
+    /// A number with `precision` digits, `scale` of them after the decimal
+    /// point. Stored as a FLOAT: values are approximate, only the precision
+    /// bounds them.
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+}
+
+impl DataType {
+    pub const MAX_DECIMAL_PRECISION: u8 = 38;
+
+    /// Returns the type values of this type are stored as.
+    pub fn storage_type(&self) -> DataType {
+        match self {
+            DataType::VarChar(_) | DataType::Char(_) => DataType::VarChar(None),
+            DataType::Decimal { .. } => DataType::Float,
+            data_type => *data_type,
+        }
+    }
 }
 
 impl std::fmt::Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            DataType::Boolean => "BOOLEAN",
-            DataType::Integer => "INTEGER",
-            DataType::Float => "FLOAT",
-            DataType::VarChar => "VARCHAR",
+        match self {
+            DataType::Boolean => write!(f, "BOOLEAN"),
+            DataType::Integer => write!(f, "INTEGER"),
+            DataType::Float => write!(f, "FLOAT"),
+            DataType::VarChar(None) => write!(f, "VARCHAR"),
+            DataType::VarChar(Some(n)) => write!(f, "VARCHAR({n})"),
+            DataType::Char(n) => write!(f, "CHAR({n})"),
+            DataType::Decimal { precision, scale } => write!(f, "DECIMAL({precision},{scale})"),
+        }
+    }
+}
+
+/// Parses the names printed by `Display`, e.g. as stored in the catalog.
+impl std::str::FromStr for DataType {
+    type Err = SchemaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || SchemaError::UnknownType(s.to_string());
+        let (name, params) = match s.split_once('(') {
+            Some((name, params)) => {
+                let params = params.strip_suffix(')').ok_or_else(unknown)?;
+                (name, Some(params))
+            }
+            None => (s, None),
         };
-        write!(f, "{s}")
+        let param = |param: &str| param.trim().parse().map_err(|_| unknown());
+
+        Ok(match (name, params) {
+            ("BOOLEAN", None) => DataType::Boolean,
+            ("INTEGER", None) => DataType::Integer,
+            ("FLOAT", None) => DataType::Float,
+            ("VARCHAR", None) => DataType::VarChar(None),
+            ("VARCHAR", Some(n)) => DataType::VarChar(Some(param(n)?)),
+            ("CHAR", Some(n)) => DataType::Char(param(n)?),
+            ("DECIMAL", Some(params)) => {
+                let (precision, scale) = params.split_once(',').ok_or_else(unknown)?;
+                DataType::Decimal {
+                    precision: precision.trim().parse().map_err(|_| unknown())?,
+                    scale: scale.trim().parse().map_err(|_| unknown())?,
+                }
+            }
+            _ => return Err(unknown()),
+        })
     }
 }
 
@@ -110,6 +170,8 @@ pub enum SchemaError {
     TooManyColumns,
     #[error("columns must have unique names")]
     UniqueName,
+    #[error("unknown data type {0}")]
+    UnknownType(String),
 }
 
 #[cfg(test)]
@@ -125,7 +187,7 @@ mod tests {
             },
             Column {
                 column_name: "b".into(),
-                data_type: DataType::VarChar,
+                data_type: DataType::VarChar(None),
                 constraints: ConstraintsBuilder::new().build(),
             },
         ];
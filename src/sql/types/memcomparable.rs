@@ -0,0 +1,354 @@
+//! Key encoding preserving the SQL order of values.
+//!
+//! Values are encoded into byte strings comparing (with `memcmp`) in the same
+//! order as the values, so that keys made of several values can be compared,
+//! sorted or hashed as plain bytes: composite index keys, sort runs and
+//! grouping keys.
+//!
+//! Each value starts with a tag byte, NULLs sort after all the other values.
+//! The tag is followed by:
+//! - BOOLEAN: one byte, 0 or 1.
+//! - INTEGER: 8 big-endian bytes with the sign bit flipped.
+//! - FLOAT: the 8 big-endian bytes of the IEEE 754 representation, with the
+//!   sign bit flipped for positive numbers and all bits flipped for negative
+//!   ones. Both zeros are encoded as +0 and all NaNs as a single NaN, sorting
+//!   after +inf.
+//! - VARCHAR: the bytes of the string, 0x00 escaped as 0x00 0xff, followed by
+//!   0x00 0x00. The encoding is prefix free: "a" sorts before "a\0" and "ab".
+//!
+//! Descending values are encoded with all their bytes flipped.
+
+use thiserror::Error;
+
+use crate::sql::schema::DataType;
+use crate::sql::types::Value;
+
+const TAG_VALUE: u8 = 0x01;
+const TAG_NULL: u8 = 0x02;
+
+const ESCAPE: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xff;
+const TERMINATOR: u8 = 0x00;
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("unexpected end of key")]
+    UnexpectedEnd,
+    #[error("invalid tag {0:#x}")]
+    InvalidTag(u8),
+    #[error("invalid escape sequence")]
+    InvalidEscape,
+    #[error("invalid UTF-8 string")]
+    InvalidUtf8,
+}
+
+/// How strings are compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+    /// Strings are compared byte by byte, like `Value`s.
+    #[default]
+    Binary,
+    /// Strings are compared after lowercasing them. The original case is
+    /// lost: decoding returns the lowercased string.
+    NoCase,
+}
+
+/// How a value of a key is encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyOptions {
+    pub descending: bool,
+    pub collation: Collation,
+}
+
+impl KeyOptions {
+    pub fn descending() -> Self {
+        Self {
+            descending: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Encodes values in ascending order with the binary collation.
+pub fn encode(values: &[Value]) -> Vec<u8> {
+    let mut key = Vec::new();
+    for value in values {
+        encode_value(value, KeyOptions::default(), &mut key);
+    }
+    key
+}
+
+/// The bytes the keys of the strings starting with `prefix` start with, in
+/// ascending order with the binary collation: the encoding of `prefix`
+/// without its terminator.
+pub fn encode_prefix(prefix: &str) -> Vec<u8> {
+    let mut key = encode(&[Value::VarChar(prefix.to_string())]);
+    key.truncate(key.len() - 2);
+    key
+}
+
+/// Appends the encoding of a value to a key.
+pub fn encode_value(value: &Value, options: KeyOptions, dst: &mut Vec<u8>) {
+    let start = dst.len();
+
+    match value {
+        Value::Null => dst.push(TAG_NULL),
+        Value::Boolean(b) => dst.extend([TAG_VALUE, *b as u8]),
+        Value::Integer(i) => {
+            dst.push(TAG_VALUE);
+            dst.extend(((*i as u64) ^ (1 << 63)).to_be_bytes());
+        }
+        Value::Float(f) => {
+            let f = if f.is_nan() {
+                f64::NAN
+            } else if *f == 0.0 {
+                0.0
+            } else {
+                *f
+            };
+            let bits = f.to_bits();
+            let bits = if bits >> 63 == 1 {
+                !bits
+            } else {
+                bits ^ (1 << 63)
+            };
+            dst.push(TAG_VALUE);
+            dst.extend(bits.to_be_bytes());
+        }
+        Value::VarChar(s) => {
+            let folded;
+            let s = match options.collation {
+                Collation::Binary => s.as_str(),
+                Collation::NoCase => {
+                    folded = s.to_lowercase();
+                    folded.as_str()
+                }
+            };
+
+            dst.push(TAG_VALUE);
+            for &byte in s.as_bytes() {
+                match byte {
+                    ESCAPE => dst.extend([ESCAPE, ESCAPED_ZERO]),
+                    byte => dst.push(byte),
+                }
+            }
+            dst.extend([ESCAPE, TERMINATOR]);
+        }
+    }
+
+    if options.descending {
+        dst[start..].iter_mut().for_each(|byte| *byte = !*byte);
+    }
+}
+
+/// Decodes a key made of values of the given types.
+pub fn decode(
+    mut key: &[u8],
+    columns: &[(DataType, KeyOptions)],
+) -> Result<Vec<Value>, DecodeError> {
+    columns
+        .iter()
+        .map(|(data_type, options)| decode_value(&mut key, *data_type, *options))
+        .collect()
+}
+
+/// Decodes the value at the start of a key and advances the key past it.
+pub fn decode_value(
+    key: &mut &[u8],
+    data_type: DataType,
+    options: KeyOptions,
+) -> Result<Value, DecodeError> {
+    let mask = if options.descending { 0xff } else { 0x00 };
+    let mut next = || next_byte(key, mask);
+    match next()? {
+        TAG_NULL => return Ok(Value::Null),
+        TAG_VALUE => {}
+        tag => return Err(DecodeError::InvalidTag(tag)),
+    }
+
+    Ok(match data_type.storage_type() {
+        DataType::Boolean => Value::Boolean(next()? != 0),
+        DataType::Integer => Value::Integer((next_u64(key, mask)? ^ (1 << 63)) as i64),
+        DataType::Float => {
+            let bits = next_u64(key, mask)?;
+            let bits = if bits >> 63 == 1 {
+                bits ^ (1 << 63)
+            } else {
+                !bits
+            };
+            Value::Float(f64::from_bits(bits))
+        }
+        DataType::VarChar(_) => {
+            let mut bytes = Vec::new();
+            loop {
+                match next()? {
+                    ESCAPE => match next()? {
+                        ESCAPED_ZERO => bytes.push(0),
+                        TERMINATOR => break,
+                        _ => return Err(DecodeError::InvalidEscape),
+                    },
+                    byte => bytes.push(byte),
+                }
+            }
+            Value::VarChar(String::from_utf8(bytes).map_err(|_| DecodeError::InvalidUtf8)?)
+        }
+        DataType::Char(_) | DataType::Decimal { .. } => unreachable!("not a storage type"),
+    })
+}
+
+fn next_byte(key: &mut &[u8], mask: u8) -> Result<u8, DecodeError> {
+    let (&byte, rest) = key.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+    *key = rest;
+    Ok(byte ^ mask)
+}
+
+fn next_u64(key: &mut &[u8], mask: u8) -> Result<u64, DecodeError> {
+    let mut bytes = [0; 8];
+    for byte in bytes.iter_mut() {
+        *byte = next_byte(key, mask)?;
+    }
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varchar(s: &str) -> Value {
+        Value::VarChar(s.to_string())
+    }
+
+    /// Checks that the encodings of `values`, given in ascending order, are
+    /// sorted.
+    fn assert_sorted(values: &[Value], options: KeyOptions) {
+        let keys = values
+            .iter()
+            .map(|value| {
+                let mut key = Vec::new();
+                encode_value(value, options, &mut key);
+                key
+            })
+            .collect::<Vec<_>>();
+        for (i, pair) in keys.windows(2).enumerate() {
+            let expected = if options.descending {
+                pair[0] > pair[1]
+            } else {
+                pair[0] < pair[1]
+            };
+            assert!(expected, "{:?} and {:?}", values[i], values[i + 1]);
+        }
+    }
+
+    #[test]
+    fn order() {
+        for options in [KeyOptions::default(), KeyOptions::descending()] {
+            assert_sorted(
+                &[Value::Boolean(false), Value::Boolean(true), Value::Null],
+                options,
+            );
+            assert_sorted(
+                &[i64::MIN, -256, -1, 0, 1, 255, i64::MAX]
+                    .map(Value::Integer)
+                    .into_iter()
+                    .chain([Value::Null])
+                    .collect::<Vec<_>>(),
+                options,
+            );
+            assert_sorted(
+                &[
+                    f64::NEG_INFINITY,
+                    f64::MIN,
+                    -1.5,
+                    -f64::MIN_POSITIVE,
+                    0.0,
+                    f64::MIN_POSITIVE,
+                    1.5,
+                    f64::MAX,
+                    f64::INFINITY,
+                    f64::NAN,
+                ]
+                .map(Value::Float),
+                options,
+            );
+            assert_sorted(
+                &["", "\0", "\0\0", "a", "a\0", "a\0b", "ab", "b", "é"].map(varchar),
+                options,
+            );
+        }
+
+        // Values of a key are compared one after the other.
+        assert!(
+            encode(&[varchar("a"), Value::Integer(2)])
+                < encode(&[varchar("a\0"), Value::Integer(1)])
+        );
+        assert!(
+            encode(&[Value::Integer(1), Value::Null])
+                > encode(&[Value::Integer(1), Value::Integer(9)])
+        );
+    }
+
+    #[test]
+    fn equal_values() {
+        assert_eq!(encode(&[Value::Float(0.0)]), encode(&[Value::Float(-0.0)]));
+        assert_eq!(
+            encode(&[Value::Float(f64::NAN)]),
+            encode(&[Value::Float(-f64::NAN)])
+        );
+
+        let options = KeyOptions {
+            collation: Collation::NoCase,
+            ..KeyOptions::default()
+        };
+        let (mut lower, mut upper) = (Vec::new(), Vec::new());
+        encode_value(&varchar("Abc"), options, &mut upper);
+        encode_value(&varchar("abc"), options, &mut lower);
+        assert_eq!(lower, upper);
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = vec![
+            Value::Boolean(true),
+            Value::Integer(-42),
+            Value::Float(-0.5),
+            varchar("a\0b"),
+            Value::Null,
+            Value::Float(2.5),
+        ];
+        let data_types = [
+            DataType::Boolean,
+            DataType::Integer,
+            DataType::Decimal {
+                precision: 4,
+                scale: 2,
+            },
+            DataType::Char(3),
+            DataType::Integer,
+            DataType::Float,
+        ];
+
+        for options in [KeyOptions::default(), KeyOptions::descending()] {
+            let mut key = Vec::new();
+            for value in &values {
+                encode_value(value, options, &mut key);
+            }
+            let columns = data_types.map(|data_type| (data_type, options));
+            assert_eq!(decode(&key, &columns).unwrap(), values);
+            assert!(matches!(
+                decode(&key[..key.len() - 1], &columns),
+                Err(DecodeError::UnexpectedEnd)
+            ));
+        }
+    }
+
+    #[test]
+    fn prefixes() {
+        for prefix in ["", "a", "a\0", "ab"] {
+            let key_prefix = encode_prefix(prefix);
+            for s in ["", "\0", "a", "a\0", "a\0b", "ab", "abc", "b"] {
+                let key = encode(&[varchar(s)]);
+                assert_eq!(key.starts_with(&key_prefix), s.starts_with(prefix), "{s:?}");
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod memcomparable;
 pub mod value;
 
 pub use value::Value;
@@ -1,47 +1,63 @@
 use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 
 use zerocopy::{
-    byteorder::little_endian::{F64, I64, U16},
+    byteorder::little_endian::{F64, I64},
     *,
 };
 use zerocopy_derive::*;
 
+use thiserror::Error;
+
 use crate::serialize::Serialize;
 use crate::sql::schema::DataType;
 
-#[derive(FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
-#[repr(C)]
-pub struct ValueHeader {
-    pub len: U16,
-}
+/// The length header of a VARCHAR value.
+///
+/// Short strings, the most common, get a 1-byte header holding their length. Longer strings
+/// get a 2-byte big-endian header with the high bit set: the high bit of the first byte tells
+/// both formats apart.
+pub struct VarCharHeader;
 
-impl ValueHeader {
-    const SIZE: usize = std::mem::size_of::<ValueHeader>();
+impl VarCharHeader {
+    /// Strings up to this length (in bytes) have a 1-byte header.
+    pub const SHORT_MAX_LEN: usize = 0x7f;
+    /// The maximum length of a string, more than a page can hold anyway.
+    pub const MAX_LEN: usize = 0x7fff;
+    const LONG_FLAG: u16 = 0x8000;
 
-    pub fn new(len: usize) -> Self {
-        assert!(len <= u16::MAX as usize);
-        Self {
-            len: U16::new(len as u16),
-        }
+    pub fn size(len: usize) -> usize {
+        if len <= Self::SHORT_MAX_LEN { 1 } else { 2 }
     }
 
-    #[allow(clippy::len_without_is_empty)]
-    pub fn len(&self) -> u16 {
-        self.len.get()
+    pub fn write(len: usize, dst: &mut [u8]) {
+        assert!(len <= Self::MAX_LEN);
+        if len <= Self::SHORT_MAX_LEN {
+            dst[0] = len as u8;
+        } else {
+            dst[..2].copy_from_slice(&(len as u16 | Self::LONG_FLAG).to_be_bytes());
+        }
     }
-}
 
-#[derive(SplitAt, FromBytes, KnownLayout, Immutable, Unaligned)]
-#[repr(C)]
-pub struct VarCharRef {
-    header: ValueHeader,
-    data: [u8],
+    /// Returns the length of the string and the size of the header.
+    pub fn read(bytes: &[u8]) -> (usize, usize) {
+        if bytes[0] & 0x80 == 0 {
+            (bytes[0] as usize, 1)
+        } else {
+            let header = u16::from_be_bytes([bytes[0], bytes[1]]);
+            ((header & !Self::LONG_FLAG) as usize, 2)
+        }
+    }
 }
 
-impl VarCharRef {
-    fn to_owned(&self) -> String {
-        String::from_utf8(self.data.to_vec()).unwrap()
-    }
+#[derive(Debug, Error)]
+pub enum CastError {
+    #[error("cannot cast {from} to {to}")]
+    Unsupported { from: DataType, to: DataType },
+    #[error("invalid input syntax for type {to}: {text:?}")]
+    InvalidText { text: String, to: DataType },
+    #[error("value out of range for type {0}")]
+    OutOfRange(DataType),
 }
 
 #[derive(Clone, Debug)]
@@ -55,7 +71,7 @@ pub enum Value {
 
 impl Value {
     pub fn from_bytes(bytes: &[u8], data_type: DataType) -> Self {
-        match data_type {
+        match data_type.storage_type() {
             DataType::Boolean => {
                 let b = bytes[0] == 0x01;
                 Self::Boolean(b)
@@ -68,13 +84,27 @@ impl Value {
                 let f = F64::ref_from_bytes(&bytes[0..8]).unwrap().get();
                 Self::Float(f)
             }
-            DataType::VarChar => {
-                let varchar = VarCharRef::ref_from_bytes(bytes).unwrap();
-                let split = varchar.split_at(varchar.header.len() as usize).unwrap();
-                let (varchar, _) = split.via_immutable();
-                let varchar = varchar.to_owned();
+            DataType::VarChar(_) => {
+                let (len, offset) = VarCharHeader::read(bytes);
+                let varchar = String::from_utf8(bytes[offset..offset + len].to_vec()).unwrap();
                 Self::VarChar(varchar)
             }
+            DataType::Char(_) | DataType::Decimal { .. } => unreachable!(),
+        }
+    }
+
+    /// Returns the size of the value of type `data_type` stored at the start
+    /// of `bytes`, without decoding it.
+    pub fn size_from_bytes(bytes: &[u8], data_type: DataType) -> usize {
+        match data_type.storage_type() {
+            DataType::Boolean => std::mem::size_of::<u8>(),
+            DataType::Integer => std::mem::size_of::<i64>(),
+            DataType::Float => std::mem::size_of::<f64>(),
+            DataType::VarChar(_) => {
+                let (len, offset) = VarCharHeader::read(bytes);
+                offset + len
+            }
+            DataType::Char(_) | DataType::Decimal { .. } => unreachable!(),
         }
     }
 
@@ -83,7 +113,7 @@ impl Value {
             Value::Boolean(_) => 0,
             Value::Integer(_) => 0,
             Value::Float(_) => 0,
-            Value::VarChar(_) => ValueHeader::SIZE,
+            Value::VarChar(varchar) => VarCharHeader::size(varchar.len()),
             Value::Null => 0,
         }
     }
@@ -103,7 +133,7 @@ impl Value {
             Value::Boolean(_) => Some(DataType::Boolean),
             Value::Integer(_) => Some(DataType::Integer),
             Value::Float(_) => Some(DataType::Float),
-            Value::VarChar(_) => Some(DataType::VarChar),
+            Value::VarChar(_) => Some(DataType::VarChar(None)),
             Value::Null => None,
         }
     }
@@ -111,6 +141,106 @@ impl Value {
     pub fn is_null(&self) -> bool {
         matches!(self, Value::Null)
     }
+
+    /// Converts this value to `to`, as `CAST(value AS to)` does. NULL casts to
+    /// NULL. Strings are truncated to the length of the target type and
+    /// decimals are rounded to its scale.
+    pub fn cast(&self, to: DataType) -> Result<Value, CastError> {
+        let unsupported = || CastError::Unsupported {
+            from: self.data_type().expect("NULL is handled first"),
+            to,
+        };
+        let invalid_text = |text: &str| CastError::InvalidText {
+            text: text.to_string(),
+            to,
+        };
+
+        let value = match (self, to.storage_type()) {
+            (Value::Null, _) => Value::Null,
+            (Value::Boolean(b), DataType::Boolean) => Value::Boolean(*b),
+            (Value::Boolean(b), DataType::Integer) => Value::Integer(*b as i64),
+            (Value::Integer(i), DataType::Boolean) => Value::Boolean(*i != 0),
+            (Value::Integer(i), DataType::Integer) => Value::Integer(*i),
+            (Value::Integer(i), DataType::Float) => Value::Float(*i as f64),
+            (Value::Float(f), DataType::Integer) => {
+                // Rounds half away from zero. `i64::MAX as f64` is 2^63, which
+                // is already out of range.
+                let f = f.round();
+                if f.is_nan() || f < i64::MIN as f64 || f >= i64::MAX as f64 {
+                    return Err(CastError::OutOfRange(to));
+                }
+                Value::Integer(f as i64)
+            }
+            (Value::Float(f), DataType::Float) => Value::Float(*f),
+            (Value::VarChar(s), DataType::Boolean) => {
+                let s = s.trim();
+                if ["true", "t"].iter().any(|b| s.eq_ignore_ascii_case(b)) {
+                    Value::Boolean(true)
+                } else if ["false", "f"].iter().any(|b| s.eq_ignore_ascii_case(b)) {
+                    Value::Boolean(false)
+                } else {
+                    return Err(invalid_text(s));
+                }
+            }
+            (Value::VarChar(s), DataType::Integer) => {
+                Value::Integer(s.trim().parse().map_err(|_| invalid_text(s))?)
+            }
+            (Value::VarChar(s), DataType::Float) => {
+                Value::Float(s.trim().parse().map_err(|_| invalid_text(s))?)
+            }
+            (Value::Boolean(b), DataType::VarChar(_)) => Value::VarChar(b.to_string()),
+            (Value::Integer(i), DataType::VarChar(_)) => Value::VarChar(i.to_string()),
+            (Value::Float(f), DataType::VarChar(_)) => Value::VarChar(f.to_string()),
+            (Value::VarChar(s), DataType::VarChar(_)) => Value::VarChar(s.clone()),
+            _ => return Err(unsupported()),
+        };
+
+        Ok(match (value, to) {
+            (Value::VarChar(s), DataType::VarChar(Some(n)) | DataType::Char(n)) => {
+                Value::VarChar(s.chars().take(n as usize).collect())
+            }
+            (Value::Float(f), DataType::Decimal { precision, scale }) => {
+                let factor = 10f64.powi(scale as i32);
+                let f = (f * factor).round() / factor;
+                if f.is_nan() || f.abs() >= 10f64.powi((precision - scale) as i32) {
+                    return Err(CastError::OutOfRange(to));
+                }
+                Value::Float(f)
+            }
+            (value, _) => value,
+        })
+    }
+
+    /// Compares two values of possibly different types, as comparison
+    /// operators do. Operands are first coerced to a common type: an integer
+    /// compared to a float is compared as a float, a boolean compared to an
+    /// integer as an integer, and a string compared to any other type is cast
+    /// to that type. Returns `None` if either side is NULL.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, CastError> {
+        let (Some(lhs_type), Some(rhs_type)) = (self.data_type(), other.data_type()) else {
+            return Ok(None);
+        };
+
+        let common_type = match (lhs_type, rhs_type) {
+            (lhs, rhs) if lhs == rhs => lhs,
+            (DataType::Integer, DataType::Float) | (DataType::Float, DataType::Integer) => {
+                DataType::Float
+            }
+            (DataType::Boolean, DataType::Integer) | (DataType::Integer, DataType::Boolean) => {
+                DataType::Integer
+            }
+            (DataType::VarChar(_), other) | (other, DataType::VarChar(_)) => other,
+            (from, to) => return Err(CastError::Unsupported { from, to }),
+        };
+
+        let (lhs, rhs) = (self.cast(common_type)?, other.cast(common_type)?);
+        // NaN is equal to itself, see `PartialEq`.
+        if lhs == rhs {
+            Ok(Some(Ordering::Equal))
+        } else {
+            Ok(lhs.partial_cmp(&rhs))
+        }
+    }
 }
 
 impl Serialize for Value {
@@ -131,9 +261,8 @@ impl Serialize for Value {
                 f.write_to(&mut dst[0..8]).unwrap();
             }
             Value::VarChar(s) => {
-                let header = ValueHeader::new(s.len());
-                let offset = ValueHeader::SIZE;
-                header.write_to(&mut dst[..offset]).unwrap();
+                VarCharHeader::write(s.len(), dst);
+                let offset = VarCharHeader::size(s.len());
                 let src = s.as_bytes();
                 src.write_to(&mut dst[offset..offset + src.len()]).unwrap();
             }
@@ -166,6 +295,33 @@ impl PartialEq for Value {
     }
 }
 
+// `eq` is reflexive since NaN is equal to itself.
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Boolean(b) => b.hash(state),
+            Self::Integer(i) => i.hash(state),
+            Self::Float(f) => {
+                // Values that compare equal must hash the same: all NaNs and
+                // both zeros are folded into a single representation.
+                let f = if f.is_nan() {
+                    f64::NAN
+                } else if *f == 0.0 {
+                    0.0
+                } else {
+                    *f
+                };
+                f.to_bits().hash(state);
+            }
+            Self::VarChar(s) => s.hash(state),
+            Self::Null => {}
+        }
+    }
+}
+
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         match (self, other) {
@@ -191,7 +347,13 @@ impl PartialOrd for Value {
 
 #[cfg(test)]
 mod tests {
-    use super::Value;
+    use super::{CastError, Value, VarCharHeader};
+
+    use std::cmp::Ordering;
+
+    fn varchar(s: &str) -> Value {
+        Value::VarChar(s.to_string())
+    }
 
     #[test]
     fn float_nan_eq() {
@@ -208,4 +370,122 @@ mod tests {
         assert!(Value::Float(f64::NEG_INFINITY) < Value::Float(f64::NAN));
         assert!(Value::Float(f64::NAN) > Value::Float(f64::NEG_INFINITY));
     }
+
+    #[test]
+    fn float_hash() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let state = RandomState::new();
+        assert_eq!(
+            state.hash_one(Value::Float(0.0)),
+            state.hash_one(Value::Float(-0.0))
+        );
+        assert_eq!(
+            state.hash_one(Value::Float(f64::NAN)),
+            state.hash_one(Value::Float(-f64::NAN))
+        );
+    }
+
+    #[test]
+    fn varchar_header() {
+        use crate::serialize::Serialize;
+        use crate::sql::schema::DataType;
+
+        for len in [0, 1, VarCharHeader::SHORT_MAX_LEN, 128, 3000] {
+            let value = varchar(&"a".repeat(len));
+            let header_size = if len <= VarCharHeader::SHORT_MAX_LEN {
+                1
+            } else {
+                2
+            };
+            assert_eq!(value.header_size(), header_size);
+
+            let mut bytes = vec![0; value.header_size() + value.data_size()];
+            value.write_bytes_to(&mut bytes);
+            assert_eq!(Value::from_bytes(&bytes, DataType::VarChar(None)), value);
+        }
+    }
+
+    #[test]
+    fn cast() {
+        use crate::sql::schema::DataType;
+
+        let cast = |value: Value, to| value.cast(to).unwrap();
+        assert_eq!(cast(Value::Integer(1), DataType::Float), Value::Float(1.0));
+        assert_eq!(
+            cast(Value::Float(-2.5), DataType::Integer),
+            Value::Integer(-3)
+        );
+        assert_eq!(
+            cast(Value::Boolean(true), DataType::Integer),
+            Value::Integer(1)
+        );
+        assert_eq!(
+            cast(Value::Integer(0), DataType::Boolean),
+            Value::Boolean(false)
+        );
+        assert_eq!(cast(varchar(" 42 "), DataType::Integer), Value::Integer(42));
+        assert_eq!(cast(varchar("1.5"), DataType::Float), Value::Float(1.5));
+        assert_eq!(
+            cast(varchar("TRUE"), DataType::Boolean),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            cast(Value::Float(1.5), DataType::VarChar(None)),
+            varchar("1.5")
+        );
+        assert_eq!(cast(varchar("hello"), DataType::Char(2)), varchar("he"));
+        assert_eq!(
+            cast(
+                Value::Float(1.255),
+                DataType::Decimal {
+                    precision: 3,
+                    scale: 1
+                }
+            ),
+            Value::Float(1.3)
+        );
+        assert_eq!(cast(Value::Null, DataType::Integer), Value::Null);
+
+        assert!(matches!(
+            Value::Float(1.5).cast(DataType::Boolean),
+            Err(CastError::Unsupported { .. })
+        ));
+        assert!(matches!(
+            varchar("abc").cast(DataType::Integer),
+            Err(CastError::InvalidText { .. })
+        ));
+        assert!(matches!(
+            Value::Float(1e19).cast(DataType::Integer),
+            Err(CastError::OutOfRange(DataType::Integer))
+        ));
+        assert!(matches!(
+            Value::Float(100.0).cast(DataType::Decimal {
+                precision: 3,
+                scale: 1
+            }),
+            Err(CastError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn compare_with_coercion() {
+        let compare = |lhs: Value, rhs: Value| lhs.compare(&rhs).unwrap();
+        assert_eq!(
+            compare(Value::Integer(42), varchar("42")),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare(Value::Integer(1), Value::Float(1.5)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare(Value::Boolean(true), Value::Integer(0)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare(Value::Null, Value::Integer(1)), None);
+
+        assert!(Value::Integer(1).compare(&varchar("one")).is_err());
+        assert!(Value::Boolean(true).compare(&Value::Float(1.0)).is_err());
+    }
 }
@@ -1,10 +1,17 @@
-use crate::pages::{PAGE_SIZE, Page, PageId};
+use crate::pages::{FileHeader, FileHeaderError, PAGE_RESERVED, Page, PageId};
+use crate::storage::doublewrite::DoubleWriteBuffer;
+use crate::storage::encryption::{EncryptionKey, PageCipher, remove_records};
+use crate::storage::segment::{SEGMENT_PAGES, SegmentedFile};
+use crate::storage::stats::StorageStats;
+
+use crate::config::CONFIG;
 
-use std::fs::{File, OpenOptions};
-use std::os::unix::fs::{FileExt, OpenOptionsExt};
 use std::path::Path;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
 
+use parking_lot::Mutex;
 use thiserror::Error;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -16,83 +23,187 @@ pub enum StorageError {
     Io(#[from] std::io::Error),
     #[error("file corrupted")]
     FileCorrupted,
+    #[error("page decryption failed")]
+    Decryption,
+    #[error("invalid file header: {0}")]
+    InvalidHeader(#[from] FileHeaderError),
+}
+
+/// Returns the reserved page of a new storage, holding its `FileHeader`.
+pub(crate) fn reserved_page() -> Page {
+    let mut page = Page::new();
+    <&mut FileHeader>::from(&mut page).init();
+    page
+}
+
+/// Reads the reserved page of a storage and validates its `FileHeader`.
+pub(crate) fn validate_header(storage: &impl StorageBackend) -> Result<(), StorageError> {
+    let mut page = Page::new();
+    storage.read_page(PAGE_RESERVED, &mut page)?;
+    <&FileHeader>::from(&page).validate()?;
+    Ok(())
 }
 
 pub trait StorageBackend: Sync + Send {
     fn read_page(&self, page_id: PageId, page: &mut Page) -> Result<(), StorageError>;
     fn write_page(&self, page: &Page, page_id: PageId) -> Result<(), StorageError>;
+    /// Reads a batch of pages, backends able to submit them at once override
+    /// it.
+    fn read_pages(&self, pages: &mut [(PageId, &mut Page)]) -> Result<(), StorageError> {
+        for (page_id, page) in pages {
+            self.read_page(*page_id, page)?;
+        }
+        Ok(())
+    }
+    /// Writes a batch of pages, backends able to submit them at once override
+    /// it.
+    fn write_pages(&self, pages: &[(&Page, PageId)]) -> Result<(), StorageError> {
+        for (page, page_id) in pages {
+            self.write_page(page, *page_id)?;
+        }
+        Ok(())
+    }
     fn fsync(&self);
     fn allocate_page(&self) -> Result<PageId, StorageError>;
+    /// Removes the pages after `last_page_id`.
+    fn truncate(&self, last_page_id: PageId) -> Result<(), StorageError>;
+    /// Gives the disk space of the pages from `first` to `last` back to the
+    /// operating system, the pages then read as new pages. By default, new
+    /// pages are written over them.
+    fn free_pages(&self, first: PageId, last: PageId) -> Result<(), StorageError> {
+        let pages = (first.get()..=last.get())
+            .map(|page_id| (Page::new(), PageId::new(page_id)))
+            .collect::<Vec<_>>();
+        let pages = pages
+            .iter()
+            .map(|(page, page_id)| (page, *page_id))
+            .collect::<Vec<_>>();
+        self.write_pages(&pages)
+    }
     fn first_page_id(&self) -> PageId;
     fn last_page_id(&self) -> PageId;
+    /// Returns the I/O counters of the backend, if it keeps them.
+    fn stats(&self) -> Option<Arc<StorageStats>> {
+        None
+    }
 }
 
 /// Manages the on-disk storage of table pages.
 ///
 /// The `Storage` struct is responsible for reading from and writing to the database file.
-/// It uses direct I/O to bypass the operating system's buffer cache, ensuring that data
-/// is written directly to the disk.
+/// It uses direct I/O to bypass the operating system's buffer cache when `CONFIG.DIRECT_IO`
+/// is set and the filesystem supports it, and falls back to buffered I/O otherwise.
+///
+/// The file is split into segments of `SEGMENT_PAGES` pages, `<file>`, `<file>.1`...
+///
+/// Pages go through a `DoubleWriteBuffer` before being written in place, so a
+/// page torn by a crash is repaired when the file is opened again.
+///
+/// Pages are encrypted on disk if the storage has a `PageCipher`.
+///
+/// Reads, writes and fsyncs are counted in its `StorageStats`.
 pub struct FileStorage {
-    file: File,
+    file: SegmentedFile,
     last_page_id: AtomicU32,
+    double_write: Mutex<DoubleWriteBuffer>,
+    cipher: Option<PageCipher>,
+    stats: Arc<StorageStats>,
 }
 
 impl FileStorage {
-    /// Creates a new storage file.
+    /// Creates a new storage file, encrypted with the key of
+    /// `CONFIG.ENCRYPTION_KEY_FILE` if set.
     ///
     /// Returns a `Result` containing the `Storage` instance if successful, or a `StorageError` on failure.
     pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .custom_flags(libc::O_DIRECT)
-            .open(path)
-            .map_err(StorageError::Io)?;
+        Self::create_with_key(path.as_ref(), EncryptionKey::from_config()?.as_ref())
+    }
+
+    /// Creates a new storage file encrypted with `key`.
+    pub fn create_encrypted<P: AsRef<Path>>(
+        path: P,
+        key: &EncryptionKey,
+    ) -> Result<Self, StorageError> {
+        Self::create_with_key(path.as_ref(), Some(key))
+    }
+
+    fn create_with_key(path: &Path, key: Option<&EncryptionKey>) -> Result<Self, StorageError> {
+        let file = SegmentedFile::create(path, SEGMENT_PAGES, CONFIG.DIRECT_IO)?;
+        let mut double_write = DoubleWriteBuffer::open(path)?;
+        double_write.clear()?;
+        let cipher = match key {
+            Some(key) => {
+                let cipher = PageCipher::open(path, key)?;
+                cipher.clear()?;
+                Some(cipher)
+            }
+            None => {
+                remove_records(path)?;
+                None
+            }
+        };
 
         let file = Self {
             file,
             last_page_id: AtomicU32::new(0),
+            double_write: Mutex::new(double_write),
+            cipher,
+            stats: Arc::default(),
         };
 
-        if file.file.metadata()?.len() == 0 {
-            // Create reserved page
-            let reserved_page = Page::new();
-            let reserved_page_id = PageId::new(0);
-            file.write_page(&reserved_page, reserved_page_id)?;
-            file.fsync();
-        }
+        // Create reserved page
+        file.write_page(&reserved_page(), PAGE_RESERVED)?;
+        file.fsync();
 
         Ok(file)
     }
 
-    /// Opens a new storage file.
+    /// Opens a storage file, encrypted with the key of
+    /// `CONFIG.ENCRYPTION_KEY_FILE` if set. Fails with `InvalidHeader` if it
+    /// isn't a storage file of this version.
     ///
     /// Returns a `Result` containing the `Storage` instance if successful, or a `StorageError` on failure.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(false)
-            .truncate(false)
-            .custom_flags(libc::O_DIRECT)
-            .open(path)
-            .map_err(StorageError::Io)?;
+        Self::open_with_key(path.as_ref(), EncryptionKey::from_config()?.as_ref())
+    }
+
+    /// Opens a storage file encrypted with `key`.
+    pub fn open_encrypted<P: AsRef<Path>>(
+        path: P,
+        key: &EncryptionKey,
+    ) -> Result<Self, StorageError> {
+        Self::open_with_key(path.as_ref(), Some(key))
+    }
+
+    fn open_with_key(path: &Path, key: Option<&EncryptionKey>) -> Result<Self, StorageError> {
+        let file = SegmentedFile::open(path, SEGMENT_PAGES, CONFIG.DIRECT_IO)?;
+        // Repairs the pages torn by a crash, before the file length is read:
+        // pages written back may extend it.
+        let mut double_write = DoubleWriteBuffer::open(path)?;
+        double_write.recover(&file)?;
 
-        let len = file.metadata()?.len() as usize;
-        if len == 0 || !len.is_multiple_of(PAGE_SIZE) {
+        let num_pages = file.num_pages()?;
+        if num_pages == 0 {
             return Err(StorageError::FileCorrupted);
         }
 
-        let last_page_id = (len / PAGE_SIZE) as u32 - 1;
+        let last_page_id = num_pages as u32 - 1;
         let file = Self {
             file,
             last_page_id: AtomicU32::new(last_page_id),
+            double_write: Mutex::new(double_write),
+            cipher: key.map(|key| PageCipher::open(path, key)).transpose()?,
+            stats: Arc::default(),
         };
+        validate_header(&file)?;
 
         Ok(file)
     }
+
+    /// Returns whether the file bypasses the operating system's cache.
+    pub fn is_direct_io(&self) -> bool {
+        self.file.is_direct_io()
+    }
 }
 
 impl StorageBackend for FileStorage {
@@ -100,22 +211,43 @@ impl StorageBackend for FileStorage {
     ///
     /// Returns an empty `Result` if successful, or a `StorageError` on failure.
     fn read_page(&self, page_id: PageId, page: &mut Page) -> Result<(), StorageError> {
-        let offset = page_id.get() as u64 * PAGE_SIZE as u64;
-
+        let start = Instant::now();
         self.file
-            .read_exact_at(page.data.as_mut_slice(), offset)
-            .map_err(StorageError::Io)
+            .read_exact_at(page.data.as_mut_slice(), page_id)
+            .map_err(StorageError::Io)?;
+        self.stats.record_read(1, start);
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(page_id, page),
+            None => Ok(()),
+        }
     }
 
-    /// Writes a page to the database file.
+    /// Writes a page to the database file, after writing its image to the
+    /// double-write buffer.
     ///
     /// Returns an empty `Result` if successful, or a `StorageError` on failure.
     fn write_page(&self, page: &Page, page_id: PageId) -> Result<(), StorageError> {
-        let offset = page_id.get() as u64 * PAGE_SIZE as u64;
+        // Held until the page is written in place: the slot of its image
+        // can't be reused before.
+        let mut double_write = self.double_write.lock();
+        // The file was truncated after the page was dirtied.
+        if page_id > self.last_page_id() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let ciphertext = match &self.cipher {
+            Some(cipher) => cipher.encrypt(&[(page, page_id)])?.pop(),
+            None => None,
+        };
+        let page = ciphertext.as_ref().unwrap_or(page);
 
-        self.file
-            .write_all_at(page.data.as_slice(), offset)
-            .map_err(StorageError::Io)
+        double_write.write(&self.file, &[(page, page_id)])?;
+        self.file.write_all_at(page.data.as_slice(), page_id)?;
+        if let Some(cipher) = &self.cipher {
+            cipher.commit(&[page_id]);
+        }
+        self.stats.record_write(1, start);
+        Ok(())
     }
 
     /// Attempts to sync file data and metadata to the disk.
@@ -126,7 +258,9 @@ impl StorageBackend for FileStorage {
     ///
     /// Panics if the underlying `File::sync_all` operation fails.
     fn fsync(&self) {
+        let start = Instant::now();
         let result = self.file.sync_all();
+        self.stats.record_fsync(start);
         if result.is_err() {
             // if fsync fails, we can't make sure data is flushed to disk
             // ref: https://wiki.postgresql.org/wiki/Fsync_Errors
@@ -135,17 +269,57 @@ impl StorageBackend for FileStorage {
     }
 
     /// Allocates a new page and returns its id.
+    ///
+    /// The file is preallocated `CONFIG.EXTENSION_PAGES` pages at a time,
+    /// the pages are still written as they are allocated.
     fn allocate_page(&self) -> Result<PageId, StorageError> {
         let last_page_id = self.last_page_id.fetch_add(1, Ordering::Relaxed) + 1;
         let new_page_id = PageId::new(last_page_id);
         let new_page = Page::new();
-        // TODO: could use posix_fallocate.
+        if last_page_id.is_multiple_of(CONFIG.EXTENSION_PAGES) {
+            self.file.preallocate(new_page_id, CONFIG.EXTENSION_PAGES)?;
+        }
         self.write_page(&new_page, new_page_id)?;
         Ok(new_page_id)
     }
 
+    /// Shrinks the file to end with `last_page_id`.
+    fn truncate(&self, last_page_id: PageId) -> Result<(), StorageError> {
+        let mut double_write = self.double_write.lock();
+        // The images of the removed pages would extend the file again on
+        // recovery: they are dropped once the pages kept are durable.
+        self.file.sync_data()?;
+        double_write.clear()?;
+
+        self.file.truncate(last_page_id.get() as u64 + 1)?;
+        self.last_page_id
+            .store(last_page_id.get(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Punches a hole in the file over the pages freed. Encrypted pages are
+    /// overwritten instead, an empty ciphertext wouldn't decrypt.
+    fn free_pages(&self, first: PageId, last: PageId) -> Result<(), StorageError> {
+        if self.cipher.is_some() {
+            for page_id in first.get()..=last.get() {
+                self.write_page(&Page::new(), PageId::new(page_id))?;
+            }
+            return Ok(());
+        }
+
+        let mut double_write = self.double_write.lock();
+        // The images of the pages freed would be written back on recovery.
+        self.file.sync_data()?;
+        double_write.clear()?;
+
+        self.file.punch_hole(first, last.get() - first.get() + 1)?;
+        Ok(())
+    }
+
+    /// Returns the first page after the reserved page, which holds the file
+    /// header.
     fn first_page_id(&self) -> PageId {
-        PageId::new(0)
+        PageId::new(1)
     }
 
     /// Retreives the last allocated page id.
@@ -154,4 +328,86 @@ impl StorageBackend for FileStorage {
     fn last_page_id(&self) -> PageId {
         PageId::new(self.last_page_id.load(Ordering::Relaxed))
     }
+
+    fn stats(&self) -> Option<Arc<StorageStats>> {
+        Some(Arc::clone(&self.stats))
+    }
+}
+
+impl Drop for FileStorage {
+    /// Removes the double-write buffer once all the pages are written in
+    /// place and synced.
+    fn drop(&mut self) {
+        if self.file.sync_all().is_ok() {
+            let _ = std::fs::remove_file(self.double_write.get_mut().path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+
+    use crate::pages::PAGE_SIZE;
+    use crate::storage::FileExt;
+
+    use super::*;
+
+    fn page(byte: u8) -> Page {
+        Page {
+            data: [byte; PAGE_SIZE],
+        }
+    }
+
+    fn read(storage: &FileStorage, page_id: PageId) -> Page {
+        let mut page = Page::new();
+        storage.read_page(page_id, &mut page).unwrap();
+        page
+    }
+
+    #[test]
+    fn torn_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.tbl");
+        let dwb_path = dir.path().join("users.tbl.dwb");
+
+        let storage = FileStorage::create(&path).unwrap();
+        let (a, b) = (
+            storage.allocate_page().unwrap(),
+            storage.allocate_page().unwrap(),
+        );
+        storage.write_page(&page(1), a).unwrap();
+        storage.write_page(&page(2), b).unwrap();
+        storage.write_page(&page(3), b).unwrap();
+
+        // Crash halfway through the write of `a`, and through the last image
+        // of `b` which then wasn't written in place.
+        let offset = |page_id: PageId| page_id.get() as u64 * PAGE_SIZE as u64;
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all_at(&[0xff; PAGE_SIZE / 2], offset(a))
+            .unwrap();
+        file.write_all_at(&page(2).data, offset(b)).unwrap();
+        let dwb = OpenOptions::new().write(true).open(&dwb_path).unwrap();
+        let last_slot = dwb.metadata().unwrap().len() - PAGE_SIZE as u64 / 2;
+        dwb.write_all_at(&[0xff; PAGE_SIZE / 2], last_slot).unwrap();
+        std::mem::forget(storage);
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(read(&storage, a).data, page(1).data);
+        assert_eq!(read(&storage, b).data, page(2).data);
+        assert_eq!(std::fs::metadata(&dwb_path).unwrap().len(), 0);
+
+        drop(storage);
+        assert!(!dwb_path.exists());
+    }
+
+    #[test]
+    fn foreign_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), page(b'x').data).unwrap();
+        assert!(matches!(
+            FileStorage::open(file.path()),
+            Err(StorageError::InvalidHeader(FileHeaderError::BadMagic))
+        ));
+    }
 }
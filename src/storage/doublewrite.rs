@@ -0,0 +1,136 @@
+use crate::pages::{PAGE_SIZE, Page, PageId};
+use crate::storage::os::FileExt;
+use crate::storage::segment::SegmentedFile;
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// The number of page images kept in the buffer: the storage file is synced
+/// each time the ring wraps around, before images are overwritten.
+pub const DOUBLE_WRITE_SLOTS: u64 = 32;
+
+// checksum (u64), sequence number (u64), page id (u32), reserved (u32)
+const HEADER_SIZE: usize = 24;
+const SLOT_SIZE: usize = HEADER_SIZE + PAGE_SIZE;
+
+/// A ring of page images written and synced before the pages are written in
+/// place, stored next to the storage file as `<file>.dwb`.
+///
+/// A crash in the middle of a write can leave a half-written page in the
+/// storage file, but then its image is complete in the buffer: images are
+/// written back in the order they were written when the storage is opened
+/// again. An image torn by the crash fails its checksum and is skipped, the
+/// page itself wasn't written yet.
+pub struct DoubleWriteBuffer {
+    file: File,
+    path: PathBuf,
+    next_seq: u64,
+}
+
+impl DoubleWriteBuffer {
+    /// Opens the buffer of the storage file at `path`, creating it if needed.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let mut dwb_path = OsString::from(path.as_os_str());
+        dwb_path.push(".dwb");
+        let path = PathBuf::from(dwb_path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        Ok(Self {
+            file,
+            path,
+            next_seq: 0,
+        })
+    }
+
+    /// Drops all the images, for a new storage file or once all the pages
+    /// written are durable.
+    pub fn clear(&mut self) -> std::io::Result<()> {
+        self.file.set_len(0)?;
+        self.next_seq = 0;
+        Ok(())
+    }
+
+    /// Writes the complete images of the buffer back to the storage file then
+    /// empties the buffer. Returns the number of pages written back.
+    pub fn recover(&mut self, storage: &SegmentedFile) -> std::io::Result<usize> {
+        let slots = self.file.metadata()?.len() / SLOT_SIZE as u64;
+        let mut images = Vec::new();
+        let mut slot = vec![0; SLOT_SIZE];
+        for i in 0..slots {
+            self.file.read_exact_at(&mut slot, i * SLOT_SIZE as u64)?;
+            let (header, data) = slot.split_at(HEADER_SIZE);
+            let checksum = u64::from_le_bytes(header[0..8].try_into().unwrap());
+            if checksum != Self::checksum(&header[8..], data) {
+                continue;
+            }
+            let seq = u64::from_le_bytes(header[8..16].try_into().unwrap());
+            let page_id = u32::from_le_bytes(header[16..20].try_into().unwrap());
+            let mut page = Page::new();
+            page.data.copy_from_slice(data);
+            images.push((seq, PageId::new(page_id), page));
+        }
+
+        // The same page can have several images, the newest one is written
+        // back last.
+        images.sort_by_key(|(seq, _, _)| *seq);
+        for (_, page_id, page) in &images {
+            storage.write_all_at(page.data.as_slice(), *page_id)?;
+        }
+        storage.sync_all()?;
+
+        self.clear()?;
+        self.file.sync_all()?;
+        Ok(images.len())
+    }
+
+    /// Writes the images of a batch of at most `DOUBLE_WRITE_SLOTS` pages and
+    /// syncs them once, the pages can then be written in place. The storage
+    /// file is synced first if a slot to reuse holds the image of a page which
+    /// may not be durable yet.
+    pub fn write(
+        &mut self,
+        storage: &SegmentedFile,
+        pages: &[(&Page, PageId)],
+    ) -> std::io::Result<()> {
+        debug_assert!(pages.len() as u64 <= DOUBLE_WRITE_SLOTS);
+        for (page, page_id) in pages {
+            let seq = self.next_seq;
+            if seq > 0 && seq.is_multiple_of(DOUBLE_WRITE_SLOTS) {
+                storage.sync_data()?;
+            }
+
+            let mut header = [0; HEADER_SIZE];
+            header[8..16].copy_from_slice(&seq.to_le_bytes());
+            header[16..20].copy_from_slice(&page_id.get().to_le_bytes());
+            let checksum = Self::checksum(&header[8..], page.data.as_slice());
+            header[0..8].copy_from_slice(&checksum.to_le_bytes());
+
+            let offset = (seq % DOUBLE_WRITE_SLOTS) * SLOT_SIZE as u64;
+            self.file.write_all_at(&header, offset)?;
+            self.file
+                .write_all_at(page.data.as_slice(), offset + HEADER_SIZE as u64)?;
+            self.next_seq += 1;
+        }
+        self.file.sync_data()
+    }
+
+    /// FNV-1a of an image and its header.
+    fn checksum(header: &[u8], data: &[u8]) -> u64 {
+        header
+            .iter()
+            .chain(data)
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
@@ -0,0 +1,288 @@
+use crate::config::CONFIG;
+use crate::pages::{Page, PageId};
+use crate::storage::StorageError;
+use crate::storage::os::FileExt;
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use aes_gcm::aead::AeadInPlace;
+use aes_gcm::aead::consts::U12;
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce, Tag};
+use parking_lot::Mutex;
+
+/// The size of an AES-256 key.
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+
+// counter (u64), tag (16 bytes)
+const RECORD_SIZE: usize = 24;
+const SLOTS: usize = 2;
+
+fn records_path(path: &Path) -> PathBuf {
+    let mut records_path = OsString::from(path.as_os_str());
+    records_path.push(".enc");
+    PathBuf::from(records_path)
+}
+
+/// Removes the records of a storage file, if it is encrypted.
+pub fn remove_records(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(records_path(path)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Copies the records of a storage file to `to`, if it is encrypted, and
+/// syncs them.
+pub fn copy_records(from: &Path, to: &Path) -> io::Result<()> {
+    let to = records_path(to);
+    match std::fs::copy(records_path(from), &to) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => File::open(to)?.sync_all().and(result.map(|_| ())),
+    }
+}
+
+/// A key pages are encrypted with.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; ENCRYPTION_KEY_SIZE]);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; ENCRYPTION_KEY_SIZE]) -> Self {
+        Self(key)
+    }
+
+    /// Loads a key file, holding the `ENCRYPTION_KEY_SIZE` raw bytes of the
+    /// key.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let key = bytes.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("an encryption key is {ENCRYPTION_KEY_SIZE} bytes"),
+            )
+        })?;
+        Ok(Self(key))
+    }
+
+    /// Loads the key file of `CONFIG.ENCRYPTION_KEY_FILE`, if any.
+    pub fn from_config() -> io::Result<Option<Self>> {
+        CONFIG
+            .ENCRYPTION_KEY_FILE
+            .as_ref()
+            .map(Self::load)
+            .transpose()
+    }
+}
+
+/// Encrypts the pages of a storage file with AES-256-GCM.
+///
+/// The nonce of a page is its id and a counter incremented on each write, so
+/// a nonce is never reused with the same key. Ciphertexts are the size of
+/// pages, the counter and the tag of a page are stored in `<file>.enc`.
+///
+/// Each page has two records there: the record of a write goes to the slot
+/// which doesn't authenticate the page on disk, and is synced before the page
+/// is written in place. Whether a crash happens before or after the page is
+/// written, one of the two authenticates it.
+pub struct PageCipher {
+    cipher: Aes256Gcm,
+    records: File,
+    next_counter: AtomicU64,
+    // The slot authenticating each page on disk, as far as it is known.
+    slots: Mutex<Vec<Option<u8>>>,
+}
+
+impl PageCipher {
+    /// Opens the records of the storage file at `path`, creating them if
+    /// needed.
+    pub fn open(path: &Path, key: &EncryptionKey) -> io::Result<Self> {
+        let records = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(records_path(path))?;
+
+        // The counters of the pages on disk are durable: the next one is
+        // greater than all of them.
+        let len = records.metadata()?.len() as usize;
+        let mut bytes = vec![0; len - len % RECORD_SIZE];
+        records.read_exact_at(&mut bytes, 0)?;
+        let max_counter = bytes
+            .chunks(RECORD_SIZE)
+            .map(|record| u64::from_le_bytes(record[..8].try_into().unwrap()))
+            .max()
+            .unwrap_or(0);
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(&key.0.into()),
+            records,
+            next_counter: AtomicU64::new(max_counter + 1),
+            slots: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Drops all the records, for a new storage file.
+    pub fn clear(&self) -> io::Result<()> {
+        self.records.set_len(0)?;
+        self.slots.lock().clear();
+        Ok(())
+    }
+
+    fn nonce(page_id: PageId, counter: u64) -> Nonce<U12> {
+        let mut nonce = [0; 12];
+        nonce[..4].copy_from_slice(&page_id.get().to_le_bytes());
+        nonce[4..].copy_from_slice(&counter.to_le_bytes());
+        nonce.into()
+    }
+
+    fn offset(page_id: PageId, slot: u8) -> u64 {
+        (page_id.get() as u64 * SLOTS as u64 + slot as u64) * RECORD_SIZE as u64
+    }
+
+    fn slot(&self, page_id: PageId) -> Option<u8> {
+        let slots = self.slots.lock();
+        slots.get(page_id.get() as usize).copied().flatten()
+    }
+
+    fn set_slot(&self, page_id: PageId, slot: u8) {
+        let mut slots = self.slots.lock();
+        let idx = page_id.get() as usize;
+        if slots.len() <= idx {
+            slots.resize(idx + 1, None);
+        }
+        slots[idx] = Some(slot);
+    }
+
+    /// Encrypts a batch of pages and makes their records durable. Returns the
+    /// ciphertexts to write in place, then `commit` the batch.
+    pub fn encrypt(&self, pages: &[(&Page, PageId)]) -> Result<Vec<Page>, StorageError> {
+        let mut ciphertexts = Vec::with_capacity(pages.len());
+        for (page, page_id) in pages {
+            let counter = self.next_counter.fetch_add(1, Ordering::Relaxed);
+            let mut ciphertext = Page::new();
+            ciphertext.data.copy_from_slice(&page.data);
+            let tag = self
+                .cipher
+                .encrypt_in_place_detached(
+                    &Self::nonce(*page_id, counter),
+                    &[],
+                    &mut ciphertext.data,
+                )
+                .expect("a page fits in a single message");
+
+            let mut record = [0; RECORD_SIZE];
+            record[..8].copy_from_slice(&counter.to_le_bytes());
+            record[8..].copy_from_slice(&tag);
+            let slot = self.slot(*page_id).map_or(0, |slot| 1 - slot);
+            self.records
+                .write_all_at(&record, Self::offset(*page_id, slot))?;
+            ciphertexts.push(ciphertext);
+        }
+        self.records.sync_data()?;
+        Ok(ciphertexts)
+    }
+
+    /// Records that the pages of a batch are written in place: the next write
+    /// of each page goes to the other slot.
+    pub fn commit(&self, page_ids: &[PageId]) {
+        for page_id in page_ids {
+            let slot = self.slot(*page_id).map_or(0, |slot| 1 - slot);
+            self.set_slot(*page_id, slot);
+        }
+    }
+
+    /// Decrypts a page read from disk, in place. Fails with `Decryption` if
+    /// neither of its records authenticates it, e.g. with the wrong key.
+    pub fn decrypt(&self, page_id: PageId, page: &mut Page) -> Result<(), StorageError> {
+        let ciphertext = page.data;
+        for slot in 0..SLOTS as u8 {
+            let mut record = [0; RECORD_SIZE];
+            match self
+                .records
+                .read_exact_at(&mut record, Self::offset(page_id, slot))
+            {
+                // The slot was never written.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => continue,
+                result => result?,
+            }
+
+            let counter = u64::from_le_bytes(record[..8].try_into().unwrap());
+            let tag = Tag::from_slice(&record[8..]);
+            let nonce = Self::nonce(page_id, counter);
+            if counter > 0
+                && self
+                    .cipher
+                    .decrypt_in_place_detached(&nonce, &[], &mut page.data, tag)
+                    .is_ok()
+            {
+                self.set_slot(page_id, slot);
+                return Ok(());
+            }
+            page.data = ciphertext;
+        }
+        Err(StorageError::Decryption)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pages::PAGE_SIZE;
+    use crate::storage::{FileStorage, StorageBackend};
+
+    #[test]
+    fn encrypted_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.tbl");
+        let key = EncryptionKey::new([7; ENCRYPTION_KEY_SIZE]);
+
+        let storage = FileStorage::create_encrypted(&path, &key).unwrap();
+        let page_id = storage.allocate_page().unwrap();
+        let mut page = Page::new();
+        page.data[..12].copy_from_slice(b"hello world!");
+        storage.write_page(&page, page_id).unwrap();
+        page.data[..12].copy_from_slice(b"hello again!");
+        storage.write_page(&page, page_id).unwrap();
+        drop(storage);
+
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file.len(), 2 * PAGE_SIZE);
+        assert!(!file.windows(5).any(|bytes| bytes == b"hello"));
+
+        let storage = FileStorage::open_encrypted(&path, &key).unwrap();
+        let mut read = Page::new();
+        storage.read_page(page_id, &mut read).unwrap();
+        assert_eq!(&read.data[..12], b"hello again!");
+        drop(storage);
+
+        // A crash after the record of a write is durable, before the page is
+        // written: the previous record still authenticates the page.
+        let cipher = PageCipher::open(&path, &key).unwrap();
+        let mut raw = Page::new();
+        let offset = page_id.get() as u64 * PAGE_SIZE as u64;
+        File::open(&path)
+            .unwrap()
+            .read_exact_at(&mut raw.data, offset)
+            .unwrap();
+        cipher.decrypt(page_id, &mut raw).unwrap();
+        page.data[..12].copy_from_slice(b"lost write!!");
+        cipher.encrypt(&[(&page, page_id)]).unwrap();
+        drop(cipher);
+        let storage = FileStorage::open_encrypted(&path, &key).unwrap();
+        storage.read_page(page_id, &mut read).unwrap();
+        assert_eq!(&read.data[..12], b"hello again!");
+        drop(storage);
+
+        // The header in the reserved page can't be read either.
+        let wrong_key = EncryptionKey::new([8; ENCRYPTION_KEY_SIZE]);
+        assert!(matches!(
+            FileStorage::open_encrypted(&path, &wrong_key),
+            Err(StorageError::Decryption)
+        ));
+    }
+}
@@ -5,6 +5,8 @@ use std::path::{Path, PathBuf};
 
 use regex::Regex;
 
+use crate::storage::encryption::{copy_records, remove_records};
+use crate::storage::segment::{copy_segments, remove_segments};
 use crate::storage::{FileStorage, StorageError};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -57,20 +59,25 @@ impl TableName {
     }
 }
 
+/// A table or index file, stored as `<name>.tbl` or `<name>.idx` in the
+/// database directory.
+///
+/// Indexes are named like tables and share their namespace in the catalog.
 #[derive(Debug)]
 pub struct TableFile {
     name: TableName,
     path: PathBuf,
-    // primary_index: TableName
-    // primary_index_path: PathBuf
 }
 
 impl TableFile {
-    fn new(db: &DatabaseDirectory, table_name: &TableName) -> Result<Self> {
+    const TABLE_EXTENSION: &str = "tbl";
+    const INDEX_EXTENSION: &str = "idx";
+
+    fn new(db: &DatabaseDirectory, table_name: &TableName, extension: &str) -> Result<Self> {
         let path = db
             .path
             .as_path()
-            .join(format!("{}.tbl", table_name.as_str()));
+            .join(format!("{}.{}", table_name.as_str(), extension));
         FileStorage::create(&path).unwrap();
         Self::from_path(path)
     }
@@ -79,16 +86,8 @@ impl TableFile {
         let table_path = table_path.as_ref();
 
         if table_path.is_file() {
-            let name = TableName::try_from(
-                table_path
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .strip_suffix(".tbl")
-                    .unwrap(),
-            )
-            .map_err(|_| Error::from(ErrorKind::InvalidFilename))?;
+            let name = TableName::try_from(table_path.file_stem().unwrap().to_str().unwrap())
+                .map_err(|_| Error::from(ErrorKind::InvalidFilename))?;
 
             Ok(Self {
                 name,
@@ -99,6 +98,12 @@ impl TableFile {
         }
     }
 
+    fn is_index(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|e| e == Self::INDEX_EXTENSION)
+    }
+
     pub fn path(&self) -> &Path {
         self.path.as_path()
     }
@@ -113,6 +118,7 @@ pub struct DatabaseDirectory {
     name: DatabaseName,
     path: PathBuf,
     tables: HashMap<TableName, TableFile>,
+    indexes: HashMap<TableName, TableFile>,
 }
 
 impl DatabaseDirectory {
@@ -127,12 +133,25 @@ impl DatabaseDirectory {
 
         if db_dir.is_dir() {
             let mut tables = HashMap::new();
+            let mut indexes = HashMap::new();
             for entry in fs::read_dir(db_dir)? {
-                if let Ok(entry) = entry
-                    && entry.path().is_file()
-                    && let Ok(table) = TableFile::from_path(entry.path())
+                let Ok(entry) = entry else {
+                    continue;
+                };
+                let path = entry.path();
+                let extension = path.extension().and_then(|e| e.to_str());
+                if path.is_file()
+                    && matches!(
+                        extension,
+                        Some(TableFile::TABLE_EXTENSION | TableFile::INDEX_EXTENSION)
+                    )
+                    && let Ok(file) = TableFile::from_path(&path)
                 {
-                    tables.insert(table.name.clone(), table);
+                    if file.is_index() {
+                        indexes.insert(file.name.clone(), file);
+                    } else {
+                        tables.insert(file.name.clone(), file);
+                    }
                 }
             }
 
@@ -143,6 +162,7 @@ impl DatabaseDirectory {
                 name,
                 path: db_dir.to_path_buf(),
                 tables,
+                indexes,
             })
         } else {
             Err(Error::from(ErrorKind::NotADirectory))
@@ -151,7 +171,7 @@ impl DatabaseDirectory {
 
     fn create_table(&mut self, table_name: &TableName) -> Result<&TableFile> {
         if !self.tables.contains_key(table_name) {
-            let table = TableFile::new(self, table_name)?;
+            let table = TableFile::new(self, table_name, TableFile::TABLE_EXTENSION)?;
             Ok(self.tables.entry(table_name.clone()).or_insert(table))
         } else {
             Err(Error::from(ErrorKind::AlreadyExists))
@@ -159,15 +179,39 @@ impl DatabaseDirectory {
     }
 
     fn drop_table(&mut self, table_name: &TableName) -> Result<()> {
-        if self.tables.remove(table_name).is_some() {
-            let path = self
-                .path
-                .as_path()
-                .join(format!("{}.tbl", table_name.as_str()));
-            fs::remove_file(path)
+        match self.tables.remove(table_name) {
+            Some(table) => remove_segments(table.path()).and_then(|_| remove_records(table.path())),
+            None => Err(Error::from(ErrorKind::NotFound)),
+        }
+    }
+
+    fn create_index(&mut self, index_name: &TableName) -> Result<&TableFile> {
+        if !self.indexes.contains_key(index_name) {
+            let index = TableFile::new(self, index_name, TableFile::INDEX_EXTENSION)?;
+            Ok(self.indexes.entry(index_name.clone()).or_insert(index))
         } else {
-            Err(Error::from(ErrorKind::NotFound))
+            Err(Error::from(ErrorKind::AlreadyExists))
+        }
+    }
+
+    fn drop_index(&mut self, index_name: &TableName) -> Result<()> {
+        match self.indexes.remove(index_name) {
+            Some(index) => remove_segments(index.path()).and_then(|_| remove_records(index.path())),
+            None => Err(Error::from(ErrorKind::NotFound)),
+        }
+    }
+
+    /// Copies the table and index files to `db_dir`, a new directory.
+    fn backup_to(&self, db_dir: &Path) -> Result<()> {
+        fs::create_dir(db_dir)?;
+        for file in self.tables.values().chain(self.indexes.values()) {
+            let to = db_dir.join(file.path().file_name().unwrap());
+            copy_segments(file.path(), &to)?;
+            // After the pages: the records of pages written meanwhile are
+            // still there, see `PageCipher`.
+            copy_records(file.path(), &to)?;
         }
+        fs::File::open(db_dir)?.sync_all()
     }
 }
 
@@ -199,6 +243,14 @@ impl DatabaseRootDirectory {
         }
     }
 
+    pub fn path(&self) -> &Path {
+        self.root_dir.as_path()
+    }
+
+    pub fn has_database(&self, db_name: &DatabaseName) -> bool {
+        self.databases.contains_key(db_name)
+    }
+
     pub fn get_database_mut(&mut self, db_name: &DatabaseName) -> Result<&mut DatabaseDirectory> {
         self.databases
             .get_mut(db_name)
@@ -255,6 +307,50 @@ impl DatabaseRootDirectory {
         let table = db.tables.get(table_name)?;
         Some(table.path())
     }
+
+    pub fn create_index(
+        &mut self,
+        db_name: &DatabaseName,
+        index_name: &TableName,
+    ) -> Result<&TableFile> {
+        let db = self
+            .databases
+            .get_mut(db_name)
+            .ok_or(Error::from(ErrorKind::NotFound))?;
+        let index = db.create_index(index_name)?;
+
+        Ok(index)
+    }
+
+    pub fn drop_index(&mut self, db_name: &DatabaseName, index_name: &TableName) -> Result<()> {
+        let db = self
+            .databases
+            .get_mut(db_name)
+            .ok_or(Error::from(ErrorKind::NotFound))?;
+        db.drop_index(index_name)?;
+
+        Ok(())
+    }
+
+    pub fn index_path(&self, db_name: &DatabaseName, index_name: &TableName) -> Option<&Path> {
+        let db = self.databases.get(db_name)?;
+        let index = db.indexes.get(index_name)?;
+        Some(index.path())
+    }
+
+    /// Copies all the databases to `root_dir`, a new directory, which can then
+    /// be opened as a root directory.
+    ///
+    /// The files are copied as they are on disk: the pages cached must be
+    /// flushed before.
+    pub fn backup_to<P: AsRef<Path>>(&self, root_dir: P) -> Result<()> {
+        let root_dir = root_dir.as_ref();
+        fs::create_dir(root_dir)?;
+        for db in self.databases.values() {
+            db.backup_to(&root_dir.join(db.name.as_str()))?;
+        }
+        fs::File::open(root_dir)?.sync_all()
+    }
 }
 
 #[cfg(test)]
@@ -273,4 +369,25 @@ mod tests {
         dbs.create_table(&db_name, &table_name).unwrap();
         dbs.drop_table(&db_name, &table_name).unwrap();
     }
+
+    #[test]
+    fn create_reopen_drop_index() {
+        let dir = TempDir::new().unwrap();
+        let mut dbs = DatabaseRootDirectory::from_path(dir.path()).unwrap();
+        let db_name = DatabaseName::try_from("my_db").unwrap();
+        let name = TableName::try_from("my_name").unwrap();
+        dbs.create_database(&db_name).unwrap();
+        dbs.create_table(&db_name, &name).unwrap();
+        // Tables and indexes live in separate files.
+        dbs.create_index(&db_name, &name).unwrap();
+        assert!(dbs.create_index(&db_name, &name).is_err());
+
+        let mut dbs = DatabaseRootDirectory::from_path(dir.path()).unwrap();
+        let table_path = dbs.table_path(&db_name, &name).unwrap();
+        let index_path = dbs.index_path(&db_name, &name).unwrap();
+        assert_ne!(table_path, index_path);
+        dbs.drop_index(&db_name, &name).unwrap();
+        assert!(dbs.index_path(&db_name, &name).is_none());
+        assert!(dbs.table_path(&db_name, &name).is_some());
+    }
 }
@@ -1,5 +1,25 @@
 mod backend;
+mod doublewrite;
+mod encryption;
 mod fs;
+mod object;
+mod os;
+#[cfg(feature = "s3")]
+mod s3;
+mod segment;
+mod stats;
+#[cfg(target_os = "linux")]
+mod uring;
 
 pub use backend::{FileStorage, StorageBackend, StorageError, StorageId};
+pub use encryption::{ENCRYPTION_KEY_SIZE, EncryptionKey};
 pub use fs::{DatabaseName, DatabaseRootDirectory, TableName};
+pub use object::{CHUNK_PAGES, LocalObjectStore, ObjectStorage, ObjectStore};
+pub use os::FileExt;
+#[cfg(feature = "s3")]
+pub use s3::S3ObjectStore;
+pub use segment::SEGMENT_PAGES;
+pub(crate) use segment::SegmentedFile;
+pub use stats::{LATENCY_BUCKETS, LatencyHistogram, StorageStats};
+#[cfg(target_os = "linux")]
+pub use uring::{URING_QUEUE_DEPTH, UringStorage};
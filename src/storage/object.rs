@@ -0,0 +1,373 @@
+use crate::pages::{PAGE_RESERVED, PAGE_SIZE, Page, PageId};
+use crate::storage::backend::{reserved_page, validate_header};
+use crate::storage::os::FileExt;
+use crate::storage::{StorageBackend, StorageError};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use parking_lot::Mutex;
+
+/// The number of pages of a chunk object.
+pub const CHUNK_PAGES: u32 = 256;
+
+// page id (u32), reserved (u32)
+const RECORD_HEADER_SIZE: usize = 8;
+const RECORD_SIZE: usize = RECORD_HEADER_SIZE + PAGE_SIZE;
+
+/// An object store, such as S3: objects are written whole and read by range.
+pub trait ObjectStore: Send + Sync {
+    /// Returns the bytes of an object, `ErrorKind::NotFound` if it doesn't
+    /// exist.
+    fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    /// Returns the bytes `range` of an object, fewer if the object ends
+    /// before.
+    fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<Vec<u8>>;
+    /// Creates or replaces an object.
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+}
+
+/// An object store in a local directory, an object per file.
+pub struct LocalObjectStore {
+    dir: PathBuf,
+}
+
+impl LocalObjectStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.dir.join(key))
+    }
+
+    fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let file = File::open(self.dir.join(key))?;
+        let len = file.metadata()?.len();
+        let mut bytes = vec![0; range.end.min(len).saturating_sub(range.start) as usize];
+        file.read_exact_at(&mut bytes, range.start)?;
+        Ok(bytes)
+    }
+
+    /// Writes the object next to its path then renames it: objects are
+    /// replaced atomically, as in an object store.
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.dir.join(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, data)?;
+        File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+/// Stores pages in an object store, in chunk objects of `CHUNK_PAGES` pages
+/// under `<prefix>/`, for read-mostly databases.
+///
+/// Pages are read with ranged GETs. Written pages go to a local write buffer
+/// first and are uploaded when the storage is fsynced: each chunk with
+/// buffered pages is rewritten whole, then the `manifest` object holding the
+/// number of pages. A crash before then only loses what the buffer file
+/// lost: its pages are uploaded again once the storage is reopened.
+pub struct ObjectStorage<O: ObjectStore> {
+    store: O,
+    prefix: String,
+    last_page_id: AtomicU32,
+    buffer: Mutex<WriteBuffer>,
+}
+
+/// The pages written since the last upload, appended to a local file.
+struct WriteBuffer {
+    file: File,
+    len: u64,
+    // The offset of the last record of each page.
+    pages: HashMap<PageId, u64>,
+}
+
+impl WriteBuffer {
+    fn open(path: &Path, truncate: bool) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(truncate)
+            .open(path)?;
+
+        // A record torn by a crash is dropped.
+        let len = file.metadata()?.len();
+        let len = len - len % RECORD_SIZE as u64;
+        let mut pages = HashMap::new();
+        let mut header = [0; RECORD_HEADER_SIZE];
+        for offset in (0..len).step_by(RECORD_SIZE) {
+            file.read_exact_at(&mut header, offset)?;
+            let page_id = u32::from_le_bytes(header[..4].try_into().unwrap());
+            pages.insert(PageId::new(page_id), offset);
+        }
+
+        Ok(Self { file, len, pages })
+    }
+
+    fn read(&self, page_id: PageId, page: &mut Page) -> io::Result<bool> {
+        match self.pages.get(&page_id) {
+            Some(offset) => {
+                let offset = offset + RECORD_HEADER_SIZE as u64;
+                self.file.read_exact_at(&mut page.data, offset)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn write(&mut self, page: &Page, page_id: PageId) -> io::Result<()> {
+        let mut header = [0; RECORD_HEADER_SIZE];
+        header[..4].copy_from_slice(&page_id.get().to_le_bytes());
+        self.file.write_all_at(&header, self.len)?;
+        let offset = self.len + RECORD_HEADER_SIZE as u64;
+        self.file.write_all_at(&page.data, offset)?;
+        self.pages.insert(page_id, self.len);
+        self.len += RECORD_SIZE as u64;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.len = 0;
+        self.pages.clear();
+        Ok(())
+    }
+}
+
+impl<O: ObjectStore> ObjectStorage<O> {
+    /// Creates a new storage under `prefix`, replacing the manifest of a
+    /// previous one. `buffer_path` is the local file of the write buffer.
+    pub fn create<P: AsRef<Path>>(
+        store: O,
+        prefix: &str,
+        buffer_path: P,
+    ) -> Result<Self, StorageError> {
+        let storage = Self {
+            store,
+            prefix: prefix.to_string(),
+            last_page_id: AtomicU32::new(0),
+            buffer: Mutex::new(WriteBuffer::open(buffer_path.as_ref(), true)?),
+        };
+
+        // Create reserved page
+        storage.write_page(&reserved_page(), PAGE_RESERVED)?;
+        storage.upload()?;
+
+        Ok(storage)
+    }
+
+    /// Opens the storage under `prefix`, uploading the pages left in the write
+    /// buffer by a crash.
+    pub fn open<P: AsRef<Path>>(
+        store: O,
+        prefix: &str,
+        buffer_path: P,
+    ) -> Result<Self, StorageError> {
+        let manifest = match store.get(&format!("{prefix}/manifest")) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::FileCorrupted);
+            }
+            result => result?,
+        };
+        let num_pages = manifest
+            .try_into()
+            .map(u32::from_le_bytes)
+            .map_err(|_| StorageError::FileCorrupted)?;
+        if num_pages == 0 {
+            return Err(StorageError::FileCorrupted);
+        }
+
+        let buffer = WriteBuffer::open(buffer_path.as_ref(), false)?;
+        // Pages allocated after the last upload.
+        let last_buffered = buffer.pages.keys().map(PageId::get).max();
+        let last_page_id = (num_pages - 1).max(last_buffered.unwrap_or(0));
+        let storage = Self {
+            store,
+            prefix: prefix.to_string(),
+            last_page_id: AtomicU32::new(last_page_id),
+            buffer: Mutex::new(buffer),
+        };
+        storage.upload()?;
+        validate_header(&storage)?;
+
+        Ok(storage)
+    }
+
+    fn chunk_key(&self, chunk: u32) -> String {
+        format!("{}/chunk-{chunk}", self.prefix)
+    }
+
+    /// Uploads the chunks of the buffered pages then the manifest, and empties
+    /// the buffer.
+    fn upload(&self) -> Result<(), StorageError> {
+        let mut buffer = self.buffer.lock();
+        let last_page_id = self.last_page_id();
+        let mut chunks = BTreeMap::<u32, Vec<PageId>>::new();
+        for page_id in buffer.pages.keys() {
+            if *page_id <= last_page_id {
+                chunks
+                    .entry(page_id.get() / CHUNK_PAGES)
+                    .or_default()
+                    .push(*page_id);
+            }
+        }
+
+        for (chunk, page_ids) in chunks {
+            let key = self.chunk_key(chunk);
+            let mut data = match self.store.get(&key) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                result => result?,
+            };
+            // Pages after the last one of the storage were truncated.
+            let chunk_pages = (last_page_id.get() - chunk * CHUNK_PAGES + 1).min(CHUNK_PAGES);
+            data.resize(chunk_pages as usize * PAGE_SIZE, 0);
+
+            let mut page = Page::new();
+            for page_id in page_ids {
+                buffer.read(page_id, &mut page)?;
+                let offset = (page_id.get() % CHUNK_PAGES) as usize * PAGE_SIZE;
+                data[offset..offset + PAGE_SIZE].copy_from_slice(&page.data);
+            }
+            self.store.put(&key, &data)?;
+        }
+
+        let num_pages = last_page_id.get() + 1;
+        let manifest_key = format!("{}/manifest", self.prefix);
+        self.store.put(&manifest_key, &num_pages.to_le_bytes())?;
+        buffer.clear()?;
+        Ok(())
+    }
+}
+
+impl<O: ObjectStore> StorageBackend for ObjectStorage<O> {
+    /// Reads a page from the write buffer, or with a ranged GET of its chunk.
+    fn read_page(&self, page_id: PageId, page: &mut Page) -> Result<(), StorageError> {
+        if self.buffer.lock().read(page_id, page)? {
+            return Ok(());
+        }
+
+        let offset = (page_id.get() % CHUNK_PAGES) as u64 * PAGE_SIZE as u64;
+        let key = self.chunk_key(page_id.get() / CHUNK_PAGES);
+        let data = self
+            .store
+            .get_range(&key, offset..offset + PAGE_SIZE as u64)?;
+        if data.len() != PAGE_SIZE {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        page.data.copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Writes a page to the write buffer.
+    fn write_page(&self, page: &Page, page_id: PageId) -> Result<(), StorageError> {
+        let mut buffer = self.buffer.lock();
+        // The storage was truncated after the page was dirtied.
+        if page_id > self.last_page_id() {
+            return Ok(());
+        }
+        buffer.write(page, page_id).map_err(StorageError::Io)
+    }
+
+    /// Makes the write buffer durable then uploads it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the write buffer can't be synced or uploaded, as
+    /// `FileStorage::fsync` does.
+    fn fsync(&self) {
+        if self.buffer.lock().file.sync_data().is_err() || self.upload().is_err() {
+            panic!("flush (fsync) failed");
+        }
+    }
+
+    fn allocate_page(&self) -> Result<PageId, StorageError> {
+        let last_page_id = self.last_page_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let new_page_id = PageId::new(last_page_id);
+        self.write_page(&Page::new(), new_page_id)?;
+        Ok(new_page_id)
+    }
+
+    /// Shrinks the storage to end with `last_page_id`. The chunks are
+    /// shortened the next time they are uploaded, the pages after the end
+    /// are never read.
+    fn truncate(&self, last_page_id: PageId) -> Result<(), StorageError> {
+        let mut buffer = self.buffer.lock();
+        buffer.pages.retain(|page_id, _| *page_id <= last_page_id);
+        self.last_page_id
+            .store(last_page_id.get(), Ordering::Relaxed);
+        drop(buffer);
+        self.upload()
+    }
+
+    fn first_page_id(&self) -> PageId {
+        PageId::new(1)
+    }
+
+    fn last_page_id(&self) -> PageId {
+        PageId::new(self.last_page_id.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::PageCache;
+
+    #[test]
+    fn object_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalObjectStore::new(dir.path().join("bucket"));
+        let buffer_path = dir.path().join("db.buf");
+        let storage = ObjectStorage::create(store, "db/users", &buffer_path).unwrap();
+
+        // Through the page cache, its writeback uploads the pages.
+        let page_cache = PageCache::try_new().unwrap();
+        let cache = page_cache.cache_storage(storage);
+        let nr_pages = CHUNK_PAGES + 10;
+        for i in 0..nr_pages {
+            let mut page = cache.new_page().unwrap();
+            page.page_mut().data[..4].copy_from_slice(&i.to_le_bytes());
+            cache.set_page_dirty(page.metadata());
+        }
+        page_cache.flush();
+        drop(cache);
+        drop(page_cache);
+
+        let store = LocalObjectStore::new(dir.path().join("bucket"));
+        assert!(store.get("db/users/chunk-1").is_ok());
+        let storage = ObjectStorage::open(store, "db/users", &buffer_path).unwrap();
+        assert_eq!(storage.last_page_id(), PageId::new(nr_pages));
+        let mut page = Page::new();
+        storage.read_page(PageId::new(nr_pages), &mut page).unwrap();
+        assert_eq!(page.data[..4], (nr_pages - 1).to_le_bytes());
+
+        // Buffered pages left by a crash are uploaded when reopened.
+        storage.write_page(&page, PageId::new(1)).unwrap();
+        std::mem::forget(storage);
+        let store = LocalObjectStore::new(dir.path().join("bucket"));
+        let storage = ObjectStorage::open(store, "db/users", &buffer_path).unwrap();
+        assert_eq!(std::fs::metadata(&buffer_path).unwrap().len(), 0);
+        storage.read_page(PageId::new(1), &mut page).unwrap();
+        assert_eq!(page.data[..4], (nr_pages - 1).to_le_bytes());
+
+        storage.truncate(PageId::new(3)).unwrap();
+        let store = LocalObjectStore::new(dir.path().join("bucket"));
+        let storage = ObjectStorage::open(store, "db/users", &buffer_path).unwrap();
+        assert_eq!(storage.last_page_id(), PageId::new(3));
+    }
+}
@@ -0,0 +1,150 @@
+//! The platform-specific parts of file I/O: positional reads and writes, and
+//! bypassing the operating system's cache.
+
+use crate::pages::{PAGE_SIZE, Page};
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+#[cfg(unix)]
+pub use std::os::unix::fs::FileExt;
+
+/// Reads and writes at an offset, as `std::os::unix::fs::FileExt` does.
+///
+/// Unlike on unix, the file cursor is moved: files are only accessed at an
+/// offset.
+#[cfg(windows)]
+pub trait FileExt {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()>;
+}
+
+#[cfg(windows)]
+impl FileExt for File {
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_write(buf, offset) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Allocates the blocks of `len` bytes at `offset` without changing the file
+/// size, so a file growing page by page gets contiguous extents. Does nothing
+/// where the filesystem can't.
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(()),
+            e => Err(e),
+        },
+    }
+}
+
+/// Files grow page by page on other platforms.
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_file: &File, _offset: u64, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// Deallocates the blocks of `len` bytes at `offset` without changing the
+/// file size, the range then reads as zeros. Zeros are written instead where
+/// the filesystem can't punch holes.
+#[cfg(target_os = "linux")]
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EOPNOTSUPP) => write_zeros(file, offset, len),
+            e => Err(e),
+        },
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    write_zeros(file, offset, len)
+}
+
+fn write_zeros(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    let zeros = Page::new();
+    (offset..offset + len)
+        .step_by(PAGE_SIZE)
+        .try_for_each(|offset| file.write_all_at(&zeros.data, offset))
+}
+
+/// Opens a file bypassing the operating system's cache, fails with
+/// `ErrorKind::Unsupported` if the platform or the filesystem can't.
+#[cfg(target_os = "linux")]
+pub fn open_direct(mut options: OpenOptions, path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    match options.custom_flags(libc::O_DIRECT).open(path) {
+        // e.g. tmpfs before Linux 6.6 and some network filesystems.
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Err(io::ErrorKind::Unsupported.into()),
+        result => result,
+    }
+}
+
+/// Opens a file bypassing the operating system's cache: macOS has no
+/// `O_DIRECT`, caching is disabled on the open file instead.
+#[cfg(target_os = "macos")]
+pub fn open_direct(options: OpenOptions, path: &Path) -> io::Result<File> {
+    use std::os::fd::AsRawFd;
+    let file = options.open(path)?;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Unbuffered I/O isn't supported on Windows: files are always buffered.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn open_direct(_options: OpenOptions, _path: &Path) -> io::Result<File> {
+    Err(io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,154 @@
+use crate::storage::ObjectStore;
+
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::ops::Range;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// An S3-compatible object store, accessed with path-style URLs
+/// (`<endpoint>/<bucket>/<key>`) and requests signed with AWS Signature
+/// Version 4.
+pub struct S3ObjectStore {
+    agent: ureq::Agent,
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key size is valid");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes a key as S3 expects in the canonical URI.
+fn uri_encode(key: &str) -> String {
+    key.bytes().fold(String::new(), |mut uri, byte| {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => {
+                let _ = write!(uri, "%{byte:02X}");
+            }
+        }
+        uri
+    })
+}
+
+impl S3ObjectStore {
+    /// `endpoint` is the URL of the service, e.g. `https://s3.us-east-1.amazonaws.com`.
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Self {
+        let endpoint = endpoint.trim_end_matches('/');
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, host)| host);
+        Self {
+            agent: ureq::Agent::new(),
+            endpoint: endpoint.to_string(),
+            host: host.to_string(),
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+        }
+    }
+
+    /// Returns a request for an object, signed for `payload`.
+    fn request(&self, method: &str, key: &str, payload: &[u8]) -> ureq::Request {
+        let path = uri_encode(&format!("/{}/{key}", self.bucket));
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex(&Sha256::digest(payload));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{timestamp}\n\n{signed_headers}\n{payload_hash}",
+            self.host
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+            hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date),
+            |key, data| hmac(&key, data),
+        );
+        let signature = hex(&hmac(&signing_key, &string_to_sign));
+
+        self.agent
+            .request(method, &format!("{}{path}", self.endpoint))
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("x-amz-date", &timestamp)
+            .set(
+                "authorization",
+                &format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                    self.access_key
+                ),
+            )
+    }
+}
+
+/// Maps a request error to an I/O error, a missing object to `NotFound`.
+fn io_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(404, _) => io::ErrorKind::NotFound.into(),
+        ureq::Error::Status(status, _) => io::Error::other(format!("S3 status {status}")),
+        ureq::Error::Transport(transport) => io::Error::other(transport),
+    }
+}
+
+fn read_body(response: ureq::Response) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    Ok(body)
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let response = self.request("GET", key, &[]).call().map_err(io_error)?;
+        read_body(response)
+    }
+
+    fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let response = self
+            .request("GET", key, &[])
+            .set("range", &format!("bytes={}-{}", range.start, range.end - 1))
+            .call();
+        match response {
+            // The range starts after the end of the object.
+            Err(ureq::Error::Status(416, _)) => Ok(Vec::new()),
+            response => read_body(response.map_err(io_error)?),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.request("PUT", key, data)
+            .send_bytes(data)
+            .map_err(io_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+use crate::pages::{PAGE_SIZE, PageId};
+use crate::storage::StorageError;
+use crate::storage::os::{FileExt, open_direct, preallocate, punch_hole};
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use parking_lot::RwLock;
+
+/// The number of pages of a 1GB segment.
+pub const SEGMENT_PAGES: u32 = (1 << 30) / PAGE_SIZE as u32;
+
+/// Opens a storage file with direct I/O if `direct_io` is set and the
+/// filesystem supports it, with buffered I/O otherwise. Returns whether direct
+/// I/O is used.
+///
+/// Durability doesn't depend on it: written pages are fsynced either way.
+pub(crate) fn open_storage_file(
+    options: &OpenOptions,
+    path: &Path,
+    direct_io: bool,
+) -> io::Result<(File, bool)> {
+    if direct_io {
+        match open_direct(options.clone(), path) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+            result => return result.map(|file| (file, true)),
+        }
+    }
+    Ok((options.open(path)?, false))
+}
+
+/// Returns the path of a segment: the first one is stored at `path`, the next
+/// ones at `<path>.1`, `<path>.2`...
+fn segment_path(path: &Path, segment: usize) -> PathBuf {
+    if segment == 0 {
+        return path.to_path_buf();
+    }
+    let mut segment_path = OsString::from(path.as_os_str());
+    segment_path.push(format!(".{segment}"));
+    PathBuf::from(segment_path)
+}
+
+/// Removes all the segments of a file.
+pub fn remove_segments(path: &Path) -> io::Result<()> {
+    std::fs::remove_file(path)?;
+    for segment in 1.. {
+        match std::fs::remove_file(segment_path(path, segment)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Copies all the segments of a file to `to`, and syncs them.
+pub fn copy_segments(from: &Path, to: &Path) -> io::Result<()> {
+    for segment in 0.. {
+        let from = segment_path(from, segment);
+        if segment > 0 && !from.exists() {
+            break;
+        }
+        let to = segment_path(to, segment);
+        std::fs::copy(from, &to)?;
+        File::open(to)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The pages of a storage file, split into segments of `segment_pages` pages
+/// so a large table isn't a single huge file.
+///
+/// Segments are created as pages are written past the last one. All of them
+/// but the last one are full.
+pub struct SegmentedFile {
+    path: PathBuf,
+    segment_pages: u32,
+    direct_io: bool,
+    segments: RwLock<Vec<File>>,
+}
+
+impl SegmentedFile {
+    /// Creates a new empty file, removing the segments of a previous one.
+    pub fn create(path: &Path, segment_pages: u32, direct_io: bool) -> io::Result<Self> {
+        match remove_segments(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => result?,
+        }
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(true);
+        let (file, direct_io) = open_storage_file(&options, path, direct_io)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            segment_pages,
+            direct_io,
+            segments: RwLock::new(vec![file]),
+        })
+    }
+
+    /// Opens the segments of an existing file.
+    pub fn open(path: &Path, segment_pages: u32, direct_io: bool) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+        Self::open_with(options, path, segment_pages, direct_io)
+    }
+
+    /// Opens the segments of an existing file for reading only, e.g. to check
+    /// a file in use.
+    pub fn open_read_only(path: &Path, segment_pages: u32) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        Self::open_with(options, path, segment_pages, false)
+    }
+
+    fn open_with(
+        options: OpenOptions,
+        path: &Path,
+        segment_pages: u32,
+        direct_io: bool,
+    ) -> io::Result<Self> {
+        let (file, direct_io) = open_storage_file(&options, path, direct_io)?;
+
+        let mut segments = vec![file];
+        loop {
+            let path = segment_path(path, segments.len());
+            match open_storage_file(&options, &path, direct_io) {
+                Ok((file, _)) => segments.push(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            segment_pages,
+            direct_io,
+            segments: RwLock::new(segments),
+        })
+    }
+
+    /// Returns whether the segments bypass the operating system's cache.
+    pub fn is_direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    /// Returns the number of pages, `FileCorrupted` if a segment other than the
+    /// last one isn't full.
+    pub fn num_pages(&self) -> Result<u64, StorageError> {
+        let segments = self.segments.read();
+        let segment_len = self.segment_pages as u64 * PAGE_SIZE as u64;
+        let mut pages = 0;
+        for (i, segment) in segments.iter().enumerate() {
+            let len = segment.metadata()?.len();
+            let is_last = i == segments.len() - 1;
+            if (!is_last && len != segment_len) || !len.is_multiple_of(PAGE_SIZE as u64) {
+                return Err(StorageError::FileCorrupted);
+            }
+            pages += len / PAGE_SIZE as u64;
+        }
+        Ok(pages)
+    }
+
+    /// Calls `f` with the segment of a page and the offset of the page in it.
+    /// If `create` is set, the missing segments up to the one of the page are
+    /// created, otherwise a page past the last segment is `UnexpectedEof`.
+    pub fn with_segment<R>(
+        &self,
+        page_id: PageId,
+        create: bool,
+        f: impl FnOnce(&File, u64) -> io::Result<R>,
+    ) -> io::Result<R> {
+        let segment = (page_id.get() / self.segment_pages) as usize;
+        let offset = (page_id.get() % self.segment_pages) as u64 * PAGE_SIZE as u64;
+
+        {
+            let segments = self.segments.read();
+            if let Some(file) = segments.get(segment) {
+                return f(file, offset);
+            }
+        }
+        if !create {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut segments = self.segments.write();
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        while segments.len() <= segment {
+            let path = segment_path(&self.path, segments.len());
+            let (file, _) = open_storage_file(&options, &path, self.direct_io)?;
+            segments.push(file);
+        }
+        f(&segments[segment], offset)
+    }
+
+    pub fn read_exact_at(&self, buf: &mut [u8], page_id: PageId) -> io::Result<()> {
+        self.with_segment(page_id, false, |file, offset| {
+            file.read_exact_at(buf, offset)
+        })
+    }
+
+    pub fn write_all_at(&self, buf: &[u8], page_id: PageId) -> io::Result<()> {
+        self.with_segment(page_id, true, |file, offset| file.write_all_at(buf, offset))
+    }
+
+    /// Preallocates `num_pages` pages from `page_id`, up to the end of its
+    /// segment. The file size isn't changed: it still ends with the last page
+    /// written.
+    pub fn preallocate(&self, page_id: PageId, num_pages: u32) -> io::Result<()> {
+        let num_pages = num_pages.min(self.segment_pages - page_id.get() % self.segment_pages);
+        self.with_segment(page_id, true, |file, offset| {
+            preallocate(file, offset, num_pages as u64 * PAGE_SIZE as u64)
+        })
+    }
+
+    /// Deallocates `num_pages` pages from `page_id`, see `os::punch_hole`.
+    pub fn punch_hole(&self, mut page_id: PageId, mut num_pages: u32) -> io::Result<()> {
+        while num_pages > 0 {
+            let n = num_pages.min(self.segment_pages - page_id.get() % self.segment_pages);
+            self.with_segment(page_id, false, |file, offset| {
+                punch_hole(file, offset, n as u64 * PAGE_SIZE as u64)
+            })?;
+            page_id = PageId::new(page_id.get() + n);
+            num_pages -= n;
+        }
+        Ok(())
+    }
+
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.segments.read().iter().try_for_each(File::sync_data)
+    }
+
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.segments.read().iter().try_for_each(File::sync_all)
+    }
+
+    /// Shrinks the file to `num_pages` pages, at least one, removing the
+    /// segments left empty.
+    pub fn truncate(&self, num_pages: u64) -> io::Result<()> {
+        debug_assert!(num_pages > 0);
+        let mut segments = self.segments.write();
+        let keep = num_pages.div_ceil(self.segment_pages as u64) as usize;
+        while segments.len() > keep {
+            segments.pop();
+            std::fs::remove_file(segment_path(&self.path, segments.len()))?;
+        }
+
+        let last_len = num_pages - (keep as u64 - 1) * self.segment_pages as u64;
+        let last = segments.last().unwrap();
+        last.set_len(last_len * PAGE_SIZE as u64)?;
+        last.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pages::Page;
+
+    fn page(byte: u8) -> Page {
+        Page {
+            data: [byte; PAGE_SIZE],
+        }
+    }
+
+    #[test]
+    fn segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.tbl");
+        let file = SegmentedFile::create(&path, 4, true).unwrap();
+
+        for i in 0..10 {
+            file.write_all_at(&page(i).data, PageId::new(i as u32))
+                .unwrap();
+        }
+        assert_eq!(file.num_pages().unwrap(), 10);
+        assert!(segment_path(&path, 2).exists());
+        drop(file);
+
+        let file = SegmentedFile::open(&path, 4, true).unwrap();
+        assert_eq!(file.num_pages().unwrap(), 10);
+        let mut read = Page::new();
+        file.read_exact_at(&mut read.data, PageId::new(9)).unwrap();
+        assert_eq!(read.data, page(9).data);
+        assert!(file.read_exact_at(&mut read.data, PageId::new(12)).is_err());
+
+        // Emptied segments are removed.
+        file.truncate(4).unwrap();
+        assert_eq!(file.num_pages().unwrap(), 4);
+        assert!(!segment_path(&path, 1).exists());
+
+        // A segment which isn't the last one must be full.
+        file.write_all_at(&page(0).data, PageId::new(8)).unwrap();
+        assert!(matches!(file.num_pages(), Err(StorageError::FileCorrupted)));
+
+        drop(file);
+        remove_segments(&path).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn preallocate_pages() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.tbl");
+        let file = SegmentedFile::create(&path, 64, true).unwrap();
+        file.write_all_at(&page(1).data, PageId::new(0)).unwrap();
+
+        // Up to the end of the first segment, the file size is unchanged.
+        file.preallocate(PageId::new(32), 256).unwrap();
+        assert_eq!(file.num_pages().unwrap(), 1);
+        let blocks = std::fs::metadata(&path).unwrap().blocks();
+        assert!(blocks * 512 >= 33 * PAGE_SIZE as u64);
+        assert!(!segment_path(&path, 1).exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn punch_hole() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.tbl");
+        let file = SegmentedFile::create(&path, 4, true).unwrap();
+        for page_id in 0..10 {
+            file.write_all_at(&page(1).data, PageId::new(page_id))
+                .unwrap();
+        }
+
+        // The hole spans the three segments, which keep their size.
+        file.punch_hole(PageId::new(2), 7).unwrap();
+        assert_eq!(file.num_pages().unwrap(), 10);
+        let blocks = std::fs::metadata(segment_path(&path, 1)).unwrap().blocks();
+        assert_eq!(blocks, 0);
+        for page_id in 0..10 {
+            // A page, unlike an array on the stack, is aligned for direct I/O.
+            let mut read = page(0xff);
+            file.read_exact_at(&mut read.data, PageId::new(page_id))
+                .unwrap();
+            let expected = match page_id {
+                2..=8 => page(0),
+                _ => page(1),
+            };
+            assert_eq!(read.data, expected.data);
+        }
+    }
+
+    #[test]
+    fn buffered_io() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+
+        let (file, direct_io) = open_storage_file(&options, file.path(), false).unwrap();
+        assert!(!direct_io);
+        // Unaligned I/O only works without direct I/O.
+        file.write_all_at(b"joujou", 1).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The number of buckets of a `LatencyHistogram`.
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// A histogram of latencies: bucket `i` counts the latencies under `2^i`
+/// microseconds not counted by the previous ones, the last bucket counts
+/// everything above.
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    fn record(&self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(LATENCY_BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the upper bound of each bucket and its count.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets.iter().enumerate().map(|(i, count)| {
+            let bound = match i {
+                i if i == LATENCY_BUCKETS - 1 => Duration::MAX,
+                i => Duration::from_micros(1 << i),
+            };
+            (bound, count.load(Ordering::Relaxed))
+        })
+    }
+
+    /// Returns the number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets().map(|(_, count)| count).sum()
+    }
+
+    /// Returns the upper bound of the bucket holding the `quantile` (between 0
+    /// and 1) of the latencies, `None` if none was recorded.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((quantile * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets()
+            .find(|(_, bucket_count)| {
+                seen += bucket_count;
+                seen >= rank
+            })
+            .map(|(bound, _)| bound)
+    }
+}
+
+/// I/O counters of a storage backend.
+///
+/// Latencies are recorded per call: a batch of pages read or written at once
+/// is a single latency.
+#[derive(Debug, Default)]
+pub struct StorageStats {
+    pages_read: AtomicU64,
+    pages_written: AtomicU64,
+    fsyncs: AtomicU64,
+    read_latency: LatencyHistogram,
+    write_latency: LatencyHistogram,
+    fsync_latency: LatencyHistogram,
+}
+
+impl StorageStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of pages read from storage.
+    pub fn pages_read(&self) -> u64 {
+        self.pages_read.load(Ordering::Relaxed)
+    }
+
+    /// Number of pages written to storage.
+    pub fn pages_written(&self) -> u64 {
+        self.pages_written.load(Ordering::Relaxed)
+    }
+
+    /// Number of fsyncs.
+    pub fn fsyncs(&self) -> u64 {
+        self.fsyncs.load(Ordering::Relaxed)
+    }
+
+    pub fn read_latency(&self) -> &LatencyHistogram {
+        &self.read_latency
+    }
+
+    pub fn write_latency(&self) -> &LatencyHistogram {
+        &self.write_latency
+    }
+
+    pub fn fsync_latency(&self) -> &LatencyHistogram {
+        &self.fsync_latency
+    }
+
+    pub(crate) fn record_read(&self, pages: usize, start: Instant) {
+        self.pages_read.fetch_add(pages as u64, Ordering::Relaxed);
+        self.read_latency.record(start.elapsed());
+    }
+
+    pub(crate) fn record_write(&self, pages: usize, start: Instant) {
+        self.pages_written
+            .fetch_add(pages as u64, Ordering::Relaxed);
+        self.write_latency.record(start.elapsed());
+    }
+
+    pub(crate) fn record_fsync(&self, start: Instant) {
+        self.fsyncs.fetch_add(1, Ordering::Relaxed);
+        self.fsync_latency.record(start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cache::PageCache;
+    use crate::storage::FileStorage;
+
+    #[test]
+    fn storage_stats() {
+        let histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        for micros in [0, 3, 3, 100] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        histogram.record(Duration::from_secs(3600));
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(4)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::MAX));
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let storage = FileStorage::create(file.path()).unwrap();
+        let page_cache = PageCache::try_new().unwrap();
+        let cache = page_cache.cache_storage(storage);
+        let stats = cache.storage_stats().unwrap();
+        let fsyncs = stats.fsyncs();
+
+        let page = cache.new_page().unwrap();
+        cache.set_page_dirty(page.metadata());
+        drop(page);
+        page_cache.flush();
+        assert_eq!(stats.pages_written(), 3);
+        assert_eq!(stats.fsyncs(), fsyncs + 1);
+        assert_eq!(stats.write_latency().count(), 3);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::config::CONFIG;
+use crate::pages::{PAGE_RESERVED, PAGE_SIZE, Page, PageId};
+use crate::storage::backend::{reserved_page, validate_header};
+use crate::storage::doublewrite::{DOUBLE_WRITE_SLOTS, DoubleWriteBuffer};
+use crate::storage::encryption::{EncryptionKey, PageCipher, remove_records};
+use crate::storage::segment::{SEGMENT_PAGES, SegmentedFile};
+use crate::storage::{StorageBackend, StorageError, StorageStats};
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+use io_uring::{IoUring, opcode, squeue, types};
+use parking_lot::Mutex;
+
+/// The number of pages submitted at once, each of them goes through its own
+/// registered buffer. A batch of writes fits in the double-write buffer.
+pub const URING_QUEUE_DEPTH: usize = DOUBLE_WRITE_SLOTS as usize;
+
+/// A page-sized buffer, aligned for direct I/O.
+#[repr(C, align(4096))]
+struct Buffer([u8; PAGE_SIZE]);
+
+/// A ring and the buffers registered with it.
+struct Ring {
+    // Dropped first: the buffers are registered until the ring is closed.
+    ring: IoUring,
+    buffers: Box<[Buffer]>,
+}
+
+impl Ring {
+    fn new() -> io::Result<Self> {
+        let ring = IoUring::new(URING_QUEUE_DEPTH as u32)?;
+        let mut buffers = (0..URING_QUEUE_DEPTH)
+            .map(|_| Buffer([0; PAGE_SIZE]))
+            .collect::<Box<[_]>>();
+        let iovecs = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.0.as_mut_ptr().cast(),
+                iov_len: PAGE_SIZE,
+            })
+            .collect::<Vec<_>>();
+        // SAFETY: the buffers are neither moved nor freed before the ring is
+        // dropped.
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+
+        Ok(Self { ring, buffers })
+    }
+
+    /// Submits the operations and waits for all of them to complete. Each one
+    /// reads or writes a whole page.
+    fn run(&mut self, entries: &[squeue::Entry]) -> io::Result<()> {
+        // SAFETY: the operations only use the registered buffers, which are
+        // not touched until they complete.
+        unsafe { self.ring.submission().push_multiple(entries) }.map_err(io::Error::other)?;
+
+        let mut result = Ok(());
+        let mut pending = entries.len();
+        while pending > 0 {
+            match self.ring.submit_and_wait(pending) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+                Ok(_) => {}
+            }
+            for cqe in self.ring.completion() {
+                pending -= 1;
+                if cqe.result() < 0 {
+                    result = Err(io::Error::from_raw_os_error(-cqe.result()));
+                } else if cqe.result() as usize != PAGE_SIZE && result.is_ok() {
+                    result = Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Like `FileStorage`, but pages are read and written through an io_uring
+/// with registered buffers.
+///
+/// A batch of pages is submitted at once and completes with a single wait,
+/// rather than blocking on each page. Writes still go through a
+/// `DoubleWriteBuffer`, its images are written and synced once per batch, and
+/// are encrypted like those of `FileStorage` with a configured key.
+pub struct UringStorage {
+    file: SegmentedFile,
+    last_page_id: AtomicU32,
+    ring: Mutex<Ring>,
+    // Taken before the ring.
+    double_write: Mutex<DoubleWriteBuffer>,
+    cipher: Option<PageCipher>,
+    stats: Arc<StorageStats>,
+}
+
+impl UringStorage {
+    /// Creates a new storage file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let file = SegmentedFile::create(path.as_ref(), SEGMENT_PAGES, CONFIG.DIRECT_IO)?;
+        let mut double_write = DoubleWriteBuffer::open(path.as_ref())?;
+        double_write.clear()?;
+        let cipher = match EncryptionKey::from_config()? {
+            Some(key) => {
+                let cipher = PageCipher::open(path.as_ref(), &key)?;
+                cipher.clear()?;
+                Some(cipher)
+            }
+            None => {
+                remove_records(path.as_ref())?;
+                None
+            }
+        };
+
+        let storage = Self {
+            file,
+            last_page_id: AtomicU32::new(0),
+            ring: Mutex::new(Ring::new()?),
+            double_write: Mutex::new(double_write),
+            cipher,
+            stats: Arc::default(),
+        };
+
+        // Create reserved page
+        storage.write_page(&reserved_page(), PAGE_RESERVED)?;
+        storage.fsync();
+
+        Ok(storage)
+    }
+
+    /// Opens an existing storage file, repairing the pages torn by a crash.
+    /// Fails with `InvalidHeader` if it isn't a storage file of this version.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let file = SegmentedFile::open(path.as_ref(), SEGMENT_PAGES, CONFIG.DIRECT_IO)?;
+        let mut double_write = DoubleWriteBuffer::open(path.as_ref())?;
+        double_write.recover(&file)?;
+
+        let num_pages = file.num_pages()?;
+        if num_pages == 0 {
+            return Err(StorageError::FileCorrupted);
+        }
+
+        let storage = Self {
+            file,
+            last_page_id: AtomicU32::new(num_pages as u32 - 1),
+            ring: Mutex::new(Ring::new()?),
+            double_write: Mutex::new(double_write),
+            cipher: EncryptionKey::from_config()?
+                .map(|key| PageCipher::open(path.as_ref(), &key))
+                .transpose()?,
+            stats: Arc::default(),
+        };
+        validate_header(&storage)?;
+
+        Ok(storage)
+    }
+
+    /// Returns the segment of a page and the offset of the page in it, see
+    /// `SegmentedFile::with_segment`.
+    fn locate(&self, page_id: PageId, create: bool) -> io::Result<(types::Fd, u64)> {
+        self.file.with_segment(page_id, create, |file, offset| {
+            Ok((types::Fd(file.as_raw_fd()), offset))
+        })
+    }
+}
+
+impl StorageBackend for UringStorage {
+    fn read_page(&self, page_id: PageId, page: &mut Page) -> Result<(), StorageError> {
+        self.read_pages(&mut [(page_id, page)])
+    }
+
+    /// Reads the pages `URING_QUEUE_DEPTH` at a time.
+    fn read_pages(&self, pages: &mut [(PageId, &mut Page)]) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let mut ring = self.ring.lock();
+        for chunk in pages.chunks_mut(URING_QUEUE_DEPTH) {
+            let entries = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, (page_id, _))| {
+                    let (fd, offset) = self.locate(*page_id, false)?;
+                    let buffer = ring.buffers[i].0.as_mut_ptr();
+                    let entry = opcode::ReadFixed::new(fd, buffer, PAGE_SIZE as u32, i as u16)
+                        .offset(offset)
+                        .build();
+                    Ok(entry)
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            ring.run(&entries)?;
+
+            for (buffer, (page_id, page)) in ring.buffers.iter().zip(chunk) {
+                page.data.copy_from_slice(&buffer.0);
+                if let Some(cipher) = &self.cipher {
+                    cipher.decrypt(*page_id, page)?;
+                }
+            }
+        }
+        self.stats.record_read(pages.len(), start);
+        Ok(())
+    }
+
+    fn write_page(&self, page: &Page, page_id: PageId) -> Result<(), StorageError> {
+        self.write_pages(&[(page, page_id)])
+    }
+
+    /// Writes the pages `URING_QUEUE_DEPTH` at a time, after writing their
+    /// images to the double-write buffer.
+    fn write_pages(&self, pages: &[(&Page, PageId)]) -> Result<(), StorageError> {
+        // Held until the pages are written in place: the slots of their images
+        // can't be reused before.
+        let mut double_write = self.double_write.lock();
+        // The file was truncated after the pages were dirtied.
+        let last_page_id = self.last_page_id();
+        let start = Instant::now();
+        let pages = pages
+            .iter()
+            .filter(|(_, page_id)| *page_id <= last_page_id)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut ring = self.ring.lock();
+        for chunk in pages.chunks(URING_QUEUE_DEPTH) {
+            let ciphertexts = match &self.cipher {
+                Some(cipher) => cipher.encrypt(chunk)?,
+                None => Vec::new(),
+            };
+            let encrypted = chunk
+                .iter()
+                .zip(&ciphertexts)
+                .map(|((_, page_id), ciphertext)| (ciphertext, *page_id))
+                .collect::<Vec<_>>();
+            let chunk = if self.cipher.is_some() {
+                &encrypted[..]
+            } else {
+                chunk
+            };
+            double_write.write(&self.file, chunk)?;
+
+            let entries = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, (page, page_id))| {
+                    let (fd, offset) = self.locate(*page_id, true)?;
+                    let buffer = &mut ring.buffers[i].0;
+                    buffer.copy_from_slice(&page.data);
+                    let entry =
+                        opcode::WriteFixed::new(fd, buffer.as_ptr(), PAGE_SIZE as u32, i as u16)
+                            .offset(offset)
+                            .build();
+                    Ok(entry)
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            ring.run(&entries)?;
+
+            if let Some(cipher) = &self.cipher {
+                let page_ids = chunk.iter().map(|(_, page_id)| *page_id);
+                cipher.commit(&page_ids.collect::<Vec<_>>());
+            }
+        }
+        self.stats.record_write(pages.len(), start);
+        Ok(())
+    }
+
+    /// Syncs file data and metadata to the disk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying `File::sync_all` operation fails, as
+    /// `FileStorage::fsync` does.
+    fn fsync(&self) {
+        let start = Instant::now();
+        let result = self.file.sync_all();
+        self.stats.record_fsync(start);
+        if result.is_err() {
+            panic!("flush (fsync) failed");
+        }
+    }
+
+    /// Allocates a new page, preallocating the file as `FileStorage` does.
+    fn allocate_page(&self) -> Result<PageId, StorageError> {
+        let last_page_id = self.last_page_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let new_page_id = PageId::new(last_page_id);
+        if last_page_id.is_multiple_of(CONFIG.EXTENSION_PAGES) {
+            self.file.preallocate(new_page_id, CONFIG.EXTENSION_PAGES)?;
+        }
+        self.write_page(&Page::new(), new_page_id)?;
+        Ok(new_page_id)
+    }
+
+    /// Shrinks the file to end with `last_page_id`.
+    fn truncate(&self, last_page_id: PageId) -> Result<(), StorageError> {
+        let mut double_write = self.double_write.lock();
+        // The images of the removed pages would extend the file again on
+        // recovery: they are dropped once the pages kept are durable.
+        self.file.sync_data()?;
+        double_write.clear()?;
+
+        self.file.truncate(last_page_id.get() as u64 + 1)?;
+        self.last_page_id
+            .store(last_page_id.get(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Punches a hole in the file over the pages freed, or overwrites them
+    /// when encrypted, as `FileStorage` does.
+    fn free_pages(&self, first: PageId, last: PageId) -> Result<(), StorageError> {
+        if self.cipher.is_some() {
+            for page_id in first.get()..=last.get() {
+                self.write_page(&Page::new(), PageId::new(page_id))?;
+            }
+            return Ok(());
+        }
+
+        let mut double_write = self.double_write.lock();
+        self.file.sync_data()?;
+        double_write.clear()?;
+
+        self.file.punch_hole(first, last.get() - first.get() + 1)?;
+        Ok(())
+    }
+
+    fn first_page_id(&self) -> PageId {
+        PageId::new(1)
+    }
+
+    fn last_page_id(&self) -> PageId {
+        PageId::new(self.last_page_id.load(Ordering::Relaxed))
+    }
+
+    fn stats(&self) -> Option<Arc<StorageStats>> {
+        Some(Arc::clone(&self.stats))
+    }
+}
+
+impl Drop for UringStorage {
+    /// Removes the double-write buffer once all the pages are written in
+    /// place and synced.
+    fn drop(&mut self) {
+        if self.file.sync_all().is_ok() {
+            let _ = std::fs::remove_file(self.double_write.get_mut().path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn page(byte: u8) -> Page {
+        let mut page = Page::new();
+        page.data.fill(byte);
+        page
+    }
+
+    #[test]
+    fn uring_storage() {
+        let file = NamedTempFile::new().unwrap();
+        let storage = UringStorage::create(file.path()).unwrap();
+
+        // More pages than a single submission holds.
+        let nr_pages = URING_QUEUE_DEPTH + 8;
+        let page_ids = (0..nr_pages)
+            .map(|_| storage.allocate_page().unwrap())
+            .collect::<Vec<_>>();
+        let pages = (0..nr_pages).map(|i| page(i as u8)).collect::<Vec<_>>();
+        let batch = pages
+            .iter()
+            .zip(page_ids.iter().copied())
+            .collect::<Vec<_>>();
+        storage.write_pages(&batch).unwrap();
+        drop(storage);
+
+        let storage = UringStorage::open(file.path()).unwrap();
+        assert_eq!(storage.last_page_id(), PageId::new(nr_pages as u32));
+        let mut read = (0..nr_pages).map(|_| Page::new()).collect::<Vec<_>>();
+        let mut batch = page_ids
+            .iter()
+            .copied()
+            .zip(read.iter_mut())
+            .collect::<Vec<_>>();
+        storage.read_pages(&mut batch).unwrap();
+        for (i, page) in read.iter().enumerate() {
+            assert!(page.data.iter().all(|&byte| byte == i as u8));
+        }
+
+        // Reading past the end of the file fails.
+        let mut page = Page::new();
+        let past_end = PageId::new(nr_pages as u32 + 1);
+        assert!(storage.read_page(past_end, &mut page).is_err());
+    }
+}
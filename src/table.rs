@@ -1,15 +1,59 @@
-use crate::cache::{PageCacheError, StoragePageCache};
-use crate::pages::{HeapPageError, HeapPageSlotId, PAGE_RESERVED, PageId, RecordId};
+use crate::cache::{PageCacheError, StoragePageCache, SyncMode};
+use crate::indexes::{BTree, BTreeError, FullTextIndex};
+use crate::pages::{HeapPageError, HeapPageSlotId, Key, PAGE_RESERVED, PageId, RecordId};
+use crate::sql::plan::TableStats;
 use crate::sql::schema::Schema;
+use crate::sql::types::Value;
+use crate::sql::types::memcomparable::{self, KeyOptions};
 use crate::storage::StorageBackend;
 use crate::tuple::{Tuple, TupleError};
 
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use parking_lot::{Mutex, RwLock};
 use thiserror::Error;
 
+/// An index of a table: its key columns, its included columns and its tree.
+type TableIndex<S> = (Vec<(usize, KeyOptions)>, Vec<usize>, BTree<S>);
+
 pub struct Table<S: StorageBackend + 'static> {
     pub name: String,
     pub schema: Schema,
     cache: StoragePageCache<S>,
+    // (key columns, included columns, index), kept up to date by `insert`,
+    // `update`, `delete` and `vacuum`.
+    indexes: Vec<TableIndex<S>>,
+    // (column, index), kept up to date as `indexes`.
+    fulltext_indexes: Vec<(usize, FullTextIndex<S>)>,
+    // The logs of the indexes built online, shared by the clones of the
+    // table. Writes hold the lock for reading, see `build_index_online`.
+    index_builds: Arc<RwLock<Vec<IndexBuildLog>>>,
+    // Given to the indexes registered, see `Table::set_sync_mode`.
+    sync_mode: Option<SyncMode>,
+}
+
+/// Tables are handles on a page cache: clones read and write the same pages.
+impl<S: StorageBackend> Clone for Table<S> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            schema: self.schema.clone(),
+            cache: self.cache.clone(),
+            indexes: self.indexes.clone(),
+            fulltext_indexes: self.fulltext_indexes.clone(),
+            index_builds: self.index_builds.clone(),
+            sync_mode: self.sync_mode,
+        }
+    }
+}
+
+/// What `Table::vacuum` did.
+#[derive(Debug, Default, PartialEq)]
+pub struct VacuumStats {
+    /// The old and the new record ids of the tuples moved.
+    pub relocations: Vec<(RecordId, RecordId)>,
+    pub pages_freed: u32,
 }
 
 #[derive(Debug, Error)]
@@ -20,6 +64,216 @@ pub enum TableError {
     PageCache(#[from] PageCacheError),
     #[error("tuple error")]
     Tuple(#[from] TupleError),
+    #[error("index error")]
+    Index(#[from] BTreeError),
+    #[error("duplicate value in unique column {0}")]
+    ConstraintViolation(String),
+}
+
+/// The number of changes left to the swap of an index built online: the
+/// catch up stops once fewer changes were logged meanwhile.
+const CATCH_UP_THRESHOLD: usize = 64;
+
+static NEXT_INDEX_BUILD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A change to the entries of an index built online, made by a write to the
+/// table during the build.
+enum IndexChange {
+    Insert(Key, RecordId, Vec<u8>),
+    Delete(Key, RecordId),
+}
+
+impl IndexChange {
+    /// Applies the change to the index, which may already have it: the rows
+    /// written during the snapshot scan may have been read by it. Fails with a
+    /// `BTreeError::DuplicateKey` if the key is already taken by another row.
+    fn apply<S: StorageBackend + 'static>(self, index: &BTree<S>) -> Result<(), TableError> {
+        match self {
+            IndexChange::Insert(key, record_id, payload) => {
+                if index.search(&key) != Some(record_id) {
+                    index.insert_with_payload(&key, record_id, &payload)?;
+                }
+            }
+            IndexChange::Delete(key, record_id) => {
+                if index.search(&key) == Some(record_id) {
+                    index.delete(&key)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The changes to apply to an index built online.
+struct IndexBuildLog {
+    id: u64,
+    key: Vec<(usize, KeyOptions)>,
+    // Whether the index is unique, see `entry_key`.
+    unique: bool,
+    included: Vec<usize>,
+    changes: Mutex<Vec<IndexChange>>,
+}
+
+/// The registration of an index build in the logs of a table, removed on
+/// drop: the table stops logging changes once the build is done or
+/// abandoned.
+struct IndexBuildRegistration {
+    id: u64,
+    logs: Arc<RwLock<Vec<IndexBuildLog>>>,
+}
+
+impl IndexBuildRegistration {
+    /// Takes the changes logged so far.
+    fn take_changes(&self) -> Vec<IndexChange> {
+        let logs = self.logs.read();
+        logs.iter()
+            .find(|log| log.id == self.id)
+            .map(|log| std::mem::take(&mut *log.changes.lock()))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for IndexBuildRegistration {
+    fn drop(&mut self) {
+        self.logs.write().retain(|log| log.id != self.id);
+    }
+}
+
+/// An index built while its table accepts writes, see
+/// `Table::build_index_online`, not used by the table until
+/// `Table::finish_index_build`.
+pub struct IndexBuild<S: StorageBackend + 'static> {
+    registration: IndexBuildRegistration,
+    key: Vec<(usize, KeyOptions)>,
+    included: Vec<usize>,
+    index: BTree<S>,
+}
+
+impl<S: StorageBackend + 'static> IndexBuild<S> {
+    pub fn index(&self) -> &BTree<S> {
+        &self.index
+    }
+
+    /// Applies the changes logged since the last call to the index, returns
+    /// their number.
+    pub fn catch_up(&self) -> Result<usize, TableError> {
+        let changes = self.registration.take_changes();
+        let len = changes.len();
+        for change in changes {
+            change.apply(&self.index)?;
+        }
+
+        Ok(len)
+    }
+}
+
+/// The key of the values of a tuple in an index on the `key` columns. NULLs
+/// aren't indexed: NULLs are never equal, not even in a unique column.
+fn index_key(tuple: &Tuple, key: &[(usize, KeyOptions)]) -> Option<Key> {
+    let values = key
+        .iter()
+        .map(|&(column, _)| tuple.values()[column].clone())
+        .collect::<Vec<_>>();
+    let options = key.iter().map(|&(_, options)| options).collect::<Vec<_>>();
+    (!values.iter().any(Value::is_null)).then(|| Key::from_values_with(&values, &options))
+}
+
+/// The size of the record id ending the keys of the indexes that aren't
+/// unique, see `entry_key`.
+const RECORD_ID_SIZE: usize = 6;
+
+/// The key of the entry of a tuple in an index on the `key` columns, see
+/// `index_key`. Unless the index is unique, rows can have the same values:
+/// the key of the values is followed by the record id of the row, page then
+/// slot in big endian. Keys are still sorted by values first, their encoding
+/// is prefix free.
+fn entry_key(
+    tuple: &Tuple,
+    key: &[(usize, KeyOptions)],
+    unique: bool,
+    record_id: RecordId,
+) -> Option<Key> {
+    let key = index_key(tuple, key)?;
+    if unique {
+        return Some(key);
+    }
+    let mut bytes = Vec::with_capacity(key.as_bytes().len() + RECORD_ID_SIZE);
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.extend_from_slice(&record_id.page_id.get().to_be_bytes());
+    bytes.extend_from_slice(&record_id.slot_id.get().to_be_bytes());
+    Some(Key::from(bytes))
+}
+
+/// The greatest key of the entries of the rows with the values of `key` in
+/// an index, unique or not, see `entry_key`: the entries of these rows are
+/// the keys from `key` to this one.
+pub(crate) fn last_entry_key(key: &Key) -> Key {
+    let mut bytes = key.as_bytes().to_vec();
+    bytes.extend_from_slice(&[u8::MAX; RECORD_ID_SIZE]);
+    Key::from(bytes)
+}
+
+/// The record ids of the rows with the values of `key` in an index of a
+/// table, unique or not, see `Table::index`. At most one for a unique index.
+pub fn lookup<S: StorageBackend + 'static>(
+    index: &BTree<S>,
+    key: &Key,
+) -> Result<Vec<RecordId>, BTreeError> {
+    index
+        .stable_range(key.clone()..=last_entry_key(key))?
+        .map(|entry| entry.map(|(_, record_id)| record_id))
+        .collect()
+}
+
+/// The text of a tuple in a full-text index on `column`, NULLs aren't
+/// indexed.
+fn fulltext(tuple: &Tuple, column: usize) -> Option<&str> {
+    match &tuple.values()[column] {
+        Value::VarChar(text) => Some(text),
+        _ => None,
+    }
+}
+
+/// Whether an index on the `key` columns is keyed by the values of `column`
+/// alone, in ascending order: the keys are those of `Key::from_values`.
+fn is_on_column(key: &[(usize, KeyOptions)], column: usize) -> bool {
+    key == [(column, KeyOptions::default())]
+}
+
+/// Logs the keys of a tuple written to the table for the indexes being built.
+fn log_insert(builds: &[IndexBuildLog], tuple: &Tuple, record_id: RecordId) {
+    for log in builds {
+        if let Some(key) = entry_key(tuple, &log.key, log.unique, record_id) {
+            let payload = index_payload(tuple, &log.included);
+            log.changes
+                .lock()
+                .push(IndexChange::Insert(key, record_id, payload));
+        }
+    }
+}
+
+/// Logs the keys of a tuple removed from the table for the indexes being
+/// built, before it is removed: the record id can't be reused meanwhile.
+fn log_delete(builds: &[IndexBuildLog], tuple: &Tuple, record_id: RecordId) {
+    for log in builds {
+        if let Some(key) = entry_key(tuple, &log.key, log.unique, record_id) {
+            log.changes.lock().push(IndexChange::Delete(key, record_id));
+        }
+    }
+}
+
+/// The payload of the entry of a tuple in a covering index: the values of the
+/// included columns, encoded as a key, see `Key::values`.
+pub(crate) fn index_payload(tuple: &Tuple, included: &[usize]) -> Vec<u8> {
+    if included.is_empty() {
+        return Vec::new();
+    }
+    let values = included
+        .iter()
+        .map(|&column| tuple.values()[column].clone())
+        .collect::<Vec<_>>();
+    memcomparable::encode(&values)
 }
 
 impl<S: StorageBackend + 'static> Table<S> {
@@ -32,13 +286,130 @@ impl<S: StorageBackend + 'static> Table<S> {
             name: name.to_string(),
             schema: schema.clone(),
             cache,
+            indexes: Vec::new(),
+            fulltext_indexes: Vec::new(),
+            index_builds: Arc::default(),
+            sync_mode: None,
         })
     }
 
+    /// Returns the sync mode of the table, see `StoragePageCache::sync_mode`.
+    pub fn sync_mode(&self) -> SyncMode {
+        self.cache.sync_mode()
+    }
+
+    /// Gives the table and its indexes, including the ones registered later,
+    /// their own sync mode, see `StoragePageCache::set_sync_mode`.
+    pub fn set_sync_mode(&mut self, mode: Option<SyncMode>) {
+        self.sync_mode = mode;
+        self.cache.set_sync_mode(mode);
+        for (_, _, index) in &self.indexes {
+            index.set_sync_mode(mode);
+        }
+        for (_, index) in &self.fulltext_indexes {
+            index.set_sync_mode(mode);
+        }
+    }
+
+    /// Commits the writes to the table and its indexes, see
+    /// `StoragePageCache::commit`: with `SyncMode::Full`, they are durable
+    /// once this returns.
+    pub fn commit(&self) {
+        self.cache.commit();
+        for (_, _, index) in &self.indexes {
+            index.commit();
+        }
+        for (_, index) in &self.fulltext_indexes {
+            index.commit();
+        }
+    }
+
+    /// Registers an index on a column, keyed by `Key::from_values` followed
+    /// by the record ids of the rows unless the column is unique, see
+    /// `Table::index_entries`. The index must already hold the rows of the
+    /// table, see `BTree::bulk_load`.
+    ///
+    /// Unique columns are enforced by their index: without one, duplicates
+    /// are accepted.
+    pub fn add_index(&mut self, column: usize, index: BTree<S>) {
+        self.add_covering_index(column, Vec::new(), index);
+    }
+
+    /// Registers a covering index on a column: the values of the `included`
+    /// columns are stored along with the keys, in the payload of their
+    /// entries, see `BTree::insert_with_payload`. They are encoded as a key
+    /// made of these values, in order.
+    pub fn add_covering_index(&mut self, column: usize, included: Vec<usize>, index: BTree<S>) {
+        let key = vec![(column, KeyOptions::default())];
+        self.add_composite_index(key, included, index);
+    }
+
+    /// Registers an index keyed by the values of several columns, each in
+    /// ascending or descending order, see `Key::from_values_with`: its keys
+    /// are sorted as by `ORDER BY` on these columns. Rows with a NULL in one
+    /// of them aren't indexed.
+    ///
+    /// Only the indexes on a single column in ascending order are used for
+    /// lookups, see `Table::index`, and enforce unique columns.
+    pub fn add_composite_index(
+        &mut self,
+        key: Vec<(usize, KeyOptions)>,
+        included: Vec<usize>,
+        index: BTree<S>,
+    ) {
+        if self.sync_mode.is_some() {
+            index.set_sync_mode(self.sync_mode);
+        }
+        self.indexes.push((key, included, index));
+    }
+
+    /// Registers a full-text index on a VARCHAR column, see `FullTextIndex`.
+    /// The index must already hold the rows of the table, see
+    /// `FullTextIndex::bulk_load`.
+    pub fn add_fulltext_index(&mut self, column: usize, index: FullTextIndex<S>) {
+        if self.sync_mode.is_some() {
+            index.set_sync_mode(self.sync_mode);
+        }
+        self.fulltext_indexes.push((column, index));
+    }
+
+    /// The full-text index on a column, if any.
+    pub fn fulltext_index(&self, column: usize) -> Option<&FullTextIndex<S>> {
+        self.fulltext_indexes
+            .iter()
+            .find(|(indexed, _)| *indexed == column)
+            .map(|(_, index)| index)
+    }
+
+    /// The index on a column, if any. Its rows are looked up by `lookup`.
+    pub fn index(&self, column: usize) -> Option<&BTree<S>> {
+        self.indexes
+            .iter()
+            .find(|(key, ..)| is_on_column(key, column))
+            .map(|(_, _, index)| index)
+    }
+
+    /// The columns included in the index on a column, see
+    /// `Table::add_covering_index`.
+    pub fn included(&self, column: usize) -> Option<&[usize]> {
+        self.indexes
+            .iter()
+            .find(|(key, ..)| is_on_column(key, column))
+            .map(|(_, included, _)| included.as_slice())
+    }
+
+    /// The indexes of the table along with their key columns, see
+    /// `Table::add_composite_index`.
+    pub fn indexes(&self) -> impl Iterator<Item = (&[(usize, KeyOptions)], &BTree<S>)> {
+        self.indexes
+            .iter()
+            .map(|(key, _, index)| (key.as_slice(), index))
+    }
+
     pub fn get(&self, record_id: RecordId) -> Result<Tuple, TableError> {
         let page_ref = self
             .cache
-            .get_page(record_id.page_id)
+            .get_page(Self::heap_page_id(record_id)?)
             .map_err(TableError::PageCache)?;
         let heappage = page_ref.heap_page();
 
@@ -48,9 +419,300 @@ impl<S: StorageBackend + 'static> Table<S> {
             .to_owned(&self.schema))
     }
 
+    /// Inserts a tuple and its keys in the indexes of the table. Nothing is
+    /// inserted if an index rejects its key, e.g. a `BTreeError::DuplicateKey`,
+    /// or a `TableError::ConstraintViolation` for a unique column.
     pub fn insert(&self, tuple: &Tuple) -> Result<RecordId, TableError> {
         self.validate_tuple(tuple)?;
+        self.check_unique(tuple, None)?;
+        let builds = self.index_builds.read();
+        let record_id = self.insert_heap(tuple)?;
+        if let Err(e) = self.insert_keys(tuple, record_id) {
+            // The snapshot scan of a build may have read the tuple.
+            log_delete(&builds, tuple, record_id);
+            self.delete_heap(record_id)?;
+            return Err(e);
+        }
+        log_insert(&builds, tuple, record_id);
+
+        Ok(record_id)
+    }
+
+    /// Replaces the tuple of a record, returning its new record id: the tuple
+    /// is written at the end of the table. The indexes are updated, the old
+    /// tuple and its keys are left untouched if an index rejects a key of the
+    /// new one, or if a step fails.
+    pub fn update(&self, record_id: RecordId, tuple: &Tuple) -> Result<RecordId, TableError> {
+        self.validate_tuple(tuple)?;
+        self.check_unique(tuple, Some(record_id))?;
+        let builds = self.index_builds.read();
+        let old_tuple = self.get(record_id)?;
+        let new_record_id = self.insert_heap(tuple)?;
+        // Removes the new tuple, returns the first error. The snapshot scan of
+        // a build may have read it.
+        let discard = |e: TableError| {
+            log_delete(&builds, tuple, new_record_id);
+            self.delete_heap(new_record_id).err().unwrap_or(e)
+        };
+        if let Err(e) = self.delete_keys(&old_tuple, record_id) {
+            return Err(discard(e));
+        }
+        if let Err(e) = self.insert_keys(tuple, new_record_id) {
+            let e = self.insert_keys(&old_tuple, record_id).err().unwrap_or(e);
+            return Err(discard(e));
+        }
+        // The old key is removed first, the new one may be the same.
+        log_delete(&builds, &old_tuple, record_id);
+        if let Err(e) = self.delete_heap(record_id) {
+            log_insert(&builds, &old_tuple, record_id);
+            let e = self
+                .delete_keys(tuple, new_record_id)
+                .and_then(|()| self.insert_keys(&old_tuple, record_id))
+                .err()
+                .unwrap_or(e);
+            return Err(discard(e));
+        }
+        log_insert(&builds, tuple, new_record_id);
+
+        Ok(new_record_id)
+    }
+
+    /// Deletes a tuple and its keys from the indexes of the table. The keys
+    /// are deleted first: the tuple and its keys are left untouched if one
+    /// of them can't be deleted.
+    pub fn delete(&self, record_id: RecordId) -> Result<(), TableError> {
+        let builds = self.index_builds.read();
+        if self.indexes.is_empty() && self.fulltext_indexes.is_empty() && builds.is_empty() {
+            return self.delete_heap(record_id);
+        }
+        let tuple = self.get(record_id)?;
+        self.delete_keys(&tuple, record_id)?;
+        log_delete(&builds, &tuple, record_id);
+        if let Err(e) = self.delete_heap(record_id) {
+            log_insert(&builds, &tuple, record_id);
+            return Err(self.insert_keys(&tuple, record_id).err().unwrap_or(e));
+        }
+
+        Ok(())
+    }
 
+    /// Builds an index on the `key` columns while the table accepts writes,
+    /// through this handle or its clones, see `Table::add_composite_index`.
+    ///
+    /// The writes are logged from the start of the build. The tuples are read
+    /// and bulk loaded into an index of `page_cache`, then the changes logged
+    /// meanwhile are applied, again and again until few are left: they are
+    /// applied by `finish_index_build`, which adds the index to the table.
+    ///
+    /// Rows may have the same values, their keys end with their record ids,
+    /// see `Table::index_entries`, unless the index enforces a unique column:
+    /// it then fails with a `BTreeError::DuplicateKey` if rows have the same
+    /// value, possibly both versions of a tuple updated during the scan. The
+    /// table must not be vacuumed meanwhile.
+    pub fn build_index_online(
+        &self,
+        key: Vec<(usize, KeyOptions)>,
+        included: Vec<usize>,
+        page_cache: StoragePageCache<S>,
+    ) -> Result<IndexBuild<S>, TableError> {
+        // Writes end before the log is registered: the ones not logged are
+        // read by the scan.
+        let id = NEXT_INDEX_BUILD_ID.fetch_add(1, Ordering::Relaxed);
+        self.index_builds.write().push(IndexBuildLog {
+            id,
+            key: key.clone(),
+            unique: self.is_unique_index(&key),
+            included: included.clone(),
+            changes: Mutex::default(),
+        });
+        let registration = IndexBuildRegistration {
+            id,
+            logs: self.index_builds.clone(),
+        };
+
+        let entries = self.index_entries(&key, &included);
+        let index = BTree::bulk_load_with_payloads(page_cache, entries)?;
+        let build = IndexBuild {
+            registration,
+            key,
+            included,
+            index,
+        };
+        while build.catch_up()? >= CATCH_UP_THRESHOLD {}
+
+        Ok(build)
+    }
+
+    /// Adds an index built by `build_index_online` to the table. The writes
+    /// wait while the last changes logged are applied: the index is then up
+    /// to date, and maintained by the writes through this handle. As with
+    /// `add_index`, the handles cloned before don't maintain it.
+    pub fn finish_index_build(&mut self, build: IndexBuild<S>) -> Result<(), TableError> {
+        let IndexBuild {
+            registration,
+            key,
+            included,
+            index,
+        } = build;
+        debug_assert!(Arc::ptr_eq(&registration.logs, &self.index_builds));
+        {
+            let mut logs = self.index_builds.write();
+            if let Some(pos) = logs.iter().position(|log| log.id == registration.id) {
+                let log = logs.remove(pos);
+                for change in log.changes.into_inner() {
+                    change.apply(&index)?;
+                }
+            }
+        }
+        self.add_composite_index(key, included, index);
+
+        Ok(())
+    }
+
+    /// The entries of an index on the `key` columns for the tuples of the
+    /// table, sorted by key, along with the values of the `included` columns,
+    /// see `BTree::bulk_load_with_payloads`. NULLs aren't indexed. Unless the
+    /// index is unique, the keys end with the record ids of the rows, see
+    /// `Table::is_unique_index`: keys repeat only for rows with the same
+    /// values in a unique column.
+    pub fn index_entries(
+        &self,
+        key: &[(usize, KeyOptions)],
+        included: &[usize],
+    ) -> Vec<(Key, RecordId, Vec<u8>)> {
+        let unique = self.is_unique_index(key);
+        self.scan_entries(key, included, |tuple, record_id| {
+            entry_key(tuple, key, unique, record_id)
+        })
+    }
+
+    /// The keys of the values of the `key` columns for the tuples of the
+    /// table, sorted, along with the record ids of their rows, e.g. for a
+    /// `HashIndex`. NULLs aren't indexed.
+    pub fn index_keys(&self, key: &[(usize, KeyOptions)]) -> Vec<(Key, RecordId)> {
+        self.scan_entries(key, &[], |tuple, _| index_key(tuple, key))
+            .into_iter()
+            .map(|(key, record_id, _)| (key, record_id))
+            .collect()
+    }
+
+    fn scan_entries(
+        &self,
+        key: &[(usize, KeyOptions)],
+        included: &[usize],
+        entry_key: impl Fn(&Tuple, RecordId) -> Option<Key>,
+    ) -> Vec<(Key, RecordId, Vec<u8>)> {
+        // The columns decoded, in increasing order.
+        let mut columns = key.iter().map(|&(column, _)| column).collect::<Vec<_>>();
+        columns.extend(included);
+        columns.sort_unstable();
+        columns.dedup();
+        let mut entries = Vec::new();
+        let mut iter = self.iter_columns(&columns);
+        while let Some((record_id, tuple)) = iter.next_record() {
+            if let Some(key) = entry_key(&tuple, record_id) {
+                entries.push((key, record_id, index_payload(&tuple, included)));
+            }
+        }
+        entries.sort_unstable_by(|(lhs, ..), (rhs, ..)| lhs.cmp(rhs));
+
+        entries
+    }
+
+    /// The key of the entry of a tuple in an index of the table on the
+    /// `columns`, see `entry_key`.
+    fn entry_key(
+        &self,
+        tuple: &Tuple,
+        columns: &[(usize, KeyOptions)],
+        record_id: RecordId,
+    ) -> Option<Key> {
+        entry_key(tuple, columns, self.is_unique_index(columns), record_id)
+    }
+
+    /// Inserts the keys of a tuple in the indexes. The keys already inserted
+    /// are removed if an index rejects one.
+    fn insert_keys(&self, tuple: &Tuple, record_id: RecordId) -> Result<(), TableError> {
+        for (i, (columns, included, index)) in self.indexes.iter().enumerate() {
+            let Some(key) = self.entry_key(tuple, columns, record_id) else {
+                continue;
+            };
+            let payload = index_payload(tuple, included);
+            if let Err(e) = index.insert_with_payload(&key, record_id, &payload) {
+                for (columns, _, index) in &self.indexes[..i] {
+                    if let Some(key) = self.entry_key(tuple, columns, record_id) {
+                        index.delete(&key)?;
+                    }
+                }
+                return Err(match e {
+                    BTreeError::DuplicateKey(_) if self.is_unique_index(columns) => {
+                        self.constraint_violation(columns[0].0)
+                    }
+                    e => e.into(),
+                });
+            }
+        }
+        for (i, (column, index)) in self.fulltext_indexes.iter().enumerate() {
+            let Some(text) = fulltext(tuple, *column) else {
+                continue;
+            };
+            if let Err(e) = index.insert(text, record_id) {
+                for (column, index) in &self.fulltext_indexes[..i] {
+                    if let Some(text) = fulltext(tuple, *column) {
+                        index.delete(text, record_id)?;
+                    }
+                }
+                for (columns, _, index) in &self.indexes {
+                    if let Some(key) = self.entry_key(tuple, columns, record_id) {
+                        index.delete(&key)?;
+                    }
+                }
+                return Err(e.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the keys of a tuple from the indexes. The keys already deleted
+    /// are inserted back if one can't be.
+    fn delete_keys(&self, tuple: &Tuple, record_id: RecordId) -> Result<(), TableError> {
+        let restore = |indexes: &[TableIndex<S>]| -> Result<(), TableError> {
+            for (columns, included, index) in indexes {
+                if let Some(key) = self.entry_key(tuple, columns, record_id) {
+                    index.insert_with_payload(&key, record_id, &index_payload(tuple, included))?;
+                }
+            }
+            Ok(())
+        };
+        for (i, (columns, _, index)) in self.indexes.iter().enumerate() {
+            let Some(key) = self.entry_key(tuple, columns, record_id) else {
+                continue;
+            };
+            if let Err(e) = index.delete(&key) {
+                restore(&self.indexes[..i])?;
+                return Err(e.into());
+            }
+        }
+        for (i, (column, index)) in self.fulltext_indexes.iter().enumerate() {
+            let Some(text) = fulltext(tuple, *column) else {
+                continue;
+            };
+            if let Err(e) = index.delete(text, record_id) {
+                for (column, index) in &self.fulltext_indexes[..i] {
+                    if let Some(text) = fulltext(tuple, *column) {
+                        index.insert(text, record_id)?;
+                    }
+                }
+                restore(&self.indexes)?;
+                return Err(e.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_heap(&self, tuple: &Tuple) -> Result<RecordId, TableError> {
         let last_page_id = self.cache.last_page_id();
         let mut page_ref = if last_page_id == PAGE_RESERVED {
             // Allocate the first heap page.
@@ -78,38 +740,241 @@ impl<S: StorageBackend + 'static> Table<S> {
         }
     }
 
-    pub fn delete(&self, record_id: RecordId) -> Result<(), TableError> {
+    fn delete_heap(&self, record_id: RecordId) -> Result<(), TableError> {
         let mut page_ref = self
             .cache
-            .get_page_mut(record_id.page_id)
+            .get_page_mut(Self::heap_page_id(record_id)?)
             .map_err(TableError::PageCache)?;
         let heappage = page_ref.heap_page_mut();
 
         heappage
             .delete_tuple(record_id.slot_id)
             .map_err(TableError::HeapPage)?;
+        self.cache.set_page_dirty(page_ref.metadata());
 
         Ok(())
     }
 
+    /// Returns the page of a record, the reserved page holds the file header
+    /// and no tuples.
+    fn heap_page_id(record_id: RecordId) -> Result<PageId, TableError> {
+        match record_id.page_id {
+            PAGE_RESERVED => Err(TableError::HeapPage(HeapPageError::SlotNotFound)),
+            page_id => Ok(page_id),
+        }
+    }
+
     fn validate_tuple(&self, tuple: &Tuple) -> Result<(), TableError> {
         // check data types and nullable constraints
         tuple
             .validate_with_schema(&self.schema)
             .map_err(TableError::Tuple)
+    }
+
+    /// Probes the indexes of the unique columns for the keys of a tuple, before
+    /// it is written: `replaced` is the record the tuple replaces, if any. A
+    /// key inserted meanwhile is still caught by the insert in the index, the
+    /// leaf is searched and written under the same latch.
+    fn check_unique(&self, tuple: &Tuple, replaced: Option<RecordId>) -> Result<(), TableError> {
+        for (columns, _, index) in &self.indexes {
+            if !self.is_unique_index(columns) {
+                continue;
+            }
+            let Some(key) = index_key(tuple, columns) else {
+                continue;
+            };
+            if index
+                .search(&key)
+                .is_some_and(|record_id| Some(record_id) != replaced)
+            {
+                return Err(self.constraint_violation(columns[0].0));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether an index on the `key` columns enforces a unique column: its
+    /// keys are those of the values alone, see `entry_key`.
+    fn is_unique_index(&self, key: &[(usize, KeyOptions)]) -> bool {
+        let column = key[0].0;
+        is_on_column(key, column) && self.schema.columns()[column].constraints.is_unique()
+    }
+
+    fn constraint_violation(&self, column: usize) -> TableError {
+        TableError::ConstraintViolation(self.schema.columns()[column].column_name.clone())
+    }
+
+    /// Compacts the heap: the live tuples are packed into the first pages, in
+    /// order, and the pages left empty are removed from the file.
+    ///
+    /// Tuples are moved to other pages and slots, the indexes of the table are
+    /// pointed to their new record ids. The table must not be used meanwhile,
+    /// and the pages are rewritten in place: a crash halfway loses tuples.
+    pub fn vacuum(&self) -> Result<VacuumStats, TableError> {
+        let mut stats = VacuumStats::default();
+        let Some((_, last_page_id)) = self.page_range() else {
+            return Ok(stats);
+        };
+
+        // Tuples only move to the pages before theirs, or earlier in theirs:
+        // the target page is always read before being rewritten.
+        // Heap pages are numbered from 1, after the reserved page.
+        let mut target = PageId::new(1);
+        let mut cleared = false;
+        for page_id in 1..=last_page_id.get() {
+            let page_id = PageId::new(page_id);
+            let tuples = {
+                let page_ref = self.cache.get_page(page_id)?;
+                let heappage = page_ref.heap_page();
+                let mut tuples = Vec::new();
+                let mut slot_id = HeapPageSlotId::new(0);
+                loop {
+                    match heappage.get_tuple(slot_id) {
+                        Ok(tuple) => tuples.push((slot_id, tuple.to_owned(&self.schema))),
+                        Err(HeapPageError::SlotDeleted) => {}
+                        Err(_) => break,
+                    }
+                    slot_id.next();
+                }
+                tuples
+            };
+
+            for (slot_id, tuple) in tuples {
+                loop {
+                    let mut page_ref = self.cache.get_page_mut(target)?;
+                    let heappage = page_ref.heap_page_mut();
+                    if !cleared {
+                        heappage.clear();
+                        cleared = true;
+                    }
+                    let result = heappage.insert_tuple(&tuple);
+                    self.cache.set_page_dirty(page_ref.metadata());
+                    match result {
+                        Ok(new_slot_id) => {
+                            let (old, new) = (
+                                RecordId::new(page_id, slot_id),
+                                RecordId::new(target, new_slot_id),
+                            );
+                            if old != new {
+                                stats.relocations.push((old, new));
+                            }
+                            break;
+                        }
+                        Err(HeapPageError::NoFreeSpace) => {
+                            target.next();
+                            debug_assert!(target <= page_id);
+                            cleared = false;
+                        }
+                        Err(e) => return Err(TableError::from(e)),
+                    }
+                }
+            }
+        }
+
+        // Without tuples, all the heap pages are removed.
+        let new_last_page_id = match cleared {
+            true => target,
+            false => PAGE_RESERVED,
+        };
+        stats.pages_freed = last_page_id.get() - new_last_page_id.get();
+        if stats.pages_freed > 0 {
+            self.cache.truncate(new_last_page_id)?;
+        }
+
+        if !self.indexes.is_empty() || !self.fulltext_indexes.is_empty() {
+            for &(old_record_id, record_id) in &stats.relocations {
+                let tuple = self.get(record_id)?;
+                // Tuples only move to record ids before theirs: the new keys
+                // aren't those of the tuples left to move.
+                for (columns, included, index) in &self.indexes {
+                    if let Some(key) = self.entry_key(&tuple, columns, old_record_id) {
+                        index.delete(&key)?;
+                    }
+                    if let Some(key) = self.entry_key(&tuple, columns, record_id) {
+                        index.insert_with_payload(
+                            &key,
+                            record_id,
+                            &index_payload(&tuple, included),
+                        )?;
+                    }
+                }
+                // Postings are keyed by record id, see `FullTextIndex`.
+                for (column, index) in &self.fulltext_indexes {
+                    if let Some(text) = fulltext(&tuple, *column) {
+                        index.delete(text, old_record_id)?;
+                        index.insert(text, record_id)?;
+                    }
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Counts the tuples and the heap pages of the table.
+    pub fn statistics(&self) -> TableStats {
+        TableStats {
+            rows: self.iter_columns(&[]).count() as u64,
+            // Heap pages are numbered from 1, after the reserved page.
+            pages: self.cache.last_page_id().get() as u64,
+        }
+    }
 
-        // TODO: check for column uniqueness
+    /// Removes the file of the table from the page cache, e.g. once its
+    /// database is detached. The pages not written back are lost.
+    pub fn detach(&self) {
+        self.cache.detach_storage();
     }
 
     pub fn iter(&self) -> TableIterator<'_, S> {
         TableIterator::new(self)
     }
+
+    /// Iterates over the tuples only decoding the given columns (in increasing
+    /// order), the other columns are NULL.
+    pub fn iter_columns<'table>(
+        &'table self,
+        columns: &'table [usize],
+    ) -> TableIterator<'table, S> {
+        TableIterator {
+            columns: Some(columns),
+            ..TableIterator::new(self)
+        }
+    }
+
+    /// Returns the first and the last heap pages, `None` if the table has
+    /// none yet.
+    pub fn page_range(&self) -> Option<(PageId, PageId)> {
+        let last_page_id = self.cache.last_page_id();
+        (last_page_id != PAGE_RESERVED).then(|| (self.cache.first_page_id(), last_page_id))
+    }
+
+    /// Iterates over the tuples of the heap pages `first..=last`, only
+    /// decoding the given columns if any.
+    pub fn iter_pages<'table>(
+        &'table self,
+        first: PageId,
+        last: PageId,
+        columns: Option<&'table [usize]>,
+    ) -> TableIterator<'table, S> {
+        TableIterator {
+            page_id: first,
+            last_page_id: Some(last),
+            columns,
+            ..TableIterator::new(self)
+        }
+    }
 }
 
 pub struct TableIterator<'table, S: StorageBackend + 'static> {
     table: &'table Table<S>,
     page_id: PageId,
     slot_id: HeapPageSlotId,
+    // The last page read, `None` to read up to the end of the table.
+    last_page_id: Option<PageId>,
+    // `None` to decode all the columns.
+    columns: Option<&'table [usize]>,
 }
 
 impl<'table, S: StorageBackend + 'static> TableIterator<'table, S> {
@@ -118,27 +983,46 @@ impl<'table, S: StorageBackend + 'static> TableIterator<'table, S> {
             table,
             page_id: table.cache.first_page_id(),
             slot_id: HeapPageSlotId::new(0),
+            last_page_id: None,
+            columns: None,
         }
     }
-}
-
-impl<'table, S: StorageBackend + 'static> Iterator for TableIterator<'table, S> {
-    type Item = Tuple;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Returns the next tuple along with its record id.
+    pub fn next_record(&mut self) -> Option<(RecordId, Tuple)> {
+        // The table may have no heap page yet.
+        let last_page_id = self
+            .last_page_id
+            .unwrap_or_else(|| self.table.cache.last_page_id());
+        if self.page_id > last_page_id {
+            return None;
+        }
         let mut page_ref = self.table.cache.get_page(self.page_id).ok()?;
 
         loop {
             let heappage = page_ref.heap_page();
             match heappage.get_tuple(self.slot_id) {
                 Ok(tuple) => {
+                    let record_id = RecordId::new(self.page_id, self.slot_id);
                     self.slot_id.next();
-                    return Some(tuple.to_owned(&self.table.schema));
+                    let tuple = match self.columns {
+                        Some(columns) => tuple.to_owned_columns(&self.table.schema, columns),
+                        None => tuple.to_owned(&self.table.schema),
+                    };
+                    return Some((record_id, tuple));
                 }
                 Err(HeapPageError::SlotDeleted) => {
                     self.slot_id.next();
                 }
                 Err(HeapPageError::SlotNotFound) => {
+                    // Don't read past the end of the table, that page may be
+                    // allocated later on.
+                    let last_page_id = self
+                        .last_page_id
+                        .unwrap_or_else(|| self.table.cache.last_page_id());
+                    if self.page_id >= last_page_id {
+                        return None;
+                    }
                     self.page_id.next();
                     page_ref = self.table.cache.get_page(self.page_id).ok()?;
                     self.slot_id = HeapPageSlotId::new(0);
@@ -149,16 +1033,27 @@ impl<'table, S: StorageBackend + 'static> Iterator for TableIterator<'table, S>
     }
 }
 
+impl<'table, S: StorageBackend + 'static> Iterator for TableIterator<'table, S> {
+    type Item = Tuple;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().map(|(_, tuple)| tuple)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use tempfile::NamedTempFile;
 
-    use crate::cache::GLOBAL_PAGE_CACHE;
-    use crate::pages::{HeapPageSlotId, PageId, RecordId};
+    use crate::cache::{GLOBAL_PAGE_CACHE, PageCache};
+    use crate::indexes::{BTree, BTreeError, FullTextIndex};
+    use crate::pages::{HeapPageSlotId, Key, PageId, RecordId};
+    use crate::sql::plan::TableStats;
     use crate::sql::schema::{Column, ConstraintsBuilder, DataType, Schema};
     use crate::sql::types::Value;
+    use crate::sql::types::memcomparable::KeyOptions;
     use crate::storage::FileStorage;
-    use crate::table::Table;
+    use crate::table::{Table, TableError, lookup};
     use crate::tuple::Tuple;
 
     const NR_ROWS: usize = 10000;
@@ -208,7 +1103,7 @@ mod tests {
             ),
             Column::new(
                 "name".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().build(),
             ),
         ])
@@ -237,6 +1132,34 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn statistics() {
+        let table = test_table(false);
+        assert_eq!(table.statistics(), TableStats { rows: 0, pages: 0 });
+
+        let table = test_table(true);
+        let stats = table.statistics();
+        assert_eq!(stats.rows, NR_ROWS as u64);
+        assert!(stats.pages > 1);
+    }
+
+    #[test]
+    fn iter_pages() {
+        assert_eq!(test_table(false).page_range(), None);
+
+        let table = test_table(true);
+        let (first, last) = table.page_range().unwrap();
+        let middle = PageId::new((first.get() + last.get()) / 2);
+        let mut after_middle = middle;
+        after_middle.next();
+
+        // Splitting the pages reads every tuple once.
+        let head = table.iter_pages(first, middle, None).count();
+        let tail = table.iter_pages(after_middle, last, None).count();
+        assert!(head > 0 && tail > 0);
+        assert_eq!(head + tail, NR_ROWS);
+    }
+
     #[test]
     fn delete() {
         let table = test_table(false);
@@ -270,14 +1193,43 @@ mod tests {
 
     #[test]
     fn constraint_unique() {
-        // TODO: this test would require a unique constraint implementation
-        let table = test_table(false);
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().unique().build(),
+        )])
+        .unwrap();
+        let mut table = Table::try_new(
+            "test_tbl",
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        table.add_index(
+            0,
+            BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap(),
+        );
+        let row = |id| Tuple::try_new(vec![Value::Integer(id)]).unwrap();
 
-        let tuple = Tuple::try_new(vec![Value::Integer(42)]).unwrap();
-        table.insert(&tuple).unwrap();
+        let record_id = table.insert(&row(42)).unwrap();
+        assert!(matches!(
+            table.insert(&row(42)),
+            Err(TableError::ConstraintViolation(column)) if column == "id"
+        ));
+        assert_eq!(table.iter().count(), 1);
 
-        let _result = table.insert(&tuple);
-        // assert!(result.is_err());
+        // A tuple may keep its value when updated, not take another's.
+        let record_id = table.update(record_id, &row(42)).unwrap();
+        let other = table.insert(&row(43)).unwrap();
+        assert!(matches!(
+            table.update(other, &row(42)),
+            Err(TableError::ConstraintViolation(_))
+        ));
+        table.delete(record_id).unwrap();
+        table.update(other, &row(42)).unwrap();
+        assert_eq!(table.iter().count(), 1);
     }
 
     #[test]
@@ -292,6 +1244,527 @@ mod tests {
         );
     }
 
+    #[test]
+    fn vacuum() {
+        let table = test_table(true);
+        let (_, last) = table.page_range().unwrap();
+        let mut kept = Vec::new();
+        let mut iter = table.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            match tuple.values()[0] {
+                Value::Integer(id) if id % 3 == 0 => kept.push((record_id, id)),
+                _ => table.delete(record_id).unwrap(),
+            }
+        }
+
+        let stats = table.vacuum().unwrap();
+        let (_, new_last) = table.page_range().unwrap();
+        assert_eq!(stats.pages_freed, last.get() - new_last.get());
+        assert!(new_last.get() <= last.get().div_ceil(3));
+
+        // The tuples keep their order, relocated ones are found at their new
+        // record id.
+        let ids = table.iter().map(|tuple| tuple.values()[0].clone());
+        let expected = kept.iter().map(|&(_, id)| Value::Integer(id));
+        assert!(ids.eq(expected));
+        for (old, new) in stats.relocations {
+            let id = kept
+                .iter()
+                .find(|(record_id, _)| *record_id == old)
+                .unwrap()
+                .1;
+            assert_eq!(table.get(new).unwrap().values()[0], Value::Integer(id));
+        }
+        assert_eq!(table.vacuum().unwrap(), Default::default());
+
+        // The pages freed are allocated again.
+        let tuple = Tuple::try_new(vec![Value::Integer(-1)]).unwrap();
+        let mut inserted = 0;
+        while table.page_range().unwrap().1 == new_last {
+            table.insert(&tuple).unwrap();
+            inserted += 1;
+        }
+        assert_eq!(table.iter().count(), kept.len() + inserted);
+
+        // Without tuples, all the heap pages are freed.
+        let mut iter = table.iter();
+        while let Some((record_id, _)) = iter.next_record() {
+            table.delete(record_id).unwrap();
+        }
+        table.vacuum().unwrap();
+        assert_eq!(table.page_range(), None);
+        assert_eq!(table.iter().count(), 0);
+    }
+
+    #[test]
+    fn indexes() {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().unique().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        let mut table = Table::try_new(
+            "test_tbl",
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .unwrap();
+        for column in 0..2 {
+            let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+            let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+            table.add_index(column, index);
+        }
+        let (ids, names) = (table.index(0).unwrap(), table.index(1).unwrap());
+        let row = |id: i64, name: Option<&str>| {
+            let name = name.map_or(Value::Null, |name| Value::VarChar(name.to_string()));
+            Tuple::try_new(vec![Value::Integer(id), name]).unwrap()
+        };
+        let id = |id: i64| lookup(ids, &Key::from_values(&[Value::Integer(id)])).unwrap();
+        let name = |name: &str| {
+            lookup(
+                names,
+                &Key::from_values(&[Value::VarChar(name.to_string())]),
+            )
+            .unwrap()
+        };
+
+        let alice = table.insert(&row(1, Some("alice"))).unwrap();
+        let bob = table.insert(&row(2, Some("bob"))).unwrap();
+        // NULLs aren't indexed.
+        table.insert(&row(3, None)).unwrap();
+        table.insert(&row(4, None)).unwrap();
+        assert_eq!(id(1), [alice]);
+        assert_eq!(name("bob"), [bob]);
+        assert_eq!(names.range(..).unwrap().count(), 2);
+
+        // Names repeat, the column isn't unique.
+        let other_alice = table.insert(&row(5, Some("alice"))).unwrap();
+        assert_eq!(name("alice"), [alice, other_alice]);
+        assert_eq!(names.range(..).unwrap().count(), 3);
+
+        // A duplicate id leaves the table and the other indexes untouched.
+        assert!(matches!(
+            table.insert(&row(1, Some("carol"))),
+            Err(TableError::ConstraintViolation(_))
+        ));
+        assert_eq!(table.iter().count(), 5);
+        assert!(name("carol").is_empty());
+        assert!(matches!(
+            table.update(bob, &row(1, Some("bob"))),
+            Err(TableError::ConstraintViolation(_))
+        ));
+        assert_eq!(
+            table.get(bob).unwrap().values(),
+            row(2, Some("bob")).values()
+        );
+        assert_eq!(id(2), [bob]);
+        assert_eq!(name("bob"), [bob]);
+
+        let bob = table.update(bob, &row(2, Some("robert"))).unwrap();
+        assert_eq!(id(2), [bob]);
+        assert!(name("bob").is_empty());
+        assert_eq!(name("robert"), [bob]);
+
+        table.delete(alice).unwrap();
+        assert!(id(1).is_empty());
+        assert_eq!(name("alice"), [other_alice]);
+        assert_eq!(table.iter().count(), 4);
+
+        // The indexes follow the tuples moved by vacuum.
+        let stats = table.vacuum().unwrap();
+        assert!(!stats.relocations.is_empty());
+        let mut iter = table.iter();
+        while let Some((record_id, tuple)) = iter.next_record() {
+            let Value::Integer(i) = tuple.values()[0] else {
+                panic!("expected an INTEGER id");
+            };
+            assert_eq!(id(i), [record_id]);
+            if let Value::VarChar(text) = &tuple.values()[1] {
+                assert_eq!(name(text), [record_id]);
+            }
+        }
+        assert_eq!(names.range(..).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn failed_writes() {
+        let mut table = test_table(false);
+        let row = |id: i64| Tuple::try_new(vec![Value::Integer(id)]).unwrap();
+        let index = || {
+            let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+            BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap()
+        };
+        table.add_index(0, index());
+        let record_id = table.insert(&row(1)).unwrap();
+        // An index missing the row: its key can't be deleted.
+        let key = vec![(0, KeyOptions::descending())];
+        table.add_composite_index(key, Vec::new(), index());
+        let ids = |id: i64| {
+            let index = table.index(0).unwrap();
+            lookup(index, &Key::from_values(&[Value::Integer(id)])).unwrap()
+        };
+
+        // The tuple and its other keys are left untouched.
+        assert!(matches!(
+            table.delete(record_id),
+            Err(TableError::Index(BTreeError::Page(_)))
+        ));
+        assert_eq!(table.get(record_id).unwrap().values(), row(1).values());
+        assert_eq!(ids(1), [record_id]);
+
+        // The new tuple is removed.
+        assert!(table.update(record_id, &row(2)).is_err());
+        assert_eq!(table.iter().count(), 1);
+        assert_eq!(table.get(record_id).unwrap().values(), row(1).values());
+        assert_eq!(ids(1), [record_id]);
+        assert!(ids(2).is_empty());
+    }
+
+    #[test]
+    fn delete_is_written_back() {
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().build(),
+        )])
+        .unwrap();
+        let row = Tuple::try_new(vec![Value::Integer(1)]).unwrap();
+
+        let page_cache = PageCache::try_new().unwrap();
+        let cache = page_cache.cache_storage(FileStorage::create(&path).unwrap());
+        let table = Table::try_new("test_tbl", &schema, cache).unwrap();
+        let record_id = table.insert(&row).unwrap();
+        page_cache.flush();
+        // The page of the tuple is clean: the delete must dirty it again.
+        table.delete(record_id).unwrap();
+        page_cache.flush();
+        drop(table);
+        drop(page_cache);
+
+        let page_cache = PageCache::try_new().unwrap();
+        let cache = page_cache.cache_storage(FileStorage::open(&path).unwrap());
+        let table = Table::try_new("test_tbl", &schema, cache).unwrap();
+        assert!(table.get(record_id).is_err());
+        assert_eq!(table.iter().count(), 0);
+    }
+
+    #[test]
+    fn covering_index() {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "id".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "name".into(),
+                DataType::VarChar(None),
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+            Column::new(
+                "age".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
+        .unwrap();
+        let mut table = Table::try_new(
+            "test_tbl",
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        table.add_covering_index(0, vec![2, 1], index);
+        let row = |id: i64, name: Option<&str>, age: i64| {
+            let name = name.map_or(Value::Null, |name| Value::VarChar(name.to_string()));
+            Tuple::try_new(vec![Value::Integer(id), name, Value::Integer(age)]).unwrap()
+        };
+        // The included columns as stored in the index, by id.
+        let included = |table: &Table<FileStorage>| {
+            let columns = [DataType::Integer, DataType::VarChar(None)]
+                .map(|data_type| (data_type, KeyOptions::default()));
+            let mut iter = table.index(0).unwrap().range(..).unwrap();
+            let mut rows = Vec::new();
+            while let Some((_, record_id, payload)) = iter.next_with_payload() {
+                let values = Key::from(payload).values(&columns).unwrap();
+                let tuple = table.get(record_id).unwrap();
+                assert_eq!(
+                    values,
+                    [tuple.values()[2].clone(), tuple.values()[1].clone()]
+                );
+                rows.push(values);
+            }
+            rows
+        };
+
+        let alice = table.insert(&row(1, Some("alice"), 30)).unwrap();
+        table.insert(&row(2, None, 40)).unwrap();
+        assert_eq!(
+            included(&table),
+            [
+                vec![Value::Integer(30), Value::VarChar("alice".to_string())],
+                vec![Value::Integer(40), Value::Null],
+            ]
+        );
+
+        // Updates and vacuum rewrite the payloads.
+        table.update(alice, &row(1, Some("alice"), 31)).unwrap();
+        table.vacuum().unwrap();
+        assert_eq!(
+            included(&table),
+            [
+                vec![Value::Integer(31), Value::VarChar("alice".to_string())],
+                vec![Value::Integer(40), Value::Null],
+            ]
+        );
+    }
+
+    #[test]
+    fn composite_index() {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "a".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "b".into(),
+                DataType::Integer,
+                ConstraintsBuilder::new().nullable().build(),
+            ),
+        ])
+        .unwrap();
+        let mut table = Table::try_new(
+            "test_tbl",
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = BTree::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        let key = vec![(0, KeyOptions::default()), (1, KeyOptions::descending())];
+        table.add_composite_index(key, Vec::new(), index);
+        // Only single column ascending indexes are looked up by column.
+        assert!(table.index(0).is_none());
+        let row = |a: i64, b: Option<i64>| {
+            let b = b.map_or(Value::Null, Value::Integer);
+            Tuple::try_new(vec![Value::Integer(a), b]).unwrap()
+        };
+        // The rows in the order of the index: a ASC, b DESC.
+        let rows = |table: &Table<FileStorage>| {
+            let (_, index) = table.indexes().next().unwrap();
+            index
+                .range(..)
+                .unwrap()
+                .map(|(_, record_id)| table.get(record_id).unwrap().values().to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        for (a, b) in [(1, 1), (2, 1), (1, 3), (2, 2), (1, 2)] {
+            table.insert(&row(a, Some(b))).unwrap();
+        }
+        // NULLs aren't indexed, duplicates are kept in record id order.
+        table.insert(&row(1, None)).unwrap();
+        table.insert(&row(2, Some(2))).unwrap();
+        assert_eq!(
+            rows(&table),
+            [(1, 3), (1, 2), (1, 1), (2, 2), (2, 2), (2, 1)]
+                .map(|(a, b)| row(a, Some(b)).values().to_vec())
+        );
+    }
+
+    #[test]
+    fn fulltext_index() {
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "body".into(),
+            DataType::VarChar(None),
+            ConstraintsBuilder::new().nullable().build(),
+        )])
+        .unwrap();
+        let mut table = Table::try_new(
+            "test_tbl",
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .unwrap();
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let index = FullTextIndex::try_new(GLOBAL_PAGE_CACHE.cache_storage(storage)).unwrap();
+        table.add_fulltext_index(0, index);
+        assert!(table.fulltext_index(1).is_none());
+        let row = |body: Option<&str>| {
+            let body = body.map_or(Value::Null, |body| Value::VarChar(body.to_string()));
+            Tuple::try_new(vec![body]).unwrap()
+        };
+        let search = |table: &Table<FileStorage>, query: &str| {
+            let record_ids = table.fulltext_index(0).unwrap().search(query).unwrap();
+            record_ids
+                .into_iter()
+                .map(|record_id| table.get(record_id).unwrap().values()[0].clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut record_ids = Vec::new();
+        for i in 0..1000 {
+            let body = format!("row {i} is {}", if i % 2 == 0 { "even" } else { "odd" });
+            record_ids.push(table.insert(&row(Some(&body))).unwrap());
+        }
+        table.insert(&row(None)).unwrap();
+        assert_eq!(search(&table, "even").len(), 500);
+        assert_eq!(
+            search(&table, "row 42"),
+            [Value::VarChar("row 42 is even".into())]
+        );
+
+        // Updates and deletes move and remove the postings.
+        table.update(record_ids[41], &row(Some("odd one"))).unwrap();
+        assert_eq!(search(&table, "41"), []);
+        assert_eq!(
+            search(&table, "odd one"),
+            [Value::VarChar("odd one".into())]
+        );
+        for &record_id in record_ids.iter().step_by(2) {
+            table.delete(record_id).unwrap();
+        }
+        assert_eq!(search(&table, "even"), []);
+        assert_eq!(search(&table, "odd").len(), 500);
+
+        // So does vacuum, the postings being keyed by record id.
+        let stats = table.vacuum().unwrap();
+        assert!(!stats.relocations.is_empty());
+        assert_eq!(search(&table, "odd").len(), 500);
+        assert_eq!(
+            search(&table, "row 999"),
+            [Value::VarChar("row 999 is odd".into())]
+        );
+        let updated = table.fulltext_index(0).unwrap().search("one").unwrap();
+        table.delete(updated[0]).unwrap();
+        assert_eq!(search(&table, "one"), []);
+    }
+
+    #[test]
+    fn online_index_build() {
+        let mut table = test_table(true);
+        let key = vec![(0, KeyOptions::default())];
+        let row = |id: i64| Tuple::try_new(vec![Value::Integer(id)]).unwrap();
+        let page_cache = || {
+            let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+            GLOBAL_PAGE_CACHE.cache_storage(storage)
+        };
+
+        // A writer moves rows while the index is built.
+        let writer = table.clone();
+        let (started, wait_start) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let mut iter = writer.iter();
+            let mut records = Vec::new();
+            while let Some((record_id, tuple)) = iter.next_record() {
+                records.push((record_id, tuple));
+            }
+            for (i, (record_id, tuple)) in records.into_iter().step_by(3).enumerate() {
+                match i % 3 {
+                    0 => writer.delete(record_id).unwrap(),
+                    1 => {
+                        writer.update(record_id, &tuple).unwrap();
+                    }
+                    _ => {
+                        writer.insert(&row(NR_ROWS as i64 + i as i64)).unwrap();
+                    }
+                }
+                if i == 0 {
+                    started.send(()).unwrap();
+                }
+            }
+        });
+        wait_start.recv().unwrap();
+        let build = table
+            .build_index_online(key.clone(), Vec::new(), page_cache())
+            .unwrap();
+        handle.join().unwrap();
+
+        // The writes after the catch up are applied by the swap.
+        table.insert(&row(-1)).unwrap();
+        let (record_id, _) = table.iter().next_record().unwrap();
+        table.delete(record_id).unwrap();
+        table.finish_index_build(build).unwrap();
+        let entries = table
+            .index_entries(&key, &[])
+            .into_iter()
+            .map(|(key, record_id, _)| (key, record_id))
+            .collect::<Vec<_>>();
+        let index = table.index(0).unwrap();
+        assert_eq!(index.range(..).unwrap().collect::<Vec<_>>(), entries);
+
+        // The index is maintained by the writes, the logs are gone.
+        let record_id = table.insert(&row(-2)).unwrap();
+        assert_eq!(
+            lookup(index, &Key::from_values(&[Value::Integer(-2)])).unwrap(),
+            [record_id]
+        );
+        assert!(table.index_builds.read().is_empty());
+
+        // A build dropped stops logging.
+        let build = table
+            .build_index_online(key.clone(), Vec::new(), page_cache())
+            .unwrap();
+        assert_eq!(table.index_builds.read().len(), 1);
+        drop(build);
+        assert!(table.index_builds.read().is_empty());
+
+        // The column isn't unique, its values may repeat.
+        let mut table = test_table(false);
+        let record_ids = [
+            table.insert(&row(1)).unwrap(),
+            table.insert(&row(1)).unwrap(),
+        ];
+        let build = table
+            .build_index_online(key.clone(), Vec::new(), page_cache())
+            .unwrap();
+        table.finish_index_build(build).unwrap();
+        let index = table.index(0).unwrap();
+        assert_eq!(
+            lookup(index, &Key::from_values(&[Value::Integer(1)])).unwrap(),
+            record_ids
+        );
+
+        // Unless unique: a failing build stops logging.
+        let storage = FileStorage::create(NamedTempFile::new().unwrap()).unwrap();
+        let schema = Schema::try_new(vec![Column::new(
+            "id".into(),
+            DataType::Integer,
+            ConstraintsBuilder::new().unique().build(),
+        )])
+        .unwrap();
+        let table = Table::try_new(
+            "test_tbl",
+            &schema,
+            GLOBAL_PAGE_CACHE.cache_storage(storage),
+        )
+        .unwrap();
+        // Without an index, the column isn't enforced.
+        table.insert(&row(1)).unwrap();
+        table.insert(&row(1)).unwrap();
+        assert!(matches!(
+            table.build_index_online(key, Vec::new(), page_cache()),
+            Err(TableError::Index(BTreeError::DuplicateKey(_)))
+        ));
+        assert!(table.index_builds.read().is_empty());
+    }
+
     #[test]
     fn iterator_empty_table() {
         let table = test_table(false);
@@ -1,4 +1,4 @@
-use crate::sql::schema::Schema;
+use crate::sql::schema::{DataType, Schema};
 use crate::sql::types::Value;
 use crate::{pages::HeapPage, serialize::Serialize};
 
@@ -74,6 +74,32 @@ impl TupleRef {
 
         Tuple { values }
     }
+
+    /// Like `to_owned`, but only decodes the given columns (in increasing
+    /// order): the other ones are skipped and read as NULL.
+    pub fn to_owned_columns(&self, schema: &Schema, columns: &[usize]) -> Tuple {
+        let mut values = vec![Value::Null; schema.num_columns()];
+        let mut columns = columns.iter().peekable();
+
+        let mut offset = 0;
+        for (i, column) in schema.columns().iter().enumerate() {
+            if columns.peek().is_none() {
+                break;
+            }
+            if self.header.null_bitmap.is_null(i) {
+                columns.next_if_eq(&&i);
+                continue;
+            }
+
+            let bytes = &self.values[offset..];
+            if columns.next_if_eq(&&i).is_some() {
+                values[i] = Value::from_bytes(bytes, column.data_type);
+            }
+            offset += Value::size_from_bytes(bytes, column.data_type);
+        }
+
+        Tuple { values }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -84,6 +110,10 @@ pub enum TupleError {
     TooManyColumns,
     #[error("tuple values and table schema mismatch")]
     SchemaMismatch,
+    #[error("value too long for type {0}")]
+    ValueTooLong(DataType),
+    #[error("value out of range for type {0}")]
+    OutOfRange(DataType),
 }
 
 impl Tuple {
@@ -131,20 +161,34 @@ impl Tuple {
             return Err(TupleError::TooManyColumns);
         }
 
-        let values_match_schema =
-            self.values
-                .iter()
-                .zip(schema.columns())
-                .all(|(value, column)| match value {
-                    Value::Null => column.constraints.is_nullable(),
-                    value => value.data_type().is_some_and(|v| v == column.data_type),
-                });
-
-        if !values_match_schema {
-            Err(TupleError::SchemaMismatch)
-        } else {
-            Ok(())
+        for (value, column) in self.values.iter().zip(schema.columns()) {
+            let data_type = column.data_type;
+            match value {
+                Value::Null if column.constraints.is_nullable() => continue,
+                Value::Null => return Err(TupleError::SchemaMismatch),
+                value if value.data_type() != Some(data_type.storage_type()) => {
+                    return Err(TupleError::SchemaMismatch);
+                }
+                _ => {}
+            }
+
+            // Lengths are counted in characters. NaN is out of range of any DECIMAL.
+            match (data_type, value) {
+                (DataType::VarChar(Some(n)) | DataType::Char(n), Value::VarChar(s))
+                    if s.chars().count() > n as usize =>
+                {
+                    return Err(TupleError::ValueTooLong(data_type));
+                }
+                (DataType::Decimal { precision, scale }, Value::Float(f))
+                    if f.is_nan() || f.abs() >= 10f64.powi((precision - scale) as i32) =>
+                {
+                    return Err(TupleError::OutOfRange(data_type));
+                }
+                _ => {}
+            }
         }
+
+        Ok(())
     }
 
     #[cfg(test)]
@@ -156,7 +200,7 @@ impl Tuple {
         Box::leak(v.into_boxed_slice())
     }
 
-    #[cfg(test)]
+    /// Returns the values of the tuple, one per column.
     pub fn values(&self) -> &[Value] {
         self.values.as_slice()
     }
@@ -204,7 +248,7 @@ mod tests {
             ),
             Column::new(
                 "b".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().build(),
             ),
             Column::new(
@@ -214,7 +258,7 @@ mod tests {
             ),
             Column::new(
                 "d".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().unique().build(),
             ),
         ])
@@ -237,6 +281,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_some_columns() {
+        let schema = Schema::try_new(
+            [
+                DataType::VarChar(None),
+                DataType::Integer,
+                DataType::VarChar(None),
+                DataType::Float,
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, data_type)| {
+                Column::new(
+                    format!("c{i}"),
+                    data_type,
+                    ConstraintsBuilder::new().nullable().build(),
+                )
+            })
+            .collect(),
+        )
+        .unwrap();
+        let tuple = Tuple::try_new(vec![
+            Value::VarChar("a".repeat(200)),
+            Value::Null,
+            Value::VarChar("b".to_string()),
+            Value::Float(1.5),
+        ])
+        .unwrap();
+        let bytes = tuple.as_bytes();
+        let tuple = TupleRef::ref_from_bytes(bytes).unwrap();
+
+        assert_eq!(
+            tuple.to_owned_columns(&schema, &[1, 3]).values(),
+            [Value::Null, Value::Null, Value::Null, Value::Float(1.5)]
+        );
+        assert_eq!(
+            tuple.to_owned_columns(&schema, &[2]).values(),
+            [
+                Value::Null,
+                Value::Null,
+                Value::VarChar("b".to_string()),
+                Value::Null
+            ]
+        );
+        assert_eq!(
+            tuple.to_owned_columns(&schema, &[0, 1, 2, 3]).values(),
+            tuple.to_owned(&schema).values()
+        );
+        assert!(
+            tuple
+                .to_owned_columns(&schema, &[])
+                .values()
+                .iter()
+                .all(Value::is_null)
+        );
+    }
+
     #[test]
     fn validate_tuple_ok() {
         let schema = Schema::try_new(vec![
@@ -247,17 +348,17 @@ mod tests {
             ),
             Column::new(
                 "b".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().build(),
             ),
             Column::new(
                 "d".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().build(),
             ),
             Column::new(
                 "e".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().nullable().build(),
             ),
         ])
@@ -282,17 +383,17 @@ mod tests {
             ),
             Column::new(
                 "b".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().nullable().build(),
             ),
             Column::new(
                 "c".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().nullable().build(),
             ),
             Column::new(
                 "d".into(),
-                DataType::VarChar,
+                DataType::VarChar(None),
                 ConstraintsBuilder::new().nullable().build(),
             ),
         ])
@@ -301,4 +402,60 @@ mod tests {
         let tuple = Tuple::try_new(values).unwrap();
         assert!(tuple.validate_with_schema(&schema).is_ok());
     }
+
+    #[test]
+    fn validate_parameterized_types() {
+        let schema = Schema::try_new(vec![
+            Column::new(
+                "name".into(),
+                DataType::VarChar(Some(3)),
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "code".into(),
+                DataType::Char(2),
+                ConstraintsBuilder::new().build(),
+            ),
+            Column::new(
+                "price".into(),
+                DataType::Decimal {
+                    precision: 4,
+                    scale: 2,
+                },
+                ConstraintsBuilder::new().build(),
+            ),
+        ])
+        .unwrap();
+        let tuple = |name: &str, code: &str, price: f64| {
+            Tuple::try_new(vec![
+                Value::VarChar(name.into()),
+                Value::VarChar(code.into()),
+                Value::Float(price),
+            ])
+            .unwrap()
+        };
+
+        // Lengths are in characters, not bytes.
+        assert!(
+            tuple("été", "fr", 99.99)
+                .validate_with_schema(&schema)
+                .is_ok()
+        );
+        assert!(matches!(
+            tuple("abcd", "fr", 1.0).validate_with_schema(&schema),
+            Err(TupleError::ValueTooLong(DataType::VarChar(Some(3))))
+        ));
+        assert!(matches!(
+            tuple("abc", "fra", 1.0).validate_with_schema(&schema),
+            Err(TupleError::ValueTooLong(DataType::Char(2)))
+        ));
+        assert!(matches!(
+            tuple("abc", "fr", -100.0).validate_with_schema(&schema),
+            Err(TupleError::OutOfRange(DataType::Decimal { .. }))
+        ));
+        assert!(matches!(
+            tuple("abc", "fr", f64::NAN).validate_with_schema(&schema),
+            Err(TupleError::OutOfRange(DataType::Decimal { .. }))
+        ));
+    }
 }
@@ -0,0 +1,13 @@
+use std::process::Command;
+
+#[test]
+fn demo_persists_across_restarts() {
+    let output = Command::new(env!("CARGO_BIN_EXE_demo")).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{stdout}{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.ends_with("shop is persisted\n"), "{stdout}");
+}